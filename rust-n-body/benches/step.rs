@@ -0,0 +1,127 @@
+use bevy::prelude::*;
+use rust_n_body::Body;
+use rust_n_body::bhtree::Quadtree;
+use rust_n_body::force::ForceParams;
+use rust_n_body::pipeline::{TreeInputs, build_tree};
+use rust_n_body::soa::BodyBuffers;
+use std::collections::HashMap;
+use std::time::{Duration, Instant};
+
+// Tree build plus one step, on the struct-of-arrays buffers the update system works on against the
+// per-entity path it replaced, where the tree came from a query and every entity looked its kick up in a
+// map. Both walk the same tree, so they end up in the same place and only the bookkeeping around the
+// walks differs. Run with `cargo bench`, the sizes can be given as arguments: `cargo bench -- 1000 50000`.
+
+const SIZES: [usize; 3] = [1_000, 10_000, 100_000];
+const STEPS: u32 = 5;
+// the settings' default
+const LEAF_CAPACITY: usize = 16;
+const DT: f32 = 0.001;
+const THETA: f32 = 0.5;
+
+#[derive(Component)]
+struct Velocity(Vec3);
+
+type Bodies<'a> = (Entity, &'a mut Transform, &'a mut Velocity, &'a Body);
+
+// The same scattered cloud every time, from a small xorshift so the bench needs nothing beyond the crate
+fn spawn_bodies(n: usize) -> World {
+    let mut world = World::new();
+    let mut state = 0x2545_f491_4f6c_dd1du64;
+    let mut next = || {
+        state ^= state << 13;
+        state ^= state >> 7;
+        state ^= state << 17;
+        (state >> 40) as f32 / (1u64 << 24) as f32
+    };
+    for _ in 0..n {
+        let position = Vec3::new(next() * 600.0 - 300.0, next() * 600.0 - 300.0, 0.0);
+        let velocity = Vec3::new(next() - 0.5, next() - 0.5, 0.0);
+        let body = Body {
+            mass: 1.0 + next() * 9.0,
+            radius: 1.0,
+            hue: 0.0,
+        };
+        world.spawn((Transform::from_translation(position), Velocity(velocity), body));
+    }
+    world
+}
+
+fn step_per_entity(world: &mut World, query: &mut QueryState<Bodies>, force: ForceParams) {
+    let inputs: TreeInputs = query
+        .iter(world)
+        .map(|(entity, transform, _velocity, body)| (entity, *transform, *body))
+        .collect();
+    let tree = build_tree(&inputs, LEAF_CAPACITY);
+    let kicks: HashMap<Entity, Vec3> = inputs
+        .iter()
+        .map(|&(entity, transform, body)| (entity, tree.get_total_accel(entity, transform, body, force, DT, THETA)))
+        .collect();
+    for (entity, mut transform, mut velocity, _body) in query.iter_mut(world) {
+        velocity.0 += kicks[&entity];
+        transform.translation.x += velocity.0.x * DT;
+        transform.translation.y += velocity.0.y * DT;
+    }
+}
+
+fn step_soa(world: &mut World, query: &mut QueryState<Bodies>, buffers: &mut BodyBuffers, force: ForceParams) {
+    buffers.clear();
+    for (entity, transform, velocity, body) in query.iter(world) {
+        buffers.push(entity, transform.translation, velocity.0, body.mass, true);
+    }
+    let mut tree = Quadtree::with_leaf_capacity(buffers.bounds(), LEAF_CAPACITY);
+    for (i, (entity, transform, _velocity, body)) in query.iter(world).enumerate() {
+        tree.insert(entity, buffers.transform(i, transform), *body);
+    }
+    for (i, (entity, transform, _velocity, body)) in query.iter(world).enumerate() {
+        let accel = tree.get_total_accel(entity, buffers.transform(i, transform), *body, force, DT, THETA);
+        buffers.ax[i] = accel.x;
+        buffers.ay[i] = accel.y;
+    }
+    buffers.step(DT);
+    for (i, (_entity, mut transform, mut velocity, _body)) in query.iter_mut(world).enumerate() {
+        velocity.0.x = buffers.vx[i];
+        velocity.0.y = buffers.vy[i];
+        transform.translation.x = buffers.x[i];
+        transform.translation.y = buffers.y[i];
+    }
+}
+
+fn positions(world: &mut World) -> Vec<Vec3> {
+    world.query::<&Transform>().iter(world).map(|transform| transform.translation).collect()
+}
+
+// Fastest of the steps, the slower ones are whatever else the machine was doing
+fn fastest(mut step: impl FnMut()) -> Duration {
+    // the first step touches every allocation once
+    step();
+    (0..STEPS)
+        .map(|_| {
+            let start = Instant::now();
+            step();
+            start.elapsed()
+        })
+        .min()
+        .unwrap_or_default()
+}
+
+fn main() {
+    // cargo bench passes --bench along, anything that is a number is a size
+    let asked: Vec<usize> = std::env::args().skip(1).filter_map(|arg| arg.parse().ok()).collect();
+    let sizes = if asked.is_empty() { SIZES.to_vec() } else { asked };
+    let force = ForceParams::default();
+
+    for n in sizes {
+        let mut entity_world = spawn_bodies(n);
+        let mut entity_query = entity_world.query::<Bodies>();
+        let entity_time = fastest(|| step_per_entity(&mut entity_world, &mut entity_query, force));
+
+        let mut soa_world = spawn_bodies(n);
+        let mut soa_query = soa_world.query::<Bodies>();
+        let mut buffers = BodyBuffers::default();
+        let soa_time = fastest(|| step_soa(&mut soa_world, &mut soa_query, &mut buffers, force));
+
+        assert_eq!(positions(&mut entity_world), positions(&mut soa_world), "the two paths went apart");
+        println!("n = {:>6}: tree and step per entity {:?}, soa {:?}", n, entity_time, soa_time);
+    }
+}
@@ -0,0 +1,21 @@
+/* C API of the Barnes-Hut solver, built with
+ * `cargo rustc --release --lib --features ffi --crate-type cdylib`
+ * into target/release/librust_n_body.so (.dylib, .dll). See src/ffi.rs. */
+#ifndef NBODY_H
+#define NBODY_H
+
+#include <stddef.h>
+#include <stdint.h>
+
+typedef struct NBodySim NBodySim;
+
+NBodySim *nbody_create(float g, float theta, float dt);
+void nbody_destroy(NBodySim *sim);
+/* index of the new body, -1 if sim is null */
+int64_t nbody_add_body(NBodySim *sim, float x, float y, float vx, float vy, float mass);
+void nbody_step(NBodySim *sim, uint32_t n);
+size_t nbody_body_count(const NBodySim *sim);
+/* x, y pairs in index order, as many bodies as fit in len floats; returns the bodies written */
+size_t nbody_read_positions(const NBodySim *sim, float *out, size_t len);
+
+#endif
@@ -0,0 +1,86 @@
+use bevy::audio::{Decodable, Source};
+use bevy::prelude::*;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicU32, Ordering};
+use std::time::Duration;
+
+const SAMPLE_RATE: u32 = 44_100;
+
+// A sine tone whose pitch and volume are changed from the game side while it plays.
+// The audio thread reads them once per sample, f32 bits in atomics is all the sharing it needs.
+#[derive(Asset, TypePath, Clone, Default)]
+pub struct Tone {
+    pitch: Arc<AtomicU32>,
+    gain: Arc<AtomicU32>,
+}
+
+impl Tone {
+    pub fn set(&self, pitch: f32, gain: f32) {
+        self.pitch.store(pitch.to_bits(), Ordering::Relaxed);
+        self.gain.store(gain.clamp(0.0, 1.0).to_bits(), Ordering::Relaxed);
+    }
+
+    pub fn pitch(&self) -> f32 {
+        f32::from_bits(self.pitch.load(Ordering::Relaxed))
+    }
+
+    pub fn gain(&self) -> f32 {
+        f32::from_bits(self.gain.load(Ordering::Relaxed))
+    }
+}
+
+pub struct ToneDecoder {
+    tone: Tone,
+    // in cycles, kept between samples so pitch changes don't click
+    phase: f32,
+    // follows the requested gain over a few milliseconds, again to avoid clicks
+    gain: f32,
+}
+
+impl Iterator for ToneDecoder {
+    type Item = f32;
+
+    fn next(&mut self) -> Option<f32> {
+        self.phase = (self.phase + self.tone.pitch() / SAMPLE_RATE as f32).fract();
+        self.gain += (self.tone.gain() - self.gain) * 0.002;
+        Some(self.gain * (self.phase * std::f32::consts::TAU).sin())
+    }
+}
+
+impl Source for ToneDecoder {
+    fn current_frame_len(&self) -> Option<usize> {
+        None
+    }
+
+    fn channels(&self) -> u16 {
+        1
+    }
+
+    fn sample_rate(&self) -> u32 {
+        SAMPLE_RATE
+    }
+
+    fn total_duration(&self) -> Option<Duration> {
+        None
+    }
+}
+
+impl Decodable for Tone {
+    type DecoderItem = f32;
+    type Decoder = ToneDecoder;
+
+    fn decoder(&self) -> ToneDecoder {
+        ToneDecoder {
+            tone: self.clone(),
+            phase: 0.0,
+            gain: 0.0,
+        }
+    }
+}
+
+// Starts a silent tone that plays forever and hands back the controls for it
+pub fn play_tone(commands: &mut Commands, tones: &mut Assets<Tone>) -> Tone {
+    let tone = Tone::default();
+    commands.spawn(AudioPlayer(tones.add(tone.clone())));
+    tone
+}
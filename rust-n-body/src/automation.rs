@@ -0,0 +1,199 @@
+use bevy::prelude::*;
+use bevy_egui::{EguiContexts, egui};
+use crate::clock::SimulationClock;
+use crate::energy::Energy;
+use crate::headless::HeadlessSim;
+use crate::virial::VirialMonitor;
+use crate::selection::Selected;
+use crate::ui_config::UiConfig;
+use crate::tracer::Massless;
+use crate::{Body, SimulationSettings};
+
+// Conditions that pause the simulation, checked after every step.
+// The stop time lives in the settings together with the rest of the run setup.
+#[derive(Resource)]
+pub struct Automation {
+    pub energy_rule: bool,
+    pub max_energy_error: f64,
+    pub bodies_rule: bool,
+    pub min_bodies: u32,
+    pub pair: Option<(Entity, Entity)>,
+    pub pair_distance: f32,
+    // What stopped the run last, shown until the next reset
+    pub triggered: Option<String>,
+}
+
+impl Default for Automation {
+    fn default() -> Self {
+        Automation {
+            energy_rule: false,
+            max_energy_error: 0.01,
+            bodies_rule: false,
+            min_bodies: 100,
+            pair: None,
+            pair_distance: 10.0,
+            triggered: None,
+        }
+    }
+}
+
+// What the rules are checked against, so a headless run can check them the same way
+pub struct RuleState {
+    pub time: f64,
+    pub energy_error: Option<f64>,
+    pub bodies: u32,
+    // how far apart the watched pair is, None once one of them is gone
+    pub pair_distance: Option<f32>,
+}
+
+impl Automation {
+    // The rule that fired, the last one in the list if several did
+    pub fn check(&self, settings: &SimulationSettings, state: &RuleState) -> Option<String> {
+        let mut reason = None;
+        if settings.stop_at_time && state.time >= settings.stop_time {
+            reason = Some(format!("Reached stop time (t = {:.4})", state.time));
+        }
+        if self.energy_rule
+            && let Some(error) = state.energy_error
+            && error > self.max_energy_error
+        {
+            reason = Some(format!("Energy error {:.2e} above {:.2e}", error, self.max_energy_error));
+        }
+        if self.bodies_rule && state.bodies < self.min_bodies {
+            reason = Some(format!("Only {} bodies left", state.bodies));
+        }
+        if let Some(distance) = state.pair_distance
+            && distance < self.pair_distance
+        {
+            reason = Some(format!("Pair came within {:.2}", distance));
+        }
+        reason
+    }
+}
+
+// The rules against a headless run, where body i is the entity with index i. The energy is
+// only measured when the energy rule needs it, it costs a tree build.
+pub fn headless_state(
+    automation: &Automation,
+    sim: &HeadlessSim,
+    settings: &SimulationSettings,
+    initial_energy: f64,
+) -> RuleState {
+    let position = |entity: Entity| sim.transforms.get(entity.index() as usize).map(|t| t.translation);
+    let energy_error = automation.energy_rule.then(|| {
+        let (kinetic, potential) = sim.energy(settings);
+        if initial_energy != 0.0 { ((kinetic + potential - initial_energy) / initial_energy).abs() } else { 0.0 }
+    });
+    RuleState {
+        time: sim.time,
+        energy_error,
+        bodies: sim.bodies.len() as u32,
+        pair_distance: automation.pair.and_then(|(a, b)| Some(position(a)?.distance(position(b)?))),
+    }
+}
+
+pub fn check_automation(
+    mut automation: ResMut<Automation>,
+    mut settings: ResMut<SimulationSettings>,
+    clock: Res<SimulationClock>,
+    energy: Res<Energy>,
+    bodies: Query<(), (With<Body>, Without<Massless>)>,
+    transforms: Query<&Transform, With<Body>>,
+) {
+    if settings.paused {
+        return;
+    }
+
+    // Bodies that have been removed just never trigger
+    let pair_distance = automation.pair.and_then(|(a, b)| {
+        let (ta, tb) = (transforms.get(a).ok()?, transforms.get(b).ok()?);
+        Some(ta.translation.distance(tb.translation))
+    });
+    let state = RuleState {
+        time: clock.time,
+        energy_error: energy.relative_error(),
+        bodies: bodies.iter().count() as u32,
+        pair_distance,
+    };
+
+    if let Some(reason) = automation.check(&settings, &state) {
+        info!(time = clock.time, steps = clock.steps, "{}, pausing", reason);
+        automation.triggered = Some(reason);
+        settings.paused = true;
+    }
+}
+
+pub fn automation_window(
+    mut contexts: EguiContexts,
+    mut automation: ResMut<Automation>,
+    mut settings: ResMut<SimulationSettings>,
+    mut energy: ResMut<Energy>,
+    mut virial: ResMut<VirialMonitor>,
+    selected: Query<Entity, With<Selected>>,
+    ui_config: Res<UiConfig>,
+) {
+    ui_config
+        .place(egui::Window::new("Automation"), "Automation")
+        .default_open(false)
+        .show(contexts.ctx_mut(), |ui| {
+            ui.add(egui::Label::new("Pause the simulation when:"));
+            ui.horizontal(|ui| {
+                ui.add(egui::Checkbox::new(&mut settings.stop_at_time, "Time reaches"));
+                ui.add(egui::DragValue::new(&mut settings.stop_time).speed(0.1).range(0.0..=f64::MAX));
+            });
+            ui.horizontal(|ui| {
+                ui.add(egui::Checkbox::new(&mut automation.energy_rule, "Energy error above"));
+                ui.add(
+                    egui::DragValue::new(&mut automation.max_energy_error)
+                        .speed(0.001)
+                        .range(0.0..=1.0),
+                );
+            });
+            ui.horizontal(|ui| {
+                ui.add(egui::Checkbox::new(&mut automation.bodies_rule, "Bodies fewer than"));
+                ui.add(egui::DragValue::new(&mut automation.min_bodies));
+            });
+            ui.horizontal(|ui| {
+                ui.label("Pair closer than");
+                ui.add(egui::DragValue::new(&mut automation.pair_distance).speed(0.5).range(0.0..=f32::MAX));
+            });
+            ui.horizontal(|ui| {
+                let selected: Vec<Entity> = selected.iter().collect();
+                if ui
+                    .add_enabled(selected.len() == 2, egui::Button::new("Watch Selected Pair"))
+                    .clicked()
+                {
+                    automation.pair = Some((selected[0], selected[1]));
+                }
+                if automation.pair.is_some() && ui.button("Clear Pair").clicked() {
+                    automation.pair = None;
+                }
+            });
+
+            // The energy is only computed while something needs it
+            if automation.energy_rule {
+                energy.enabled = true;
+            }
+            ui.add(egui::Checkbox::new(&mut energy.enabled, "Track Energy"));
+            if energy.enabled {
+                ui.label(format!("Energy: {:.6e}", energy.total()));
+                if let Some(error) = energy.relative_error() {
+                    ui.label(format!("Relative error: {:.3e}", error));
+                }
+            }
+
+            ui.add(egui::Checkbox::new(&mut energy.virial, "Monitor Virial Ratio"));
+            if let Some(ratio) = energy.virial_ratio() {
+                ui.label(format!("2T/|U|: {:.3}", ratio));
+                ui.add(egui::Slider::new(&mut virial.tolerance, 0.05..=2.0).text("Warn Beyond ±"));
+                if ui.button("Rescale Velocities To Virial Equilibrium").clicked() {
+                    virial.rescale_requested = true;
+                }
+            }
+
+            if let Some(triggered) = &automation.triggered {
+                ui.separator();
+                ui.label(format!("Stopped: {}", triggered));
+            }
+        });
+}
@@ -0,0 +1,113 @@
+use bevy::prelude::*;
+use bevy::tasks::{AsyncComputeTaskPool, Task, block_on, futures_lite::future};
+use bevy_egui::{EguiContexts, egui};
+use crate::headless::{HeadlessSim, StepTimes};
+use crate::ui_config::UiConfig;
+use crate::SimulationSettings;
+
+pub const BENCHMARK_STEPS: u32 = 200;
+// Runs kept in the table, the oldest go first
+const MAX_RESULTS: usize = 12;
+
+pub struct BenchmarkResult {
+    // what was measured, so runs with different settings can be told apart
+    pub label: String,
+    pub bodies: usize,
+    // averages per step
+    pub times: StepTimes,
+}
+
+// Times the current settings on a fresh set of bodies, away from the frame so the UI keeps going
+#[derive(Resource, Default)]
+pub struct Benchmark {
+    pub requested: bool,
+    pub results: Vec<BenchmarkResult>,
+    task: Option<Task<BenchmarkResult>>,
+}
+
+impl Benchmark {
+    pub fn running(&self) -> bool {
+        self.task.is_some()
+    }
+}
+
+// The bodies a reset would spawn, stepped without collisions like every headless run
+pub fn run_benchmark(settings: &SimulationSettings, steps: u32) -> BenchmarkResult {
+    let mut sim = HeadlessSim::new(settings, settings.seed);
+    let mut total = StepTimes::default();
+    for _ in 0..steps {
+        total.add(sim.step_timed(settings, settings.delta_t));
+    }
+    BenchmarkResult {
+        label: format!(
+            "θ {}, leaf {}, {}",
+            settings.theta,
+            settings.leaf_capacity,
+            settings.force_law.label()
+        ),
+        bodies: sim.bodies.len(),
+        times: total.scaled(1.0 / steps.max(1) as f64),
+    }
+}
+
+pub fn update_benchmark(mut benchmark: ResMut<Benchmark>, settings: Res<SimulationSettings>) {
+    if benchmark.requested && !benchmark.running() {
+        benchmark.requested = false;
+        let settings = settings.clone();
+        benchmark.task =
+            Some(AsyncComputeTaskPool::get().spawn(async move { run_benchmark(&settings, BENCHMARK_STEPS) }));
+    }
+
+    let Some(task) = benchmark.task.as_mut() else {
+        return;
+    };
+    if let Some(result) = block_on(future::poll_once(task)) {
+        info!(
+            "Benchmark {} with {} bodies: {:.3} ms per step",
+            result.label,
+            result.bodies,
+            result.times.total()
+        );
+        benchmark.task = None;
+        benchmark.results.push(result);
+        if benchmark.results.len() > MAX_RESULTS {
+            benchmark.results.remove(0);
+        }
+    }
+}
+
+pub fn benchmark_window(mut contexts: EguiContexts, mut benchmark: ResMut<Benchmark>, ui_config: Res<UiConfig>) {
+    ui_config
+        .place(egui::Window::new("Benchmark"), "Benchmark")
+        .default_open(false)
+        .show(contexts.ctx_mut(), |ui| {
+            ui.horizontal(|ui| {
+                let run = egui::Button::new(format!("Run {} Steps", BENCHMARK_STEPS));
+                if ui.add_enabled(!benchmark.running(), run).clicked() {
+                    benchmark.requested = true;
+                }
+                if benchmark.running() {
+                    ui.spinner();
+                }
+                if ui.button("Clear").clicked() {
+                    benchmark.results.clear();
+                }
+            });
+            ui.label("Runs the current settings on the bodies a reset would spawn, without collisions");
+
+            egui::Grid::new("benchmark results").striped(true).show(ui, |ui| {
+                for heading in ["Settings", "N", "Tree (ms)", "Force (ms)", "Integrate (ms)", "Total (ms)"] {
+                    ui.label(heading);
+                }
+                ui.end_row();
+                for result in &benchmark.results {
+                    ui.label(&result.label);
+                    ui.label(result.bodies.to_string());
+                    for ms in [result.times.tree, result.times.force, result.times.integrate, result.times.total()] {
+                        ui.label(format!("{:.3}", ms));
+                    }
+                    ui.end_row();
+                }
+            });
+        });
+}
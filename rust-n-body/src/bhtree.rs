@@ -1,33 +1,279 @@
 use crate::Body;
+use crate::dualtree::Cell;
+use crate::force::ForceParams;
 use bevy::prelude::*;
+use std::collections::HashMap;
 
 pub struct Quadtree {
     root: TreeNode,
+    // bodies a leaf holds before it is split, the ones sharing a leaf are summed directly
+    leaf_capacity: usize,
+    bodies: usize,
 }
 
 impl Quadtree {
+    // One body per leaf
     pub fn new(quad: Quad) -> Self {
+        Self::with_leaf_capacity(quad, 1)
+    }
+
+    pub fn with_leaf_capacity(quad: Quad, leaf_capacity: usize) -> Self {
         Quadtree {
             root: TreeNode::new(quad),
+            leaf_capacity: leaf_capacity.max(1),
+            bodies: 0,
         }
     }
 
     pub fn insert(&mut self, entity: Entity, transform: Transform, body: Body) {
-        self.root.insert_into_subquad(entity, transform, body);
+        let pos = Vec2::new(transform.translation.x, transform.translation.y);
+
+        if !self.root.quad.contains(pos) {
+            warn!(
+                ?entity,
+                position = ?transform.translation,
+                root = ?self.root.quad,
+                "Position outside the tree"
+            );
+            return;
+        }
+
+        self.root.insert_into_subquad(entity, transform, body, self.leaf_capacity);
+        self.bodies += 1;
     }
 
     pub fn get_total_accel(
-        &mut self,
+        &self,
+        entity: Entity,
+        transform: Transform,
+        body: Body,
+        force: ForceParams,
+        dt: f32,
+        theta: f32,
+    ) -> Vec3 {
+        force.mond_kick(self.get_newtonian_accel(entity, transform, body, force, dt, theta), dt)
+    }
+
+    // get_total_accel without MOND on top. Kicks from several trees have to be added up with this and
+    // MOND applied once to the sum, since it isn't linear.
+    pub fn get_newtonian_accel(
+        &self,
         entity: Entity,
         transform: Transform,
         body: Body,
-        g: f32,
+        force: ForceParams,
         dt: f32,
         theta: f32,
     ) -> Vec3 {
+        self.root.get_total_accel(body, &mut Walk::new(entity, transform, force, dt, theta))
+    }
+
+    // get_total_accel starting from the nodes the last walk for this body ended at, its cut. Most of
+    // them still pass the opening test, so the nodes above them are only tested again once all their
+    // children do, and one that is too close now is opened as usual. If the structure changed under the cut (a node is gone, or its
+    // mass changed by more than WARM_MASS_CHANGE) the walk is done from the root instead. The cut is
+    // replaced with the one this walk ended at. Also returns whether the old cut could be used.
+    pub fn get_total_accel_warm(
+        &self,
+        entity: Entity,
+        transform: Transform,
+        force: ForceParams,
+        dt: f32,
+        theta: f32,
+        cut: &mut Vec<CutNode>,
+    ) -> (Vec3, bool) {
+        let walk = Walk::new(entity, transform, force, dt, theta);
+        let mut next = Vec::with_capacity(cut.len());
+        let mut used = 0;
+        let warm = if cut.is_empty() {
+            None
+        } else {
+            self.root
+                .warm_accel(0, 0, &walk, cut, &mut used, &mut next)
+                .filter(|_| used == cut.len())
+                .map(|(kick, _settled)| kick)
+        };
+        let (kick, hit) = match warm {
+            Some(kick) => (kick, true),
+            None => {
+                next.clear();
+                (self.root.cut_accel(0, 0, &walk, &mut next), false)
+            }
+        };
+        // too deep to be named by the path, these bodies always take the full walk
+        if next.iter().any(|node| node.depth > MAX_CUT_DEPTH) {
+            next.clear();
+        }
+        *cut = next;
+        (force.mond_kick(kick, dt), hit)
+    }
+
+    // Same walk as get_total_accel, but records what the tree did on the way.
+    // Only meant for debugging a single body, the bookkeeping is too slow to do for all of them.
+    pub fn get_force_breakdown(
+        &self,
+        entity: Entity,
+        transform: Transform,
+        body: Body,
+        force: ForceParams,
+        dt: f32,
+        theta: f32,
+    ) -> ForceBreakdown {
+        let mut breakdown = ForceBreakdown::default();
+        let mut walk = Walk {
+            breakdown: Some(&mut breakdown),
+            ..Walk::new(entity, transform, force, dt, theta)
+        };
+        let total = self.root.get_total_accel(body, &mut walk);
+        breakdown.total = force.mond_kick(total, dt);
+        breakdown
+    }
+    // The closest body to pos, which can be a body sitting exactly on pos
+    pub fn nearest_neighbor(&self, pos: Vec2) -> Option<(Entity, Vec2)> {
+        self.knn(pos, 1).into_iter().next()
+    }
+
+    // The k closest bodies to pos, nearest first
+    pub fn knn(&self, pos: Vec2, k: usize) -> Vec<(Entity, Vec2)> {
+        let mut found: Vec<(f32, Entity, Vec2)> = Vec::with_capacity(k + 1);
+        if k > 0 {
+            self.root.knn(pos, k, &mut found);
+        }
+        found.into_iter().map(|(_d, e, p)| (e, p)).collect()
+    }
+
+    // Potential per unit mass at the body's position, from every other body in the tree.
+    // Uses the same opening rule as the force, so it matches what the integrator actually sees.
+    // There's no simple potential with MOND switched on, this is always the Newtonian one.
+    pub fn get_potential(&self, entity: Entity, transform: Transform, force: ForceParams, theta: f32) -> f32 {
+        self.root.get_potential(entity, transform, force, theta)
+    }
+
+    // All bodies inside the rectangle, edges included
+    pub fn query_rect(&self, aabb: Rect) -> impl Iterator<Item = (Entity, Transform, Body)> + '_ {
+        self.query_region(
+            move |quad| {
+                let (min, max) = quad.bounds();
+                min.x <= aabb.max.x && max.x >= aabb.min.x && min.y <= aabb.max.y && max.y >= aabb.min.y
+            },
+            move |pos| aabb.contains(pos),
+        )
+    }
+
+    // All bodies whose center lies within radius of center
+    pub fn query_circle(
+        &self,
+        center: Vec2,
+        radius: f32,
+    ) -> impl Iterator<Item = (Entity, Transform, Body)> + '_ {
+        let r2 = radius * radius;
+        self.query_region(
+            move |quad| quad.distance_squared(center) <= r2,
+            move |pos| pos.distance_squared(center) <= r2,
+        )
+    }
+
+    // Walks the tree with an explicit stack, skipping every quad the region does not touch
+    fn query_region<'a>(
+        &'a self,
+        overlaps: impl Fn(&Quad) -> bool + 'a,
+        inside: impl Fn(Vec2) -> bool + 'a,
+    ) -> impl Iterator<Item = (Entity, Transform, Body)> + 'a {
+        let mut stack: Vec<&Subquad> = self.root.children().to_vec();
+        // what is left of the leaf currently being handed out
+        let mut leaf: &[(Entity, Transform, Body)] = &[];
+
+        std::iter::from_fn(move || {
+            loop {
+                while let Some((tuple, rest)) = leaf.split_first() {
+                    leaf = rest;
+                    if inside(Vec2::new(tuple.1.translation.x, tuple.1.translation.y)) {
+                        return Some(*tuple);
+                    }
+                }
+                let subquad = stack.pop()?;
+                if !overlaps(&subquad.quad) {
+                    continue;
+                }
+                match &subquad.node {
+                    Some(node) => stack.extend(node.children()),
+                    None => leaf = &subquad.bodies,
+                }
+            }
+        })
+    }
+
+    // Bodies that made it into the tree, the ones outside the root are left out
+    pub fn body_count(&self) -> usize {
+        self.bodies
+    }
+
+    pub fn total_mass(&self) -> f32 {
+        self.root.children().iter().map(|child| child.mass).sum()
+    }
+
+    // Internal nodes, each one owns the four boxed subquads below it
+    pub fn node_count(&self) -> usize {
+        self.root.node_count()
+    }
+
+    // Heap plus inline size of the whole tree, for the memory readout
+    pub fn memory_bytes(&self) -> usize {
+        size_of::<Quadtree>()
+            + self.node_count() * 4 * size_of::<Subquad>()
+            + self.bodies * size_of::<(Entity, Transform, Body)>()
+    }
+
+    fn center_of_mass(&self) -> Vec2 {
+        let mass = self.total_mass();
+        if mass <= 0.0 {
+            return Vec2::ZERO;
+        }
         self.root
-            .get_total_accel(entity, transform, body, g, dt, theta)
+            .children()
+            .iter()
+            .map(|child| child.mass * child.pos_mass.truncate())
+            .sum::<Vec2>()
+            / mass
+    }
+
+    // Every quad in the tree, the root first and each quad's children (nw, ne, sw, se) right after it
+    pub fn nodes(&self) -> Vec<NodeInfo> {
+        let (min, max) = self.root.quad.bounds();
+        let mut nodes = vec![NodeInfo {
+            depth: 0,
+            parent: None,
+            min,
+            max,
+            mass: self.total_mass(),
+            com: self.center_of_mass(),
+            bodies: self.bodies,
+            leaf: false,
+            entities: Vec::new(),
+        }];
+        self.root.collect_nodes(0, 1, &mut nodes);
+        nodes
     }
+
+    // A flat copy for the walks in dualtree.rs and buckets.rs: only the quads with bodies in them, the children of every
+    // node next to each other and the bodies below every node a contiguous run of the body list
+    pub fn flatten(&self) -> (Vec<Cell>, Vec<(Entity, Vec2, f32)>) {
+        let mut cells = Vec::new();
+        let mut bodies = Vec::with_capacity(self.bodies);
+        if self.bodies == 0 {
+            return (cells, bodies);
+        }
+        cells.push(Cell {
+            size: self.root.quad.size,
+            mass: self.total_mass(),
+            com: self.center_of_mass(),
+            children: 0..0,
+            bodies: 0..0,
+        });
+        self.root.flatten(0, &mut cells, &mut bodies);
+        (cells, bodies)
+    }
+
     pub fn draw_tree(&self, mut gizmos: Gizmos) {
         fn draw_node(node: &TreeNode, gizmos: &mut Gizmos) {
             gizmos.rect_2d(
@@ -38,7 +284,7 @@ impl Quadtree {
         }
 
         fn recurse(gizmos: &mut Gizmos, node: &TreeNode) {
-            draw_node(&node, gizmos);
+            draw_node(node, gizmos);
 
             if let Some(child) = &node.nw.node {
                 recurse(gizmos, child);
@@ -57,6 +303,132 @@ impl Quadtree {
     }
 }
 
+// What a walk down the tree carries along: the body it is for, the force summed over the step, how far
+// away a node has to be to be taken whole, and the breakdown to record into when one was asked for
+struct Walk<'a> {
+    entity: Entity,
+    transform: Transform,
+    force: ForceParams,
+    dt: f32,
+    theta: f32,
+    breakdown: Option<&'a mut ForceBreakdown>,
+}
+
+impl Walk<'_> {
+    fn new(entity: Entity, transform: Transform, force: ForceParams, dt: f32, theta: f32) -> Self {
+        Walk {
+            entity,
+            transform,
+            force,
+            dt,
+            theta,
+            breakdown: None,
+        }
+    }
+}
+
+#[derive(Default, Debug, Clone)]
+pub struct ForceBreakdown {
+    // internal nodes that were too close and had to be opened
+    pub opened: u32,
+    // internal nodes treated as a single body at their center of mass
+    pub approximated: u32,
+    // leaf bodies summed directly
+    pub direct: u32,
+    pub total: Vec3,
+    pub largest: Vec3,
+    pub largest_source: Vec3,
+    pub approximated_coms: Vec<Vec3>,
+}
+
+impl ForceBreakdown {
+    fn record(&mut self, accel: Vec3, source: Vec3) {
+        if accel.length_squared() > self.largest.length_squared() {
+            self.largest = accel;
+            self.largest_source = source;
+        }
+    }
+}
+
+// One quad of the tree, for looking at the structure from outside
+#[derive(Clone, Debug, PartialEq)]
+pub struct NodeInfo {
+    pub depth: u32,
+    // index of the parent in the list, None for the root
+    pub parent: Option<usize>,
+    pub min: Vec2,
+    pub max: Vec2,
+    pub mass: f32,
+    pub com: Vec2,
+    // bodies anywhere below the quad
+    pub bodies: usize,
+    pub leaf: bool,
+    // what a leaf holds, empty for internal nodes
+    pub entities: Vec<Entity>,
+}
+
+// Relative change in a cached node's mass that means bodies moved in or out and the cut is stale
+const WARM_MASS_CHANGE: f32 = 0.1;
+// Two bits of the path per level
+const MAX_CUT_DEPTH: u32 = 32;
+
+// A node a body's force walk ended at, taken whole at its center of mass or a leaf summed body by
+// body. Nodes are named by the child indices on the way down from the root, which still name the
+// same part of the tree after it is rebuilt as long as the structure above didn't change.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct CutNode {
+    depth: u32,
+    path: u64,
+    mass: f32,
+}
+
+impl CutNode {
+    fn child_key(depth: u32, path: u64, child: usize) -> (u32, u64) {
+        let path = if depth < MAX_CUT_DEPTH { path << 2 | child as u64 } else { path };
+        (depth + 1, path)
+    }
+
+    // Whether this node lies below the node with the given key
+    fn below(&self, depth: u32, path: u64) -> bool {
+        self.depth > depth && self.path >> (2 * (self.depth - depth)) == path
+    }
+}
+
+// Every body's cut from the last step, for get_total_accel_warm
+#[derive(Default)]
+pub struct WarmStart {
+    cuts: HashMap<Entity, Vec<CutNode>>,
+    next: HashMap<Entity, Vec<CutNode>>,
+}
+
+impl WarmStart {
+    pub fn accel(
+        &mut self,
+        tree: &Quadtree,
+        entity: Entity,
+        transform: Transform,
+        force: ForceParams,
+        dt: f32,
+        theta: f32,
+    ) -> Vec3 {
+        let mut cut = self.cuts.remove(&entity).unwrap_or_default();
+        let (accel, _warm) = tree.get_total_accel_warm(entity, transform, force, dt, theta, &mut cut);
+        self.next.insert(entity, cut);
+        accel
+    }
+
+    // Once every body has had its walk. Bodies that are gone lose their cut here.
+    pub fn finish_step(&mut self) {
+        std::mem::swap(&mut self.cuts, &mut self.next);
+        self.next.clear();
+    }
+
+    pub fn clear(&mut self) {
+        self.cuts.clear();
+        self.next.clear();
+    }
+}
+
 struct TreeNode {
     quad: Quad,
     nw: Box<Subquad>,
@@ -67,51 +439,40 @@ struct TreeNode {
 
 impl TreeNode {
     fn new(quad: Quad) -> Self {
-        let h = quad.size / 2.0;
-        let q = h / 2.0;
+        let [nw, ne, sw, se] = quad.subdivide();
 
         TreeNode {
-            nw: Box::new(Subquad::new(quad.center.x - q, quad.center.y + q, h)),
-            ne: Box::new(Subquad::new(quad.center.x + q, quad.center.y + q, h)),
-            sw: Box::new(Subquad::new(quad.center.x - q, quad.center.y - q, h)),
-            se: Box::new(Subquad::new(quad.center.x + q, quad.center.y - q, h)),
+            nw: Box::new(Subquad::new(nw)),
+            ne: Box::new(Subquad::new(ne)),
+            sw: Box::new(Subquad::new(sw)),
+            se: Box::new(Subquad::new(se)),
             quad,
         }
     }
 
-    fn insert_into_subquad(&mut self, entity: Entity, transform: Transform, body: Body) {
+    fn insert_into_subquad(&mut self, entity: Entity, transform: Transform, body: Body, capacity: usize) {
         let pos = Vec2::new(transform.translation.x, transform.translation.y);
 
-        if !self.quad.contains(pos) {
-            eprintln!(
-                "\nPosition not found in any quads!: {:?}.\nTree node: \n{:?} \nQuads: \nnw: {:?} \nne: {:?} \nsw: {:?} \nse: {:?}",
-                transform.translation,
-                &self.quad,
-                &self.nw.quad,
-                &self.ne.quad,
-                &self.sw.quad,
-                &self.se.quad,
-            );
-            return;
-        }
-
+        // Containment is only checked once at the root, below that the body is routed by comparing
+        // against the center alone. The split is half-open like Quad::contains, so every position
+        // inside the root ends up in exactly one child, even when it lies on a shared edge.
         if pos.y < self.quad.center.y {
             // going south
             if pos.x < self.quad.center.x {
                 // going west
-                self.sw.insert_or_divide(entity, transform, body);
+                self.sw.insert_or_divide(entity, transform, body, capacity);
             } else {
                 // going east with ambiguous cases
-                self.se.insert_or_divide(entity, transform, body);
+                self.se.insert_or_divide(entity, transform, body, capacity);
             }
         } else {
             // going north
             if pos.x < self.quad.center.x {
                 // going west
-                self.nw.insert_or_divide(entity, transform, body);
+                self.nw.insert_or_divide(entity, transform, body, capacity);
             } else {
                 // going east with amb cases
-                self.ne.insert_or_divide(entity, transform, body);
+                self.ne.insert_or_divide(entity, transform, body, capacity);
             }
         }
 
@@ -138,97 +499,313 @@ impl TreeNode {
         */
     }
 
-    fn get_total_accel(
+    fn children(&self) -> [&Subquad; 4] {
+        [&*self.nw, &*self.ne, &*self.sw, &*self.se]
+    }
+
+    fn node_count(&self) -> usize {
+        1 + self
+            .children()
+            .iter()
+            .filter_map(|child| child.node.as_ref())
+            .map(|node| node.node_count())
+            .sum::<usize>()
+    }
+
+    fn flatten(&self, index: usize, cells: &mut Vec<Cell>, bodies: &mut Vec<(Entity, Vec2, f32)>) {
+        let start = bodies.len();
+        // a split quad always has bodies below it
+        let children: Vec<&Subquad> = self
+            .children()
+            .into_iter()
+            .filter(|child| child.node.is_some() || !child.bodies.is_empty())
+            .collect();
+        let first = cells.len();
+        for child in &children {
+            cells.push(Cell {
+                size: child.quad.size,
+                mass: child.mass,
+                com: child.pos_mass.truncate(),
+                children: 0..0,
+                bodies: 0..0,
+            });
+        }
+        cells[index].children = first..first + children.len();
+        for (k, child) in children.into_iter().enumerate() {
+            match &child.node {
+                Some(node) => node.flatten(first + k, cells, bodies),
+                None => {
+                    let leaf_start = bodies.len();
+                    bodies.extend(
+                        child
+                            .bodies
+                            .iter()
+                            .map(|(entity, transform, body)| (*entity, transform.translation.truncate(), body.mass)),
+                    );
+                    cells[first + k].bodies = leaf_start..bodies.len();
+                }
+            }
+        }
+        cells[index].bodies = start..bodies.len();
+    }
+
+    fn collect_nodes(&self, parent: usize, depth: u32, nodes: &mut Vec<NodeInfo>) {
+        for child in self.children() {
+            let (min, max) = child.quad.bounds();
+            let index = nodes.len();
+            nodes.push(NodeInfo {
+                depth,
+                parent: Some(parent),
+                min,
+                max,
+                mass: child.mass,
+                com: child.pos_mass.truncate(),
+                bodies: child.body_count(),
+                leaf: child.node.is_none(),
+                entities: child.bodies.iter().map(|(entity, _t, _b)| *entity).collect(),
+            });
+            if let Some(node) = &child.node {
+                node.collect_nodes(index, depth + 1, nodes);
+            }
+        }
+    }
+
+    // The children of this node at the given key, walked from the root down and every node the walk
+    // ends at added to the cut
+    fn cut_accel(&self, depth: u32, path: u64, walk: &Walk, cut: &mut Vec<CutNode>) -> Vec3 {
+        let mut cum_accel = Vec3::ZERO;
+        for (i, child) in self.children().into_iter().enumerate() {
+            let (depth, path) = CutNode::child_key(depth, path, i);
+            cum_accel += match &child.node {
+                Some(node) if !accepts(child, walk.transform, walk.theta) => node.cut_accel(depth, path, walk, cut),
+                Some(_node) => {
+                    cut.push(CutNode { depth, path, mass: child.mass });
+                    calc_accel(child.mass, walk.transform.translation, child.pos_mass, walk.dt, walk.force)
+                }
+                None => {
+                    cut.push(CutNode { depth, path, mass: child.mass });
+                    leaf_accel(child, walk)
+                }
+            };
+        }
+        cum_accel
+    }
+
+    // Goes straight down to the cached cut below this node, None if the tree no longer matches it.
+    // The sums are nested the same way as in cut_accel, so an unchanged tree gives the same bits.
+    // A node whose children all ended the walk is tested again on the way back up, and taken whole
+    // if it passes now, so a cut shrinks again as the body moves away instead of only ever growing.
+    // Also returns whether every child of this node ended the walk.
+    fn warm_accel(
         &self,
-        entity: Entity,
-        transform: Transform,
-        body: Body,
-        g: f32,
-        dt: f32,
-        theta: f32,
-    ) -> Vec3 {
+        depth: u32,
+        path: u64,
+        walk: &Walk,
+        cut: &[CutNode],
+        used: &mut usize,
+        next: &mut Vec<CutNode>,
+    ) -> Option<(Vec3, bool)> {
+        let mut cum_accel = Vec3::ZERO;
+        let mut settled = true;
+        for (i, child) in self.children().into_iter().enumerate() {
+            let (depth, path) = CutNode::child_key(depth, path, i);
+            let cached = cut.get(*used)?;
+            if cached.depth == depth && cached.path == path {
+                *used += 1;
+                if (child.mass - cached.mass).abs() > WARM_MASS_CHANGE * child.mass.max(cached.mass) {
+                    return None;
+                }
+                cum_accel += match &child.node {
+                    Some(node) if !accepts(child, walk.transform, walk.theta) => {
+                        settled = false;
+                        node.cut_accel(depth, path, walk, next)
+                    }
+                    Some(_node) => {
+                        next.push(CutNode { depth, path, mass: child.mass });
+                        calc_accel(child.mass, walk.transform.translation, child.pos_mass, walk.dt, walk.force)
+                    }
+                    None => {
+                        next.push(CutNode { depth, path, mass: child.mass });
+                        leaf_accel(child, walk)
+                    }
+                };
+            } else if cached.below(depth, path) {
+                let node = child.node.as_ref()?;
+                let start = next.len();
+                let (accel, below) = node.warm_accel(depth, path, walk, cut, used, next)?;
+                if below && accepts(child, walk.transform, walk.theta) {
+                    next.truncate(start);
+                    next.push(CutNode { depth, path, mass: child.mass });
+                    cum_accel +=
+                        calc_accel(child.mass, walk.transform.translation, child.pos_mass, walk.dt, walk.force);
+                } else {
+                    settled = false;
+                    cum_accel += accel;
+                }
+            } else {
+                return None;
+            }
+        }
+        Some((cum_accel, settled))
+    }
+
+    // Branch and bound: visit the closest children first, and skip any quad that is further away
+    // than the k-th best body found so far
+    fn knn(&self, pos: Vec2, k: usize, found: &mut Vec<(f32, Entity, Vec2)>) {
+        let mut children = [&self.nw, &self.ne, &self.sw, &self.se];
+        children.sort_by(|a, b| {
+            a.quad
+                .distance_squared(pos)
+                .total_cmp(&b.quad.distance_squared(pos))
+        });
+
+        for child in children {
+            if found.len() == k && child.quad.distance_squared(pos) > found[k - 1].0 {
+                continue;
+            }
+
+            match &child.node {
+                Some(node) => node.knn(pos, k, found),
+                None => {
+                    for (entity, transform, _body) in &child.bodies {
+                        let p = Vec2::new(transform.translation.x, transform.translation.y);
+                        let d = p.distance_squared(pos);
+                        let i = found.partition_point(|(fd, _e, _p)| *fd <= d);
+                        if i < k {
+                            found.insert(i, (d, *entity, p));
+                            found.truncate(k);
+                        }
+                    }
+                }
+            }
+        }
+    }
+
+    fn get_potential(&self, entity: Entity, transform: Transform, force: ForceParams, theta: f32) -> f32 {
+        self.children()
+            .into_iter()
+            .map(|child| get_subquad_potential(child, entity, transform, force, theta))
+            .sum()
+    }
+
+    fn get_total_accel(&self, body: Body, walk: &mut Walk) -> Vec3 {
         let mut cum_accel = Vec3::ZERO;
 
-        cum_accel += get_accel(&self.nw, entity, transform, body, g, dt, theta);
-        cum_accel += get_accel(&self.ne, entity, transform, body, g, dt, theta);
-        cum_accel += get_accel(&self.sw, entity, transform, body, g, dt, theta);
-        cum_accel += get_accel(&self.se, entity, transform, body, g, dt, theta);
+        cum_accel += get_accel(&self.nw, body, walk);
+        cum_accel += get_accel(&self.ne, body, walk);
+        cum_accel += get_accel(&self.sw, body, walk);
+        cum_accel += get_accel(&self.se, body, walk);
 
         cum_accel
     }
 }
 
-fn get_accel(
-    subquad: &Box<Subquad>,
-    entity: Entity,
-    transform: Transform,
-    body: Body,
-    g: f32,
-    dt: f32,
-    theta: f32,
-) -> Vec3 {
+fn get_subquad_potential(subquad: &Subquad, entity: Entity, transform: Transform, force: ForceParams, theta: f32) -> f32 {
+    match &subquad.node {
+        None => subquad
+            .bodies
+            .iter()
+            .filter(|tuple| tuple.0.index() != entity.index())
+            .map(|tuple| calc_potential(tuple.2.mass, transform.translation, tuple.1.translation, force))
+            .sum(),
+        Some(next_node) => {
+            if accepts(subquad, transform, theta) {
+                calc_potential(subquad.mass, transform.translation, subquad.pos_mass, force)
+            } else {
+                next_node.get_potential(entity, transform, force, theta)
+            }
+        }
+    }
+}
+
+fn get_accel(subquad: &Subquad, body: Body, walk: &mut Walk) -> Vec3 {
     match &subquad.node {
         None => {
-            // Node is a leaf
-            match subquad.entity {
-                // With an occupant
-                Some(tuple) => {
-                    if tuple.0.index() == entity.index() {
-                        return Vec3::ZERO;
-                    } else {
-                        return calc_accel(
-                            tuple.2.mass,
-                            transform.translation,
-                            tuple.1.translation,
-                            dt,
-                            g,
-                        );
-                    }
+            // Node is a leaf, every occupant is summed exactly. Nobody home gives zero.
+            let mut cum_accel = Vec3::ZERO;
+            for tuple in &subquad.bodies {
+                if tuple.0.index() == walk.entity.index() {
+                    continue;
                 }
-                None => {
-                    // Nobody home;
-                    return Vec3::ZERO;
+                let accel = calc_accel(
+                    tuple.2.mass,
+                    walk.transform.translation,
+                    tuple.1.translation,
+                    walk.dt,
+                    walk.force,
+                );
+                if let Some(breakdown) = &mut walk.breakdown {
+                    breakdown.direct += 1;
+                    breakdown.record(accel, tuple.1.translation);
                 }
+                cum_accel += accel;
             }
+            cum_accel
         }
         Some(next_node) => {
             // Node is an internal node
-            // S =  quad size
-            // d = distance between node center of mass and body
-
-            let s = subquad.quad.size;
-            let d = transform.translation.distance(next_node.nw.pos_mass);
-
-            if s / d < theta {
-                return calc_accel(subquad.mass, transform.translation, subquad.pos_mass, dt, g);
+            if accepts(subquad, walk.transform, walk.theta) {
+                let accel = calc_accel(subquad.mass, walk.transform.translation, subquad.pos_mass, walk.dt, walk.force);
+                if let Some(breakdown) = &mut walk.breakdown {
+                    breakdown.approximated += 1;
+                    breakdown.approximated_coms.push(subquad.pos_mass);
+                    breakdown.record(accel, subquad.pos_mass);
+                }
+                accel
             } else {
                 // node is too close to be treated as one. DIG DEEPER!!
-                next_node.get_total_accel(entity, transform, body, g, dt, theta)
+                if let Some(breakdown) = &mut walk.breakdown {
+                    breakdown.opened += 1;
+                }
+                next_node.get_total_accel(body, walk)
             }
         }
     }
 }
 
-fn calc_accel(m2: f32, t1: Vec3, t2: Vec3, dt: f32, g: f32) -> Vec3 {
-    let r = t2 - t1;
+// The opening criterion, whether an internal node is far enough away to be taken as one body
+fn accepts(subquad: &Subquad, transform: Transform, theta: f32) -> bool {
+    // S =  quad size
+    // d = distance between node center of mass and body
+    let s = subquad.quad.size;
+    let d = transform.translation.distance(subquad.pos_mass);
+    s / d < theta
+}
 
-    let mag = r.length();
-    g * (m2 / mag) * r.normalize() * dt
+// Every occupant of a leaf summed exactly, leaving out the body itself
+fn leaf_accel(subquad: &Subquad, walk: &Walk) -> Vec3 {
+    let mut cum_accel = Vec3::ZERO;
+    for tuple in &subquad.bodies {
+        if tuple.0.index() != walk.entity.index() {
+            cum_accel += calc_accel(tuple.2.mass, walk.transform.translation, tuple.1.translation, walk.dt, walk.force);
+        }
+    }
+    cum_accel
+}
+
+// Velocity change over dt from the pull of m2
+pub fn calc_accel(m2: f32, t1: Vec3, t2: Vec3, dt: f32, force: ForceParams) -> Vec3 {
+    force.accel(m2, t1, t2) * dt
+}
+
+pub fn calc_potential(m2: f32, t1: Vec3, t2: Vec3, force: ForceParams) -> f32 {
+    force.potential(m2, t1, t2)
 }
 
 struct Subquad {
     quad: Quad,
-    entity: Option<(Entity, Transform, Body)>,
+    // occupants of a leaf, up to the tree's leaf capacity. Empty once the leaf is split.
+    bodies: Vec<(Entity, Transform, Body)>,
     node: Option<TreeNode>,
     mass: f32,
     pos_mass: Vec3,
 }
 
 impl Subquad {
-    fn new(x: f32, y: f32, size: f32) -> Self {
+    fn new(quad: Quad) -> Self {
         Subquad {
-            quad: Quad::new(x, y, size),
-            entity: Option::None,
+            quad,
+            bodies: Vec::new(),
             node: Option::None,
             mass: 0.0,
             pos_mass: Vec3 {
@@ -239,79 +816,57 @@ impl Subquad {
         }
     }
 
-    fn insert_or_divide(&mut self, entity: Entity, transform: Transform, body: Body) {
+    fn body_count(&self) -> usize {
+        match &self.node {
+            Some(node) => node.children().iter().map(|child| child.body_count()).sum(),
+            None => self.bodies.len(),
+        }
+    }
+
+    // Update center of mass and total mass with a body that ends up somewhere below this quad
+    fn add_mass(&mut self, transform: Transform, body: Body) {
+        let m1 = self.mass;
+        let m2 = body.mass;
+        let m = m1 + m2;
+        let x1 = self.pos_mass.x;
+        let x2 = transform.translation.x;
+        let y1 = self.pos_mass.y;
+        let y2 = transform.translation.y;
+
+        let x = (x1 * m1 + x2 * m2) / m;
+        let y = (y1 * m1 + y2 * m2) / m;
+
+        self.mass = m;
+        self.pos_mass.x = x;
+        self.pos_mass.y = y;
+    }
+
+    fn insert_or_divide(&mut self, entity: Entity, transform: Transform, body: Body, capacity: usize) {
+        self.add_mass(transform, body);
+
         match &mut self.node {
             Some(node) => {
-                // Node Is internal. Updat center of mass and total mass, and insert into subquadrants
-                let m1 = self.mass;
-                let m2 = body.mass;
-                let m = m1 + m2;
-                let x1 = self.pos_mass.x;
-                let x2 = transform.translation.x;
-                let y1 = self.pos_mass.y;
-                let y2 = transform.translation.y;
-
-                let x = (x1 * m1 + x2 * m2) / m;
-                let y = (y1 * m1 + y2 * m2) / m;
-
-                self.mass = m;
-                self.pos_mass.x = x;
-                self.pos_mass.y = y;
-
-                node.insert_into_subquad(entity, transform, body);
+                // Node Is internal, insert into subquadrants
+                node.insert_into_subquad(entity, transform, body, capacity);
             }
             None => {
-                // Node is leaf. Insert if no body, or subdivide if occupied
-                match self.entity {
-                    None => {
-                        // No body present
-                        self.entity = Some((entity, transform, body));
-                    }
-                    Some(tuple) => {
-                        // Node is occupied. We must dig deeper!!!1
-
-                        if self.quad.size < 1.0 {
-                            // unless node is too small.
-                            // To avoid weird edge cases where it cannot be computed if a position is in a quad,
-                            // we just add the mass and update center of mass of the node.
-                            let m1 = self.mass;
-                            let m2 = body.mass;
-                            let m = m1 + m2;
-                            let x1 = self.pos_mass.x;
-                            let x2 = transform.translation.x;
-                            let y1 = self.pos_mass.y;
-                            let y2 = transform.translation.y;
-
-                            let x = (x1 * m1 + x2 * m2) / m;
-                            let y = (y1 * m1 + y2 * m2) / m;
-
-                            self.mass = m;
-                            self.pos_mass.x = x;
-                            self.pos_mass.y = y;
-                        } else {
-                            let mut new_node = TreeNode::new(self.quad);
-
-                            new_node.insert_into_subquad(entity, transform, body);
-                            new_node.insert_into_subquad(tuple.0, tuple.1, tuple.2);
-
-                            let m1 = self.mass;
-                            let m2 = body.mass;
-                            let m = m1 + m2;
-                            let x1 = self.pos_mass.x;
-                            let x2 = transform.translation.x;
-                            let y1 = self.pos_mass.y;
-                            let y2 = transform.translation.y;
-
-                            let x = (x1 * m1 + x2 * m2) / m;
-                            let y = (y1 * m1 + y2 * m2) / m;
-
-                            self.mass = m;
-                            self.pos_mass.x = x;
-                            self.pos_mass.y = y;
-
-                            self.node = Some(new_node);
-                        }
+                // Node is leaf. Insert if there is room left, or subdivide if full.
+                // A leaf that is too small is never split, to avoid weird edge cases where it
+                // cannot be computed if a position is in a quad. It just holds more than capacity.
+                // An infinite one can't be split either, its children would be infinite too.
+                if self.bodies.len() < capacity || self.quad.size < 1.0 || !self.quad.size.is_finite() {
+                    self.bodies.push((entity, transform, body));
+                } else {
+                    // Leaf is full. We must dig deeper!!!1
+                    let mut new_node = TreeNode::new(self.quad);
+
+                    for (e, t, b) in self.bodies.drain(..) {
+                        new_node.insert_into_subquad(e, t, b, capacity);
                     }
+                    new_node.insert_into_subquad(entity, transform, body, capacity);
+
+                    self.bodies = Vec::new();
+                    self.node = Some(new_node);
                 }
             }
         }
@@ -345,20 +900,57 @@ impl Quad {
             max_y = max_y.max(p.y);
         }
 
-        let center = Vec2::new(min_x + max_x, min_y + max_y) * 0.5;
-        let size = (max_x - min_x).max(max_y - min_y) + 1.0;
+        Self::from_bounds(Vec2::new(min_x, min_y), Vec2::new(max_x, max_y))
+    }
 
-        Self { center, size }
+    // Square quad around the given corners, padded a little so the max edge is still inside
+    pub fn from_bounds(min: Vec2, max: Vec2) -> Self {
+        // halved first, the sum of two huge coordinates would overflow
+        let center = min * 0.5 + max * 0.5;
+        let size = (max.x - min.x).max(max.y - min.y) + 1.0;
+        let mut quad = Self { center, size };
+        // Far from the origin the padding rounds away, and the max corner would fall out of the half-open quad
+        if !quad.contains(max) {
+            quad.size += min.abs().max(max.abs()).max_element() * f32::EPSILON * 4.0;
+        }
+        quad
     }
 
-    fn contains(&self, pos: Vec2) -> bool {
+    // Half-open on both axes: [min, max). A point on an edge shared by two quads belongs to the
+    // one on its north/east side, which matches how TreeNode routes bodies by the center.
+    pub fn contains(&self, pos: Vec2) -> bool {
         let hl = self.size / 2.0;
-        let eps = hl * 0.0001;
-        /*         (pos.x >= self.center.x - hl - eps)
-        && (pos.x <= self.center.x + hl + eps)
-        && (pos.y >= self.center.y - hl - eps)
-        && (pos.y <= self.center.y + hl + eps) */
 
-        (self.center.x - pos.x).abs() - eps <= hl && (self.center.y - pos.y).abs() - eps <= hl
+        pos.x >= self.center.x - hl
+            && pos.x < self.center.x + hl
+            && pos.y >= self.center.y - hl
+            && pos.y < self.center.y + hl
+    }
+
+    // Min and max corners
+    pub fn bounds(&self) -> (Vec2, Vec2) {
+        let hl = Vec2::splat(self.size / 2.0);
+        (self.center - hl, self.center + hl)
+    }
+
+    // Squared distance from pos to the closest point of the quad, zero when pos is inside
+    pub fn distance_squared(&self, pos: Vec2) -> f32 {
+        let hl = self.size / 2.0;
+        let dx = ((pos.x - self.center.x).abs() - hl).max(0.0);
+        let dy = ((pos.y - self.center.y).abs() - hl).max(0.0);
+        dx * dx + dy * dy
+    }
+
+    // Children in nw, ne, sw, se order
+    pub fn subdivide(&self) -> [Quad; 4] {
+        let h = self.size / 2.0;
+        let q = h / 2.0;
+
+        [
+            Quad::new(self.center.x - q, self.center.y + q, h),
+            Quad::new(self.center.x + q, self.center.y + q, h),
+            Quad::new(self.center.x - q, self.center.y - q, h),
+            Quad::new(self.center.x + q, self.center.y - q, h),
+        ]
     }
 }
@@ -0,0 +1,201 @@
+use bevy::prelude::*;
+use bevy_egui::{EguiContexts, egui};
+use crate::bhtree::Quad;
+use crate::clock::SimulationClock;
+use crate::energy::Energy;
+use crate::minimap::bin_bodies;
+use crate::ui_config::UiConfig;
+use crate::undo::{BodyState, Snapshot, UndoBodies, UndoHistory, restore};
+use crate::SimulationSettings;
+
+// Side of the thumbnails in pixels
+const THUMBNAIL: usize = 64;
+// Most bookmarks kept and most bodies in all of them together, like the undo history. Past either a new
+// bookmark is refused instead of dropping an old one, they were all made on purpose.
+pub const MAX_BOOKMARKS: usize = 50;
+pub const MAX_BOOKMARKED_BODIES: usize = 2_000_000;
+
+// A moment of the run kept in memory to come back to, with a picture of what it looked like.
+// The picture is empty once it was dropped to save memory.
+pub struct Bookmark {
+    pub name: String,
+    pub snapshot: Snapshot,
+    pub thumbnail: Vec<u8>,
+    texture: Option<egui::TextureHandle>,
+}
+
+impl Bookmark {
+    pub fn new(name: String, snapshot: Snapshot) -> Self {
+        let positions: Vec<Vec2> = snapshot
+            .bodies
+            .iter()
+            .filter(|s| !s.tracer)
+            .map(|s| s.transform.translation.truncate())
+            .collect();
+        Bookmark {
+            name,
+            thumbnail: thumbnail_pixels(&positions, THUMBNAIL),
+            snapshot,
+            texture: None,
+        }
+    }
+}
+
+#[derive(Resource, Default)]
+pub struct Bookmarks {
+    pub list: Vec<Bookmark>,
+    pub name: String,
+    pub capture_requested: bool,
+    pub jump_requested: Option<usize>,
+    // taken by the branch, which runs the bookmark next to the original
+    pub fork_requested: Option<usize>,
+}
+
+impl Bookmarks {
+    fn stored_bodies(&self) -> usize {
+        self.list.iter().map(|b| b.snapshot.bodies.len()).sum()
+    }
+
+    // Whether a bookmark of this many bodies can still be kept
+    pub fn has_room(&self, bodies: usize) -> bool {
+        self.list.len() < MAX_BOOKMARKS && self.stored_bodies() + bodies <= MAX_BOOKMARKED_BODIES
+    }
+
+    pub fn memory_bytes(&self) -> usize {
+        self.stored_bodies() * size_of::<BodyState>() + self.thumbnail_bytes()
+    }
+
+    // The thumbnails and their textures, which egui keeps as RGBA
+    pub fn thumbnail_bytes(&self) -> usize {
+        self.list
+            .iter()
+            .map(|b| b.thumbnail.len() + if b.texture.is_some() { 4 * b.thumbnail.len() } else { 0 })
+            .sum()
+    }
+
+    pub fn drop_thumbnails(&mut self) {
+        for bookmark in &mut self.list {
+            bookmark.thumbnail = Vec::new();
+            bookmark.texture = None;
+        }
+    }
+}
+
+// Gray pixels of the bodies over their bounding square, row by row from the top
+pub fn thumbnail_pixels(positions: &[Vec2], side: usize) -> Vec<u8> {
+    if positions.is_empty() {
+        return vec![0; side * side];
+    }
+    let (min, max) = Quad::new_containing(positions).bounds();
+    bin_bodies(positions, min, (max - min).x, side)
+        .into_iter()
+        .map(|count| if count == 0 { 0 } else { (120 + 40 * count.ilog2()).min(255) as u8 })
+        .collect()
+}
+
+pub fn update_bookmarks(
+    mut bookmarks: ResMut<Bookmarks>,
+    mut history: ResMut<UndoHistory>,
+    bodies: UndoBodies,
+    mut clock: ResMut<SimulationClock>,
+    mut energy: ResMut<Energy>,
+    settings: Res<SimulationSettings>,
+    mut commands: Commands,
+    mut materials: ResMut<Assets<ColorMaterial>>,
+    mut meshes: ResMut<Assets<Mesh>>,
+) {
+    if bookmarks.capture_requested {
+        bookmarks.capture_requested = false;
+        if bookmarks.has_room(bodies.iter().len()) {
+            let snapshot = Snapshot::capture("Bookmark", &bodies, &clock);
+            let name = if bookmarks.name.trim().is_empty() {
+                format!("t = {:.3}", clock.time)
+            } else {
+                std::mem::take(&mut bookmarks.name)
+            };
+            info!("Bookmarked {} with {} bodies", name, snapshot.bodies.len());
+            bookmarks.list.push(Bookmark::new(name, snapshot));
+        } else {
+            warn!("No room for another bookmark, delete one first");
+        }
+    }
+
+    let Some(bookmark) = bookmarks.jump_requested.take().and_then(|i| bookmarks.list.get(i)) else {
+        return;
+    };
+    // jumping away is as destructive as a reset, so it can be undone
+    history.record(Snapshot::capture("Jump To Bookmark", &bodies, &clock));
+    restore(&bookmark.snapshot, &bodies, &settings, &mut commands, &mut materials, &mut meshes);
+    clock.time = bookmark.snapshot.time;
+    clock.steps = bookmark.snapshot.steps;
+    energy.reset();
+}
+
+pub fn bookmarks_window(mut contexts: EguiContexts, mut bookmarks: ResMut<Bookmarks>, ui_config: Res<UiConfig>) {
+    let ctx = contexts.ctx_mut().clone();
+    ui_config
+        .place(egui::Window::new("Bookmarks"), "Bookmarks")
+        .default_open(false)
+        .show(&ctx, |ui| {
+            ui.horizontal(|ui| {
+                ui.add(egui::TextEdit::singleline(&mut bookmarks.name).hint_text("Name"));
+                if ui.button("Bookmark Current State").clicked() {
+                    bookmarks.capture_requested = true;
+                }
+            });
+            ui.label(format!(
+                "{} of {} bookmarks, {} bodies kept in memory",
+                bookmarks.list.len(),
+                MAX_BOOKMARKS,
+                bookmarks.stored_bodies()
+            ));
+
+            let mut jump = None;
+            let mut fork = None;
+            let mut delete = None;
+            egui::ScrollArea::vertical().max_height(400.0).show(ui, |ui| {
+                for (i, bookmark) in bookmarks.list.iter_mut().enumerate() {
+                    let texture = (!bookmark.thumbnail.is_empty()).then(|| {
+                        let texture = bookmark.texture.get_or_insert_with(|| {
+                            let image = egui::ColorImage::from_gray([THUMBNAIL, THUMBNAIL], &bookmark.thumbnail);
+                            ctx.load_texture(format!("bookmark {}", i), image, egui::TextureOptions::NEAREST)
+                        });
+                        texture.id()
+                    });
+                    ui.horizontal(|ui| {
+                        if let Some(texture) = texture {
+                            ui.image((texture, egui::vec2(THUMBNAIL as f32, THUMBNAIL as f32)));
+                        }
+                        ui.vertical(|ui| {
+                            ui.label(&bookmark.name);
+                            ui.label(format!(
+                                "t = {:.3}, {} bodies",
+                                bookmark.snapshot.time,
+                                bookmark.snapshot.bodies.len()
+                            ));
+                            ui.horizontal(|ui| {
+                                if ui.button("Jump").clicked() {
+                                    jump = Some(i);
+                                }
+                                if ui.button("Fork").clicked() {
+                                    fork = Some(i);
+                                }
+                                if ui.button("Delete").clicked() {
+                                    delete = Some(i);
+                                }
+                            });
+                        });
+                    });
+                }
+            });
+            if jump.is_some() {
+                bookmarks.jump_requested = jump;
+            }
+            if fork.is_some() {
+                bookmarks.fork_requested = fork;
+            }
+            if let Some(i) = delete {
+                bookmarks.list.remove(i);
+            }
+        });
+}
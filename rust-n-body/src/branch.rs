@@ -0,0 +1,236 @@
+use bevy::prelude::*;
+use bevy::render::camera::Viewport;
+use bevy::render::view::RenderLayers;
+use bevy::window::PrimaryWindow;
+use bevy_egui::{EguiContexts, egui};
+use crate::bookmarks::Bookmarks;
+use crate::clock::{MAX_SUBSTEPS, SimulationClock};
+use crate::energy::Energy;
+use crate::headless::HeadlessSim;
+use crate::ui_config::UiConfig;
+use crate::undo::{BodyState, Snapshot, UndoBodies, UndoHistory, restore};
+use crate::{MainCamera, SimulationSettings};
+
+// Only the branch camera sees the branch's bodies, and it sees nothing else
+const BRANCH_LAYER: usize = 3;
+
+#[derive(Component)]
+pub struct BranchCamera;
+
+// Drawn copy of body i of the branch
+#[derive(Component)]
+pub struct BranchBody(usize);
+
+// A second run started from a bookmark that goes on next to the original, in the right half of the window.
+// It has no entities of its own to simulate, so it steps like the headless runs: no collisions, no tracers,
+// and pinned bodies, stars and controlled bodies are plain bodies there.
+#[derive(Resource, Default)]
+pub struct Branch {
+    pub sim: Option<HeadlessSim>,
+    pub name: String,
+    pub close_requested: bool,
+    pub adopt_requested: bool,
+    // the bodies as they were branched off, for their tags when the branch becomes the main run
+    kept: Vec<BodyState>,
+    last_step: Option<u64>,
+}
+
+impl Branch {
+    fn snapshot(&self, label: &'static str) -> Option<Snapshot> {
+        let sim = self.sim.as_ref()?;
+        Some(Snapshot {
+            label,
+            bodies: (0..sim.bodies.len())
+                .map(|i| BodyState {
+                    body: sim.bodies[i],
+                    transform: sim.transforms[i],
+                    velocity: sim.velocities[i],
+                    ..self.kept[i].clone()
+                })
+                .collect(),
+            time: sim.time,
+            steps: sim.steps,
+        })
+    }
+}
+
+// Right half of the window as (position, size) in physical pixels
+pub fn right_half(window: UVec2) -> Option<(UVec2, UVec2)> {
+    let half = window.x / 2;
+    if half == 0 || window.y == 0 {
+        return None;
+    }
+    Some((UVec2::new(window.x - half, 0), UVec2::new(half, window.y)))
+}
+
+pub fn update_branch(
+    mut branch: ResMut<Branch>,
+    mut bookmarks: ResMut<Bookmarks>,
+    (mut history, mut energy): (ResMut<UndoHistory>, ResMut<Energy>),
+    bodies: UndoBodies,
+    mut clock: ResMut<SimulationClock>,
+    settings: Res<SimulationSettings>,
+    windows: Query<&Window, With<PrimaryWindow>>,
+    main_camera: Query<(&Transform, &Projection), With<MainCamera>>,
+    mut branch_camera: Query<
+        (Entity, &mut Camera, &mut Transform, &mut Projection),
+        (With<BranchCamera>, Without<MainCamera>),
+    >,
+    mut drawn: Query<(Entity, &BranchBody, &mut Transform), (Without<BranchCamera>, Without<MainCamera>)>,
+    mut commands: Commands,
+    mut materials: ResMut<Assets<ColorMaterial>>,
+    mut meshes: ResMut<Assets<Mesh>>,
+) {
+    let fork = bookmarks.fork_requested.take().and_then(|i| bookmarks.list.get(i));
+
+    if branch.adopt_requested {
+        branch.adopt_requested = false;
+        if let Some(snapshot) = branch.snapshot("Continue From Branch") {
+            history.record(Snapshot::capture("Continue From Branch", &bodies, &clock));
+            restore(&snapshot, &bodies, &settings, &mut commands, &mut materials, &mut meshes);
+            clock.time = snapshot.time;
+            clock.steps = snapshot.steps;
+            energy.reset();
+            branch.close_requested = true;
+        }
+    }
+
+    if branch.close_requested || fork.is_some() {
+        branch.close_requested = false;
+        branch.sim = None;
+        for (entity, ..) in &drawn {
+            commands.entity(entity).despawn();
+        }
+    }
+
+    if let Some(bookmark) = fork {
+        let snapshot = &bookmark.snapshot;
+        let kept: Vec<BodyState> = snapshot.bodies.iter().filter(|s| !s.tracer).cloned().collect();
+        let mut sim = HeadlessSim::from_state(kept.iter().map(|s| (s.body, s.transform, s.velocity)));
+        sim.time = snapshot.time;
+        sim.steps = snapshot.steps;
+        for (i, state) in kept.iter().enumerate() {
+            commands.spawn((
+                Mesh2d(meshes.add(Circle::new(settings.render_radius(state.body.radius)))),
+                MeshMaterial2d(materials.add(ColorMaterial::from_color(settings.colormap.sample(state.body.hue)))),
+                Transform::from_translation(state.transform.translation),
+                RenderLayers::layer(BRANCH_LAYER),
+                BranchBody(i),
+            ));
+        }
+        info!("Branched off {} with {} bodies", bookmark.name, kept.len());
+        branch.kept = kept;
+        branch.name = bookmark.name.clone();
+        branch.sim = Some(sim);
+        branch.last_step = Some(clock.steps);
+        return;
+    }
+
+    let branch = &mut *branch;
+    let Some(sim) = branch.sim.as_mut() else {
+        for (entity, ..) in &branch_camera {
+            commands.entity(entity).despawn();
+        }
+        return;
+    };
+
+    // as many steps as the original took this frame, at its dt, so both go at the same pace
+    let last = branch.last_step.unwrap_or(clock.steps);
+    let steps = clock.steps.saturating_sub(last).min(MAX_SUBSTEPS as u64);
+    let (_substeps, dt) = settings.pacing();
+    for _ in 0..steps {
+        sim.step_dt(&settings, dt);
+    }
+    for (_entity, BranchBody(i), mut transform) in &mut drawn {
+        if let Some(t) = sim.transforms.get(*i) {
+            transform.translation = t.translation;
+        }
+    }
+    branch.last_step = Some(clock.steps);
+
+    let Ok((_entity, mut camera, mut transform, mut projection)) = branch_camera.single_mut() else {
+        commands.spawn((
+            Camera2d,
+            Camera {
+                // over the main view and the inset
+                order: 2,
+                clear_color: ClearColorConfig::Custom(Color::srgb(0.03, 0.02, 0.0)),
+                ..default()
+            },
+            RenderLayers::layer(BRANCH_LAYER),
+            BranchCamera,
+        ));
+        return;
+    };
+    let Ok(window) = windows.single() else {
+        return;
+    };
+    camera.viewport = right_half(UVec2::new(window.physical_width(), window.physical_height())).map(
+        |(physical_position, physical_size)| Viewport {
+            physical_position,
+            physical_size,
+            ..default()
+        },
+    );
+    camera.is_active = camera.viewport.is_some();
+
+    // shows the same part of space as the original does in the left half, at the same zoom
+    let Ok((main, Projection::Orthographic(ortho))) = main_camera.single() else {
+        return;
+    };
+    let offset = main.rotation * Vec3::new(-ortho.area.width() / 4.0, 0.0, 0.0);
+    *transform = main.with_translation(main.translation + offset);
+    if let Projection::Orthographic(branch_ortho) = &mut *projection {
+        branch_ortho.scale = ortho.scale;
+    }
+}
+
+pub fn branch_window(
+    mut contexts: EguiContexts,
+    mut branch: ResMut<Branch>,
+    clock: Res<SimulationClock>,
+    ui_config: Res<UiConfig>,
+) {
+    let ctx = contexts.ctx_mut().clone();
+    if let Some(sim) = &branch.sim {
+        // names the two halves, so it is clear which is which
+        let rect = ctx.screen_rect();
+        let painter = ctx.layer_painter(egui::LayerId::background());
+        let font = egui::FontId::proportional(16.0);
+        painter.vline(rect.center().x, rect.y_range(), egui::Stroke::new(2.0, egui::Color32::GRAY));
+        painter.text(
+            egui::pos2(rect.center().x - 10.0, rect.bottom() - 10.0),
+            egui::Align2::RIGHT_BOTTOM,
+            format!("Original, t = {:.3}", clock.time),
+            font.clone(),
+            egui::Color32::WHITE,
+        );
+        painter.text(
+            egui::pos2(rect.center().x + 10.0, rect.bottom() - 10.0),
+            egui::Align2::LEFT_BOTTOM,
+            format!("Branch from {}, t = {:.3}", branch.name, sim.time),
+            font,
+            egui::Color32::from_rgb(255, 200, 120),
+        );
+    }
+
+    ui_config
+        .place(egui::Window::new("Branch"), "Branch")
+        .default_open(false)
+        .show(&ctx, |ui| {
+            let Some(sim) = &branch.sim else {
+                ui.label("No branch running. Fork one from a bookmark in the Bookmarks window");
+                return;
+            };
+            ui.label(format!("Branched from {}, {} bodies", branch.name, sim.bodies.len()));
+            ui.label("The branch goes on next to the original at the same pace, without collisions");
+            ui.horizontal(|ui| {
+                if ui.button("Close Branch").clicked() {
+                    branch.close_requested = true;
+                }
+                if ui.button("Continue From Branch").clicked() {
+                    branch.adopt_requested = true;
+                }
+            });
+        });
+}
@@ -0,0 +1,79 @@
+use bevy::prelude::*;
+use crate::bhtree::Quadtree;
+use crate::dualtree::Cell;
+use crate::force::ForceParams;
+
+// What every body of one leaf feels from the rest of the tree
+#[derive(Default)]
+struct InteractionList {
+    // nodes taken whole, mass and center of mass
+    nodes: Vec<(f32, Vec2)>,
+    // bodies of other leaves summed one by one, mass and position
+    bodies: Vec<(f32, Vec2)>,
+}
+
+// Squared distance from p to the box
+fn distance_squared(min: Vec2, max: Vec2, p: Vec2) -> f32 {
+    (p - p.clamp(min, max)).length_squared()
+}
+
+// Walks the tree once for the whole leaf. A node is only taken whole if it passes the opening test
+// from the point of the leaf's bodies closest to it, so it passes for every one of them.
+fn interaction_list(cells: &[Cell], bodies: &[(Entity, Vec2, f32)], leaf: usize, theta: f32) -> InteractionList {
+    let own = cells[leaf].bodies.clone();
+    let (min, max) = bodies[own.clone()]
+        .iter()
+        .fold((Vec2::MAX, Vec2::MIN), |(min, max), (_e, p, _m)| (min.min(*p), max.max(*p)));
+    let mut list = InteractionList::default();
+    let mut stack = vec![0];
+    while let Some(i) = stack.pop() {
+        let cell = &cells[i];
+        if i == leaf {
+            continue;
+        }
+        if cell.children.is_empty() {
+            list.bodies.extend(bodies[cell.bodies.clone()].iter().map(|(_e, p, m)| (*m, *p)));
+            continue;
+        }
+        // the leaf's own ancestors always have to be opened
+        let holds_leaf = cell.bodies.start <= own.start && own.end <= cell.bodies.end;
+        let d2 = distance_squared(min, max, cell.com);
+        if !holds_leaf && d2 > 0.0 && cell.size * cell.size < theta * theta * d2 {
+            list.nodes.push((cell.mass, cell.com));
+        } else {
+            stack.extend(cell.children.clone());
+        }
+    }
+    list
+}
+
+// Velocity change over dt of every body in the tree, with one walk per leaf instead of one per body.
+// Bodies sharing a leaf are close together and would open nearly the same nodes, so the list of
+// nodes and bodies they interact with is made once and every one of them is summed over it. The
+// pairs within the leaf are summed exactly. Works in the plane like the dual tree walk.
+pub fn bucket_accels(tree: &Quadtree, force: ForceParams, dt: f32, theta: f32) -> Vec<(Entity, Vec3)> {
+    let (cells, bodies) = tree.flatten();
+    let accel = |m2: f32, p1: Vec2, p2: Vec2| force.accel(m2, p1.extend(0.0), p2.extend(0.0));
+    let mut accels = Vec::with_capacity(bodies.len());
+    for leaf in (0..cells.len()).filter(|i| cells[*i].children.is_empty()) {
+        let list = interaction_list(&cells, &bodies, leaf, theta);
+        let own = cells[leaf].bodies.clone();
+        for i in own.clone() {
+            let (entity, position, _mass) = bodies[i];
+            let mut total = Vec3::ZERO;
+            for (mass, com) in &list.nodes {
+                total += accel(*mass, position, *com);
+            }
+            for (mass, other) in &list.bodies {
+                total += accel(*mass, position, *other);
+            }
+            for j in own.clone() {
+                if j != i {
+                    total += accel(bodies[j].2, position, bodies[j].1);
+                }
+            }
+            accels.push((entity, force.mond_kick(total * dt, dt)));
+        }
+    }
+    accels
+}
@@ -0,0 +1,148 @@
+use bevy::prelude::*;
+use crate::audio::{Tone, play_tone};
+use crate::orbital_mechanics::orbit_elements;
+use crate::tracer::Massless;
+use crate::{Body, SimulationSettings, Velocity};
+
+// Pitch when a pair is picked up. It goes up by the same factor as the orbital frequency from there.
+const BASE_PITCH: f32 = 110.0;
+const MAX_PITCH: f32 = 3520.0;
+const BASE_GAIN: f32 = 0.05;
+const MAX_GAIN: f32 = 0.4;
+// After the merger the pitch keeps sweeping up this many octaves per second while it fades out
+const RINGDOWN_SECS: f32 = 0.4;
+const RINGDOWN_OCTAVES_PER_SEC: f32 = 6.0;
+
+// Plays a rising chirp while the tightest bound pair of heavy bodies spirals in, in the spirit of
+// a gravitational wave signal, and a short sweep when they touch or one of them disappears.
+#[derive(Resource)]
+pub struct MergerChirp {
+    pub enabled: bool,
+    // only bodies at least this heavy are listened to
+    pub min_mass: f32,
+    pub pair: Option<(Entity, Entity)>,
+    // orbital frequency, in orbits per simulated time unit
+    pub frequency: f32,
+    // frequency when the pair was picked up, the pitch is relative to it
+    start_frequency: f32,
+    pitch: f32,
+    // time left of the sweep after a merger
+    ringdown: f32,
+    tone: Option<Tone>,
+}
+
+impl Default for MergerChirp {
+    fn default() -> Self {
+        MergerChirp {
+            enabled: false,
+            min_mass: 500.0,
+            pair: None,
+            frequency: 0.0,
+            start_frequency: 0.0,
+            pitch: BASE_PITCH,
+            ringdown: 0.0,
+            tone: None,
+        }
+    }
+}
+
+// Orbits per unit of simulated time of an isolated pair, None when it isn't bound
+pub fn orbital_frequency(g: f32, m1: f32, m2: f32, rel_pos: Vec2, rel_vel: Vec2) -> Option<f32> {
+    let period = orbit_elements(g * (m1 + m2), rel_pos, rel_vel).period?;
+    if period > 0.0 && period.is_finite() { Some(1.0 / period) } else { None }
+}
+
+// The pitch rises by the same factor as the orbital frequency, like the real signal does
+pub fn chirp_pitch(frequency: f32, start_frequency: f32) -> f32 {
+    if start_frequency <= 0.0 {
+        return BASE_PITCH;
+    }
+    (BASE_PITCH * frequency / start_frequency).clamp(BASE_PITCH, MAX_PITCH)
+}
+
+// Louder as it gets higher, amplitude going as f^(2/3)
+fn chirp_gain(pitch: f32) -> f32 {
+    (BASE_GAIN * (pitch / BASE_PITCH).powf(2.0 / 3.0)).min(MAX_GAIN)
+}
+
+pub fn start_chirp(mut commands: Commands, mut tones: ResMut<Assets<Tone>>, mut chirp: ResMut<MergerChirp>) {
+    chirp.tone = Some(play_tone(&mut commands, &mut tones));
+}
+
+pub fn merger_chirp(
+    time: Res<Time>,
+    settings: Res<SimulationSettings>,
+    bodies: Query<(Entity, &Transform, &Velocity, &Body), Without<Massless>>,
+    mut chirp: ResMut<MergerChirp>,
+) {
+    let Some(tone) = chirp.tone.clone() else {
+        return;
+    };
+    if !chirp.enabled {
+        chirp.pair = None;
+        chirp.ringdown = 0.0;
+        tone.set(chirp.pitch, 0.0);
+        return;
+    }
+
+    if chirp.ringdown > 0.0 {
+        let dt = time.delta_secs();
+        chirp.ringdown = (chirp.ringdown - dt).max(0.0);
+        chirp.pitch = (chirp.pitch * 2f32.powf(RINGDOWN_OCTAVES_PER_SEC * dt)).min(MAX_PITCH * 2.0);
+        tone.set(chirp.pitch, chirp_gain(chirp.pitch) * chirp.ringdown / RINGDOWN_SECS);
+        return;
+    }
+
+    if let Some((a, b)) = chirp.pair {
+        let (Ok((_a, ta, va, ba)), Ok((_b, tb, vb, bb))) = (bodies.get(a), bodies.get(b)) else {
+            // one of them is gone, which is as much of a merger as we get
+            chirp.pair = None;
+            chirp.ringdown = RINGDOWN_SECS;
+            return;
+        };
+        let rel_pos = (tb.translation - ta.translation).truncate();
+        if rel_pos.length() < ba.radius + bb.radius {
+            chirp.pair = None;
+            chirp.ringdown = RINGDOWN_SECS;
+            return;
+        }
+        match orbital_frequency(settings.g, ba.mass, bb.mass, rel_pos, (vb.0 - va.0).truncate()) {
+            Some(frequency) => {
+                chirp.frequency = frequency;
+                chirp.pitch = chirp_pitch(frequency, chirp.start_frequency);
+                tone.set(chirp.pitch, chirp_gain(chirp.pitch));
+                return;
+            }
+            // pulled apart by something else, go look for another pair
+            None => chirp.pair = None,
+        }
+    }
+
+    // Heavy bodies are rare, so checking every pair of them is fine
+    let heavy: Vec<(Entity, Vec2, Vec2, f32)> = bodies
+        .iter()
+        .filter(|(_e, _t, _v, b)| b.mass >= chirp.min_mass)
+        .map(|(e, t, v, b)| (e, t.translation.truncate(), v.0.truncate(), b.mass))
+        .collect();
+    let mut tightest: Option<((Entity, Entity), f32)> = None;
+    for (i, (ea, pa, va, ma)) in heavy.iter().enumerate() {
+        for (eb, pb, vb, mb) in &heavy[i + 1..] {
+            if let Some(frequency) = orbital_frequency(settings.g, *ma, *mb, *pb - *pa, *vb - *va)
+                && tightest.is_none_or(|(_pair, f)| frequency > f)
+            {
+                tightest = Some(((*ea, *eb), frequency));
+            }
+        }
+    }
+
+    match tightest {
+        Some((pair, frequency)) => {
+            chirp.pair = Some(pair);
+            chirp.frequency = frequency;
+            chirp.start_frequency = frequency;
+            chirp.pitch = BASE_PITCH;
+            tone.set(chirp.pitch, chirp_gain(chirp.pitch));
+        }
+        None => tone.set(chirp.pitch, 0.0),
+    }
+}
@@ -0,0 +1,210 @@
+use bevy::prelude::*;
+use bevy_egui::{EguiContexts, egui};
+use std::f32::consts::{PI, TAU};
+use crate::fade::despawn_with_fade;
+use crate::force::ForceLaw;
+use crate::ui_config::UiConfig;
+use crate::{Body, SimulationSettings, Velocity, mass_to_hue, spawn_body};
+
+// Steps per period the presets are recommended to run at, the unstable ones need a lot more
+const STEPS_PER_PERIOD: f32 = 2000.0;
+const SENSITIVE_STEPS_PER_PERIOD: f32 = 20000.0;
+
+// Known periodic solutions of three or four equal masses
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Choreography {
+    // Chenciner and Montgomery's, all three chasing each other along one figure eight
+    FigureEight,
+    // Lagrange's equilateral triangle turning rigidly
+    LagrangeTriangle,
+    // the same with four bodies on a square
+    Square,
+    // from Šuvakov and Dmitrašinović's 2013 families
+    ButterflyI,
+    MothI,
+    YinYangIa,
+    Goggles,
+}
+
+impl Choreography {
+    pub const ALL: [Choreography; 7] = [
+        Choreography::FigureEight,
+        Choreography::LagrangeTriangle,
+        Choreography::Square,
+        Choreography::ButterflyI,
+        Choreography::MothI,
+        Choreography::YinYangIa,
+        Choreography::Goggles,
+    ];
+
+    pub fn label(self) -> &'static str {
+        match self {
+            Choreography::FigureEight => "Figure Eight",
+            Choreography::LagrangeTriangle => "Lagrange Triangle",
+            Choreography::Square => "Rotating Square",
+            Choreography::ButterflyI => "Butterfly I",
+            Choreography::MothI => "Moth I",
+            Choreography::YinYangIa => "Yin-Yang Ia",
+            Choreography::Goggles => "Goggles",
+        }
+    }
+
+    // From the label in lower case with dashes for spaces, "figure-eight" or "moth-i", for the command line
+    pub fn from_name(name: &str) -> Option<Choreography> {
+        Choreography::ALL
+            .into_iter()
+            .find(|choreography| choreography.label().to_lowercase().replace(' ', "-") == name.to_lowercase())
+    }
+
+    // Only the figure eight is stable, the rest come apart from small errors unless the forces
+    // are exact and the step is small
+    pub fn sensitive(self) -> bool {
+        self != Choreography::FigureEight
+    }
+
+    // Positions and velocities with G = 1 and unit masses, and the period in those units
+    pub fn unit_state(self) -> (Vec<(Vec2, Vec2)>, f32) {
+        // the Šuvakov and Dmitrašinović orbits all start collinear, the outer two with the same velocity
+        let collinear = |p1: f32, p2: f32, period: f32| {
+            let v = Vec2::new(p1, p2);
+            (vec![(Vec2::new(-1.0, 0.0), v), (Vec2::new(1.0, 0.0), v), (Vec2::ZERO, -2.0 * v)], period)
+        };
+        match self {
+            Choreography::FigureEight => {
+                let x = Vec2::new(0.970_004_4, -0.24308753);
+                let v = Vec2::new(-0.932_407_4, -0.86473146);
+                (vec![(x, -v / 2.0), (-x, -v / 2.0), (Vec2::ZERO, v)], 6.325_914)
+            }
+            Choreography::LagrangeTriangle => rotating_polygon(3),
+            Choreography::Square => rotating_polygon(4),
+            Choreography::ButterflyI => collinear(0.306893, 0.125507, 6.235_641),
+            Choreography::MothI => collinear(0.464445, 0.396060, 14.893_911),
+            Choreography::YinYangIa => collinear(0.513938, 0.304736, 17.328_37),
+            Choreography::Goggles => collinear(0.083300, 0.127889, 10.466_818),
+        }
+    }
+}
+
+// n unit masses on the unit circle, each pulled to the center by the sum over the others of 1 / (4 sin(πk / n))
+pub fn rotating_polygon(n: usize) -> (Vec<(Vec2, Vec2)>, f32) {
+    let pull: f32 = (1..n).map(|k| 1.0 / (4.0 * (PI * k as f32 / n as f32).sin())).sum();
+    let speed = pull.sqrt();
+    let bodies = (0..n)
+        .map(|i| {
+            let dir = Vec2::from_angle(TAU * i as f32 / n as f32);
+            (dir, dir.perp() * speed)
+        })
+        .collect();
+    (bodies, TAU / speed)
+}
+
+// The preset at the simulation's G, with every body of the given mass and the pattern `size` across
+// in place of 1. Returns (mass, position, velocity) of each body and the period.
+pub fn scaled_state(choreography: Choreography, g: f32, mass: f32, size: f32) -> (Vec<(f32, Vec2, Vec2)>, f32) {
+    let (bodies, period) = choreography.unit_state();
+    let speed = (g * mass / size).sqrt();
+    let bodies = bodies
+        .into_iter()
+        .map(|(position, velocity)| (mass, position * size, velocity * speed))
+        .collect();
+    (bodies, period * size / speed)
+}
+
+pub fn recommended_dt(choreography: Choreography, period: f32) -> f32 {
+    let steps = if choreography.sensitive() { SENSITIVE_STEPS_PER_PERIOD } else { STEPS_PER_PERIOD };
+    period / steps
+}
+
+#[derive(Resource)]
+pub struct ChoreographyLibrary {
+    pub choreography: Choreography,
+    pub mass: f32,
+    pub size: f32,
+    pub replace: bool,
+}
+
+impl Default for ChoreographyLibrary {
+    fn default() -> Self {
+        ChoreographyLibrary {
+            choreography: Choreography::FigureEight,
+            mass: 1000.0,
+            size: 100.0,
+            replace: true,
+        }
+    }
+}
+
+pub fn choreography_window(
+    mut contexts: EguiContexts,
+    mut library: ResMut<ChoreographyLibrary>,
+    mut settings: ResMut<SimulationSettings>,
+    ui_config: Res<UiConfig>,
+    bodies: Query<Entity, With<Body>>,
+    mut commands: Commands,
+    mut materials: ResMut<Assets<ColorMaterial>>,
+    mut meshes: ResMut<Assets<Mesh>>,
+) {
+    ui_config
+        .place(egui::Window::new("Choreographies"), "Choreographies")
+        .default_open(false)
+        .show(contexts.ctx_mut(), |ui| {
+            egui::ComboBox::from_label("Solution")
+                .selected_text(library.choreography.label())
+                .show_ui(ui, |ui| {
+                    for choreography in Choreography::ALL {
+                        ui.selectable_value(&mut library.choreography, choreography, choreography.label());
+                    }
+                });
+            ui.add(egui::Slider::new(&mut library.mass, 1.0..=100000.0).logarithmic(true).text("Body Mass"));
+            ui.add(egui::Slider::new(&mut library.size, 10.0..=1000.0).logarithmic(true).text("Size"));
+            ui.add(egui::Checkbox::new(&mut library.replace, "Replace Existing Bodies"));
+
+            let (state, period) = scaled_state(library.choreography, settings.g, library.mass, library.size);
+            let dt = recommended_dt(library.choreography, period);
+            ui.label(format!("Period: {}", settings.units.time(period as f64)));
+            if library.choreography.sensitive() {
+                ui.colored_label(
+                    egui::Color32::YELLOW,
+                    "Unstable: needs exact forces (BH Theta 0, plain 1/r²) and a small step to hold its shape",
+                );
+            }
+            let exact = settings.theta == 0.0 && settings.force_law == ForceLaw::InverseSquare;
+            ui.label(format!(
+                "Recommended: Delta T {:.2e} or less, BH Theta 0, 1/r², no collisions{}",
+                dt,
+                if exact && settings.delta_t <= dt { " (in use)" } else { "" }
+            ));
+            if ui.button("Use Recommended Settings").clicked() {
+                settings.delta_t = dt;
+                settings.theta = 0.0;
+                settings.force_law = ForceLaw::InverseSquare;
+                settings.mond = false;
+                settings.post_newtonian = false;
+                settings.collision_enabled = false;
+            }
+
+            if ui.button("Spawn").clicked() {
+                if library.replace {
+                    for entity in &bodies {
+                        despawn_with_fade(&mut commands, entity);
+                    }
+                }
+                for (mass, position, velocity) in state {
+                    let body = Body {
+                        mass,
+                        radius: settings.collision_radius(mass),
+                        hue: mass_to_hue(mass, settings.min_body_mass, settings.max_body_mass).clamp(0.0, 1.0),
+                    };
+                    spawn_body(
+                        body,
+                        Transform::from_xyz(position.x, position.y, settings.z),
+                        Velocity(velocity.extend(0.0)),
+                        &settings,
+                        &mut commands,
+                        &mut materials,
+                        &mut meshes,
+                    );
+                }
+            }
+        });
+}
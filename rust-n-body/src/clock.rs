@@ -0,0 +1,44 @@
+use bevy::prelude::*;
+
+// Most physics steps one frame may take. Past this, a faster speed has to come from a bigger dt.
+pub const MAX_SUBSTEPS: f32 = 32.0;
+
+// Simulated time (sum of delta_t) and number of steps since the last reset.
+// Runs are compared at matching simulated times, since frame counts depend on the delta_t used.
+#[derive(Resource, Default)]
+pub struct SimulationClock {
+    pub time: f64,
+    pub steps: u64,
+    // fraction of a step carried over to the next frame
+    pub pending: f32,
+    // dt of the last step, which a faster speed stretches past delta_t
+    pub dt: f32,
+}
+
+impl SimulationClock {
+    pub fn advance(&mut self, dt: f32) {
+        self.time += dt as f64;
+        self.steps += 1;
+        self.dt = dt;
+    }
+
+    // Physics steps to take this frame. Fractions add up over frames, so 0.25 substeps per frame
+    // means one step every fourth frame, for slow motion.
+    pub fn take_substeps(&mut self, substeps_per_frame: f32) -> u32 {
+        self.pending += substeps_per_frame.max(0.0);
+        let steps = self.pending.floor();
+        self.pending -= steps;
+        steps as u32
+    }
+}
+
+// Steps per frame and the dt of each, for the requested speed on top of the substep setting.
+// As many steps of the stable dt as fit in a frame, and a stretched dt for whatever is left.
+pub fn pacing(substeps: f32, time_scale: f32, stable_dt: f32) -> (f32, f32) {
+    let wanted = substeps * time_scale;
+    if wanted <= MAX_SUBSTEPS {
+        (wanted, stable_dt)
+    } else {
+        (MAX_SUBSTEPS, stable_dt * wanted / MAX_SUBSTEPS)
+    }
+}
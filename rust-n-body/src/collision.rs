@@ -1,5 +1,24 @@
 use bevy::prelude::*;
 use crate::{Body, Velocity, SimulationSettings};
+use crate::bhtree::{Quad, Quadtree};
+use crate::guard::Quarantined;
+use crate::tracer::Massless;
+use std::collections::{HashMap, HashSet};
+
+// Collisions resolved since startup, for anything that wants a rate
+#[derive(Resource, Default)]
+pub struct CollisionCount(pub u64);
+
+// One resolved collision, with the closing speed of the pair and where they touched
+#[derive(Event, Clone, Copy, Debug)]
+pub struct CollisionEvent {
+    pub a: Entity,
+    pub b: Entity,
+    pub mass_a: f32,
+    pub mass_b: f32,
+    pub impact_speed: f32,
+    pub position: Vec2,
+}
 
 // Check collisions with bodies and update their velocities?
 // The velocity of a body is a vector that represents its speed and direction in 3D space (or 2D space)
@@ -11,12 +30,15 @@ pub fn collision(
     // The Body component contains the mass and radius of the entity
     // A query is a way to access entities and their components in Bevy
 
-    mut bodies: Query<(Entity, &mut Transform, &mut Velocity, &Body)>,
+    // Tracers are left out, they have no mass to exchange momentum with
+    mut bodies: Query<(Entity, &mut Transform, &mut Velocity, &Body), (Without<Massless>, Without<Quarantined>)>,
     settings: Res<SimulationSettings>, // SimulationSettings contains the simulation settings defined in main.rs
+    mut count: ResMut<CollisionCount>,
+    mut collision_events: EventWriter<CollisionEvent>,
 ) {
 
     // Checking to see if collision detection is enabled, if its not, then it should just return
-    if !settings.collision_enabled {
+    if !settings.collision_enabled || settings.paused {
         return; 
     }
 
@@ -27,29 +49,59 @@ pub fn collision(
     // let mut items: Vec<_> = bodies.iter_mut().collect();
     let mut items: Vec<(Entity, Mut<Transform>, Mut<Velocity>, &Body)> = bodies.iter_mut().collect();
 
-    // We loop through all the items in the simulation
-    for i in 0..items.len() {
-    // We need to split the items into two parts
-    // Because of rust borrow checker rules, Body A and Body B cannot be borrowed with the same mutable reference
-    // So we split the items into two parts, left and right
-    // Left part gets the current item index plus the next item and the right part gets the rest of the items
-
-    let split = i + 1;
-    let (left, right) = items.split_at_mut(split);
-    let (_entity_a, transform_a, velocity_a, body_a) = &mut left[i]; 
-
-    // We loop throught the right part of the items split
-    for li in 0..right.len() {
-        let (_entity_b, transform_b, velocity_b, body_b) = &mut right[li];
+    if items.is_empty() {
+        return;
+    }
 
-        // We get the positions of the two bodies
-        let position_a = transform_a.translation;
-        let position_b = transform_b.translation;
+    // Instead of checking every body against every other body, we put them in a quadtree
+    // and only check each body against the bodies close enough to touch it
+    let positions: Vec<Vec2> = items
+        .iter()
+        .map(|(_entity, transform, _velocity, _body)| transform.translation.truncate())
+        .collect();
+    let mut tree = Quadtree::new(Quad::new_containing(&positions));
+
+    // The tree gives us entities back, so we need to know where each entity is in items
+    let mut index_of: HashMap<Entity, usize> = HashMap::new();
+    let mut max_radius: f32 = 0.0;
+    for (i, (entity, transform, _velocity, body)) in items.iter().enumerate() {
+        tree.insert(*entity, **transform, **body);
+        index_of.insert(*entity, i);
+        max_radius = max_radius.max(body.radius);
+    }
 
-        // We get the distance between the two bodies
-        let distance = position_a.distance(position_b);
+    // A pair can be found from both of its bodies, this makes sure we only resolve it once
+    let mut handled: HashSet<(usize, usize)> = HashSet::new();
 
-        let min_distance = body_a.radius + body_b.radius; // If the sum of radius is bigger than the distance, then they are colliding
+    // We loop through all the items in the simulation
+    for i in 0..items.len() {
+        let position_a = items[i].1.translation;
+
+        // Any body touching A has its center within A's radius plus the biggest radius there is
+        let search_radius = items[i].3.radius + max_radius;
+        let neighbours: Vec<usize> = tree
+            .query_circle(position_a.truncate(), search_radius)
+            .filter_map(|(entity, _transform, _body)| index_of.get(&entity).copied())
+            .filter(|j| *j != i && handled.insert((i.min(*j), i.max(*j))))
+            .collect();
+
+        for j in neighbours {
+            // Because of rust borrow checker rules, Body A and Body B cannot be borrowed with the same mutable reference
+            // So we split the items in two at the higher index, the lower one is then in the left part
+            // and the higher one is the first item of the right part
+            let (low, high) = (i.min(j), i.max(j));
+            let (left, right) = items.split_at_mut(high);
+            let (entity_a, transform_a, velocity_a, body_a) = &mut left[low];
+            let (entity_b, transform_b, velocity_b, body_b) = &mut right[0];
+
+            // We get the positions of the two bodies
+            let position_a = transform_a.translation;
+            let position_b = transform_b.translation;
+
+            // We get the distance between the two bodies
+            let distance = position_a.distance(position_b);
+
+            let min_distance = body_a.radius + body_b.radius; // If the sum of radius is bigger than the distance, then they are colliding
 
             // Check if the distance is less than the minimum distance
             if distance < min_distance {
@@ -68,8 +120,16 @@ pub fn collision(
 
                 velocity_a.0 -= impulse / body_a.mass;
                 velocity_b.0 += impulse / body_b.mass;
+                count.0 += 1;
+                collision_events.write(CollisionEvent {
+                    a: *entity_a,
+                    b: *entity_b,
+                    mass_a: body_a.mass,
+                    mass_b: body_b.mass,
+                    impact_speed: -velocity_along_normal,
+                    position: (position_a + normal * body_a.radius).truncate(),
+                });
             }
         }
     }
 }
-
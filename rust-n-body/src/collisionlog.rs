@@ -0,0 +1,200 @@
+use bevy::math::DVec2;
+use bevy::prelude::*;
+use bevy_egui::{EguiContexts, egui};
+use std::collections::VecDeque;
+use std::fs::File;
+use std::io::{BufWriter, Write};
+use crate::clock::SimulationClock;
+use crate::collision::CollisionEvent;
+use crate::flash::ImpactFlash;
+use crate::origin::WorldOrigin;
+use crate::plot::line_plot;
+use crate::ui_config::UiConfig;
+use crate::SimulationSettings;
+
+// Collisions kept in the window, the CSV has all of them
+const MAX_ENTRIES: usize = 500;
+const MAX_HISTORY: usize = 2000;
+
+pub const COLLISION_HEADER: &str = "time,body_a,body_b,mass_a,mass_b,impact_speed,x,y";
+
+// Every collision with the simulated time it happened at, and the collision rate over time.
+// Positions are in the coordinates the run started in, so moving the world origin doesn't show up in them.
+#[derive(Resource)]
+pub struct CollisionLog {
+    pub entries: VecDeque<(f64, CollisionEvent)>,
+    pub total: u64,
+    pub csv_enabled: bool,
+    pub csv_path: String,
+    // simulated time each point of the rate plot counts collisions over
+    pub bin_width: f32,
+    // collisions per unit of simulated time
+    pub rate_history: Vec<f32>,
+    bin_start: f64,
+    bin_count: u32,
+    writer: Option<BufWriter<File>>,
+}
+
+impl Default for CollisionLog {
+    fn default() -> Self {
+        CollisionLog {
+            entries: VecDeque::new(),
+            total: 0,
+            csv_enabled: false,
+            csv_path: "collisions.csv".to_string(),
+            bin_width: 0.1,
+            rate_history: Vec::new(),
+            bin_start: 0.0,
+            bin_count: 0,
+            writer: None,
+        }
+    }
+}
+
+impl CollisionLog {
+    pub fn record(&mut self, time: f64, event: CollisionEvent) {
+        self.entries.push_back((time, event));
+        if self.entries.len() > MAX_ENTRIES {
+            self.entries.pop_front();
+        }
+        self.total += 1;
+        self.bin_count += 1;
+    }
+
+    // Turns every bin that ended by this time into a point of the rate plot.
+    // The clock going back means a reset, the plot starts over then.
+    pub fn close_bins(&mut self, time: f64) {
+        if time < self.bin_start {
+            self.rate_history.clear();
+            self.bin_start = time;
+            self.bin_count = 0;
+            return;
+        }
+        let width = self.bin_width.max(1e-6) as f64;
+        while time >= self.bin_start + width {
+            self.rate_history.push(self.bin_count as f32 / width as f32);
+            self.bin_count = 0;
+            self.bin_start += width;
+        }
+        if self.rate_history.len() > MAX_HISTORY {
+            let excess = self.rate_history.len() - MAX_HISTORY;
+            self.rate_history.drain(..excess);
+        }
+    }
+}
+
+// position is where the event happened in the starting coordinates, at full precision
+pub fn csv_line(time: f64, event: &CollisionEvent, position: DVec2) -> String {
+    format!(
+        "{:?},{},{},{:?},{:?},{:?},{:?},{:?}",
+        time,
+        event.a.index(),
+        event.b.index(),
+        event.mass_a,
+        event.mass_b,
+        event.impact_speed,
+        position.x,
+        position.y
+    )
+}
+
+pub fn log_collisions(
+    mut collision_events: EventReader<CollisionEvent>,
+    clock: Res<SimulationClock>,
+    origin: Res<WorldOrigin>,
+    mut log: ResMut<CollisionLog>,
+) {
+    if !log.csv_enabled {
+        if let Some(mut writer) = log.writer.take() {
+            let _ = writer.flush();
+        }
+    } else if log.writer.is_none() {
+        match File::create(&log.csv_path) {
+            Ok(file) => {
+                let mut writer = BufWriter::new(file);
+                let _ = writeln!(writer, "{}", COLLISION_HEADER);
+                log.writer = Some(writer);
+            }
+            Err(err) => {
+                error!("Could not open collision log {}: {}", log.csv_path, err);
+                log.csv_enabled = false;
+            }
+        }
+    }
+
+    for event in collision_events.read() {
+        let position = origin.original(event.position);
+        log.record(
+            clock.time,
+            CollisionEvent {
+                position: position.as_vec2(),
+                ..*event
+            },
+        );
+        if let Some(writer) = log.writer.as_mut() {
+            let _ = writeln!(writer, "{}", csv_line(clock.time, event, position));
+        }
+    }
+    log.close_bins(clock.time);
+}
+
+pub fn collision_log_window(
+    mut contexts: EguiContexts,
+    mut log: ResMut<CollisionLog>,
+    mut flash: ResMut<ImpactFlash>,
+    settings: Res<SimulationSettings>,
+    ui_config: Res<UiConfig>,
+) {
+    let units = settings.units;
+    ui_config
+        .place(egui::Window::new("Collisions"), "Collisions")
+        .default_open(false)
+        .show(contexts.ctx_mut(), |ui| {
+            if !settings.collision_enabled {
+                ui.label("Collisions are off in the settings");
+            }
+            ui.label(format!("Collisions: {}", log.total));
+            if let Some(rate) = log.rate_history.last() {
+                ui.label(format!("Rate: {:.1} per unit of time", rate));
+            }
+            line_plot(ui, &[(log.rate_history.as_slice(), egui::Color32::LIGHT_RED)]);
+            ui.add(
+                egui::Slider::new(&mut log.bin_width, 0.01..=10.0)
+                    .logarithmic(true)
+                    .text("Rate Over (time)"),
+            );
+
+            ui.horizontal(|ui| {
+                ui.add(egui::Checkbox::new(&mut log.csv_enabled, "Write CSV"));
+                ui.add(egui::TextEdit::singleline(&mut log.csv_path));
+            });
+            ui.add(egui::Checkbox::new(&mut flash.enabled, "Flash On Impact"));
+            if flash.enabled {
+                ui.add(egui::Slider::new(&mut flash.duration, 0.1..=3.0).text("Flash Duration (s)"));
+                ui.add(egui::Slider::new(&mut flash.scale, 0.1..=50.0).logarithmic(true).text("Flash Size"));
+            }
+            if ui.button("Clear").clicked() {
+                log.entries.clear();
+            }
+
+            ui.separator();
+            egui::ScrollArea::vertical()
+                .max_height(200.0)
+                .stick_to_bottom(true)
+                .show(ui, |ui| {
+                    for (time, event) in &log.entries {
+                        ui.label(format!(
+                            "t = {:.3}: #{} ({}) and #{} ({}) at {}, ({:.1}, {:.1})",
+                            time,
+                            event.a.index(),
+                            units.mass(event.mass_a),
+                            event.b.index(),
+                            units.mass(event.mass_b),
+                            units.speed(event.impact_speed),
+                            event.position.x,
+                            event.position.y
+                        ));
+                    }
+                });
+        });
+}
@@ -0,0 +1,237 @@
+use bevy::prelude::*;
+use bevy_egui::egui;
+use crate::energy::WorkError;
+use crate::groups::GroupAnalysis;
+use crate::starformation::FormedStar;
+use crate::tags::{GroupTag, TagGroups};
+use crate::tracer::Massless;
+use crate::{Body, SimulationSettings};
+
+// Perceptual palettes, sampled at evenly spaced stops and interpolated linearly in sRGB.
+// The stops are taken from the matplotlib versions of these maps.
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Default)]
+pub enum Colormap {
+    #[default]
+    Viridis,
+    Inferno,
+    Coolwarm,
+    Turbo,
+    // The original red/green look, kept for comparison
+    Classic,
+}
+
+const VIRIDIS: [[u8; 3]; 9] = [
+    [68, 1, 84],
+    [72, 40, 120],
+    [62, 73, 137],
+    [49, 104, 142],
+    [38, 130, 142],
+    [31, 158, 137],
+    [53, 183, 121],
+    [110, 206, 88],
+    [253, 231, 37],
+];
+
+const INFERNO: [[u8; 3]; 9] = [
+    [0, 0, 4],
+    [31, 12, 72],
+    [85, 15, 109],
+    [136, 34, 106],
+    [186, 54, 85],
+    [227, 89, 51],
+    [249, 140, 10],
+    [249, 201, 50],
+    [252, 255, 164],
+];
+
+const COOLWARM: [[u8; 3]; 5] = [
+    [59, 76, 192],
+    [141, 176, 254],
+    [221, 221, 221],
+    [244, 154, 123],
+    [180, 4, 38],
+];
+
+const TURBO: [[u8; 3]; 9] = [
+    [48, 18, 59],
+    [70, 107, 227],
+    [40, 188, 235],
+    [50, 241, 153],
+    [164, 252, 60],
+    [237, 208, 58],
+    [251, 128, 34],
+    [210, 49, 5],
+    [122, 4, 3],
+];
+
+impl Colormap {
+    pub const ALL: [Colormap; 5] = [
+        Colormap::Viridis,
+        Colormap::Inferno,
+        Colormap::Coolwarm,
+        Colormap::Turbo,
+        Colormap::Classic,
+    ];
+
+    // t is clamped to [0, 1]
+    pub fn sample_rgb(self, t: f32) -> [f32; 3] {
+        let t = if t.is_nan() { 0.0 } else { t.clamp(0.0, 1.0) };
+        let stops: &[[u8; 3]] = match self {
+            Colormap::Viridis => &VIRIDIS,
+            Colormap::Inferno => &INFERNO,
+            Colormap::Coolwarm => &COOLWARM,
+            Colormap::Turbo => &TURBO,
+            Colormap::Classic => return [t, 0.5, 0.0],
+        };
+
+        let scaled = t * (stops.len() - 1) as f32;
+        let i = (scaled.floor() as usize).min(stops.len() - 2);
+        let f = scaled - i as f32;
+        let (a, b) = (stops[i], stops[i + 1]);
+        [0, 1, 2].map(|c| (a[c] as f32 + (b[c] as f32 - a[c] as f32) * f) / 255.0)
+    }
+
+    pub fn sample(self, t: f32) -> Color {
+        let [r, g, b] = self.sample_rgb(t);
+        Color::srgb(r, g, b)
+    }
+}
+
+// Horizontal gradient of the palette with the values of both ends written underneath
+pub fn color_bar(ui: &mut egui::Ui, colormap: Colormap, low: &str, high: &str) {
+    let (rect, _response) = ui.allocate_exact_size(egui::vec2(200.0, 14.0), egui::Sense::hover());
+    let steps = 64;
+    for i in 0..steps {
+        let [r, g, b] = colormap.sample_rgb(i as f32 / (steps - 1) as f32);
+        let x0 = rect.left() + rect.width() * i as f32 / steps as f32;
+        let x1 = rect.left() + rect.width() * (i + 1) as f32 / steps as f32;
+        ui.painter().rect_filled(
+            egui::Rect::from_min_max(egui::pos2(x0, rect.top()), egui::pos2(x1, rect.bottom())),
+            0.0,
+            egui::Color32::from_rgb((r * 255.0) as u8, (g * 255.0) as u8, (b * 255.0) as u8),
+        );
+    }
+    ui.horizontal(|ui| {
+        ui.set_width(rect.width());
+        ui.label(low);
+        ui.with_layout(egui::Layout::right_to_left(egui::Align::Center), |ui| {
+            ui.label(high);
+        });
+    });
+}
+
+// What the body colors show
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum ColorMode {
+    Mass,
+    // Accumulated work error, on a log scale over the 4 decades below the worst body
+    WorkError,
+    // Bound group from the last group analysis, unbound bodies dark
+    Group,
+}
+
+impl ColorMode {
+    pub const ALL: [ColorMode; 3] = [ColorMode::Mass, ColorMode::WorkError, ColorMode::Group];
+
+    pub fn label(self) -> &'static str {
+        match self {
+            ColorMode::Mass => "Mass",
+            ColorMode::WorkError => "Work Error",
+            ColorMode::Group => "Bound Group",
+        }
+    }
+}
+
+const WORK_ERROR_DECADES: f32 = 4.0;
+
+// Spreads neighbouring group numbers far apart along the palette, so the first few groups are easy
+// to tell apart whatever their count
+pub fn group_shade(group: usize) -> f32 {
+    const GOLDEN: f32 = 0.618_034;
+    0.1 + 0.9 * (group as f32 * GOLDEN).fract()
+}
+
+// Range of the current color mode, for the legend
+#[derive(Resource, Default)]
+pub struct ColorScale {
+    pub max_work_error: f32,
+    pub groups: usize,
+}
+
+// Draws the color bar for whatever the bodies are colored by
+pub fn legend(ui: &mut egui::Ui, settings: &SimulationSettings, scale: &ColorScale) {
+    match settings.color_mode {
+        ColorMode::Mass => {
+            ui.label("Color: mass");
+            color_bar(
+                ui,
+                settings.colormap,
+                &format!("{:.0}", settings.min_body_mass),
+                &format!("{:.0}", settings.max_body_mass),
+            );
+        }
+        ColorMode::WorkError => {
+            ui.label("Color: |work error| (log)");
+            color_bar(
+                ui,
+                settings.colormap,
+                &format!("{:.1e}", scale.max_work_error / 10f32.powf(WORK_ERROR_DECADES)),
+                &format!("{:.1e}", scale.max_work_error),
+            );
+        }
+        ColorMode::Group => {
+            ui.label(format!("Color: bound group, {} found (darkest is unbound)", scale.groups));
+        }
+    }
+}
+
+// Applies the palette to the bodies that are already there. In mass mode that is only needed when the
+// palette or mode changes, the work error and groups change as it runs so those modes recolor every frame.
+// Bodies in a body group with a color of its own get that instead.
+pub fn recolor_bodies(
+    settings: Res<SimulationSettings>,
+    mut current: Local<Option<(Colormap, ColorMode)>>,
+    mut scale: ResMut<ColorScale>,
+    bodies: Query<
+        (Entity, &Body, &MeshMaterial2d<ColorMaterial>, Option<&WorkError>, Option<&GroupTag>),
+        (Without<Massless>, Without<FormedStar>),
+    >,
+    groups: Res<GroupAnalysis>,
+    mut materials: ResMut<Assets<ColorMaterial>>,
+    (tag_groups, retagged): (Res<TagGroups>, Query<(), Or<(Added<Body>, Changed<GroupTag>)>>),
+) {
+    let wanted = (settings.colormap, settings.color_mode);
+    // a group color also has to go on bodies that were just spawned or moved between groups
+    let tags_changed = tag_groups.is_changed() || !retagged.is_empty();
+    if *current == Some(wanted) && settings.color_mode == ColorMode::Mass && !tags_changed {
+        return;
+    }
+    *current = Some(wanted);
+
+    scale.max_work_error = bodies
+        .iter()
+        .filter_map(|(_e, _b, _m, work_error, _tag)| work_error.map(|w| w.error.abs()))
+        .fold(0.0, f32::max);
+    scale.groups = groups.groups.len();
+
+    for (entity, body, material, work_error, tag) in &bodies {
+        let t = match settings.color_mode {
+            ColorMode::Mass => body.hue,
+            ColorMode::WorkError => {
+                let error = work_error.map_or(0.0, |w| w.error.abs());
+                if error > 0.0 && scale.max_work_error > 0.0 {
+                    1.0 + (error / scale.max_work_error).log10() / WORK_ERROR_DECADES
+                } else {
+                    0.0
+                }
+            }
+            ColorMode::Group => groups.group_of.get(&entity).map_or(0.0, |g| group_shade(*g)),
+        };
+        if let Some(material) = materials.get_mut(&material.0) {
+            // keep the alpha, the body might be fading in
+            let alpha = material.color.alpha();
+            let color = tag_groups.color(tag).unwrap_or_else(|| settings.colormap.sample(t));
+            material.color = color.with_alpha(alpha);
+        }
+    }
+}
@@ -0,0 +1,293 @@
+use bevy::prelude::*;
+use std::fs;
+use std::path::{Path, PathBuf};
+use crate::headless::HeadlessSim;
+use crate::{Body, SimulationSettings};
+
+// Columns of an exported frame, see export.rs
+const FEATURES: usize = 6;
+// Report lines printed to the terminal, the CSV gets every frame
+const REPORT_LINES: usize = 20;
+const PLOT_HEIGHT: usize = 12;
+
+const USAGE: &str = "usage: rust-n-body --compare <run_a> <run_b> [--out <report.csv>] [--tolerance <distance>] [--plot]
+run_a and run_b are directories written by Export Frames from the same scenario. Frames are compared in order,
+bodies by their row. --tolerance (default 0.001) is the RMS distance the runs count as diverged at.";
+
+// Reads back a float32 array written by write_npy
+pub fn read_npy(bytes: &[u8]) -> Result<(Vec<usize>, Vec<f32>), String> {
+    if bytes.len() < 10 || &bytes[..8] != b"\x93NUMPY\x01\x00" {
+        return Err("not an npy v1.0 file".to_string());
+    }
+    let header_len = u16::from_le_bytes([bytes[8], bytes[9]]) as usize;
+    let header = bytes
+        .get(10..10 + header_len)
+        .and_then(|h| std::str::from_utf8(h).ok())
+        .ok_or("truncated header")?;
+    if !header.contains("'<f4'") || header.contains("'fortran_order': True") {
+        return Err(format!("only little-endian float32 in C order is read, got {}", header.trim()));
+    }
+    let shape_start = header.find("'shape': (").ok_or("no shape in header")? + "'shape': (".len();
+    let shape_end = shape_start + header[shape_start..].find(')').ok_or("no shape in header")?;
+    let shape = header[shape_start..shape_end]
+        .split(',')
+        .map(str::trim)
+        .filter(|d| !d.is_empty())
+        .map(|d| d.parse().map_err(|_| format!("bad dimension {}", d)))
+        .collect::<Result<Vec<usize>, String>>()?;
+
+    let data = &bytes[10 + header_len..];
+    let count: usize = shape.iter().product();
+    if data.len() != count * 4 {
+        return Err(format!("shape {:?} needs {} bytes of data, found {}", shape, count * 4, data.len()));
+    }
+    let values = data
+        .chunks_exact(4)
+        .map(|b| f32::from_le_bytes([b[0], b[1], b[2], b[3]]))
+        .collect();
+    Ok((shape, values))
+}
+
+// A number from the export manifest. Every key in it is unique, so no JSON parser is needed.
+pub fn manifest_number(manifest: &str, key: &str) -> Option<f32> {
+    let pattern = format!("\"{}\":", key);
+    let rest = manifest[manifest.find(&pattern)? + pattern.len()..].trim_start();
+    let end = rest.find([',', '}', '\n']).unwrap_or(rest.len());
+    rest[..end].trim().parse().ok()
+}
+
+// Root mean square and largest distance between matching bodies
+pub fn position_difference(a: &[Vec2], b: &[Vec2]) -> (f64, f64) {
+    let n = a.len().min(b.len());
+    if n == 0 {
+        return (0.0, 0.0);
+    }
+    let mut sum = 0.0;
+    let mut max: f64 = 0.0;
+    for (pa, pb) in a.iter().zip(b) {
+        let d = pa.distance(*pb) as f64;
+        sum += d * d;
+        max = max.max(d);
+    }
+    ((sum / n as f64).sqrt(), max)
+}
+
+// Rows of text drawing the values from left to right, tallest at the top
+pub fn text_plot(values: &[f64], height: usize) -> Vec<String> {
+    let (min, max) = values
+        .iter()
+        .filter(|v| v.is_finite())
+        .fold((f64::INFINITY, f64::NEG_INFINITY), |(lo, hi), v| (lo.min(*v), hi.max(*v)));
+    if values.is_empty() || !min.is_finite() || height == 0 {
+        return Vec::new();
+    }
+    let span = (max - min).max(f64::EPSILON);
+    let level = |v: f64| (((v - min) / span) * (height - 1) as f64).round() as usize;
+    (0..height)
+        .rev()
+        .map(|row| {
+            values
+                .iter()
+                .map(|v| if v.is_finite() && level(*v) == row { '*' } else { ' ' })
+                .collect()
+        })
+        .collect()
+}
+
+// One exported run, frames in order
+struct Run {
+    dir: PathBuf,
+    frames: Vec<PathBuf>,
+    pos_scale: f32,
+    vel_scale: f32,
+    mass_scale: f32,
+    g: Option<f32>,
+    softening: Option<f32>,
+}
+
+impl Run {
+    fn open(dir: &str) -> Result<Run, String> {
+        let dir = PathBuf::from(dir);
+        let manifest_path = dir.join("manifest.json");
+        let manifest =
+            fs::read_to_string(&manifest_path).map_err(|e| format!("{}: {}", manifest_path.display(), e))?;
+        let scale = |key: &str| {
+            manifest_number(&manifest, key).ok_or(format!("{}: no {} normalization", manifest_path.display(), key))
+        };
+        let mut frames: Vec<PathBuf> = fs::read_dir(&dir)
+            .map_err(|e| format!("{}: {}", dir.display(), e))?
+            .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+            .filter(|path| is_frame(path))
+            .collect();
+        // zero padded, so names sort in step order
+        frames.sort();
+        Ok(Run {
+            pos_scale: scale("position")?,
+            vel_scale: scale("velocity")?,
+            mass_scale: scale("mass")?,
+            // older exports don't record them, the energy is left out then
+            g: manifest_number(&manifest, "g"),
+            softening: manifest_number(&manifest, "softening"),
+            dir,
+            frames,
+        })
+    }
+
+    // Mass, position and velocity of every valid body in a frame, in world units
+    fn frame(&self, i: usize) -> Result<Vec<(f32, Vec2, Vec2)>, String> {
+        let path = &self.frames[i];
+        let bytes = fs::read(path).map_err(|e| format!("{}: {}", path.display(), e))?;
+        let (shape, values) = read_npy(&bytes).map_err(|e| format!("{}: {}", path.display(), e))?;
+        if shape.len() != 2 || shape[1] != FEATURES {
+            return Err(format!("{}: expected rows of {} features, got shape {:?}", path.display(), FEATURES, shape));
+        }
+        Ok(values
+            .chunks_exact(FEATURES)
+            .filter(|row| row[5] != 0.0)
+            .map(|row| {
+                (
+                    row[4] * self.mass_scale,
+                    Vec2::new(row[0], row[1]) * self.pos_scale,
+                    Vec2::new(row[2], row[3]) * self.vel_scale,
+                )
+            })
+            .collect())
+    }
+}
+
+fn is_frame(path: &Path) -> bool {
+    path.file_name()
+        .and_then(|name| name.to_str())
+        .is_some_and(|name| name.starts_with("frame_") && name.ends_with(".npy"))
+}
+
+// Kinetic plus potential energy, with the run's G and softening and the tree the simulation uses
+fn total_energy(bodies: &[(f32, Vec2, Vec2)], settings: &SimulationSettings) -> f64 {
+    let sim = HeadlessSim::from_state(bodies.iter().map(|(mass, position, velocity)| {
+        let body = Body {
+            mass: *mass,
+            radius: 0.0,
+            hue: 0.0,
+        };
+        (body, Transform::from_translation(position.extend(0.0)), velocity.extend(0.0))
+    }));
+    let (kinetic, potential) = sim.energy(settings);
+    kinetic + potential
+}
+
+// Compares two exported runs frame by frame and reports how they drift apart
+pub fn run(args: &[String]) -> Result<(), String> {
+    let mut dirs = Vec::new();
+    let mut out_path: Option<String> = None;
+    let mut tolerance: f64 = 1e-3;
+    let mut plot = false;
+
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--out" => out_path = Some(args.next().ok_or(USAGE)?.clone()),
+            "--tolerance" => tolerance = args.next().and_then(|t| t.parse().ok()).ok_or(USAGE)?,
+            "--plot" => plot = true,
+            dir if dirs.len() < 2 => dirs.push(dir.to_string()),
+            _ => return Err(USAGE.to_string()),
+        }
+    }
+    let [a, b] = dirs.as_slice() else {
+        return Err(USAGE.to_string());
+    };
+    let (a, b) = (Run::open(a)?, Run::open(b)?);
+    if a.frames.len() != b.frames.len() {
+        println!(
+            "{} has {} frames and {} has {}, comparing the first {}",
+            a.dir.display(),
+            a.frames.len(),
+            b.dir.display(),
+            b.frames.len(),
+            a.frames.len().min(b.frames.len())
+        );
+    }
+    let frames = a.frames.len().min(b.frames.len());
+    if frames == 0 {
+        return Err("no frames to compare".to_string());
+    }
+
+    // the same forces for both, so only the states differ
+    let energy_settings = match (a.g, a.softening) {
+        (Some(g), Some(softening)) if a.g == b.g && a.softening == b.softening => Some(SimulationSettings {
+            g,
+            softening,
+            ..Default::default()
+        }),
+        _ => {
+            println!("The manifests don't agree on G and softening, leaving out the energy");
+            None
+        }
+    };
+
+    let mut report = String::from("frame,rms_position,max_position,energy_a,energy_b,relative_energy_difference\n");
+    let mut rms_history = Vec::with_capacity(frames);
+    let mut diverged = None;
+    let every = frames.div_ceil(REPORT_LINES);
+    println!("{:>8} {:>14} {:>14} {:>14}", "frame", "rms", "max", "energy diff");
+    for i in 0..frames {
+        let (bodies_a, bodies_b) = (a.frame(i)?, b.frame(i)?);
+        if bodies_a.len() != bodies_b.len() {
+            println!("frame {}: {} bodies against {}", i, bodies_a.len(), bodies_b.len());
+        }
+        let positions = |bodies: &[(f32, Vec2, Vec2)]| bodies.iter().map(|(_, p, _)| *p).collect::<Vec<Vec2>>();
+        let (rms, max) = position_difference(&positions(&bodies_a), &positions(&bodies_b));
+        let energies = energy_settings
+            .as_ref()
+            .map(|settings| (total_energy(&bodies_a, settings), total_energy(&bodies_b, settings)));
+        let relative = energies.map(|(ea, eb)| if ea != 0.0 { ((eb - ea) / ea).abs() } else { (eb - ea).abs() });
+
+        if diverged.is_none() && rms > tolerance {
+            diverged = Some(i);
+        }
+        rms_history.push(rms);
+        // empty columns when the energy is left out
+        let text = |value: Option<f64>| value.map_or(String::new(), |v| format!("{:?}", v));
+        report.push_str(&format!(
+            "{},{:?},{:?},{},{},{}\n",
+            i,
+            rms,
+            max,
+            text(energies.map(|(ea, _)| ea)),
+            text(energies.map(|(_, eb)| eb)),
+            text(relative)
+        ));
+        if i % every == 0 || i == frames - 1 {
+            println!(
+                "{:>8} {:>14.6e} {:>14.6e} {:>14}",
+                i,
+                rms,
+                max,
+                relative.map_or("-".to_string(), |r| format!("{:.6e}", r))
+            );
+        }
+    }
+
+    match diverged {
+        Some(frame) => println!("RMS difference first went over {} at frame {}", tolerance, frame),
+        None => println!("RMS difference stayed within {} over all {} frames", tolerance, frames),
+    }
+
+    if plot {
+        // on a log scale, identical frames sit at the bottom
+        let logs: Vec<f64> = rms_history.iter().map(|rms| rms.max(1e-12).log10()).collect();
+        println!("log10 RMS position difference, frame 0 to {}:", frames - 1);
+        let columns = REPORT_LINES * 4;
+        let sampled: Vec<f64> = (0..columns.min(frames))
+            .map(|c| logs[c * (frames - 1) / (columns.min(frames) - 1).max(1)])
+            .collect();
+        for row in text_plot(&sampled, PLOT_HEIGHT) {
+            println!("|{}", row);
+        }
+    }
+
+    if let Some(path) = out_path {
+        fs::write(&path, report).map_err(|e| format!("{}: {}", path, e))?;
+        println!("Wrote {}", path);
+    }
+    Ok(())
+}
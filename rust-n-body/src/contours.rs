@@ -0,0 +1,155 @@
+use bevy::prelude::*;
+use crate::bhtree::{Quad, Quadtree};
+use crate::tracer::Massless;
+use crate::{Body, MainCamera, SimulationSettings};
+
+// Iso-potential lines over the visible area, sampled from the tree on a coarse grid. Recomputed every
+// few frames only, since every grid point is a full potential walk.
+#[derive(Resource)]
+pub struct PotentialContours {
+    pub enabled: bool,
+    pub every_frames: u32,
+    // grid points along each side of the view
+    pub resolution: u32,
+    pub levels: u32,
+    // line segments with the level they belong to, 0 the deepest
+    pub segments: Vec<(Vec2, Vec2, f32)>,
+    frames: u32,
+}
+
+impl Default for PotentialContours {
+    fn default() -> Self {
+        PotentialContours {
+            enabled: false,
+            every_frames: 10,
+            resolution: 48,
+            levels: 12,
+            segments: Vec::new(),
+            frames: 0,
+        }
+    }
+}
+
+// Where the value crosses level along the edge from a to b
+fn crossing(a: (Vec2, f32), b: (Vec2, f32), level: f32) -> Vec2 {
+    let t = if b.1 != a.1 { (level - a.1) / (b.1 - a.1) } else { 0.5 };
+    a.0.lerp(b.0, t.clamp(0.0, 1.0))
+}
+
+// Marching squares over a row major nx by ny grid of values, grid point (i, j) at origin + (i, j) * cell.
+// Saddle cells are split the way the average of the four corners says.
+pub fn marching_squares(values: &[f32], nx: usize, ny: usize, origin: Vec2, cell: Vec2, level: f32) -> Vec<(Vec2, Vec2)> {
+    let mut segments = Vec::new();
+    if nx < 2 || ny < 2 {
+        return segments;
+    }
+    let point = |i: usize, j: usize| (origin + Vec2::new(i as f32, j as f32) * cell, values[j * nx + i]);
+
+    for j in 0..ny - 1 {
+        for i in 0..nx - 1 {
+            // corners counter-clockwise from the bottom left
+            let c = [point(i, j), point(i + 1, j), point(i + 1, j + 1), point(i, j + 1)];
+            let case = c
+                .iter()
+                .enumerate()
+                .fold(0, |case, (k, (_p, v))| if *v > level { case | 1 << k } else { case });
+            // edges bottom, right, top, left
+            let edge = |e: usize| match e {
+                0 => crossing(c[0], c[1], level),
+                1 => crossing(c[1], c[2], level),
+                2 => crossing(c[3], c[2], level),
+                _ => crossing(c[0], c[3], level),
+            };
+            let center_above = c.iter().map(|(_p, v)| v).sum::<f32>() / 4.0 > level;
+            let pairs: &[(usize, usize)] = match case {
+                1 | 14 => &[(3, 0)],
+                2 | 13 => &[(0, 1)],
+                3 | 12 => &[(3, 1)],
+                4 | 11 => &[(1, 2)],
+                6 | 9 => &[(0, 2)],
+                7 | 8 => &[(3, 2)],
+                5 if center_above => &[(0, 1), (3, 2)],
+                5 => &[(3, 0), (1, 2)],
+                10 if center_above => &[(3, 0), (1, 2)],
+                10 => &[(0, 1), (3, 2)],
+                _ => &[],
+            };
+            segments.extend(pairs.iter().map(|(a, b)| (edge(*a), edge(*b))));
+        }
+    }
+    segments
+}
+
+// Levels evenly spaced in log |potential|, since the wells are many times deeper than the
+// space between them. 0 is the deepest level.
+pub fn contour_levels(values: &[f32], levels: u32) -> Vec<f32> {
+    let (min, max) = values
+        .iter()
+        .filter(|v| **v < 0.0 && v.is_finite())
+        .map(|v| (-v).ln())
+        .fold((f32::MAX, f32::MIN), |(min, max), v| (min.min(v), max.max(v)));
+    if levels == 0 || min >= max {
+        return Vec::new();
+    }
+    (0..levels)
+        .map(|k| -(max - (max - min) * (k as f32 + 0.5) / levels as f32).exp())
+        .collect()
+}
+
+pub fn update_contours(
+    mut contours: ResMut<PotentialContours>,
+    settings: Res<SimulationSettings>,
+    cameras: Query<(&Transform, &Projection), With<MainCamera>>,
+    bodies: Query<(Entity, &Transform, &Body), Without<Massless>>,
+) {
+    if !contours.enabled {
+        contours.segments.clear();
+        return;
+    }
+    contours.frames += 1;
+    if contours.frames < contours.every_frames && !contours.segments.is_empty() {
+        return;
+    }
+    contours.frames = 0;
+
+    let Ok((camera, Projection::Orthographic(ortho))) = cameras.single() else {
+        return;
+    };
+    let center = camera.translation.truncate();
+    let (min, max) = (center + ortho.area.min, center + ortho.area.max);
+
+    let positions: Vec<Vec2> = bodies.iter().map(|(_e, t, _b)| t.translation.truncate()).collect();
+    if positions.is_empty() {
+        contours.segments.clear();
+        return;
+    }
+    let mut tree = Quadtree::with_leaf_capacity(Quad::new_containing(&positions), settings.leaf_capacity as usize);
+    for (entity, transform, body) in &bodies {
+        tree.insert(entity, *transform, *body);
+    }
+
+    let n = contours.resolution.max(2) as usize;
+    let cell = (max - min) / (n - 1) as f32;
+    let mut values = Vec::with_capacity(n * n);
+    for j in 0..n {
+        for i in 0..n {
+            let p = min + Vec2::new(i as f32, j as f32) * cell;
+            let at = Transform::from_xyz(p.x, p.y, 0.0);
+            values.push(tree.get_potential(Entity::PLACEHOLDER, at, settings.force_params(), settings.theta));
+        }
+    }
+
+    let levels = contour_levels(&values, contours.levels);
+    let mut segments = Vec::new();
+    for (k, level) in levels.iter().enumerate() {
+        let shade = k as f32 / (levels.len().max(2) - 1) as f32;
+        segments.extend(marching_squares(&values, n, n, min, cell, *level).into_iter().map(|(a, b)| (a, b, shade)));
+    }
+    contours.segments = segments;
+}
+
+pub fn draw_contours(contours: Res<PotentialContours>, settings: Res<SimulationSettings>, mut gizmos: Gizmos) {
+    for (a, b, shade) in &contours.segments {
+        gizmos.line_2d(*a, *b, settings.colormap.sample(*shade).with_alpha(0.6));
+    }
+}
@@ -0,0 +1,287 @@
+use bevy::prelude::*;
+use bevy_egui::{EguiContexts, egui};
+use std::collections::HashMap;
+use std::sync::Arc;
+use crate::bhtree::Quadtree;
+use crate::orbital_mechanics::{find_primary, l2_point};
+use crate::providers::ForceInput;
+use crate::selection::Selected;
+use crate::soa::BodyBuffers;
+use crate::ui_config::UiConfig;
+use crate::Body;
+
+pub type ControlFn = dyn Fn(&ControlInput) -> Vec2 + Send + Sync;
+
+// How many of the nearest bodies a keep clear controller looks out for
+const KEEP_CLEAR_NEIGHBORS: usize = 4;
+
+// What a controller can see when it decides on its thrust: its own body, and any other body by entity
+// or by distance, all at the start of the step
+pub struct ControlInput<'a> {
+    pub body: ForceInput,
+    pub time: f64,
+    pub dt: f32,
+    bodies: &'a [ForceInput],
+    index: &'a HashMap<Entity, usize>,
+    // where the bodies the closure was made for are now, if they were spawned anew since
+    moved: Option<&'a HashMap<Entity, Entity>>,
+    // for nearest
+    tree: &'a Quadtree,
+}
+
+impl ControlInput<'_> {
+    pub fn get(&self, entity: Entity) -> Option<ForceInput> {
+        let entity = self.moved.and_then(|moved| moved.get(&entity)).copied().unwrap_or(entity);
+        self.index.get(&entity).map(|i| self.bodies[*i])
+    }
+
+    // The k closest bodies that are in the tree, nearest first
+    pub fn nearest(&self, k: usize) -> Vec<ForceInput> {
+        self.tree
+            .knn(self.body.position, k + 1)
+            .into_iter()
+            .filter(|(entity, _position)| *entity != self.body.entity)
+            .filter_map(|(entity, _position)| self.get(entity))
+            .take(k)
+            .collect()
+    }
+}
+
+// Thrust for one body, decided every step by a closure. The thrust is an acceleration, capped at max_accel.
+// Clones share the closure, which is how undo and bookmarks keep a controller.
+#[derive(Component, Clone)]
+pub struct Controller {
+    pub name: String,
+    pub max_accel: f32,
+    pub enabled: bool,
+    control: Arc<ControlFn>,
+    // the entities the closure knows by, to the ones the bodies have now
+    moved: Option<Arc<HashMap<Entity, Entity>>>,
+    // what it did on the last step and in total, the delta-v budget of a spacecraft
+    pub thrust: Vec2,
+    pub delta_v: f32,
+}
+
+impl Controller {
+    pub fn new(name: &str, max_accel: f32, control: impl Fn(&ControlInput) -> Vec2 + Send + Sync + 'static) -> Self {
+        Controller {
+            name: name.to_string(),
+            max_accel,
+            enabled: true,
+            control: Arc::new(control),
+            moved: None,
+            thrust: Vec2::ZERO,
+            delta_v: 0.0,
+        }
+    }
+
+    // Holds the body at the L2 point of the pair, outside the secondary. The point turns with the pair,
+    // so it is chased with a critically damped spring towards where it is and how it moves.
+    pub fn station_keeping_l2(primary: Entity, secondary: Entity, gain: f32, max_accel: f32) -> Self {
+        Controller::new("Station keeping at L2", max_accel, move |input| {
+            let (Some(p), Some(s)) = (input.get(primary), input.get(secondary)) else {
+                return Vec2::ZERO;
+            };
+            let Some(target) = l2_point(p.mass, p.position, s.mass, s.position) else {
+                return Vec2::ZERO;
+            };
+            let total = p.mass + s.mass;
+            let com = (p.mass * p.position + s.mass * s.position) / total;
+            let com_vel = (p.mass * p.velocity + s.mass * s.velocity) / total;
+            let rel = s.position - p.position;
+            let omega = rel.perp_dot(s.velocity - p.velocity) / rel.length_squared();
+            let target_vel = com_vel + omega * (target - com).perp();
+            gain * gain * (target - input.body.position) + 2.0 * gain * (target_vel - input.body.velocity)
+        })
+    }
+
+    // Keeps the nearest bodies at least clearance away, pushing off each one that is closer with the same
+    // damped spring as station keeping, so it backs off more the faster the other one closes in
+    pub fn keep_clear(clearance: f32, gain: f32, max_accel: f32) -> Self {
+        Controller::new("Keep clear", max_accel, move |input| {
+            input
+                .nearest(KEEP_CLEAR_NEIGHBORS)
+                .iter()
+                .filter_map(|other| {
+                    let away = input.body.position - other.position;
+                    let d = away.length();
+                    if d >= clearance || d <= 0.0 {
+                        return None;
+                    }
+                    let dir = away / d;
+                    let closing = (other.velocity - input.body.velocity).dot(dir).max(0.0);
+                    Some(dir * (gain * gain * (clearance - d) + 2.0 * gain * closing))
+                })
+                .sum()
+        })
+    }
+
+    // After the bodies were spawned anew from a snapshot, with moved from their old entities to the new ones
+    pub fn respawned(&mut self, moved: &Arc<HashMap<Entity, Entity>>) {
+        self.moved = match &self.moved {
+            // the closure still asks for the entities from before the earlier respawn
+            Some(before) => {
+                let mut all = HashMap::clone(moved);
+                for (from, to) in before.iter() {
+                    all.insert(*from, moved.get(to).copied().unwrap_or(*to));
+                }
+                Some(Arc::new(all))
+            }
+            None => Some(moved.clone()),
+        };
+    }
+
+    // The capped thrust for this step, which is also recorded
+    pub fn thrust_for(&mut self, input: &ControlInput) -> Vec2 {
+        if !self.enabled {
+            self.thrust = Vec2::ZERO;
+            return Vec2::ZERO;
+        }
+        let input = ControlInput {
+            moved: self.moved.as_deref(),
+            ..*input
+        };
+        let thrust = (self.control)(&input);
+        self.thrust = if thrust.is_finite() { thrust.clamp_length_max(self.max_accel) } else { Vec2::ZERO };
+        self.delta_v += self.thrust.length() * input.dt;
+        self.thrust
+    }
+}
+
+// Adds every controller's thrust to its body's kick for this step, before the integration
+pub fn apply_controllers(
+    controllers: &mut Query<&mut Controller>,
+    buffers: &mut BodyBuffers,
+    tree: &Quadtree,
+    time: f64,
+    dt: f32,
+) {
+    if controllers.is_empty() {
+        return;
+    }
+    let bodies: Vec<ForceInput> = (0..buffers.len())
+        .map(|i| ForceInput {
+            entity: buffers.entities[i],
+            mass: buffers.m[i],
+            position: Vec2::new(buffers.x[i], buffers.y[i]),
+            velocity: buffers.velocity(i).truncate(),
+        })
+        .collect();
+    let index: HashMap<Entity, usize> = bodies.iter().enumerate().map(|(i, body)| (body.entity, i)).collect();
+    for (i, body) in bodies.iter().enumerate() {
+        let Ok(mut controller) = controllers.get_mut(body.entity) else {
+            continue;
+        };
+        let input = ControlInput {
+            body: *body,
+            time,
+            dt,
+            bodies: &bodies,
+            index: &index,
+            moved: None,
+            tree,
+        };
+        let thrust = controller.thrust_for(&input);
+        buffers.ax[i] += thrust.x * dt;
+        buffers.ay[i] += thrust.y * dt;
+    }
+}
+
+// Settings for the controllers added from the window
+#[derive(Resource)]
+pub struct ControllerTools {
+    pub gain: f32,
+    pub max_accel: f32,
+    pub clearance: f32,
+}
+
+impl Default for ControllerTools {
+    fn default() -> Self {
+        ControllerTools {
+            gain: 5.0,
+            max_accel: 50.0,
+            clearance: 10.0,
+        }
+    }
+}
+
+pub fn controllers_window(
+    mut contexts: EguiContexts,
+    mut tools: ResMut<ControllerTools>,
+    ui_config: Res<UiConfig>,
+    mut controlled: Query<(Entity, &mut Controller)>,
+    selected: Query<Entity, With<Selected>>,
+    bodies: Query<(Entity, &Body, &Transform)>,
+    mut commands: Commands,
+) {
+    ui_config
+        .place(egui::Window::new("Controllers"), "Controllers")
+        .default_open(false)
+        .show(contexts.ctx_mut(), |ui| {
+            ui.add(egui::Slider::new(&mut tools.gain, 0.1..=50.0).logarithmic(true).text("Gain"));
+            ui.add(egui::Slider::new(&mut tools.max_accel, 0.1..=1000.0).logarithmic(true).text("Max Thrust"));
+            let selected = selected.single().ok();
+            if ui
+                .add_enabled(selected.is_some(), egui::Button::new("Station Keep Selected At L2"))
+                .clicked()
+            {
+                // the pair is the body pulling hardest on the selected one, and what pulls hardest on that
+                let others: Vec<(Entity, Vec2, f32)> = bodies
+                    .iter()
+                    .map(|(entity, body, transform)| (entity, transform.translation.truncate(), body.mass))
+                    .collect();
+                let points: Vec<(Vec2, f32)> = others.iter().map(|(_e, p, m)| (*p, *m)).collect();
+                let pair = selected.and_then(|entity| bodies.get(entity).ok()).and_then(|(entity, body, transform)| {
+                    let secondary = find_primary(transform.translation.truncate(), body.mass, &points)?;
+                    let primary = find_primary(points[secondary].0, points[secondary].1, &points)?;
+                    Some((entity, others[primary].0, others[secondary].0))
+                });
+                match pair {
+                    Some((entity, primary, secondary)) => {
+                        commands.entity(entity).insert(Controller::station_keeping_l2(
+                            primary,
+                            secondary,
+                            tools.gain,
+                            tools.max_accel,
+                        ));
+                    }
+                    None => warn!("Station keeping needs a heavier body near the selected one, and one heavier still"),
+                }
+            }
+            ui.horizontal(|ui| {
+                if ui
+                    .add_enabled(selected.is_some(), egui::Button::new("Keep Selected Clear Of Others"))
+                    .clicked()
+                    && let Some(entity) = selected
+                {
+                    commands
+                        .entity(entity)
+                        .insert(Controller::keep_clear(tools.clearance, tools.gain, tools.max_accel));
+                }
+                ui.add(
+                    egui::DragValue::new(&mut tools.clearance)
+                        .range(0.1..=10000.0)
+                        .prefix("by "),
+                );
+            });
+
+            ui.separator();
+            if controlled.is_empty() {
+                ui.label("No controlled bodies");
+            }
+            for (entity, mut controller) in &mut controlled {
+                ui.horizontal(|ui| {
+                    let name = controller.name.clone();
+                    ui.checkbox(&mut controller.enabled, name);
+                    ui.label(format!(
+                        "thrust {:.2}, delta-v {:.2}",
+                        controller.thrust.length(),
+                        controller.delta_v
+                    ));
+                    if ui.button("Remove").clicked() {
+                        commands.entity(entity).remove::<Controller>();
+                    }
+                });
+            }
+        });
+}
@@ -0,0 +1,109 @@
+use bevy::prelude::*;
+use crate::fade::Fade;
+use crate::guard::Quarantined;
+use crate::tags::GroupHidden;
+use crate::tracer::Massless;
+use crate::{Body, MainCamera, SimulationSettings};
+
+// Which bodies get drawn when only a fraction of them are. Hidden bodies are still simulated,
+// they just never make it into the render world, which is what gets slow at very large N.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Decimation {
+    // a fixed random subset, the same bodies stay visible from frame to frame
+    Random,
+    Heaviest,
+}
+
+impl Decimation {
+    pub const ALL: [Decimation; 2] = [Decimation::Random, Decimation::Heaviest];
+}
+
+// Stable pseudo random number in [0, 1) for a body, splitmix64 of its id
+fn unit_hash(id: u32) -> f64 {
+    let mut z = (id as u64).wrapping_add(0x9E3779B97F4A7C15);
+    z = (z ^ (z >> 30)).wrapping_mul(0xBF58476D1CE4E5B9);
+    z = (z ^ (z >> 27)).wrapping_mul(0x94D049BB133111EB);
+    z ^= z >> 31;
+    (z >> 11) as f64 / (1u64 << 53) as f64
+}
+
+// For each (id, mass), whether it should be drawn
+pub fn pick_visible(bodies: &[(u32, f32)], fraction: f32, mode: Decimation) -> Vec<bool> {
+    if fraction >= 1.0 {
+        return vec![true; bodies.len()];
+    }
+    match mode {
+        Decimation::Random => bodies
+            .iter()
+            .map(|(id, _m)| unit_hash(*id) < fraction as f64)
+            .collect(),
+        Decimation::Heaviest => {
+            let keep = (bodies.len() as f32 * fraction).ceil() as usize;
+            let mut order: Vec<usize> = (0..bodies.len()).collect();
+            order.sort_by(|a, b| bodies[*b].1.total_cmp(&bodies[*a].1));
+            let mut visible = vec![false; bodies.len()];
+            for i in order.into_iter().take(keep) {
+                visible[i] = true;
+            }
+            visible
+        }
+    }
+}
+
+// Only redone when the setting or the number of bodies changes, sorting every frame would defeat the point
+pub fn render_decimation(
+    settings: Res<SimulationSettings>,
+    mut last: Local<Option<(f32, Decimation, usize)>>,
+    mut bodies: Query<(Entity, &Body, &mut Visibility), (Without<Massless>, Without<Quarantined>, Without<GroupHidden>)>,
+) {
+    let count = bodies.iter().count();
+    let wanted = (settings.render_fraction, settings.decimation, count);
+    if *last == Some(wanted) {
+        return;
+    }
+    *last = Some(wanted);
+
+    let masses: Vec<(u32, f32)> = bodies.iter().map(|(e, b, _v)| (e.index(), b.mass)).collect();
+    let visible = pick_visible(&masses, settings.render_fraction, settings.decimation);
+    for ((_entity, _body, mut visibility), show) in bodies.iter_mut().zip(visible) {
+        let wanted = if show { Visibility::Inherited } else { Visibility::Hidden };
+        visibility.set_if_neq(wanted);
+    }
+}
+
+// Scale that keeps a body between min_pixels and max_pixels in radius on screen
+pub fn display_scale(radius: f32, world_per_pixel: f32, min_pixels: f32, max_pixels: f32) -> f32 {
+    if radius <= 0.0 || world_per_pixel <= 0.0 {
+        return 1.0;
+    }
+    let pixels = radius / world_per_pixel;
+    pixels.clamp(min_pixels, max_pixels.max(min_pixels)) / pixels
+}
+
+// Only the drawn size follows the zoom, collisions keep using the body's radius.
+// Bodies that are fading in or out are left to the fade, which animates the scale itself.
+pub fn clamp_render_size(
+    settings: Res<SimulationSettings>,
+    mut was_enabled: Local<bool>,
+    cameras: Query<&Projection, With<MainCamera>>,
+    mut bodies: Query<(&Body, &mut Transform), (Without<Fade>, Without<MainCamera>)>,
+) {
+    if !settings.size_clamp {
+        if *was_enabled {
+            *was_enabled = false;
+            for (_body, mut transform) in &mut bodies {
+                transform.scale = Vec3::ONE;
+            }
+        }
+        return;
+    }
+    *was_enabled = true;
+    let Ok(Projection::Orthographic(ortho)) = cameras.single() else {
+        return;
+    };
+    for (body, mut transform) in &mut bodies {
+        let radius = settings.render_radius(body.radius);
+        let scale = display_scale(radius, ortho.scale, settings.min_pixels, settings.max_pixels);
+        transform.scale = Vec3::splat(scale);
+    }
+}
@@ -0,0 +1,247 @@
+use bevy::prelude::*;
+use bevy::window::PrimaryWindow;
+use bevy_egui::{EguiContexts, egui};
+use crate::lagrangian::densest_point;
+use crate::leaderboard::{Merges, heaviest};
+use crate::tracer::Massless;
+use crate::ui_config::UiConfig;
+use crate::{Body, MainCamera, ResetEvent, SimulationSettings, Velocity};
+
+// Only the heaviest bodies are checked for encounters, every pair of them
+const CANDIDATES: usize = 50;
+// Bodies passing closer than this many times their radii count as an encounter
+const ENCOUNTER_RADII: f32 = 3.0;
+// A clump is framed out to its this many nearest bodies
+const CLUMP_MEMBERS: usize = 30;
+
+// What the camera is looking at
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum Shot {
+    Encounter(Entity, Entity),
+    Merger(Entity),
+    // center and radius, clumps are too loose to follow body by body
+    Clump(Vec2, f32),
+}
+
+impl Shot {
+    pub fn label(&self) -> &'static str {
+        match self {
+            Shot::Encounter(..) => "Close encounter",
+            Shot::Merger(_) => "Biggest merger so far",
+            Shot::Clump(..) => "Densest clump",
+        }
+    }
+}
+
+// Points the camera at whatever is most worth watching, for recordings nobody is at the controls for.
+// A new biggest merger cuts in at once, otherwise each shot is held for a while before looking again.
+#[derive(Resource)]
+pub struct Director {
+    pub enabled: bool,
+    pub encounters: bool,
+    pub mergers: bool,
+    pub clumps: bool,
+    // how far ahead encounters are looked for, in steps of delta_t
+    pub look_ahead_steps: f32,
+    pub hold_secs: f32,
+    // how quickly the camera catches up with the shot, per second
+    pub smoothing: f32,
+    pub shot: Option<Shot>,
+    biggest_merger: f32,
+    held: f32,
+}
+
+impl Default for Director {
+    fn default() -> Self {
+        Director {
+            enabled: false,
+            encounters: true,
+            mergers: true,
+            clumps: true,
+            look_ahead_steps: 200.0,
+            hold_secs: 8.0,
+            smoothing: 1.5,
+            shot: None,
+            biggest_merger: 0.0,
+            held: 0.0,
+        }
+    }
+}
+
+// Time until two bodies moving in straight lines are closest, and how far apart they are then
+pub fn closest_approach(offset: Vec2, relative_velocity: Vec2) -> (f32, f32) {
+    let speed2 = relative_velocity.length_squared();
+    if speed2 == 0.0 {
+        return (0.0, offset.length());
+    }
+    let t = (-offset.dot(relative_velocity) / speed2).max(0.0);
+    (t, (offset + relative_velocity * t).length())
+}
+
+// Distance from a point to its k-th nearest body
+pub fn kth_nearest_distance(center: Vec2, positions: &[Vec2], k: usize) -> Option<f32> {
+    let mut distances: Vec<f32> = positions.iter().map(|p| p.distance(center)).collect();
+    let k = k.min(distances.len().checked_sub(1)?);
+    distances.select_nth_unstable_by(k, f32::total_cmp);
+    Some(distances[k])
+}
+
+// Projection scale that fits a circle of the given radius into the window
+pub fn zoom_for_extent(extent: f32, window: Vec2) -> f32 {
+    let pixels = window.min_element();
+    if pixels <= 0.0 || extent <= 0.0 {
+        return 1.0;
+    }
+    (2.0 * extent / pixels).clamp(1e-4, 1e4)
+}
+
+type DirectedBodies<'w, 's> =
+    Query<'w, 's, (Entity, &'static Body, &'static Transform, &'static Velocity), (Without<Massless>, Without<MainCamera>)>;
+
+// The closest coming encounter among the heaviest bodies
+fn find_encounter(bodies: &DirectedBodies, horizon: f32) -> Option<Shot> {
+    let all: Vec<_> = bodies.iter().collect();
+    let masses: Vec<f32> = all.iter().map(|(_, body, ..)| body.mass).collect();
+    let candidates = heaviest(&masses, CANDIDATES);
+
+    let mut best: Option<(f32, Shot)> = None;
+    for (n, &i) in candidates.iter().enumerate() {
+        for &j in &candidates[n + 1..] {
+            let (a, body_a, transform_a, velocity_a) = all[i];
+            let (b, body_b, transform_b, velocity_b) = all[j];
+            let (t, d) = closest_approach(
+                (transform_b.translation - transform_a.translation).truncate(),
+                (velocity_b.0 - velocity_a.0).truncate(),
+            );
+            let reach = body_a.radius + body_b.radius;
+            // already touching is a collision, not something coming
+            if t <= 0.0 || t > horizon || d > ENCOUNTER_RADII * reach {
+                continue;
+            }
+            let score = d / reach.max(f32::EPSILON);
+            if best.is_none_or(|(s, _)| score < s) {
+                best = Some((score, Shot::Encounter(a, b)));
+            }
+        }
+    }
+    best.map(|(_, shot)| shot)
+}
+
+fn find_clump(bodies: &DirectedBodies) -> Option<Shot> {
+    let (masses, positions): (Vec<f32>, Vec<Vec2>) = bodies
+        .iter()
+        .map(|(_, body, transform, _)| (body.mass, transform.translation.truncate()))
+        .unzip();
+    let center = densest_point(&masses, &positions)?;
+    let extent = kth_nearest_distance(center, &positions, CLUMP_MEMBERS)?;
+    Some(Shot::Clump(center, extent * 1.5))
+}
+
+// Center and radius of the shot right now, none once its bodies are gone
+fn frame(shot: Shot, bodies: &DirectedBodies) -> Option<(Vec2, f32)> {
+    match shot {
+        Shot::Encounter(a, b) => {
+            let (_, body_a, transform_a, _) = bodies.get(a).ok()?;
+            let (_, body_b, transform_b, _) = bodies.get(b).ok()?;
+            let (pa, pb) = (transform_a.translation.truncate(), transform_b.translation.truncate());
+            Some(((pa + pb) / 2.0, (pa.distance(pb) * 0.75).max(8.0 * (body_a.radius + body_b.radius))))
+        }
+        Shot::Merger(entity) => {
+            let (_, body, transform, _) = bodies.get(entity).ok()?;
+            Some((transform.translation.truncate(), body.radius * 40.0))
+        }
+        Shot::Clump(center, extent) => Some((center, extent)),
+    }
+}
+
+pub fn direct_camera(
+    time: Res<Time>,
+    settings: Res<SimulationSettings>,
+    mut director: ResMut<Director>,
+    mut reset_events: EventReader<ResetEvent>,
+    merged: Query<(Entity, &Body), Changed<Merges>>,
+    bodies: DirectedBodies,
+    windows: Query<&Window, With<PrimaryWindow>>,
+    mut cameras: Query<(&mut Transform, &mut Projection), With<MainCamera>>,
+) {
+    if !reset_events.is_empty() {
+        reset_events.clear();
+        director.biggest_merger = 0.0;
+        director.shot = None;
+    }
+
+    // the record is kept while the director is off too, so turning it on doesn't replay old mergers
+    let biggest = merged.iter().max_by(|a, b| a.1.mass.total_cmp(&b.1.mass));
+    if let Some((entity, body)) = biggest
+        && body.mass > director.biggest_merger
+    {
+        director.biggest_merger = body.mass;
+        if director.enabled && director.mergers {
+            director.shot = Some(Shot::Merger(entity));
+            director.held = 0.0;
+        }
+    }
+    if !director.enabled {
+        director.shot = None;
+        return;
+    }
+
+    director.held += time.delta_secs();
+    if director.shot.is_none() || director.held >= director.hold_secs {
+        let horizon = director.look_ahead_steps * settings.delta_t;
+        let next = director
+            .encounters
+            .then(|| find_encounter(&bodies, horizon))
+            .flatten()
+            .or_else(|| director.clumps.then(|| find_clump(&bodies)).flatten());
+        // nothing new is worth a cut, stay on the old shot
+        if next.is_some() {
+            director.shot = next;
+        }
+        director.held = 0.0;
+    }
+
+    let Some(shot) = director.shot else {
+        return;
+    };
+    let Some((center, extent)) = frame(shot, &bodies) else {
+        director.shot = None;
+        return;
+    };
+    let (Ok(window), Ok((mut camera, mut projection))) = (windows.single(), cameras.single_mut()) else {
+        return;
+    };
+
+    // eases in, so cuts turn into smooth moves
+    let follow = 1.0 - (-director.smoothing * time.delta_secs()).exp();
+    camera.translation = camera.translation.truncate().lerp(center, follow).extend(camera.translation.z);
+    if let Projection::Orthographic(ortho) = &mut *projection {
+        let target = zoom_for_extent(extent, window.size());
+        ortho.scale = (ortho.scale.ln() + (target.ln() - ortho.scale.ln()) * follow).exp();
+    }
+}
+
+pub fn director_window(mut contexts: EguiContexts, mut director: ResMut<Director>, ui_config: Res<UiConfig>) {
+    ui_config
+        .place(egui::Window::new("Director"), "Director")
+        .default_open(false)
+        .show(contexts.ctx_mut(), |ui| {
+            ui.add(egui::Checkbox::new(&mut director.enabled, "Direct Camera"));
+            ui.label("Watch for:");
+            ui.add(egui::Checkbox::new(&mut director.encounters, "Close Encounters"));
+            ui.add(egui::Checkbox::new(&mut director.mergers, "Biggest Merger So Far"));
+            ui.add(egui::Checkbox::new(&mut director.clumps, "Densest Clump"));
+            ui.add(
+                egui::Slider::new(&mut director.look_ahead_steps, 10.0..=5000.0)
+                    .logarithmic(true)
+                    .text("Look Ahead (steps)"),
+            );
+            ui.add(egui::Slider::new(&mut director.hold_secs, 1.0..=60.0).text("Hold Each Shot (seconds)"));
+            ui.add(egui::Slider::new(&mut director.smoothing, 0.1..=10.0).logarithmic(true).text("Camera Speed"));
+            match director.shot {
+                Some(shot) if director.enabled => ui.label(format!("Showing: {}", shot.label())),
+                _ => ui.label("Showing: nothing"),
+            };
+            ui.label("Following a leader or the attract mode fight the director for the camera, use one at a time");
+        });
+}
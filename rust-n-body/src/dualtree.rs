@@ -0,0 +1,167 @@
+use bevy::prelude::*;
+use std::ops::{Add, Range};
+use crate::bhtree::Quadtree;
+use crate::force::ForceParams;
+
+// Step of the finite differences giving a node's tidal field, as a fraction of the distance to it
+const GRADIENT_STEP: f32 = 0.01;
+
+// A node of the flat copy of the tree the dual and bucket walks run on, see Quadtree::flatten
+pub struct Cell {
+    pub size: f32,
+    pub mass: f32,
+    pub com: Vec2,
+    // empty for leaves
+    pub children: Range<usize>,
+    pub bodies: Range<usize>,
+}
+
+// The pull of far away nodes on a node, to first order around its center of mass
+#[derive(Clone, Copy)]
+struct Expansion {
+    accel: Vec2,
+    gradient: Mat2,
+}
+
+// not derived, a default Mat2 is the identity
+impl Default for Expansion {
+    fn default() -> Self {
+        Expansion {
+            accel: Vec2::ZERO,
+            gradient: Mat2::ZERO,
+        }
+    }
+}
+
+impl Add for Expansion {
+    type Output = Expansion;
+
+    fn add(self, other: Expansion) -> Expansion {
+        Expansion {
+            accel: self.accel + other.accel,
+            gradient: self.gradient + other.gradient,
+        }
+    }
+}
+
+struct DualWalk {
+    cells: Vec<Cell>,
+    // entity, position and mass
+    bodies: Vec<(Entity, Vec2, f32)>,
+    force: ForceParams,
+    theta: f32,
+    locals: Vec<Expansion>,
+    accels: Vec<Vec2>,
+}
+
+impl DualWalk {
+    fn accel(&self, m2: f32, p1: Vec2, p2: Vec2) -> Vec2 {
+        self.force.accel(m2, p1.extend(0.0), p2.extend(0.0)).truncate()
+    }
+
+    // What target feels of source at the distance d, with the gradient from central differences
+    // so it works for every force law
+    fn far_field(&self, target: &Cell, source: &Cell, d: f32) -> Expansion {
+        let at = |p: Vec2| self.accel(source.mass, p, source.com);
+        let h = GRADIENT_STEP * d;
+        let dx = (at(target.com + Vec2::X * h) - at(target.com - Vec2::X * h)) / (2.0 * h);
+        let dy = (at(target.com + Vec2::Y * h) - at(target.com - Vec2::Y * h)) / (2.0 * h);
+        Expansion {
+            accel: at(target.com),
+            gradient: Mat2::from_cols(dx, dy),
+        }
+    }
+
+    // Every body of the target leaf pulled by every body of the source leaf
+    fn direct(&mut self, target: usize, source: usize) {
+        for i in self.cells[target].bodies.clone() {
+            let (_entity, position, _mass) = self.bodies[i];
+            let mut accel = Vec2::ZERO;
+            for j in self.cells[source].bodies.clone() {
+                if i != j {
+                    accel += self.accel(self.bodies[j].2, position, self.bodies[j].1);
+                }
+            }
+            self.accels[i] += accel;
+        }
+    }
+
+    // The pull of source on target. Nodes far enough apart for their sizes interact as a whole,
+    // otherwise the bigger one is opened.
+    fn interact(&mut self, target: usize, source: usize) {
+        if target == source {
+            let children = self.cells[target].children.clone();
+            if children.is_empty() {
+                self.direct(target, source);
+            }
+            for a in children.clone() {
+                for b in children.clone() {
+                    self.interact(a, b);
+                }
+            }
+            return;
+        }
+
+        let (t, s) = (&self.cells[target], &self.cells[source]);
+        let d = t.com.distance(s.com);
+        if d > 0.0 && t.size + s.size < self.theta * d {
+            let local = self.far_field(t, s, d);
+            self.locals[target] = self.locals[target] + local;
+        } else if t.children.is_empty() && s.children.is_empty() {
+            self.direct(target, source);
+        } else if !t.children.is_empty() && (s.children.is_empty() || t.size >= s.size) {
+            for a in t.children.clone() {
+                self.interact(a, source);
+            }
+        } else {
+            for b in s.children.clone() {
+                self.interact(target, b);
+            }
+        }
+    }
+
+    // Hands every node's expansion down to its children, moved to their center, and on to the bodies
+    fn push_down(&mut self, cell: usize, inherited: Expansion, center: Vec2) {
+        let com = self.cells[cell].com;
+        let here = Expansion {
+            accel: inherited.accel + inherited.gradient * (com - center),
+            gradient: inherited.gradient,
+        } + self.locals[cell];
+        if self.cells[cell].children.is_empty() {
+            for i in self.cells[cell].bodies.clone() {
+                self.accels[i] += here.accel + here.gradient * (self.bodies[i].1 - com);
+            }
+        } else {
+            for child in self.cells[cell].children.clone() {
+                self.push_down(child, here, com);
+            }
+        }
+    }
+}
+
+// Velocity change over dt of every body in the tree, from a walk over pairs of nodes instead of one
+// walk per body. Two nodes far enough apart that (size a + size b) / distance < theta interact once,
+// as the pull of one's center of mass expanded to first order over the other, so a whole group of
+// bodies is done with a single interaction. Works in the plane, without the z the bodies are drawn at.
+pub fn dual_tree_accels(tree: &Quadtree, force: ForceParams, dt: f32, theta: f32) -> Vec<(Entity, Vec3)> {
+    let (cells, bodies) = tree.flatten();
+    if cells.is_empty() {
+        return Vec::new();
+    }
+    let mut walk = DualWalk {
+        locals: vec![Expansion::default(); cells.len()],
+        accels: vec![Vec2::ZERO; bodies.len()],
+        cells,
+        bodies,
+        force,
+        theta,
+    };
+    walk.interact(0, 0);
+    let root_com = walk.cells[0].com;
+    walk.push_down(0, Expansion::default(), root_com);
+    walk.bodies
+        .iter()
+        .zip(&walk.accels)
+        .map(|((entity, _position, _mass), accel)| (*entity, force.mond_kick(accel.extend(0.0) * dt, dt)))
+        .collect()
+}
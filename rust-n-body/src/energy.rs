@@ -0,0 +1,96 @@
+use bevy::prelude::*;
+
+// Total energy of the system, only computed while something needs it since it costs an extra tree walk.
+// The relative error against the energy at the first measured step is the usual yardstick
+// for how much the integrator is drifting.
+#[derive(Resource)]
+pub struct Energy {
+    pub enabled: bool,
+    // the virial monitor needs the same sums, so they are also computed while it is on
+    pub virial: bool,
+    pub kinetic: f64,
+    // kinetic energy in the center of mass frame
+    pub internal_kinetic: f64,
+    pub potential: f64,
+    pub initial: Option<f64>,
+}
+
+impl Default for Energy {
+    fn default() -> Self {
+        Energy {
+            enabled: false,
+            virial: true,
+            kinetic: 0.0,
+            internal_kinetic: 0.0,
+            potential: 0.0,
+            initial: None,
+        }
+    }
+}
+
+impl Energy {
+    pub fn needed(&self) -> bool {
+        self.enabled || self.virial
+    }
+
+    // 2T/|U|, which is 1 for a system in equilibrium
+    pub fn virial_ratio(&self) -> Option<f64> {
+        if !self.virial || self.potential == 0.0 {
+            return None;
+        }
+        Some(2.0 * self.internal_kinetic / self.potential.abs())
+    }
+
+    pub fn total(&self) -> f64 {
+        self.kinetic + self.potential
+    }
+
+    pub fn relative_error(&self) -> Option<f64> {
+        let initial = self.initial?;
+        if initial == 0.0 {
+            return None;
+        }
+        Some(((self.total() - initial) / initial).abs())
+    }
+
+    pub fn record(&mut self, kinetic: f64, internal_kinetic: f64, potential: f64) {
+        self.kinetic = kinetic;
+        self.internal_kinetic = internal_kinetic;
+        self.potential = potential;
+        // only the energy tracking has a starting point, the virial ratio is just the current state
+        if self.enabled && self.initial.is_none() {
+            self.initial = Some(self.total());
+        }
+    }
+
+    pub fn reset(&mut self) {
+        self.initial = None;
+    }
+}
+
+// Accumulated difference between a body's change in kinetic energy and the work the forces did on it
+// along the way it actually moved. The kick-drift step never conserves this exactly, and the error piles
+// up in close encounters.
+#[derive(Component, Default)]
+pub struct WorkError {
+    pub error: f32,
+    // acceleration, displacement and kinetic energy change of the last step, which can only be settled
+    // once the force at the end of it is known
+    last: Option<(Vec2, Vec2, f32)>,
+}
+
+impl WorkError {
+    // kick is the velocity change from the forces this step, computed where the body is now, which is
+    // where the last step ended. The last step's work is the force averaged over its two ends times its
+    // displacement, exact for forces that change linearly along the way.
+    pub fn record(&mut self, mass: f32, old_velocity: Vec3, new_velocity: Vec3, kick: Vec3, dt: f32) {
+        let (old_velocity, new_velocity) = (old_velocity.truncate(), new_velocity.truncate());
+        let accel = kick.truncate() / dt;
+        if let Some((last_accel, displacement, kinetic_change)) = self.last {
+            let work = 0.5 * mass * (last_accel + accel).dot(displacement);
+            self.error += kinetic_change - work;
+        }
+        let kinetic_change = 0.5 * mass * (new_velocity.length_squared() - old_velocity.length_squared());
+        self.last = Some((accel, new_velocity * dt, kinetic_change));
+    }
+}
@@ -0,0 +1,162 @@
+use bevy::prelude::*;
+use std::fs::{self, File};
+use std::io::{self, BufWriter, Write};
+use std::path::{Path, PathBuf};
+use crate::clock::SimulationClock;
+use crate::guard::Quarantined;
+use crate::origin::WorldOrigin;
+use crate::tracer::Massless;
+use crate::{Body, SimulationSettings, Velocity};
+
+// Per-step snapshots for training learned simulators. Every step becomes one frame_NNNNNN.npy holding a
+// float32 array of shape (export_bodies, FEATURES), and manifest.json next to them says what the columns
+// are and what they were divided by. The body count is fixed so every frame has the same shape: bodies
+// are sorted by entity so rows stay stable, extra bodies are dropped and missing rows are zero with
+// valid = 0. A frame is written whenever the clock moved on, and steps.csv says which step and time of the
+// run every frame is and the dt of the step that led to it, since a frame can be several steps apart from
+// the last and a faster speed stretches dt. Positions are in the coordinates the run started in, whatever
+// the world origin has moved to since. Bodies the NaN guard froze are left out, their state isn't a real one.
+#[derive(Resource, Default)]
+pub struct Export {
+    dir: Option<PathBuf>,
+    steps: Option<BufWriter<File>>,
+    frame: u64,
+    // the clock's steps at the last frame
+    last_step: Option<u64>,
+    // normalization, fixed when the export starts so it always matches the manifest
+    pos_scale: f32,
+    vel_scale: f32,
+    mass_scale: f32,
+}
+
+const FEATURES: [&str; 6] = ["x", "y", "vx", "vy", "mass", "valid"];
+
+pub fn export_step(
+    mut export: ResMut<Export>,
+    mut settings: ResMut<SimulationSettings>,
+    clock: Res<SimulationClock>,
+    bodies: Query<(Entity, &Transform, &Velocity, &Body), (Without<Massless>, Without<Quarantined>)>,
+    origin: Res<WorldOrigin>,
+) {
+    if !settings.export_enabled {
+        export.dir = None;
+        if let Some(mut steps) = export.steps.take() {
+            let _ = steps.flush();
+        }
+        return;
+    }
+
+    if export.dir.is_none() {
+        let extent = settings.spawn_area.start().abs().max(settings.spawn_area.end().abs());
+        export.pos_scale = extent.max(1.0);
+        export.vel_scale = settings.init_vel.max(1.0);
+        export.mass_scale = settings.max_body_mass.max(1.0);
+        export.frame = 0;
+        export.last_step = None;
+
+        let dir = PathBuf::from(&settings.export_dir);
+        let started = fs::create_dir_all(&dir)
+            .and_then(|_| write_manifest(&dir, &export, &settings))
+            .and_then(|_| File::create(dir.join("steps.csv")))
+            .and_then(|file| {
+                let mut steps = BufWriter::new(file);
+                writeln!(steps, "frame,step,time,dt")?;
+                Ok(steps)
+            });
+        match started {
+            Ok(steps) => export.steps = Some(steps),
+            Err(err) => {
+                error!("Could not start export to {}: {}", settings.export_dir, err);
+                settings.export_enabled = false;
+                return;
+            }
+        }
+        export.dir = Some(dir);
+    }
+
+    // Nothing moved, so there is no new frame
+    if export.last_step == Some(clock.steps) {
+        return;
+    }
+
+    let n = settings.export_bodies as usize;
+    let mut rows: Vec<_> = bodies.iter().collect();
+    rows.sort_by_key(|(entity, _t, _v, _b)| *entity);
+
+    let mut data = vec![0.0f32; n * FEATURES.len()];
+    for (row, (_entity, transform, velocity, body)) in data.chunks_exact_mut(FEATURES.len()).zip(rows) {
+        let position = (origin.original(transform.translation.truncate()) / export.pos_scale as f64).as_vec2();
+        row.copy_from_slice(&[
+            position.x,
+            position.y,
+            velocity.0.x / export.vel_scale,
+            velocity.0.y / export.vel_scale,
+            body.mass / export.mass_scale,
+            1.0,
+        ]);
+    }
+
+    let Some(dir) = &export.dir else {
+        return;
+    };
+    let path = dir.join(format!("frame_{:06}.npy", export.frame));
+    let result = File::create(&path)
+        .and_then(|file| write_npy(&mut BufWriter::new(file), &[n, FEATURES.len()], &data));
+    if let Err(err) = result {
+        error!("Could not write {}: {}", path.display(), err);
+        settings.export_enabled = false;
+        return;
+    }
+    let frame = export.frame;
+    if let Some(steps) = export.steps.as_mut() {
+        let _ = writeln!(steps, "{},{},{:?},{:?}", frame, clock.steps, clock.time, clock.dt);
+    }
+    export.frame += 1;
+    export.last_step = Some(clock.steps);
+}
+
+fn write_manifest(dir: &Path, export: &Export, settings: &SimulationSettings) -> io::Result<()> {
+    let features: Vec<String> = FEATURES.iter().map(|f| format!("\"{}\"", f)).collect();
+    let manifest = format!(
+        "{{\n  \"format\": \"npy\",\n  \"dtype\": \"float32\",\n  \"files\": \"frame_NNNNNN.npy\",\n  \
+         \"steps\": \"steps.csv\",\n  \
+         \"shape\": [{}, {}],\n  \"features\": [{}],\n  \"delta_t\": {:?},\n  \"g\": {:?},\n  \
+         \"softening\": {:?},\n  \
+         \"normalization\": {{\"position\": {:?}, \"velocity\": {:?}, \"mass\": {:?}}}\n}}\n",
+        settings.export_bodies,
+        FEATURES.len(),
+        features.join(", "),
+        settings.delta_t,
+        settings.g,
+        settings.softening,
+        export.pos_scale,
+        export.vel_scale,
+        export.mass_scale,
+    );
+    fs::write(dir.join("manifest.json"), manifest)
+}
+
+// Writes a little-endian float32 array in the .npy v1.0 format numpy.load understands.
+// The header is padded so the data starts at a multiple of 64 bytes, like numpy does itself.
+pub fn write_npy(writer: &mut impl Write, shape: &[usize], data: &[f32]) -> io::Result<()> {
+    let dims: Vec<String> = shape.iter().map(|d| d.to_string()).collect();
+    // a one element tuple needs its trailing comma
+    let shape = if dims.len() == 1 {
+        format!("({},)", dims[0])
+    } else {
+        format!("({})", dims.join(", "))
+    };
+    let mut header = format!("{{'descr': '<f4', 'fortran_order': False, 'shape': {}, }}", shape);
+    // magic (6) + version (2) + header length (2) + header + newline
+    let unpadded = 10 + header.len() + 1;
+    header.push_str(&" ".repeat((64 - unpadded % 64) % 64));
+    header.push('\n');
+
+    writer.write_all(b"\x93NUMPY\x01\x00")?;
+    writer.write_all(&(header.len() as u16).to_le_bytes())?;
+    writer.write_all(header.as_bytes())?;
+    for value in data {
+        writer.write_all(&value.to_le_bytes())?;
+    }
+    writer.flush()
+}
@@ -0,0 +1,315 @@
+use bevy::prelude::*;
+use bevy_egui::{EguiContexts, egui};
+use crate::fade::despawn_with_fade;
+use crate::lifetime::{Lifetimes, SpawnSource};
+use crate::roche::RocheOverflow;
+use crate::tracer::{tracer_bundle, tracer_mesh};
+use crate::ui_config::UiConfig;
+use crate::{Body, SimulationSettings, Velocity, mass_to_hue, spawn_body};
+
+// Hierarchical systems, all on circular orbits so they start out as stable as they can be
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum SystemKind {
+    Binary,
+    // planets circling the whole binary, starting well outside it
+    BinaryWithPlanets,
+    // a binary with a third star circling the pair
+    Triple,
+    // a planet with a ring of small bodies, a moon shepherding its outer edge and one clearing a gap
+    Ring,
+    // a star and a Jupiter with a belt of massless tracers inside its orbit, for the Kirkwood gaps
+    AsteroidBelt,
+}
+
+impl SystemKind {
+    pub const ALL: [SystemKind; 5] = [
+        SystemKind::Binary,
+        SystemKind::BinaryWithPlanets,
+        SystemKind::Triple,
+        SystemKind::Ring,
+        SystemKind::AsteroidBelt,
+    ];
+}
+
+#[derive(Resource)]
+pub struct SystemFactory {
+    pub kind: SystemKind,
+    pub primary_mass: f32,
+    // secondary mass / primary mass
+    pub mass_ratio: f32,
+    pub separation: f32,
+    pub n_planets: u32,
+    pub planet_mass: f32,
+    // third star mass / inner binary mass
+    pub outer_mass_ratio: f32,
+    // outer orbit radius / inner separation
+    pub outer_separation_ratio: f32,
+    // the ring starts at the separation and ends at ring_width times it
+    pub ring_particles: u32,
+    pub ring_particle_mass: f32,
+    pub ring_width: f32,
+    pub n_moons: u32,
+    pub moon_mass: f32,
+    // Jupiter mass / star mass, Jupiter orbits at the separation
+    pub jupiter_mass_ratio: f32,
+    pub belt_particles: u32,
+    // remove everything else first
+    pub replace: bool,
+}
+
+impl Default for SystemFactory {
+    fn default() -> Self {
+        SystemFactory {
+            kind: SystemKind::Binary,
+            primary_mass: 5000.0,
+            mass_ratio: 0.5,
+            separation: 60.0,
+            n_planets: 3,
+            planet_mass: 1.0,
+            outer_mass_ratio: 0.3,
+            // hierarchical triples need roughly 3 or more to survive for long
+            outer_separation_ratio: 6.0,
+            ring_particles: 1500,
+            ring_particle_mass: 0.05,
+            ring_width: 1.6,
+            n_moons: 2,
+            moon_mass: 25.0,
+            // ten times the real one, so the gaps open in fewer orbits
+            jupiter_mass_ratio: 0.01,
+            belt_particles: 3000,
+            replace: true,
+        }
+    }
+}
+
+// Circumbinary orbits closer than about this many binary separations are unstable
+const PLANET_START: f32 = 3.0;
+const PLANET_SPACING: f32 = 1.5;
+// The outer shepherd sits this far out from the ring's edge, in ring radii
+const SHEPHERD_GAP: f32 = 1.1;
+// Ring particles are laid out on a sunflower spiral, evenly spread without needing an rng
+const GOLDEN_ANGLE: f32 = 2.399_963;
+// The belt spans these semi-major axes in units of Jupiter's, which covers the 3:1 to 2:1 resonances
+pub const BELT_INNER: f32 = 0.4;
+pub const BELT_OUTER: f32 = 0.8;
+
+// Two masses on a circular orbit around their common center of mass: (mass, position, velocity) each
+fn circular_pair(m1: f32, m2: f32, separation: f32, g: f32) -> [(f32, Vec2, Vec2); 2] {
+    let total = m1 + m2;
+    let speed = (g * total / separation).sqrt();
+    let (dir, along) = (Vec2::X, Vec2::Y);
+    [
+        (m1, -dir * separation * m2 / total, -along * speed * m2 / total),
+        (m2, dir * separation * m1 / total, along * speed * m1 / total),
+    ]
+}
+
+// Masses, positions and velocities of the system, centered on its center of mass and with no net momentum
+pub fn build_system(factory: &SystemFactory, g: f32) -> Vec<(f32, Vec2, Vec2)> {
+    let m1 = factory.primary_mass;
+    let m2 = m1 * factory.mass_ratio;
+    let inner = circular_pair(m1, m2, factory.separation, g);
+
+    let mut bodies = match factory.kind {
+        SystemKind::Binary => inner.to_vec(),
+        SystemKind::BinaryWithPlanets => {
+            let mut bodies = inner.to_vec();
+            let mut radius = factory.separation * PLANET_START;
+            for i in 0..factory.n_planets {
+                // spread them around so they don't all start lined up
+                let angle = i as f32 * 2.4;
+                let dir = Vec2::from_angle(angle);
+                let speed = (g * (m1 + m2) / radius).sqrt();
+                bodies.push((factory.planet_mass, dir * radius, dir.perp() * speed));
+                radius *= PLANET_SPACING;
+            }
+            bodies
+        }
+        SystemKind::Triple => {
+            let m3 = (m1 + m2) * factory.outer_mass_ratio;
+            let [(_m, inner_pos, inner_vel), third] = circular_pair(
+                m1 + m2,
+                m3,
+                factory.separation * factory.outer_separation_ratio,
+                g,
+            );
+            let mut bodies: Vec<_> = inner
+                .iter()
+                .map(|(m, pos, vel)| (*m, inner_pos + *pos, inner_vel + *vel))
+                .collect();
+            bodies.push(third);
+            bodies
+        }
+        SystemKind::Ring => {
+            let mut bodies = vec![(m1, Vec2::ZERO, Vec2::ZERO)];
+            let ring_inner = factory.separation;
+            let ring_outer = factory.separation * factory.ring_width;
+            let circular = |radius: f32, angle: f32| {
+                let dir = Vec2::from_angle(angle);
+                (dir * radius, dir.perp() * (g * m1 / radius).sqrt())
+            };
+            let n = factory.ring_particles;
+            for i in 0..n {
+                // even in area, so the ring starts out with a flat surface density
+                let fraction = (i as f32 + 0.5) / n as f32;
+                let radius = ring_inner.powi(2) + (ring_outer.powi(2) - ring_inner.powi(2)) * fraction;
+                let radius = radius.sqrt();
+                let (pos, vel) = circular(radius, i as f32 * GOLDEN_ANGLE);
+                bodies.push((factory.ring_particle_mass, pos, vel));
+            }
+            let moon_orbits = [ring_outer * SHEPHERD_GAP, 0.5 * (ring_inner + ring_outer)];
+            for (i, radius) in moon_orbits.iter().take(factory.n_moons as usize).enumerate() {
+                let (pos, vel) = circular(*radius, i as f32 * std::f32::consts::PI);
+                bodies.push((factory.moon_mass, pos, vel));
+            }
+            bodies
+        }
+        SystemKind::AsteroidBelt => {
+            let jupiter = m1 * factory.jupiter_mass_ratio;
+            let mut bodies = circular_pair(m1, jupiter, factory.separation, g).to_vec();
+            let (star_pos, star_vel) = (bodies[0].1, bodies[0].2);
+            let n = factory.belt_particles;
+            for i in 0..n {
+                // even in semi-major axis, so the histogram starts out flat
+                let fraction = (i as f32 + 0.5) / n as f32;
+                let radius = factory.separation * (BELT_INNER + (BELT_OUTER - BELT_INNER) * fraction);
+                let dir = Vec2::from_angle(i as f32 * GOLDEN_ANGLE);
+                let speed = (g * m1 / radius).sqrt();
+                // massless, these become tracers
+                bodies.push((0.0, star_pos + dir * radius, star_vel + dir.perp() * speed));
+            }
+            bodies
+        }
+    };
+
+    // the planets carry a little momentum of their own, take it out so the system stays put.
+    // Massless tracers don't count, they just move along.
+    let total: f32 = bodies.iter().map(|(m, _p, _v)| m).sum();
+    let com: Vec2 = bodies.iter().map(|(m, p, _v)| *m * *p).sum::<Vec2>() / total;
+    let drift: Vec2 = bodies.iter().map(|(m, _p, v)| *m * *v).sum::<Vec2>() / total;
+    for (_m, pos, vel) in &mut bodies {
+        *pos -= com;
+        *vel -= drift;
+    }
+    bodies
+}
+
+#[derive(Event)]
+pub struct SpawnSystemEvent;
+
+pub fn factory_window(
+    mut contexts: EguiContexts,
+    mut factory: ResMut<SystemFactory>,
+    mut spawn_writer: EventWriter<SpawnSystemEvent>,
+    mut overflow: ResMut<RocheOverflow>,
+    mut lifetimes: ResMut<Lifetimes>,
+    ui_config: Res<UiConfig>,
+) {
+    ui_config
+        .place(egui::Window::new("System Factory"), "System Factory")
+        .default_open(false)
+        .show(contexts.ctx_mut(), |ui| {
+            egui::ComboBox::from_label("System")
+                .selected_text(format!("{:?}", factory.kind))
+                .show_ui(ui, |ui| {
+                    for kind in SystemKind::ALL {
+                        ui.selectable_value(&mut factory.kind, kind, format!("{:?}", kind));
+                    }
+                });
+            ui.add(egui::Slider::new(&mut factory.primary_mass, 10.0..=100000.0).logarithmic(true).text("Primary Mass"));
+            if factory.kind == SystemKind::AsteroidBelt {
+                ui.add(egui::Slider::new(&mut factory.separation, 5.0..=500.0).text("Jupiter Orbit Radius"));
+                ui.add(
+                    egui::Slider::new(&mut factory.jupiter_mass_ratio, 0.0001..=0.1)
+                        .logarithmic(true)
+                        .text("Jupiter Mass Ratio"),
+                );
+                ui.add(egui::Slider::new(&mut factory.belt_particles, 10..=20000).logarithmic(true).text("Belt Tracers"));
+            } else if factory.kind == SystemKind::Ring {
+                ui.add(egui::Slider::new(&mut factory.separation, 5.0..=500.0).text("Ring Inner Radius"));
+                ui.add(egui::Slider::new(&mut factory.ring_width, 1.05..=4.0).text("Outer / Inner Radius"));
+                ui.add(egui::Slider::new(&mut factory.ring_particles, 10..=20000).logarithmic(true).text("Ring Particles"));
+                ui.add(
+                    egui::Slider::new(&mut factory.ring_particle_mass, 0.001..=10.0)
+                        .logarithmic(true)
+                        .text("Particle Mass"),
+                );
+                ui.add(egui::Slider::new(&mut factory.n_moons, 0..=2).text("Moons"));
+                ui.add(egui::Slider::new(&mut factory.moon_mass, 0.1..=1000.0).logarithmic(true).text("Moon Mass"));
+            } else {
+                ui.add(egui::Slider::new(&mut factory.mass_ratio, 0.01..=1.0).text("Mass Ratio"));
+                ui.add(egui::Slider::new(&mut factory.separation, 5.0..=500.0).text("Separation"));
+            }
+            if factory.kind == SystemKind::BinaryWithPlanets {
+                ui.add(egui::Slider::new(&mut factory.n_planets, 1..=10).text("Planets"));
+                ui.add(egui::Slider::new(&mut factory.planet_mass, 0.1..=100.0).logarithmic(true).text("Planet Mass"));
+            }
+            if factory.kind == SystemKind::Triple {
+                ui.add(egui::Slider::new(&mut factory.outer_mass_ratio, 0.01..=2.0).text("Third Star Mass Ratio"));
+                ui.add(egui::Slider::new(&mut factory.outer_separation_ratio, 1.5..=20.0).text("Outer Separation Ratio"));
+            }
+            ui.add(egui::Checkbox::new(&mut factory.replace, "Replace Existing Bodies"));
+            if ui.button("Spawn System").clicked() {
+                spawn_writer.write(SpawnSystemEvent);
+            }
+
+            ui.separator();
+            ui.add(egui::Checkbox::new(&mut overflow.enabled, "Roche Lobe Overflow"));
+            ui.add(egui::Slider::new(&mut overflow.rate, 1.0..=500.0).logarithmic(true).text("Tracers Per Second"));
+            ui.add(egui::Slider::new(&mut overflow.kick, 0.0..=0.5).text("Push Towards Accretor"));
+            if overflow.enabled && overflow.l1.is_none() {
+                ui.label("The two heaviest bodies aren't a bound pair");
+            }
+
+            ui.separator();
+            egui::CollapsingHeader::new("Body Lifetimes").show(ui, |ui| {
+                ui.label("Bodies from these go after their lifetime in simulated time");
+                lifetimes.ui(ui);
+            });
+        });
+}
+
+pub fn system_handler(
+    mut spawn_event: EventReader<SpawnSystemEvent>,
+    factory: Res<SystemFactory>,
+    bodies: Query<Entity, With<Body>>,
+    mut commands: Commands,
+    mut materials: ResMut<Assets<ColorMaterial>>,
+    mut meshes: ResMut<Assets<Mesh>>,
+    settings: Res<SimulationSettings>,
+    lifetimes: Res<Lifetimes>,
+) {
+    if spawn_event.is_empty() {
+        return;
+    }
+    spawn_event.clear();
+
+    if factory.replace {
+        for entity in &bodies {
+            despawn_with_fade(&mut commands, entity);
+        }
+    }
+    let tracer_handles = (tracer_mesh(&mut meshes), materials.add(ColorMaterial::from_color(Color::WHITE)));
+    for (mass, pos, vel) in build_system(&factory, settings.g) {
+        if mass == 0.0 {
+            let entity = commands.spawn(tracer_bundle(&tracer_handles.0, &tracer_handles.1, pos, vel, settings.z)).id();
+            lifetimes.apply(SpawnSource::Factory, &mut commands, entity);
+            continue;
+        }
+        let body = Body {
+            mass,
+            radius: settings.collision_radius(mass),
+            hue: mass_to_hue(mass, settings.min_body_mass, settings.max_body_mass).clamp(0.0, 1.0),
+        };
+        let entity = spawn_body(
+            body,
+            Transform::from_xyz(pos.x, pos.y, settings.z),
+            Velocity(vel.extend(0.0)),
+            &settings,
+            &mut commands,
+            &mut materials,
+            &mut meshes,
+        );
+        lifetimes.apply(SpawnSource::Factory, &mut commands, entity);
+    }
+}
@@ -0,0 +1,82 @@
+use bevy::prelude::*;
+use bevy::sprite::AlphaMode2d;
+use crate::selection::Selected;
+use crate::tracer::Massless;
+use crate::{Body, Velocity};
+
+const FADE_SECS: f32 = 0.4;
+
+// Short scale/alpha animation when a body appears or disappears, so bodies don't just pop
+#[derive(Component)]
+pub struct Fade {
+    elapsed: f32,
+    out: bool,
+    // Tracers share one material, so a fading tracer gets a copy of its own to change the alpha of.
+    // The shared one is given back once it has faded in. Every other body's material is its own and
+    // is changed in place.
+    shared: Option<Handle<ColorMaterial>>,
+}
+
+impl Fade {
+    pub fn fade_in() -> Self {
+        Fade {
+            elapsed: 0.0,
+            out: false,
+            shared: None,
+        }
+    }
+}
+
+// Takes the body out of the simulation right away and despawns it once it has faded out
+pub fn despawn_with_fade(commands: &mut Commands, entity: Entity) {
+    commands
+        .entity(entity)
+        .remove::<(Body, Velocity, Selected)>()
+        .insert(Fade {
+            elapsed: 0.0,
+            out: true,
+            shared: None,
+        });
+}
+
+pub fn fade_system(
+    time: Res<Time>,
+    mut fading: Query<(Entity, &mut Fade, &mut Transform, &mut MeshMaterial2d<ColorMaterial>, Has<Massless>)>,
+    mut materials: ResMut<Assets<ColorMaterial>>,
+    mut commands: Commands,
+) {
+    for (entity, mut fade, mut transform, mut material, tracer) in &mut fading {
+        if tracer
+            && fade.shared.is_none()
+            && let Some(copy) = materials.get(&material.0).cloned()
+        {
+            fade.shared = Some(std::mem::replace(&mut material.0, materials.add(copy)));
+        }
+        fade.elapsed += time.delta_secs();
+        let t = (fade.elapsed / FADE_SECS).min(1.0);
+        // smoothstep, so it eases in and out
+        let eased = t * t * (3.0 - 2.0 * t);
+        let amount = if fade.out { 1.0 - eased } else { eased };
+
+        transform.scale = Vec3::splat(amount.max(0.01));
+        if let Some(material) = materials.get_mut(&material.0) {
+            material.color.set_alpha(amount);
+            material.alpha_mode = if t < 1.0 {
+                AlphaMode2d::Blend
+            } else {
+                AlphaMode2d::Opaque
+            };
+        }
+
+        if t >= 1.0 {
+            if fade.out {
+                commands.entity(entity).despawn();
+            } else {
+                if let Some(shared) = fade.shared.take() {
+                    material.0 = shared;
+                }
+                commands.entity(entity).remove::<Fade>();
+            }
+        }
+    }
+}
@@ -0,0 +1,76 @@
+use crate::solver::NBodySim;
+use bevy::prelude::*;
+
+// The solver in solver.rs behind a C API, declared in include/nbody.h. The handle is an NBodySim
+// the caller only ever sees through a pointer.
+
+/// A new empty simulation with gravitational constant `g`, opening angle `theta` and step length
+/// `dt`. Free it with `nbody_destroy`.
+#[unsafe(no_mangle)]
+pub extern "C" fn nbody_create(g: f32, theta: f32, dt: f32) -> *mut NBodySim {
+    Box::into_raw(Box::new(NBodySim::new(g, theta, dt)))
+}
+
+/// # Safety
+/// `sim` must come from `nbody_create` and not have been destroyed yet, or be null.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn nbody_destroy(sim: *mut NBodySim) {
+    if !sim.is_null() {
+        drop(unsafe { Box::from_raw(sim) });
+    }
+}
+
+/// Adds a body and returns its index, or -1 if `sim` is null.
+///
+/// # Safety
+/// `sim` must come from `nbody_create` and not have been destroyed yet, or be null.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn nbody_add_body(sim: *mut NBodySim, x: f32, y: f32, vx: f32, vy: f32, mass: f32) -> i64 {
+    let Some(sim) = (unsafe { sim.as_mut() }) else {
+        return -1;
+    };
+    sim.add_body(Vec2::new(x, y), Vec2::new(vx, vy), mass) as i64
+}
+
+/// Advances the simulation by `n` steps of `dt`.
+///
+/// # Safety
+/// `sim` must come from `nbody_create` and not have been destroyed yet, or be null.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn nbody_step(sim: *mut NBodySim, n: u32) {
+    if let Some(sim) = unsafe { sim.as_mut() } {
+        for _ in 0..n {
+            sim.step();
+        }
+    }
+}
+
+/// # Safety
+/// `sim` must come from `nbody_create` and not have been destroyed yet, or be null.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn nbody_body_count(sim: *const NBodySim) -> usize {
+    unsafe { sim.as_ref() }.map_or(0, |sim| sim.body_count())
+}
+
+/// Writes the positions as x, y pairs in index order into `out`, as many bodies as fit in its `len`
+/// floats, and returns how many bodies were written.
+///
+/// # Safety
+/// `sim` must come from `nbody_create` and not have been destroyed yet, or be null. `out` must
+/// point to `len` writable floats, or be null.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn nbody_read_positions(sim: *const NBodySim, out: *mut f32, len: usize) -> usize {
+    let Some(sim) = (unsafe { sim.as_ref() }) else {
+        return 0;
+    };
+    if out.is_null() {
+        return 0;
+    }
+    let out = unsafe { std::slice::from_raw_parts_mut(out, len) };
+    let mut written = 0;
+    for (pair, position) in out.chunks_exact_mut(2).zip(sim.positions()) {
+        pair.copy_from_slice(&position.to_array());
+        written += 1;
+    }
+    written
+}
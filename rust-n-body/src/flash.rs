@@ -0,0 +1,98 @@
+use bevy::prelude::*;
+use crate::collision::CollisionEvent;
+
+// More flashes than this at once would just cover the view
+const MAX_FLASHES: usize = 300;
+
+// A ring that grows out of an impact and fades, an entity of its own that goes when its time is up
+#[derive(Component)]
+pub struct Flash {
+    age: f32,
+    duration: f32,
+    radius: f32,
+}
+
+#[derive(Resource)]
+pub struct ImpactFlash {
+    pub enabled: bool,
+    // real seconds, so flashes are readable even when the simulation runs fast
+    pub duration: f32,
+    // ring radius for an impact energy of 1
+    pub scale: f32,
+}
+
+impl Default for ImpactFlash {
+    fn default() -> Self {
+        ImpactFlash {
+            enabled: true,
+            duration: 0.5,
+            scale: 2.0,
+        }
+    }
+}
+
+// Energy lost in the impact frame: the kinetic energy of the reduced mass at the closing speed
+pub fn impact_energy(event: &CollisionEvent) -> f32 {
+    let total = event.mass_a + event.mass_b;
+    if total <= 0.0 {
+        return 0.0;
+    }
+    let reduced = event.mass_a * event.mass_b / total;
+    0.5 * reduced * event.impact_speed * event.impact_speed
+}
+
+// Like a blast wave, the ring reaches out with the cube root of the energy, so big impacts
+// stand out without the largest ones filling the screen
+pub fn flash_radius(energy: f32, scale: f32) -> f32 {
+    scale * energy.max(0.0).cbrt()
+}
+
+pub fn spawn_flashes(
+    mut collision_events: EventReader<CollisionEvent>,
+    settings: Res<ImpactFlash>,
+    flashes: Query<(), With<Flash>>,
+    mut commands: Commands,
+) {
+    if !settings.enabled {
+        collision_events.clear();
+        return;
+    }
+    let room = MAX_FLASHES.saturating_sub(flashes.iter().count());
+    for event in collision_events.read().take(room) {
+        let radius = flash_radius(impact_energy(event), settings.scale);
+        if radius <= 0.0 {
+            continue;
+        }
+        commands.spawn((
+            Transform::from_translation(event.position.extend(0.0)),
+            Flash {
+                age: 0.0,
+                duration: settings.duration,
+                radius,
+            },
+        ));
+    }
+}
+
+pub fn update_flashes(
+    time: Res<Time>,
+    mut flashes: Query<(Entity, &mut Flash, &Transform)>,
+    mut gizmos: Gizmos,
+    mut commands: Commands,
+) {
+    for (entity, mut flash, transform) in &mut flashes {
+        flash.age += time.delta_secs();
+        let t = flash.age / flash.duration.max(1e-3);
+        if t >= 1.0 {
+            commands.entity(entity).despawn();
+            continue;
+        }
+        // grows fast at first and slows down, fading all the while
+        let grown = 1.0 - (1.0 - t) * (1.0 - t);
+        gizmos.circle_2d(
+            Isometry2d::from_translation(transform.translation.truncate()),
+            flash.radius * grown,
+            Color::srgba(1.0, 0.7, 0.3, 1.0 - t),
+        );
+    }
+}
@@ -0,0 +1,161 @@
+use bevy::prelude::*;
+
+// Bodies that push light bodies away with radiation pressure, set from the inspector
+#[derive(Component)]
+pub struct Star;
+
+// How the pull between two bodies falls off with distance
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum ForceLaw {
+    InverseLinear,
+    // Newtonian gravity, the default
+    InverseSquare,
+    InverseCube,
+    // Inverse square with a softening length, so close encounters don't produce huge kicks
+    Plummer,
+}
+
+impl ForceLaw {
+    pub const ALL: [ForceLaw; 4] = [
+        ForceLaw::InverseLinear,
+        ForceLaw::InverseSquare,
+        ForceLaw::InverseCube,
+        ForceLaw::Plummer,
+    ];
+
+    pub fn label(self) -> &'static str {
+        match self {
+            ForceLaw::InverseLinear => "1/r",
+            ForceLaw::InverseSquare => "1/r²",
+            ForceLaw::InverseCube => "1/r³",
+            ForceLaw::Plummer => "Plummer softened",
+        }
+    }
+}
+
+// Everything the tree needs to turn a mass and a distance into a pull
+#[derive(Clone, Copy, Debug)]
+pub struct ForceParams {
+    pub g: f32,
+    pub law: ForceLaw,
+    // only used by the Plummer law
+    pub softening: f32,
+    // MOND-like boost of weak accelerations, see mond_accel
+    pub mond: bool,
+    pub a0: f32,
+    // first order post-Newtonian correction near bodies at least compact_mass heavy
+    pub post_newtonian: bool,
+    pub c: f32,
+    pub compact_mass: f32,
+    // Bit-identical results on every machine. glam's Vec3 is plain scalar math summed in a fixed
+    // order, rustc never fuses a * b + c into an FMA and sqrt is correctly rounded everywhere, so
+    // that part is already portable. What isn't is the platform libm behind ln, so this switches
+    // to the pure Rust one. The gravity walks are spread over the compute threads, but only whole
+    // bodies are: each body's kick is summed on one thread in the order its walk visits the tree,
+    // so how many threads there are never reorders a sum.
+    pub deterministic: bool,
+}
+
+impl Default for ForceParams {
+    fn default() -> Self {
+        ForceParams {
+            g: 1.0,
+            law: ForceLaw::InverseSquare,
+            softening: 1.0,
+            mond: false,
+            a0: 20.0,
+            post_newtonian: false,
+            c: 2000.0,
+            compact_mass: 500.0,
+            deterministic: false,
+        }
+    }
+}
+
+impl ForceParams {
+    // Acceleration of a body at t1 towards a mass m2 at t2
+    pub fn accel(&self, m2: f32, t1: Vec3, t2: Vec3) -> Vec3 {
+        let r = t2 - t1;
+        let mag = r.length();
+        // r / mag^(n + 1) gives a magnitude falling off as 1/mag^n without normalizing r first
+        let scale = match self.law {
+            ForceLaw::InverseLinear => 1.0 / (mag * mag),
+            ForceLaw::InverseSquare => 1.0 / (mag * mag * mag),
+            ForceLaw::InverseCube => 1.0 / (mag * mag * mag * mag),
+            ForceLaw::Plummer => {
+                let soft2 = mag * mag + self.softening * self.softening;
+                1.0 / (soft2 * soft2.sqrt())
+            }
+        };
+        self.g * m2 * scale * r
+    }
+
+    // Potential per unit mass at t1 from a mass m2 at t2, matching accel
+    pub fn potential(&self, m2: f32, t1: Vec3, t2: Vec3) -> f32 {
+        let mag = t1.distance(t2);
+        let per_mass = match self.law {
+            ForceLaw::InverseLinear if self.deterministic => libm::logf(mag),
+            ForceLaw::InverseLinear => mag.ln(),
+            ForceLaw::InverseSquare => -1.0 / mag,
+            ForceLaw::InverseCube => -1.0 / (2.0 * mag * mag),
+            ForceLaw::Plummer => -1.0 / (mag * mag + self.softening * self.softening).sqrt(),
+        };
+        self.g * m2 * per_mass
+    }
+
+    // Turns the summed Newtonian acceleration into the MOND one, using the "simple" interpolation
+    // mu(x) = x / (1 + x). Solving a * mu(a / a0) = a_N for a gives the expression below: strong
+    // fields are left alone and fields well below a0 fall off as 1/r instead, which is what keeps
+    // rotation curves flat. It has to act on the total, not per pair, because it isn't linear.
+    pub fn mond_accel(&self, newtonian: Vec3) -> Vec3 {
+        let a_n = newtonian.length();
+        if !self.mond || a_n == 0.0 || self.a0 <= 0.0 {
+            return newtonian;
+        }
+        let a = 0.5 * a_n + (0.25 * a_n * a_n + a_n * self.a0).sqrt();
+        newtonian * (a / a_n)
+    }
+
+    // Same as mond_accel, for the velocity kicks the tree hands out
+    pub fn mond_kick(&self, kick: Vec3, dt: f32) -> Vec3 {
+        if !self.mond || dt == 0.0 {
+            return kick;
+        }
+        self.mond_accel(kick / dt) * dt
+    }
+
+    // 1PN correction to the pull of a compact mass m2, in the test particle limit.
+    // rel_pos and rel_vel are the body's position and velocity relative to the compact object.
+    // Only the xy part is used, z is just the draw order. This is what makes tight orbits precess,
+    // by 6 pi G M / (c² a (1 - e²)) per orbit.
+    pub fn post_newtonian(&self, m2: f32, rel_pos: Vec3, rel_vel: Vec3) -> Vec3 {
+        let r = rel_pos.truncate();
+        let v = rel_vel.truncate();
+        let mag = r.length();
+        if mag == 0.0 || self.c <= 0.0 {
+            return Vec3::ZERO;
+        }
+        let gm = self.g * m2;
+        let a = gm / (self.c * self.c * mag * mag * mag)
+            * ((4.0 * gm / mag - v.length_squared()) * r + 4.0 * r.dot(v) * v);
+        a.extend(0.0)
+    }
+}
+
+// What a push that goes as 1/mass is divided by. Tracers have no mass and would be thrown off to
+// infinity, so they get the push a body of mass 1 would. Every real body keeps its own mass, however light.
+pub fn push_mass(mass: f32) -> f32 {
+    if mass == 0.0 { 1.0 } else { mass }
+}
+
+// Outward push from a star with the given luminosity on a body of the given mass, rel_pos being
+// the body's position relative to the star. Falls off as 1/r² like gravity, but goes as 1/mass,
+// so dust gets blown away while heavy bodies barely notice.
+pub fn radiation_pressure(luminosity: f32, mass: f32, rel_pos: Vec3) -> Vec3 {
+    let r = rel_pos.truncate();
+    let mag = r.length();
+    if mag == 0.0 {
+        return Vec3::ZERO;
+    }
+    (luminosity / (push_mass(mass) * mag * mag * mag) * r).extend(0.0)
+}
@@ -0,0 +1,68 @@
+use bevy::prelude::*;
+use crate::{MainCamera, SimulationSettings};
+use crate::tools::{ATTRACTOR_MAX_MASS, ATTRACTOR_MIN_MASS, Attractor};
+
+// Screen heights per second at full stick
+const PAN_SPEED: f32 = 1.0;
+// Zoom doubles or halves per second at full stick
+const ZOOM_SPEED: f32 = 1.0;
+const MIN_ZOOM: f32 = 0.01;
+const MAX_ZOOM: f32 = 100.0;
+// Attractor mass changes by this factor per second while a bumper is held
+const MASS_RATE: f32 = 4.0;
+
+// Drives the simulation without keyboard or mouse, for the couch or a museum kiosk:
+// left stick pans, right stick zooms, the d-pad halves or doubles the time scale, Start pauses,
+// South holds an attractor at the center of the screen and West a repulsor, the bumpers set its mass.
+// Runs after the mouse attractor, so a held button wins over the mouse.
+pub fn gamepad_input(
+    time: Res<Time>,
+    gamepads: Query<&Gamepad>,
+    mut cameras: Query<(&mut Transform, &mut Projection), With<MainCamera>>,
+    windows: Query<&Window>,
+    mut settings: ResMut<SimulationSettings>,
+    mut attractor: ResMut<Attractor>,
+) {
+    let dt = time.delta_secs();
+    let Ok((mut camera, mut projection)) = cameras.single_mut() else {
+        return;
+    };
+    let Projection::Orthographic(ortho) = &mut *projection else {
+        return;
+    };
+    let screen_height = windows.single().map(|w| w.height()).unwrap_or(720.0);
+
+    for gamepad in &gamepads {
+        let pan = gamepad.left_stick();
+        camera.translation += (pan * PAN_SPEED * screen_height * ortho.scale * dt).extend(0.0);
+
+        let zoom = gamepad.right_stick().y;
+        // pushing up zooms in
+        ortho.scale = (ortho.scale * 2f32.powf(-zoom * ZOOM_SPEED * dt)).clamp(MIN_ZOOM, MAX_ZOOM);
+
+        if gamepad.just_pressed(GamepadButton::Start) {
+            settings.paused = !settings.paused;
+        }
+        if gamepad.just_pressed(GamepadButton::DPadUp) {
+            settings.time_scale = (settings.time_scale * 2.0).min(100.0);
+        }
+        if gamepad.just_pressed(GamepadButton::DPadDown) {
+            settings.time_scale = (settings.time_scale / 2.0).max(0.1);
+        }
+
+        if gamepad.pressed(GamepadButton::RightTrigger) {
+            attractor.mass *= MASS_RATE.powf(dt);
+        }
+        if gamepad.pressed(GamepadButton::LeftTrigger) {
+            attractor.mass /= MASS_RATE.powf(dt);
+        }
+        attractor.mass = attractor.mass.clamp(ATTRACTOR_MIN_MASS, ATTRACTOR_MAX_MASS);
+
+        let attract = gamepad.pressed(GamepadButton::South);
+        let repel = gamepad.pressed(GamepadButton::West);
+        if attract || repel {
+            attractor.repel = repel && !attract;
+            attractor.pos = Some(camera.translation.truncate());
+        }
+    }
+}
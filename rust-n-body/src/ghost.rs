@@ -0,0 +1,104 @@
+use bevy::prelude::*;
+use crate::bhtree::{Quad, Quadtree, calc_accel};
+use crate::tools::Attractor;
+use crate::tracer::Massless;
+use crate::{Body, SimulationSettings, Velocity};
+
+// A faint copy of a body that is simulated separately with its own theta.
+// The body lives in here instead of in a Body component, so none of the other systems see ghosts.
+#[derive(Component)]
+pub struct Ghost {
+    body: Body,
+}
+
+#[derive(Event)]
+pub enum GhostEvent {
+    // Copy the current bodies into a new ghost set
+    Spawn,
+    Clear,
+}
+
+pub fn ghost_handler(
+    mut ghost_event: EventReader<GhostEvent>,
+    bodies: Query<(&Body, &Transform, &Velocity), Without<Massless>>,
+    ghosts: Query<Entity, With<Ghost>>,
+    mut commands: Commands,
+    mut materials: ResMut<Assets<ColorMaterial>>,
+    mut meshes: ResMut<Assets<Mesh>>,
+    settings: Res<SimulationSettings>,
+) {
+    for event in ghost_event.read() {
+        // Either way the old ghosts go, there is only ever one ghost set
+        for entity in &ghosts {
+            commands.entity(entity).despawn();
+        }
+
+        if let GhostEvent::Spawn = event {
+            let material = materials.add(ColorMaterial::from_color(Color::srgba(0.8, 0.9, 1.0, 0.25)));
+            for (body, transform, velocity) in &bodies {
+                let mut transform = *transform;
+                // drawn just below the real bodies
+                transform.translation.z -= 1.0;
+                transform.scale = Vec3::ONE;
+                commands.spawn((
+                    Mesh2d(meshes.add(Circle::new(settings.render_radius(body.radius)))),
+                    MeshMaterial2d(material.clone()),
+                    Ghost { body: *body },
+                    transform,
+                    Velocity(velocity.0),
+                ));
+            }
+        }
+    }
+}
+
+// Second physics pass, the same as the update system but with the ghost theta
+pub fn ghost_update(
+    mut ghosts: Query<(Entity, &Ghost, &mut Transform, &mut Velocity)>,
+    attractor: Res<Attractor>,
+    settings: Res<SimulationSettings>,
+) {
+    if settings.paused || ghosts.is_empty() {
+        return;
+    }
+
+    let positions: Vec<Vec2> = ghosts
+        .iter()
+        .map(|(_e, _g, t, _v)| t.translation.truncate())
+        .collect();
+    let mut tree = Quadtree::with_leaf_capacity(
+        Quad::new_containing(&positions),
+        settings.leaf_capacity as usize,
+    );
+    for (entity, ghost, transform, _velocity) in &ghosts {
+        tree.insert(entity, *transform, ghost.body);
+    }
+
+    for (entity, ghost, mut transform, mut velocity) in &mut ghosts {
+        let mut accel = tree.get_total_accel(
+            entity,
+            *transform,
+            ghost.body,
+            settings.force_params(),
+            settings.delta_t,
+            settings.ghost_theta,
+        );
+        // the ghosts feel the attractor too, otherwise the comparison would not be fair
+        if let Some(pos) = attractor.pos {
+            let target = pos.extend(transform.translation.z);
+            if target.distance(transform.translation) > 1.0 {
+                accel += calc_accel(
+                    attractor.signed_mass(),
+                    transform.translation,
+                    target,
+                    settings.delta_t,
+                    settings.force_params(),
+                );
+            }
+        }
+
+        velocity.0 += accel;
+        transform.translation.x += velocity.0.x * settings.delta_t;
+        transform.translation.y += velocity.0.y * settings.delta_t;
+    }
+}
@@ -0,0 +1,415 @@
+use bevy::prelude::*;
+use bevy_egui::{EguiContexts, egui};
+use std::collections::HashMap;
+use std::fs;
+use crate::bhtree::{Quad, Quadtree};
+use crate::clock::SimulationClock;
+use crate::origin::WorldOrigin;
+use crate::plot::line_plot;
+use crate::remnant::{RemnantReport, analyze_remnant};
+use crate::tracer::Massless;
+use crate::ui_config::UiConfig;
+use crate::{Body, SimulationSettings, Velocity};
+
+// How many of the heaviest groups the window lists
+const LISTED_GROUPS: usize = 10;
+// Passes of throwing out unbound members, each one can unbind more since the group got lighter
+const UNBIND_PASSES: usize = 10;
+
+// A gravitationally bound group found by the last analysis pass
+#[derive(Clone, Debug)]
+pub struct Group {
+    // indices into whatever the analysis was run on
+    pub members: Vec<usize>,
+    pub mass: f32,
+    pub com: Vec2,
+    pub com_vel: Vec2,
+    // mass weighted rms speed relative to com_vel
+    pub velocity_dispersion: f32,
+}
+
+impl Group {
+    fn new(members: Vec<usize>, masses: &[f32], positions: &[Vec2], velocities: &[Vec2]) -> Self {
+        let mass: f32 = members.iter().map(|i| masses[*i]).sum();
+        let weight = if mass > 0.0 { 1.0 / mass } else { 0.0 };
+        let com = members.iter().map(|i| masses[*i] * positions[*i]).sum::<Vec2>() * weight;
+        let com_vel = members.iter().map(|i| masses[*i] * velocities[*i]).sum::<Vec2>() * weight;
+        let spread: f32 = members
+            .iter()
+            .map(|i| masses[*i] * (velocities[*i] - com_vel).length_squared())
+            .sum();
+        Group {
+            members,
+            mass,
+            com,
+            com_vel,
+            velocity_dispersion: (spread * weight).sqrt(),
+        }
+    }
+}
+
+// Friends of friends: bodies closer than the linking length are in the same group, and so are their
+// friends, and theirs. Union-find over the tree's circle queries, so it stays close to N log N.
+pub fn friends_of_friends(positions: &[Vec2], linking_length: f32) -> Vec<Vec<usize>> {
+    if positions.is_empty() {
+        return Vec::new();
+    }
+    let mut tree = Quadtree::new(Quad::new_containing(positions));
+    for (i, p) in positions.iter().enumerate() {
+        let body = Body {
+            mass: 1.0,
+            radius: 0.0,
+            hue: 0.0,
+        };
+        tree.insert(Entity::from_raw(i as u32), Transform::from_xyz(p.x, p.y, 0.0), body);
+    }
+
+    let mut parent: Vec<usize> = (0..positions.len()).collect();
+    fn root(parent: &mut [usize], mut i: usize) -> usize {
+        while parent[i] != i {
+            parent[i] = parent[parent[i]];
+            i = parent[i];
+        }
+        i
+    }
+
+    for (i, p) in positions.iter().enumerate() {
+        for (entity, _transform, _body) in tree.query_circle(*p, linking_length) {
+            let (a, b) = (root(&mut parent, i), root(&mut parent, entity.index() as usize));
+            if a != b {
+                parent[a.max(b)] = a.min(b);
+            }
+        }
+    }
+
+    let mut groups: HashMap<usize, Vec<usize>> = HashMap::new();
+    for i in 0..positions.len() {
+        let r = root(&mut parent, i);
+        groups.entry(r).or_default().push(i);
+    }
+    let mut groups: Vec<Vec<usize>> = groups.into_values().collect();
+    groups.sort_by_key(|members| members[0]);
+    groups
+}
+
+// Drops the members moving fast enough to leave, judged against the rest of the group as a point
+// mass at its center of mass. Closer than the softening length the pull is taken as constant.
+pub fn unbind(
+    mut members: Vec<usize>,
+    masses: &[f32],
+    positions: &[Vec2],
+    velocities: &[Vec2],
+    g: f32,
+    softening: f32,
+) -> Vec<usize> {
+    for _ in 0..UNBIND_PASSES {
+        let group = Group::new(members.clone(), masses, positions, velocities);
+        let before = members.len();
+        members.retain(|i| {
+            let kinetic = 0.5 * (velocities[*i] - group.com_vel).length_squared();
+            let r = positions[*i].distance(group.com).max(softening);
+            let potential = -g * (group.mass - masses[*i]) / r;
+            kinetic + potential < 0.0
+        });
+        if members.len() == before {
+            break;
+        }
+    }
+    members
+}
+
+// Bound groups with at least min_members members, heaviest first
+pub fn find_groups(
+    masses: &[f32],
+    positions: &[Vec2],
+    velocities: &[Vec2],
+    g: f32,
+    softening: f32,
+    linking_length: f32,
+    min_members: usize,
+) -> Vec<Group> {
+    let mut groups: Vec<Group> = friends_of_friends(positions, linking_length)
+        .into_iter()
+        .filter(|members| members.len() >= min_members)
+        .map(|members| unbind(members, masses, positions, velocities, g, softening))
+        .filter(|members| members.len() >= min_members)
+        .map(|members| Group::new(members, masses, positions, velocities))
+        .collect();
+    groups.sort_by(|a, b| b.mass.total_cmp(&a.mass));
+    groups
+}
+
+// Plain friends-of-friends halos, nothing unbound, with at least min_members members, heaviest first
+pub fn find_halos(
+    masses: &[f32],
+    positions: &[Vec2],
+    velocities: &[Vec2],
+    linking_length: f32,
+    min_members: usize,
+) -> Vec<Group> {
+    let mut halos: Vec<Group> = friends_of_friends(positions, linking_length)
+        .into_iter()
+        .filter(|members| members.len() >= min_members)
+        .map(|members| Group::new(members, masses, positions, velocities))
+        .collect();
+    halos.sort_by(|a, b| b.mass.total_cmp(&a.mass));
+    halos
+}
+
+pub const CATALOG_HEADER: &str = "time,halo,com_x,com_y,mass,members,velocity_dispersion";
+
+// One catalog line per halo, without a line end after the last one. The centers are written in the
+// coordinates the run started in.
+pub fn catalog_lines(time: f64, halos: &[Group], origin: &WorldOrigin) -> Vec<String> {
+    halos
+        .iter()
+        .enumerate()
+        .map(|(i, h)| {
+            let com = origin.original(h.com);
+            format!(
+                "{},{},{},{},{},{},{}",
+                time,
+                i,
+                com.x,
+                com.y,
+                h.mass,
+                h.members.len(),
+                h.velocity_dispersion
+            )
+        })
+        .collect()
+}
+
+// The usual choice, a fraction of the mean distance between bodies over their bounding box
+pub fn default_linking_length(positions: &[Vec2], factor: f32) -> f32 {
+    if positions.len() < 2 {
+        return 0.0;
+    }
+    let (min, max) = positions
+        .iter()
+        .fold((Vec2::MAX, Vec2::MIN), |(min, max), p| (min.min(*p), max.max(*p)));
+    let area = ((max.x - min.x) * (max.y - min.y)).max(f32::EPSILON);
+    factor * (area / positions.len() as f32).sqrt()
+}
+
+pub struct GroupSnapshot {
+    pub time: f64,
+    pub groups: usize,
+    // share of the total mass in any bound group
+    pub bound_fraction: f32,
+    pub largest_mass: f32,
+}
+
+// Bound group finding, run every few seconds while switched on since it walks the tree for every body
+#[derive(Resource)]
+pub struct GroupAnalysis {
+    pub enabled: bool,
+    pub interval_secs: f32,
+    // linking length as a fraction of the mean distance between bodies
+    pub linking_factor: f32,
+    // or a fixed linking length in world units
+    pub fixed_length: bool,
+    pub linking_length: f32,
+    pub min_members: u32,
+    pub groups: Vec<Group>,
+    pub entities: Vec<Entity>,
+    // which group each body is in, for the group color mode
+    pub group_of: HashMap<Entity, usize>,
+    pub history: Vec<GroupSnapshot>,
+    pub catalog_path: String,
+    // set by the button, the halos are written on the next frame
+    pub catalog_requested: bool,
+    pub remnant_path: String,
+    pub remnant_requested: bool,
+    pub remnant: Option<RemnantReport>,
+    elapsed: f32,
+}
+
+impl Default for GroupAnalysis {
+    fn default() -> Self {
+        GroupAnalysis {
+            enabled: false,
+            interval_secs: 2.0,
+            linking_factor: 0.2,
+            fixed_length: false,
+            linking_length: 10.0,
+            min_members: 10,
+            groups: Vec::new(),
+            entities: Vec::new(),
+            group_of: HashMap::new(),
+            history: Vec::new(),
+            catalog_path: "halos.csv".to_string(),
+            catalog_requested: false,
+            remnant_path: "remnant.json".to_string(),
+            remnant_requested: false,
+            remnant: None,
+            elapsed: 0.0,
+        }
+    }
+}
+
+impl GroupAnalysis {
+    pub fn linking_length(&self, positions: &[Vec2]) -> f32 {
+        if self.fixed_length {
+            self.linking_length
+        } else {
+            default_linking_length(positions, self.linking_factor)
+        }
+    }
+}
+
+pub fn analyze_groups(
+    time: Res<Time>,
+    clock: Res<SimulationClock>,
+    settings: Res<SimulationSettings>,
+    bodies: Query<(Entity, &Transform, &Velocity, &Body), Without<Massless>>,
+    origin: Res<WorldOrigin>,
+    mut analysis: ResMut<GroupAnalysis>,
+) {
+    analysis.elapsed += time.delta_secs();
+    let due = analysis.enabled && analysis.elapsed >= analysis.interval_secs;
+    if !due && !analysis.catalog_requested && !analysis.remnant_requested {
+        return;
+    }
+
+    let mut entities = Vec::new();
+    let mut masses = Vec::new();
+    let mut positions = Vec::new();
+    let mut velocities = Vec::new();
+    for (entity, transform, velocity, body) in &bodies {
+        entities.push(entity);
+        masses.push(body.mass);
+        positions.push(transform.translation.truncate());
+        velocities.push(velocity.0.truncate());
+    }
+
+    let linking_length = analysis.linking_length(&positions);
+
+    if analysis.catalog_requested {
+        analysis.catalog_requested = false;
+        let halos = find_halos(&masses, &positions, &velocities, linking_length, analysis.min_members as usize);
+        let mut text = format!("{}\n", CATALOG_HEADER);
+        for line in catalog_lines(clock.time, &halos, &origin) {
+            text.push_str(&line);
+            text.push('\n');
+        }
+        match fs::write(&analysis.catalog_path, text) {
+            Ok(()) => info!("Wrote {} halos to {}", halos.len(), analysis.catalog_path),
+            Err(err) => error!("Could not write halo catalog {}: {}", analysis.catalog_path, err),
+        }
+    }
+    if analysis.remnant_requested {
+        analysis.remnant_requested = false;
+        let mut report = analyze_remnant(clock.time, &masses, &positions, &velocities, settings.g, settings.softening);
+        // found in the local coordinates, written in the ones the run started in
+        report.com += origin.offset;
+        match fs::write(&analysis.remnant_path, report.json()) {
+            Ok(()) => info!("Wrote the remnant report to {}", analysis.remnant_path),
+            Err(err) => error!("Could not write remnant report {}: {}", analysis.remnant_path, err),
+        }
+        analysis.remnant = Some(report);
+    }
+    if !due {
+        return;
+    }
+    analysis.elapsed = 0.0;
+
+    let groups = find_groups(
+        &masses,
+        &positions,
+        &velocities,
+        settings.g,
+        settings.softening,
+        linking_length,
+        analysis.min_members as usize,
+    );
+
+    let total_mass: f32 = masses.iter().sum();
+    let bound_mass: f32 = groups.iter().map(|g| g.mass).sum();
+    let snapshot = GroupSnapshot {
+        time: clock.time,
+        groups: groups.len(),
+        bound_fraction: if total_mass > 0.0 { bound_mass / total_mass } else { 0.0 },
+        largest_mass: groups.first().map_or(0.0, |g| g.mass),
+    };
+    analysis.history.push(snapshot);
+
+    analysis.group_of = groups
+        .iter()
+        .enumerate()
+        .flat_map(|(g, group)| group.members.iter().map(move |i| (*i, g)))
+        .map(|(i, g)| (entities[i], g))
+        .collect();
+    analysis.groups = groups;
+    analysis.entities = entities;
+}
+
+pub fn groups_window(mut contexts: EguiContexts, mut analysis: ResMut<GroupAnalysis>, ui_config: Res<UiConfig>) {
+    ui_config
+        .place(egui::Window::new("Groups"), "Groups")
+        .default_open(false)
+        .show(contexts.ctx_mut(), |ui| {
+            ui.add(egui::Checkbox::new(&mut analysis.enabled, "Find Bound Groups"));
+            ui.add(egui::Slider::new(&mut analysis.interval_secs, 0.1..=30.0).text("Every (s)"));
+            ui.add(egui::Checkbox::new(&mut analysis.fixed_length, "Fixed Linking Length"));
+            if analysis.fixed_length {
+                ui.add(egui::DragValue::new(&mut analysis.linking_length).range(0.1..=1000.0).prefix("Linking Length: "));
+            } else {
+                ui.add(egui::Slider::new(&mut analysis.linking_factor, 0.01..=1.0).text("Linking Length Factor"));
+            }
+            ui.add(egui::Slider::new(&mut analysis.min_members, 2..=1000).logarithmic(true).text("Min Members"));
+            if ui.button("Clear History").clicked() {
+                analysis.history.clear();
+            }
+            ui.add(egui::Label::new("Halo catalog (plain friends-of-friends, nothing unbound):"));
+            ui.add(egui::TextEdit::singleline(&mut analysis.catalog_path));
+            if ui.button("Write Halo Catalog").clicked() {
+                analysis.catalog_requested = true;
+            }
+
+            ui.separator();
+            ui.add(egui::Label::new("Merger remnant report (JSON):"));
+            ui.add(egui::TextEdit::singleline(&mut analysis.remnant_path));
+            if ui.button("Analyze Remnant").clicked() {
+                analysis.remnant_requested = true;
+            }
+            if let Some(report) = &analysis.remnant {
+                ui.label(format!(
+                    "t = {:.3}: {:.1}% of the mass bound, {:.1}% in tails",
+                    report.time,
+                    report.bound_fraction() * 100.0,
+                    report.tail_fraction() * 100.0
+                ));
+                ui.label(format!(
+                    "Half-mass radius {:.2}, rotation {:.3}",
+                    report.half_mass_radius, report.rotation
+                ));
+            }
+
+            ui.separator();
+            ui.label(format!("{} bound groups", analysis.groups.len()));
+            for (i, group) in analysis.groups.iter().take(LISTED_GROUPS).enumerate() {
+                ui.label(format!(
+                    "#{}: mass {:.0}, {} bodies, at ({:.0}, {:.0})",
+                    i,
+                    group.mass,
+                    group.members.len(),
+                    group.com.x,
+                    group.com.y
+                ));
+            }
+
+            if let Some(last) = analysis.history.last() {
+                ui.separator();
+                ui.label(format!("Bound mass fraction: {:.1}% (t = {:.3})", last.bound_fraction * 100.0, last.time));
+                let fractions: Vec<f32> = analysis.history.iter().map(|s| s.bound_fraction).collect();
+                line_plot(ui, &[(&fractions, egui::Color32::LIGHT_GREEN)]);
+                ui.label(format!("Groups: {}", last.groups));
+                let counts: Vec<f32> = analysis.history.iter().map(|s| s.groups as f32).collect();
+                line_plot(ui, &[(&counts, egui::Color32::LIGHT_BLUE)]);
+                ui.label(format!("Largest group mass: {:.0}", last.largest_mass));
+                let largest: Vec<f32> = analysis.history.iter().map(|s| s.largest_mass).collect();
+                line_plot(ui, &[(&largest, egui::Color32::GOLD)]);
+            }
+        });
+}
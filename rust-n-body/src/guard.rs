@@ -0,0 +1,55 @@
+use bevy::prelude::*;
+use crate::{Body, SimulationSettings, Velocity};
+
+// What happens to a body once its position or velocity is no longer a finite number
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum NanAction {
+    Despawn,
+    // Keeps the entity around (hidden) for inspection, but takes it out of the simulation
+    Freeze,
+}
+
+// Marker for frozen bodies, the physics systems skip these
+#[derive(Component)]
+pub struct Quarantined;
+
+// Number of bodies caught since the last reset, shown in the settings window
+#[derive(Resource, Default)]
+pub struct NanGuard {
+    pub caught: u32,
+}
+
+// Runs before the tree is built, one NaN position would otherwise blow up the bounding box for everyone
+pub fn nan_guard(
+    mut bodies: Query<(Entity, &Transform, &mut Velocity), (With<Body>, Without<Quarantined>)>,
+    mut guard: ResMut<NanGuard>,
+    mut settings: ResMut<SimulationSettings>,
+    mut commands: Commands,
+) {
+    let mut caught = 0;
+    for (entity, transform, mut velocity) in &mut bodies {
+        if transform.translation.is_finite() && velocity.0.is_finite() {
+            continue;
+        }
+
+        warn!(
+            ?entity,
+            position = ?transform.translation,
+            velocity = ?velocity.0,
+            "Quarantining body with non-finite state"
+        );
+        match settings.nan_action {
+            NanAction::Despawn => commands.entity(entity).despawn(),
+            NanAction::Freeze => {
+                velocity.0 = Vec3::ZERO;
+                commands.entity(entity).insert((Quarantined, Visibility::Hidden));
+            }
+        }
+        caught += 1;
+    }
+
+    guard.caught += caught;
+    if caught > 0 && settings.nan_auto_pause {
+        settings.paused = true;
+    }
+}
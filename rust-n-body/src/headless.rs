@@ -0,0 +1,202 @@
+use bevy::platform::time::Instant;
+use bevy::prelude::*;
+use rand::SeedableRng;
+use rand::rngs::StdRng;
+use crate::bhtree::{Quad, Quadtree};
+use crate::groups::{Group, default_linking_length, find_halos};
+use crate::{Body, SimulationSettings, generate_bodies};
+
+// Milliseconds spent in each part of a step
+#[derive(Clone, Copy, Default, Debug)]
+pub struct StepTimes {
+    pub tree: f64,
+    pub force: f64,
+    pub integrate: f64,
+}
+
+impl StepTimes {
+    pub fn total(&self) -> f64 {
+        self.tree + self.force + self.integrate
+    }
+
+    pub fn add(&mut self, other: StepTimes) {
+        self.tree += other.tree;
+        self.force += other.force;
+        self.integrate += other.integrate;
+    }
+
+    pub fn scaled(self, factor: f64) -> StepTimes {
+        StepTimes {
+            tree: self.tree * factor,
+            force: self.force * factor,
+            integrate: self.integrate * factor,
+        }
+    }
+}
+
+// Gravity on plain vectors instead of entities, so runs can be done without opening a window. It uses
+// the settings' force law (MOND included), the same per-body tree walk and the same kick-drift step as
+// the update system, but none of what update adds on top: collisions, post-Newtonian corrections,
+// radiation pressure, custom force providers, controllers, pinned bodies, body groups, substeps and
+// the dual tree, bucket and warm-started walks. A run with any of those on differs from the app's.
+// Tracers feel the same tree the bodies do but stay out of it, like in update.
+pub struct HeadlessSim {
+    pub bodies: Vec<Body>,
+    pub transforms: Vec<Transform>,
+    pub velocities: Vec<Vec3>,
+    pub tracers: Vec<(Body, Transform, Vec3)>,
+    pub time: f64,
+    pub steps: u64,
+}
+
+impl HeadlessSim {
+    pub fn new(settings: &SimulationSettings, seed: u64) -> Self {
+        let mut rng = StdRng::seed_from_u64(seed);
+        Self::from_state(
+            generate_bodies(settings, &mut rng)
+                .into_iter()
+                .map(|(body, transform, velocity)| (body, transform, velocity.0)),
+        )
+    }
+
+    // A copy of bodies that already exist, starting at time 0
+    pub fn from_state(state: impl IntoIterator<Item = (Body, Transform, Vec3)>) -> Self {
+        let mut sim = HeadlessSim {
+            bodies: Vec::new(),
+            transforms: Vec::new(),
+            velocities: Vec::new(),
+            tracers: Vec::new(),
+            time: 0.0,
+            steps: 0,
+        };
+        for (body, transform, velocity) in state {
+            sim.bodies.push(body);
+            sim.transforms.push(transform);
+            sim.velocities.push(velocity);
+        }
+        sim
+    }
+
+    // Body i is stored in the tree as the entity with index i
+    fn entity(i: usize) -> Entity {
+        Entity::from_raw(i as u32)
+    }
+
+    pub fn build_tree(&self, settings: &SimulationSettings) -> Quadtree {
+        let positions: Vec<Vec2> = self.transforms.iter().map(|t| t.translation.truncate()).collect();
+        let mut tree =
+            Quadtree::with_leaf_capacity(Quad::new_containing(&positions), settings.leaf_capacity as usize);
+        for i in 0..self.bodies.len() {
+            tree.insert(Self::entity(i), self.transforms[i], self.bodies[i]);
+        }
+        tree
+    }
+
+    pub fn step(&mut self, settings: &SimulationSettings) {
+        self.step_dt(settings, settings.delta_t);
+    }
+
+    // One step of the given length, for keeping pace with a run whose dt is stretched
+    pub fn step_dt(&mut self, settings: &SimulationSettings, dt: f32) {
+        self.step_timed(settings, dt);
+    }
+
+    // One step, timing the tree build, the forces and the integration on the way
+    pub fn step_timed(&mut self, settings: &SimulationSettings, dt: f32) -> StepTimes {
+        let start = Instant::now();
+        let tree = self.build_tree(settings);
+        let built = Instant::now();
+
+        let accels: Vec<Vec3> = (0..self.bodies.len())
+            .map(|i| {
+                tree.get_total_accel(
+                    Self::entity(i),
+                    self.transforms[i],
+                    self.bodies[i],
+                    settings.force_params(),
+                    dt,
+                    settings.theta,
+                )
+            })
+            .collect();
+        let tracer_accels: Vec<Vec3> = self
+            .tracers
+            .iter()
+            .map(|(body, transform, _velocity)| {
+                tree.get_total_accel(
+                    Entity::PLACEHOLDER,
+                    *transform,
+                    *body,
+                    settings.force_params(),
+                    dt,
+                    settings.theta,
+                )
+            })
+            .collect();
+        let forced = Instant::now();
+
+        for ((velocity, transform), accel) in self.velocities.iter_mut().zip(&mut self.transforms).zip(accels) {
+            *velocity += accel;
+            transform.translation.x += velocity.x * dt;
+            transform.translation.y += velocity.y * dt;
+        }
+        for ((_body, transform, velocity), accel) in self.tracers.iter_mut().zip(tracer_accels) {
+            *velocity += accel;
+            transform.translation.x += velocity.x * dt;
+            transform.translation.y += velocity.y * dt;
+        }
+
+        self.time += dt as f64;
+        self.steps += 1;
+
+        let ms = |from: Instant, to: Instant| (to - from).as_secs_f64() * 1000.0;
+        StepTimes {
+            tree: ms(start, built),
+            force: ms(built, forced),
+            integrate: ms(forced, Instant::now()),
+        }
+    }
+
+    // Potential per unit mass of every body
+    fn potentials(&self, settings: &SimulationSettings) -> Vec<f64> {
+        let tree = self.build_tree(settings);
+        (0..self.bodies.len())
+            .map(|i| tree.get_potential(Self::entity(i), self.transforms[i], settings.force_params(), settings.theta) as f64)
+            .collect()
+    }
+
+    // Kinetic and potential energy
+    pub fn energy(&self, settings: &SimulationSettings) -> (f64, f64) {
+        let potentials = self.potentials(settings);
+        let mut kinetic = 0.0;
+        let mut potential = 0.0;
+        for ((body, velocity), body_potential) in self.bodies.iter().zip(&self.velocities).zip(potentials) {
+            let m = body.mass as f64;
+            kinetic += 0.5 * m * velocity.truncate().length_squared() as f64;
+            potential += 0.5 * m * body_potential;
+        }
+        (kinetic, potential)
+    }
+
+    // Fraction of bodies whose own kinetic plus potential energy is negative
+    pub fn bound_fraction(&self, settings: &SimulationSettings) -> f64 {
+        if self.bodies.is_empty() {
+            return 0.0;
+        }
+        let potentials = self.potentials(settings);
+        let bound = (0..self.bodies.len())
+            .filter(|i| 0.5 * (self.velocities[*i].truncate().length_squared() as f64) + potentials[*i] < 0.0)
+            .count();
+        bound as f64 / self.bodies.len() as f64
+    }
+
+    // Friends-of-friends halos of the current state. Without a linking length the usual
+    // 0.2 times the mean spacing is used.
+    pub fn halos(&self, linking_length: Option<f32>, min_members: usize) -> Vec<Group> {
+        let masses: Vec<f32> = self.bodies.iter().map(|b| b.mass).collect();
+        let positions: Vec<Vec2> = self.transforms.iter().map(|t| t.translation.truncate()).collect();
+        let velocities: Vec<Vec2> = self.velocities.iter().map(|v| v.truncate()).collect();
+        let linking_length = linking_length.unwrap_or_else(|| default_linking_length(&positions, 0.2));
+        find_halos(&masses, &positions, &velocities, linking_length, min_members)
+    }
+}
@@ -0,0 +1,112 @@
+use bevy::prelude::*;
+use bevy_egui::{EguiContexts, egui};
+use crate::force::{ForceLaw, ForceParams};
+use crate::tracer::Massless;
+use crate::ui_config::UiConfig;
+use crate::{Body, SimulationSettings};
+
+// dt is recommended at this fraction of the dynamical time
+pub const STEPS_PER_DYNAMICAL_TIME: f32 = 1000.0;
+
+// Time for a body at the edge of the system to fall a good way in, sqrt(R / a(R)).
+// Goes through ForceParams, so it follows whatever force law is selected.
+pub fn dynamical_time(force: ForceParams, total_mass: f32, radius: f32) -> f32 {
+    let accel = force.accel(total_mass, Vec3::new(radius, 0.0, 0.0), Vec3::ZERO).length();
+    if accel > 0.0 {
+        (radius / accel).sqrt()
+    } else {
+        f32::INFINITY
+    }
+}
+
+pub fn recommended_dt(force: ForceParams, total_mass: f32, radius: f32) -> f32 {
+    dynamical_time(force, total_mass, radius) / STEPS_PER_DYNAMICAL_TIME
+}
+
+fn law_formula(law: ForceLaw) -> &'static str {
+    match law {
+        ForceLaw::InverseLinear => "a = G m / r",
+        ForceLaw::InverseSquare => "a = G m / r²",
+        ForceLaw::InverseCube => "a = G m / r³",
+        ForceLaw::Plummer => "a = G m r / (r² + ε²)^(3/2)",
+    }
+}
+
+// Explains the parameters with the formulas the code uses, plus numbers from the current settings
+pub fn help_window(
+    mut contexts: EguiContexts,
+    settings: Res<SimulationSettings>,
+    bodies: Query<&Body, Without<Massless>>,
+    ui_config: Res<UiConfig>,
+) {
+    let force = settings.force_params();
+    let total_mass: f32 = bodies.iter().map(|b| b.mass).sum();
+    let radius = settings.spawn_area.end().abs().max(settings.spawn_area.start().abs());
+    let t_dyn = dynamical_time(force, total_mass, radius);
+
+    ui_config
+        .place(egui::Window::new("Help"), "Help")
+        .default_open(false)
+        .vscroll(true)
+        .show(contexts.ctx_mut(), |ui| {
+            ui.heading("Force");
+            ui.label(format!("Current law: {}", law_formula(settings.force_law)));
+            ui.label(format!(
+                "G = {}, total mass M = {:.0}. At the edge of the spawn area (r = {:.0}) a body feels a = {:.3}",
+                settings.g,
+                total_mass,
+                radius,
+                force.accel(total_mass, Vec3::new(radius, 0.0, 0.0), Vec3::ZERO).length()
+            ));
+
+            ui.separator();
+            ui.heading("Delta T");
+            ui.label("Every step: v += a dt, then x += v dt (semi-implicit Euler).");
+            ui.label(format!(
+                "Dynamical time sqrt(r / a(r)) = {:.4}. Aim for about {} steps per dynamical time, \
+                 dt ≈ {:.2e}.",
+                t_dyn,
+                STEPS_PER_DYNAMICAL_TIME,
+                recommended_dt(force, total_mass, radius)
+            ));
+            ui.label(format!(
+                "Current dt = {:.2e} gives {:.0} steps per dynamical time.",
+                settings.delta_t,
+                t_dyn / settings.delta_t
+            ));
+
+            ui.separator();
+            ui.heading("BH Theta");
+            ui.label("A tree node of size s at distance d is treated as one body at its center of mass when s / d < θ.");
+            ui.label(format!(
+                "With θ = {}, a node {:.0} wide is approximated once it is further away than {:.0}.",
+                settings.theta,
+                radius,
+                radius / settings.theta
+            ));
+
+            ui.separator();
+            ui.heading("Softening");
+            ui.label(format!("Plummer law: {}", law_formula(ForceLaw::Plummer)));
+            let softened = ForceParams {
+                law: ForceLaw::Plummer,
+                ..force
+            };
+            let newtonian = ForceParams {
+                law: ForceLaw::InverseSquare,
+                ..force
+            };
+            let at = Vec3::new(settings.softening, 0.0, 0.0);
+            ui.label(format!(
+                "With ε = {}, the pull at r = ε is {:.0}% of the unsoftened one, and goes to 0 at r = 0 instead of blowing up.",
+                settings.softening,
+                100.0 * softened.accel(1.0, at, Vec3::ZERO).length() / newtonian.accel(1.0, at, Vec3::ZERO).length()
+            ));
+
+            ui.separator();
+            ui.heading("Modified gravity and corrections");
+            ui.label("MOND: a = a_N / 2 + sqrt(a_N² / 4 + a_N a0), for Newtonian acceleration a_N.");
+            ui.label("Post-Newtonian: precession of 6π G M / (c² a (1 - e²)) per orbit around a compact object.");
+            ui.label("Radiation pressure: a = L / (m r²), pushing away from each star.");
+        });
+}
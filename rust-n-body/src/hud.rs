@@ -0,0 +1,64 @@
+use bevy::prelude::*;
+use bevy_egui::{EguiContexts, egui};
+use crate::clock::SimulationClock;
+use crate::colormap::{ColorScale, legend};
+use crate::energy::Energy;
+use crate::memory::MemoryUsage;
+use crate::threads::Performance;
+use crate::tracer::Massless;
+use crate::{Body, SimulationSettings};
+
+// Drawn on top of the simulation so it ends up in screenshots and recordings
+pub fn overlay(
+    mut contexts: EguiContexts,
+    settings: Res<SimulationSettings>,
+    clock: Res<SimulationClock>,
+    scale: Res<ColorScale>,
+    memory: Res<MemoryUsage>,
+    energy: Res<Energy>,
+    performance: Res<Performance>,
+    bodies: Query<(), (With<Body>, Without<Massless>)>,
+    tracers: Query<(), (With<Body>, With<Massless>)>,
+) {
+    if !settings.show_overlay {
+        return;
+    }
+
+    egui::Area::new(egui::Id::new("overlay"))
+        .anchor(egui::Align2::RIGHT_TOP, [-10.0, 10.0])
+        .interactable(false)
+        .show(contexts.ctx_mut(), |ui| {
+            egui::Frame::popup(ui.style()).show(ui, |ui| {
+                ui.label(format!("t = {:.4}", clock.time));
+                ui.label(format!("Step {}", clock.steps));
+                ui.label(format!("N = {}", bodies.iter().count()));
+                let n_tracers = tracers.iter().count();
+                if n_tracers > 0 {
+                    ui.label(format!("Tracers: {}", n_tracers));
+                }
+                if let Some(ratio) = energy.virial_ratio() {
+                    ui.label(format!("2T/|U| = {:.3}", ratio));
+                }
+                let mb = |bytes: usize| bytes as f32 / (1024.0 * 1024.0);
+                ui.label(format!(
+                    "Memory: ~{:.1} MB (tree {:.1})",
+                    mb(memory.total()),
+                    mb(memory.tree)
+                ));
+                ui.label(format!(
+                    "Frame {:.1} ms, physics {:.1} ms ({:.0}% of the frame)",
+                    performance.frame_ms,
+                    performance.physics_ms,
+                    performance.physics_share() * 100.0
+                ));
+
+                ui.separator();
+                legend(ui, &settings, &scale);
+
+                if !settings.annotation.is_empty() {
+                    ui.separator();
+                    ui.label(&settings.annotation);
+                }
+            });
+        });
+}
@@ -0,0 +1,180 @@
+use bevy::input::mouse::{AccumulatedMouseMotion, AccumulatedMouseScroll};
+use bevy::prelude::*;
+use bevy_egui::{EguiContexts, egui};
+use crate::factory::{SpawnSystemEvent, SystemFactory, SystemKind};
+use crate::pending::PendingSettings;
+use crate::ui_config::UiConfig;
+use crate::{MainCamera, ResetEvent, SimulationSettings};
+
+// Camera turns this many radians per second during the show
+const ROTATE_SPEED: f32 = 0.03;
+// and breathes in and out by this much around the normal zoom, once per ZOOM_PERIOD seconds
+const ZOOM_DEPTH: f32 = 0.3;
+const ZOOM_PERIOD: f32 = 40.0;
+// stick deflection that counts as someone using the gamepad
+const STICK_INPUT: f32 = 0.2;
+
+// What the attract mode cycles through
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Scenario {
+    Cloud,
+    Donut,
+    BinaryWithPlanets,
+    Triple,
+}
+
+impl Scenario {
+    pub const ALL: [Scenario; 4] = [
+        Scenario::Cloud,
+        Scenario::Donut,
+        Scenario::BinaryWithPlanets,
+        Scenario::Triple,
+    ];
+}
+
+// Self-running exhibit: once nobody has touched anything for a while, a new scenario is started
+// every few minutes while the camera slowly turns and zooms. Any input hands control back.
+#[derive(Resource)]
+pub struct Kiosk {
+    pub enabled: bool,
+    pub minutes_per_scenario: f32,
+    pub idle_secs: f32,
+    // running the show right now
+    pub active: bool,
+    pub scenario: usize,
+    // since the last input while waiting, since the scenario started while active
+    elapsed: f32,
+    show_time: f32,
+}
+
+impl Default for Kiosk {
+    fn default() -> Self {
+        Kiosk {
+            enabled: false,
+            minutes_per_scenario: 3.0,
+            idle_secs: 60.0,
+            active: false,
+            scenario: 0,
+            elapsed: 0.0,
+            show_time: 0.0,
+        }
+    }
+}
+
+impl Kiosk {
+    // Scenario to start next, going round in order
+    pub fn advance(&mut self) -> Scenario {
+        let scenario = Scenario::ALL[self.scenario % Scenario::ALL.len()];
+        self.scenario = (self.scenario + 1) % Scenario::ALL.len();
+        self.elapsed = 0.0;
+        scenario
+    }
+
+    // Time to switch, either to the show after being idle or to the next scenario during it
+    pub fn tick(&mut self, dt: f32) -> bool {
+        self.elapsed += dt;
+        if self.active {
+            self.show_time += dt;
+            self.elapsed >= self.minutes_per_scenario * 60.0
+        } else {
+            self.elapsed >= self.idle_secs
+        }
+    }
+}
+
+fn any_input(
+    keys: &ButtonInput<KeyCode>,
+    mouse: &ButtonInput<MouseButton>,
+    motion: &AccumulatedMouseMotion,
+    scroll: &AccumulatedMouseScroll,
+    gamepads: &Query<&Gamepad>,
+) -> bool {
+    keys.get_just_pressed().next().is_some()
+        || mouse.get_just_pressed().next().is_some()
+        || motion.delta != Vec2::ZERO
+        || scroll.delta != Vec2::ZERO
+        || gamepads.iter().any(|gamepad| {
+            gamepad.get_just_pressed().next().is_some()
+                || gamepad.left_stick().length() > STICK_INPUT
+                || gamepad.right_stick().length() > STICK_INPUT
+        })
+}
+
+pub fn kiosk(
+    time: Res<Time>,
+    keys: Res<ButtonInput<KeyCode>>,
+    mouse: Res<ButtonInput<MouseButton>>,
+    motion: Res<AccumulatedMouseMotion>,
+    scroll: Res<AccumulatedMouseScroll>,
+    gamepads: Query<&Gamepad>,
+    mut cameras: Query<(&mut Transform, &mut Projection), With<MainCamera>>,
+    mut kiosk: ResMut<Kiosk>,
+    mut settings: ResMut<SimulationSettings>,
+    mut pending: ResMut<PendingSettings>,
+    mut factory: ResMut<SystemFactory>,
+    mut reset_writer: EventWriter<ResetEvent>,
+    mut spawn_writer: EventWriter<SpawnSystemEvent>,
+) {
+    let Ok((mut camera, mut projection)) = cameras.single_mut() else {
+        return;
+    };
+
+    if !kiosk.enabled || any_input(&keys, &mouse, &motion, &scroll, &gamepads) {
+        if kiosk.active {
+            // hand the view back the way the visitor expects it
+            kiosk.active = false;
+            camera.rotation = Quat::IDENTITY;
+            if let Projection::Orthographic(ortho) = &mut *projection {
+                ortho.scale = 1.0;
+            }
+        }
+        kiosk.elapsed = 0.0;
+        return;
+    }
+
+    if kiosk.tick(time.delta_secs()) {
+        if !kiosk.active {
+            kiosk.active = true;
+            kiosk.show_time = 0.0;
+        }
+        let scenario = kiosk.advance();
+        match scenario {
+            Scenario::Cloud | Scenario::Donut => {
+                // the staged edits go in first so the reset doesn't undo the scenario's start
+                pending.apply(&mut settings);
+                settings.donut = scenario == Scenario::Donut;
+                reset_writer.write(ResetEvent);
+            }
+            Scenario::BinaryWithPlanets => {
+                factory.kind = SystemKind::BinaryWithPlanets;
+                factory.replace = true;
+                spawn_writer.write(SpawnSystemEvent);
+            }
+            Scenario::Triple => {
+                factory.kind = SystemKind::Triple;
+                factory.replace = true;
+                spawn_writer.write(SpawnSystemEvent);
+            }
+        }
+        settings.paused = false;
+    }
+
+    if kiosk.active {
+        camera.rotation = Quat::from_rotation_z(kiosk.show_time * ROTATE_SPEED);
+        if let Projection::Orthographic(ortho) = &mut *projection {
+            ortho.scale = 1.0 + ZOOM_DEPTH * (kiosk.show_time * std::f32::consts::TAU / ZOOM_PERIOD).sin();
+        }
+    }
+}
+
+pub fn kiosk_window(mut contexts: EguiContexts, mut kiosk: ResMut<Kiosk>, ui_config: Res<UiConfig>) {
+    ui_config
+        .place(egui::Window::new("Kiosk"), "Kiosk")
+        .default_open(false)
+        .show(contexts.ctx_mut(), |ui| {
+            ui.add(egui::Checkbox::new(&mut kiosk.enabled, "Attract Mode"));
+            ui.add(egui::Slider::new(&mut kiosk.idle_secs, 5.0..=600.0).text("Idle Seconds Before Starting"));
+            ui.add(egui::Slider::new(&mut kiosk.minutes_per_scenario, 0.5..=30.0).text("Minutes Per Scenario"));
+            ui.label("Cycles through a random cloud, a donut, a binary with planets and a triple. Any input stops it.");
+        });
+}
@@ -0,0 +1,153 @@
+use bevy::prelude::*;
+use bevy_egui::{EguiContexts, egui};
+use crate::factory::{BELT_INNER, BELT_OUTER};
+use crate::orbital_mechanics::orbit_elements;
+use crate::tracer::Massless;
+use crate::ui_config::UiConfig;
+use crate::{Body, SimulationSettings, Velocity};
+
+// Mean motion resonances with Jupiter as (asteroid orbits, Jupiter orbits), the big Kirkwood gaps
+pub const RESONANCES: [(u32, u32); 4] = [(3, 1), (5, 2), (7, 3), (2, 1)];
+
+// Semi-major axis in units of Jupiter's where an asteroid goes round p times while Jupiter goes round q times.
+// Kepler's third law, a goes as the period to the 2/3.
+pub fn resonance_axis(p: u32, q: u32) -> f32 {
+    (q as f32 / p as f32).powf(2.0 / 3.0)
+}
+
+// Counts the bound tracers by semi-major axis around the star, in units of the reference axis
+pub fn axis_histogram(mu: f32, star: (Vec2, Vec2), tracers: &[(Vec2, Vec2)], reference: f32, bins: usize) -> Vec<u32> {
+    let mut counts = vec![0; bins];
+    if reference <= 0.0 || bins == 0 {
+        return counts;
+    }
+    for (pos, vel) in tracers {
+        let elements = orbit_elements(mu, *pos - star.0, *vel - star.1);
+        if elements.period.is_none() {
+            continue;
+        }
+        let a = elements.semi_major_axis / reference;
+        let bin = ((a - BELT_INNER) / (BELT_OUTER - BELT_INNER) * bins as f32).floor();
+        if bin >= 0.0 && (bin as usize) < bins {
+            counts[bin as usize] += 1;
+        }
+    }
+    counts
+}
+
+// Belt tracers counted by semi-major axis, where the Kirkwood gaps open over time. The heaviest body
+// is taken as the star and the second heaviest as Jupiter.
+#[derive(Resource)]
+pub struct KirkwoodHistogram {
+    pub enabled: bool,
+    pub bins: u32,
+    pub every_frames: u32,
+    pub counts: Vec<u32>,
+    frames: u32,
+}
+
+impl Default for KirkwoodHistogram {
+    fn default() -> Self {
+        KirkwoodHistogram {
+            enabled: false,
+            bins: 80,
+            every_frames: 30,
+            counts: Vec::new(),
+            frames: 0,
+        }
+    }
+}
+
+pub fn kirkwood_histogram(
+    settings: Res<SimulationSettings>,
+    massive: Query<(&Transform, &Velocity, &Body), Without<Massless>>,
+    tracers: Query<(&Transform, &Velocity), With<Massless>>,
+    mut histogram: ResMut<KirkwoodHistogram>,
+) {
+    if !histogram.enabled {
+        return;
+    }
+    histogram.frames += 1;
+    if histogram.frames < histogram.every_frames && !histogram.counts.is_empty() {
+        return;
+    }
+    histogram.frames = 0;
+
+    let mut heaviest: Vec<(f32, Vec2, Vec2)> = massive
+        .iter()
+        .map(|(t, v, b)| (b.mass, t.translation.truncate(), v.0.truncate()))
+        .collect();
+    heaviest.sort_by(|a, b| b.0.total_cmp(&a.0));
+    let [(m_star, star_pos, star_vel), (m_jupiter, jupiter_pos, jupiter_vel), ..] = heaviest[..] else {
+        histogram.counts.clear();
+        return;
+    };
+    let jupiter = orbit_elements(
+        settings.g * (m_star + m_jupiter),
+        jupiter_pos - star_pos,
+        jupiter_vel - star_vel,
+    );
+    let tracers: Vec<(Vec2, Vec2)> = tracers
+        .iter()
+        .map(|(t, v)| (t.translation.truncate(), v.0.truncate()))
+        .collect();
+    histogram.counts = axis_histogram(
+        settings.g * m_star,
+        (star_pos, star_vel),
+        &tracers,
+        jupiter.semi_major_axis,
+        histogram.bins as usize,
+    );
+}
+
+pub fn kirkwood_window(mut contexts: EguiContexts, mut histogram: ResMut<KirkwoodHistogram>, ui_config: Res<UiConfig>) {
+    ui_config
+        .place(egui::Window::new("Kirkwood Gaps"), "Kirkwood Gaps")
+        .default_open(false)
+        .show(contexts.ctx_mut(), |ui| {
+            ui.add(egui::Checkbox::new(&mut histogram.enabled, "Count Tracers By Semi-Major Axis"));
+            ui.add(egui::Slider::new(&mut histogram.bins, 10..=200).text("Bins"));
+            ui.add(egui::Slider::new(&mut histogram.every_frames, 1..=300).text("Update Every N Frames"));
+            if histogram.counts.is_empty() {
+                ui.label("Spawn an Asteroid Belt from the System Factory");
+                return;
+            }
+
+            let (rect, _response) = ui.allocate_exact_size(egui::vec2(300.0, 100.0), egui::Sense::hover());
+            let painter = ui.painter();
+            let max = histogram.counts.iter().copied().max().unwrap_or(0).max(1);
+            let width = rect.width() / histogram.counts.len() as f32;
+            for (i, count) in histogram.counts.iter().enumerate() {
+                let height = rect.height() * *count as f32 / max as f32;
+                let x = rect.left() + i as f32 * width;
+                painter.rect_filled(
+                    egui::Rect::from_min_max(egui::pos2(x, rect.bottom() - height), egui::pos2(x + width, rect.bottom())),
+                    0.0,
+                    egui::Color32::LIGHT_GRAY,
+                );
+            }
+            // where the gaps should open
+            for (p, q) in RESONANCES {
+                let fraction = (resonance_axis(p, q) - BELT_INNER) / (BELT_OUTER - BELT_INNER);
+                let x = rect.left() + rect.width() * fraction;
+                painter.line_segment(
+                    [egui::pos2(x, rect.top()), egui::pos2(x, rect.bottom())],
+                    egui::Stroke::new(1.0, egui::Color32::LIGHT_RED),
+                );
+                painter.text(
+                    egui::pos2(x, rect.top()),
+                    egui::Align2::CENTER_TOP,
+                    format!("{}:{}", p, q),
+                    egui::FontId::proportional(10.0),
+                    egui::Color32::LIGHT_RED,
+                );
+            }
+            ui.horizontal(|ui| {
+                ui.set_width(rect.width());
+                ui.label(format!("a = {:.2} a_J", BELT_INNER));
+                ui.with_layout(egui::Layout::right_to_left(egui::Align::Center), |ui| {
+                    ui.label(format!("{:.2} a_J", BELT_OUTER));
+                });
+            });
+        });
+}
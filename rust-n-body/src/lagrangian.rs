@@ -0,0 +1,229 @@
+use bevy::prelude::*;
+use bevy_egui::{EguiContexts, egui};
+use crate::bhtree::{Quad, Quadtree};
+use crate::clock::SimulationClock;
+use crate::plot::line_plot;
+use crate::tracer::Massless;
+use crate::ui_config::UiConfig;
+use crate::Body;
+
+// Mass fractions whose radii are tracked
+pub const LAGRANGIAN_FRACTIONS: [f32; 3] = [0.1, 0.5, 0.9];
+// Neighbours used for the local density, as in Casertano & Hut
+const DENSITY_NEIGHBOURS: usize = 6;
+// Longest history kept for the plot, older points are dropped
+const MAX_HISTORY: usize = 2000;
+const RADIUS_COLORS: [egui::Color32; 3] = [egui::Color32::LIGHT_RED, egui::Color32::GOLD, egui::Color32::LIGHT_BLUE];
+
+// Each body's density: the mass of its nearest neighbours over the area reaching them.
+// Zero where it can't be told, for too few bodies or bodies sitting on top of each other.
+pub fn local_densities(masses: &[f32], positions: &[Vec2]) -> Vec<f32> {
+    if positions.len() <= DENSITY_NEIGHBOURS {
+        return vec![0.0; positions.len()];
+    }
+
+    let mut tree = Quadtree::new(Quad::new_containing(positions));
+    for (i, p) in positions.iter().enumerate() {
+        let body = Body {
+            mass: masses[i],
+            radius: 0.0,
+            hue: 0.0,
+        };
+        tree.insert(Entity::from_raw(i as u32), Transform::from_xyz(p.x, p.y, 0.0), body);
+    }
+
+    positions
+        .iter()
+        .map(|p| {
+            // the body itself comes back first
+            let neighbours = tree.knn(*p, DENSITY_NEIGHBOURS + 1);
+            let Some((_e, farthest)) = neighbours.last() else {
+                return 0.0;
+            };
+            let r2 = p.distance_squared(*farthest);
+            if r2 <= 0.0 {
+                return 0.0;
+            }
+            let mass: f32 = neighbours[1..].iter().map(|(e, _p)| masses[e.index() as usize]).sum();
+            mass / (std::f32::consts::PI * r2)
+        })
+        .collect()
+}
+
+// Density weighted center, which follows the core instead of being pulled around by escapers like the
+// center of mass is
+pub fn density_center(masses: &[f32], positions: &[Vec2]) -> Option<Vec2> {
+    if positions.len() <= DENSITY_NEIGHBOURS {
+        let total: f32 = masses.iter().sum();
+        if total <= 0.0 {
+            return None;
+        }
+        return Some(masses.iter().zip(positions).map(|(m, p)| *m * *p).sum::<Vec2>() / total);
+    }
+
+    let mut weighted = Vec2::ZERO;
+    let mut total = 0.0;
+    for (density, p) in local_densities(masses, positions).into_iter().zip(positions) {
+        weighted += density * *p;
+        total += density;
+    }
+    if total > 0.0 { Some(weighted / total) } else { None }
+}
+
+// Where the densest clump is: the body with the highest local density, or the center of mass of a handful
+pub fn densest_point(masses: &[f32], positions: &[Vec2]) -> Option<Vec2> {
+    if positions.len() <= DENSITY_NEIGHBOURS {
+        return density_center(masses, positions);
+    }
+    local_densities(masses, positions)
+        .into_iter()
+        .zip(positions)
+        .filter(|(density, _p)| *density > 0.0)
+        .max_by(|a, b| a.0.total_cmp(&b.0))
+        .map(|(_density, p)| *p)
+}
+
+pub fn lagrangian_radii(masses: &[f32], positions: &[Vec2], center: Vec2, fractions: &[f32]) -> Vec<f32> {
+    let mut by_distance: Vec<(f32, f32)> = positions
+        .iter()
+        .zip(masses)
+        .map(|(p, m)| (p.distance(center), *m))
+        .collect();
+    by_distance.sort_by(|a, b| a.0.total_cmp(&b.0));
+    let total: f32 = masses.iter().sum();
+
+    fractions
+        .iter()
+        .map(|fraction| {
+            let mut enclosed = 0.0;
+            for (r, m) in &by_distance {
+                enclosed += m;
+                if enclosed >= fraction * total {
+                    return *r;
+                }
+            }
+            by_distance.last().map_or(0.0, |(r, _m)| *r)
+        })
+        .collect()
+}
+
+pub struct LagrangianSample {
+    pub time: f64,
+    pub radii: Vec<f32>,
+}
+
+// The standard core collapse diagnostic: how the radii enclosing fixed shares of the mass change.
+// The inner one shrinking while the outer ones grow is the core collapsing and the halo puffing up.
+#[derive(Resource)]
+pub struct LagrangianRadii {
+    pub enabled: bool,
+    // measured every this many steps
+    pub every_steps: u32,
+    // circles at the radii around the density center
+    pub draw: bool,
+    pub center: Vec2,
+    pub radii: Vec<f32>,
+    pub history: Vec<LagrangianSample>,
+    last_step: Option<u64>,
+}
+
+impl Default for LagrangianRadii {
+    fn default() -> Self {
+        LagrangianRadii {
+            enabled: false,
+            every_steps: 10,
+            draw: true,
+            center: Vec2::ZERO,
+            radii: Vec::new(),
+            history: Vec::new(),
+            last_step: None,
+        }
+    }
+}
+
+pub fn track_lagrangian(
+    clock: Res<SimulationClock>,
+    bodies: Query<(&Transform, &Body), Without<Massless>>,
+    mut lagrangian: ResMut<LagrangianRadii>,
+) {
+    if !lagrangian.enabled {
+        lagrangian.radii.clear();
+        lagrangian.last_step = None;
+        return;
+    }
+    let due = lagrangian
+        .last_step
+        .is_none_or(|last| clock.steps >= last + lagrangian.every_steps as u64 || clock.steps < last);
+    if !due {
+        return;
+    }
+    lagrangian.last_step = Some(clock.steps);
+
+    let (masses, positions): (Vec<f32>, Vec<Vec2>) = bodies
+        .iter()
+        .map(|(transform, body)| (body.mass, transform.translation.truncate()))
+        .unzip();
+    let Some(center) = density_center(&masses, &positions) else {
+        return;
+    };
+    lagrangian.center = center;
+    lagrangian.radii = lagrangian_radii(&masses, &positions, center, &LAGRANGIAN_FRACTIONS);
+
+    let sample = LagrangianSample {
+        time: clock.time,
+        radii: lagrangian.radii.clone(),
+    };
+    lagrangian.history.push(sample);
+    if lagrangian.history.len() > MAX_HISTORY {
+        lagrangian.history.remove(0);
+    }
+}
+
+pub fn draw_lagrangian(lagrangian: Res<LagrangianRadii>, mut gizmos: Gizmos) {
+    if !lagrangian.enabled || !lagrangian.draw {
+        return;
+    }
+    for (r, color) in lagrangian.radii.iter().zip(RADIUS_COLORS) {
+        let [red, green, blue, _a] = color.to_array();
+        gizmos.circle_2d(lagrangian.center, *r, Color::srgba_u8(red, green, blue, 120));
+    }
+}
+
+pub fn lagrangian_window(
+    mut contexts: EguiContexts,
+    mut lagrangian: ResMut<LagrangianRadii>,
+    ui_config: Res<UiConfig>,
+) {
+    ui_config
+        .place(egui::Window::new("Lagrangian Radii"), "Lagrangian Radii")
+        .default_open(false)
+        .show(contexts.ctx_mut(), |ui| {
+            ui.add(egui::Checkbox::new(&mut lagrangian.enabled, "Track Lagrangian Radii"));
+            ui.add(egui::Slider::new(&mut lagrangian.every_steps, 1..=1000).logarithmic(true).text("Every N Steps"));
+            ui.add(egui::Checkbox::new(&mut lagrangian.draw, "Draw Radii"));
+            if ui.button("Clear History").clicked() {
+                lagrangian.history.clear();
+            }
+
+            if lagrangian.radii.is_empty() {
+                return;
+            }
+            ui.separator();
+            ui.label(format!(
+                "Density center: ({:.1}, {:.1})",
+                lagrangian.center.x, lagrangian.center.y
+            ));
+            for ((fraction, r), color) in LAGRANGIAN_FRACTIONS.iter().zip(&lagrangian.radii).zip(RADIUS_COLORS) {
+                ui.colored_label(color, format!("r{:.0}%: {:.2}", fraction * 100.0, r));
+            }
+            let series: Vec<Vec<f32>> = (0..LAGRANGIAN_FRACTIONS.len())
+                .map(|i| lagrangian.history.iter().map(|s| s.radii[i]).collect())
+                .collect();
+            let lines: Vec<(&[f32], egui::Color32)> =
+                series.iter().zip(RADIUS_COLORS).map(|(s, c)| (s.as_slice(), c)).collect();
+            line_plot(ui, &lines);
+            if let (Some(first), Some(last)) = (lagrangian.history.first(), lagrangian.history.last()) {
+                ui.label(format!("t = {:.2} to {:.2}", first.time, last.time));
+            }
+        });
+}
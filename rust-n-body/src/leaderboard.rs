@@ -0,0 +1,157 @@
+use bevy::prelude::*;
+use bevy_egui::{EguiContexts, egui};
+use crate::selection::Selected;
+use crate::tracer::Massless;
+use crate::ui_config::UiConfig;
+use crate::{Body, MainCamera, SimulationSettings, Velocity};
+
+// How many bodies went into this one, for bodies made by merging others
+#[derive(Component, Clone, Copy, Default)]
+pub struct Merges(pub u32);
+
+#[derive(Clone, Copy)]
+pub struct Leader {
+    pub entity: Entity,
+    pub mass: f32,
+    pub speed: f32,
+    pub merges: u32,
+}
+
+// The heaviest bodies, once merging leaves a few dominant ones they are hard to find among the rest
+#[derive(Resource)]
+pub struct Leaderboard {
+    pub count: usize,
+    pub highlight: bool,
+    // body the main camera stays centered on
+    pub follow: Option<Entity>,
+    pub leaders: Vec<Leader>,
+}
+
+impl Default for Leaderboard {
+    fn default() -> Self {
+        Leaderboard {
+            count: 10,
+            highlight: true,
+            follow: None,
+            leaders: Vec::new(),
+        }
+    }
+}
+
+// Indices of the n largest masses, heaviest first
+pub fn heaviest(masses: &[f32], n: usize) -> Vec<usize> {
+    let mut order: Vec<usize> = (0..masses.len()).collect();
+    // only the top n have to be in order
+    if n < order.len() {
+        order.select_nth_unstable_by(n, |a, b| masses[*b].total_cmp(&masses[*a]));
+        order.truncate(n);
+    }
+    order.sort_by(|a, b| masses[*b].total_cmp(&masses[*a]).then(a.cmp(b)));
+    order
+}
+
+pub fn rank_bodies(
+    mut board: ResMut<Leaderboard>,
+    bodies: Query<(Entity, &Body, &Velocity, Option<&Merges>), Without<Massless>>,
+) {
+    let all: Vec<(Entity, &Body, &Velocity, Option<&Merges>)> = bodies.iter().collect();
+    let masses: Vec<f32> = all.iter().map(|(_e, body, _v, _m)| body.mass).collect();
+    board.leaders = heaviest(&masses, board.count)
+        .into_iter()
+        .map(|i| {
+            let (entity, body, velocity, merges) = all[i];
+            Leader {
+                entity,
+                mass: body.mass,
+                speed: velocity.0.truncate().length(),
+                merges: merges.map_or(0, |m| m.0),
+            }
+        })
+        .collect();
+}
+
+// Keeps the main camera on the followed body, until it is gone
+pub fn follow_leader(
+    mut board: ResMut<Leaderboard>,
+    bodies: Query<&Transform, (With<Body>, Without<MainCamera>)>,
+    mut cameras: Query<&mut Transform, With<MainCamera>>,
+) {
+    let Some(entity) = board.follow else {
+        return;
+    };
+    let Ok(target) = bodies.get(entity) else {
+        board.follow = None;
+        return;
+    };
+    if let Ok(mut camera) = cameras.single_mut() {
+        camera.translation = target.translation.truncate().extend(camera.translation.z);
+    }
+}
+
+pub fn draw_leaders(board: Res<Leaderboard>, bodies: Query<(&Transform, &Body)>, mut gizmos: Gizmos) {
+    if !board.highlight {
+        return;
+    }
+    for leader in &board.leaders {
+        if let Ok((transform, body)) = bodies.get(leader.entity) {
+            gizmos.circle_2d(
+                Isometry2d::from_translation(transform.translation.truncate()),
+                body.radius * 1.5 + 2.0,
+                Color::srgba(1.0, 0.85, 0.2, 0.6),
+            );
+        }
+    }
+}
+
+pub fn leaderboard_window(
+    mut contexts: EguiContexts,
+    mut board: ResMut<Leaderboard>,
+    settings: Res<SimulationSettings>,
+    selected: Query<Entity, With<Selected>>,
+    ui_config: Res<UiConfig>,
+    mut commands: Commands,
+) {
+    let units = settings.units;
+    ui_config
+        .place(egui::Window::new("Leaderboard"), "Leaderboard")
+        .default_open(false)
+        .show(contexts.ctx_mut(), |ui| {
+            ui.add(egui::Slider::new(&mut board.count, 1..=50).text("Bodies"));
+            ui.add(egui::Checkbox::new(&mut board.highlight, "Highlight In View"));
+
+            let mut select = None;
+            let mut follow = board.follow;
+            egui::ScrollArea::vertical().max_height(300.0).show(ui, |ui| {
+                egui::Grid::new("leaderboard").striped(true).show(ui, |ui| {
+                    ui.label("#");
+                    ui.label("Mass");
+                    ui.label("Speed");
+                    ui.label("Merges");
+                    ui.end_row();
+                    for (rank, leader) in board.leaders.iter().enumerate() {
+                        ui.label(format!("{}", rank + 1));
+                        ui.label(units.mass(leader.mass));
+                        ui.label(units.speed(leader.speed));
+                        ui.label(format!("{}", leader.merges));
+                        let is_selected = selected.contains(leader.entity);
+                        if ui.selectable_label(is_selected, "Select").clicked() {
+                            select = Some(leader.entity);
+                        }
+                        let following = follow == Some(leader.entity);
+                        if ui.selectable_label(following, "Follow").clicked() {
+                            follow = if following { None } else { Some(leader.entity) };
+                        }
+                        ui.end_row();
+                    }
+                });
+            });
+            board.follow = follow;
+
+            if let Some(entity) = select {
+                for other in &selected {
+                    commands.entity(other).remove::<Selected>();
+                }
+                commands.entity(entity).insert(Selected);
+            }
+        });
+}
@@ -0,0 +1,15 @@
+// The Barnes-Hut solver without the app around it, for embedding. The app uses it from here too, along
+// with the body buffers and tree building its step runs on, which the benches time.
+pub mod bhtree;
+pub mod body;
+pub mod dualtree;
+#[cfg(feature = "ffi")]
+pub mod ffi;
+pub mod force;
+pub mod pipeline;
+#[cfg(feature = "python")]
+pub mod python;
+pub mod soa;
+pub mod solver;
+
+pub use body::Body;
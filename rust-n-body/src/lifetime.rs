@@ -0,0 +1,188 @@
+use bevy::prelude::*;
+use bevy_egui::egui;
+use crate::clock::SimulationClock;
+use crate::fade::despawn_with_fade;
+use crate::tracer::Massless;
+use crate::{Body, SimulationSettings, Velocity, mass_to_hue, spawn_body};
+
+// Where a body came from, each with its own lifetime preset
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum SpawnSource {
+    Slingshot,
+    Satellite,
+    Tracers,
+    Factory,
+    RocheStream,
+}
+
+impl SpawnSource {
+    pub const ALL: [SpawnSource; 5] = [
+        SpawnSource::Slingshot,
+        SpawnSource::Satellite,
+        SpawnSource::Tracers,
+        SpawnSource::Factory,
+        SpawnSource::RocheStream,
+    ];
+
+    pub fn label(self) -> &'static str {
+        match self {
+            SpawnSource::Slingshot => "Slingshot",
+            SpawnSource::Satellite => "Satellite",
+            SpawnSource::Tracers => "Tracers",
+            SpawnSource::Factory => "System Factory",
+            SpawnSource::RocheStream => "Roche Stream",
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug)]
+pub struct LifetimePreset {
+    pub enabled: bool,
+    // simulated time
+    pub lifetime: f32,
+    // pieces a massive body breaks into at the end, fewer than 2 and it just fades out
+    pub fragments: u32,
+    pub fragment_speed: f32,
+}
+
+impl Default for LifetimePreset {
+    fn default() -> Self {
+        LifetimePreset {
+            enabled: false,
+            lifetime: 1.0,
+            fragments: 0,
+            fragment_speed: 20.0,
+        }
+    }
+}
+
+// Time left before the body goes, counted in simulated time so pausing and slow motion stretch it
+#[derive(Component, Clone, Copy, Debug)]
+pub struct Lifetime {
+    pub remaining: f32,
+    // the full lifetime, which the fragments get again
+    pub span: f32,
+    pub fragments: u32,
+    pub fragment_speed: f32,
+}
+
+// Lifetimes given to newly spawned bodies, so streams and debris don't keep piling up
+#[derive(Resource, Default)]
+pub struct Lifetimes {
+    pub presets: [LifetimePreset; 5],
+}
+
+impl Lifetimes {
+    pub fn preset(&self, source: SpawnSource) -> &LifetimePreset {
+        &self.presets[source as usize]
+    }
+
+    pub fn component(&self, source: SpawnSource) -> Option<Lifetime> {
+        let preset = self.preset(source);
+        preset.enabled.then_some(Lifetime {
+            remaining: preset.lifetime,
+            span: preset.lifetime,
+            fragments: preset.fragments,
+            fragment_speed: preset.fragment_speed,
+        })
+    }
+
+    // Gives the entity the source's lifetime, if the source has one
+    pub fn apply(&self, source: SpawnSource, commands: &mut Commands, entity: Entity) {
+        if let Some(lifetime) = self.component(source) {
+            commands.entity(entity).insert(lifetime);
+        }
+    }
+
+    pub fn ui(&mut self, ui: &mut egui::Ui) {
+        for (source, preset) in SpawnSource::ALL.iter().zip(&mut self.presets) {
+            ui.add(egui::Checkbox::new(&mut preset.enabled, source.label()));
+            if !preset.enabled {
+                continue;
+            }
+            ui.push_id(source.label(), |ui| {
+                ui.add(egui::Slider::new(&mut preset.lifetime, 0.01..=100.0).logarithmic(true).text("Lifetime"));
+                if *source != SpawnSource::Tracers && *source != SpawnSource::RocheStream {
+                    ui.add(egui::Slider::new(&mut preset.fragments, 0..=20).text("Decay Into Fragments"));
+                    if preset.fragments >= 2 {
+                        ui.add(egui::Slider::new(&mut preset.fragment_speed, 0.0..=500.0).text("Fragment Speed"));
+                    }
+                }
+            });
+        }
+    }
+}
+
+// n equal pieces on a circle of the given radius around the body, flying apart symmetrically so
+// mass and momentum come out the same as they went in. As (mass, position, velocity).
+pub fn fragment_states(mass: f32, pos: Vec2, vel: Vec2, radius: f32, speed: f32, n: u32) -> Vec<(f32, Vec2, Vec2)> {
+    if n < 2 {
+        return Vec::new();
+    }
+    (0..n)
+        .map(|i| {
+            let dir = Vec2::from_angle(i as f32 / n as f32 * std::f32::consts::TAU);
+            (mass / n as f32, pos + dir * radius, vel + dir * speed)
+        })
+        .collect()
+}
+
+pub fn age_bodies(
+    clock: Res<SimulationClock>,
+    settings: Res<SimulationSettings>,
+    mut last_time: Local<f64>,
+    mut aging: Query<(Entity, &mut Lifetime, &Body, &Transform, &Velocity, Has<Massless>)>,
+    mut commands: Commands,
+    mut materials: ResMut<Assets<ColorMaterial>>,
+    mut meshes: ResMut<Assets<Mesh>>,
+) {
+    let elapsed = clock.time - *last_time;
+    *last_time = clock.time;
+    // nothing passes while paused, and a reset sends the clock back
+    if elapsed <= 0.0 {
+        return;
+    }
+
+    for (entity, mut lifetime, body, transform, velocity, massless) in &mut aging {
+        lifetime.remaining -= elapsed as f32;
+        if lifetime.remaining > 0.0 {
+            continue;
+        }
+        despawn_with_fade(&mut commands, entity);
+        // tracers have no mass to break up
+        if massless {
+            continue;
+        }
+
+        let pieces = fragment_states(
+            body.mass,
+            transform.translation.truncate(),
+            velocity.0.truncate(),
+            body.radius,
+            lifetime.fragment_speed,
+            lifetime.fragments,
+        );
+        for (mass, pos, vel) in pieces {
+            let fragment = Body {
+                mass,
+                radius: settings.collision_radius(mass),
+                hue: mass_to_hue(mass, settings.min_body_mass, settings.max_body_mass).clamp(0.0, 1.0),
+            };
+            let piece = spawn_body(
+                fragment,
+                Transform::from_xyz(pos.x, pos.y, transform.translation.z),
+                Velocity(vel.extend(0.0)),
+                &settings,
+                &mut commands,
+                &mut materials,
+                &mut meshes,
+            );
+            // the pieces live as long again but don't break up any further
+            commands.entity(piece).insert(Lifetime {
+                remaining: lifetime.span,
+                fragments: 0,
+                ..*lifetime
+            });
+        }
+    }
+}
@@ -0,0 +1,115 @@
+use bevy::log::{DEFAULT_FILTER, Level};
+use bevy::prelude::*;
+use std::fs::File;
+use std::io::{self, Write};
+use std::sync::Mutex;
+use std::sync::atomic::{AtomicBool, AtomicU8, Ordering};
+use tracing_subscriber::filter::{EnvFilter, filter_fn};
+use tracing_subscriber::prelude::*;
+use crate::SimulationSettings;
+
+// The subscriber is set up once at startup, so the layers read these instead of the settings resource.
+static VERBOSITY: AtomicU8 = AtomicU8::new(LogVerbosity::Info as u8);
+static JSON_ENABLED: AtomicBool = AtomicBool::new(false);
+static JSON_LOG: Mutex<Option<File>> = Mutex::new(None);
+
+// Only applies to our own log messages, bevy and wgpu stay at their defaults
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum LogVerbosity {
+    Error,
+    Warn,
+    Info,
+    Debug,
+    Trace,
+}
+
+impl LogVerbosity {
+    pub const ALL: [LogVerbosity; 5] = [
+        LogVerbosity::Error,
+        LogVerbosity::Warn,
+        LogVerbosity::Info,
+        LogVerbosity::Debug,
+        LogVerbosity::Trace,
+    ];
+
+    fn level(self) -> Level {
+        match self {
+            LogVerbosity::Error => Level::ERROR,
+            LogVerbosity::Warn => Level::WARN,
+            LogVerbosity::Info => Level::INFO,
+            LogVerbosity::Debug => Level::DEBUG,
+            LogVerbosity::Trace => Level::TRACE,
+        }
+    }
+}
+
+fn simulation_enabled(meta: &tracing::Metadata) -> bool {
+    if !meta.target().starts_with(env!("CARGO_CRATE_NAME")) {
+        return true;
+    }
+    let verbosity = LogVerbosity::ALL[VERBOSITY.load(Ordering::Relaxed) as usize];
+    *meta.level() <= verbosity.level()
+}
+
+// Writes to the JSON log file when one is open, and drops everything otherwise
+struct JsonLogWriter;
+
+impl Write for JsonLogWriter {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        match JSON_LOG.lock().unwrap().as_mut() {
+            Some(file) => file.write(buf),
+            None => Ok(buf.len()),
+        }
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        match JSON_LOG.lock().unwrap().as_mut() {
+            Some(file) => file.flush(),
+            None => Ok(()),
+        }
+    }
+}
+
+// Takes the place of bevy's LogPlugin: a console layer and a JSON file layer, both following the
+// verbosity picked in the UI. Our own messages are let through at every level by the filter, the
+// verbosity picks from them later.
+pub fn init_logging() {
+    let console = tracing_subscriber::fmt::layer()
+        .with_writer(io::stderr)
+        .with_filter(filter_fn(simulation_enabled));
+    let json = tracing_subscriber::fmt::layer()
+        .json()
+        .with_writer(|| JsonLogWriter)
+        .with_filter(filter_fn(|meta| {
+            JSON_ENABLED.load(Ordering::Relaxed) && simulation_enabled(meta)
+        }));
+
+    let filter = EnvFilter::new(format!("info,{},{}=trace", DEFAULT_FILTER, env!("CARGO_CRATE_NAME")));
+
+    if let Err(err) = tracing_subscriber::registry().with(filter).with(console).with(json).try_init() {
+        eprintln!("Could not set up logging: {}", err);
+    }
+}
+
+// Picks up changes to the log settings made in the UI
+pub fn apply_log_settings(mut settings: ResMut<SimulationSettings>) {
+    if !settings.is_changed() {
+        return;
+    }
+
+    VERBOSITY.store(settings.log_verbosity as u8, Ordering::Relaxed);
+
+    let is_open = JSON_LOG.lock().unwrap().is_some();
+    if settings.json_log && !is_open {
+        match File::create(&settings.json_log_path) {
+            Ok(file) => *JSON_LOG.lock().unwrap() = Some(file),
+            Err(err) => {
+                error!("Could not open JSON log file {}: {}", settings.json_log_path, err);
+                settings.json_log = false;
+            }
+        }
+    } else if !settings.json_log && is_open {
+        *JSON_LOG.lock().unwrap() = None;
+    }
+    JSON_ENABLED.store(settings.json_log, Ordering::Relaxed);
+}
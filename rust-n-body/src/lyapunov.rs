@@ -0,0 +1,221 @@
+use bevy::prelude::*;
+use bevy_egui::{EguiContexts, egui};
+use crate::clock::{MAX_SUBSTEPS, SimulationClock};
+use crate::headless::HeadlessSim;
+use crate::plot::line_plot;
+use crate::selection::Selected;
+use crate::tracer::Massless;
+use crate::ui_config::UiConfig;
+use crate::{Body, ResetEvent, SimulationSettings, Velocity};
+
+const MAX_HISTORY: usize = 2000;
+
+// Two headless copies of the system, one with a body nudged by d0, stepped side by side.
+// Only gravity is in them, so the attractor and collisions of the real run don't blur the estimate.
+pub struct Divergence {
+    pub reference: HeadlessSim,
+    pub shadow: HeadlessSim,
+    pub d0: f32,
+    // ln(d / d0) collected at every renormalization
+    log_sum: f64,
+    pub renormalizations: u32,
+}
+
+impl Divergence {
+    pub fn new(reference: HeadlessSim, body: usize, d0: f32) -> Self {
+        let mut shadow = HeadlessSim::from_state(
+            reference
+                .bodies
+                .iter()
+                .zip(&reference.transforms)
+                .zip(&reference.velocities)
+                .map(|((b, t), v)| (*b, *t, *v)),
+        );
+        if let Some(transform) = shadow.transforms.get_mut(body) {
+            transform.translation.x += d0;
+        }
+        Divergence {
+            reference,
+            shadow,
+            d0,
+            log_sum: 0.0,
+            renormalizations: 0,
+        }
+    }
+
+    // Distance between the two copies over all body positions
+    pub fn separation(&self) -> f32 {
+        self.reference
+            .transforms
+            .iter()
+            .zip(&self.shadow.transforms)
+            .map(|(a, b)| a.translation.truncate().distance_squared(b.translation.truncate()))
+            .sum::<f32>()
+            .sqrt()
+    }
+
+    pub fn step(&mut self, settings: &SimulationSettings, dt: f32) {
+        self.reference.step_dt(settings, dt);
+        self.shadow.step_dt(settings, dt);
+    }
+
+    // Once the copies are factor times d0 apart, pulls the shadow back to d0 along the same direction,
+    // positions and velocities alike, before the separation saturates at the size of the system
+    pub fn renormalize(&mut self, factor: f32) {
+        let d = self.separation();
+        if d < factor * self.d0 || d <= 0.0 {
+            return;
+        }
+        let scale = self.d0 / d;
+        for i in 0..self.shadow.bodies.len() {
+            let (pos, vel) = (self.reference.transforms[i].translation, self.reference.velocities[i]);
+            let shadow = &mut self.shadow.transforms[i].translation;
+            *shadow = pos + (*shadow - pos) * scale;
+            self.shadow.velocities[i] = vel + (self.shadow.velocities[i] - vel) * scale;
+        }
+        self.log_sum += (d / self.d0).ln() as f64;
+        self.renormalizations += 1;
+    }
+
+    // ln(d / d0) as if the shadow had never been pulled back
+    pub fn log_separation(&self) -> f64 {
+        let d = self.separation();
+        if d <= 0.0 {
+            return self.log_sum;
+        }
+        self.log_sum + (d / self.d0).ln() as f64
+    }
+
+    // Largest Lyapunov exponent, the slope of the log separation. Its inverse is the Lyapunov time.
+    pub fn exponent(&self) -> Option<f64> {
+        (self.reference.time > 0.0).then(|| self.log_separation() / self.reference.time)
+    }
+}
+
+// How fast nearby starts of the same system drift apart: a chaotic system's separation grows as e^(t / t_L)
+#[derive(Resource)]
+pub struct LyapunovEstimator {
+    pub enabled: bool,
+    // nudge given to one body of the shadow
+    pub perturbation: f32,
+    // separation, in units of the perturbation, at which the shadow is pulled back
+    pub renormalize_factor: f32,
+    pub restart_requested: bool,
+    pub divergence: Option<Divergence>,
+    // ln(d / d0) per frame
+    pub history: Vec<f32>,
+    last_step: Option<u64>,
+}
+
+impl Default for LyapunovEstimator {
+    fn default() -> Self {
+        LyapunovEstimator {
+            enabled: false,
+            perturbation: 0.01,
+            renormalize_factor: 100.0,
+            restart_requested: false,
+            divergence: None,
+            history: Vec::new(),
+            last_step: None,
+        }
+    }
+}
+
+pub fn track_lyapunov(
+    mut reset_events: EventReader<ResetEvent>,
+    settings: Res<SimulationSettings>,
+    clock: Res<SimulationClock>,
+    bodies: Query<(Entity, &Body, &Transform, &Velocity), Without<Massless>>,
+    selected: Query<(), With<Selected>>,
+    mut lyapunov: ResMut<LyapunovEstimator>,
+) {
+    if !reset_events.is_empty() {
+        reset_events.clear();
+        lyapunov.restart_requested = true;
+    }
+    if !lyapunov.enabled {
+        lyapunov.divergence = None;
+        lyapunov.last_step = None;
+        return;
+    }
+
+    if lyapunov.restart_requested || lyapunov.divergence.is_none() {
+        lyapunov.restart_requested = false;
+        lyapunov.history.clear();
+        // the selected body gets the nudge, or else the first one
+        let nudged = bodies.iter().position(|(entity, ..)| selected.contains(entity)).unwrap_or(0);
+        let reference =
+            HeadlessSim::from_state(bodies.iter().map(|(_entity, body, transform, velocity)| (*body, *transform, velocity.0)));
+        let d0 = lyapunov.perturbation;
+        lyapunov.divergence = (!reference.bodies.is_empty()).then(|| Divergence::new(reference, nudged, d0));
+        lyapunov.last_step = Some(clock.steps);
+        return;
+    }
+
+    // as many steps as the real run took this frame, at its dt
+    let last = lyapunov.last_step.unwrap_or(clock.steps);
+    let steps = clock.steps.saturating_sub(last).min(MAX_SUBSTEPS as u64);
+    lyapunov.last_step = Some(clock.steps);
+    if steps == 0 {
+        return;
+    }
+    let (_substeps, dt) = settings.pacing();
+    let factor = lyapunov.renormalize_factor;
+    let Some(divergence) = lyapunov.divergence.as_mut() else {
+        return;
+    };
+    for _ in 0..steps {
+        divergence.step(&settings, dt);
+        divergence.renormalize(factor);
+    }
+    let log_separation = divergence.log_separation() as f32;
+
+    lyapunov.history.push(log_separation);
+    if lyapunov.history.len() > MAX_HISTORY {
+        lyapunov.history.remove(0);
+    }
+}
+
+pub fn lyapunov_window(mut contexts: EguiContexts, mut lyapunov: ResMut<LyapunovEstimator>, ui_config: Res<UiConfig>) {
+    ui_config
+        .place(egui::Window::new("Lyapunov"), "Lyapunov")
+        .default_open(false)
+        .show(contexts.ctx_mut(), |ui| {
+            ui.add(egui::Checkbox::new(&mut lyapunov.enabled, "Run Shadow Copy"));
+            ui.add(
+                egui::Slider::new(&mut lyapunov.perturbation, 1e-4..=1.0)
+                    .logarithmic(true)
+                    .text("Perturbation"),
+            );
+            ui.add(
+                egui::Slider::new(&mut lyapunov.renormalize_factor, 10.0..=1e4)
+                    .logarithmic(true)
+                    .text("Renormalize At x Perturbation"),
+            );
+            ui.label("The selected body is the one nudged");
+            if ui.button("Restart").clicked() {
+                lyapunov.restart_requested = true;
+            }
+
+            let Some(divergence) = &lyapunov.divergence else {
+                return;
+            };
+            ui.separator();
+            ui.label(format!("Separation: {:.3e}", divergence.separation()));
+            ui.label(format!(
+                "ln(d / d0): {:.2} over t = {:.2}, {} renormalizations",
+                divergence.log_separation(),
+                divergence.reference.time,
+                divergence.renormalizations
+            ));
+            match divergence.exponent() {
+                Some(exponent) if exponent > 0.0 => {
+                    ui.label(format!("Exponent: {:.4}, Lyapunov time: {:.2}", exponent, 1.0 / exponent));
+                }
+                _ => {
+                    ui.label("Exponent: not positive yet");
+                }
+            }
+            line_plot(ui, &[(lyapunov.history.as_slice(), egui::Color32::LIGHT_GREEN)]);
+        });
+}
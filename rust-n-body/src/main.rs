@@ -1,20 +1,280 @@
-pub(crate) mod bhtree;
+// Bevy systems get every resource and query they use as an argument, and the queries spell out their
+// components, so long argument lists and nested query types are what systems look like here
+#![allow(clippy::too_many_arguments, clippy::type_complexity)]
 pub(crate) mod tests;
+#[cfg(test)]
+pub(crate) mod test_app;
+use bevy::audio::AddAudioSource;
+use bevy::log::LogPlugin;
+use bevy::math::DVec2;
+use bevy::platform::time::Instant;
 use bevy::prelude::*;
+use bevy::render::view::RenderLayers;
+use bevy::tasks::{ComputeTaskPool, ParallelSlice, TaskPool, available_parallelism};
 use bevy_egui::{EguiContextPass, EguiContexts, EguiPlugin, egui};
-use bhtree::{Quad, Quadtree};
-use rand::Rng;
-use std::{collections::HashMap, ops::RangeInclusive};
+use bhtree::{Quadtree, WarmStart, calc_accel};
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+use rust_n_body::{Body, bhtree, dualtree, force, pipeline, soa};
+use std::collections::HashMap;
+use std::ops::RangeInclusive;
 
 mod collision;  
-use collision::{collision};
+use collision::{CollisionCount, CollisionEvent, collision};
 
-#[derive(Resource)]
+mod collisionlog;
+use collisionlog::{CollisionLog, collision_log_window, log_collisions};
+
+mod audio;
+use audio::Tone;
+
+mod benchmark;
+use benchmark::{Benchmark, benchmark_window, update_benchmark};
+
+mod buckets;
+use buckets::bucket_accels;
+
+mod bookmarks;
+use bookmarks::{Bookmarks, bookmarks_window, update_bookmarks};
+
+mod branch;
+use branch::{Branch, branch_window, update_branch};
+
+mod automation;
+use automation::{Automation, automation_window, check_automation};
+
+mod chirp;
+use chirp::{MergerChirp, merger_chirp, start_chirp};
+
+mod choreography;
+use choreography::{ChoreographyLibrary, choreography_window};
+
+mod clock;
+use clock::{MAX_SUBSTEPS, SimulationClock, pacing};
+
+mod compare;
+
+mod colormap;
+use colormap::{ColorMode, ColorScale, Colormap, legend, recolor_bodies};
+
+mod contours;
+use contours::{PotentialContours, draw_contours, update_contours};
+
+mod controller;
+use controller::{Controller, ControllerTools, apply_controllers, controllers_window};
+
+mod decimation;
+use decimation::{Decimation, clamp_render_size, render_decimation};
+
+mod director;
+use director::{Director, direct_camera, director_window};
+
+use dualtree::dual_tree_accels;
+
+mod energy;
+use energy::{Energy, WorkError};
+
+mod export;
+use export::{Export, export_step};
+
+mod factory;
+use factory::{SpawnSystemEvent, SystemFactory, factory_window, system_handler};
+
+mod fade;
+use fade::{Fade, despawn_with_fade, fade_system};
+
+mod flash;
+use flash::{ImpactFlash, spawn_flashes, update_flashes};
+
+use force::{ForceLaw, ForceParams, Star, radiation_pressure};
+
+mod gamepad;
+use gamepad::gamepad_input;
+
+mod ghost;
+use ghost::{GhostEvent, ghost_handler, ghost_update};
+
+mod groups;
+use groups::{GroupAnalysis, analyze_groups, groups_window};
+
+mod guard;
+use guard::{NanAction, NanGuard, Quarantined, nan_guard};
+
+mod headless;
+
+mod help;
+use help::help_window;
+
+mod hud;
+use hud::overlay;
+
+mod kiosk;
+use kiosk::{Kiosk, kiosk, kiosk_window};
+
+mod kirkwood;
+use kirkwood::{KirkwoodHistogram, kirkwood_histogram, kirkwood_window};
+
+mod lagrangian;
+use lagrangian::{LagrangianRadii, draw_lagrangian, lagrangian_window, track_lagrangian};
+
+mod leaderboard;
+use leaderboard::{Leaderboard, draw_leaders, follow_leader, leaderboard_window, rank_bodies};
+
+mod lifetime;
+use lifetime::{Lifetimes, age_bodies};
+
+mod logging;
+use logging::{LogVerbosity, apply_log_settings};
+
+mod lyapunov;
+use lyapunov::{LyapunovEstimator, lyapunov_window, track_lyapunov};
+
+mod memory;
+use memory::{MemoryUsage, memory_budget};
+
+mod minimap;
+use minimap::minimap;
+
+mod net;
+use net::{NetMode, NetSession, net_host, net_viewer};
+
+mod orbital_mechanics;
+
+mod origin;
+use origin::{WorldOrigin, rebase_origin};
+
+mod pending;
+use pending::PendingSettings;
+
+mod physics_thread;
+use physics_thread::{PhysicsThread, physics_on_main_thread, physics_thread_window, update_physics_thread};
+
+use pipeline::PipelinedTree;
+
+mod plot;
+
+mod pip;
+use pip::{PictureInPicture, pip_window, update_pip};
+
+mod poincare;
+use poincare::{PoincareSection, poincare_window, record_crossings};
+
+mod progress;
+
+mod providers;
+use providers::{AddForceProvider, CentralPotential, Drag, ForceInput, ForceProviders, WorldSummary, providers_window};
+
+mod radius;
+use radius::{RadiusLaw, radius_for_mass};
+
+mod remnant;
+
+mod roche;
+use roche::{RocheOverflow, draw_roche, roche_overflow};
+
+mod selection;
+use selection::{
+    DragSelect, GroupTools, Pinned, Selected, SelectedBreakdown, draw_breakdown, draw_drag_rect,
+    draw_orbit_zones, inspector_window, select_body,
+};
+
+mod shutdown;
+use shutdown::dump_on_exit;
+
+mod sonify;
+use sonify::{Sonification, sonify, start_sonification};
+
+use soa::BodyBuffers;
+
+mod stability;
+
+mod starformation;
+use starformation::form_stars;
+
+mod sweep;
+
+mod tools;
+use tools::{
+    ActiveTool, Attractor, Explosion, Falloff, Satellite, Slingshot, attractor_input, draw_attractor,
+    draw_explosion, draw_satellite, draw_slingshot, explosion_input, satellite_spawner, slingshot_spawner,
+};
+
+mod tags;
+use tags::{GroupFrames, GroupTag, TagGroups, hide_groups, tag_spawned, tags_window, track_group_frames};
+
+mod timeline;
+use timeline::{Timeline, run_timeline, timeline_window};
+
+mod timestep;
+use timestep::{SuggestedDt, suggest_dt};
+
+mod trace;
+use trace::{TraceLog, TraceRow, trace_step};
+
+mod trails;
+use trails::{DISPLAY_LAYER, Trails, trails_window, update_trails};
+
+mod transfer;
+use transfer::{TransferPlanner, draw_transfer, transfer_window};
+
+mod treedump;
+use treedump::{TreeExport, export_tree};
+
+mod ui_config;
+use ui_config::{UiConfig, apply_ui_config};
+
+mod units;
+use units::UnitSystem;
+
+mod undo;
+use undo::{UndoHistory, UndoStep, apply_undo, checkpoint_reset, undo_input};
+
+mod validate;
+use validate::{SettingsWarnings, validate, validate_settings};
+
+mod threads;
+use threads::{Performance, task_pool_plugin, track_frame_time};
+
+#[cfg(feature = "tui")]
+mod tui;
+
+mod tracer;
+use tracer::{Massless, SpawnTracersEvent, tracer_handler};
+
+mod virial;
+use virial::{VirialMonitor, virial_monitor, virial_window};
+
+mod wind;
+use wind::{BackgroundFlow, apply_flow, draw_flow, flow_window};
+
+#[derive(Resource, Clone)]
 pub struct SimulationSettings {
     // live tweakables
+    paused: bool,
     delta_t: f32,
+    // physics steps per rendered frame, fractions step only every few frames
+    substeps: f32,
+    // speed relative to one step of delta_t per frame, see clock::pacing
+    time_scale: f32,
     g: f32,
+    force_law: ForceLaw,
+    softening: f32,
+    mond: bool,
+    mond_a0: f32,
+    post_newtonian: bool,
+    speed_of_light: f32,
+    compact_mass: f32,
+    radiation_enabled: bool,
+    luminosity: f32,
     show_tree: bool,
+    radius_law: RadiusLaw,
+    // mass per unit area or volume, depending on the law
+    density: f32,
+    // drawn radius over collision radius
+    render_scale: f32,
+    size_clamp: bool,
+    min_pixels: f32,
+    max_pixels: f32,
     // needs simulation reset
     min_body_mass: f32,
     max_body_mass: f32,
@@ -22,57 +282,443 @@ pub struct SimulationSettings {
     spawn_area: RangeInclusive<f32>,
     z: f32,
     theta: f32,
+    leaf_capacity: u32,
+    // build the next frame's tree while this one renders
+    pipelined_tree: bool,
+    // start each body's tree walk from the nodes it ended at last step
+    warm_start: bool,
+    // forces from a walk over pairs of nodes instead of one walk per body
+    dual_tree: bool,
+    // one walk per leaf, shared by the bodies in it
+    bucket_walks: bool,
     init_vel: f32,
     donut: bool,
+    deterministic: bool,
+    seed: u64,
     elasticity: f32,
     collision_enabled: bool,
+    star_formation: bool,
+    star_members: u32,
+    star_radius: f32,
+    n_tracers: u32,
+    tracer_ring: bool,
+    trace_enabled: bool,
+    trace_path: String,
+    export_enabled: bool,
+    export_dir: String,
+    export_bodies: u32,
+    // final state and run summary written when the app is closed
+    dump_on_exit: bool,
+    dump_dir: String,
+    net_mode: NetMode,
+    net_addr: String,
+    show_orbit_zones: bool,
+    render_fraction: f32,
+    decimation: Decimation,
+    memory_budget_mb: f32,
+    log_verbosity: LogVerbosity,
+    json_log: bool,
+    json_log_path: String,
+    nan_action: NanAction,
+    nan_auto_pause: bool,
+    colormap: Colormap,
+    color_mode: ColorMode,
+    show_overlay: bool,
+    show_minimap: bool,
+    annotation: String,
+    stop_at_time: bool,
+    stop_time: f64,
+    ghost_theta: f32,
+    units: UnitSystem,
 }
 
 impl Default for SimulationSettings {
     fn default() -> Self {
         SimulationSettings {
+            paused: false,
             delta_t: 0.001,
-            g: 1.0,
+            substeps: 1.0,
+            time_scale: 1.0,
+            // the pull falls off as 1/r², so G has to be fairly large at this scale
+            g: 300.0,
+            force_law: ForceLaw::InverseSquare,
+            softening: 5.0,
+            mond: false,
+            mond_a0: 20.0,
+            post_newtonian: false,
+            speed_of_light: 2000.0,
+            compact_mass: 500.0,
+            radiation_enabled: false,
+            luminosity: 1000000.0,
             show_tree: false,
+            radius_law: RadiusLaw::Area,
+            density: 10.0,
+            render_scale: 1.0,
+            size_clamp: false,
+            min_pixels: 1.5,
+            max_pixels: 40.0,
             min_body_mass: 10.0,
             max_body_mass: 100.0,
             n_bodies: 1500,
             spawn_area: -300.0..=300.0,
             z: 10.0,
             theta: 0.5,
+            leaf_capacity: 16,
+            pipelined_tree: true,
+            warm_start: false,
+            dual_tree: false,
+            bucket_walks: false,
             init_vel: 50.0,
             donut: false,
+            deterministic: false,
+            seed: 0,
             elasticity: 1.0, 
             collision_enabled: false,
+            star_formation: false,
+            star_members: 8,
+            star_radius: 6.0,
+            n_tracers: 400,
+            tracer_ring: false,
+            trace_enabled: false,
+            trace_path: "trace.csv".to_string(),
+            export_enabled: false,
+            export_dir: "export".to_string(),
+            export_bodies: 2048,
+            dump_on_exit: false,
+            dump_dir: "final_state".to_string(),
+            net_mode: NetMode::Off,
+            net_addr: "127.0.0.1:7777".to_string(),
+            show_orbit_zones: false,
+            render_fraction: 1.0,
+            decimation: Decimation::Random,
+            memory_budget_mb: 2048.0,
+            log_verbosity: LogVerbosity::Info,
+            json_log: false,
+            json_log_path: "log.json".to_string(),
+            nan_action: NanAction::Despawn,
+            nan_auto_pause: false,
+            colormap: Colormap::default(),
+            color_mode: ColorMode::Mass,
+            show_overlay: true,
+            show_minimap: false,
+            annotation: String::new(),
+            stop_at_time: false,
+            stop_time: 10.0,
+            ghost_theta: 1.0,
+            units: UnitSystem::Simulation,
         }
     }
 }
 
-#[derive(Component)]
-pub struct Velocity(Vec3);
+impl SimulationSettings {
+    // Substeps per frame and the dt each one uses, delta_t being the stable value
+    fn pacing(&self) -> (f32, f32) {
+        pacing(self.substeps, self.time_scale, self.delta_t)
+    }
+
+    // Simulated time that passes per wall clock second at the given frame time
+    fn simulated_per_second(&self, frame_secs: f32) -> f32 {
+        if frame_secs <= 0.0 {
+            return 0.0;
+        }
+        let (substeps, dt) = self.pacing();
+        substeps * dt / frame_secs
+    }
 
-#[derive(Component, Clone, Copy)]
-pub struct Body {
-    mass: f32,
-    radius: f32,
-    hue: f32,
+    // Radius used for collisions, mergers and everything else physical
+    fn collision_radius(&self, mass: f32) -> f32 {
+        radius_for_mass(mass, self.radius_law, self.density, self.deterministic)
+    }
+
+    // Radius the body is drawn with, so small bodies can be made visible without making them collide more
+    fn render_radius(&self, collision_radius: f32) -> f32 {
+        collision_radius * self.render_scale
+    }
+
+    fn force_params(&self) -> ForceParams {
+        ForceParams {
+            g: self.g,
+            law: self.force_law,
+            softening: self.softening,
+            mond: self.mond,
+            a0: self.mond_a0,
+            post_newtonian: self.post_newtonian,
+            c: self.speed_of_light,
+            compact_mass: self.compact_mass,
+            deterministic: self.deterministic,
+        }
+    }
 }
 
+#[derive(Component)]
+pub struct Velocity(Vec3);
+
 #[derive(Event)]
 struct ResetEvent;
 
 fn main() {
+    // Headless parameter sweep, no window
+    let args: Vec<String> = std::env::args().collect();
+    if args.get(1).is_some_and(|a| a == "--sweep") {
+        if let Err(err) = sweep::run(&args[2..]) {
+            eprintln!("{}", err);
+            std::process::exit(1);
+        }
+        return;
+    }
+    // Compares two exported runs, no window either
+    if args.get(1).is_some_and(|a| a == "--compare") {
+        if let Err(err) = compare::run(&args[2..]) {
+            eprintln!("{}", err);
+            std::process::exit(1);
+        }
+        return;
+    }
+
+    // Survival times of perturbed runs, for stability maps
+    if args.get(1).is_some_and(|a| a == "--stability") {
+        if let Err(err) = stability::run(&args[2..]) {
+            eprintln!("{}", err);
+            std::process::exit(1);
+        }
+        return;
+    }
+
+    // Characters in the terminal instead of a window, for servers without a display
+    if args.get(1).is_some_and(|a| a == "--tui") {
+        #[cfg(feature = "tui")]
+        let result = tui::run(&args[2..]);
+        #[cfg(not(feature = "tui"))]
+        let result: Result<(), String> = Err("built without the terminal view, rebuild with --features tui".to_string());
+        if let Err(err) = result {
+            eprintln!("{}", err);
+            std::process::exit(1);
+        }
+        return;
+    }
+
+    // the task pools can't be resized once the app is running
+    let config = UiConfig::default();
+    let task_pools = task_pool_plugin(config.compute_threads, config.reserve_core);
+    let plugins = DefaultPlugins.set(task_pools);
+    // bevy's own console output can't follow the verbosity setting, so off the web the log is set up
+    // by init_logging instead. In the browser the plugin's console is kept.
+    #[cfg(not(target_arch = "wasm32"))]
+    let plugins = {
+        logging::init_logging();
+        plugins.disable::<LogPlugin>()
+    };
+
     App::new()
         .insert_resource(ClearColor(Color::BLACK))
+        .insert_resource(config)
         .insert_resource(SimulationSettings::default())
-        .add_plugins(DefaultPlugins)
+        .init_resource::<DragSelect>()
+        .init_resource::<GroupTools>()
+        .init_resource::<ActiveTool>()
+        .init_resource::<Slingshot>()
+        .init_resource::<Satellite>()
+        .init_resource::<Explosion>()
+        .init_resource::<SystemFactory>()
+        .init_resource::<ColorScale>()
+        .init_resource::<Automation>()
+        .init_resource::<Export>()
+        .init_resource::<NetSession>()
+        .init_resource::<MergerChirp>()
+        .init_resource::<Sonification>()
+        .init_resource::<Kiosk>()
+        .init_resource::<GroupAnalysis>()
+        .init_resource::<LagrangianRadii>()
+        .init_resource::<VirialMonitor>()
+        .init_resource::<PotentialContours>()
+        .init_resource::<RocheOverflow>()
+        .init_resource::<KirkwoodHistogram>()
+        .init_resource::<PoincareSection>()
+        .init_resource::<LyapunovEstimator>()
+        .init_resource::<Lifetimes>()
+        .init_resource::<BackgroundFlow>()
+        .init_resource::<PictureInPicture>()
+        .init_resource::<Leaderboard>()
+        .init_resource::<CollisionLog>()
+        .init_resource::<ImpactFlash>()
+        .init_resource::<Trails>()
+        .init_resource::<SettingsWarnings>()
+        .init_resource::<PendingSettings>()
+        .init_resource::<UndoHistory>()
+        .init_resource::<Bookmarks>()
+        .init_resource::<Branch>()
+        .init_resource::<Director>()
+        .init_resource::<Benchmark>()
+        .init_resource::<Timeline>()
+        .init_resource::<GroupFrames>()
+        .init_resource::<ControllerTools>()
+        .init_resource::<TransferPlanner>()
+        .init_resource::<ChoreographyLibrary>()
+        .add_plugins(plugins)
+        .add_plugins(PhysicsPlugin)
         .add_plugins(EguiPlugin {
             enable_multipass_for_primary_context: true,
         })
+        .add_audio_source::<Tone>()
+        // examples of forces added from outside the integrator, off until switched on in Custom Forces
+        .add_force_provider(Drag::default(), false)
+        .add_force_provider(CentralPotential::default(), false)
         .add_event::<ResetEvent>()
-        .add_systems(EguiContextPass, ui_window)
-        .add_systems(Startup, (spawn_camera, add_bodies))
-        .add_systems(Update, (collision, reset_handler, update))
+        .add_event::<SpawnTracersEvent>()
+        .add_event::<GhostEvent>()
+        .add_event::<SpawnSystemEvent>()
+        .add_systems(
+            EguiContextPass,
+            (
+                ui_window,
+                inspector_window,
+                overlay,
+                minimap,
+                automation_window,
+                help_window,
+                factory_window,
+                kiosk_window,
+                groups_window,
+                lagrangian_window,
+                virial_window,
+                kirkwood_window,
+                poincare_window,
+                lyapunov_window,
+                flow_window,
+                pip_window,
+                leaderboard_window,
+                collision_log_window,
+                trails_window,
+                apply_ui_config
+                    .after(ui_window)
+                    .after(inspector_window)
+                    .after(automation_window)
+                    .after(help_window)
+                    .after(factory_window)
+                    .after(kiosk_window)
+                    .after(groups_window)
+                    .after(lagrangian_window)
+                    .after(kirkwood_window)
+                    .after(poincare_window)
+                    .after(lyapunov_window)
+                    .after(flow_window)
+                    .after(pip_window)
+                    .after(leaderboard_window)
+                    .after(collision_log_window)
+                    .after(trails_window),
+            ),
+        )
+        .add_systems(
+            EguiContextPass,
+            (
+                bookmarks_window,
+                branch_window,
+                director_window,
+                benchmark_window,
+                physics_thread_window,
+                timeline_window,
+                tags_window,
+                providers_window,
+                controllers_window,
+                transfer_window,
+                choreography_window,
+            )
+                .before(apply_ui_config),
+        )
+        // before anything reads the settings changed in the last frame's UI
+        .add_systems(PreUpdate, validate_settings)
+        .add_systems(Startup, (spawn_camera, add_bodies, start_chirp, start_sonification))
+        .add_systems(
+            Update,
+            (
+                net_host.after(update),
+                net_viewer,
+                draw_orbit_zones,
+                satellite_spawner,
+                draw_satellite,
+                system_handler,
+                render_decimation,
+                memory_budget,
+                suggest_dt.after(update),
+                explosion_input.before(update),
+                draw_explosion,
+                merger_chirp.after(update),
+                sonify.after(collision),
+                form_stars.after(update).after(collision),
+                age_bodies.after(update),
+                apply_flow.after(update),
+                draw_flow,
+                gamepad_input.after(attractor_input).before(update),
+                kiosk.after(gamepad_input),
+                update_pip.after(update),
+            ),
+        )
+        // Analysis of the simulated state and its overlays
+        .add_systems(
+            Update,
+            (
+                analyze_groups.after(update).before(recolor_bodies),
+                track_lagrangian.after(update).before(trace_step),
+                draw_lagrangian,
+                virial_monitor.after(update),
+                update_contours.after(update),
+                draw_contours.after(update_contours),
+                roche_overflow.after(update),
+                draw_roche,
+                kirkwood_histogram.after(update),
+                record_crossings.after(update),
+                track_lyapunov.after(update),
+                rank_bodies.after(update),
+                draw_leaders.after(rank_bodies),
+                follow_leader.after(update),
+                log_collisions.after(collision),
+                spawn_flashes.after(collision),
+                update_flashes.after(spawn_flashes),
+                direct_camera.after(update).after(kiosk),
+                update_benchmark,
+            ),
+        )
+        .add_systems(
+            Update,
+            (
+                checkpoint_reset.before(reset_handler),
+                undo_input,
+                apply_undo.after(undo_input).after(reset_handler).before(update),
+                update_bookmarks.after(apply_undo).before(update),
+                update_branch.after(update_bookmarks).after(update),
+                run_timeline.after(update),
+                tag_spawned,
+                hide_groups.before(render_decimation),
+                track_group_frames.after(update),
+            ),
+        )
+        // How the bodies are drawn
+        .add_systems(Update, (update_trails.after(update), clamp_render_size.after(fade_system), draw_transfer))
+        .add_systems(First, track_frame_time)
+        .add_systems(PreUpdate, rebase_origin)
+        .add_systems(Last, dump_on_exit)
+        .add_systems(
+            Update,
+            (
+                reset_handler,
+                tracer_handler,
+                ghost_handler,
+                ghost_update,
+                select_body,
+                draw_breakdown,
+                draw_drag_rect,
+                attractor_input.before(update),
+                draw_attractor,
+                slingshot_spawner,
+                draw_slingshot,
+                trace_step.after(update),
+                export_step.after(update),
+                check_automation.after(update),
+                apply_log_settings,
+                fade_system,
+                recolor_bodies,
+            ),
+        )
         .run();
 }
 
@@ -80,63 +726,402 @@ fn ui_window(
     mut contexts: EguiContexts,
     mut settings: ResMut<SimulationSettings>,
     mut reset_writer: EventWriter<ResetEvent>,
+    mut tracer_writer: EventWriter<SpawnTracersEvent>,
+    mut ghost_writer: EventWriter<GhostEvent>,
+    mut tool: ResMut<ActiveTool>,
+    mut slingshot: ResMut<Slingshot>,
+    mut satellite: ResMut<Satellite>,
+    mut explosion: ResMut<Explosion>,
+    mut audio: (ResMut<MergerChirp>, ResMut<Sonification>),
+    nan_guard: Res<NanGuard>,
+    clocks: (Res<SimulationClock>, Res<Time>),
+    color_scale: Res<ColorScale>,
+    mut ui_config: ResMut<UiConfig>,
+    mut contours: ResMut<PotentialContours>,
+    (suggested_dt, mut warnings, mut history, mut pending, mut tree_export, mut origin): (
+        Res<SuggestedDt>,
+        ResMut<SettingsWarnings>,
+        ResMut<UndoHistory>,
+        ResMut<PendingSettings>,
+        ResMut<TreeExport>,
+        ResMut<WorldOrigin>,
+    ),
 ) {
-    egui::Window::new("Settings").show(contexts.ctx_mut(), |ui| {
-        ui.add(egui::Slider::new(&mut settings.g, 0.0..=10.0).text("Gravity constant"));
-        ui.add(egui::Slider::new(&mut settings.delta_t, 0.00000001..=0.01).text("Delta T"));
+    let (clock, time) = (&clocks.0, &clocks.1);
+    let window = ui_config.place(egui::Window::new("Settings"), "Settings");
+    window.show(contexts.ctx_mut(), |ui| {
+        ui.horizontal(|ui| {
+            ui.add(egui::Slider::new(&mut ui_config.scale, 0.5..=4.0).text("UI Scale"));
+            ui.add(egui::Checkbox::new(&mut ui_config.dark, "Dark Theme"));
+        });
+        if !warnings.messages.is_empty() {
+            for message in &warnings.messages {
+                ui.colored_label(egui::Color32::YELLOW, message);
+            }
+            if ui.button("Dismiss").clicked() {
+                warnings.messages.clear();
+            }
+        }
+        ui.add(egui::Checkbox::new(&mut settings.paused, "Pause"));
+        ui.label(format!("Simulated time: {:.4} ({} steps)", clock.time, clock.steps));
+        if settings.units.km_per_s().is_some() {
+            ui.label(format!("= {}", settings.units.time(clock.time)));
+        }
+        ui.horizontal(|ui| {
+            ui.label("Mouse tool:");
+            ui.radio_value(&mut *tool, ActiveTool::Select, "Select");
+            ui.radio_value(&mut *tool, ActiveTool::Attractor, "Attractor");
+            ui.radio_value(&mut *tool, ActiveTool::Slingshot, "Slingshot");
+            ui.radio_value(&mut *tool, ActiveTool::Satellite, "Satellite");
+            ui.radio_value(&mut *tool, ActiveTool::Explosion, "Explosion");
+        });
+        if *tool == ActiveTool::Slingshot {
+            ui.add(egui::Slider::new(&mut slingshot.rate, 1.0..=100.0).text("Launch Rate"));
+            ui.add(egui::Slider::new(&mut slingshot.speed, 10.0..=2000.0).text("Launch Speed"));
+            ui.add(egui::Slider::new(&mut slingshot.mass, 1.0..=500.0).text("Launch Mass"));
+        }
+        if *tool == ActiveTool::Satellite {
+            ui.label("Select a primary first, then click where periapsis should be");
+            ui.add(egui::Slider::new(&mut satellite.semi_major_axis, 5.0..=2000.0).logarithmic(true).text("Semi-major Axis"));
+            ui.add(egui::Slider::new(&mut satellite.eccentricity, 0.0..=0.99).text("Eccentricity"));
+            ui.add(egui::Slider::new(&mut satellite.mass, 0.1..=500.0).logarithmic(true).text("Satellite Mass"));
+            ui.add(egui::Checkbox::new(&mut satellite.retrograde, "Retrograde"));
+        }
+        if *tool == ActiveTool::Explosion {
+            ui.add(egui::Slider::new(&mut explosion.strength, 10.0..=1000000.0).logarithmic(true).text("Explosion Strength"));
+            ui.add(egui::Slider::new(&mut explosion.radius, 5.0..=2000.0).logarithmic(true).text("Explosion Radius"));
+            egui::ComboBox::from_label("Falloff")
+                .selected_text(format!("{:?}", explosion.falloff))
+                .show_ui(ui, |ui| {
+                    for falloff in Falloff::ALL {
+                        ui.selectable_value(&mut explosion.falloff, falloff, format!("{:?}", falloff));
+                    }
+                });
+        }
+        ui.add(egui::Slider::new(&mut settings.g, 0.0..=1000.0).text("Gravity constant"));
+        let units = settings.units;
+        egui::ComboBox::from_label("Units")
+            .selected_text(units.label())
+            .show_ui(ui, |ui| {
+                for system in UnitSystem::ALL {
+                    ui.selectable_value(&mut settings.units, system, system.label());
+                }
+            });
+        if settings.units != units
+            && let Some(g) = settings.units.g()
+        {
+            settings.g = g as f32;
+        }
+        egui::ComboBox::from_label("Force Law")
+            .selected_text(settings.force_law.label())
+            .show_ui(ui, |ui| {
+                for law in ForceLaw::ALL {
+                    ui.selectable_value(&mut settings.force_law, law, law.label());
+                }
+            });
+        if settings.force_law == ForceLaw::Plummer {
+            ui.add(egui::Slider::new(&mut settings.softening, 0.1..=50.0).text("Softening Length"));
+        }
+        ui.add(egui::Checkbox::new(&mut settings.mond, "Modified Gravity (MOND)"));
+        if settings.mond {
+            ui.add(egui::Slider::new(&mut settings.mond_a0, 0.1..=1000.0).logarithmic(true).text("a0"));
+        }
+        ui.add(egui::Checkbox::new(&mut settings.post_newtonian, "Post-Newtonian Correction"));
+        if settings.post_newtonian {
+            ui.add(egui::Slider::new(&mut settings.speed_of_light, 100.0..=100000.0).logarithmic(true).text("Speed of Light"));
+            ui.add(egui::Slider::new(&mut settings.compact_mass, 1.0..=100000.0).logarithmic(true).text("Compact Object Mass"));
+        }
+        ui.add(egui::Checkbox::new(&mut settings.radiation_enabled, "Radiation Pressure"));
+        if settings.radiation_enabled {
+            ui.add(egui::Slider::new(&mut settings.luminosity, 1000.0..=100000000.0).logarithmic(true).text("Star Luminosity"));
+        }
+        ui.horizontal(|ui| {
+            ui.add(egui::Slider::new(&mut settings.delta_t, 0.00000001..=0.01).text("Delta T"));
+            if let Some(dt) = suggested_dt.dt {
+                ui.label(format!("suggested {:.2e}", dt));
+                if ui.button("Use").clicked() {
+                    settings.delta_t = dt.clamp(0.00000001, 0.01);
+                }
+            }
+        });
+        ui.add(egui::Slider::new(&mut settings.substeps, 0.1..=MAX_SUBSTEPS).logarithmic(true).text("Substeps Per Frame"));
+        ui.horizontal(|ui| {
+            ui.add(egui::Slider::new(&mut settings.time_scale, 0.1..=100.0).logarithmic(true).suffix("×").text("Time Scale"));
+            if ui.button("1×").clicked() {
+                settings.time_scale = 1.0;
+            }
+        });
+        let (_substeps, step_dt) = settings.pacing();
+        if step_dt > settings.delta_t {
+            ui.colored_label(
+                egui::Color32::YELLOW,
+                format!(
+                    "Too fast for {} substeps, steps use dt = {:.2e} instead of the stable {:.2e}",
+                    MAX_SUBSTEPS, step_dt, settings.delta_t
+                ),
+            );
+        }
+        ui.label(format!(
+            "Simulated time per second: {:.4}",
+            settings.simulated_per_second(time.delta_secs())
+        ));
         ui.add(egui::Slider::new(&mut settings.theta, 0.1..=1.0).text("BH Theta"));
+        ui.add(egui::Slider::new(&mut settings.leaf_capacity, 1..=64).text("Bodies Per Leaf"));
+        ui.add(egui::Checkbox::new(&mut settings.pipelined_tree, "Build Tree In Background"));
+        ui.add(egui::Checkbox::new(&mut settings.warm_start, "Reuse Last Step's Tree Nodes"));
+        ui.add(egui::Checkbox::new(&mut settings.dual_tree, "Dual Tree Forces"));
+        ui.add(egui::Checkbox::new(&mut settings.bucket_walks, "Share Walks Within Leaves"));
         ui.add(egui::Checkbox::new(
             &mut settings.show_tree,
             "Draw Quadtree",
         ));
+        ui.horizontal(|ui| {
+            ui.add(egui::TextEdit::singleline(&mut tree_export.path).desired_width(120.0));
+            if ui.button("Export Quadtree").clicked() {
+                tree_export.requested = true;
+            }
+        });
+        ui.horizontal(|ui| {
+            ui.add(egui::Checkbox::new(&mut origin.enabled, "Rebase World Origin"));
+            ui.add(egui::DragValue::new(&mut origin.threshold).range(100.0..=1e6).prefix("Beyond: "));
+        });
+        if origin.rebases > 0 {
+            ui.label(format!(
+                "Origin at ({:.0}, {:.0}) of the starting coordinates, moved {} times",
+                origin.offset.x, origin.offset.y, origin.rebases
+            ));
+        }
+        ui.add(egui::Checkbox::new(&mut contours.enabled, "Draw Potential Contours"));
+        if contours.enabled {
+            ui.add(egui::Slider::new(&mut contours.levels, 2..=40).text("Contour Levels"));
+            ui.add(egui::Slider::new(&mut contours.resolution, 8..=128).text("Contour Grid"));
+            ui.add(egui::Slider::new(&mut contours.every_frames, 1..=120).text("Update Every N Frames"));
+        }
         ui.add(egui::Checkbox::new(&mut settings.collision_enabled, "Enable Collision"));
+        ui.add(egui::Checkbox::new(&mut settings.star_formation, "Form Stars From Dense Clumps"));
+        if settings.star_formation {
+            ui.add(egui::Slider::new(&mut settings.star_members, 2..=100).text("Bodies Per Star"));
+            ui.add(egui::Slider::new(&mut settings.star_radius, 0.5..=100.0).logarithmic(true).text("Clump Radius"));
+        }
+        egui::ComboBox::from_label("Color By")
+            .selected_text(settings.color_mode.label())
+            .show_ui(ui, |ui| {
+                for mode in ColorMode::ALL {
+                    ui.selectable_value(&mut settings.color_mode, mode, mode.label());
+                }
+            });
+        egui::ComboBox::from_label("Palette")
+            .selected_text(format!("{:?}", settings.colormap))
+            .show_ui(ui, |ui| {
+                for colormap in Colormap::ALL {
+                    ui.selectable_value(&mut settings.colormap, colormap, format!("{:?}", colormap));
+                }
+            });
+        legend(ui, &settings, &color_scale);
+        ui.horizontal(|ui| {
+            ui.add(egui::Checkbox::new(&mut settings.size_clamp, "Keep Size On Screen Between"));
+            ui.add_enabled(settings.size_clamp, egui::DragValue::new(&mut settings.min_pixels).range(0.5..=100.0));
+            ui.add_enabled(settings.size_clamp, egui::DragValue::new(&mut settings.max_pixels).range(0.5..=500.0));
+            ui.label("pixels");
+        });
+
         ui.add(egui::Slider::new(&mut settings.elasticity, 0.0..=1.0).text("Elasticity"));
 
+        ui.horizontal(|ui| {
+            ui.add(egui::Slider::new(&mut settings.render_fraction, 0.001..=1.0).logarithmic(true).text("Drawn"));
+            egui::ComboBox::from_id_salt("decimation")
+                .selected_text(format!("{:?}", settings.decimation))
+                .show_ui(ui, |ui| {
+                    for mode in Decimation::ALL {
+                        ui.selectable_value(&mut settings.decimation, mode, format!("{:?}", mode));
+                    }
+                });
+        });
+
         ui.add(egui::Label::new("Reset Sim after tweaking these:"));
-        ui.add(egui::Slider::new(&mut settings.n_bodies, 2..=50000).text("Num Bodies"));
-        ui.add(egui::Slider::new(&mut settings.min_body_mass, 1.0..=5000.0).text("Min Body Mass"));
-        ui.add(egui::Slider::new(&mut settings.max_body_mass, 1.0..=5000.0).text("Max Body Mass"));
-        ui.add(egui::Checkbox::new(&mut settings.donut, "Donut Start"));
+        // edits here are staged, the bodies on screen keep the settings they were made with
+        let mut spawn = pending.editable(&settings);
+        ui.add(egui::Slider::new(&mut spawn.n_bodies, 2..=500000).logarithmic(true).text("Num Bodies"));
+        ui.add(egui::Slider::new(&mut spawn.min_body_mass, 1.0..=5000.0).text("Min Body Mass"));
+        ui.add(egui::Slider::new(&mut spawn.max_body_mass, 1.0..=5000.0).text("Max Body Mass"));
+        egui::ComboBox::from_label("Body Size")
+            .selected_text(spawn.radius_law.label())
+            .show_ui(ui, |ui| {
+                for law in RadiusLaw::ALL {
+                    ui.selectable_value(&mut spawn.radius_law, law, law.label());
+                }
+            });
+        ui.add(egui::Slider::new(&mut spawn.density, 0.01..=100.0).logarithmic(true).text("Density"));
+        ui.add(egui::Slider::new(&mut spawn.render_scale, 0.1..=10.0).logarithmic(true).text("Drawn Radius x"));
+        ui.add(egui::Checkbox::new(&mut spawn.donut, "Donut Start"));
         ui.add(
-            egui::Slider::new(&mut settings.init_vel, 0.0..=1000.0)
+            egui::Slider::new(&mut spawn.init_vel, 0.0..=1000.0)
                 .text("Initial Velocity (Only Donut)"),
         );
-        if ui.button("Reset").clicked() {
-            reset_writer.write(ResetEvent);
+        ui.horizontal(|ui| {
+            ui.add(egui::Checkbox::new(&mut spawn.deterministic, "Deterministic, Seed"));
+            ui.add_enabled(spawn.deterministic, egui::DragValue::new(&mut spawn.seed));
+        });
+        pending.stage(spawn, &settings);
+        let changed = pending.changed_fields(&settings);
+        if !changed.is_empty() {
+            ui.horizontal(|ui| {
+                ui.colored_label(egui::Color32::YELLOW, format!("Pending until reset: {}", changed.join(", ")));
+                if ui.button("Revert").clicked() {
+                    pending.revert();
+                }
+            });
+        }
+        ui.horizontal(|ui| {
+            if ui.button("Reset").clicked() {
+                reset_writer.write(ResetEvent);
+                ghost_writer.write(GhostEvent::Clear);
+            }
+            let undo = history.undo_label();
+            if ui.add_enabled(undo.is_some(), egui::Button::new(format!("Undo {}", undo.unwrap_or("")))).clicked() {
+                history.pending = Some(UndoStep::Undo);
+            }
+            let redo = history.redo_label();
+            if ui.add_enabled(redo.is_some(), egui::Button::new(format!("Redo {}", redo.unwrap_or("")))).clicked() {
+                history.pending = Some(UndoStep::Redo);
+            }
+        });
+
+        ui.add(egui::Label::new("Massless tracers:"));
+        ui.add(egui::Slider::new(&mut settings.n_tracers, 1..=5000).text("Num Tracers"));
+        ui.add(egui::Checkbox::new(&mut settings.tracer_ring, "Ring Layout"));
+        if ui.button("Spawn Tracers").clicked() {
+            tracer_writer.write(SpawnTracersEvent);
+        }
+
+        ui.add(egui::Label::new("A/B ghost copy, simulated with its own theta:"));
+        ui.add(egui::Slider::new(&mut settings.ghost_theta, 0.1..=1.0).text("Ghost Theta"));
+        ui.horizontal(|ui| {
+            if ui.button("Spawn Ghosts").clicked() {
+                ghost_writer.write(GhostEvent::Spawn);
+            }
+            if ui.button("Clear Ghosts").clicked() {
+                ghost_writer.write(GhostEvent::Clear);
+            }
+        });
+
+        ui.add(egui::Label::new("Step trace (selected body gets full detail):"));
+        ui.add(egui::TextEdit::singleline(&mut settings.trace_path));
+        ui.add(egui::Checkbox::new(&mut settings.trace_enabled, "Write Trace"));
+        ui.add(egui::Label::new("Training data export (one .npy per step):"));
+        ui.add(egui::TextEdit::singleline(&mut settings.export_dir));
+        ui.add(egui::Slider::new(&mut settings.export_bodies, 1..=10000).text("Bodies Per Frame"));
+        ui.add(egui::Checkbox::new(&mut settings.export_enabled, "Export Frames"));
+        ui.add(egui::Label::new("Final state and run summary, written on exit:"));
+        ui.add(egui::TextEdit::singleline(&mut settings.dump_dir));
+        ui.add(egui::Checkbox::new(&mut settings.dump_on_exit, "Save State On Exit"));
+
+        ui.add(egui::Label::new("Shared session (host: viewer address, viewer: address to listen on):"));
+        ui.horizontal(|ui| {
+            for mode in NetMode::ALL {
+                ui.radio_value(&mut settings.net_mode, mode, format!("{:?}", mode));
+            }
+        });
+        ui.add(egui::TextEdit::singleline(&mut settings.net_addr));
+
+        egui::ComboBox::from_label("Log Verbosity")
+            .selected_text(format!("{:?}", settings.log_verbosity))
+            .show_ui(ui, |ui| {
+                for verbosity in LogVerbosity::ALL {
+                    ui.selectable_value(&mut settings.log_verbosity, verbosity, format!("{:?}", verbosity));
+                }
+            });
+        ui.add(egui::TextEdit::singleline(&mut settings.json_log_path));
+        ui.add(egui::Checkbox::new(&mut settings.json_log, "Write JSON Log"));
+
+        let (chirp, sonification) = (&mut audio.0, &mut audio.1);
+        ui.horizontal(|ui| {
+            ui.add(egui::Checkbox::new(&mut chirp.enabled, "Merger Chirp, bodies from mass"));
+            ui.add_enabled(chirp.enabled, egui::DragValue::new(&mut chirp.min_mass).range(1.0..=100000.0));
+        });
+        if chirp.pair.is_some() {
+            ui.label(format!("Listening to a pair at {:.3} orbits per time unit", chirp.frequency));
         }
+        ui.add(egui::Checkbox::new(&mut sonification.enabled, "Sonify Kinetic Energy and Collisions"));
+        if sonification.enabled {
+            ui.label(format!("Collisions per second: {:.1}", sonification.collision_rate));
+        }
+
+        ui.add(egui::Checkbox::new(&mut settings.show_overlay, "Show Overlay"));
+        ui.add(egui::Checkbox::new(&mut settings.show_minimap, "Show Minimap"));
+        ui.add(egui::TextEdit::multiline(&mut settings.annotation).hint_text("Overlay annotation"));
+
+        ui.add(egui::Label::new(format!("NaN bodies caught: {}", nan_guard.caught)));
+        ui.horizontal(|ui| {
+            ui.radio_value(&mut settings.nan_action, NanAction::Despawn, "Despawn");
+            ui.radio_value(&mut settings.nan_action, NanAction::Freeze, "Freeze");
+        });
+        ui.add(egui::Checkbox::new(&mut settings.nan_auto_pause, "Pause on NaN"));
+
+        ui.add(
+            egui::Slider::new(&mut settings.memory_budget_mb, 64.0..=65536.0)
+                .logarithmic(true)
+                .text("Memory Budget (MB)"),
+        );
+
+        let cores = available_parallelism();
+        ui.add(
+            egui::Slider::new(&mut ui_config.compute_threads, 0..=cores)
+                .text("Compute Threads")
+                .custom_formatter(|n, _| if n == 0.0 { "Auto".to_string() } else { n.to_string() }),
+        );
+        ui.add(egui::Checkbox::new(&mut ui_config.reserve_core, "Reserve A Core For Rendering"));
+        ui.label(format!(
+            "Using {} compute threads on {} cores, changes apply on the next start",
+            ComputeTaskPool::get().thread_num(),
+            cores
+        ));
     });
 }
 
 fn reset_handler(
     query: Query<Entity, With<Body>>,
-    reset_event: EventReader<ResetEvent>,
+    mut reset_event: EventReader<ResetEvent>,
     mut commands: Commands,
     materials: ResMut<Assets<ColorMaterial>>,
     meshes: ResMut<Assets<Mesh>>,
-    settings: Res<SimulationSettings>,
+    mut settings: ResMut<SimulationSettings>,
+    mut pending: ResMut<PendingSettings>,
+    mut nan_guard: ResMut<NanGuard>,
+    mut clock: ResMut<SimulationClock>,
+    mut energy: ResMut<Energy>,
+    mut automation: ResMut<Automation>,
+    mut warnings: ResMut<SettingsWarnings>,
 ) {
     if reset_event.is_empty() {
         return;
     }
+    reset_event.clear();
+    // every reset, from the button, the kiosk or a timeline script, takes all the staged edits at once.
+    // The bodies are made from them right away, before validate_settings has seen them.
+    pending.apply(&mut settings);
+    warnings.record(validate(&mut settings));
+    nan_guard.caught = 0;
+    *clock = SimulationClock::default();
+    energy.reset();
+    automation.triggered = None;
 
     for entity in &query {
-        commands.entity(entity).despawn();
+        despawn_with_fade(&mut commands, entity);
     }
 
-    add_bodies(commands, materials, meshes, settings);
+    add_bodies(commands, materials, meshes, settings.into());
 }
 
+// The camera the simulation is viewed and clicked through, as opposed to insets like the picture-in-picture
+#[derive(Component)]
+struct MainCamera;
+
 fn spawn_camera(mut commands: Commands) {
-    commands.spawn(Camera2d::default());
+    // also sees the trail image, which the other cameras leave out
+    commands.spawn((Camera2d, RenderLayers::from_layers(&[0, DISPLAY_LAYER]), MainCamera));
 }
 
-fn mass_to_radius(m: f32) -> f32 {
-    // Radius determination: r=sqrt(G * Mass/accel)
-    let g = 1.0; // constant for size
-    let a = 10.0; // initial accel
-    let res = g * m / a;
-    res.sqrt()
-}
 
 pub fn mass_to_hue(m: f32, min_mass: f32, max_mass: f32) -> f32 {
     // linear conversion
@@ -175,18 +1160,34 @@ fn add_bodies(
     mut meshes: ResMut<Assets<Mesh>>,
     settings: Res<SimulationSettings>,
 ) {
-    let norm_min = if settings.min_body_mass < settings.max_body_mass {
-        settings.min_body_mass
+    // A seeded StdRng gives the same bodies on every machine, the default rng is seeded from the OS
+    let bodies = if settings.deterministic {
+        generate_bodies(&settings, &mut StdRng::seed_from_u64(settings.seed))
     } else {
-        settings.max_body_mass
+        generate_bodies(&settings, &mut rand::rng())
     };
+    for (body, transform, velocity) in bodies {
+        spawn_body(
+            body,
+            transform,
+            velocity,
+            &settings,
+            &mut commands,
+            &mut materials,
+            &mut meshes,
+        );
+    }
+}
 
-    let mut rng = rand::rng();
+// Random initial conditions from the settings. Split out from add_bodies so a seeded rng
+// can be passed in when running without a window.
+fn generate_bodies(settings: &SimulationSettings, rng: &mut impl Rng) -> Vec<(Body, Transform, Velocity)> {
+    let mut bodies = Vec::with_capacity(settings.n_bodies as usize);
     for _ in 0..settings.n_bodies {
-        let rng_mass = rng.random_range(norm_min..=settings.max_body_mass);
+        let rng_mass = rng.random_range(settings.min_body_mass..=settings.max_body_mass);
         let body = Body {
             mass: rng_mass,
-            radius: mass_to_radius(rng_mass),
+            radius: settings.collision_radius(rng_mass),
             hue: mass_to_hue(rng_mass, settings.min_body_mass, settings.max_body_mass),
         };
 
@@ -198,7 +1199,8 @@ fn add_bodies(
             let y = rng.random_range(settings.spawn_area.clone());
             let rng_mag = rng.random_range(10.0..=200.0);
 
-            let dir = Vec2::new(x, y).normalize();
+            // a body right at the center has no direction of its own
+            let dir = Vec2::new(x, y).try_normalize().unwrap_or(Vec2::X);
             let rng_vec = dir * rng_mag;
 
             transform = Transform::from_xyz(rng_vec.x, rng_vec.y, settings.z);
@@ -213,52 +1215,159 @@ fn add_bodies(
             velocity = Velocity(Vec3::ZERO);
         }
 
-        spawn_body(
-            body,
-            transform,
-            velocity,
-            &mut commands,
-            &mut materials,
-            &mut meshes,
-        );
+        bodies.push((body, transform, velocity));
+    }
+    bodies
+}
+
+// The stepping of the bodies and what it needs, without any drawing or UI beyond gizmos,
+// so tests can run it in an app without a window
+pub struct PhysicsPlugin;
+
+impl Plugin for PhysicsPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<SimulationSettings>()
+            .init_resource::<SelectedBreakdown>()
+            .init_resource::<Attractor>()
+            .init_resource::<MemoryUsage>()
+            .init_resource::<NanGuard>()
+            .init_resource::<SimulationClock>()
+            .init_resource::<SuggestedDt>()
+            .init_resource::<Energy>()
+            .init_resource::<CollisionCount>()
+            .init_resource::<Performance>()
+            .init_resource::<PhysicsThread>()
+            .init_resource::<TagGroups>()
+            .init_resource::<ForceProviders>()
+            .init_resource::<TreeExport>()
+            .init_resource::<TraceLog>()
+            .init_resource::<WorldOrigin>()
+            .add_event::<CollisionEvent>()
+            .add_systems(
+                Update,
+                (
+                    (nan_guard.before(collision).before(update), collision, update).run_if(physics_on_main_thread),
+                    update_physics_thread.after(update),
+                ),
+            );
     }
 }
 
 fn update(
-    mut query: Query<(Entity, &mut Body, &mut Transform, &mut Velocity)>,
+    mut query: Query<(Entity, &mut Body, &mut Transform, &mut Velocity), Without<Quarantined>>,
+    tracers: Query<(), With<Massless>>,
+    selected: Query<Entity, With<Selected>>,
+    pinned: Query<(), With<Pinned>>,
+    stars: Query<(), With<Star>>,
+    mut work_errors: Query<&mut WorkError>,
+    mut selected_breakdown: ResMut<SelectedBreakdown>,
+    attractor: Res<Attractor>,
+    mut clock: ResMut<SimulationClock>,
+    mut energy: ResMut<Energy>,
+    (mut memory, mut performance): (ResMut<MemoryUsage>, ResMut<Performance>),
+    mut suggested_dt: ResMut<SuggestedDt>,
     settings: Res<SimulationSettings>,
+    (mut buffers, mut pipelined, mut warm): (Local<BodyBuffers>, Local<PipelinedTree>, Local<WarmStart>),
     gizmos: Gizmos,
+    (tags, tag_groups, providers, mut controllers, mut tree_export, mut trace, origin): (
+        Query<&GroupTag>,
+        Res<TagGroups>,
+        Res<ForceProviders>,
+        Query<&mut Controller>,
+        ResMut<TreeExport>,
+        ResMut<TraceLog>,
+        Res<WorldOrigin>,
+    ),
 ) {
-    let mut accel_map: HashMap<u32, Vec3> = HashMap::new();
     // let mut col_map: HashMap<u32, Vec3> = HashMap::new();
+    let started = Instant::now();
 
-    let positions: Vec<Vec2> = query
-        .iter()
-        .map(|(_e, _b, t, _v)| Vec2::new(t.translation.x, t.translation.y))
-        .collect();
-
-    let quad = Quad::new_containing(&positions);
-    // let quad = Quad::new(0.0, 0.0, 100000.0);
-    let mut tree = Quadtree::new(quad);
+    let tree_build_span = debug_span!("tree_build").entered();
 
-    for (entity1, body1, transform1, _velocity1) in query.iter() {
-        tree.insert(entity1, *transform1, *body1);
+    // One pass over the components, everything after this works on the buffers.
+    // Tracers are left out of the tree, they feel gravity but exert none.
+    buffers.clear();
+    for (entity1, body1, transform1, velocity1) in query.iter() {
+        buffers.push(entity1, transform1.translation, velocity1.0, body1.mass, !tracers.contains(entity1));
     }
 
+    // With some groups not pulling on others, every group gets a tree of its own and a body only
+    // feels the trees of the groups that act on it
+    let restricted = !tag_groups.all_interact();
+    let group_of: Vec<usize> = if restricted {
+        buffers.entities.iter().map(|entity| tag_groups.index(tags.get(*entity).ok())).collect()
+    } else {
+        Vec::new()
+    };
+
+    // Nothing is spawned or despawned inside this system, so the query keeps the order it was
+    // synced in and the i-th entity is the i-th slot in the buffers
+    let build_tree = |buffers: &BodyBuffers, group: Option<usize>| {
+        // let quad = Quad::new(0.0, 0.0, 100000.0);
+        let mut tree = Quadtree::with_leaf_capacity(buffers.bounds(), settings.leaf_capacity as usize);
+        for (i, (entity1, body1, transform1, _velocity1)) in query.iter().enumerate() {
+            if buffers.in_tree[i] && group.is_none_or(|group| group_of[i] == group) {
+                tree.insert(entity1, buffers.transform(i, transform1), *body1);
+            }
+        }
+        tree
+    };
+
+    // the tree started in the background at the end of the last frame, if nothing moved since
+    let pipelined_tree = if settings.pipelined_tree {
+        let inputs = buffers.tree_inputs(
+            query.iter().map(|(entity1, body1, transform1, _velocity1)| (entity1, transform1, body1)),
+        );
+        pipelined.take(&inputs, settings.leaf_capacity as usize)
+    } else {
+        None
+    };
+    let mut tree = pipelined_tree.unwrap_or_else(|| build_tree(&buffers, None));
+    tree_build_span.exit();
+    memory.tree = tree.memory_bytes();
+
     if settings.show_tree {
         tree.draw_tree(gizmos);
     }
+    export_tree(&mut tree_export, &tree, clock.time, settings.theta, settings.leaf_capacity);
 
-    for (entity1, body1, transform1, _velocity1) in query.iter_mut() {
-        let accel = tree.get_total_accel(
-            entity1,
-            *transform1,
-            *body1,
-            settings.g,
-            settings.delta_t,
-            settings.theta,
-        );
-        accel_map.insert(entity1.index(), accel);
+    // Nothing moves while paused, so there is no need for the forces either
+    if !settings.paused && energy.needed() {
+        let mut kinetic = 0.0;
+        let mut potential = 0.0;
+        let mut momentum = DVec2::ZERO;
+        let mut total_mass = 0.0;
+        for (entity1, body1, transform1, velocity1) in query.iter() {
+            let m = body1.mass as f64;
+            kinetic += 0.5 * m * velocity1.0.truncate().length_squared() as f64;
+            momentum += m * velocity1.0.truncate().as_dvec2();
+            total_mass += m;
+            // every pair shows up twice, once from each side
+            potential += 0.5
+                * m
+                * tree.get_potential(entity1, *transform1, settings.force_params(), settings.theta) as f64;
+        }
+        // the bulk motion of the whole system isn't part of its internal kinetic energy
+        let bulk = if total_mass > 0.0 { 0.5 * momentum.length_squared() / total_mass } else { 0.0 };
+        energy.record(kinetic, kinetic - bulk, potential);
+    }
+
+    // Several small steps per rendered frame for accuracy, or a step only every few frames for
+    // slow motion. Every step after the first needs a tree of the moved bodies.
+    let (substeps, dt) = settings.pacing();
+
+    selected_breakdown.dt = dt;
+    selected_breakdown.breakdown = selected
+        .single()
+        .ok()
+        .and_then(|entity| query.get(entity).ok())
+        .map(|(entity1, body1, transform1, _velocity1)| {
+            tree.get_force_breakdown(entity1, *transform1, *body1, settings.force_params(), dt, settings.theta)
+        });
+
+    if settings.paused {
+        performance.record_physics(started.elapsed().as_secs_f32() * 1000.0);
+        return;
     }
 
     /*        for (entity2, body2, transform2, velocity2) in query.iter().remaining() {
@@ -286,12 +1395,298 @@ fn update(
      accel_cum += a1;
      } */
 
-    for (entity1, _body1, mut transform1, mut velocity) in query.iter_mut() {
+    let substeps = clock.take_substeps(substeps);
+    let force = settings.force_params();
+    let dual_tree = settings.dual_tree && !restricted;
+    let bucket_walks = settings.bucket_walks && !restricted && !dual_tree;
+    let warm_start = settings.warm_start && !restricted && !dual_tree && !bucket_walks;
+    if !warm_start {
+        warm.clear();
+    }
+    // where the selected body is in the buffers, for the trace
+    let traced = selected.single().ok().and_then(|entity| buffers.entities.iter().position(|e| *e == entity));
+    for substep in 0..substeps {
+        if substep > 0 && !restricted {
+            let _tree_build_span = debug_span!("tree_build").entered();
+            tree = build_tree(&buffers, None);
+        }
+        let group_trees: Vec<Option<Quadtree>> = if restricted {
+            let _tree_build_span = debug_span!("tree_build").entered();
+            (0..tag_groups.groups.len())
+                .map(|group| tag_groups.exerts(group).then(|| build_tree(&buffers, Some(group))))
+                .collect()
+        } else {
+            Vec::new()
+        };
+
+        let force_span = debug_span!("force").entered();
+        let bodies: Vec<(Entity, Body, Transform)> = query
+            .iter()
+            .enumerate()
+            .map(|(i, (entity1, body1, transform1, _velocity1))| (entity1, *body1, buffers.transform(i, transform1)))
+            .collect();
+        let walk = if restricted {
+            GravityWalk::Groups {
+                trees: &group_trees,
+                group_of: &group_of,
+                tag_groups: &tag_groups,
+            }
+        } else if dual_tree {
+            GravityWalk::DualTree(&tree)
+        } else if bucket_walks {
+            GravityWalk::Buckets(&tree)
+        } else if warm_start {
+            GravityWalk::WarmStart(&tree)
+        } else {
+            GravityWalk::Plain(&tree)
+        };
+        let pool = ComputeTaskPool::get_or_init(TaskPool::default);
+        let mut accels = gravity_accels(&walk, &bodies, &mut warm, force, dt, settings.theta, pool);
+
+        let extra = ExtraForces::new(&buffers, &settings, &stars, &attractor, &providers, clock.time);
+        for (i, accel) in accels.iter_mut().enumerate() {
+            let (entity1, body1, transform1) = bodies[i];
+            debug_assert_eq!(buffers.entities[i], entity1);
+            extra.add(accel, entity1, body1, transform1, buffers.velocity(i), dt);
+            buffers.ax[i] = accel.x;
+            buffers.ay[i] = accel.y;
+            suggested_dt.record(accel.truncate().length() / dt);
+        }
+        force_span.exit();
+        apply_controllers(&mut controllers, &mut buffers, &tree, clock.time, dt);
+
+        let _integrate_span = debug_span!("integrate").entered();
+        for i in 0..buffers.len() {
+            if pinned.contains(buffers.entities[i]) {
+                buffers.vx[i] = 0.0;
+                buffers.vy[i] = 0.0;
+                buffers.ax[i] = 0.0;
+                buffers.ay[i] = 0.0;
+            }
+        }
+        let old_velocities: Vec<Vec3> = (0..buffers.len()).map(|i| buffers.velocity(i)).collect();
+        buffers.step(dt);
+
+        for (i, old_velocity) in old_velocities.into_iter().enumerate() {
+            if let Ok(mut work_error) = work_errors.get_mut(buffers.entities[i]) {
+                let kick = Vec3::new(buffers.ax[i], buffers.ay[i], 0.0);
+                work_error.record(buffers.m[i], old_velocity, buffers.velocity(i), kick, dt);
+            }
+        }
+
+        clock.advance(dt);
+
+        // a row for every step, so two runs can be told apart at the step they diverge
+        if trace.recording() {
+            let original = |i: usize| origin.original(Vec2::new(buffers.x[i], buffers.y[i]));
+            let velocity = |i: usize| Vec2::new(buffers.vx[i], buffers.vy[i]);
+            let body = traced.map(|i| {
+                // the buffers hold the kick of the step, turn it back into an acceleration
+                let accel = Vec2::new(buffers.ax[i], buffers.ay[i]) / dt;
+                (buffers.entities[i].index(), original(i), velocity(i), accel)
+            });
+            let bodies = (0..buffers.len()).map(|i| (buffers.m[i], original(i), velocity(i)));
+            trace.record(TraceRow::measure(clock.steps, dt, bodies, body));
+        }
+    }
+
+    // Write the buffers back, z is only the draw order and is left alone
+    for (i, (entity1, _body1, mut transform1, mut velocity)) in query.iter_mut().enumerate() {
         // velocity.0 += col_map.get(&entity1.index()).unwrap_or(&Vec3::ZERO);
+        if pinned.contains(entity1) {
+            velocity.0 = Vec3::ZERO;
+            continue;
+        }
 
-        velocity.0 += accel_map.get(&entity1.index()).unwrap();
-        transform1.translation.x += velocity.0.x * settings.delta_t;
-        transform1.translation.y += velocity.0.y * settings.delta_t;
+        velocity.0.x = buffers.vx[i];
+        velocity.0.y = buffers.vy[i];
+        transform1.translation.x = buffers.x[i];
+        transform1.translation.y = buffers.y[i];
+    }
+    if settings.pipelined_tree {
+        let inputs = buffers.tree_inputs(
+            query.iter().map(|(entity1, body1, transform1, _velocity1)| (entity1, transform1, body1)),
+        );
+        pipelined.start(inputs, settings.leaf_capacity as usize);
+    }
+    performance.record_physics(started.elapsed().as_secs_f32() * 1000.0);
+}
+
+// Where the gravity of a step is walked from
+enum GravityWalk<'a> {
+    Plain(&'a Quadtree),
+    WarmStart(&'a Quadtree),
+    DualTree(&'a Quadtree),
+    Buckets(&'a Quadtree),
+    // With some groups not pulling on others, every group has a tree of its own and a body only
+    // feels the trees of the groups that act on it. MOND goes on the sum of their pulls.
+    Groups {
+        trees: &'a [Option<Quadtree>],
+        group_of: &'a [usize],
+        tag_groups: &'a TagGroups,
+    },
+}
+
+// Bodies each compute thread walks the tree for at a time
+const GRAVITY_CHUNK: usize = 256;
+
+// The gravity kick over dt of every body, in the order they are given in. The walks are split between
+// the pool's threads a chunk of bodies at a time, each body's own walk stays on one thread.
+fn gravity_accels(
+    walk: &GravityWalk,
+    bodies: &[(Entity, Body, Transform)],
+    warm: &mut WarmStart,
+    force: ForceParams,
+    dt: f32,
+    theta: f32,
+    pool: &TaskPool,
+) -> Vec<Vec3> {
+    // every body in the tree at once, tracers and bodies outside the tree still walk it one by one
+    let batch_accels: HashMap<Entity, Vec3> = match walk {
+        GravityWalk::DualTree(tree) => {
+            let _dual_span = debug_span!("dual_tree").entered();
+            dual_tree_accels(tree, force, dt, theta).into_iter().collect()
+        }
+        GravityWalk::Buckets(tree) => {
+            let _bucket_span = debug_span!("bucket_walks").entered();
+            bucket_accels(tree, force, dt, theta).into_iter().collect()
+        }
+        _ => HashMap::new(),
+    };
+
+    // a warm start carries what each walk used over to the next step, so those go one body after another
+    if let GravityWalk::WarmStart(tree) = walk {
+        let accels = bodies
+            .iter()
+            .map(|&(entity, _body, transform)| warm.accel(tree, entity, transform, force, dt, theta))
+            .collect();
+        warm.finish_step();
+        return accels;
+    }
+
+    // every other walk only reads the trees, so the bodies are split between the compute threads
+    let accel_of = |i: usize, &(entity, body, transform): &(Entity, Body, Transform)| match walk {
+        GravityWalk::Plain(tree) | GravityWalk::WarmStart(tree) => {
+            tree.get_total_accel(entity, transform, body, force, dt, theta)
+        }
+        GravityWalk::DualTree(tree) | GravityWalk::Buckets(tree) => match batch_accels.get(&entity) {
+            Some(accel) => *accel,
+            None => tree.get_total_accel(entity, transform, body, force, dt, theta),
+        },
+        GravityWalk::Groups {
+            trees,
+            group_of,
+            tag_groups,
+        } => force.mond_kick(
+            trees
+                .iter()
+                .enumerate()
+                .filter(|(source, _tree)| tag_groups.acts_on(*source, group_of[i]))
+                .filter_map(|(_source, tree)| tree.as_ref())
+                .map(|tree| tree.get_newtonian_accel(entity, transform, body, force, dt, theta))
+                .sum(),
+            dt,
+        ),
+    };
+    bodies
+        .par_chunk_map(pool, GRAVITY_CHUNK, |chunk, slice| {
+            let first = chunk * GRAVITY_CHUNK;
+            slice.iter().enumerate().map(|(i, body)| accel_of(first + i, body)).collect::<Vec<Vec3>>()
+        })
+        .concat()
+}
+
+// Everything added on top of gravity in a step, gathered once before the pass over the bodies
+struct ExtraForces<'a> {
+    force: ForceParams,
+    // The post-Newtonian term depends on velocity, so it can't go through the tree.
+    // There are only ever a handful of compact objects though, so it's done pairwise.
+    compact: Vec<(Entity, f32, Vec3, Vec3)>,
+    stars: Vec<(Entity, Vec3)>,
+    luminosity: f32,
+    attractor: &'a Attractor,
+    providers: &'a ForceProviders,
+    world_summary: Option<WorldSummary>,
+}
+
+impl<'a> ExtraForces<'a> {
+    fn new(
+        buffers: &BodyBuffers,
+        settings: &SimulationSettings,
+        stars: &Query<(), With<Star>>,
+        attractor: &'a Attractor,
+        providers: &'a ForceProviders,
+        time: f64,
+    ) -> Self {
+        let force = settings.force_params();
+        let compact = if force.post_newtonian {
+            (0..buffers.len())
+                .filter(|i| buffers.m[*i] >= force.compact_mass && buffers.in_tree[*i])
+                .map(|i| (buffers.entities[i], buffers.m[i], Vec3::new(buffers.x[i], buffers.y[i], 0.0), buffers.velocity(i)))
+                .collect()
+        } else {
+            Vec::new()
+        };
+        let stars = if settings.radiation_enabled {
+            (0..buffers.len())
+                .filter(|i| stars.contains(buffers.entities[*i]))
+                .map(|i| (buffers.entities[i], Vec3::new(buffers.x[i], buffers.y[i], 0.0)))
+                .collect()
+        } else {
+            Vec::new()
+        };
+        let world_summary = providers.any_enabled().then(|| {
+            WorldSummary::new(
+                time,
+                settings.g,
+                (0..buffers.len())
+                    .map(|i| (buffers.m[i], Vec2::new(buffers.x[i], buffers.y[i]), buffers.velocity(i).truncate())),
+            )
+        });
+        ExtraForces {
+            force,
+            compact,
+            stars,
+            luminosity: settings.luminosity,
+            attractor,
+            providers,
+            world_summary,
+        }
+    }
+
+    // Adds them to the kick over dt of one body
+    fn add(&self, accel: &mut Vec3, entity: Entity, body: Body, transform: Transform, velocity: Vec3, dt: f32) {
+        for (entity2, mass2, pos2, vel2) in &self.compact {
+            if *entity2 != entity {
+                *accel += self.force.post_newtonian(*mass2, transform.translation.with_z(0.0) - *pos2, velocity - *vel2)
+                    * dt;
+            }
+        }
+        for (star, star_pos) in &self.stars {
+            if *star != entity {
+                *accel += radiation_pressure(
+                    self.luminosity,
+                    body.mass,
+                    transform.translation.with_z(0.0) - *star_pos,
+                ) * dt;
+            }
+        }
+        if let Some(pos) = self.attractor.pos {
+            let target = pos.extend(transform.translation.z);
+            // too close and the force blows up, just let the body pass through the cursor
+            if target.distance(transform.translation) > 1.0 {
+                *accel += calc_accel(self.attractor.signed_mass(), transform.translation, target, dt, self.force);
+            }
+        }
+        if let Some(world_summary) = &self.world_summary {
+            let state = ForceInput {
+                entity,
+                mass: body.mass,
+                position: transform.translation.truncate(),
+                velocity: velocity.truncate(),
+            };
+            *accel += self.providers.accel(&state, world_summary).extend(0.0) * dt;
+        }
     }
 }
 
@@ -299,16 +1694,20 @@ fn spawn_body(
     body: Body,
     transform: Transform,
     velocity: Velocity,
+    settings: &SimulationSettings,
     commands: &mut Commands,
     materials: &mut ResMut<Assets<ColorMaterial>>,
     meshes: &mut ResMut<Assets<Mesh>>,
-) {
+) -> Entity {
     commands.spawn((
-        Mesh2d(meshes.add(Circle::new(body.radius))),
-        MeshMaterial2d(materials.add(ColorMaterial::from_color(Srgba::rgb(body.hue, 0.5, 0.0)))),
+        Mesh2d(meshes.add(Circle::new(settings.render_radius(body.radius)))),
+        MeshMaterial2d(materials.add(ColorMaterial::from_color(settings.colormap.sample(body.hue)))),
         body,
-        transform,
+        transform.with_scale(Vec3::splat(0.01)),
         velocity,
-    ));
+        Fade::fade_in(),
+        WorkError::default(),
+    ))
+    .id()
 }
 
@@ -0,0 +1,79 @@
+use bevy::prelude::*;
+use crate::bookmarks::Bookmarks;
+use crate::energy::WorkError;
+use crate::ghost::{Ghost, GhostEvent};
+use crate::trails::Trails;
+use crate::undo::UndoHistory;
+use crate::{Body, SimulationSettings, Velocity};
+
+// Every body also owns its own circle mesh and material. A 32 segment circle is 33 vertices
+// with position, normal and uv, plus 96 indices, which comes to about this.
+const MESH_BYTES: usize = 1500;
+
+// Rough memory use, the parts we can account for. Shown in the overlay.
+#[derive(Resource, Default)]
+pub struct MemoryUsage {
+    // filled in by the update system, the tree only lives there
+    pub tree: usize,
+    pub bodies: usize,
+    pub ghosts: usize,
+    // the trail image, which lives on the GPU
+    pub trails: usize,
+    pub undo: usize,
+    pub bookmarks: usize,
+}
+
+impl MemoryUsage {
+    pub fn total(&self) -> usize {
+        self.tree + self.bodies + self.ghosts + self.trails + self.undo + self.bookmarks
+    }
+}
+
+pub fn body_bytes(n: usize) -> usize {
+    n * (size_of::<(Body, Transform, GlobalTransform, Velocity, WorkError)>() + MESH_BYTES)
+}
+
+pub fn ghost_bytes(n: usize) -> usize {
+    n * (size_of::<(Ghost, Transform, GlobalTransform, Velocity)>() + MESH_BYTES)
+}
+
+// Over budget, the optional extras go one at a time until it fits again: the trails, the undo history,
+// the bookmark thumbnails and the ghost set. The bookmarks themselves were made on purpose and stay, and
+// the bodies and the tree are the simulation itself. The step trace and the frame export go straight
+// to disk, they hold next to nothing.
+pub fn memory_budget(
+    mut usage: ResMut<MemoryUsage>,
+    settings: Res<SimulationSettings>,
+    bodies: Query<(), With<Body>>,
+    ghosts: Query<(), With<Ghost>>,
+    mut ghost_writer: EventWriter<GhostEvent>,
+    (mut trails, mut history, mut bookmarks): (ResMut<Trails>, ResMut<UndoHistory>, ResMut<Bookmarks>),
+) {
+    usage.bodies = body_bytes(bodies.iter().count());
+    usage.ghosts = ghost_bytes(ghosts.iter().count());
+    usage.trails = trails.memory_bytes();
+    usage.undo = history.memory_bytes();
+    usage.bookmarks = bookmarks.memory_bytes();
+
+    let budget = (settings.memory_budget_mb * 1024.0 * 1024.0) as usize;
+    if usage.total() > budget && usage.trails > 0 {
+        warn!(used = usage.total(), budget, "Over the memory budget, turning trails off");
+        trails.enabled = false;
+        usage.trails = 0;
+    }
+    if usage.total() > budget && usage.undo > 0 {
+        warn!(used = usage.total(), budget, "Over the memory budget, clearing the undo history");
+        history.clear();
+        usage.undo = 0;
+    }
+    let thumbnails = bookmarks.thumbnail_bytes();
+    if usage.total() > budget && thumbnails > 0 {
+        warn!(used = usage.total(), budget, "Over the memory budget, dropping the bookmark thumbnails");
+        bookmarks.drop_thumbnails();
+        usage.bookmarks -= thumbnails;
+    }
+    if usage.total() > budget && usage.ghosts > 0 {
+        warn!(used = usage.total(), budget, "Over the memory budget, clearing ghosts");
+        ghost_writer.write(GhostEvent::Clear);
+    }
+}
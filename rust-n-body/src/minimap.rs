@@ -0,0 +1,107 @@
+use bevy::prelude::*;
+use bevy_egui::{EguiContexts, egui};
+use crate::bhtree::Quad;
+use crate::{Body, MainCamera, SimulationSettings};
+
+// Side of the minimap in points, every point is one pixel of the binned bodies
+const SIDE: usize = 160;
+
+// Fraction of the way across the square from min, with y pointing down like the screen
+pub fn world_to_minimap(pos: Vec2, min: Vec2, size: f32) -> Vec2 {
+    let frac = (pos - min) / size;
+    Vec2::new(frac.x, 1.0 - frac.y)
+}
+
+pub fn minimap_to_world(frac: Vec2, min: Vec2, size: f32) -> Vec2 {
+    min + Vec2::new(frac.x, 1.0 - frac.y) * size
+}
+
+// Bodies per pixel of a side x side grid over the square, row by row from the top.
+// Drawing one rectangle per lit pixel keeps the minimap cheap however many bodies there are.
+pub fn bin_bodies(positions: &[Vec2], min: Vec2, size: f32, side: usize) -> Vec<u32> {
+    let mut counts = vec![0; side * side];
+    if size <= 0.0 {
+        return counts;
+    }
+    for p in positions {
+        let frac = world_to_minimap(*p, min, size);
+        if !(0.0..1.0).contains(&frac.x) || !(0.0..1.0).contains(&frac.y) {
+            continue;
+        }
+        let (x, y) = ((frac.x * side as f32) as usize, (frac.y * side as f32) as usize);
+        counts[y.min(side - 1) * side + x.min(side - 1)] += 1;
+    }
+    counts
+}
+
+// The whole system in a corner with the main view's rectangle on it, click or drag to move the view there
+pub fn minimap(
+    mut contexts: EguiContexts,
+    settings: Res<SimulationSettings>,
+    bodies: Query<&Transform, (With<Body>, Without<MainCamera>)>,
+    mut cameras: Query<(&mut Transform, &Projection), With<MainCamera>>,
+) {
+    if !settings.show_minimap {
+        return;
+    }
+    let positions: Vec<Vec2> = bodies.iter().map(|transform| transform.translation.truncate()).collect();
+    if positions.is_empty() {
+        return;
+    }
+    let (min, max) = Quad::new_containing(&positions).bounds();
+    let size = (max - min).x;
+    let counts = bin_bodies(&positions, min, size, SIDE);
+
+    egui::Area::new(egui::Id::new("minimap"))
+        .anchor(egui::Align2::LEFT_BOTTOM, [10.0, -10.0])
+        .show(contexts.ctx_mut(), |ui| {
+            egui::Frame::popup(ui.style()).show(ui, |ui| {
+                let (rect, response) =
+                    ui.allocate_exact_size(egui::vec2(SIDE as f32, SIDE as f32), egui::Sense::click_and_drag());
+                let painter = ui.painter_at(rect);
+                painter.rect_filled(rect, 0.0, egui::Color32::from_rgb(5, 5, 12));
+
+                for (i, count) in counts.iter().enumerate() {
+                    if *count == 0 {
+                        continue;
+                    }
+                    // busier pixels get brighter, on a log scale so a lone body still shows
+                    let brightness = (110.0 + 40.0 * (*count as f32).ln()).min(255.0) as u8;
+                    let pixel = egui::Rect::from_min_size(
+                        rect.min + egui::vec2((i % SIDE) as f32, (i / SIDE) as f32),
+                        egui::vec2(1.0, 1.0),
+                    );
+                    painter.rect_filled(pixel, 0.0, egui::Color32::from_gray(brightness));
+                }
+
+                let Ok((mut camera, projection)) = cameras.single_mut() else {
+                    return;
+                };
+                let to_screen = |p: Vec2| {
+                    let frac = world_to_minimap(p, min, size);
+                    rect.min + egui::vec2(frac.x, frac.y) * SIDE as f32
+                };
+                if let Projection::Orthographic(ortho) = projection {
+                    let center = camera.translation.truncate();
+                    let view = egui::Rect::from_two_pos(
+                        to_screen(center + ortho.area.min),
+                        to_screen(center + ortho.area.max),
+                    );
+                    painter.rect_stroke(
+                        view,
+                        0.0,
+                        egui::Stroke::new(1.0, egui::Color32::YELLOW),
+                        egui::StrokeKind::Middle,
+                    );
+                }
+
+                if (response.clicked() || response.dragged())
+                    && let Some(pointer) = response.interact_pointer_pos()
+                {
+                    let frac = (pointer - rect.min) / SIDE as f32;
+                    let target = minimap_to_world(Vec2::new(frac.x, frac.y), min, size);
+                    camera.translation = target.extend(camera.translation.z);
+                }
+            });
+        });
+}
@@ -0,0 +1,292 @@
+use bevy::prelude::*;
+use std::collections::HashMap;
+use std::io::ErrorKind;
+use std::net::UdpSocket;
+use crate::fade::despawn_with_fade;
+use crate::{Body, SimulationSettings};
+
+// Host/viewer mode. The host sends the bodies of every frame over UDP, the viewer draws them
+// without simulating anything itself, easing each body from where it was to where it is now.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum NetMode {
+    Off,
+    Host,
+    Viewer,
+}
+
+impl NetMode {
+    pub const ALL: [NetMode; 3] = [NetMode::Off, NetMode::Host, NetMode::Viewer];
+}
+
+// Positions are sent as 16 bit fractions of the frame's bounding square, which keeps a body at
+// 11 bytes. A frame is split over as many packets as needed to stay under the UDP size limit.
+const MAGIC: [u8; 2] = *b"NB";
+const HEADER_LEN: usize = 2 + 4 + 2 + 2 + 4 * 3;
+const BODY_LEN: usize = 4 + 2 + 2 + 2 + 1;
+const BODIES_PER_CHUNK: usize = 4000;
+const RADIUS_STEPS: f32 = 64.0;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct RemoteState {
+    pub id: u32,
+    pub pos: Vec2,
+    pub radius: f32,
+    pub hue: f32,
+}
+
+pub struct Chunk {
+    pub frame: u32,
+    pub index: u16,
+    pub count: u16,
+    pub bodies: Vec<RemoteState>,
+}
+
+pub fn encode_frame(frame: u32, bodies: &[RemoteState]) -> Vec<Vec<u8>> {
+    let mut min = Vec2::splat(f32::MAX);
+    let mut max = Vec2::splat(f32::MIN);
+    for body in bodies {
+        min = min.min(body.pos);
+        max = max.max(body.pos);
+    }
+    if bodies.is_empty() {
+        min = Vec2::ZERO;
+        max = Vec2::ZERO;
+    }
+    let size = (max - min).max_element().max(f32::MIN_POSITIVE);
+
+    // an empty frame still needs one packet, so viewers clear their bodies
+    let chunks: Vec<&[RemoteState]> = if bodies.is_empty() {
+        vec![&[]]
+    } else {
+        bodies.chunks(BODIES_PER_CHUNK).collect()
+    };
+    let count = chunks.len() as u16;
+    chunks
+        .iter()
+        .enumerate()
+        .map(|(index, chunk)| {
+            let mut bytes = Vec::with_capacity(HEADER_LEN + chunk.len() * BODY_LEN);
+            bytes.extend_from_slice(&MAGIC);
+            bytes.extend_from_slice(&frame.to_le_bytes());
+            bytes.extend_from_slice(&(index as u16).to_le_bytes());
+            bytes.extend_from_slice(&count.to_le_bytes());
+            bytes.extend_from_slice(&min.x.to_le_bytes());
+            bytes.extend_from_slice(&min.y.to_le_bytes());
+            bytes.extend_from_slice(&size.to_le_bytes());
+            for body in chunk.iter() {
+                let q = ((body.pos - min) / size * 65535.0).round();
+                bytes.extend_from_slice(&body.id.to_le_bytes());
+                bytes.extend_from_slice(&(q.x as u16).to_le_bytes());
+                bytes.extend_from_slice(&(q.y as u16).to_le_bytes());
+                bytes.extend_from_slice(&((body.radius * RADIUS_STEPS).round().min(65535.0) as u16).to_le_bytes());
+                bytes.push((body.hue.clamp(0.0, 1.0) * 255.0).round() as u8);
+            }
+            bytes
+        })
+        .collect()
+}
+
+// None for anything that isn't one of our packets
+pub fn decode_chunk(bytes: &[u8]) -> Option<Chunk> {
+    if bytes.len() < HEADER_LEN || bytes[..2] != MAGIC || !(bytes.len() - HEADER_LEN).is_multiple_of(BODY_LEN) {
+        return None;
+    }
+    let u16_at = |i: usize| u16::from_le_bytes([bytes[i], bytes[i + 1]]);
+    let u32_at = |i: usize| u32::from_le_bytes([bytes[i], bytes[i + 1], bytes[i + 2], bytes[i + 3]]);
+    let f32_at = |i: usize| f32::from_bits(u32_at(i));
+
+    let min = Vec2::new(f32_at(10), f32_at(14));
+    let size = f32_at(18);
+    let bodies = (HEADER_LEN..bytes.len())
+        .step_by(BODY_LEN)
+        .map(|i| RemoteState {
+            id: u32_at(i),
+            pos: min + Vec2::new(u16_at(i + 4) as f32, u16_at(i + 6) as f32) / 65535.0 * size,
+            radius: u16_at(i + 8) as f32 / RADIUS_STEPS,
+            hue: bytes[i + 10] as f32 / 255.0,
+        })
+        .collect();
+    Some(Chunk {
+        frame: u32_at(2),
+        index: u16_at(6),
+        count: u16_at(8),
+        bodies,
+    })
+}
+
+// A body drawn from the host's data, it has no Body so the physics never sees it
+#[derive(Component)]
+pub struct RemoteBody {
+    id: u32,
+    from: Vec2,
+    to: Vec2,
+}
+
+#[derive(Resource, Default)]
+pub struct NetSession {
+    socket: Option<UdpSocket>,
+    // what the socket was opened for, it's reopened when either changes
+    opened: Option<(NetMode, String)>,
+    frame: u32,
+    // viewer side, chunks of the newest frame seen so far
+    pending: Option<(u32, Vec<Option<Vec<RemoteState>>>)>,
+    last_frame_at: f32,
+    frame_interval: f32,
+}
+
+fn open_socket(mode: NetMode, addr: &str) -> std::io::Result<Option<UdpSocket>> {
+    let socket = match mode {
+        NetMode::Off => return Ok(None),
+        NetMode::Host => UdpSocket::bind("0.0.0.0:0")?,
+        NetMode::Viewer => UdpSocket::bind(addr)?,
+    };
+    socket.set_nonblocking(true)?;
+    Ok(Some(socket))
+}
+
+pub fn net_host(
+    mut session: ResMut<NetSession>,
+    mut settings: ResMut<SimulationSettings>,
+    bodies: Query<(Entity, &Transform, &Body)>,
+    remote: Query<Entity, With<RemoteBody>>,
+    local: Query<Entity, With<Body>>,
+    mut commands: Commands,
+) {
+    let wanted = (settings.net_mode, settings.net_addr.clone());
+    if session.opened.as_ref() != Some(&wanted) {
+        // leaving viewer mode drops the remote bodies, entering it drops our own
+        if settings.net_mode != NetMode::Viewer {
+            for entity in &remote {
+                commands.entity(entity).despawn();
+            }
+        } else {
+            for entity in &local {
+                despawn_with_fade(&mut commands, entity);
+            }
+            settings.paused = true;
+        }
+        session.pending = None;
+        session.socket = match open_socket(settings.net_mode, &settings.net_addr) {
+            Ok(socket) => socket,
+            Err(err) => {
+                error!("Could not open socket for {:?} on {}: {}", settings.net_mode, settings.net_addr, err);
+                settings.net_mode = NetMode::Off;
+                None
+            }
+        };
+        session.opened = Some((settings.net_mode, settings.net_addr.clone()));
+    }
+
+    if settings.net_mode != NetMode::Host {
+        return;
+    }
+    let Some(socket) = &session.socket else {
+        return;
+    };
+
+    let states: Vec<RemoteState> = bodies
+        .iter()
+        .map(|(entity, transform, body)| RemoteState {
+            id: entity.index(),
+            pos: transform.translation.truncate(),
+            radius: body.radius,
+            hue: body.hue,
+        })
+        .collect();
+    for packet in encode_frame(session.frame, &states) {
+        // a viewer that isn't listening yet is not an error worth stopping for
+        if let Err(err) = socket.send_to(&packet, &settings.net_addr) {
+            debug!("Dropped packet to {}: {}", settings.net_addr, err);
+        }
+    }
+    session.frame = session.frame.wrapping_add(1);
+}
+
+pub fn net_viewer(
+    time: Res<Time>,
+    mut session: ResMut<NetSession>,
+    settings: Res<SimulationSettings>,
+    mut remote: Query<(Entity, &mut RemoteBody, &mut Transform)>,
+    mut commands: Commands,
+    mut materials: ResMut<Assets<ColorMaterial>>,
+    mut meshes: ResMut<Assets<Mesh>>,
+) {
+    if settings.net_mode != NetMode::Viewer {
+        return;
+    }
+    // split the borrow, the socket is read while the pending frame is written
+    let session = &mut *session;
+
+    let mut complete = None;
+    if let Some(socket) = &session.socket {
+        let mut buf = vec![0u8; 65536];
+        let mut pending = session.pending.take();
+        loop {
+            let len = match socket.recv_from(&mut buf) {
+                Ok((len, _from)) => len,
+                Err(err) if err.kind() == ErrorKind::WouldBlock => break,
+                Err(err) => {
+                    debug!("Receive failed: {}", err);
+                    break;
+                }
+            };
+            let Some(chunk) = decode_chunk(&buf[..len]) else {
+                continue;
+            };
+            // a newer frame replaces whatever is left of the older one, late packets are dropped
+            let newer = pending.as_ref().is_none_or(|(frame, _)| chunk.frame.wrapping_sub(*frame) as i32 > 0);
+            if newer {
+                pending = Some((chunk.frame, vec![None; chunk.count as usize]));
+            }
+            let mut done = false;
+            if let Some((frame, chunks)) = &mut pending
+                && *frame == chunk.frame
+                && (chunk.index as usize) < chunks.len()
+            {
+                chunks[chunk.index as usize] = Some(chunk.bodies);
+                done = chunks.iter().all(Option::is_some);
+            }
+            if done && let Some((_frame, chunks)) = pending.take() {
+                complete = Some(chunks.into_iter().flatten().flatten().collect::<Vec<_>>());
+            }
+        }
+        session.pending = pending;
+    }
+
+    let now = time.elapsed_secs();
+    if let Some(states) = complete {
+        // ease over however long frames are taking to arrive
+        session.frame_interval = (now - session.last_frame_at).clamp(0.001, 0.5);
+        session.last_frame_at = now;
+
+        let mut by_id: HashMap<u32, RemoteState> = states.into_iter().map(|s| (s.id, s)).collect();
+        for (entity, mut body, transform) in &mut remote {
+            match by_id.remove(&body.id) {
+                Some(state) => {
+                    body.from = transform.translation.truncate();
+                    body.to = state.pos;
+                }
+                None => commands.entity(entity).despawn(),
+            }
+        }
+        for state in by_id.into_values() {
+            commands.spawn((
+                Mesh2d(meshes.add(Circle::new(settings.render_radius(state.radius)))),
+                MeshMaterial2d(materials.add(ColorMaterial::from_color(settings.colormap.sample(state.hue)))),
+                RemoteBody {
+                    id: state.id,
+                    from: state.pos,
+                    to: state.pos,
+                },
+                Transform::from_xyz(state.pos.x, state.pos.y, settings.z),
+            ));
+        }
+    }
+
+    let t = ((now - session.last_frame_at) / session.frame_interval.max(0.001)).min(1.0);
+    for (_entity, body, mut transform) in &mut remote {
+        let pos = body.from.lerp(body.to, t);
+        transform.translation.x = pos.x;
+        transform.translation.y = pos.y;
+    }
+}
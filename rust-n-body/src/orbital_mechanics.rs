@@ -0,0 +1,117 @@
+use bevy::prelude::*;
+
+// Instantaneous Keplerian elements of a two-body orbit, relative to the pair's center of mass.
+// Only meaningful for the 1/r² law, the other laws don't have closed ellipses.
+pub struct OrbitElements {
+    pub semi_major_axis: f32,
+    pub eccentricity: f32,
+    // None when the pair isn't bound
+    pub period: Option<f32>,
+}
+
+// mu is G (m1 + m2), rel_pos and rel_vel are body 2 relative to body 1
+pub fn orbit_elements(mu: f32, rel_pos: Vec2, rel_vel: Vec2) -> OrbitElements {
+    let r = rel_pos.length();
+    let v2 = rel_vel.length_squared();
+    // specific orbital energy, negative for a bound orbit
+    let energy = 0.5 * v2 - mu / r;
+    let semi_major_axis = -mu / (2.0 * energy);
+    // eccentricity vector, points at periapsis
+    let e = ((v2 - mu / r) * rel_pos - rel_pos.dot(rel_vel) * rel_vel) / mu;
+    let period = if energy < 0.0 {
+        Some(std::f32::consts::TAU * (semi_major_axis.powi(3) / mu).sqrt())
+    } else {
+        None
+    };
+    OrbitElements {
+        semi_major_axis,
+        eccentricity: e.length(),
+        period,
+    }
+}
+
+// Position and velocity relative to the primary that put a body at periapsis of an orbit with the given
+// elements, periapsis pointing along dir. Goes counterclockwise unless retrograde.
+pub fn periapsis_state(mu: f32, semi_major_axis: f32, eccentricity: f32, dir: Vec2, retrograde: bool) -> (Vec2, Vec2) {
+    let r = semi_major_axis * (1.0 - eccentricity);
+    // vis-viva at periapsis, v² = mu (2 / r - 1 / a) = mu (1 + e) / r
+    let speed = (mu * (1.0 + eccentricity) / r).sqrt();
+    let along = if retrograde { -dir.perp() } else { dir.perp() };
+    (dir * r, along * speed)
+}
+
+// Radius around a body of mass m orbiting a primary of mass m_primary inside which the body's own
+// gravity wins. Uses the periapsis distance, which is where the sphere is smallest.
+pub fn hill_radius(m: f32, m_primary: f32, semi_major_axis: f32, eccentricity: f32) -> f32 {
+    semi_major_axis * (1.0 - eccentricity) * (m / (3.0 * m_primary)).cbrt()
+}
+
+// Distance from the primary inside which a fluid body of the given radius and mass is torn apart
+// by tides. The usual 2.44 R_primary (rho_primary / rho)^(1/3), with the densities written in terms
+// of the masses and radii, so the primary's radius cancels out.
+pub fn roche_limit(radius: f32, m: f32, m_primary: f32) -> f32 {
+    2.44 * radius * (m_primary / m).cbrt()
+}
+
+// The body that pulls hardest on pos, out of those heavier than mass. Returns its index.
+pub fn find_primary(pos: Vec2, mass: f32, others: &[(Vec2, f32)]) -> Option<usize> {
+    others
+        .iter()
+        .enumerate()
+        .filter(|(_i, (p, m))| *m > mass && *p != pos)
+        .map(|(i, (p, m))| (i, m / p.distance_squared(pos)))
+        .max_by(|a, b| a.1.total_cmp(&b.1))
+        .map(|(i, _pull)| i)
+}
+
+// Inner Lagrange point of a circular binary, where the two pulls and the centrifugal push in the frame
+// rotating with the pair cancel. The balance has no closed form, but it changes sign exactly once
+// between the bodies, so bisection finds it.
+pub fn l1_point(m1: f32, p1: Vec2, m2: f32, p2: Vec2) -> Option<Vec2> {
+    let d = p1.distance(p2);
+    let total = m1 + m2;
+    if d <= 0.0 || m1 <= 0.0 || m2 <= 0.0 {
+        return None;
+    }
+    // along the axis from body 1 to body 2 with the center of mass at 0, in units of the separation
+    let (x1, x2) = (-m2 / total, m1 / total);
+    // net outward pull at x in units of G / d², omega² being G M / d³
+    let balance = |x: f32| -m1 / (x - x1).powi(2) + m2 / (x2 - x).powi(2) + total * x;
+
+    let (mut lo, mut hi) = (x1 + 1e-4, x2 - 1e-4);
+    for _ in 0..60 {
+        let mid = 0.5 * (lo + hi);
+        if balance(mid) < 0.0 {
+            lo = mid;
+        } else {
+            hi = mid;
+        }
+    }
+    let com = (m1 * p1 + m2 * p2) / total;
+    Some(com + (p2 - p1) * 0.5 * (lo + hi))
+}
+
+// Outer Lagrange point on the far side of body 2, found the same way as l1_point. Between just past body 2
+// and a whole separation further out the balance goes from inward to outward exactly once.
+pub fn l2_point(m1: f32, p1: Vec2, m2: f32, p2: Vec2) -> Option<Vec2> {
+    let d = p1.distance(p2);
+    let total = m1 + m2;
+    if d <= 0.0 || m1 <= 0.0 || m2 <= 0.0 {
+        return None;
+    }
+    let (x1, x2) = (-m2 / total, m1 / total);
+    // both bodies pull inward out here
+    let balance = |x: f32| -m1 / (x - x1).powi(2) - m2 / (x - x2).powi(2) + total * x;
+
+    let (mut lo, mut hi) = (x2 + 1e-4, x2 + 1.0);
+    for _ in 0..60 {
+        let mid = 0.5 * (lo + hi);
+        if balance(mid) < 0.0 {
+            lo = mid;
+        } else {
+            hi = mid;
+        }
+    }
+    let com = (m1 * p1 + m2 * p2) / total;
+    Some(com + (p2 - p1) * 0.5 * (lo + hi))
+}
@@ -0,0 +1,104 @@
+use bevy::math::DVec2;
+use bevy::prelude::*;
+use crate::MainCamera;
+use crate::bookmarks::Bookmarks;
+use crate::branch::Branch;
+use crate::physics_thread::PhysicsThread;
+use crate::providers::ForceProviders;
+use crate::undo::UndoHistory;
+use crate::wind::BackgroundFlow;
+
+// Positions are f32, which only has about a hundredth of a unit left at a million units out. Once the
+// camera has followed the bodies far enough away, everything is moved back by the camera's position so
+// what's being looked at is near the origin again. The forces only depend on differences of positions,
+// so the physics doesn't notice. offset keeps where the origin is in the coordinates the run started in.
+#[derive(Resource)]
+pub struct WorldOrigin {
+    pub enabled: bool,
+    // distance of the camera from the origin that starts a rebase
+    pub threshold: f32,
+    pub offset: DVec2,
+    pub rebases: u32,
+}
+
+impl Default for WorldOrigin {
+    fn default() -> Self {
+        WorldOrigin {
+            enabled: false,
+            threshold: 10000.0,
+            offset: DVec2::ZERO,
+            rebases: 0,
+        }
+    }
+}
+
+impl WorldOrigin {
+    // A position given in the coordinates the run started in, like the ones in timeline scripts
+    pub fn local(&self, original: Vec2) -> Vec2 {
+        (original.as_dvec2() - self.offset).as_vec2()
+    }
+
+    // The other way, a position on screen in the coordinates the run started in, for whatever is written
+    // out and has to line up across a rebase
+    pub fn original(&self, local: Vec2) -> DVec2 {
+        local.as_dvec2() + self.offset
+    }
+}
+
+// How far to move the origin with the camera at focus, rounded to whole units so the offset adds up
+// exactly over many moves
+pub fn rebase_shift(focus: Vec2, threshold: f32) -> Option<Vec2> {
+    (focus.length() > threshold).then(|| focus.round())
+}
+
+// Runs before the frame's systems with the camera where the last frame left it, so the whole frame is
+// stepped and drawn in the new coordinates. Every entity at the root of the world moves, that is the
+// bodies, the cameras and whatever is drawn with them, along with the saved states and the fixed
+// centers of the custom forces and the gas flow. The resources missing in tests are skipped.
+pub fn rebase_origin(
+    mut origin: ResMut<WorldOrigin>,
+    physics_thread: Res<PhysicsThread>,
+    mut world: Query<(&mut Transform, Has<MainCamera>), (Without<ChildOf>, Without<Node>)>,
+    history: Option<ResMut<UndoHistory>>,
+    bookmarks: Option<ResMut<Bookmarks>>,
+    branch: Option<ResMut<Branch>>,
+    providers: Option<ResMut<ForceProviders>>,
+    flow: Option<ResMut<BackgroundFlow>>,
+) {
+    // the thread holds its own copy of the positions until it hands them back
+    if !origin.enabled || physics_thread.running() {
+        return;
+    }
+    let Some(focus) = world.iter().find(|(_t, camera)| *camera).map(|(t, _c)| t.translation.truncate()) else {
+        return;
+    };
+    let Some(shift) = rebase_shift(focus, origin.threshold) else {
+        return;
+    };
+
+    for (mut transform, _camera) in &mut world {
+        transform.translation -= shift.extend(0.0);
+    }
+    if let Some(mut history) = history {
+        history.rebase(shift);
+    }
+    if let Some(mut bookmarks) = bookmarks {
+        for bookmark in &mut bookmarks.list {
+            bookmark.snapshot.rebase(shift);
+        }
+    }
+    if let Some(sim) = branch.and_then(|branch| branch.into_inner().sim.as_mut()) {
+        for transform in &mut sim.transforms {
+            transform.translation -= shift.extend(0.0);
+        }
+    }
+    if let Some(mut providers) = providers {
+        providers.rebase(shift);
+    }
+    if let Some(mut flow) = flow {
+        flow.center -= shift;
+    }
+    origin.offset += shift.as_dvec2();
+    origin.rebases += 1;
+    info!("Moved the world origin by {}, now at {} of the starting coordinates", shift, origin.offset);
+}
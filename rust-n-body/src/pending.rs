@@ -0,0 +1,117 @@
+use bevy::prelude::*;
+use crate::SimulationSettings;
+use crate::radius::RadiusLaw;
+
+// The settings only a reset reads, the ones the new bodies are generated from
+#[derive(Clone, PartialEq, Debug)]
+pub struct SpawnSettings {
+    pub n_bodies: u32,
+    pub min_body_mass: f32,
+    pub max_body_mass: f32,
+    pub radius_law: RadiusLaw,
+    pub density: f32,
+    pub render_scale: f32,
+    pub donut: bool,
+    pub init_vel: f32,
+    pub deterministic: bool,
+    pub seed: u64,
+}
+
+impl SpawnSettings {
+    pub fn from_settings(settings: &SimulationSettings) -> Self {
+        SpawnSettings {
+            n_bodies: settings.n_bodies,
+            min_body_mass: settings.min_body_mass,
+            max_body_mass: settings.max_body_mass,
+            radius_law: settings.radius_law,
+            density: settings.density,
+            render_scale: settings.render_scale,
+            donut: settings.donut,
+            init_vel: settings.init_vel,
+            deterministic: settings.deterministic,
+            seed: settings.seed,
+        }
+    }
+
+    pub fn apply_to(&self, settings: &mut SimulationSettings) {
+        settings.n_bodies = self.n_bodies;
+        settings.min_body_mass = self.min_body_mass;
+        settings.max_body_mass = self.max_body_mass;
+        settings.radius_law = self.radius_law;
+        settings.density = self.density;
+        settings.render_scale = self.render_scale;
+        settings.donut = self.donut;
+        settings.init_vel = self.init_vel;
+        settings.deterministic = self.deterministic;
+        settings.seed = self.seed;
+    }
+
+    // Names of the fields that differ, as the settings window labels them
+    pub fn changed_fields(&self, other: &SpawnSettings) -> Vec<&'static str> {
+        let mut changed = Vec::new();
+        if self.n_bodies != other.n_bodies {
+            changed.push("Num Bodies");
+        }
+        if self.min_body_mass != other.min_body_mass {
+            changed.push("Min Body Mass");
+        }
+        if self.max_body_mass != other.max_body_mass {
+            changed.push("Max Body Mass");
+        }
+        if self.radius_law != other.radius_law {
+            changed.push("Body Size");
+        }
+        if self.density != other.density {
+            changed.push("Density");
+        }
+        if self.render_scale != other.render_scale {
+            changed.push("Drawn Radius x");
+        }
+        if self.donut != other.donut {
+            changed.push("Donut Start");
+        }
+        if self.init_vel != other.init_vel {
+            changed.push("Initial Velocity");
+        }
+        if self.deterministic != other.deterministic || self.seed != other.seed {
+            changed.push("Seed");
+        }
+        changed
+    }
+}
+
+// Edits to the reset-only settings, held back until the next reset applies all of them at once
+// or Revert throws them away. Until then the settings keep describing the bodies on screen.
+#[derive(Resource, Default)]
+pub struct PendingSettings {
+    pub staged: Option<SpawnSettings>,
+}
+
+impl PendingSettings {
+    // What the settings window edits, the staged values or else the applied ones
+    pub fn editable(&self, settings: &SimulationSettings) -> SpawnSettings {
+        self.staged.clone().unwrap_or_else(|| SpawnSettings::from_settings(settings))
+    }
+
+    // Edited back to what is applied means nothing is pending
+    pub fn stage(&mut self, edited: SpawnSettings, settings: &SimulationSettings) {
+        self.staged = (edited != SpawnSettings::from_settings(settings)).then_some(edited);
+    }
+
+    pub fn changed_fields(&self, settings: &SimulationSettings) -> Vec<&'static str> {
+        self.staged
+            .as_ref()
+            .map(|staged| staged.changed_fields(&SpawnSettings::from_settings(settings)))
+            .unwrap_or_default()
+    }
+
+    pub fn apply(&mut self, settings: &mut SimulationSettings) {
+        if let Some(staged) = self.staged.take() {
+            staged.apply_to(settings);
+        }
+    }
+
+    pub fn revert(&mut self) {
+        self.staged = None;
+    }
+}
@@ -0,0 +1,451 @@
+use bevy::math::DVec2;
+use bevy::platform::time::Instant;
+use bevy::prelude::*;
+use bevy_egui::{EguiContexts, egui};
+use std::sync::{Arc, Mutex};
+use std::thread::JoinHandle;
+use std::time::Duration;
+use crate::clock::SimulationClock;
+use crate::controller::Controller;
+use crate::energy::Energy;
+use crate::guard::Quarantined;
+use crate::headless::HeadlessSim;
+use crate::providers::ForceProviders;
+use crate::selection::{Pinned, Selected};
+use crate::tags::TagGroups;
+use crate::timeline::Timeline;
+use crate::tools::{ActiveTool, Attractor};
+use crate::tracer::Massless;
+use crate::ui_config::UiConfig;
+use crate::virial::VirialMonitor;
+use crate::wind::BackgroundFlow;
+use crate::{Body, SimulationSettings, Velocity};
+
+// How long the thread naps while paused before looking at the settings again
+const PAUSED_POLL: Duration = Duration::from_millis(10);
+
+// Where every body was after a step and how fast it went, in the order the bodies were handed to the thread
+#[derive(Default)]
+pub struct PhysicsFrame {
+    pub positions: Vec<Vec2>,
+    pub velocities: Vec<Vec2>,
+    // kinetic, internal kinetic and potential energy, only measured while the main thread wants them
+    pub energy: Option<(f64, f64, f64)>,
+    pub time: f64,
+    pub steps: u64,
+    pub at: Option<Instant>,
+}
+
+// Bodies drawn between two frames, `alpha` of the way from the older to the newer
+pub fn interpolate(previous: &[Vec2], current: &[Vec2], alpha: f32) -> Vec<Vec2> {
+    if previous.len() != current.len() {
+        return current.to_vec();
+    }
+    previous.iter().zip(current).map(|(a, b)| a.lerp(*b, alpha)).collect()
+}
+
+// How far the render is between the two newest frames. The render stays one step behind the
+// physics, so it can always move towards a step that is already done.
+pub fn interpolation_alpha(previous: Option<Instant>, current: Option<Instant>, now: Instant) -> f32 {
+    let (Some(previous), Some(current)) = (previous, current) else {
+        return 1.0;
+    };
+    let interval = current.saturating_duration_since(previous).as_secs_f32();
+    if interval <= 0.0 {
+        return 1.0;
+    }
+    (now.saturating_duration_since(current).as_secs_f32() / interval).min(1.0)
+}
+
+// What the two threads swap under the lock
+#[derive(Default)]
+struct Shared {
+    // the newest frame, and whether the render has taken it yet
+    latest: PhysicsFrame,
+    fresh: bool,
+    // set when the settings change, the thread takes them before its next step
+    settings: Option<SimulationSettings>,
+    // steps per second, 0 for as fast as possible
+    rate: f32,
+    energy_needed: bool,
+    stop: bool,
+    // set by the thread when a body stopped being finite, it steps no further
+    halted: bool,
+}
+
+struct Running {
+    shared: Arc<Mutex<Shared>>,
+    handle: JoinHandle<HeadlessSim>,
+    // which entity each body on the thread is, the tracers after the massive bodies
+    entities: Vec<Entity>,
+}
+
+// Runs the physics on a thread of its own, as fast as it can or at a fixed rate, however long
+// the frames take. The render gets a copy of the positions and velocities after each step and puts
+// the bodies between the two newest copies, so the motion is smooth at any rate. Only gravity runs
+// there, so the thread waits while anything else that moves the bodies is on.
+#[derive(Resource, Default)]
+pub struct PhysicsThread {
+    pub enabled: bool,
+    // a frame's worth of steps per second, 0 for as fast as possible
+    pub rate: f32,
+    pub steps_per_sec: f32,
+    // what keeps the physics on the main thread while enabled, empty when nothing does
+    pub blocked_by: Vec<&'static str>,
+    running: Option<Running>,
+    previous: PhysicsFrame,
+    current: PhysicsFrame,
+}
+
+impl PhysicsThread {
+    pub fn running(&self) -> bool {
+        self.running.is_some()
+    }
+
+    fn start(
+        &mut self,
+        settings: &SimulationSettings,
+        energy_needed: bool,
+        state: Vec<(Entity, Body, Transform, Vec3, bool)>,
+    ) {
+        let (tracers, bodies): (Vec<_>, Vec<_>) = state.into_iter().partition(|(.., tracer)| *tracer);
+        let entities = bodies.iter().chain(&tracers).map(|(entity, ..)| *entity).collect();
+        let mut sim = HeadlessSim::from_state(
+            bodies.into_iter().map(|(_, body, transform, velocity, _)| (body, transform, velocity)),
+        );
+        sim.tracers =
+            tracers.into_iter().map(|(_, body, transform, velocity, _)| (body, transform, velocity)).collect();
+        let shared = Arc::new(Mutex::new(Shared {
+            settings: Some(settings.clone()),
+            rate: self.rate,
+            energy_needed,
+            ..default()
+        }));
+        let thread_shared = shared.clone();
+        let handle = std::thread::Builder::new()
+            .name("physics".to_string())
+            .spawn(move || run(thread_shared, sim))
+            .expect("failed to start the physics thread");
+        self.previous = PhysicsFrame::default();
+        self.current = PhysicsFrame::default();
+        self.running = Some(Running { shared, handle, entities });
+    }
+
+    // The state the thread left the bodies in, none if it is not running
+    fn stop(&mut self) -> Option<(Vec<Entity>, HeadlessSim)> {
+        let running = self.running.take()?;
+        running.shared.lock().unwrap().stop = true;
+        let sim = running.handle.join().expect("the physics thread panicked");
+        self.steps_per_sec = 0.0;
+        Some((running.entities, sim))
+    }
+
+    // Moves the newest frame over from the thread, true if there was one
+    fn receive(&mut self) -> bool {
+        let Some(running) = &self.running else {
+            return false;
+        };
+        let mut shared = running.shared.lock().unwrap();
+        if !shared.fresh {
+            return false;
+        }
+        shared.fresh = false;
+        // three buffers go round, the oldest is handed back to the thread to write into
+        std::mem::swap(&mut self.previous, &mut self.current);
+        std::mem::swap(&mut self.current, &mut shared.latest);
+        true
+    }
+}
+
+// Every body's transform and velocity in the order of the entities, the tracers last
+fn states(sim: &HeadlessSim) -> impl Iterator<Item = (&Transform, &Vec3)> {
+    sim.transforms
+        .iter()
+        .zip(&sim.velocities)
+        .chain(sim.tracers.iter().map(|(_body, transform, velocity)| (transform, velocity)))
+}
+
+// The same sums update records, the bulk motion of the whole system taken out of the internal kinetic energy
+fn measure_energy(sim: &HeadlessSim, settings: &SimulationSettings) -> (f64, f64, f64) {
+    let (kinetic, potential) = sim.energy(settings);
+    let mut momentum = DVec2::ZERO;
+    let mut total_mass = 0.0;
+    for (body, velocity) in sim.bodies.iter().zip(&sim.velocities) {
+        let m = body.mass as f64;
+        momentum += m * velocity.truncate().as_dvec2();
+        total_mass += m;
+    }
+    let bulk = if total_mass > 0.0 { 0.5 * momentum.length_squared() / total_mass } else { 0.0 };
+    (kinetic, kinetic - bulk, potential)
+}
+
+fn run(shared: Arc<Mutex<Shared>>, mut sim: HeadlessSim) -> HeadlessSim {
+    let mut settings = SimulationSettings::default();
+    let mut back = PhysicsFrame::default();
+    let mut next_step = Instant::now();
+    // only for carrying over the fractions of a step, like the main thread's clock does between frames
+    let mut clock = SimulationClock::default();
+    loop {
+        let (rate, energy_needed) = {
+            let mut shared = shared.lock().unwrap();
+            if shared.stop {
+                return sim;
+            }
+            if let Some(new) = shared.settings.take() {
+                settings = new;
+            }
+            (shared.rate, shared.energy_needed)
+        };
+        if settings.paused {
+            std::thread::sleep(PAUSED_POLL);
+            next_step = Instant::now();
+            continue;
+        }
+        if rate > 0.0 {
+            let now = Instant::now();
+            if next_step > now {
+                std::thread::sleep(next_step - now);
+            }
+            // after falling far behind, start counting again instead of rushing to catch up
+            let behind = now.checked_sub(Duration::from_secs(1)).unwrap_or(now);
+            next_step = next_step.max(behind) + Duration::from_secs_f32(1.0 / rate);
+        }
+
+        // as many steps as a frame would take, with the same dt
+        let (substeps, dt) = settings.pacing();
+        let steps = clock.take_substeps(substeps);
+        if steps == 0 {
+            continue;
+        }
+        for _ in 0..steps {
+            sim.step_dt(&settings, dt);
+        }
+        // a body that isn't finite any more would break the tree for all the others, the bodies go
+        // back to the main thread and its NaN guard instead
+        let finite =
+            states(&sim).all(|(transform, velocity)| transform.translation.is_finite() && velocity.is_finite());
+
+        back.positions.clear();
+        back.positions.extend(states(&sim).map(|(transform, _velocity)| transform.translation.truncate()));
+        back.velocities.clear();
+        back.velocities.extend(states(&sim).map(|(_transform, velocity)| velocity.truncate()));
+        back.energy = (energy_needed && finite).then(|| measure_energy(&sim, &settings));
+        back.time = sim.time;
+        back.steps = sim.steps;
+        back.at = Some(Instant::now());
+        let mut shared = shared.lock().unwrap();
+        std::mem::swap(&mut shared.latest, &mut back);
+        shared.fresh = true;
+        if !finite {
+            shared.halted = true;
+            return sim;
+        }
+    }
+}
+
+type ThreadBodies<'w, 's> = Query<
+    'w,
+    's,
+    (Entity, &'static Body, &'static mut Transform, &'static mut Velocity, Has<Massless>),
+    Without<Quarantined>,
+>;
+
+// What else can change the bodies while the thread has them, besides the settings
+#[derive(Default)]
+pub struct MainThreadNeeds {
+    pub controllers: bool,
+    pub pinned: bool,
+    pub attractor: bool,
+    pub flow: bool,
+    pub explosion_tool: bool,
+    pub selection: bool,
+    pub timeline: bool,
+    pub virial_warning: bool,
+    pub non_finite: bool,
+}
+
+// What the main thread does on top of gravity that the thread's step leaves out, and what changes
+// velocities on the main thread that the thread would overwrite. Running the thread with any of it on
+// would quietly drop it, so the physics stays on the main thread instead.
+pub fn unsupported_features(
+    settings: &SimulationSettings,
+    providers: &ForceProviders,
+    tag_groups: &TagGroups,
+    needs: &MainThreadNeeds,
+) -> Vec<&'static str> {
+    [
+        (settings.collision_enabled, "Collisions"),
+        (settings.post_newtonian, "Post-Newtonian Correction"),
+        (settings.radiation_enabled, "Radiation Pressure"),
+        (settings.trace_enabled, "Step Trace"),
+        (providers.any_enabled(), "Force Providers"),
+        (!tag_groups.all_interact(), "Group Interactions"),
+        (needs.controllers, "Controllers"),
+        (needs.pinned, "Pinned Bodies"),
+        (needs.attractor, "Cursor Attractor"),
+        (needs.flow, "Gas Flow"),
+        (needs.explosion_tool, "Explosion Tool"),
+        (needs.selection, "Selected Bodies"),
+        (needs.timeline, "Timeline"),
+        (needs.virial_warning, "Virial Warning"),
+        (needs.non_finite, "Non-Finite Bodies"),
+    ]
+    .into_iter()
+    .filter_map(|(on, name)| on.then_some(name))
+    .collect()
+}
+
+pub fn physics_on_main_thread(physics_thread: Res<PhysicsThread>) -> bool {
+    !physics_thread.running()
+}
+
+// Writes what the thread did back into the bodies that are still there
+fn write_back(entities: &[Entity], sim: &HeadlessSim, bodies: &mut ThreadBodies) {
+    for (entity, (state, state_velocity)) in entities.iter().zip(states(sim)) {
+        if let Ok((_, _, mut transform, mut velocity, _)) = bodies.get_mut(*entity) {
+            transform.translation.x = state.translation.x;
+            transform.translation.y = state.translation.y;
+            velocity.0 = *state_velocity;
+        }
+    }
+}
+
+pub fn update_physics_thread(
+    mut physics_thread: ResMut<PhysicsThread>,
+    settings: Res<SimulationSettings>,
+    (mut clock, mut energy): (ResMut<SimulationClock>, ResMut<Energy>),
+    mut bodies: ThreadBodies,
+    (providers, tag_groups, attractor): (Res<ForceProviders>, Res<TagGroups>, Res<Attractor>),
+    (controllers, pinned, selected): (Query<&Controller>, Query<(), With<Pinned>>, Query<(), With<Selected>>),
+    // left out of the physics-only app the tests use
+    (flow, tool, timeline, virial): (
+        Option<Res<BackgroundFlow>>,
+        Option<Res<ActiveTool>>,
+        Option<Res<Timeline>>,
+        Option<Res<VirialMonitor>>,
+    ),
+) {
+    let halted = physics_thread.running.as_ref().is_some_and(|running| running.shared.lock().unwrap().halted);
+    let needs = MainThreadNeeds {
+        controllers: controllers.iter().any(|controller| controller.enabled),
+        pinned: !pinned.is_empty(),
+        attractor: attractor.pos.is_some(),
+        flow: flow.is_some_and(|flow| flow.enabled),
+        explosion_tool: tool.is_some_and(|tool| *tool == ActiveTool::Explosion),
+        selection: !selected.is_empty(),
+        timeline: timeline.is_some_and(|timeline| timeline.playing),
+        virial_warning: virial.is_some_and(|virial| virial.warning.is_some()),
+        non_finite: halted
+            || bodies
+                .iter()
+                .any(|(_, _, transform, velocity, _)| !transform.translation.is_finite() || !velocity.0.is_finite()),
+    };
+    physics_thread.blocked_by = unsupported_features(&settings, &providers, &tag_groups, &needs);
+    // threads can't be started in the browser
+    let wanted = physics_thread.enabled && physics_thread.blocked_by.is_empty() && !cfg!(target_arch = "wasm32");
+
+    // bodies were added or removed (a reset, a spawn, an undo), start over with them
+    let changed = physics_thread.running.as_ref().is_some_and(|running| {
+        running.entities.len() != bodies.iter().count()
+            || running.entities.iter().any(|entity| !bodies.contains(*entity))
+    });
+    if (!wanted || changed)
+        && let Some((entities, sim)) = physics_thread.stop()
+    {
+        write_back(&entities, &sim, &mut bodies);
+    }
+    if !wanted {
+        return;
+    }
+    if !physics_thread.running() {
+        let state = bodies
+            .iter()
+            .map(|(entity, body, transform, velocity, tracer)| (entity, *body, *transform, velocity.0, tracer))
+            .collect();
+        physics_thread.start(&settings, energy.needed(), state);
+        return;
+    }
+
+    let rate = physics_thread.rate;
+    if let Some(running) = &physics_thread.running {
+        let mut shared = running.shared.lock().unwrap();
+        shared.rate = rate;
+        shared.energy_needed = energy.needed();
+        if settings.is_changed() {
+            shared.settings = Some(settings.clone());
+        }
+    }
+
+    if physics_thread.receive() {
+        // the clock counts on from wherever it was, a reset on this side still sets it back to 0
+        let (previous, current) = (&physics_thread.previous, &physics_thread.current);
+        let steps = current.steps.saturating_sub(previous.steps);
+        if previous.at.is_some() {
+            clock.time += current.time - previous.time;
+            clock.steps += steps;
+        }
+        if steps > 0 {
+            clock.dt = settings.pacing().1;
+            if let Some((kinetic, internal_kinetic, potential)) = current.energy {
+                energy.record(kinetic, internal_kinetic, potential);
+            }
+        }
+        let interval = match (previous.at, current.at) {
+            (Some(a), Some(b)) => b.saturating_duration_since(a).as_secs_f32(),
+            _ => 0.0,
+        };
+        if interval > 0.0 {
+            physics_thread.steps_per_sec = steps as f32 / interval;
+        }
+    }
+
+    let alpha = interpolation_alpha(physics_thread.previous.at, physics_thread.current.at, Instant::now());
+    let (previous, current) = (&physics_thread.previous, &physics_thread.current);
+    let positions = interpolate(&previous.positions, &current.positions, alpha);
+    // the velocities go along, so whatever reads them on this side sees the run as it is drawn
+    let velocities = interpolate(&previous.velocities, &current.velocities, alpha);
+    let Some(running) = &physics_thread.running else {
+        return;
+    };
+    for ((entity, position), moving) in running.entities.iter().zip(positions).zip(velocities) {
+        if let Ok((_, _, mut transform, mut velocity, _)) = bodies.get_mut(*entity) {
+            transform.translation.x = position.x;
+            transform.translation.y = position.y;
+            velocity.0.x = moving.x;
+            velocity.0.y = moving.y;
+        }
+    }
+}
+
+pub fn physics_thread_window(
+    mut contexts: EguiContexts,
+    mut physics_thread: ResMut<PhysicsThread>,
+    ui_config: Res<UiConfig>,
+) {
+    ui_config
+        .place(egui::Window::new("Physics Thread"), "Physics Thread")
+        .default_open(false)
+        .show(contexts.ctx_mut(), |ui| {
+            if cfg!(target_arch = "wasm32") {
+                ui.label("Not available in the browser");
+                return;
+            }
+            ui.add(egui::Checkbox::new(&mut physics_thread.enabled, "Run Physics On Its Own Thread"));
+            ui.add(
+                egui::Slider::new(&mut physics_thread.rate, 0.0..=2000.0)
+                    .text("Rounds Per Second")
+                    .custom_formatter(|n, _| if n == 0.0 { "As Fast As Possible".to_string() } else { n.to_string() }),
+            );
+            if physics_thread.running() {
+                ui.label(format!("{:.0} steps per second", physics_thread.steps_per_sec));
+            }
+            if physics_thread.enabled && !physics_thread.blocked_by.is_empty() {
+                ui.colored_label(
+                    egui::Color32::YELLOW,
+                    format!("Running on the main thread while on: {}", physics_thread.blocked_by.join(", ")),
+                );
+            }
+            ui.label("Gravity only, with the plain tree walk");
+            ui.label("Each round takes a frame's worth of steps, Substeps Per Frame times Time Scale");
+        });
+}
@@ -0,0 +1,148 @@
+use bevy::prelude::*;
+use bevy::render::camera::Viewport;
+use bevy::window::PrimaryWindow;
+use bevy_egui::{EguiContexts, egui};
+use crate::lagrangian::densest_point;
+use crate::selection::Selected;
+use crate::tracer::Massless;
+use crate::ui_config::UiConfig;
+use crate::Body;
+
+// Gap between the inset and the window edges, in physical pixels
+const MARGIN: u32 = 10;
+
+// Camera of the inset, drawn after the main view into a corner of the window
+#[derive(Component)]
+pub struct PipCamera;
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum PipTarget {
+    // the selected body, when exactly one is selected, otherwise the densest clump
+    Selected,
+    DensestClump,
+}
+
+// A zoomed-in inset that follows one body, for watching a tight binary while keeping the whole view
+#[derive(Resource)]
+pub struct PictureInPicture {
+    pub enabled: bool,
+    pub target: PipTarget,
+    // world units per pixel in the inset, the main view starts at 1
+    pub zoom: f32,
+    // inset width as a fraction of the window width, it is square
+    pub size: f32,
+    // how often the densest clump is looked for, it needs a density for every body
+    pub every_frames: u32,
+    pub center: Option<Vec2>,
+    frames: u32,
+}
+
+impl Default for PictureInPicture {
+    fn default() -> Self {
+        PictureInPicture {
+            enabled: false,
+            target: PipTarget::Selected,
+            zoom: 0.1,
+            size: 0.3,
+            every_frames: 30,
+            center: None,
+            frames: 0,
+        }
+    }
+}
+
+// Square inset in the bottom right corner as (position, size) in physical pixels, away from the settings windows
+pub fn inset_viewport(window: UVec2, size: f32) -> Option<(UVec2, UVec2)> {
+    let side = (window.x as f32 * size.clamp(0.0, 1.0)) as u32;
+    let side = side.min(window.y.saturating_sub(2 * MARGIN));
+    if side == 0 || window.x < side + MARGIN {
+        return None;
+    }
+    Some((UVec2::new(window.x - side - MARGIN, window.y - side - MARGIN), UVec2::splat(side)))
+}
+
+pub fn update_pip(
+    mut pip: ResMut<PictureInPicture>,
+    windows: Query<&Window, With<PrimaryWindow>>,
+    mut cameras: Query<(Entity, &mut Camera, &mut Transform, &mut Projection), With<PipCamera>>,
+    selected: Query<&Transform, (With<Selected>, Without<PipCamera>)>,
+    bodies: Query<(&Transform, &Body), (Without<Massless>, Without<PipCamera>)>,
+    mut commands: Commands,
+) {
+    if !pip.enabled {
+        for (entity, ..) in &cameras {
+            commands.entity(entity).despawn();
+        }
+        pip.center = None;
+        return;
+    }
+
+    // follow the selected body every frame, the densest clump only now and then
+    match (pip.target, selected.single().ok()) {
+        (PipTarget::Selected, Some(transform)) => pip.center = Some(transform.translation.truncate()),
+        _ => {
+            pip.frames += 1;
+            if pip.frames >= pip.every_frames || pip.center.is_none() {
+                pip.frames = 0;
+                let (masses, positions): (Vec<f32>, Vec<Vec2>) = bodies
+                    .iter()
+                    .map(|(transform, body)| (body.mass, transform.translation.truncate()))
+                    .unzip();
+                pip.center = densest_point(&masses, &positions);
+            }
+        }
+    }
+
+    let Ok(window) = windows.single() else {
+        return;
+    };
+    let viewport = inset_viewport(UVec2::new(window.physical_width(), window.physical_height()), pip.size);
+
+    let Ok((_entity, mut camera, mut transform, mut projection)) = cameras.single_mut() else {
+        commands.spawn((
+            Camera2d,
+            Camera {
+                // drawn over the main view
+                order: 1,
+                clear_color: ClearColorConfig::Custom(Color::srgb(0.02, 0.02, 0.05)),
+                ..default()
+            },
+            PipCamera,
+        ));
+        return;
+    };
+    camera.viewport = viewport.map(|(physical_position, physical_size)| Viewport {
+        physical_position,
+        physical_size,
+        ..default()
+    });
+    camera.is_active = camera.viewport.is_some() && pip.center.is_some();
+    if let Some(center) = pip.center {
+        transform.translation = center.extend(transform.translation.z);
+    }
+    if let Projection::Orthographic(ortho) = &mut *projection {
+        ortho.scale = pip.zoom;
+    }
+}
+
+pub fn pip_window(mut contexts: EguiContexts, mut pip: ResMut<PictureInPicture>, ui_config: Res<UiConfig>) {
+    ui_config
+        .place(egui::Window::new("Picture In Picture"), "Picture In Picture")
+        .default_open(false)
+        .show(contexts.ctx_mut(), |ui| {
+            ui.add(egui::Checkbox::new(&mut pip.enabled, "Show Inset"));
+            ui.horizontal(|ui| {
+                ui.label("Follow:");
+                ui.radio_value(&mut pip.target, PipTarget::Selected, "Selected Body");
+                ui.radio_value(&mut pip.target, PipTarget::DensestClump, "Densest Clump");
+            });
+            ui.add(egui::Slider::new(&mut pip.zoom, 0.001..=1.0).logarithmic(true).text("Zoom (world units per pixel)"));
+            ui.add(egui::Slider::new(&mut pip.size, 0.1..=0.6).text("Inset Size"));
+            if pip.target == PipTarget::DensestClump {
+                ui.add(egui::Slider::new(&mut pip.every_frames, 1..=300).text("Look For Clump Every N Frames"));
+            }
+            if pip.enabled && pip.center.is_none() {
+                ui.label("Nothing to follow");
+            }
+        });
+}
@@ -0,0 +1,47 @@
+use bevy::prelude::*;
+use bevy::tasks::{AsyncComputeTaskPool, Task, block_on};
+use crate::bhtree::{Quad, Quadtree};
+use crate::Body;
+
+// What a tree is built from, in insertion order
+pub type TreeInputs = Vec<(Entity, Transform, Body)>;
+
+pub fn build_tree(inputs: &[(Entity, Transform, Body)], leaf_capacity: usize) -> Quadtree {
+    let positions: Vec<Vec2> = inputs.iter().map(|(_, transform, _)| transform.translation.truncate()).collect();
+    let mut tree = Quadtree::with_leaf_capacity(Quad::new_containing(&positions), leaf_capacity);
+    for (entity, transform, body) in inputs {
+        tree.insert(*entity, *transform, *body);
+    }
+    tree
+}
+
+// The same tree comes out of both, only positions and masses go into it
+pub fn same_inputs(a: &[(Entity, Transform, Body)], b: &[(Entity, Transform, Body)]) -> bool {
+    a.len() == b.len()
+        && a.iter().zip(b).all(|((ea, ta, ba), (eb, tb, bb))| {
+            ea == eb && ta.translation.truncate() == tb.translation.truncate() && ba.mass == bb.mass
+        })
+}
+
+// The tree for the next frame, built on a background task from where the bodies were left at the end
+// of this one, while the frame is rendered. Anything that moves a body in between (collisions, tools,
+// a reset) makes it useless, then the tree is built the usual way.
+#[derive(Default)]
+pub struct PipelinedTree {
+    task: Option<Task<(TreeInputs, usize, Quadtree)>>,
+}
+
+impl PipelinedTree {
+    pub fn start(&mut self, inputs: TreeInputs, leaf_capacity: usize) {
+        self.task = Some(AsyncComputeTaskPool::get().spawn(async move {
+            let tree = build_tree(&inputs, leaf_capacity);
+            (inputs, leaf_capacity, tree)
+        }));
+    }
+
+    // Waits for the tree if it isn't done yet, that is never slower than building it again
+    pub fn take(&mut self, inputs: &[(Entity, Transform, Body)], leaf_capacity: usize) -> Option<Quadtree> {
+        let (built_from, built_capacity, tree) = block_on(self.task.take()?);
+        (built_capacity == leaf_capacity && same_inputs(&built_from, inputs)).then_some(tree)
+    }
+}
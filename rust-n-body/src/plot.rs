@@ -0,0 +1,29 @@
+use bevy_egui::egui;
+
+// Small line plot over time, every series scaled to the largest value of all of them so they can be compared
+pub fn line_plot(ui: &mut egui::Ui, series: &[(&[f32], egui::Color32)]) {
+    let (rect, _response) = ui.allocate_exact_size(egui::vec2(200.0, 40.0), egui::Sense::hover());
+    let max = series
+        .iter()
+        .flat_map(|(values, _color)| values.iter().copied())
+        .fold(0.0, f32::max);
+    if max <= 0.0 {
+        return;
+    }
+    for (values, color) in series {
+        if values.len() < 2 {
+            continue;
+        }
+        let points: Vec<egui::Pos2> = values
+            .iter()
+            .enumerate()
+            .map(|(i, v)| {
+                egui::pos2(
+                    rect.left() + rect.width() * i as f32 / (values.len() - 1) as f32,
+                    rect.bottom() - rect.height() * v / max,
+                )
+            })
+            .collect();
+        ui.painter().add(egui::Shape::line(points, egui::Stroke::new(1.5, *color)));
+    }
+}
@@ -0,0 +1,291 @@
+use bevy::prelude::*;
+use bevy_egui::{EguiContexts, egui};
+use std::collections::HashMap;
+use crate::colormap::group_shade;
+use crate::tracer::{Massless, tracer_bundle, tracer_mesh};
+use crate::ui_config::UiConfig;
+use crate::{Body, SimulationSettings, Velocity};
+
+// Oldest points are dropped past this, a few hundred crossings of a hundred tracers
+const MAX_POINTS: usize = 50_000;
+
+// Surface of section in the frame rotating with the binary, crossed going in the positive direction
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Section {
+    // y = 0 going up, plotted as (x, vx)
+    Y,
+    // x = 0 going right, plotted as (y, vy)
+    X,
+}
+
+impl Section {
+    pub const ALL: [Section; 2] = [Section::Y, Section::X];
+
+    pub fn label(self) -> &'static str {
+        match self {
+            Section::Y => "y = 0, plot x against vx",
+            Section::X => "x = 0, plot y against vy",
+        }
+    }
+}
+
+// The two primaries of the restricted problem as (mass, position, velocity), heavier first
+pub type Primaries = [(f32, Vec2, Vec2); 2];
+
+// The binary's rotating frame: center of mass at the origin, the heavier primary on the negative x axis.
+// Lengths are in units of the separation and velocities in units of omega times it, so sections
+// of binaries of any size look the same.
+pub struct RotatingFrame {
+    com: Vec2,
+    com_vel: Vec2,
+    // rotation from the simulation frame into the rotating one
+    rotation: Vec2,
+    omega: f32,
+    separation: f32,
+    // mass fraction of the lighter primary
+    pub mu: f32,
+}
+
+impl RotatingFrame {
+    pub fn new(primaries: Primaries) -> Option<Self> {
+        let [(m1, p1, v1), (m2, p2, v2)] = primaries;
+        let total = m1 + m2;
+        let rel = p2 - p1;
+        let separation = rel.length();
+        if total <= 0.0 || separation <= 0.0 {
+            return None;
+        }
+        let omega = rel.perp_dot(v2 - v1) / rel.length_squared();
+        if omega == 0.0 {
+            return None;
+        }
+        Some(RotatingFrame {
+            com: (m1 * p1 + m2 * p2) / total,
+            com_vel: (m1 * v1 + m2 * v2) / total,
+            rotation: Vec2::new(rel.x, -rel.y) / separation,
+            omega,
+            separation,
+            mu: m2 / total,
+        })
+    }
+
+    pub fn to_rotating(&self, pos: Vec2, vel: Vec2) -> (Vec2, Vec2) {
+        let r = self.rotation.rotate(pos - self.com);
+        // the frame itself turns, which takes omega × r off every velocity
+        let v = self.rotation.rotate(vel - self.com_vel) - self.omega * r.perp();
+        (r / self.separation, v / (self.omega * self.separation))
+    }
+
+    pub fn to_inertial(&self, pos: Vec2, vel: Vec2) -> (Vec2, Vec2) {
+        let (r, v) = (pos * self.separation, vel * self.omega * self.separation);
+        let back = Vec2::new(self.rotation.x, -self.rotation.y);
+        (self.com + back.rotate(r), self.com_vel + back.rotate(v + self.omega * r.perp()))
+    }
+}
+
+// Jacobi constant in the rotating frame's units, 2 Omega - v². It's conserved along every tracer's
+// path, so tracers started with the same value share one section.
+pub fn jacobi_constant(mu: f32, pos: Vec2, vel: Vec2) -> f32 {
+    2.0 * effective_potential(mu, pos) - vel.length_squared()
+}
+
+fn effective_potential(mu: f32, pos: Vec2) -> f32 {
+    let r1 = pos.distance(Vec2::new(-mu, 0.0));
+    let r2 = pos.distance(Vec2::new(1.0 - mu, 0.0));
+    0.5 * pos.length_squared() + (1.0 - mu) / r1 + mu / r2
+}
+
+// Where the step from prev to cur went through the section, interpolated, as a point of the plot
+pub fn section_crossing(section: Section, prev: (Vec2, Vec2), cur: (Vec2, Vec2)) -> Option<Vec2> {
+    let (before, after) = match section {
+        Section::Y => (prev.0.y, cur.0.y),
+        Section::X => (prev.0.x, cur.0.x),
+    };
+    if !(before < 0.0 && after >= 0.0) {
+        return None;
+    }
+    let t = before / (before - after);
+    let (pos, vel) = (prev.0.lerp(cur.0, t), prev.1.lerp(cur.1, t));
+    Some(match section {
+        Section::Y => Vec2::new(pos.x, vel.x),
+        Section::X => Vec2::new(pos.y, vel.y),
+    })
+}
+
+// Tracers on the section line at the given Jacobi constant, standing still along it so only the
+// crossing velocity is left. Places the forbidden region doesn't allow are skipped.
+pub fn section_starts(mu: f32, jacobi: f32, from: f32, to: f32, n: u32) -> Vec<(Vec2, Vec2)> {
+    (0..n)
+        .filter_map(|i| {
+            let x = from + (to - from) * (i as f32 + 0.5) / n as f32;
+            let pos = Vec2::new(x, 0.0);
+            let v2 = jacobi_constant(mu, pos, Vec2::ZERO) - jacobi;
+            (v2 > 0.0).then(|| (pos, Vec2::new(0.0, v2.sqrt())))
+        })
+        .collect()
+}
+
+#[derive(Resource)]
+pub struct PoincareSection {
+    pub enabled: bool,
+    pub section: Section,
+    // crossings and the tracer they came from
+    pub points: Vec<(Vec2, u32)>,
+    pub jacobi: f32,
+    pub start_from: f32,
+    pub start_to: f32,
+    pub n_starts: u32,
+    pub spawn_requested: bool,
+    previous: HashMap<Entity, (Vec2, Vec2)>,
+}
+
+impl Default for PoincareSection {
+    fn default() -> Self {
+        PoincareSection {
+            enabled: false,
+            section: Section::Y,
+            points: Vec::new(),
+            jacobi: 3.5,
+            start_from: -1.0,
+            start_to: -0.6,
+            n_starts: 40,
+            spawn_requested: false,
+            previous: HashMap::new(),
+        }
+    }
+}
+
+fn primaries(massive: &Query<(&Transform, &Velocity, &Body), Without<Massless>>) -> Option<Primaries> {
+    let mut heaviest: Vec<(f32, Vec2, Vec2)> = massive
+        .iter()
+        .map(|(t, v, b)| (b.mass, t.translation.truncate(), v.0.truncate()))
+        .collect();
+    heaviest.sort_by(|a, b| b.0.total_cmp(&a.0));
+    match heaviest[..] {
+        [first, second, ..] => Some([first, second]),
+        _ => None,
+    }
+}
+
+pub fn record_crossings(
+    settings: Res<SimulationSettings>,
+    massive: Query<(&Transform, &Velocity, &Body), Without<Massless>>,
+    tracers: Query<(Entity, &Transform, &Velocity), With<Massless>>,
+    mut poincare: ResMut<PoincareSection>,
+    mut commands: Commands,
+    mut materials: ResMut<Assets<ColorMaterial>>,
+    mut meshes: ResMut<Assets<Mesh>>,
+) {
+    let frame = primaries(&massive).and_then(RotatingFrame::new);
+
+    if poincare.spawn_requested {
+        poincare.spawn_requested = false;
+        if let Some(frame) = &frame {
+            let mesh = tracer_mesh(&mut meshes);
+            let material = materials.add(ColorMaterial::from_color(Color::WHITE));
+            let starts = section_starts(
+                frame.mu,
+                poincare.jacobi,
+                poincare.start_from,
+                poincare.start_to,
+                poincare.n_starts,
+            );
+            for (pos, vel) in starts {
+                let (pos, vel) = frame.to_inertial(pos, vel);
+                commands.spawn(tracer_bundle(&mesh, &material, pos, vel, settings.z));
+            }
+        }
+    }
+
+    let Some(frame) = frame.filter(|_| poincare.enabled) else {
+        poincare.previous.clear();
+        return;
+    };
+    if settings.paused {
+        return;
+    }
+
+    let section = poincare.section;
+    let mut previous = std::mem::take(&mut poincare.previous);
+    for (entity, transform, velocity) in &tracers {
+        let cur = frame.to_rotating(transform.translation.truncate(), velocity.0.truncate());
+        if let Some(point) = previous.get(&entity).and_then(|prev| section_crossing(section, *prev, cur)) {
+            poincare.points.push((point, entity.index()));
+        }
+        previous.insert(entity, cur);
+    }
+    // forget tracers that are gone
+    previous.retain(|entity, _| tracers.contains(*entity));
+    poincare.previous = previous;
+
+    if poincare.points.len() > MAX_POINTS {
+        let excess = poincare.points.len() - MAX_POINTS;
+        poincare.points.drain(..excess);
+    }
+}
+
+pub fn poincare_window(
+    mut contexts: EguiContexts,
+    mut poincare: ResMut<PoincareSection>,
+    settings: Res<SimulationSettings>,
+    ui_config: Res<UiConfig>,
+) {
+    ui_config
+        .place(egui::Window::new("Poincare Section"), "Poincare Section")
+        .default_open(false)
+        .show(contexts.ctx_mut(), |ui| {
+            ui.label("Tracers around the two heaviest bodies, in the frame turning with them");
+            ui.add(egui::Checkbox::new(&mut poincare.enabled, "Record Crossings"));
+            let current = poincare.section;
+            egui::ComboBox::from_label("Section")
+                .selected_text(current.label())
+                .show_ui(ui, |ui| {
+                    for section in Section::ALL {
+                        ui.selectable_value(&mut poincare.section, section, section.label());
+                    }
+                });
+            if poincare.section != current || ui.button("Clear").clicked() {
+                poincare.points.clear();
+            }
+
+            ui.separator();
+            ui.add(egui::Slider::new(&mut poincare.jacobi, 2.0..=5.0).text("Jacobi Constant"));
+            ui.add(egui::Slider::new(&mut poincare.start_from, -2.0..=2.0).text("Start x From"));
+            ui.add(egui::Slider::new(&mut poincare.start_to, -2.0..=2.0).text("Start x To"));
+            ui.add(egui::Slider::new(&mut poincare.n_starts, 1..=500).text("Tracers"));
+            if ui.button("Spawn Tracers On The Section").clicked() {
+                poincare.spawn_requested = true;
+            }
+
+            let (rect, _response) = ui.allocate_exact_size(egui::vec2(300.0, 300.0), egui::Sense::hover());
+            let painter = ui.painter();
+            painter.rect_filled(rect, 0.0, egui::Color32::from_black_alpha(120));
+            let (min, max) = poincare
+                .points
+                .iter()
+                .fold((Vec2::MAX, Vec2::MIN), |(min, max), (p, _id)| (min.min(*p), max.max(*p)));
+            let span = (max - min).max(Vec2::splat(1e-6));
+            for (point, id) in &poincare.points {
+                let t = (*point - min) / span;
+                let [r, g, b] = settings.colormap.sample_rgb(group_shade(*id as usize));
+                painter.rect_filled(
+                    egui::Rect::from_center_size(
+                        egui::pos2(rect.left() + t.x * rect.width(), rect.bottom() - t.y * rect.height()),
+                        egui::vec2(1.5, 1.5),
+                    ),
+                    0.0,
+                    egui::Color32::from_rgb((r * 255.0) as u8, (g * 255.0) as u8, (b * 255.0) as u8),
+                );
+            }
+            if !poincare.points.is_empty() {
+                ui.label(format!(
+                    "{} crossings, horizontal {:.3} to {:.3}, vertical {:.3} to {:.3}",
+                    poincare.points.len(),
+                    min.x,
+                    max.x,
+                    min.y,
+                    max.y
+                ));
+            }
+        });
+}
@@ -0,0 +1,81 @@
+// Where a long headless run is, printed every so often so batch runs can be watched
+#[derive(Clone, Debug)]
+pub struct Progress {
+    // 1-based, out of `cases`
+    pub case: usize,
+    pub cases: usize,
+    pub step: u64,
+    pub total_steps: u64,
+    pub time: f64,
+    pub duration: f64,
+    pub steps_per_sec: f64,
+    pub energy_error: f64,
+    pub elapsed_secs: f64,
+}
+
+// Seconds left at the rate so far, none before anything is done
+pub fn eta_secs(done: f64, total: f64, elapsed_secs: f64) -> Option<f64> {
+    if done <= 0.0 || total <= 0.0 || !elapsed_secs.is_finite() {
+        return None;
+    }
+    Some((elapsed_secs * (total - done).max(0.0) / done).max(0.0))
+}
+
+// 1h02m03s, 4m05s or 6s
+pub fn format_duration(secs: f64) -> String {
+    let secs = secs.max(0.0).round() as u64;
+    let (h, m, s) = (secs / 3600, secs / 60 % 60, secs % 60);
+    if h > 0 {
+        format!("{}h{:02}m{:02}s", h, m, s)
+    } else if m > 0 {
+        format!("{}m{:02}s", m, s)
+    } else {
+        format!("{}s", s)
+    }
+}
+
+// JSON has no NaN or infinity
+pub fn json_number(value: f64) -> String {
+    if value.is_finite() { format!("{:?}", value) } else { "null".to_string() }
+}
+
+impl Progress {
+    // Steps are counted from the simulated time, the last step of a case may overshoot it
+    pub fn eta(&self) -> Option<f64> {
+        eta_secs(self.time, self.duration, self.elapsed_secs)
+    }
+
+    pub fn text(&self) -> String {
+        format!(
+            "[{}/{}] step {}/{} (t = {:.4} of {}), {:.0} steps/s, ETA {}, energy error {:.3e}",
+            self.case,
+            self.cases,
+            self.step,
+            self.total_steps,
+            self.time,
+            self.duration,
+            self.steps_per_sec,
+            self.eta().map_or("-".to_string(), format_duration),
+            self.energy_error
+        )
+    }
+
+    // One line of JSON, `event` says whether the case is still running or done
+    pub fn json(&self, event: &str) -> String {
+        format!(
+            "{{\"event\":\"{}\",\"case\":{},\"cases\":{},\"step\":{},\"total_steps\":{},\"time\":{},\"duration\":{},\
+             \"steps_per_sec\":{},\"eta_s\":{},\"energy_error\":{},\"elapsed_s\":{}}}",
+            event,
+            self.case,
+            self.cases,
+            self.step,
+            self.total_steps,
+            json_number(self.time),
+            json_number(self.duration),
+            json_number(self.steps_per_sec),
+            self.eta().map_or("null".to_string(), json_number),
+            json_number(self.energy_error),
+            json_number(self.elapsed_secs)
+        )
+    }
+}
@@ -0,0 +1,202 @@
+use bevy::prelude::*;
+use bevy_egui::{EguiContexts, egui};
+use crate::ui_config::UiConfig;
+
+// What a provider gets to see of the body it acts on, at the start of the step
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct ForceInput {
+    pub entity: Entity,
+    pub mass: f32,
+    pub position: Vec2,
+    pub velocity: Vec2,
+}
+
+// The whole system at the start of the step, for forces that depend on more than the body
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct WorldSummary {
+    pub time: f64,
+    pub g: f32,
+    pub bodies: usize,
+    pub total_mass: f32,
+    pub com: Vec2,
+    pub com_vel: Vec2,
+}
+
+impl WorldSummary {
+    // From (mass, position, velocity) of every body
+    pub fn new(time: f64, g: f32, bodies: impl Iterator<Item = (f32, Vec2, Vec2)>) -> Self {
+        let mut summary = WorldSummary {
+            time,
+            g,
+            ..Default::default()
+        };
+        for (mass, position, velocity) in bodies {
+            summary.bodies += 1;
+            summary.total_mass += mass;
+            summary.com += mass * position;
+            summary.com_vel += mass * velocity;
+        }
+        if summary.total_mass > 0.0 {
+            summary.com /= summary.total_mass;
+            summary.com_vel /= summary.total_mass;
+        }
+        summary
+    }
+}
+
+// A force outside of gravity between the bodies, added to every body on every step. Register one
+// with App::add_force_provider, the integrator needs no changes for it.
+pub trait ForceProvider: Send + Sync + 'static {
+    fn name(&self) -> &str;
+
+    // Acceleration on the body, the integrator multiplies it by the step
+    fn accel(&self, body: &ForceInput, world: &WorldSummary) -> Vec2;
+
+    // Controls for the provider's own parameters in the Custom Forces window
+    fn ui(&mut self, _ui: &mut egui::Ui) {}
+
+    // Providers with a fixed position move it back by shift when the world origin moves
+    fn rebase(&mut self, _shift: Vec2) {}
+}
+
+struct Registered {
+    provider: Box<dyn ForceProvider>,
+    enabled: bool,
+}
+
+// Every registered provider, in the order they were added
+#[derive(Resource, Default)]
+pub struct ForceProviders {
+    registered: Vec<Registered>,
+}
+
+impl ForceProviders {
+    pub fn register(&mut self, provider: impl ForceProvider, enabled: bool) {
+        self.registered.push(Registered {
+            provider: Box::new(provider),
+            enabled,
+        });
+    }
+
+    // Nothing to add, so the force loop can skip summarizing the world
+    pub fn any_enabled(&self) -> bool {
+        self.registered.iter().any(|registered| registered.enabled)
+    }
+
+    pub fn accel(&self, body: &ForceInput, world: &WorldSummary) -> Vec2 {
+        self.registered
+            .iter()
+            .filter(|registered| registered.enabled)
+            .map(|registered| registered.provider.accel(body, world))
+            .sum()
+    }
+
+    pub fn rebase(&mut self, shift: Vec2) {
+        for registered in &mut self.registered {
+            registered.provider.rebase(shift);
+        }
+    }
+}
+
+pub trait AddForceProvider {
+    fn add_force_provider(&mut self, provider: impl ForceProvider, enabled: bool) -> &mut Self;
+}
+
+impl AddForceProvider for App {
+    fn add_force_provider(&mut self, provider: impl ForceProvider, enabled: bool) -> &mut Self {
+        self.world_mut()
+            .get_resource_or_init::<ForceProviders>()
+            .register(provider, enabled);
+        self
+    }
+}
+
+// Linear drag, slowing every body by the same fraction of its speed per unit of time
+pub struct Drag {
+    pub coefficient: f32,
+}
+
+impl Default for Drag {
+    fn default() -> Self {
+        Drag { coefficient: 0.1 }
+    }
+}
+
+impl ForceProvider for Drag {
+    fn name(&self) -> &str {
+        "Drag"
+    }
+
+    fn accel(&self, body: &ForceInput, _world: &WorldSummary) -> Vec2 {
+        -self.coefficient * body.velocity
+    }
+
+    fn ui(&mut self, ui: &mut egui::Ui) {
+        ui.add(egui::Slider::new(&mut self.coefficient, 0.0..=10.0).logarithmic(true).text("Coefficient"));
+    }
+}
+
+// A fixed Plummer sphere, like a dark matter halo the bodies move in without it being simulated
+pub struct CentralPotential {
+    pub center: Vec2,
+    pub mass: f32,
+    // zero for a point mass
+    pub core_radius: f32,
+}
+
+impl Default for CentralPotential {
+    fn default() -> Self {
+        CentralPotential {
+            center: Vec2::ZERO,
+            mass: 10000.0,
+            core_radius: 50.0,
+        }
+    }
+}
+
+impl ForceProvider for CentralPotential {
+    fn name(&self) -> &str {
+        "Central Potential"
+    }
+
+    fn accel(&self, body: &ForceInput, world: &WorldSummary) -> Vec2 {
+        let r = body.position - self.center;
+        let d2 = r.length_squared() + self.core_radius * self.core_radius;
+        if d2 == 0.0 {
+            return Vec2::ZERO;
+        }
+        -world.g * self.mass * r / (d2 * d2.sqrt())
+    }
+
+    fn ui(&mut self, ui: &mut egui::Ui) {
+        ui.add(egui::DragValue::new(&mut self.center.x).prefix("Center X: "));
+        ui.add(egui::DragValue::new(&mut self.center.y).prefix("Center Y: "));
+        ui.add(egui::Slider::new(&mut self.mass, 0.0..=1e6).logarithmic(true).text("Mass"));
+        ui.add(egui::Slider::new(&mut self.core_radius, 0.0..=1000.0).text("Core Radius"));
+    }
+
+    fn rebase(&mut self, shift: Vec2) {
+        self.center -= shift;
+    }
+}
+
+pub fn providers_window(mut contexts: EguiContexts, mut providers: ResMut<ForceProviders>, ui_config: Res<UiConfig>) {
+    ui_config
+        .place(egui::Window::new("Custom Forces"), "Custom Forces")
+        .default_open(false)
+        .show(contexts.ctx_mut(), |ui| {
+            if providers.registered.is_empty() {
+                ui.label("No force providers registered");
+            }
+            for (i, registered) in providers.registered.iter_mut().enumerate() {
+                if i > 0 {
+                    ui.separator();
+                }
+                let name = registered.provider.name().to_string();
+                ui.checkbox(&mut registered.enabled, name);
+                if registered.enabled {
+                    registered.provider.ui(ui);
+                }
+            }
+        });
+}
@@ -0,0 +1,62 @@
+use crate::solver::NBodySim;
+use bevy::math::Vec2;
+use pyo3::exceptions::PyValueError;
+use pyo3::prelude::*;
+
+// Positions, velocities and masses
+type State = (Vec<(f32, f32)>, Vec<(f32, f32)>, Vec<f32>);
+
+// The solver as a Python module, rust_n_body.Simulation. Positions and velocities go in and out as lists
+// of (x, y) tuples in index order, so they convert to and from numpy arrays with np.array and tolist.
+#[pyclass(name = "Simulation")]
+pub struct PySimulation {
+    sim: NBodySim,
+}
+
+#[pymethods]
+impl PySimulation {
+    #[new]
+    #[pyo3(signature = (g = 1.0, theta = 0.5, dt = 0.01))]
+    fn new(g: f32, theta: f32, dt: f32) -> Self {
+        PySimulation {
+            sim: NBodySim::new(g, theta, dt),
+        }
+    }
+
+    // Returns the new body's index
+    fn add_body(&mut self, x: f32, y: f32, vx: f32, vy: f32, mass: f32) -> usize {
+        self.sim.add_body(Vec2::new(x, y), Vec2::new(vx, vy), mass)
+    }
+
+    #[pyo3(signature = (n = 1))]
+    fn step(&mut self, n: u32) {
+        for _ in 0..n {
+            self.sim.step();
+        }
+    }
+
+    fn get_state(&self) -> State {
+        let pairs = |v: &[Vec2]| v.iter().map(|p| (p.x, p.y)).collect();
+        (pairs(self.sim.positions()), pairs(self.sim.velocities()), self.sim.masses())
+    }
+
+    fn set_state(&mut self, positions: Vec<(f32, f32)>, velocities: Vec<(f32, f32)>, masses: Vec<f32>) -> PyResult<()> {
+        let vecs = |v: Vec<(f32, f32)>| v.into_iter().map(|(x, y)| Vec2::new(x, y)).collect();
+        self.sim.set_state(vecs(positions), vecs(velocities), &masses).map_err(PyValueError::new_err)
+    }
+
+    // The acceleration the tree gives at (x, y)
+    fn accel(&self, x: f32, y: f32) -> (f32, f32) {
+        let accel = self.sim.accel_at(Vec2::new(x, y));
+        (accel.x, accel.y)
+    }
+
+    fn __len__(&self) -> usize {
+        self.sim.body_count()
+    }
+}
+
+#[pymodule]
+pub fn rust_n_body(module: &Bound<'_, PyModule>) -> PyResult<()> {
+    module.add_class::<PySimulation>()
+}
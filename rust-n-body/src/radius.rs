@@ -0,0 +1,32 @@
+// How a body's size follows from its mass, at a fixed density
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Default)]
+pub enum RadiusLaw {
+    // flat discs, mass per unit area: r ∝ m^(1/2)
+    #[default]
+    Area,
+    // spheres, mass per unit volume: r ∝ m^(1/3), so heavy bodies don't swell as much
+    Volume,
+}
+
+impl RadiusLaw {
+    pub const ALL: [RadiusLaw; 2] = [RadiusLaw::Area, RadiusLaw::Volume];
+
+    pub fn label(self) -> &'static str {
+        match self {
+            RadiusLaw::Area => "Discs (r ∝ m^1/2)",
+            RadiusLaw::Volume => "Spheres (r ∝ m^1/3)",
+        }
+    }
+}
+
+// The radius bodies collide at. sqrt is exact everywhere, cbrt isn't, so deterministic runs take libm's.
+pub fn radius_for_mass(mass: f32, law: RadiusLaw, density: f32, deterministic: bool) -> f32 {
+    if mass <= 0.0 || density <= 0.0 {
+        return 0.0;
+    }
+    match law {
+        RadiusLaw::Area => (mass / density).sqrt(),
+        RadiusLaw::Volume if deterministic => libm::cbrtf(mass / density),
+        RadiusLaw::Volume => (mass / density).cbrt(),
+    }
+}
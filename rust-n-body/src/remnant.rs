@@ -0,0 +1,123 @@
+use bevy::math::DVec2;
+use bevy::prelude::*;
+use crate::groups::unbind;
+use crate::progress::json_number;
+
+// What is left after a merger: the part still bound together and what was flung out into tails
+#[derive(Clone, Debug, PartialEq)]
+pub struct RemnantReport {
+    pub time: f64,
+    pub bodies: usize,
+    pub total_mass: f32,
+    pub bound_bodies: usize,
+    pub bound_mass: f32,
+    // in the coordinates the run started in, once the caller has added the origin's offset
+    pub com: DVec2,
+    // radius around the remnant's center of mass holding half its bound mass
+    pub half_mass_radius: f32,
+    // share of the remnant's kinetic energy in rotation around its center, 1 for a cold disc, 0 for no net spin
+    pub rotation: f32,
+    // everything not bound to the remnant
+    pub tail_mass: f32,
+}
+
+impl RemnantReport {
+    pub fn bound_fraction(&self) -> f32 {
+        if self.total_mass > 0.0 { self.bound_mass / self.total_mass } else { 0.0 }
+    }
+
+    pub fn tail_fraction(&self) -> f32 {
+        if self.total_mass > 0.0 { self.tail_mass / self.total_mass } else { 0.0 }
+    }
+
+    pub fn json(&self) -> String {
+        let number = |value: f32| json_number(value as f64);
+        format!(
+            "{{\n  \"time\": {},\n  \"bodies\": {},\n  \"total_mass\": {},\n  \"bound_bodies\": {},\n  \
+             \"bound_mass\": {},\n  \"bound_fraction\": {},\n  \"com\": [{}, {}],\n  \"half_mass_radius\": {},\n  \
+             \"rotation\": {},\n  \"tail_mass\": {},\n  \"tail_fraction\": {}\n}}\n",
+            json_number(self.time),
+            self.bodies,
+            number(self.total_mass),
+            self.bound_bodies,
+            number(self.bound_mass),
+            number(self.bound_fraction()),
+            json_number(self.com.x),
+            json_number(self.com.y),
+            number(self.half_mass_radius),
+            number(self.rotation),
+            number(self.tail_mass),
+            number(self.tail_fraction())
+        )
+    }
+}
+
+// Smallest radius around center that holds half the members' mass
+pub fn half_mass_radius(members: &[usize], masses: &[f32], positions: &[Vec2], center: Vec2) -> f32 {
+    let mut by_distance: Vec<(f32, f32)> = members
+        .iter()
+        .map(|i| (positions[*i].distance(center), masses[*i]))
+        .collect();
+    by_distance.sort_by(|a, b| a.0.total_cmp(&b.0));
+    let half = by_distance.iter().map(|(_r, m)| m).sum::<f32>() / 2.0;
+    let mut enclosed = 0.0;
+    for (r, m) in by_distance {
+        enclosed += m;
+        if enclosed >= half {
+            return r;
+        }
+    }
+    0.0
+}
+
+// Mass weighted share of the kinetic energy in the tangential motion around center
+pub fn rotation_parameter(
+    members: &[usize],
+    masses: &[f32],
+    positions: &[Vec2],
+    velocities: &[Vec2],
+    center: Vec2,
+    center_vel: Vec2,
+) -> f32 {
+    let mut tangential = 0.0;
+    let mut total = 0.0;
+    for i in members {
+        let r = positions[*i] - center;
+        let v = velocities[*i] - center_vel;
+        total += masses[*i] * v.length_squared();
+        if r.length_squared() > 0.0 {
+            tangential += masses[*i] * (r.perp_dot(v) / r.length()).powi(2);
+        }
+    }
+    if total > 0.0 { tangential / total } else { 0.0 }
+}
+
+// Everything starts out in the remnant, the bodies moving fast enough to leave it are thrown out
+// the same way the bound group finding does. Meant for after the galaxies have merged, two that
+// are still apart look like one loose remnant with most of its mass unbound.
+pub fn analyze_remnant(
+    time: f64,
+    masses: &[f32],
+    positions: &[Vec2],
+    velocities: &[Vec2],
+    g: f32,
+    softening: f32,
+) -> RemnantReport {
+    let total_mass: f32 = masses.iter().sum();
+    let bound = unbind((0..masses.len()).collect(), masses, positions, velocities, g, softening);
+    let bound_mass: f32 = bound.iter().map(|i| masses[*i]).sum();
+    let weight = if bound_mass > 0.0 { 1.0 / bound_mass } else { 0.0 };
+    let com = bound.iter().map(|i| masses[*i] * positions[*i]).sum::<Vec2>() * weight;
+    let com_vel = bound.iter().map(|i| masses[*i] * velocities[*i]).sum::<Vec2>() * weight;
+    RemnantReport {
+        time,
+        bodies: masses.len(),
+        total_mass,
+        bound_bodies: bound.len(),
+        bound_mass,
+        com: com.as_dvec2(),
+        half_mass_radius: half_mass_radius(&bound, masses, positions, com),
+        rotation: rotation_parameter(&bound, masses, positions, velocities, com, com_vel),
+        tail_mass: total_mass - bound_mass,
+    }
+}
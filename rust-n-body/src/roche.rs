@@ -0,0 +1,114 @@
+use bevy::prelude::*;
+use rand::Rng;
+use crate::lifetime::{Lifetimes, SpawnSource};
+use crate::orbital_mechanics::{l1_point, orbit_elements};
+use crate::tracer::{Massless, tracer_bundle, tracer_mesh};
+use crate::{Body, SimulationSettings, Velocity};
+
+// Mass transfer demo: tracers are let loose at the inner Lagrange point of the two heaviest bodies,
+// drifting over to the heavier one, so the stream and the disk it winds up into can be watched.
+#[derive(Resource)]
+pub struct RocheOverflow {
+    pub enabled: bool,
+    // tracers per second
+    pub rate: f32,
+    // push towards the accretor, as a fraction of the binary's orbital speed
+    pub kick: f32,
+    // where the last tracer was let go, for drawing
+    pub l1: Option<Vec2>,
+    elapsed: f32,
+    handles: Option<(Handle<Mesh>, Handle<ColorMaterial>)>,
+}
+
+impl Default for RocheOverflow {
+    fn default() -> Self {
+        RocheOverflow {
+            enabled: false,
+            rate: 30.0,
+            kick: 0.05,
+            l1: None,
+            elapsed: 0.0,
+            handles: None,
+        }
+    }
+}
+
+// Where a tracer starts at L1 and how fast: turning with the binary, plus the push to the accretor.
+// donor and accretor are (mass, position, velocity).
+pub fn overflow_state(donor: (f32, Vec2, Vec2), accretor: (f32, Vec2, Vec2), g: f32, kick: f32) -> Option<(Vec2, Vec2)> {
+    let (m1, p1, v1) = donor;
+    let (m2, p2, v2) = accretor;
+    let (rel_pos, rel_vel) = (p2 - p1, v2 - v1);
+    // only a bound pair has a Roche geometry to speak of
+    orbit_elements(g * (m1 + m2), rel_pos, rel_vel).period?;
+
+    let l1 = l1_point(m1, p1, m2, p2)?;
+    let total = m1 + m2;
+    let com = (m1 * p1 + m2 * p2) / total;
+    let com_vel = (m1 * v1 + m2 * v2) / total;
+    let omega = rel_pos.perp_dot(rel_vel) / rel_pos.length_squared();
+    let corotating = com_vel + omega * (l1 - com).perp();
+    let push = rel_pos.normalize_or_zero() * rel_vel.length() * kick;
+    Some((l1, corotating + push))
+}
+
+pub fn roche_overflow(
+    time: Res<Time>,
+    settings: Res<SimulationSettings>,
+    bodies: Query<(&Transform, &Velocity, &Body), Without<Massless>>,
+    mut overflow: ResMut<RocheOverflow>,
+    mut commands: Commands,
+    mut materials: ResMut<Assets<ColorMaterial>>,
+    mut meshes: ResMut<Assets<Mesh>>,
+    lifetimes: Res<Lifetimes>,
+) {
+    if !overflow.enabled || settings.paused {
+        overflow.l1 = None;
+        return;
+    }
+
+    // the two heaviest bodies make the binary, the lighter of them gives the mass away
+    let mut heaviest: Vec<(f32, Vec2, Vec2)> = bodies
+        .iter()
+        .map(|(t, v, b)| (b.mass, t.translation.truncate(), v.0.truncate()))
+        .collect();
+    heaviest.sort_by(|a, b| b.0.total_cmp(&a.0));
+    let [accretor, donor, ..] = heaviest[..] else {
+        overflow.l1 = None;
+        return;
+    };
+    let Some((l1, velocity)) = overflow_state(donor, accretor, settings.g, overflow.kick) else {
+        overflow.l1 = None;
+        return;
+    };
+    overflow.l1 = Some(l1);
+
+    let (mesh, material) = overflow
+        .handles
+        .get_or_insert_with(|| {
+            (
+                tracer_mesh(&mut meshes),
+                materials.add(ColorMaterial::from_color(Color::srgb(1.0, 0.6, 0.3))),
+            )
+        })
+        .clone();
+
+    // a little scatter across the nozzle so the stream has some width
+    let width = donor.1.distance(accretor.1) * 0.01;
+    let mut rng = rand::rng();
+    overflow.elapsed += time.delta_secs();
+    let interval = 1.0 / overflow.rate;
+    while overflow.elapsed >= interval {
+        overflow.elapsed -= interval;
+        let jitter = Vec2::new(rng.random_range(-width..=width), rng.random_range(-width..=width));
+        let pos = l1 + jitter + velocity * overflow.elapsed;
+        let entity = commands.spawn(tracer_bundle(&mesh, &material, pos, velocity, settings.z)).id();
+        lifetimes.apply(SpawnSource::RocheStream, &mut commands, entity);
+    }
+}
+
+pub fn draw_roche(overflow: Res<RocheOverflow>, mut gizmos: Gizmos) {
+    if let Some(l1) = overflow.l1 {
+        gizmos.cross_2d(Isometry2d::from_translation(l1), 6.0, Color::srgb(1.0, 0.6, 0.3));
+    }
+}
@@ -0,0 +1,408 @@
+use bevy::prelude::*;
+use bevy_egui::{EguiContexts, egui};
+use crate::bhtree::{ForceBreakdown, Quad, Quadtree};
+use crate::clock::SimulationClock;
+use crate::fade::despawn_with_fade;
+use crate::force::{ForceLaw, Star};
+use crate::origin::WorldOrigin;
+use crate::orbital_mechanics::{find_primary, hill_radius, orbit_elements, roche_limit};
+use crate::tools::ActiveTool;
+use crate::tracer::Massless;
+use crate::ui_config::UiConfig;
+use crate::undo::{Snapshot, UndoBodies, UndoHistory};
+use crate::{Body, MainCamera, SimulationSettings, Velocity};
+
+// Marker for the body currently shown in the inspector
+#[derive(Component)]
+pub struct Selected;
+
+// Force breakdown of the selected body, filled in by the update system while the tree is alive
+#[derive(Resource, Default)]
+pub struct SelectedBreakdown {
+    pub breakdown: Option<ForceBreakdown>,
+    // the step the kicks in the breakdown are for
+    pub dt: f32,
+    pub draw: bool,
+}
+
+// Returns the cursor position in world coordinates, if the cursor is inside the window
+pub fn cursor_world_pos(
+    windows: &Query<&Window>,
+    cameras: &Query<(&Camera, &GlobalTransform), With<MainCamera>>,
+) -> Option<Vec2> {
+    let window = windows.single().ok()?;
+    let (camera, camera_transform) = cameras.single().ok()?;
+    let cursor = window.cursor_position()?;
+    camera.viewport_to_world_2d(camera_transform, cursor).ok()
+}
+
+// Bodies that are held in place. They still pull on everything else, they just don't move.
+#[derive(Component)]
+pub struct Pinned;
+
+// Where the current drag selection started, in world coordinates
+#[derive(Resource, Default)]
+pub struct DragSelect {
+    start: Option<Vec2>,
+}
+
+// Values used by the group operations in the inspector
+#[derive(Resource)]
+pub struct GroupTools {
+    pub kick: Vec2,
+    pub mass_scale: f32,
+}
+
+impl Default for GroupTools {
+    fn default() -> Self {
+        GroupTools {
+            kick: Vec2::new(0.0, 50.0),
+            mass_scale: 2.0,
+        }
+    }
+}
+
+// Drags shorter than this (in world units) count as a click
+const DRAG_THRESHOLD: f32 = 4.0;
+
+// Left click selects the body under the cursor, clicking empty space clears the selection.
+// Dragging selects every body inside the rectangle. Holding shift adds to the selection instead.
+pub fn select_body(
+    mut contexts: EguiContexts,
+    buttons: Res<ButtonInput<MouseButton>>,
+    keys: Res<ButtonInput<KeyCode>>,
+    windows: Query<&Window>,
+    cameras: Query<(&Camera, &GlobalTransform), With<MainCamera>>,
+    bodies: Query<(Entity, &Transform, &Body)>,
+    selected: Query<Entity, With<Selected>>,
+    mut drag: ResMut<DragSelect>,
+    tool: Res<ActiveTool>,
+    mut commands: Commands,
+) {
+    if *tool != ActiveTool::Select {
+        drag.start = None;
+        return;
+    }
+    let Some(cursor) = cursor_world_pos(&windows, &cameras) else {
+        return;
+    };
+
+    if buttons.just_pressed(MouseButton::Left) {
+        // Clicks on the settings windows should not change the selection
+        if !contexts.ctx_mut().wants_pointer_input() {
+            drag.start = Some(cursor);
+        }
+        return;
+    }
+
+    if !buttons.just_released(MouseButton::Left) {
+        return;
+    }
+    let Some(start) = drag.start.take() else {
+        return;
+    };
+
+    if !keys.pressed(KeyCode::ShiftLeft) && !keys.pressed(KeyCode::ShiftRight) {
+        for entity in &selected {
+            commands.entity(entity).remove::<Selected>();
+        }
+    }
+
+    if start.distance(cursor) < DRAG_THRESHOLD {
+        let hit = bodies
+            .iter()
+            .filter(|(_e, t, b)| t.translation.truncate().distance(cursor) <= b.radius.max(2.0))
+            .min_by(|(_, a, _), (_, b, _)| {
+                let da = a.translation.truncate().distance_squared(cursor);
+                let db = b.translation.truncate().distance_squared(cursor);
+                da.total_cmp(&db)
+            });
+
+        if let Some((entity, _t, _b)) = hit {
+            commands.entity(entity).insert(Selected);
+        }
+        return;
+    }
+
+    // Build a tree of the current positions so the rectangle only has to look at the quads it touches
+    let positions: Vec<Vec2> = bodies
+        .iter()
+        .map(|(_e, t, _b)| t.translation.truncate())
+        .collect();
+    if positions.is_empty() {
+        return;
+    }
+    let mut tree = Quadtree::new(Quad::new_containing(&positions));
+    for (entity, transform, body) in &bodies {
+        tree.insert(entity, *transform, *body);
+    }
+
+    for (entity, _transform, _body) in tree.query_rect(Rect::from_corners(start, cursor)) {
+        commands.entity(entity).insert(Selected);
+    }
+}
+
+pub fn draw_drag_rect(
+    mut gizmos: Gizmos,
+    drag: Res<DragSelect>,
+    windows: Query<&Window>,
+    cameras: Query<(&Camera, &GlobalTransform), With<MainCamera>>,
+) {
+    let (Some(start), Some(cursor)) = (drag.start, cursor_world_pos(&windows, &cameras)) else {
+        return;
+    };
+    let rect = Rect::from_corners(start, cursor);
+    gizmos.rect_2d(
+        Isometry2d::from_translation(rect.center()),
+        rect.size(),
+        Color::linear_rgba(1.0, 1.0, 1.0, 0.5),
+    );
+}
+
+#[derive(Clone, Copy)]
+enum GroupAction {
+    Delete,
+    Kick,
+    ScaleMass,
+    Pin,
+    Unpin,
+    MakeStar,
+    UnmakeStar,
+}
+
+impl GroupAction {
+    fn label(self) -> &'static str {
+        match self {
+            GroupAction::Delete => "Delete",
+            GroupAction::Kick => "Kick",
+            GroupAction::ScaleMass => "Scale Mass",
+            GroupAction::Pin => "Pin",
+            GroupAction::Unpin => "Unpin",
+            GroupAction::MakeStar => "Make Star",
+            GroupAction::UnmakeStar => "Unmake Star",
+        }
+    }
+}
+
+pub fn inspector_window(
+    mut contexts: EguiContexts,
+    mut debug: ResMut<SelectedBreakdown>,
+    mut tools: ResMut<GroupTools>,
+    mut queries: ParamSet<(Query<(Entity, &Transform, &mut Velocity, &mut Body, Has<Star>), With<Selected>>, UndoBodies)>,
+    mut commands: Commands,
+    mut meshes: ResMut<Assets<Mesh>>,
+    ui_config: Res<UiConfig>,
+    mut settings: ResMut<SimulationSettings>,
+    (mut history, clock, origin): (ResMut<UndoHistory>, Res<SimulationClock>, Res<WorldOrigin>),
+) {
+    let selected = queries.p0();
+    let count = selected.iter().count();
+    if count == 0 {
+        return;
+    }
+
+    let mut action = None;
+    let window = ui_config.place(egui::Window::new("Inspector"), "Inspector");
+    window.show(contexts.ctx_mut(), |ui| {
+        if let Ok((_entity, transform, velocity, body, star)) = selected.single() {
+            let units = settings.units;
+            ui.label(format!("Mass: {}", units.mass(body.mass)));
+            if star {
+                ui.label("Star (emits radiation pressure)");
+            }
+            ui.label(format!("Radius: {}", units.length(body.radius)));
+            // in the coordinates the run started in, so moving the world origin doesn't make it jump
+            let position = origin.original(transform.translation.truncate()).as_vec2();
+            ui.label(format!("Position: ({}, {})", units.length(position.x), units.length(position.y)));
+            ui.label(format!("Velocity: ({:.2}, {:.2})", velocity.0.x, velocity.0.y));
+            ui.label(format!("Speed: {}", units.speed(velocity.0.truncate().length())));
+
+            ui.add(egui::Checkbox::new(&mut settings.show_orbit_zones, "Draw Hill Sphere and Roche Limit"));
+
+            ui.separator();
+            ui.add(egui::Checkbox::new(&mut debug.draw, "Draw Approximated Nodes"));
+            if let Some(breakdown) = &debug.breakdown {
+                let largest_source = origin.original(breakdown.largest_source.truncate());
+                ui.label(format!("Nodes opened: {}", breakdown.opened));
+                ui.label(format!("Nodes approximated: {}", breakdown.approximated));
+                ui.label(format!("Bodies summed directly: {}", breakdown.direct));
+                ui.label(format!("Total kick: {:.4}", breakdown.total.length()));
+                ui.label(format!(
+                    "Largest kick: {:.4} from ({:.1}, {:.1})",
+                    breakdown.largest.length(),
+                    largest_source.x,
+                    largest_source.y
+                ));
+            }
+        } else {
+            let total_mass: f32 = selected.iter().map(|(_e, _t, _v, b, _s)| b.mass).sum();
+            ui.label(format!("Selected bodies: {}", count));
+            ui.label(format!("Total mass: {}", settings.units.mass(total_mass)));
+
+            // Two bodies are treated as an isolated pair, the rest of the system is ignored
+            let pair: Vec<_> = selected.iter().collect();
+            if let [(_e1, t1, v1, _b1, _s1), (_e2, t2, v2, _b2, _s2)] = pair[..] {
+                ui.separator();
+                let elements = orbit_elements(
+                    settings.g * total_mass,
+                    (t2.translation - t1.translation).truncate(),
+                    (v2.0 - v1.0).truncate(),
+                );
+                if settings.force_law != ForceLaw::InverseSquare {
+                    ui.label("Orbit elements assume the 1/r² law");
+                }
+                match elements.period {
+                    Some(period) => {
+                        ui.label(format!("Semi-major axis: {}", settings.units.length(elements.semi_major_axis)));
+                        ui.label(format!("Eccentricity: {:.4}", elements.eccentricity));
+                        ui.label(format!("Period: {}", settings.units.time(period as f64)));
+                    }
+                    None => {
+                        ui.label(format!("Unbound, eccentricity: {:.4}", elements.eccentricity));
+                    }
+                }
+            }
+        }
+
+        ui.separator();
+        ui.add(egui::Slider::new(&mut tools.kick.x, -500.0..=500.0).text("Kick X"));
+        ui.add(egui::Slider::new(&mut tools.kick.y, -500.0..=500.0).text("Kick Y"));
+        if ui.button("Kick").clicked() {
+            action = Some(GroupAction::Kick);
+        }
+        ui.add(egui::Slider::new(&mut tools.mass_scale, 0.1..=10.0).text("Mass Factor"));
+        if ui.button("Scale Mass").clicked() {
+            action = Some(GroupAction::ScaleMass);
+        }
+        ui.horizontal(|ui| {
+            if ui.button("Pin").clicked() {
+                action = Some(GroupAction::Pin);
+            }
+            if ui.button("Unpin").clicked() {
+                action = Some(GroupAction::Unpin);
+            }
+            if ui.button("Delete").clicked() {
+                action = Some(GroupAction::Delete);
+            }
+        });
+        ui.horizontal(|ui| {
+            if ui.button("Make Star").clicked() {
+                action = Some(GroupAction::MakeStar);
+            }
+            if ui.button("Unmake Star").clicked() {
+                action = Some(GroupAction::UnmakeStar);
+            }
+        });
+    });
+
+    let Some(action) = action else {
+        return;
+    };
+    history.record(Snapshot::capture(action.label(), &queries.p1(), &clock));
+    for (entity, _transform, mut velocity, mut body, _star) in &mut queries.p0() {
+        match action {
+            GroupAction::Delete => despawn_with_fade(&mut commands, entity),
+            GroupAction::Kick => velocity.0 += tools.kick.extend(0.0),
+            GroupAction::ScaleMass => {
+                // tracers have no mass to scale
+                if body.mass == 0.0 {
+                    continue;
+                }
+                body.mass *= tools.mass_scale;
+                body.radius = settings.collision_radius(body.mass);
+                commands
+                    .entity(entity)
+                    .insert(Mesh2d(meshes.add(Circle::new(settings.render_radius(body.radius)))));
+            }
+            GroupAction::Pin => {
+                velocity.0 = Vec3::ZERO;
+                commands.entity(entity).insert(Pinned);
+            }
+            GroupAction::Unpin => {
+                commands.entity(entity).remove::<Pinned>();
+            }
+            GroupAction::MakeStar => {
+                commands.entity(entity).insert(Star);
+            }
+            GroupAction::UnmakeStar => {
+                commands.entity(entity).remove::<Star>();
+            }
+        }
+    }
+}
+
+// Highlights the selected body and draws lines to the nodes it treated as single bodies
+pub fn draw_breakdown(
+    mut gizmos: Gizmos,
+    debug: Res<SelectedBreakdown>,
+    selected: Query<&Transform, With<Selected>>,
+) {
+    let Ok(transform) = selected.single() else {
+        return;
+    };
+    let from = transform.translation.truncate();
+    gizmos.circle_2d(Isometry2d::from_translation(from), 8.0, Color::WHITE);
+
+    if !debug.draw {
+        return;
+    }
+    let Some(breakdown) = &debug.breakdown else {
+        return;
+    };
+
+    for com in &breakdown.approximated_coms {
+        gizmos.line_2d(from, com.truncate(), Color::linear_rgba(0.0, 1.0, 1.0, 0.3));
+    }
+    gizmos.line_2d(from, breakdown.largest_source.truncate(), Color::linear_rgba(1.0, 0.0, 1.0, 1.0));
+}
+
+// Hill sphere around the selected body and the Roche limit around whatever it orbits
+pub fn draw_orbit_zones(
+    mut gizmos: Gizmos,
+    settings: Res<SimulationSettings>,
+    selected: Query<(Entity, &Transform, &Velocity, &Body), With<Selected>>,
+    bodies: Query<(Entity, &Transform, &Velocity, &Body), Without<Massless>>,
+) {
+    if !settings.show_orbit_zones {
+        return;
+    }
+    let Ok((entity, transform, velocity, body)) = selected.single() else {
+        return;
+    };
+    let pos = transform.translation.truncate();
+
+    let others: Vec<_> = bodies.iter().filter(|(e, _t, _v, _b)| *e != entity).collect();
+    let candidates: Vec<(Vec2, f32)> = others
+        .iter()
+        .map(|(_e, t, _v, b)| (t.translation.truncate(), b.mass))
+        .collect();
+    let Some(i) = find_primary(pos, body.mass, &candidates) else {
+        return;
+    };
+    let (_e, primary_transform, primary_velocity, primary) = others[i];
+    let primary_pos = primary_transform.translation.truncate();
+
+    let elements = orbit_elements(
+        settings.g * (body.mass + primary.mass),
+        pos - primary_pos,
+        (velocity.0 - primary_velocity.0).truncate(),
+    );
+    // not on a closed orbit, the current distance is the best there is
+    let (a, e) = if elements.period.is_some() {
+        (elements.semi_major_axis, elements.eccentricity)
+    } else {
+        (pos.distance(primary_pos), 0.0)
+    };
+
+    gizmos.line_2d(pos, primary_pos, Color::linear_rgba(1.0, 1.0, 1.0, 0.2));
+    gizmos.circle_2d(
+        Isometry2d::from_translation(pos),
+        hill_radius(body.mass, primary.mass, a, e),
+        Color::linear_rgba(0.2, 1.0, 0.2, 0.8),
+    );
+    gizmos.circle_2d(
+        Isometry2d::from_translation(primary_pos),
+        roche_limit(body.radius, body.mass, primary.mass),
+        Color::linear_rgba(1.0, 0.3, 0.2, 0.8),
+    );
+}
@@ -0,0 +1,109 @@
+use bevy::math::DVec2;
+use bevy::prelude::*;
+use std::fs;
+use std::path::Path;
+use crate::collision::CollisionCount;
+use crate::clock::SimulationClock;
+use crate::energy::Energy;
+use crate::origin::WorldOrigin;
+use crate::tracer::Massless;
+use crate::{Body, SimulationSettings, Velocity};
+
+pub const STATE_HEADER: &str = "entity,mass,radius,x,y,vx,vy";
+
+// How the run went, written next to the final state
+pub struct RunSummary {
+    pub wall_secs: f64,
+    pub time: f64,
+    pub steps: u64,
+    pub bodies: usize,
+    // none when the energy wasn't being tracked
+    pub energy_error: Option<f64>,
+    pub collisions: u64,
+    pub delta_t: f32,
+    pub theta: f32,
+    pub seed: Option<u64>,
+}
+
+impl RunSummary {
+    // key=value lines, like the UI config
+    pub fn text(&self) -> String {
+        let mut text = format!(
+            "wall_time_s={:.3}\nsimulated_time={:?}\nsteps={}\nbodies={}\ncollisions={}\ndelta_t={:?}\ntheta={:?}\n",
+            self.wall_secs, self.time, self.steps, self.bodies, self.collisions, self.delta_t, self.theta
+        );
+        match self.energy_error {
+            Some(error) => text.push_str(&format!("energy_error={:e}\n", error)),
+            None => text.push_str("energy_error=not tracked\n"),
+        }
+        if let Some(seed) = self.seed {
+            text.push_str(&format!("seed={}\n", seed));
+        }
+        text
+    }
+}
+
+// Every body at full precision, one per line, so a run can be picked up again or checked later.
+// Positions are in the coordinates the run started in.
+pub fn state_csv(bodies: &[(u32, Body, DVec2, Vec2)]) -> String {
+    let mut csv = format!("{}\n", STATE_HEADER);
+    for (entity, body, position, velocity) in bodies {
+        csv.push_str(&format!(
+            "{},{:?},{:?},{:?},{:?},{:?},{:?}\n",
+            entity, body.mass, body.radius, position.x, position.y, velocity.x, velocity.y
+        ));
+    }
+    csv
+}
+
+fn write_dump(dir: &Path, state: &str, summary: &str) -> std::io::Result<()> {
+    fs::create_dir_all(dir)?;
+    fs::write(dir.join("final_state.csv"), state)?;
+    fs::write(dir.join("run_summary.txt"), summary)
+}
+
+// Runs last in the frame the app is told to quit in, by closing the window or Ctrl+C in the terminal,
+// while the bodies are all still there
+pub fn dump_on_exit(
+    mut exits: EventReader<AppExit>,
+    settings: Res<SimulationSettings>,
+    clock: Res<SimulationClock>,
+    energy: Res<Energy>,
+    collisions: Res<CollisionCount>,
+    real_time: Res<Time<Real>>,
+    origin: Res<WorldOrigin>,
+    bodies: Query<(Entity, &Body, &Transform, &Velocity), Without<Massless>>,
+) {
+    if exits.is_empty() {
+        return;
+    }
+    exits.clear();
+    if !settings.dump_on_exit {
+        return;
+    }
+
+    let mut state: Vec<(u32, Body, DVec2, Vec2)> = bodies
+        .iter()
+        .map(|(entity, body, transform, velocity)| {
+            (entity.index(), *body, origin.original(transform.translation.truncate()), velocity.0.truncate())
+        })
+        .collect();
+    state.sort_by_key(|(entity, ..)| *entity);
+    let summary = RunSummary {
+        wall_secs: real_time.elapsed_secs_f64(),
+        time: clock.time,
+        steps: clock.steps,
+        bodies: state.len(),
+        energy_error: energy.relative_error(),
+        collisions: collisions.0,
+        delta_t: settings.delta_t,
+        theta: settings.theta,
+        seed: settings.deterministic.then_some(settings.seed),
+    };
+
+    let dir = Path::new(&settings.dump_dir);
+    match write_dump(dir, &state_csv(&state), &summary.text()) {
+        Ok(()) => info!("Saved the final state of {} bodies to {}", state.len(), dir.display()),
+        Err(err) => error!("Could not save the final state to {}: {}", dir.display(), err),
+    }
+}
@@ -0,0 +1,110 @@
+use bevy::prelude::*;
+use crate::bhtree::Quad;
+use crate::pipeline::TreeInputs;
+use crate::Body;
+
+// Struct-of-arrays copy of the bodies, filled from the ECS components once per frame.
+// The hot loops run over plain f32 slices the compiler can vectorize, instead of hopping
+// between entities and looking the kicks up in a map.
+#[derive(Default)]
+pub struct BodyBuffers {
+    pub entities: Vec<Entity>,
+    pub x: Vec<f32>,
+    pub y: Vec<f32>,
+    pub vx: Vec<f32>,
+    pub vy: Vec<f32>,
+    pub m: Vec<f32>,
+    // velocity change from this frame's forces
+    pub ax: Vec<f32>,
+    pub ay: Vec<f32>,
+    // false for tracers, which feel gravity but are left out of the tree
+    pub in_tree: Vec<bool>,
+}
+
+impl BodyBuffers {
+    // Keeps the allocations, so syncing every frame doesn't reallocate
+    pub fn clear(&mut self) {
+        self.entities.clear();
+        self.x.clear();
+        self.y.clear();
+        self.vx.clear();
+        self.vy.clear();
+        self.m.clear();
+        self.ax.clear();
+        self.ay.clear();
+        self.in_tree.clear();
+    }
+
+    pub fn push(&mut self, entity: Entity, pos: Vec3, vel: Vec3, mass: f32, in_tree: bool) {
+        self.entities.push(entity);
+        self.x.push(pos.x);
+        self.y.push(pos.y);
+        self.vx.push(vel.x);
+        self.vy.push(vel.y);
+        self.m.push(mass);
+        self.ax.push(0.0);
+        self.ay.push(0.0);
+        self.in_tree.push(in_tree);
+    }
+
+    pub fn len(&self) -> usize {
+        self.entities.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entities.is_empty()
+    }
+
+    // Body i at its buffer position, z and the rest are taken from its component
+    pub fn transform(&self, i: usize, component: &Transform) -> Transform {
+        Transform {
+            translation: Vec3::new(self.x[i], self.y[i], component.translation.z),
+            ..*component
+        }
+    }
+
+    // The bodies that go into the tree, at their positions in the buffers. Takes the components
+    // in the order the buffers were filled in.
+    pub fn tree_inputs<'a>(&self, bodies: impl Iterator<Item = (Entity, &'a Transform, &'a Body)>) -> TreeInputs {
+        bodies
+            .enumerate()
+            .filter(|(i, _)| self.in_tree[*i])
+            .map(|(i, (entity, transform, body))| (entity, self.transform(i, transform), *body))
+            .collect()
+    }
+
+    pub fn velocity(&self, i: usize) -> Vec3 {
+        Vec3::new(self.vx[i], self.vy[i], 0.0)
+    }
+
+    // Smallest quad around every body that goes into the tree, in one pass per axis
+    pub fn bounds(&self) -> Quad {
+        let mut min = Vec2::splat(f32::MAX);
+        let mut max = Vec2::splat(f32::MIN);
+        for i in 0..self.len() {
+            if self.in_tree[i] {
+                min.x = min.x.min(self.x[i]);
+                max.x = max.x.max(self.x[i]);
+                min.y = min.y.min(self.y[i]);
+                max.y = max.y.max(self.y[i]);
+            }
+        }
+        Quad::from_bounds(min, max)
+    }
+
+    // Adds the kicks to the velocities, then moves every body by its new velocity
+    pub fn step(&mut self, dt: f32) {
+        for (v, a) in self.vx.iter_mut().zip(&self.ax) {
+            *v += a;
+        }
+        for (v, a) in self.vy.iter_mut().zip(&self.ay) {
+            *v += a;
+        }
+        for (p, v) in self.x.iter_mut().zip(&self.vx) {
+            *p += v * dt;
+        }
+        for (p, v) in self.y.iter_mut().zip(&self.vy) {
+            *p += v * dt;
+        }
+    }
+}
@@ -0,0 +1,120 @@
+use crate::Body;
+use crate::bhtree::{Quad, Quadtree};
+use crate::force::ForceParams;
+use bevy::prelude::*;
+
+// The Barnes-Hut solver on plain arrays, behind the C API and the Python bindings. Bodies are put in a
+// fresh tree every step, the same kick-drift step as the headless runs with Newtonian gravity and
+// nothing on top. A body is known by the index add_body returned for it.
+pub struct NBodySim {
+    pub force: ForceParams,
+    pub theta: f32,
+    pub dt: f32,
+    bodies: Vec<Body>,
+    positions: Vec<Vec2>,
+    velocities: Vec<Vec2>,
+}
+
+impl NBodySim {
+    pub fn new(g: f32, theta: f32, dt: f32) -> Self {
+        NBodySim {
+            force: ForceParams { g, ..default() },
+            theta,
+            dt,
+            bodies: Vec::new(),
+            positions: Vec::new(),
+            velocities: Vec::new(),
+        }
+    }
+
+    // Returns the new body's index
+    pub fn add_body(&mut self, position: Vec2, velocity: Vec2, mass: f32) -> usize {
+        // the tree only looks at the mass
+        self.bodies.push(Body { mass, radius: 0.0, hue: 0.0 });
+        self.positions.push(position);
+        self.velocities.push(velocity);
+        self.bodies.len() - 1
+    }
+
+    pub fn body_count(&self) -> usize {
+        self.bodies.len()
+    }
+
+    pub fn positions(&self) -> &[Vec2] {
+        &self.positions
+    }
+
+    pub fn velocities(&self) -> &[Vec2] {
+        &self.velocities
+    }
+
+    pub fn masses(&self) -> Vec<f32> {
+        self.bodies.iter().map(|body| body.mass).collect()
+    }
+
+    // Replaces every body at once, one entry per body in each list
+    pub fn set_state(&mut self, positions: Vec<Vec2>, velocities: Vec<Vec2>, masses: &[f32]) -> Result<(), String> {
+        if positions.len() != masses.len() || velocities.len() != masses.len() {
+            return Err(format!(
+                "{} positions, {} velocities and {} masses, expected one of each per body",
+                positions.len(),
+                velocities.len(),
+                masses.len()
+            ));
+        }
+        self.bodies = masses.iter().map(|&mass| Body { mass, radius: 0.0, hue: 0.0 }).collect();
+        self.positions = positions;
+        self.velocities = velocities;
+        Ok(())
+    }
+
+    fn build_tree(&self) -> Quadtree {
+        let mut tree = Quadtree::new(Quad::new_containing(&self.positions));
+        for (i, (body, position)) in self.bodies.iter().zip(&self.positions).enumerate() {
+            tree.insert(Entity::from_raw(i as u32), Transform::from_translation(position.extend(0.0)), *body);
+        }
+        tree
+    }
+
+    pub fn step(&mut self) {
+        if self.bodies.is_empty() {
+            return;
+        }
+        let tree = self.build_tree();
+        let kicks: Vec<Vec2> = (0..self.bodies.len())
+            .map(|i| {
+                tree.get_total_accel(
+                    Entity::from_raw(i as u32),
+                    Transform::from_translation(self.positions[i].extend(0.0)),
+                    self.bodies[i],
+                    self.force,
+                    self.dt,
+                    self.theta,
+                )
+                .truncate()
+            })
+            .collect();
+        for ((position, velocity), kick) in self.positions.iter_mut().zip(&mut self.velocities).zip(kicks) {
+            *velocity += kick;
+            *position += *velocity * self.dt;
+        }
+    }
+
+    // The acceleration the tree gives at any point, from all the bodies
+    pub fn accel_at(&self, position: Vec2) -> Vec2 {
+        if self.bodies.is_empty() {
+            return Vec2::ZERO;
+        }
+        // no body has the placeholder's index, so none is skipped as the point itself
+        self.build_tree()
+            .get_total_accel(
+                Entity::PLACEHOLDER,
+                Transform::from_translation(position.extend(0.0)),
+                Body { mass: 0.0, radius: 0.0, hue: 0.0 },
+                self.force,
+                1.0,
+                self.theta,
+            )
+            .truncate()
+    }
+}
@@ -0,0 +1,94 @@
+use bevy::prelude::*;
+use crate::audio::{Tone, play_tone};
+use crate::collision::CollisionCount;
+use crate::tracer::Massless;
+use crate::{Body, Velocity};
+
+const KINETIC_PITCH: f32 = 220.0;
+// the kinetic energy tone moves at most this many octaves away from where it started
+const KINETIC_OCTAVES: f32 = 3.0;
+const KINETIC_GAIN: f32 = 0.08;
+const COLLISION_PITCH: f32 = 880.0;
+// collisions per second at which the collision tone is at full volume and an octave up
+const COLLISION_FULL_RATE: f32 = 50.0;
+const COLLISION_GAIN: f32 = 0.15;
+// how quickly the measured collision rate follows the real one, per second
+const RATE_SMOOTHING: f32 = 3.0;
+
+// Turns global quantities into sound: total kinetic energy sets the pitch of a low tone,
+// the collision rate the pitch and volume of a high one
+#[derive(Resource, Default)]
+pub struct Sonification {
+    pub enabled: bool,
+    // kinetic energy when sonification was switched on, the low tone starts at its base pitch there
+    reference: Option<f64>,
+    last_collisions: u64,
+    pub collision_rate: f32,
+    kinetic_tone: Option<Tone>,
+    collision_tone: Option<Tone>,
+}
+
+// Octaves up or down from the reference, one octave per doubling of the energy
+pub fn kinetic_pitch(kinetic: f64, reference: f64) -> f32 {
+    if kinetic <= 0.0 || reference <= 0.0 {
+        return KINETIC_PITCH;
+    }
+    let octaves = ((kinetic / reference).log2() as f32).clamp(-KINETIC_OCTAVES, KINETIC_OCTAVES);
+    KINETIC_PITCH * 2f32.powf(octaves)
+}
+
+// Pitch and gain of the collision tone, silent without collisions
+pub fn collision_voice(rate: f32) -> (f32, f32) {
+    let level = (rate / COLLISION_FULL_RATE).clamp(0.0, 1.0);
+    (COLLISION_PITCH * 2f32.powf(level), COLLISION_GAIN * level)
+}
+
+pub fn start_sonification(
+    mut commands: Commands,
+    mut tones: ResMut<Assets<Tone>>,
+    mut sonification: ResMut<Sonification>,
+) {
+    sonification.kinetic_tone = Some(play_tone(&mut commands, &mut tones));
+    sonification.collision_tone = Some(play_tone(&mut commands, &mut tones));
+}
+
+pub fn sonify(
+    time: Res<Time>,
+    collisions: Res<CollisionCount>,
+    bodies: Query<(&Body, &Velocity), Without<Massless>>,
+    mut sonification: ResMut<Sonification>,
+) {
+    let (Some(kinetic_tone), Some(collision_tone)) =
+        (sonification.kinetic_tone.clone(), sonification.collision_tone.clone())
+    else {
+        return;
+    };
+
+    let new_collisions = collisions.0 - sonification.last_collisions;
+    sonification.last_collisions = collisions.0;
+
+    if !sonification.enabled {
+        sonification.reference = None;
+        sonification.collision_rate = 0.0;
+        kinetic_tone.set(KINETIC_PITCH, 0.0);
+        collision_tone.set(COLLISION_PITCH, 0.0);
+        return;
+    }
+
+    let dt = time.delta_secs();
+    if dt > 0.0 {
+        let rate = new_collisions as f32 / dt;
+        let blend = (RATE_SMOOTHING * dt).min(1.0);
+        sonification.collision_rate += (rate - sonification.collision_rate) * blend;
+    }
+
+    let kinetic: f64 = bodies
+        .iter()
+        .map(|(b, v)| 0.5 * b.mass as f64 * v.0.truncate().length_squared() as f64)
+        .sum();
+    let reference = *sonification.reference.get_or_insert(kinetic);
+
+    kinetic_tone.set(kinetic_pitch(kinetic, reference), KINETIC_GAIN);
+    let (pitch, gain) = collision_voice(sonification.collision_rate);
+    collision_tone.set(pitch, gain);
+}
@@ -0,0 +1,295 @@
+use bevy::prelude::*;
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+use std::f32::consts::TAU;
+use std::fs;
+use crate::choreography::{Choreography, ChoreographyLibrary, recommended_dt, scaled_state};
+use crate::force::ForceLaw;
+use crate::headless::HeadlessSim;
+use crate::validate::validate;
+use crate::{Body, SimulationSettings, generate_bodies};
+
+// Neighbors looked at for touching bodies, more than one so a big body behind a small one isn't missed
+const COLLISION_NEIGHBORS: usize = 4;
+
+const USAGE: &str = "usage: rust-n-body --stability [--choreography <name> | --n <bodies> --seed <seed>]
+                   [--epsilons <e1,e2,...>] [--trials <runs per epsilon>] [--duration <simulated time>]
+                   [--dt <step>] [--escape <factor>] [--no-collisions] [--check-every <steps>] [--out <stability.csv>]
+Every velocity gets a random kick of epsilon times the RMS speed, and each run goes until a body is further
+from the center of mass than --escape (default 10) times the starting extent, two bodies touch or --duration
+(default 100) is over. Trial k uses the same kick directions at every epsilon. --choreography takes a preset
+from the Choreographies window (figure-eight, lagrange-triangle, moth-i, ...), otherwise the bodies a reset
+with --n and --seed would spawn are used.";
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Outcome {
+    Survived,
+    Ejection,
+    Collision,
+}
+
+impl Outcome {
+    pub fn label(self) -> &'static str {
+        match self {
+            Outcome::Survived => "survived",
+            Outcome::Ejection => "ejection",
+            Outcome::Collision => "collision",
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Survival {
+    pub time: f64,
+    pub steps: u64,
+    pub outcome: Outcome,
+}
+
+// When a run counts as broken up
+#[derive(Clone, Copy, Debug)]
+pub struct Criteria {
+    // times the largest starting distance from the center of mass
+    pub escape_factor: f32,
+    pub collisions: bool,
+    // the checks cost about a tree build, so they don't have to be done every step
+    pub check_every: u64,
+}
+
+impl Default for Criteria {
+    fn default() -> Self {
+        Criteria {
+            escape_factor: 10.0,
+            collisions: true,
+            check_every: 10,
+        }
+    }
+}
+
+// Adds epsilon times the RMS speed to every velocity, in a random direction
+pub fn perturb(velocities: &mut [Vec3], epsilon: f32, rng: &mut impl Rng) {
+    if velocities.is_empty() {
+        return;
+    }
+    let rms = (velocities.iter().map(|v| v.length_squared()).sum::<f32>() / velocities.len() as f32).sqrt();
+    for velocity in velocities {
+        let kick = Vec2::from_angle(rng.random_range(0.0..TAU)) * epsilon * rms;
+        *velocity += kick.extend(0.0);
+    }
+}
+
+fn center_of_mass(sim: &HeadlessSim) -> Vec2 {
+    let mass: f32 = sim.bodies.iter().map(|b| b.mass).sum();
+    if mass <= 0.0 {
+        return Vec2::ZERO;
+    }
+    sim.bodies
+        .iter()
+        .zip(&sim.transforms)
+        .map(|(body, transform)| body.mass * transform.translation.truncate())
+        .sum::<Vec2>()
+        / mass
+}
+
+// Largest distance of a body from the center of mass
+pub fn extent(sim: &HeadlessSim) -> f32 {
+    let com = center_of_mass(sim);
+    sim.transforms
+        .iter()
+        .map(|t| t.translation.truncate().distance(com))
+        .fold(0.0, f32::max)
+}
+
+// Two bodies closer than the sum of their radii, looking only at each body's nearest neighbors
+pub fn touching(sim: &HeadlessSim, settings: &SimulationSettings) -> Option<(usize, usize)> {
+    let tree = sim.build_tree(settings);
+    for (i, transform) in sim.transforms.iter().enumerate() {
+        let position = transform.translation.truncate();
+        for (entity, other) in tree.knn(position, COLLISION_NEIGHBORS + 1) {
+            let j = entity.index() as usize;
+            if j != i && position.distance(other) < sim.bodies[i].radius + sim.bodies[j].radius {
+                return Some((i.min(j), i.max(j)));
+            }
+        }
+    }
+    None
+}
+
+// Steps the run until it breaks up or duration is over
+pub fn survive(sim: &mut HeadlessSim, settings: &SimulationSettings, criteria: Criteria, duration: f64) -> Survival {
+    let escape_radius = criteria.escape_factor * extent(sim);
+    let check_every = criteria.check_every.max(1);
+    let broken = |sim: &HeadlessSim| {
+        if extent(sim) > escape_radius {
+            Some(Outcome::Ejection)
+        } else if criteria.collisions && touching(sim, settings).is_some() {
+            Some(Outcome::Collision)
+        } else {
+            None
+        }
+    };
+    loop {
+        if (sim.steps.is_multiple_of(check_every) || sim.time >= duration)
+            && let Some(outcome) = broken(sim)
+        {
+            return Survival {
+                time: sim.time,
+                steps: sim.steps,
+                outcome,
+            };
+        }
+        if sim.time >= duration {
+            return Survival {
+                time: sim.time,
+                steps: sim.steps,
+                outcome: Outcome::Survived,
+            };
+        }
+        sim.step(settings);
+    }
+}
+
+// Survival time of each epsilon's runs: (epsilon, trial, survival) in the order they were run
+pub fn stability_runs(
+    state: &[(Body, Transform, Vec3)],
+    settings: &SimulationSettings,
+    criteria: Criteria,
+    epsilons: &[f32],
+    trials: u64,
+    duration: f64,
+    seed: u64,
+) -> Vec<(f32, u64, Survival)> {
+    let mut runs = Vec::new();
+    for epsilon in epsilons {
+        for trial in 0..trials {
+            let mut sim = HeadlessSim::from_state(state.iter().copied());
+            perturb(&mut sim.velocities, *epsilon, &mut StdRng::seed_from_u64(seed.wrapping_add(trial)));
+            runs.push((*epsilon, trial, survive(&mut sim, settings, criteria, duration)));
+        }
+    }
+    runs
+}
+
+pub fn csv(runs: &[(f32, u64, Survival)]) -> String {
+    let mut csv = String::from("epsilon,trial,survival_time,steps,outcome\n");
+    for (epsilon, trial, survival) in runs {
+        csv.push_str(&format!(
+            "{},{},{},{},{}\n",
+            epsilon,
+            trial,
+            survival.time,
+            survival.steps,
+            survival.outcome.label()
+        ));
+    }
+    csv
+}
+
+pub fn run(args: &[String]) -> Result<(), String> {
+    let mut choreography = None;
+    let mut n_bodies: Option<u32> = None;
+    let mut seed: u64 = 0;
+    let mut epsilons: Vec<f32> = vec![1e-6, 1e-5, 1e-4, 1e-3, 1e-2, 1e-1];
+    let mut trials: u64 = 10;
+    let mut duration: f64 = 100.0;
+    let mut delta_t: Option<f32> = None;
+    let mut criteria = Criteria::default();
+    let mut out_path = "stability.csv".to_string();
+
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--choreography" => {
+                choreography = Some(args.next().and_then(|name| Choreography::from_name(name)).ok_or(USAGE)?)
+            }
+            "--n" => n_bodies = Some(args.next().and_then(|n| n.parse().ok()).ok_or(USAGE)?),
+            "--seed" => seed = args.next().and_then(|s| s.parse().ok()).ok_or(USAGE)?,
+            "--epsilons" => {
+                epsilons = args
+                    .next()
+                    .and_then(|list| list.split(',').map(|e| e.trim().parse().ok()).collect::<Option<Vec<f32>>>())
+                    .filter(|list| !list.is_empty())
+                    .ok_or(USAGE)?
+            }
+            "--trials" => trials = args.next().and_then(|n| n.parse().ok()).filter(|n| *n > 0).ok_or(USAGE)?,
+            "--duration" => duration = args.next().and_then(|d| d.parse().ok()).ok_or(USAGE)?,
+            "--dt" => delta_t = Some(args.next().and_then(|d| d.parse().ok()).ok_or(USAGE)?),
+            "--escape" => {
+                criteria.escape_factor = args
+                    .next()
+                    .and_then(|f| f.parse().ok())
+                    .filter(|f: &f32| *f > 1.0)
+                    .ok_or(USAGE)?
+            }
+            "--no-collisions" => criteria.collisions = false,
+            "--check-every" => {
+                criteria.check_every = args.next().and_then(|n| n.parse().ok()).filter(|n| *n > 0).ok_or(USAGE)?
+            }
+            "--out" => out_path = args.next().ok_or(USAGE)?.clone(),
+            _ => return Err(USAGE.to_string()),
+        }
+    }
+
+    let mut settings = SimulationSettings::default();
+    let state: Vec<(Body, Transform, Vec3)> = match choreography {
+        Some(choreography) => {
+            // the presets only hold together with exact forces
+            let library = ChoreographyLibrary::default();
+            let (state, period) = scaled_state(choreography, settings.g, library.mass, library.size);
+            settings.delta_t = recommended_dt(choreography, period);
+            settings.theta = 0.0;
+            settings.force_law = ForceLaw::InverseSquare;
+            println!("{}: period {}", choreography.label(), period);
+            state
+                .into_iter()
+                .map(|(mass, position, velocity)| {
+                    let body = Body {
+                        mass,
+                        radius: settings.collision_radius(mass),
+                        hue: 0.0,
+                    };
+                    (body, Transform::from_translation(position.extend(0.0)), velocity.extend(0.0))
+                })
+                .collect()
+        }
+        None => {
+            settings.n_bodies = n_bodies.ok_or(USAGE)?;
+            generate_bodies(&settings, &mut StdRng::seed_from_u64(seed))
+                .into_iter()
+                .map(|(body, transform, velocity)| (body, transform, velocity.0))
+                .collect()
+        }
+    };
+    if let Some(delta_t) = delta_t {
+        settings.delta_t = delta_t;
+    }
+    let warnings = validate(&mut settings);
+    if !warnings.is_empty() {
+        return Err(warnings.join(", "));
+    }
+
+    let runs = stability_runs(&state, &settings, criteria, &epsilons, trials, duration, seed);
+    for epsilon in &epsilons {
+        let mut times: Vec<f64> = runs
+            .iter()
+            .filter(|(e, ..)| e == epsilon)
+            .map(|(_e, _trial, survival)| survival.time)
+            .collect();
+        times.sort_by(f64::total_cmp);
+        let survived = runs
+            .iter()
+            .filter(|(e, _trial, survival)| e == epsilon && survival.outcome == Outcome::Survived)
+            .count();
+        println!(
+            "epsilon {:e}: median survival {:.3}, {}/{} lasted the whole {}",
+            epsilon,
+            times[times.len() / 2],
+            survived,
+            trials,
+            duration
+        );
+    }
+
+    fs::write(&out_path, csv(&runs)).map_err(|e| format!("{}: {}", out_path, e))?;
+    println!("Wrote {}", out_path);
+    Ok(())
+}
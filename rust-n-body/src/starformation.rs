@@ -0,0 +1,121 @@
+use bevy::prelude::*;
+use crate::bhtree::{Quad, Quadtree};
+use crate::energy::WorkError;
+use crate::fade::{Fade, despawn_with_fade};
+use crate::force::Star;
+use crate::guard::Quarantined;
+use crate::leaderboard::Merges;
+use crate::tracer::Massless;
+use crate::{Body, SimulationSettings, Velocity};
+
+const STAR_COLOR: Color = Color::srgb(1.0, 0.92, 0.6);
+
+// A body made out of a collapsed clump. It keeps its own color when the palette changes
+// and doesn't take part in forming further stars.
+#[derive(Component)]
+pub struct FormedStar;
+
+// Groups of at least members bodies within radius of one of them, found with the tree.
+// Every body ends up in at most one clump, the first one that claims it.
+pub fn find_clumps(positions: &[Vec2], radius: f32, members: usize) -> Vec<Vec<usize>> {
+    let mut clumps = Vec::new();
+    if positions.len() < members.max(1) {
+        return clumps;
+    }
+    let mut tree = Quadtree::new(Quad::new_containing(positions));
+    for (i, p) in positions.iter().enumerate() {
+        let body = Body {
+            mass: 1.0,
+            radius: 0.0,
+            hue: 0.0,
+        };
+        tree.insert(Entity::from_raw(i as u32), Transform::from_translation(p.extend(0.0)), body);
+    }
+
+    let mut claimed = vec![false; positions.len()];
+    for i in 0..positions.len() {
+        if claimed[i] {
+            continue;
+        }
+        let clump: Vec<usize> = tree
+            .query_circle(positions[i], radius)
+            .map(|(entity, _transform, _body)| entity.index() as usize)
+            .filter(|j| !claimed[*j])
+            .collect();
+        if clump.len() >= members {
+            for j in &clump {
+                claimed[*j] = true;
+            }
+            clumps.push(clump);
+        }
+    }
+    clumps
+}
+
+// One body standing in for the clump: the total mass at the center of mass, moving with the total momentum
+pub fn merge_clump(members: &[(f32, Vec2, Vec2)]) -> Option<(f32, Vec2, Vec2)> {
+    let mass: f32 = members.iter().map(|(m, _p, _v)| m).sum();
+    if mass <= 0.0 {
+        return None;
+    }
+    let (weighted_pos, momentum) = members
+        .iter()
+        .fold((Vec2::ZERO, Vec2::ZERO), |(p, v), (m, pos, vel)| (p + *m * *pos, v + *m * *vel));
+    Some((mass, weighted_pos / mass, momentum / mass))
+}
+
+// Turns dense clumps into stars, so a collapsing cloud visibly fragments into them
+pub fn form_stars(
+    settings: Res<SimulationSettings>,
+    bodies: Query<
+        (Entity, &Body, &Transform, &Velocity),
+        (Without<Massless>, Without<FormedStar>, Without<Quarantined>),
+    >,
+    mut commands: Commands,
+    mut materials: ResMut<Assets<ColorMaterial>>,
+    mut meshes: ResMut<Assets<Mesh>>,
+) {
+    if !settings.star_formation || settings.paused {
+        return;
+    }
+
+    let candidates: Vec<(Entity, f32, Vec2, Vec2)> = bodies
+        .iter()
+        .map(|(entity, body, transform, velocity)| {
+            (entity, body.mass, transform.translation.truncate(), velocity.0.truncate())
+        })
+        .collect();
+    let positions: Vec<Vec2> = candidates.iter().map(|(_e, _m, p, _v)| *p).collect();
+
+    for clump in find_clumps(&positions, settings.star_radius, settings.star_members as usize) {
+        let members: Vec<(f32, Vec2, Vec2)> = clump
+            .iter()
+            .map(|i| (candidates[*i].1, candidates[*i].2, candidates[*i].3))
+            .collect();
+        let Some((mass, pos, vel)) = merge_clump(&members) else {
+            continue;
+        };
+        for i in &clump {
+            despawn_with_fade(&mut commands, candidates[*i].0);
+        }
+
+        let body = Body {
+            mass,
+            radius: settings.collision_radius(mass),
+            hue: 1.0,
+        };
+        commands.spawn((
+            Mesh2d(meshes.add(Circle::new(settings.render_radius(body.radius)))),
+            MeshMaterial2d(materials.add(ColorMaterial::from_color(STAR_COLOR))),
+            body,
+            Transform::from_xyz(pos.x, pos.y, settings.z).with_scale(Vec3::splat(0.01)),
+            Velocity(vel.extend(0.0)),
+            Fade::fade_in(),
+            WorkError::default(),
+            Star,
+            FormedStar,
+            Merges(clump.len() as u32),
+        ));
+        debug!("Formed a star of mass {:.1} from {} bodies", mass, clump.len());
+    }
+}
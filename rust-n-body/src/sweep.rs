@@ -0,0 +1,225 @@
+use bevy::prelude::Entity;
+use std::fs;
+use std::time::Instant;
+use crate::SimulationSettings;
+use crate::automation::{Automation, headless_state};
+use crate::validate::validate;
+use crate::groups::{CATALOG_HEADER, catalog_lines};
+use crate::headless::HeadlessSim;
+use crate::origin::WorldOrigin;
+use crate::progress::Progress;
+
+// Halos smaller than this are left out of the catalog
+const HALO_MIN_MEMBERS: usize = 10;
+
+// One line of the sweep file
+#[derive(Debug, PartialEq)]
+pub struct SweepCase {
+    pub theta: f32,
+    pub delta_t: f32,
+    pub n_bodies: u32,
+    pub seed: u64,
+}
+
+const USAGE: &str = "usage: rust-n-body --sweep <cases.csv> [--out <summary.csv>] [--duration <simulated time>]
+                   [--halos <catalog.csv>] [--halo-every <steps>] [--linking-length <length>]
+                   [--progress <seconds>] [--progress-json] [--stop-energy-error <error>] [--stop-pair <i,j,distance>]
+cases.csv has the header theta,dt,n,seed and one case per line. Lines starting with # are skipped.
+The automation rules end a case early: --stop-energy-error when the relative energy error goes above it,
+--stop-pair when bodies i and j come closer than the distance. The rule that fired goes in the summary.
+With --halos a friends-of-friends halo catalog is written every --halo-every steps (default 100).
+Progress is printed every --progress seconds of wall time (default 10, 0 for none), as JSON lines
+with --progress-json.";
+
+pub fn parse_cases(text: &str) -> Result<Vec<SweepCase>, String> {
+    let mut cases = Vec::new();
+    for (line_no, line) in text.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') || line.starts_with("theta") {
+            continue;
+        }
+        let fields: Vec<&str> = line.split(',').map(|f| f.trim()).collect();
+        if fields.len() != 4 {
+            return Err(format!("line {}: expected 4 fields, got {}", line_no + 1, fields.len()));
+        }
+        let bad = |name: &str| format!("line {}: could not parse {}", line_no + 1, name);
+        cases.push(SweepCase {
+            theta: fields[0].parse().map_err(|_| bad("theta"))?,
+            delta_t: fields[1].parse().map_err(|_| bad("dt"))?,
+            n_bodies: fields[2].parse().map_err(|_| bad("n"))?,
+            seed: fields[3].parse().map_err(|_| bad("seed"))?,
+        });
+    }
+    if cases.is_empty() {
+        return Err("no cases in the file".to_string());
+    }
+    Ok(cases)
+}
+
+// Runs every case for the same simulated time and writes one summary line per case
+pub fn run(args: &[String]) -> Result<(), String> {
+    let mut cases_path = None;
+    let mut out_path = "sweep_summary.csv".to_string();
+    let mut duration: f64 = 1.0;
+    let mut halo_path: Option<String> = None;
+    let mut halo_every: u64 = 100;
+    let mut linking_length: Option<f32> = None;
+    let mut progress_every: f64 = 10.0;
+    let mut progress_json = false;
+    let mut automation = Automation::default();
+
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--out" => out_path = args.next().ok_or(USAGE)?.clone(),
+            "--duration" => {
+                duration = args
+                    .next()
+                    .and_then(|d| d.parse().ok())
+                    .ok_or(USAGE)?
+            }
+            "--halos" => halo_path = Some(args.next().ok_or(USAGE)?.clone()),
+            "--halo-every" => {
+                halo_every = args
+                    .next()
+                    .and_then(|n| n.parse().ok())
+                    .filter(|n| *n > 0)
+                    .ok_or(USAGE)?
+            }
+            "--linking-length" => linking_length = Some(args.next().and_then(|l| l.parse().ok()).ok_or(USAGE)?),
+            "--progress" => {
+                progress_every = args
+                    .next()
+                    .and_then(|s| s.parse().ok())
+                    .filter(|s: &f64| *s >= 0.0)
+                    .ok_or(USAGE)?
+            }
+            "--progress-json" => progress_json = true,
+            "--stop-energy-error" => {
+                automation.energy_rule = true;
+                automation.max_energy_error = args
+                    .next()
+                    .and_then(|e| e.parse().ok())
+                    .filter(|e: &f64| *e > 0.0)
+                    .ok_or(USAGE)?
+            }
+            "--stop-pair" => {
+                let fields: Vec<&str> = args.next().ok_or(USAGE)?.split(',').map(|f| f.trim()).collect();
+                let [i, j, distance] = fields[..] else {
+                    return Err(USAGE.to_string());
+                };
+                let index = |f: &str| f.parse().map(Entity::from_raw).map_err(|_| USAGE.to_string());
+                automation.pair = Some((index(i)?, index(j)?));
+                automation.pair_distance = distance.parse().map_err(|_| USAGE.to_string())?;
+            }
+            path if cases_path.is_none() => cases_path = Some(path.to_string()),
+            _ => return Err(USAGE.to_string()),
+        }
+    }
+    let cases_path = cases_path.ok_or(USAGE)?;
+
+    let text = fs::read_to_string(&cases_path).map_err(|e| format!("{}: {}", cases_path, e))?;
+    let cases = parse_cases(&text)?;
+
+    let mut summary = String::from("theta,dt,n,seed,steps,wall_time_s,energy_error,bound_fraction,stopped_by\n");
+    let mut catalog = format!("case,{}\n", CATALOG_HEADER);
+    for (i, case) in cases.iter().enumerate() {
+        let mut settings = SimulationSettings {
+            theta: case.theta,
+            delta_t: case.delta_t,
+            n_bodies: case.n_bodies,
+            ..Default::default()
+        };
+        // a sweep should run what was asked for, not a corrected version of it
+        let warnings = validate(&mut settings);
+        if !warnings.is_empty() {
+            return Err(format!("case {}: {}", i, warnings.join(", ")));
+        }
+
+        let mut sim = HeadlessSim::new(&settings, case.seed);
+        let (k0, p0) = sim.energy(&settings);
+        let initial = k0 + p0;
+
+        let relative_error = |(k, p): (f64, f64)| {
+            if initial != 0.0 { ((k + p - initial) / initial).abs() } else { 0.0 }
+        };
+        let progress = |sim: &HeadlessSim, energy_error: f64, elapsed_secs: f64| Progress {
+            case: i + 1,
+            cases: cases.len(),
+            step: sim.steps,
+            total_steps: (duration / case.delta_t as f64).ceil() as u64,
+            time: sim.time,
+            duration,
+            steps_per_sec: if elapsed_secs > 0.0 { sim.steps as f64 / elapsed_secs } else { 0.0 },
+            energy_error,
+            elapsed_secs,
+        };
+
+        // the headless runs never move the origin
+        let origin = WorldOrigin::default();
+        let start = Instant::now();
+        let mut last_report = start;
+        let mut stopped_by = None;
+        while sim.time < duration && stopped_by.is_none() {
+            if halo_path.is_some() && sim.steps.is_multiple_of(halo_every) {
+                for line in catalog_lines(sim.time, &sim.halos(linking_length, HALO_MIN_MEMBERS), &origin) {
+                    catalog.push_str(&format!("{},{}\n", i, line));
+                }
+            }
+            sim.step(&settings);
+            stopped_by = automation.check(&settings, &headless_state(&automation, &sim, &settings, initial));
+
+            if progress_every > 0.0 && last_report.elapsed().as_secs_f64() >= progress_every {
+                last_report = Instant::now();
+                // the energy costs a tree build, so it is only measured when it is reported
+                let report = progress(&sim, relative_error(sim.energy(&settings)), start.elapsed().as_secs_f64());
+                println!("{}", if progress_json { report.json("progress") } else { report.text() });
+            }
+        }
+        let wall_time = start.elapsed().as_secs_f64();
+
+        let energy_error = relative_error(sim.energy(&settings));
+        let bound_fraction = sim.bound_fraction(&settings);
+
+        if progress_json {
+            println!("{}", progress(&sim, energy_error, wall_time).json("done"));
+        } else {
+            println!(
+                "[{}/{}] theta={} dt={} n={} seed={}: {} steps in {:.2}s, energy error {:.3e}, bound {:.3}",
+                i + 1,
+                cases.len(),
+                case.theta,
+                case.delta_t,
+                case.n_bodies,
+                case.seed,
+                sim.steps,
+                wall_time,
+                energy_error,
+                bound_fraction
+            );
+        }
+        if let Some(reason) = &stopped_by {
+            println!("  stopped early: {}", reason);
+        }
+        summary.push_str(&format!(
+            "{},{},{},{},{},{},{},{},{}\n",
+            case.theta,
+            case.delta_t,
+            case.n_bodies,
+            case.seed,
+            sim.steps,
+            wall_time,
+            energy_error,
+            bound_fraction,
+            stopped_by.unwrap_or_default()
+        ));
+    }
+
+    fs::write(&out_path, summary).map_err(|e| format!("{}: {}", out_path, e))?;
+    println!("Wrote {}", out_path);
+    if let Some(halo_path) = halo_path {
+        fs::write(&halo_path, catalog).map_err(|e| format!("{}: {}", halo_path, e))?;
+        println!("Wrote {}", halo_path);
+    }
+    Ok(())
+}
@@ -0,0 +1,369 @@
+use bevy::prelude::*;
+use bevy_egui::{EguiContexts, egui};
+use crate::guard::Quarantined;
+use crate::selection::Selected;
+use crate::tracer::Massless;
+use crate::ui_config::UiConfig;
+use crate::{Body, MainCamera, SimulationSettings, Velocity};
+
+// Each group that exerts on anything gets its own tree, so the number is kept small
+pub const MAX_GROUPS: usize = 8;
+
+// The user-defined group a body was put in. Bodies without one are in the first group.
+#[derive(Component, Clone, Copy, Debug, PartialEq, Eq)]
+pub struct GroupTag(pub usize);
+
+// Hidden because its group is, left alone by the render decimation until shown again
+#[derive(Component)]
+pub struct GroupHidden;
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct TagGroup {
+    pub name: String,
+    pub visible: bool,
+    pub override_color: bool,
+    // sRGB, only used while override_color is on
+    pub color: [f32; 3],
+}
+
+impl TagGroup {
+    fn new(name: &str) -> Self {
+        TagGroup {
+            name: name.to_string(),
+            visible: true,
+            override_color: false,
+            color: [1.0, 1.0, 1.0],
+        }
+    }
+
+    pub fn color(&self) -> Option<Color> {
+        self.override_color
+            .then(|| Color::srgb(self.color[0], self.color[1], self.color[2]))
+    }
+}
+
+// The groups and which of them pull on which. Starts out as one group where everything interacts,
+// which is the plain simulation.
+#[derive(Resource, Clone, Debug, PartialEq)]
+pub struct TagGroups {
+    pub groups: Vec<TagGroup>,
+    // acts_on[source][target]: whether bodies in source pull on bodies in target
+    acts_on: Vec<Vec<bool>>,
+    // newly spawned bodies go in here
+    pub spawn_group: usize,
+}
+
+impl Default for TagGroups {
+    fn default() -> Self {
+        TagGroups {
+            groups: vec![TagGroup::new("Default")],
+            acts_on: vec![vec![true]],
+            spawn_group: 0,
+        }
+    }
+}
+
+impl TagGroups {
+    // A new group interacts with every other one both ways. None when there are already MAX_GROUPS.
+    pub fn add(&mut self, name: &str) -> Option<usize> {
+        if self.groups.len() >= MAX_GROUPS {
+            return None;
+        }
+        self.groups.push(TagGroup::new(name));
+        for row in &mut self.acts_on {
+            row.push(true);
+        }
+        self.acts_on.push(vec![true; self.groups.len()]);
+        Some(self.groups.len() - 1)
+    }
+
+    // The first group can't be removed, it's where untagged bodies are
+    pub fn remove(&mut self, group: usize) {
+        if group == 0 || group >= self.groups.len() {
+            return;
+        }
+        self.groups.remove(group);
+        self.acts_on.remove(group);
+        for row in &mut self.acts_on {
+            row.remove(group);
+        }
+        if self.spawn_group == group {
+            self.spawn_group = 0;
+        } else if self.spawn_group > group {
+            self.spawn_group -= 1;
+        }
+    }
+
+    // Tags that point past the end, left over from a removed group, count as the first group
+    pub fn index(&self, tag: Option<&GroupTag>) -> usize {
+        tag.map(|tag| tag.0).filter(|group| *group < self.groups.len()).unwrap_or(0)
+    }
+
+    pub fn acts_on(&self, source: usize, target: usize) -> bool {
+        self.acts_on[source][target]
+    }
+
+    pub fn set_acts_on(&mut self, source: usize, target: usize, acts: bool) {
+        self.acts_on[source][target] = acts;
+    }
+
+    // Whether the bodies in this group pull on anything at all
+    pub fn exerts(&self, source: usize) -> bool {
+        self.acts_on[source].iter().any(|acts| *acts)
+    }
+
+    // Nothing is switched off, so one tree of every body does
+    pub fn all_interact(&self) -> bool {
+        self.acts_on.iter().flatten().all(|acts| *acts)
+    }
+
+    pub fn visible(&self, group: usize) -> bool {
+        self.groups[group].visible
+    }
+
+    pub fn color(&self, tag: Option<&GroupTag>) -> Option<Color> {
+        self.groups[self.index(tag)].color()
+    }
+}
+
+// A group on its own: where it is, how it moves as a whole and how much it moves inside
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct GroupFrame {
+    pub bodies: usize,
+    pub mass: f32,
+    pub com: Vec2,
+    pub com_vel: Vec2,
+    // kinetic energy relative to com_vel, the bulk motion left out
+    pub internal_kinetic: f32,
+}
+
+// One frame per group from (group, mass, position, velocity) of every body
+pub fn group_frames(groups: usize, bodies: &[(usize, f32, Vec2, Vec2)]) -> Vec<GroupFrame> {
+    let mut frames = vec![GroupFrame::default(); groups];
+    for (group, mass, position, velocity) in bodies {
+        let frame = &mut frames[*group];
+        frame.bodies += 1;
+        frame.mass += mass;
+        frame.com += *mass * *position;
+        frame.com_vel += *mass * *velocity;
+    }
+    for frame in &mut frames {
+        if frame.mass > 0.0 {
+            frame.com /= frame.mass;
+            frame.com_vel /= frame.mass;
+        }
+    }
+    for (group, mass, _position, velocity) in bodies {
+        let frame = &mut frames[*group];
+        frame.internal_kinetic += 0.5 * mass * (*velocity - frame.com_vel).length_squared();
+    }
+    frames
+}
+
+// The last frames of the groups and the one the camera is locked to
+#[derive(Resource, Default)]
+pub struct GroupFrames {
+    pub frames: Vec<GroupFrame>,
+    pub follow: Option<usize>,
+}
+
+// Tracers have no mass, they would only count as bodies
+pub fn track_group_frames(
+    mut tracked: ResMut<GroupFrames>,
+    tag_groups: Res<TagGroups>,
+    bodies: Query<(&Body, &Transform, &Velocity, Option<&GroupTag>), (Without<Massless>, Without<MainCamera>)>,
+    mut cameras: Query<&mut Transform, With<MainCamera>>,
+) {
+    let states: Vec<(usize, f32, Vec2, Vec2)> = bodies
+        .iter()
+        .map(|(body, transform, velocity, tag)| {
+            (tag_groups.index(tag), body.mass, transform.translation.truncate(), velocity.0.truncate())
+        })
+        .collect();
+    tracked.frames = group_frames(tag_groups.groups.len(), &states);
+
+    let Some(group) = tracked.follow else {
+        return;
+    };
+    // an empty group has nowhere to be
+    match tracked.frames.get(group) {
+        Some(frame) if frame.mass > 0.0 => {
+            if let Ok(mut camera) = cameras.single_mut() {
+                camera.translation = frame.com.extend(camera.translation.z);
+            }
+        }
+        _ => tracked.follow = None,
+    }
+}
+
+// Anything spawned while a group other than the first is picked goes into it
+pub fn tag_spawned(
+    mut commands: Commands,
+    tag_groups: Res<TagGroups>,
+    spawned: Query<Entity, (Added<Body>, Without<GroupTag>)>,
+) {
+    if tag_groups.spawn_group == 0 {
+        return;
+    }
+    for entity in &spawned {
+        commands.entity(entity).insert(GroupTag(tag_groups.spawn_group));
+    }
+}
+
+pub fn hide_groups(
+    mut commands: Commands,
+    tag_groups: Res<TagGroups>,
+    bodies: Query<(Entity, Option<&GroupTag>, Has<GroupHidden>), (With<Body>, Without<Quarantined>)>,
+) {
+    for (entity, tag, hidden) in &bodies {
+        let show = tag_groups.visible(tag_groups.index(tag));
+        if show && hidden {
+            commands.entity(entity).remove::<GroupHidden>().insert(Visibility::Inherited);
+        } else if !show && !hidden {
+            commands.entity(entity).insert((GroupHidden, Visibility::Hidden));
+        }
+    }
+}
+
+pub fn tags_window(
+    mut contexts: EguiContexts,
+    mut tag_groups: ResMut<TagGroups>,
+    mut new_name: Local<String>,
+    ui_config: Res<UiConfig>,
+    bodies: Query<(Entity, Option<&GroupTag>, Has<Selected>), With<Body>>,
+    mut commands: Commands,
+    (mut tracked, settings): (ResMut<GroupFrames>, Res<SimulationSettings>),
+) {
+    // edited on a copy so the bodies are only recolored when something did change
+    let mut edited = tag_groups.clone();
+    let mut assign = None;
+    let mut remove = None;
+    ui_config
+        .place(egui::Window::new("Body Groups"), "Body Groups")
+        .default_open(false)
+        .show(contexts.ctx_mut(), |ui| {
+            let mut counts = vec![0; edited.groups.len()];
+            for (_entity, tag, _selected) in &bodies {
+                counts[edited.index(tag)] += 1;
+            }
+            for (i, group) in edited.groups.iter_mut().enumerate() {
+                ui.horizontal(|ui| {
+                    ui.add(egui::TextEdit::singleline(&mut group.name).desired_width(100.0));
+                    ui.label(format!("{} bodies", counts[i]));
+                    ui.checkbox(&mut group.visible, "Visible");
+                    ui.checkbox(&mut group.override_color, "Color");
+                    ui.color_edit_button_rgb(&mut group.color);
+                    if i > 0 && ui.button("Remove").clicked() {
+                        remove = Some(i);
+                    }
+                });
+            }
+            ui.horizontal(|ui| {
+                ui.add(egui::TextEdit::singleline(&mut *new_name).desired_width(100.0));
+                let can_add = edited.groups.len() < MAX_GROUPS;
+                if ui.add_enabled(can_add, egui::Button::new("Add Group")).clicked() {
+                    let name = if new_name.is_empty() {
+                        format!("Group {}", edited.groups.len() + 1)
+                    } else {
+                        new_name.clone()
+                    };
+                    edited.add(&name);
+                    new_name.clear();
+                }
+            });
+
+            ui.separator();
+            let units = settings.units;
+            let mut follow = tracked.follow;
+            for (i, frame) in tracked.frames.iter().enumerate().filter(|(_i, frame)| frame.bodies > 0) {
+                let Some(group) = edited.groups.get(i) else {
+                    continue;
+                };
+                ui.horizontal(|ui| {
+                    ui.label(format!(
+                        "{}: mass {}, COM ({}, {}), moving at {}, internal KE {:.4e}",
+                        group.name,
+                        units.mass(frame.mass),
+                        units.length(frame.com.x),
+                        units.length(frame.com.y),
+                        units.speed(frame.com_vel.length()),
+                        frame.internal_kinetic
+                    ));
+                    let following = follow == Some(i);
+                    if ui.selectable_label(following, "Follow").clicked() {
+                        follow = if following { None } else { Some(i) };
+                    }
+                });
+            }
+            tracked.follow = follow;
+
+            ui.separator();
+            let names: Vec<String> = edited.groups.iter().map(|group| group.name.clone()).collect();
+            egui::ComboBox::from_label("Spawn Into")
+                .selected_text(&names[edited.spawn_group])
+                .show_ui(ui, |ui| {
+                    for (i, name) in names.iter().enumerate() {
+                        ui.selectable_value(&mut edited.spawn_group, i, name);
+                    }
+                });
+            ui.horizontal(|ui| {
+                ui.label("Move Selected To");
+                for (i, name) in names.iter().enumerate() {
+                    if ui.button(name).clicked() {
+                        assign = Some(i);
+                    }
+                }
+            });
+
+            ui.separator();
+            ui.label("Pulls on (row pulls on column)");
+            egui::Grid::new("acts_on").show(ui, |ui| {
+                ui.label("");
+                for name in &names {
+                    ui.label(name);
+                }
+                ui.end_row();
+                for source in 0..names.len() {
+                    ui.label(&names[source]);
+                    for target in 0..names.len() {
+                        let mut acts = edited.acts_on(source, target);
+                        if ui.checkbox(&mut acts, "").changed() {
+                            edited.set_acts_on(source, target, acts);
+                        }
+                    }
+                    ui.end_row();
+                }
+            });
+        });
+
+    if let Some(group) = assign {
+        for (entity, _tag, selected) in &bodies {
+            if selected {
+                commands.entity(entity).insert(GroupTag(group));
+            }
+        }
+    }
+    if let Some(group) = remove {
+        edited.remove(group);
+        tracked.follow = match tracked.follow {
+            Some(followed) if followed == group => None,
+            Some(followed) if followed > group => Some(followed - 1),
+            followed => followed,
+        };
+        // its bodies go back to the first group, the ones after it move down one
+        for (entity, tag, _selected) in &bodies {
+            match tag {
+                Some(GroupTag(g)) if *g == group => {
+                    commands.entity(entity).insert(GroupTag(0));
+                }
+                Some(GroupTag(g)) if *g > group => {
+                    commands.entity(entity).insert(GroupTag(g - 1));
+                }
+                _ => {}
+            }
+        }
+    }
+    if edited != *tag_groups {
+        *tag_groups = edited;
+    }
+}
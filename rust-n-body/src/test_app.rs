@@ -0,0 +1,137 @@
+use bevy::gizmos::GizmoPlugin;
+use bevy::math::DVec2;
+use bevy::prelude::*;
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+use crate::bhtree::{Quad, Quadtree};
+use crate::clock::SimulationClock;
+use crate::{Body, PhysicsPlugin, SimulationSettings, Velocity, generate_bodies};
+
+// An app with only the physics in it and no window, for testing the systems themselves.
+// It steps once per update, whatever the settings say about substeps and speed.
+pub fn physics_app(settings: SimulationSettings) -> App {
+    let mut app = App::new();
+    // the gizmos want the shader assets the render plugin would have set up
+    app.add_plugins((MinimalPlugins, AssetPlugin::default()))
+        .init_asset::<Shader>()
+        .add_plugins(GizmoPlugin)
+        .insert_resource(SimulationSettings {
+            substeps: 1.0,
+            time_scale: 1.0,
+            ..settings
+        })
+        .add_plugins(PhysicsPlugin);
+    app
+}
+
+// The same bodies a reset with these settings would spawn
+pub fn spawn_generated(app: &mut App, seed: u64) {
+    let bodies = generate_bodies(app.world().resource::<SimulationSettings>(), &mut StdRng::seed_from_u64(seed));
+    app.world_mut().spawn_batch(bodies);
+}
+
+pub fn step(app: &mut App, n: u32) {
+    for _ in 0..n {
+        app.update();
+    }
+}
+
+pub fn clock(app: &App) -> &SimulationClock {
+    app.world().resource::<SimulationClock>()
+}
+
+// Mass, position and velocity of every body
+pub fn bodies(app: &mut App) -> Vec<(f32, Vec2, Vec2)> {
+    let mut query = app.world_mut().query::<(&Body, &Transform, &Velocity)>();
+    query
+        .iter(app.world())
+        .map(|(body, transform, velocity)| (body.mass, transform.translation.truncate(), velocity.0.truncate()))
+        .collect()
+}
+
+pub fn total_momentum(app: &mut App) -> DVec2 {
+    bodies(app)
+        .into_iter()
+        .map(|(mass, _position, velocity)| mass as f64 * velocity.as_dvec2())
+        .sum()
+}
+
+// The same seeded run twice, with setup told which of the two it is, for options that shouldn't
+// change the physics. The apps are handed back after the last step.
+pub fn run_twice(settings: SimulationSettings, seed: u64, steps: u32, setup: impl Fn(&mut App, bool)) -> [App; 2] {
+    [false, true].map(|second| {
+        let mut app = physics_app(settings.clone());
+        spawn_generated(&mut app, seed);
+        setup(&mut app, second);
+        step(&mut app, steps);
+        app
+    })
+}
+
+// Bodies scattered over a square reaching half_width from the origin, with masses from 1 to 10
+pub fn random_bodies(seed: u64, n: usize, half_width: f32) -> Vec<(Vec2, f32)> {
+    let mut rng = StdRng::seed_from_u64(seed);
+    (0..n)
+        .map(|_| {
+            let position = Vec2::new(
+                rng.random_range(-half_width..half_width),
+                rng.random_range(-half_width..half_width),
+            );
+            (position, rng.random_range(1.0..10.0))
+        })
+        .collect()
+}
+
+// A tree of the bodies, the i-th one as entity i
+pub fn tree_of(bodies: &[(Vec2, f32)], leaf_capacity: usize) -> Quadtree {
+    let positions: Vec<Vec2> = bodies.iter().map(|(position, _mass)| *position).collect();
+    let mut tree = Quadtree::with_leaf_capacity(Quad::new_containing(&positions), leaf_capacity);
+    for (i, (position, mass)) in bodies.iter().enumerate() {
+        tree.insert(Entity::from_raw(i as u32), at(*position), test_body(*mass));
+    }
+    tree
+}
+
+// The same for bodies of mass 1, the i-th point as entity i
+pub fn tree_of_points(points: &[Vec2], leaf_capacity: usize) -> Quadtree {
+    let bodies: Vec<(Vec2, f32)> = points.iter().map(|point| (*point, 1.0)).collect();
+    tree_of(&bodies, leaf_capacity)
+}
+
+pub fn test_body(mass: f32) -> Body {
+    Body {
+        mass,
+        radius: 1.0,
+        hue: 0.0,
+    }
+}
+
+pub fn at(position: Vec2) -> Transform {
+    Transform::from_translation(position.extend(0.0))
+}
+
+// Every body's own walk of the tree, with a dt of 1 so the kick is the acceleration
+pub fn per_body_accels(tree: &Quadtree, bodies: &[(Vec2, f32)], theta: f32) -> Vec<(Entity, Vec3)> {
+    let force = SimulationSettings::default().force_params();
+    bodies
+        .iter()
+        .enumerate()
+        .map(|(i, (position, mass))| {
+            let entity = Entity::from_raw(i as u32);
+            (entity, tree.get_total_accel(entity, at(*position), test_body(*mass), force, 1.0, theta))
+        })
+        .collect()
+}
+
+// How far the accelerations are from the exact ones, relative to their size. Both are looked up by
+// entity index, exact as per_body_accels gives them.
+pub fn rms_error(accels: &[(Entity, Vec3)], exact: &[(Entity, Vec3)]) -> f32 {
+    let mut error = 0.0;
+    let mut total = 0.0;
+    for (entity, accel) in accels {
+        let (_entity, exact) = exact[entity.index() as usize];
+        error += accel.distance_squared(exact);
+        total += exact.length_squared();
+    }
+    (error / total).sqrt()
+}
@@ -1,8 +1,95 @@
 
 
 #[cfg(test)]
+#[allow(clippy::module_inception)]
 mod tests {
-    use crate::mass_to_hue;
+    use crate::automation::{Automation, RuleState, headless_state};
+    use crate::benchmark::run_benchmark;
+    use crate::bhtree::{CutNode, Quad, Quadtree, WarmStart};
+    use crate::bookmarks::{Bookmark, Bookmarks, MAX_BOOKMARKED_BODIES, MAX_BOOKMARKS, thumbnail_pixels};
+    use crate::branch::right_half;
+    use crate::buckets::bucket_accels;
+    use crate::chirp::{chirp_pitch, orbital_frequency};
+    use crate::choreography::{Choreography, recommended_dt as choreography_dt, rotating_polygon, scaled_state};
+    use crate::clock::{MAX_SUBSTEPS, SimulationClock, pacing};
+    use crate::collision::CollisionEvent;
+    use crate::collisionlog::{CollisionLog, csv_line};
+    use crate::colormap::Colormap;
+    use crate::compare::{manifest_number, position_difference, read_npy, text_plot};
+    use crate::contours::{contour_levels, marching_squares};
+    use crate::controller::Controller;
+    use crate::decimation::{Decimation, display_scale, pick_visible};
+    use crate::director::{closest_approach, kth_nearest_distance, zoom_for_extent};
+    use crate::export::write_npy;
+    use crate::fade::{Fade, fade_system};
+    use crate::factory::{SystemFactory, SystemKind, build_system};
+    use crate::groups::{CATALOG_HEADER, catalog_lines, find_groups, find_halos, friends_of_friends};
+    use crate::guard::{NanAction, NanGuard, Quarantined};
+    use crate::headless::HeadlessSim;
+    use crate::kiosk::{Kiosk, Scenario};
+    use crate::kirkwood::{RESONANCES, axis_histogram, resonance_axis};
+    use crate::lagrangian::{LagrangianRadii, density_center, densest_point, lagrangian_radii};
+    use crate::leaderboard::{Merges, heaviest};
+    use crate::lifetime::{Lifetime, Lifetimes, SpawnSource, fragment_states};
+    use crate::lyapunov::Divergence;
+    use crate::help::{STEPS_PER_DYNAMICAL_TIME, dynamical_time, recommended_dt};
+    use crate::minimap::{bin_bodies, minimap_to_world, world_to_minimap};
+    use crate::net::{RemoteState, decode_chunk, encode_frame};
+    use crate::soa::BodyBuffers;
+    use crate::stability::{Criteria, Outcome, csv as stability_csv, perturb, stability_runs, survive};
+    use crate::sweep::{SweepCase, parse_cases};
+    use crate::starformation::{find_clumps, merge_clump};
+    use crate::shutdown::{RunSummary, dump_on_exit, state_csv};
+    use crate::sonify::{collision_voice, kinetic_pitch};
+    use crate::test_app::{
+        at, bodies, clock, per_body_accels, physics_app, random_bodies, rms_error, run_twice, spawn_generated, step,
+        test_body, total_momentum, tree_of, tree_of_points,
+    };
+    use crate::threads::compute_threads;
+    use crate::tracer::{Massless, tracer_positions};
+    use crate::tags::{GroupTag, TagGroups, group_frames};
+    use crate::timeline::{Action, Timeline, cluster_bodies, parse_script};
+    use crate::timestep::{SUGGESTED_DT_FRACTION, SuggestedDt, suggested_dt};
+    use crate::pip::inset_viewport;
+    use crate::poincare::{RotatingFrame, Section, jacobi_constant, section_crossing, section_starts};
+    use crate::pending::{PendingSettings, SpawnSettings};
+    use crate::physics_thread::{PhysicsThread, interpolate, interpolation_alpha};
+    use crate::pipeline::{PipelinedTree, TreeInputs};
+    use crate::origin::{WorldOrigin, rebase_origin, rebase_shift};
+    use crate::orbital_mechanics::{
+        find_primary, hill_radius, l1_point, l2_point, orbit_elements, periapsis_state, roche_limit,
+    };
+    use crate::progress::{Progress, eta_secs, format_duration};
+    use crate::providers::{AddForceProvider, CentralPotential, Drag, ForceInput, ForceProvider, WorldSummary};
+    use crate::radius::{RadiusLaw, radius_for_mass};
+    use crate::remnant::{analyze_remnant, half_mass_radius};
+    use crate::roche::overflow_state;
+    use crate::selection::Selected;
+    use crate::trace::trace_step;
+    use crate::trails::fade_alpha;
+    use crate::treedump::tree_json;
+    use crate::tools::{Explosion, Falloff};
+    use crate::transfer::{ScheduledTransfer, TransferPlan, hohmann};
+    use crate::ui_config::UiConfig;
+    use crate::undo::{BodyState, Snapshot, UndoBodies, UndoHistory, UndoStep, apply_undo};
+    use crate::units::UnitSystem;
+    use crate::validate::{SettingsWarnings, validate};
+    use crate::virial::{VirialMonitor, virial_scale, virial_velocities};
+    use crate::wind::{BackgroundFlow, FlowKind};
+    use crate::dualtree::dual_tree_accels;
+    use crate::energy::{Energy, WorkError};
+    use crate::flash::{flash_radius, impact_energy};
+    use crate::force::{ForceLaw, ForceParams, radiation_pressure};
+    use crate::{Body, ResetEvent, SimulationSettings, Velocity, mass_to_hue, reset_handler};
+    use bevy::ecs::schedule::IntoScheduleConfigs;
+    use bevy::math::DVec2;
+    use bevy::prelude::{App, Entity, Rect, Transform, UVec2, Vec2, Vec3, Visibility, With};
+    use bevy::tasks::{AsyncComputeTaskPool, TaskPool, TaskPoolBuilder};
+    use proptest::prelude::{Just, ProptestConfig, Strategy, prop};
+    use proptest::{prop_assert, prop_assert_eq, prop_oneof, proptest};
+    use std::time::{Duration, Instant};
+    use rand::rngs::StdRng;
+    use rand::{Rng, SeedableRng};
 
     #[test]
     fn test_hue_conversion_1() {
@@ -16,5 +103,3558 @@ mod tests {
     fn test_hue_conversion_10() {
         assert_eq!(mass_to_hue(2500.0, 0.0, 5000.0), 0.5);
     }
-    
-}
\ No newline at end of file
+
+    // All quads at the given depth below the root, using exactly representable sizes
+    fn quads_at_depth(root: Quad, depth: u32) -> Vec<Quad> {
+        let mut quads = vec![root];
+        for _ in 0..depth {
+            quads = quads.iter().flat_map(|q| q.subdivide()).collect();
+        }
+        quads
+    }
+
+    // Every corner, edge midpoint and center of every quad at the given depth below a 16x16 root
+    fn edge_points(depth: u32) -> Vec<Vec2> {
+        let cells = 1 << (depth + 1);
+        let step = 16.0 / cells as f32;
+        let mut points = Vec::new();
+        for i in 0..=cells {
+            for j in 0..=cells {
+                points.push(Vec2::new(-8.0 + i as f32 * step, -8.0 + j as f32 * step));
+            }
+        }
+        points
+    }
+
+    #[test]
+    fn test_quad_contains_min_edges() {
+        let quad = Quad::new(0.0, 0.0, 16.0);
+        assert!(quad.contains(Vec2::new(-8.0, -8.0)));
+        assert!(quad.contains(Vec2::new(-8.0, 0.0)));
+        assert!(quad.contains(Vec2::new(0.0, -8.0)));
+    }
+
+    #[test]
+    fn test_quad_excludes_max_edges() {
+        let quad = Quad::new(0.0, 0.0, 16.0);
+        assert!(!quad.contains(Vec2::new(8.0, 0.0)));
+        assert!(!quad.contains(Vec2::new(0.0, 8.0)));
+        assert!(!quad.contains(Vec2::new(8.0, 8.0)));
+        assert!(!quad.contains(Vec2::new(-8.0, 8.0)));
+        assert!(!quad.contains(Vec2::new(8.0, -8.0)));
+    }
+
+    #[test]
+    fn test_quad_shared_center_goes_north_east() {
+        let [nw, ne, sw, se] = Quad::new(0.0, 0.0, 16.0).subdivide();
+        let center = Vec2::ZERO;
+        assert!(!nw.contains(center));
+        assert!(ne.contains(center));
+        assert!(!sw.contains(center));
+        assert!(!se.contains(center));
+    }
+
+    #[test]
+    fn test_quad_edge_points_match_exactly_one_child() {
+        let root = Quad::new(0.0, 0.0, 16.0);
+        for depth in 0..5 {
+            let quads = quads_at_depth(root, depth);
+            for point in edge_points(depth) {
+                let matches = quads.iter().filter(|q| q.contains(point)).count();
+                let expected = if root.contains(point) { 1 } else { 0 };
+                assert_eq!(
+                    matches, expected,
+                    "point {:?} matched {} quads at depth {}",
+                    point, matches, depth
+                );
+            }
+        }
+    }
+
+    // Random points at least 2 units apart, so none of them share a leaf that is too small to split
+    fn random_points(seed: u64, n: usize) -> Vec<Vec2> {
+        let mut rng = StdRng::seed_from_u64(seed);
+        let mut points: Vec<Vec2> = Vec::new();
+        while points.len() < n {
+            let p = Vec2::new(rng.random_range(-300.0..=300.0), rng.random_range(-300.0..=300.0));
+            if points.iter().all(|q| q.distance(p) > 2.0) {
+                points.push(p);
+            }
+        }
+        points
+    }
+
+    fn brute_force_knn(points: &[Vec2], pos: Vec2, k: usize) -> Vec<u32> {
+        let mut indices: Vec<u32> = (0..points.len() as u32).collect();
+        indices.sort_by(|a, b| {
+            points[*a as usize]
+                .distance_squared(pos)
+                .total_cmp(&points[*b as usize].distance_squared(pos))
+        });
+        indices.truncate(k);
+        indices
+    }
+
+    #[test]
+    fn test_nearest_neighbor_matches_brute_force() {
+        let points = random_points(1, 500);
+        let tree = tree_of_points(&points, 1);
+        for query in random_points(2, 100) {
+            let (entity, _pos) = tree.nearest_neighbor(query).unwrap();
+            assert_eq!(entity.index(), brute_force_knn(&points, query, 1)[0]);
+        }
+    }
+
+    #[test]
+    fn test_knn_matches_brute_force() {
+        let points = random_points(3, 500);
+        let tree = tree_of_points(&points, 1);
+        for k in [1, 5, 20] {
+            for query in random_points(4, 50) {
+                let found: Vec<u32> = tree.knn(query, k).iter().map(|(e, _p)| e.index()).collect();
+                assert_eq!(found, brute_force_knn(&points, query, k));
+            }
+        }
+    }
+
+    #[test]
+    fn test_knn_more_than_bodies() {
+        let points = random_points(5, 10);
+        let tree = tree_of_points(&points, 1);
+        assert_eq!(tree.knn(Vec2::ZERO, 50).len(), 10);
+        assert!(tree.knn(Vec2::ZERO, 0).is_empty());
+    }
+
+    #[test]
+    fn test_query_rect_matches_brute_force() {
+        let points = random_points(6, 500);
+        let tree = tree_of_points(&points, 1);
+        let aabb = Rect::new(-120.0, -40.0, 75.0, 210.0);
+
+        let mut found: Vec<u32> = tree.query_rect(aabb).map(|(e, _t, _b)| e.index()).collect();
+        found.sort();
+        let expected: Vec<u32> = (0..points.len() as u32)
+            .filter(|i| aabb.contains(points[*i as usize]))
+            .collect();
+        assert_eq!(found, expected);
+    }
+
+    #[test]
+    fn test_query_circle_matches_brute_force() {
+        let points = random_points(7, 500);
+        let tree = tree_of_points(&points, 1);
+        for (center, radius) in random_points(8, 20).into_iter().zip([5.0, 30.0, 120.0].into_iter().cycle()) {
+            let mut found: Vec<u32> = tree
+                .query_circle(center, radius)
+                .map(|(e, _t, _b)| e.index())
+                .collect();
+            found.sort();
+            let expected: Vec<u32> = (0..points.len() as u32)
+                .filter(|i| points[*i as usize].distance(center) <= radius)
+                .collect();
+            assert_eq!(found, expected);
+        }
+    }
+
+    // Not a real test, run with `cargo test --release -- --ignored --nocapture` to see the timings.
+    // The time per query should grow much slower than N for the tree, and linearly for the scan.
+    #[test]
+    #[ignore]
+    fn bench_query_circle_scaling() {
+        for n in [1_000, 4_000, 16_000] {
+            let points = random_points(9, n);
+            let tree = tree_of_points(&points, 1);
+            let queries = random_points(10, 1_000);
+
+            let start = Instant::now();
+            let mut hits = 0;
+            for q in &queries {
+                hits += tree.query_circle(*q, 10.0).count();
+            }
+            let tree_time = start.elapsed();
+
+            let start = Instant::now();
+            let mut scan_hits = 0;
+            for q in &queries {
+                scan_hits += points.iter().filter(|p| p.distance(*q) <= 10.0).count();
+            }
+            let scan_time = start.elapsed();
+
+            assert_eq!(hits, scan_hits);
+            println!("n = {:>6}: tree {:?}, scan {:?}", n, tree_time, scan_time);
+        }
+    }
+
+    #[test]
+    fn test_colormap_endpoints() {
+        assert_eq!(Colormap::Viridis.sample_rgb(0.0), [68.0 / 255.0, 1.0 / 255.0, 84.0 / 255.0]);
+        assert_eq!(Colormap::Viridis.sample_rgb(1.0), [253.0 / 255.0, 231.0 / 255.0, 37.0 / 255.0]);
+    }
+
+    #[test]
+    fn test_colormap_clamps_out_of_range() {
+        for colormap in Colormap::ALL {
+            assert_eq!(colormap.sample_rgb(-3.0), colormap.sample_rgb(0.0));
+            assert_eq!(colormap.sample_rgb(7.0), colormap.sample_rgb(1.0));
+            assert_eq!(colormap.sample_rgb(f32::NAN), colormap.sample_rgb(0.0));
+        }
+    }
+
+    #[test]
+    fn test_default_force_law_is_inverse_square() {
+        assert_eq!(ForceParams::default().law, ForceLaw::InverseSquare);
+        assert_eq!(SimulationSettings::default().force_params().law, ForceLaw::InverseSquare);
+    }
+
+    #[test]
+    fn test_inverse_square_magnitude() {
+        let force = ForceParams {
+            g: 2.0,
+            ..ForceParams::default()
+        };
+        let accel = force.accel(10.0, Vec3::ZERO, Vec3::new(4.0, 0.0, 0.0));
+        // g * m / r² towards the other body
+        assert!((accel - Vec3::new(2.0 * 10.0 / 16.0, 0.0, 0.0)).length() < 1e-6);
+
+        // twice as far away pulls a quarter as hard
+        let far = force.accel(10.0, Vec3::ZERO, Vec3::new(8.0, 0.0, 0.0));
+        assert!((accel.length() / far.length() - 4.0).abs() < 1e-4);
+    }
+
+    #[test]
+    fn test_tree_uses_inverse_square_by_default() {
+        let mut tree = Quadtree::new(Quad::new(0.0, 0.0, 100.0));
+        let body = Body {
+            mass: 5.0,
+            radius: 1.0,
+            hue: 0.0,
+        };
+        let a = Transform::from_xyz(-10.0, 1.0, 0.0);
+        let b = Transform::from_xyz(10.0, 1.0, 0.0);
+        tree.insert(Entity::from_raw(0), a, body);
+        tree.insert(Entity::from_raw(1), b, body);
+
+        let settings = SimulationSettings::default();
+        let kick = tree.get_total_accel(Entity::from_raw(0), a, body, settings.force_params(), 1.0, 0.5);
+        let expected = settings.g * 5.0 / (20.0 * 20.0);
+        assert!((kick.x - expected).abs() < expected * 1e-4);
+        assert!(kick.y.abs() < 1e-6);
+    }
+
+    #[test]
+    fn test_potential_matches_force_for_every_law() {
+        // The force should be minus the slope of the potential, checked with a central difference
+        for law in ForceLaw::ALL {
+            let force = ForceParams {
+                g: 3.0,
+                law,
+                softening: 2.0,
+                ..ForceParams::default()
+            };
+            for r in [1.0f32, 5.0, 20.0] {
+                let h = 1e-2 * r;
+                let source = Vec3::ZERO;
+                let slope = (force.potential(7.0, Vec3::new(r + h, 0.0, 0.0), source)
+                    - force.potential(7.0, Vec3::new(r - h, 0.0, 0.0), source))
+                    / (2.0 * h);
+                let accel = force.accel(7.0, Vec3::new(r, 0.0, 0.0), source);
+                assert!(
+                    (accel.x + slope).abs() < 1e-2 * accel.x.abs(),
+                    "{:?} at r = {}: accel {} slope {}",
+                    law,
+                    r,
+                    accel.x,
+                    slope
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn test_mond_limits() {
+        let newtonian = Vec3::new(0.0, -1e-4, 0.0);
+        let off = ForceParams::default();
+        assert_eq!(off.mond_accel(newtonian), newtonian);
+
+        let mond = ForceParams {
+            mond: true,
+            a0: 1.0,
+            ..ForceParams::default()
+        };
+        // deep MOND: a = sqrt(a_N * a0), same direction
+        let deep = mond.mond_accel(newtonian);
+        assert!((deep.length() - (1e-4f32).sqrt()).abs() < 1e-2 * deep.length());
+        assert!(deep.normalize().abs_diff_eq(newtonian.normalize(), 1e-6));
+
+        // strong fields are barely touched
+        let strong = Vec3::new(1e4, 0.0, 0.0);
+        assert!((mond.mond_accel(strong).x / strong.x - 1.0).abs() < 1e-3);
+    }
+
+    #[test]
+    fn test_mond_rotation_curve_is_flat() {
+        // circular speed v = sqrt(a * r) around a point mass stops depending on r far out
+        let force = ForceParams {
+            mond: true,
+            a0: 0.01,
+            ..ForceParams::default()
+        };
+        let speed = |r: f32| {
+            let a = force.mond_accel(force.accel(1000.0, Vec3::new(r, 0.0, 0.0), Vec3::ZERO));
+            (a.length() * r).sqrt()
+        };
+        let (v1, v2) = (speed(5000.0), speed(20000.0));
+        assert!((v1 / v2 - 1.0).abs() < 0.05, "{} vs {}", v1, v2);
+    }
+
+    #[test]
+    fn test_post_newtonian_perihelion_advance() {
+        // Light body on an e = 0.5 orbit around a unit mass, with c low enough that the precession is
+        // easy to measure. Integrated with RK4 straight from the force functions.
+        let force = ForceParams {
+            g: 1.0,
+            post_newtonian: true,
+            c: 1000f32.sqrt(),
+            ..ForceParams::default()
+        };
+        let (m, a, e) = (1.0f32, 1.0f32, 0.5f32);
+        let accel = |p: Vec3, v: Vec3| force.accel(m, p, Vec3::ZERO) + force.post_newtonian(m, p, v);
+
+        // start at perihelion
+        let mut pos = Vec3::new(a * (1.0 - e), 0.0, 0.0);
+        let mut vel = Vec3::new(0.0, (force.g * m * (1.0 + e) / (a * (1.0 - e))).sqrt(), 0.0);
+        let dt = std::f32::consts::TAU / 4000.0;
+
+        // the Laplace-Runge-Lenz vector points at perihelion
+        let lrl = |p: Vec3, v: Vec3| {
+            let l = p.x * v.y - p.y * v.x;
+            Vec2::new(v.y * l, -v.x * l) - force.g * m * p.truncate().normalize()
+        };
+        let start = lrl(pos, vel);
+
+        let orbits = 3;
+        let mut perihelia = 0;
+        // r_prev2 starts below r so the starting point isn't counted as a perihelion
+        let (mut r_prev2, mut r_prev) = (0.0, pos.length());
+        let mut at_prev = (pos, vel);
+        while perihelia < orbits {
+            let k1 = (vel, accel(pos, vel));
+            let k2 = (vel + k1.1 * dt / 2.0, accel(pos + k1.0 * dt / 2.0, vel + k1.1 * dt / 2.0));
+            let k3 = (vel + k2.1 * dt / 2.0, accel(pos + k2.0 * dt / 2.0, vel + k2.1 * dt / 2.0));
+            let k4 = (vel + k3.1 * dt, accel(pos + k3.0 * dt, vel + k3.1 * dt));
+            pos += (k1.0 + 2.0 * k2.0 + 2.0 * k3.0 + k4.0) * dt / 6.0;
+            vel += (k1.1 + 2.0 * k2.1 + 2.0 * k3.1 + k4.1) * dt / 6.0;
+
+            let r = pos.length();
+            if r_prev < r_prev2 && r_prev < r {
+                perihelia += 1;
+            }
+            if perihelia < orbits {
+                at_prev = (pos, vel);
+            }
+            (r_prev2, r_prev) = (r_prev, r);
+        }
+
+        let end = lrl(at_prev.0, at_prev.1);
+        let measured = start.angle_to(end);
+        let expected = orbits as f32 * 6.0 * std::f32::consts::PI * force.g * m
+            / (force.c * force.c * a * (1.0 - e * e));
+        assert!(
+            (measured / expected - 1.0).abs() < 0.1,
+            "measured {} expected {}",
+            measured,
+            expected
+        );
+    }
+
+    #[test]
+    fn test_radiation_pressure_scaling() {
+        let push = radiation_pressure(100.0, 2.0, Vec3::new(0.0, 5.0, 0.0));
+        // outward, L / (m r²)
+        assert!((push - Vec3::new(0.0, 100.0 / (2.0 * 25.0), 0.0)).length() < 1e-6);
+
+        // twice as heavy or twice as far pushes less
+        let heavy = radiation_pressure(100.0, 4.0, Vec3::new(0.0, 5.0, 0.0));
+        let far = radiation_pressure(100.0, 2.0, Vec3::new(0.0, 10.0, 0.0));
+        assert!((push.length() / heavy.length() - 2.0).abs() < 1e-5);
+        assert!((push.length() / far.length() - 4.0).abs() < 1e-5);
+
+        // bodies lighter than 1 are pushed harder still, only massless tracers stop at mass 1
+        let dust = radiation_pressure(100.0, 0.01, Vec3::new(0.0, 5.0, 0.0));
+        assert!((dust.length() / push.length() - 200.0).abs() < 1e-2);
+        let tracer = radiation_pressure(100.0, 0.0, Vec3::new(0.0, 5.0, 0.0));
+        assert_eq!(tracer, radiation_pressure(100.0, 1.0, Vec3::new(0.0, 5.0, 0.0)));
+
+        assert_eq!(radiation_pressure(100.0, 2.0, Vec3::ZERO), Vec3::ZERO);
+    }
+
+    #[test]
+    fn test_npy_layout() {
+        let data = [1.0f32, -2.5, 3.0, 0.0, 5.0, 6.0];
+        let mut bytes = Vec::new();
+        write_npy(&mut bytes, &[2, 3], &data).unwrap();
+
+        assert_eq!(&bytes[..8], b"\x93NUMPY\x01\x00");
+        let header_len = u16::from_le_bytes([bytes[8], bytes[9]]) as usize;
+        // data starts on a 64 byte boundary and the header ends with a newline
+        assert_eq!((10 + header_len) % 64, 0);
+        let header = std::str::from_utf8(&bytes[10..10 + header_len]).unwrap();
+        assert!(header.starts_with("{'descr': '<f4', 'fortran_order': False, 'shape': (2, 3), }"));
+        assert!(header.ends_with('\n'));
+
+        let body: Vec<f32> = bytes[10 + header_len..]
+            .chunks_exact(4)
+            .map(|c| f32::from_le_bytes([c[0], c[1], c[2], c[3]]))
+            .collect();
+        assert_eq!(body, data);
+    }
+
+    #[cfg(feature = "ffi")]
+    #[test]
+    fn test_c_api() {
+        use rust_n_body::ffi::*;
+        unsafe {
+            let sim = nbody_create(1.0, 0.5, 0.01);
+            assert_eq!(nbody_add_body(sim, -1.0, 0.0, 0.0, -0.3, 1.0), 0);
+            assert_eq!(nbody_add_body(sim, 1.0, 0.0, 0.0, 0.3, 1.0), 1);
+            assert_eq!(nbody_body_count(sim), 2);
+            nbody_step(sim, 100);
+
+            let mut out = [f32::NAN; 4];
+            assert_eq!(nbody_read_positions(sim, out.as_mut_ptr(), out.len()), 2);
+            // equal masses pull equally, so they stay mirrored around the origin
+            assert!((out[0] + out[2]).abs() < 1e-4 && (out[1] + out[3]).abs() < 1e-4, "{out:?}");
+            // and they fell towards each other
+            assert!(out[2] - out[0] < 2.0, "{out:?}");
+
+            // a short buffer gets the bodies that fit
+            let mut short = [f32::NAN; 3];
+            assert_eq!(nbody_read_positions(sim, short.as_mut_ptr(), short.len()), 1);
+            assert_eq!(short[..2], out[..2]);
+            assert!(short[2].is_nan());
+            nbody_destroy(sim);
+
+            assert_eq!(nbody_add_body(std::ptr::null_mut(), 0.0, 0.0, 0.0, 0.0, 1.0), -1);
+            assert_eq!(nbody_body_count(std::ptr::null()), 0);
+        }
+    }
+
+    #[test]
+    fn test_deterministic_runs_are_bit_identical() {
+        let settings = SimulationSettings {
+            n_bodies: 200,
+            deterministic: true,
+            force_law: ForceLaw::InverseLinear,
+            ..SimulationSettings::default()
+        };
+        let run = || {
+            let mut sim = HeadlessSim::new(&settings, 42);
+            for _ in 0..20 {
+                sim.step(&settings);
+            }
+            let state: Vec<u32> = sim
+                .transforms
+                .iter()
+                .zip(&sim.velocities)
+                .flat_map(|(t, v)| [t.translation.x, t.translation.y, v.x, v.y])
+                .map(f32::to_bits)
+                .collect();
+            let (kinetic, potential) = sim.energy(&settings);
+            (state, kinetic.to_bits(), potential.to_bits())
+        };
+        assert_eq!(run(), run());
+    }
+
+    #[test]
+    fn test_deterministic_trajectories_on_any_thread_count() {
+        // the app's step walks the tree on the compute pool, whose size is up to the machine
+        let force = ForceParams {
+            law: ForceLaw::InverseLinear,
+            mond: true,
+            deterministic: true,
+            ..ForceParams::default()
+        };
+        let run = |threads: usize| {
+            let pool = TaskPoolBuilder::new().num_threads(threads).build();
+            let mut bodies = random_bodies(11, 700, 300.0);
+            let mut velocities = vec![Vec2::ZERO; bodies.len()];
+            for _ in 0..10 {
+                let tree = tree_of(&bodies, 16);
+                let walked: Vec<(Entity, Body, Transform)> = bodies
+                    .iter()
+                    .enumerate()
+                    .map(|(i, (position, mass))| (Entity::from_raw(i as u32), test_body(*mass), at(*position)))
+                    .collect();
+                let walk = crate::GravityWalk::Plain(&tree);
+                let kicks = crate::gravity_accels(&walk, &walked, &mut WarmStart::default(), force, 0.01, 0.5, &pool);
+                for (((position, _mass), velocity), kick) in bodies.iter_mut().zip(&mut velocities).zip(kicks) {
+                    *velocity += kick.truncate();
+                    *position += *velocity * 0.01;
+                }
+            }
+            bodies
+                .iter()
+                .flat_map(|(position, _mass)| [position.x.to_bits(), position.y.to_bits()])
+                .collect::<Vec<u32>>()
+        };
+        assert_eq!(run(1), run(5));
+    }
+
+    #[test]
+    fn test_deterministic_log_potential() {
+        let force = ForceParams {
+            law: ForceLaw::InverseLinear,
+            deterministic: true,
+            ..ForceParams::default()
+        };
+        let potential = force.potential(1.0, Vec3::ZERO, Vec3::new(std::f32::consts::E, 0.0, 0.0));
+        assert!((potential - 1.0).abs() < 1e-6);
+    }
+
+    #[test]
+    fn test_net_frame_round_trip() {
+        let mut rng = StdRng::seed_from_u64(7);
+        let states: Vec<RemoteState> = (0..9000)
+            .map(|id| RemoteState {
+                id,
+                pos: Vec2::new(rng.random_range(-500.0..500.0), rng.random_range(-200.0..300.0)),
+                radius: rng.random_range(0.5..20.0),
+                hue: rng.random_range(0.0..1.0),
+            })
+            .collect();
+
+        let packets = encode_frame(12, &states);
+        assert!(packets.len() > 1);
+        assert!(packets.iter().all(|p| p.len() < 65507));
+
+        let mut decoded = Vec::new();
+        for (i, packet) in packets.iter().enumerate() {
+            let chunk = decode_chunk(packet).unwrap();
+            assert_eq!(chunk.frame, 12);
+            assert_eq!(chunk.index as usize, i);
+            assert_eq!(chunk.count as usize, packets.len());
+            decoded.extend(chunk.bodies);
+        }
+
+        assert_eq!(decoded.len(), states.len());
+        // 16 bits over a 1000 wide frame is about 0.015 per step
+        for (a, b) in states.iter().zip(&decoded) {
+            assert_eq!(a.id, b.id);
+            assert!(a.pos.distance(b.pos) < 0.02);
+            assert!((a.radius - b.radius).abs() <= 1.0 / 64.0);
+            assert!((a.hue - b.hue).abs() <= 1.0 / 255.0);
+        }
+
+        assert!(decode_chunk(b"not a packet at all, honestly").is_none());
+        assert_eq!(decode_chunk(&encode_frame(0, &[])[0]).unwrap().bodies.len(), 0);
+    }
+
+    #[test]
+    fn test_ui_config_round_trip() {
+        let text = "ui_scale=2.5\ntheme=light\nwindow.Inspector=400,20.5,250,300\nwindow.Settings=10,10,320,700\n";
+        let config = UiConfig::parse(text);
+        assert_eq!(config.scale, 2.5);
+        assert!(!config.dark);
+        assert_eq!(config.to_text(), text);
+    }
+
+    #[test]
+    fn test_thread_settings() {
+        assert_eq!(compute_threads(8, 0, false), 8);
+        assert_eq!(compute_threads(8, 0, true), 7);
+        assert_eq!(compute_threads(8, 4, true), 4);
+        // never more than there are cores to spare, never none
+        assert_eq!(compute_threads(8, 16, true), 7);
+        assert_eq!(compute_threads(1, 0, true), 1);
+
+        let config = UiConfig::parse("ui_scale=1\ntheme=dark\ncompute_threads=6\nreserve_core=true\n");
+        assert_eq!(config.compute_threads, 6);
+        assert!(config.reserve_core);
+        assert_eq!(config.to_text(), "ui_scale=1\ntheme=dark\ncompute_threads=6\nreserve_core=true\n");
+    }
+
+    #[test]
+    fn test_ui_config_ignores_junk() {
+        let config = UiConfig::parse("ui_scale=huge\nwindow.Settings=1,2\nnonsense\nui_scale=100\n");
+        // out of range values are clamped, unparsable ones keep the default
+        assert_eq!(config.scale, 4.0);
+        assert!(config.dark);
+        assert_eq!(UiConfig::parse("").to_text(), "ui_scale=1\ntheme=dark\n");
+    }
+
+    #[test]
+    fn test_dynamical_time_inverse_square() {
+        // sqrt(R / (G M / R²)) = sqrt(R³ / (G M))
+        let force = ForceParams {
+            g: 2.0,
+            ..ForceParams::default()
+        };
+        let t = dynamical_time(force, 50.0, 10.0);
+        assert!((t - (1000.0f32 / 100.0).sqrt()).abs() < 1e-5);
+        assert!((recommended_dt(force, 50.0, 10.0) - t / STEPS_PER_DYNAMICAL_TIME).abs() < 1e-9);
+        assert_eq!(dynamical_time(force, 0.0, 10.0), f32::INFINITY);
+    }
+
+    #[test]
+    fn test_orbit_elements_circular() {
+        // v = sqrt(mu / r) gives a circle of radius r
+        let elements = orbit_elements(4.0, Vec2::new(2.0, 0.0), Vec2::new(0.0, 2.0f32.sqrt()));
+        assert!((elements.semi_major_axis - 2.0).abs() < 1e-5);
+        assert!(elements.eccentricity < 1e-5);
+        let period = elements.period.unwrap();
+        assert!((period - std::f32::consts::TAU * (8.0f32 / 4.0).sqrt()).abs() < 1e-4);
+    }
+
+    #[test]
+    fn test_orbit_elements_ellipse_and_escape() {
+        // at periapsis of an a = 1, e = 0.5 orbit: r = a (1 - e), v = sqrt(mu (1 + e) / r)
+        let elements = orbit_elements(1.0, Vec2::new(0.0, -0.5), Vec2::new(3.0f32.sqrt(), 0.0));
+        assert!((elements.semi_major_axis - 1.0).abs() < 1e-4);
+        assert!((elements.eccentricity - 0.5).abs() < 1e-4);
+
+        // faster than escape speed sqrt(2 mu / r)
+        let escaping = orbit_elements(1.0, Vec2::new(1.0, 0.0), Vec2::new(0.0, 1.5));
+        assert!(escaping.period.is_none());
+        assert!(escaping.eccentricity > 1.0);
+    }
+
+    #[test]
+    fn test_hill_radius_earth() {
+        // Earth around the Sun: about 0.01 AU
+        let r = hill_radius(3.0e-6, 1.0, 1.0, 0.0167);
+        assert!((r - 0.00984).abs() < 1e-4, "{}", r);
+        // twice the mass ratio, cube root of two times the radius
+        let ratio = hill_radius(2.0, 1000.0, 50.0, 0.0) / hill_radius(1.0, 1000.0, 50.0, 0.0);
+        assert!((ratio - 2f32.cbrt()).abs() < 1e-5);
+    }
+
+    #[test]
+    fn test_roche_limit_scaling() {
+        // same density as the primary: 2.44 primary radii. Density goes as m / r³, so a primary of
+        // 8 times the mass has twice the radius.
+        let limit = roche_limit(1.0, 1.0, 8.0);
+        assert!((limit - 2.44 * 2.0).abs() < 1e-5);
+    }
+
+    #[test]
+    fn test_find_primary_picks_strongest_heavier_pull() {
+        let others = [
+            (Vec2::new(10.0, 0.0), 50.0),
+            // heavier but much further away
+            (Vec2::new(100.0, 0.0), 1000.0),
+            // close but lighter than the body itself
+            (Vec2::new(1.0, 0.0), 1.0),
+        ];
+        assert_eq!(find_primary(Vec2::ZERO, 5.0, &others), Some(0));
+        assert_eq!(find_primary(Vec2::ZERO, 5000.0, &others), None);
+    }
+
+    #[test]
+    fn test_periapsis_state_gives_requested_orbit() {
+        for (a, e) in [(100.0f32, 0.0f32), (50.0, 0.3), (400.0, 0.9)] {
+            let dir = Vec2::new(1.0, 2.0).normalize();
+            let (pos, vel) = periapsis_state(300.0 * 1005.0, a, e, dir, false);
+            let elements = orbit_elements(300.0 * 1005.0, pos, vel);
+            assert!((elements.semi_major_axis / a - 1.0).abs() < 1e-3, "a {} vs {}", elements.semi_major_axis, a);
+            assert!((elements.eccentricity - e).abs() < 1e-3, "e {} vs {}", elements.eccentricity, e);
+            // periapsis is where we asked, and the orbit goes counterclockwise
+            assert!(pos.normalize().abs_diff_eq(dir, 1e-5));
+            assert!(pos.perp_dot(vel) > 0.0);
+        }
+        let (pos, vel) = periapsis_state(1.0, 1.0, 0.0, Vec2::X, true);
+        assert!(pos.perp_dot(vel) < 0.0);
+    }
+
+    #[test]
+    fn test_factory_systems_are_centered() {
+        for kind in SystemKind::ALL {
+            let factory = SystemFactory {
+                kind,
+                ..SystemFactory::default()
+            };
+            let bodies = build_system(&factory, 300.0);
+            let expected = match kind {
+                SystemKind::Binary => 2,
+                SystemKind::BinaryWithPlanets => 2 + factory.n_planets as usize,
+                SystemKind::Triple => 3,
+                SystemKind::Ring => 1 + factory.ring_particles as usize + factory.n_moons as usize,
+                SystemKind::AsteroidBelt => 2 + factory.belt_particles as usize,
+            };
+            assert_eq!(bodies.len(), expected);
+
+            let total: f32 = bodies.iter().map(|(m, _p, _v)| m).sum();
+            let com: Vec2 = bodies.iter().map(|(m, p, _v)| *m * *p).sum::<Vec2>() / total;
+            let momentum: Vec2 = bodies.iter().map(|(m, _p, v)| *m * *v).sum();
+            assert!(com.length() < 1e-2, "{:?} com {:?}", kind, com);
+            assert!(momentum.length() < 1e-1 * total, "{:?} momentum {:?}", kind, momentum);
+        }
+    }
+
+    #[test]
+    fn test_factory_binary_is_circular() {
+        let factory = SystemFactory {
+            mass_ratio: 0.25,
+            ..SystemFactory::default()
+        };
+        let bodies = build_system(&factory, 300.0);
+        let (m1, p1, v1) = bodies[0];
+        let (m2, p2, v2) = bodies[1];
+        assert!((p1.distance(p2) - factory.separation).abs() < 1e-3);
+        let elements = orbit_elements(300.0 * (m1 + m2), p2 - p1, v2 - v1);
+        assert!(elements.eccentricity < 1e-3);
+
+        // the planets are on circles around the whole binary
+        let factory = SystemFactory {
+            kind: SystemKind::BinaryWithPlanets,
+            planet_mass: 0.001,
+            ..SystemFactory::default()
+        };
+        let bodies = build_system(&factory, 300.0);
+        let binary_mass = bodies[0].0 + bodies[1].0;
+        for (_m, pos, vel) in &bodies[2..] {
+            let elements = orbit_elements(300.0 * binary_mass, *pos, *vel);
+            assert!(elements.eccentricity < 1e-3);
+            assert!(elements.semi_major_axis > 2.9 * factory.separation);
+        }
+    }
+
+    #[test]
+    fn test_decimation_heaviest() {
+        let bodies: Vec<(u32, f32)> = (0..100).map(|i| (i, i as f32)).collect();
+        let visible = pick_visible(&bodies, 0.1, Decimation::Heaviest);
+        assert_eq!(visible.iter().filter(|v| **v).count(), 10);
+        assert!(visible[90..].iter().all(|v| *v));
+        assert!(pick_visible(&bodies, 1.0, Decimation::Heaviest).iter().all(|v| *v));
+    }
+
+    #[test]
+    fn test_decimation_random_is_stable() {
+        let bodies: Vec<(u32, f32)> = (0..20000).map(|i| (i, 1.0)).collect();
+        let visible = pick_visible(&bodies, 0.25, Decimation::Random);
+        let count = visible.iter().filter(|v| **v).count();
+        assert!((count as f32 / 20000.0 - 0.25).abs() < 0.02, "{}", count);
+        // same ids, same answer, also when the list is shuffled around
+        let reversed: Vec<(u32, f32)> = bodies.iter().rev().copied().collect();
+        let mut again = pick_visible(&reversed, 0.25, Decimation::Random);
+        again.reverse();
+        assert_eq!(visible, again);
+    }
+
+    #[test]
+    fn test_tree_node_count() {
+        let mut tree = Quadtree::new(Quad::new(0.0, 0.0, 16.0));
+        let body = Body {
+            mass: 1.0,
+            radius: 1.0,
+            hue: 0.0,
+        };
+        // just the root
+        assert_eq!(tree.node_count(), 1);
+        tree.insert(Entity::from_raw(0), Transform::from_xyz(-4.0, 4.0, 0.0), body);
+        tree.insert(Entity::from_raw(1), Transform::from_xyz(4.0, 4.0, 0.0), body);
+        // different quadrants of the root, no need to split
+        assert_eq!(tree.node_count(), 1);
+        // same quadrant as body 1, that leaf has to become a node
+        tree.insert(Entity::from_raw(2), Transform::from_xyz(6.0, 2.0, 0.0), body);
+        assert!(tree.node_count() >= 2);
+        assert!(tree.memory_bytes() > Quadtree::new(Quad::new(0.0, 0.0, 16.0)).memory_bytes());
+    }
+
+    fn buffers_from_points(points: &[Vec2]) -> BodyBuffers {
+        let mut buffers = BodyBuffers::default();
+        for (i, p) in points.iter().enumerate() {
+            buffers.push(Entity::from_raw(i as u32), p.extend(0.0), Vec3::new(1.0, -2.0, 0.0), 1.0, true);
+            buffers.ax[i] = p.x * 0.01;
+            buffers.ay[i] = p.y * 0.01;
+        }
+        buffers
+    }
+
+    #[test]
+    fn test_buffers_bounds_match_new_containing() {
+        let points = random_points(11, 500);
+        let buffers = buffers_from_points(&points);
+        let from_slices = buffers.bounds().bounds();
+        let from_points = Quad::new_containing(&points).bounds();
+        assert_eq!(from_slices, from_points);
+    }
+
+    #[test]
+    fn test_buffers_bounds_skip_tracers() {
+        let mut buffers = BodyBuffers::default();
+        buffers.push(Entity::from_raw(0), Vec3::new(-1.0, -1.0, 0.0), Vec3::ZERO, 1.0, true);
+        buffers.push(Entity::from_raw(1), Vec3::new(1.0, 1.0, 0.0), Vec3::ZERO, 1.0, true);
+        buffers.push(Entity::from_raw(2), Vec3::new(1000.0, 1000.0, 0.0), Vec3::ZERO, 0.0, false);
+        let (_min, max) = buffers.bounds().bounds();
+        assert!(max.x < 10.0 && max.y < 10.0);
+    }
+
+    #[test]
+    fn test_tracer_counts() {
+        for tracer_ring in [false, true] {
+            for n_tracers in [1, 5, 9, 1001] {
+                let settings = SimulationSettings {
+                    n_tracers,
+                    tracer_ring,
+                    ..SimulationSettings::default()
+                };
+                assert_eq!(tracer_positions(&settings).len(), n_tracers as usize, "ring {}", tracer_ring);
+            }
+        }
+
+        // the ring is evenly spread around the middle of the spawn area
+        let settings = SimulationSettings {
+            n_tracers: 5,
+            tracer_ring: true,
+            spawn_area: -100.0..=100.0,
+            ..SimulationSettings::default()
+        };
+        let ring = tracer_positions(&settings);
+        assert!(ring.iter().all(|p| (p.length() - 50.0).abs() < 1e-3));
+        assert!(ring.windows(2).all(|pair| (pair[0].distance(pair[1]) - ring[0].distance(ring[1])).abs() < 1e-3));
+
+        // a short grid fills rows from the bottom, all inside the spawn area
+        let settings = SimulationSettings {
+            n_tracers: 5,
+            tracer_ring: false,
+            spawn_area: -100.0..=100.0,
+            ..SimulationSettings::default()
+        };
+        let grid = tracer_positions(&settings);
+        assert_eq!(grid[..3], [Vec2::new(-100.0, -100.0), Vec2::new(0.0, -100.0), Vec2::new(100.0, -100.0)]);
+        assert_eq!(grid[3..], [Vec2::new(-100.0, 0.0), Vec2::new(0.0, 0.0)]);
+    }
+
+    #[test]
+    fn test_tracers_feel_but_do_not_pull() {
+        let mut app = physics_app(SimulationSettings::default());
+        let heavy = app.world_mut().spawn((test_body(1000.0), at(Vec2::ZERO), Velocity(Vec3::ZERO))).id();
+        // even with a mass of its own, a tracer stays out of the tree
+        let tracer = app
+            .world_mut()
+            .spawn((test_body(500.0), at(Vec2::new(50.0, 0.0)), Velocity(Vec3::ZERO), Massless))
+            .id();
+        step(&mut app, 3);
+
+        let velocity = |app: &App, entity: Entity| app.world().get::<Velocity>(entity).unwrap().0;
+        assert_eq!(velocity(&app, heavy), Vec3::ZERO);
+        let pulled = velocity(&app, tracer);
+        assert!(pulled.x < 0.0 && pulled.y.abs() < 1e-6, "{}", pulled);
+    }
+
+    #[test]
+    fn test_buffers_step_matches_per_body() {
+        let points = random_points(12, 200);
+        let mut buffers = buffers_from_points(&points);
+        let dt = 0.01;
+        buffers.step(dt);
+        for (i, p) in points.iter().enumerate() {
+            let mut velocity = Vec3::new(1.0, -2.0, 0.0);
+            velocity += Vec3::new(p.x * 0.01, p.y * 0.01, 0.0);
+            let pos = *p + velocity.truncate() * dt;
+            assert_eq!(buffers.vx[i], velocity.x);
+            assert_eq!(buffers.vy[i], velocity.y);
+            assert_eq!(buffers.x[i], pos.x);
+            assert_eq!(buffers.y[i], pos.y);
+        }
+    }
+
+    #[test]
+    fn test_bucketed_leaves_cut_node_count() {
+        let points = random_points(14, 2000);
+        let single = tree_of_points(&points, 1);
+        let bucketed = tree_of_points(&points, 16);
+        assert!(bucketed.node_count() * 4 < single.node_count(), "{} vs {}", bucketed.node_count(), single.node_count());
+    }
+
+    #[test]
+    fn test_bucketed_leaf_is_summed_exactly() {
+        // Nothing overflows the four leaves below the root, so even a huge theta has nothing to approximate
+        let points = random_points(15, 12);
+        let tree = tree_of_points(&points, 16);
+        let force = ForceParams::default();
+        let target = Transform::from_xyz(points[0].x, points[0].y, 0.0);
+        let body = Body {
+            mass: 1.0,
+            radius: 1.0,
+            hue: 0.0,
+        };
+        let breakdown = tree.get_force_breakdown(Entity::from_raw(0), target, body, force, 1.0, 100.0);
+        assert_eq!(breakdown.direct, 11);
+        assert_eq!(breakdown.approximated, 0);
+
+        let expected: Vec3 = points[1..]
+            .iter()
+            .map(|p| force.accel(1.0, target.translation, p.extend(0.0)))
+            .sum();
+        assert!((breakdown.total - expected).length() < expected.length() * 1e-5);
+    }
+
+    #[test]
+    fn test_bucketed_queries_find_every_body() {
+        let points = random_points(16, 1000);
+        let tree = tree_of_points(&points, 16);
+        let mut all: Vec<u32> = tree
+            .query_rect(Rect::new(-400.0, -400.0, 400.0, 400.0))
+            .map(|(e, _t, _b)| e.index())
+            .collect();
+        all.sort();
+        assert_eq!(all, (0..1000).collect::<Vec<u32>>());
+
+        for q in random_points(17, 20) {
+            let found: Vec<u32> = tree.knn(q, 5).iter().map(|(e, _p)| e.index()).collect();
+            assert_eq!(found, brute_force_knn(&points, q, 5));
+        }
+    }
+
+    #[test]
+    fn test_whole_substeps_every_frame() {
+        let mut clock = SimulationClock::default();
+        for _ in 0..5 {
+            assert_eq!(clock.take_substeps(4.0), 4);
+        }
+    }
+
+    #[test]
+    fn test_fractional_substeps_add_up() {
+        let mut clock = SimulationClock::default();
+        let steps: Vec<u32> = (0..8).map(|_| clock.take_substeps(0.25)).collect();
+        assert_eq!(steps, vec![0, 0, 0, 1, 0, 0, 0, 1]);
+        let total: u32 = (0..10).map(|_| clock.take_substeps(1.5)).sum();
+        assert_eq!(total, 15);
+    }
+
+    #[test]
+    fn test_simulated_time_per_second() {
+        let settings = SimulationSettings {
+            delta_t: 0.001,
+            substeps: 4.0,
+            ..SimulationSettings::default()
+        };
+        // 60 frames a second, 4 steps of 0.001 each
+        assert!((settings.simulated_per_second(1.0 / 60.0) - 0.24).abs() < 1e-5);
+        assert_eq!(settings.simulated_per_second(0.0), 0.0);
+    }
+
+    #[test]
+    fn test_pacing_keeps_stable_dt_while_substeps_fit() {
+        assert_eq!(pacing(1.0, 0.1, 0.001), (0.1, 0.001));
+        assert_eq!(pacing(2.0, 10.0, 0.001), (20.0, 0.001));
+    }
+
+    #[test]
+    fn test_pacing_stretches_dt_past_max_substeps() {
+        let (substeps, dt) = pacing(1.0, 100.0, 0.001);
+        assert_eq!(substeps, MAX_SUBSTEPS);
+        assert!(dt > 0.001);
+        // the speed asked for is still what comes out
+        assert!((substeps * dt - 100.0 * 0.001).abs() < 1e-6);
+    }
+
+    #[test]
+    fn test_suggested_dt_shrinks_with_acceleration() {
+        let slow = suggested_dt(5.0, 100.0).unwrap();
+        let fast = suggested_dt(5.0, 10000.0).unwrap();
+        assert!((slow - SUGGESTED_DT_FRACTION * (0.05f32).sqrt()).abs() < 1e-7);
+        // a hundred times the acceleration, a tenth of the dt
+        assert!((slow / fast - 10.0).abs() < 1e-4);
+        assert_eq!(suggested_dt(5.0, 0.0), None);
+    }
+
+    #[test]
+    fn test_suggested_dt_ignores_non_finite_accelerations() {
+        let mut suggestion = SuggestedDt::default();
+        suggestion.record(3.0);
+        suggestion.record(f32::NAN);
+        suggestion.record(f32::INFINITY);
+        suggestion.record(1.0);
+        assert_eq!(suggestion.max_accel, 3.0);
+    }
+
+    #[test]
+    fn test_falloff_ends_at_radius() {
+        for falloff in Falloff::ALL {
+            assert_eq!(falloff.factor(0.0), 1.0);
+            assert_eq!(falloff.factor(1.5), 0.0);
+        }
+        assert_eq!(Falloff::Linear.factor(0.5), 0.5);
+        assert_eq!(Falloff::Quadratic.factor(0.5), 0.25);
+        assert_eq!(Falloff::Constant.factor(0.99), 1.0);
+    }
+
+    #[test]
+    fn test_explosion_pushes_outward() {
+        let explosion = Explosion {
+            strength: 100.0,
+            radius: 10.0,
+            falloff: Falloff::Linear,
+        };
+        let center = Vec2::new(5.0, 5.0);
+        let kick = explosion.kick(center, Vec2::new(10.0, 5.0), 2.0);
+        // halfway out, half the strength, spread over a mass of 2
+        assert!((kick - Vec2::new(25.0, 0.0)).length() < 1e-5);
+        // twice the mass, half the kick
+        let heavy = explosion.kick(center, Vec2::new(10.0, 5.0), 4.0);
+        assert!((heavy.length() * 2.0 - kick.length()).abs() < 1e-5);
+        // a body lighter than 1 still gets its own share, a massless tracer the share of mass 1
+        let light = explosion.kick(center, Vec2::new(10.0, 5.0), 0.5);
+        assert!((light.length() - 4.0 * kick.length()).abs() < 1e-4);
+        assert_eq!(explosion.kick(center, Vec2::new(10.0, 5.0), 0.0), kick * 2.0);
+        assert_eq!(explosion.kick(center, center, 1.0), Vec2::ZERO);
+        assert_eq!(explosion.kick(center, Vec2::new(30.0, 5.0), 1.0), Vec2::ZERO);
+    }
+
+    #[test]
+    fn test_orbital_frequency_of_circular_pair() {
+        let (g, m1, m2, r) = (300.0f32, 600.0, 400.0, 50.0);
+        let mu = g * (m1 + m2);
+        let speed = (mu / r).sqrt();
+        let f = orbital_frequency(g, m1, m2, Vec2::new(r, 0.0), Vec2::new(0.0, speed)).unwrap();
+        let expected = 1.0 / (std::f32::consts::TAU * (r * r * r / mu).sqrt());
+        assert!((f - expected).abs() < expected * 1e-4);
+        // too fast to stay together
+        assert_eq!(orbital_frequency(g, m1, m2, Vec2::new(r, 0.0), Vec2::new(0.0, 2.0 * speed)), None);
+    }
+
+    #[test]
+    fn test_chirp_pitch_rises_with_frequency() {
+        let start = chirp_pitch(2.0, 2.0);
+        let doubled = chirp_pitch(4.0, 2.0);
+        assert!((doubled / start - 2.0).abs() < 1e-5);
+        // never below where it started, and capped at the top
+        assert_eq!(chirp_pitch(1.0, 2.0), start);
+        assert!(chirp_pitch(1e9, 2.0) < 10000.0);
+    }
+
+    #[test]
+    fn test_kinetic_pitch_octave_per_doubling() {
+        let base = kinetic_pitch(100.0, 100.0);
+        assert!((kinetic_pitch(200.0, 100.0) / base - 2.0).abs() < 1e-5);
+        assert!((kinetic_pitch(50.0, 100.0) / base - 0.5).abs() < 1e-5);
+        // clamped a few octaves out, and no NaN from an empty system
+        assert_eq!(kinetic_pitch(1e12, 100.0), kinetic_pitch(1e15, 100.0));
+        assert_eq!(kinetic_pitch(0.0, 100.0), base);
+    }
+
+    #[test]
+    fn test_collision_voice_silent_without_collisions() {
+        let (_pitch, gain) = collision_voice(0.0);
+        assert_eq!(gain, 0.0);
+        let (low, _) = collision_voice(10.0);
+        let (high, loud) = collision_voice(1000.0);
+        assert!(high > low && loud > 0.0);
+        assert_eq!(collision_voice(1000.0), collision_voice(5000.0));
+    }
+
+    #[test]
+    fn test_kiosk_waits_for_idle_then_cycles() {
+        let mut kiosk = Kiosk::default();
+        kiosk.idle_secs = 10.0;
+        kiosk.minutes_per_scenario = 1.0;
+        assert!(!kiosk.tick(9.0));
+        assert!(kiosk.tick(1.0));
+        kiosk.active = true;
+        let first = kiosk.advance();
+        assert!(!kiosk.tick(59.0));
+        assert!(kiosk.tick(1.0));
+        let second = kiosk.advance();
+        assert_ne!(first, second);
+        // and back round to the start
+        for _ in 2..Scenario::ALL.len() {
+            kiosk.advance();
+        }
+        assert_eq!(kiosk.advance(), first);
+    }
+
+    #[test]
+    fn test_friends_of_friends_links_chains() {
+        // a chain with spacing 1 is one group even though its ends are far apart, the far body is alone
+        let positions = vec![
+            Vec2::new(0.0, 0.0),
+            Vec2::new(1.0, 0.0),
+            Vec2::new(2.0, 0.0),
+            Vec2::new(3.0, 0.0),
+            Vec2::new(50.0, 50.0),
+        ];
+        let groups = friends_of_friends(&positions, 1.5);
+        assert_eq!(groups, vec![vec![0, 1, 2, 3], vec![4]]);
+        assert_eq!(friends_of_friends(&positions, 0.5).len(), 5);
+    }
+
+    #[test]
+    fn test_find_groups_drops_unbound_members() {
+        // a heavy clump at rest with one light body flying through it
+        let mut masses = vec![100.0; 5];
+        let mut positions = vec![
+            Vec2::new(0.0, 0.0),
+            Vec2::new(1.0, 0.0),
+            Vec2::new(0.0, 1.0),
+            Vec2::new(-1.0, 0.0),
+            Vec2::new(0.0, -1.0),
+        ];
+        let mut velocities = vec![Vec2::ZERO; 5];
+        masses.push(1.0);
+        positions.push(Vec2::new(0.5, 0.5));
+        velocities.push(Vec2::new(1000.0, 0.0));
+
+        let groups = find_groups(&masses, &positions, &velocities, 1.0, 0.1, 1.5, 3);
+        assert_eq!(groups.len(), 1);
+        assert_eq!(groups[0].members, vec![0, 1, 2, 3, 4]);
+        assert!((groups[0].mass - 500.0).abs() < 1e-3);
+        assert!(groups[0].com.length() < 1e-5);
+
+        // too small to count
+        assert!(find_groups(&masses, &positions, &velocities, 1.0, 0.1, 1.5, 6).is_empty());
+    }
+
+    #[test]
+    fn test_halo_catalog_has_dispersion() {
+        // two equal bodies moving apart at 2 each way have dispersion 2
+        let masses = vec![1.0, 1.0, 1.0];
+        let positions = vec![Vec2::new(0.0, 0.0), Vec2::new(1.0, 0.0), Vec2::new(100.0, 0.0)];
+        let velocities = vec![Vec2::new(-2.0, 0.0), Vec2::new(2.0, 0.0), Vec2::ZERO];
+        let halos = find_halos(&masses, &positions, &velocities, 1.5, 2);
+        assert_eq!(halos.len(), 1);
+        assert!((halos[0].velocity_dispersion - 2.0).abs() < 1e-5);
+        assert!((halos[0].com - Vec2::new(0.5, 0.0)).length() < 1e-5);
+
+        let lines = catalog_lines(1.5, &halos, &WorldOrigin::default());
+        assert_eq!(lines, vec!["1.5,0,0.5,0,2,2,2".to_string()]);
+        // after a rebase the centers are still where they were in the starting coordinates
+        let rebased = WorldOrigin {
+            offset: DVec2::new(1000.0, -20.0),
+            ..WorldOrigin::default()
+        };
+        assert_eq!(catalog_lines(1.5, &halos, &rebased), vec!["1.5,0,1000.5,-20,2,2,2".to_string()]);
+        assert_eq!(CATALOG_HEADER.split(',').count(), lines[0].split(',').count());
+    }
+
+    #[test]
+    fn test_lagrangian_radii_enclose_mass_fractions() {
+        // ten equal bodies at radius 1..=10
+        let masses = vec![1.0; 10];
+        let positions: Vec<Vec2> = (1..=10).map(|r| Vec2::new(r as f32, 0.0)).collect();
+        let radii = lagrangian_radii(&masses, &positions, Vec2::ZERO, &[0.1, 0.5, 0.9, 1.0]);
+        assert_eq!(radii, vec![1.0, 5.0, 9.0, 10.0]);
+    }
+
+    #[test]
+    fn test_density_center_ignores_escapers() {
+        // a tight clump at (10, 10) and a few bodies far off to one side, which drag the center of mass
+        let mut rng = StdRng::seed_from_u64(3);
+        let mut positions: Vec<Vec2> = (0..200)
+            .map(|_| Vec2::new(10.0 + rng.random_range(-1.0..1.0), 10.0 + rng.random_range(-1.0..1.0)))
+            .collect();
+        positions.extend((0..20).map(|i| Vec2::new(500.0 + 30.0 * i as f32, 0.0)));
+        let masses = vec![1.0; positions.len()];
+
+        let center = density_center(&masses, &positions).unwrap();
+        assert!(center.distance(Vec2::new(10.0, 10.0)) < 1.0, "center {:?}", center);
+        let com = positions.iter().sum::<Vec2>() / positions.len() as f32;
+        assert!(com.distance(Vec2::new(10.0, 10.0)) > 50.0);
+    }
+
+    #[test]
+    fn test_virial_scale_brings_ratio_to_one() {
+        let mut energy = Energy::default();
+        energy.record(50.0, 40.0, -20.0);
+        let ratio = energy.virial_ratio().unwrap();
+        assert!((ratio - 4.0).abs() < 1e-9);
+        // twice too hot in velocity, half the speed fixes it
+        let scale = virial_scale(ratio).unwrap();
+        assert!((scale - 0.5).abs() < 1e-6);
+        assert!(virial_scale(0.0).is_none());
+
+        // the energy tracking starting point isn't set by the virial monitor alone
+        assert!(energy.initial.is_none());
+
+        let monitor = VirialMonitor::default();
+        assert!(monitor.far_from_equilibrium(ratio));
+        assert!(!monitor.far_from_equilibrium(1.1));
+    }
+
+    #[test]
+    fn test_virial_velocities_from_rest() {
+        // T around the center of mass and the total momentum
+        let sums = |masses: &[f32], velocities: &[Vec2]| {
+            let mass: f32 = masses.iter().sum();
+            let momentum: Vec2 = masses.iter().zip(velocities).map(|(m, v)| *m * *v).sum();
+            let kinetic: f32 =
+                masses.iter().zip(velocities).map(|(m, v)| 0.5 * m * (*v - momentum / mass).length_squared()).sum();
+            (kinetic, momentum)
+        };
+        let masses = [1.0, 2.0, 3.0, 4.0, 5.0];
+
+        // everything moving together has a ratio of 0, there is nothing to scale
+        let drifting: Vec<(f32, Vec2)> = masses.iter().map(|m| (*m, Vec2::new(3.0, -1.0))).collect();
+        let velocities = virial_velocities(&drifting, -200.0, &mut StdRng::seed_from_u64(1)).unwrap();
+        let (kinetic, momentum) = sums(&masses, &velocities);
+        assert!((2.0 * kinetic - 200.0).abs() < 1e-3, "{}", kinetic);
+        assert!(momentum.distance(Vec2::new(45.0, -15.0)) < 1e-3);
+        assert!(velocities.windows(2).any(|pair| pair[0] != pair[1]));
+
+        // a moving start is only scaled around the center of mass velocity, which is 2/3 here
+        let moving: Vec<(f32, Vec2)> = masses.iter().map(|m| (*m, Vec2::new(*m - 3.0, 0.0))).collect();
+        let velocities = virial_velocities(&moving, -10.0, &mut StdRng::seed_from_u64(1)).unwrap();
+        let (kinetic, _momentum) = sums(&masses, &velocities);
+        assert!((2.0 * kinetic - 10.0).abs() < 1e-4);
+        let bulk = Vec2::new(2.0 / 3.0, 0.0);
+        let scale = (velocities[0] - bulk).x / (moving[0].1 - bulk).x;
+        for ((_m, before), after) in moving.iter().zip(&velocities) {
+            assert!((*after - bulk).distance((*before - bulk) * scale) < 1e-5);
+        }
+
+        // nothing to balance, or no one to balance it with
+        assert!(virial_velocities(&drifting, 0.0, &mut StdRng::seed_from_u64(1)).is_none());
+        assert!(virial_velocities(&drifting[..1], -200.0, &mut StdRng::seed_from_u64(1)).is_none());
+    }
+
+    #[test]
+    fn test_marching_squares_traces_circle() {
+        // distance from the middle of a 21x21 grid, the contour at 5 is a circle of radius 5
+        let n = 21;
+        let origin = Vec2::new(-10.0, -10.0);
+        let values: Vec<f32> = (0..n * n)
+            .map(|k| (origin + Vec2::new((k % n) as f32, (k / n) as f32)).length())
+            .collect();
+        let segments = marching_squares(&values, n, n, origin, Vec2::ONE, 5.0);
+        assert!(segments.len() > 20);
+        for (a, b) in &segments {
+            assert!((a.length() - 5.0).abs() < 0.2 && (b.length() - 5.0).abs() < 0.2);
+        }
+        assert!(marching_squares(&values, n, n, origin, Vec2::ONE, 100.0).is_empty());
+    }
+
+    #[test]
+    fn test_contour_levels_deepest_first() {
+        let values = [-100.0, -10.0, -1.0, 0.0];
+        let levels = contour_levels(&values, 4);
+        assert_eq!(levels.len(), 4);
+        assert!(levels.windows(2).all(|w| w[0] < w[1]));
+        assert!(levels[0] > -100.0 && levels[3] < -1.0);
+        // nothing to draw in an empty potential
+        assert!(contour_levels(&[0.0; 4], 4).is_empty());
+    }
+
+    #[test]
+    fn test_l1_point() {
+        // equal masses balance at the center of mass
+        let l1 = l1_point(1.0, Vec2::new(-1.0, 0.0), 1.0, Vec2::new(1.0, 0.0)).unwrap();
+        assert!(l1.length() < 1e-4);
+        // a light secondary has L1 close to it, about the Hill radius d (q/3)^(1/3) away
+        let l1 = l1_point(1.0, Vec2::ZERO, 0.001, Vec2::new(100.0, 0.0)).unwrap();
+        let hill = 100.0 * (0.001f32 / 3.0).cbrt();
+        assert!((100.0 - l1.x - hill).abs() < 0.05 * hill, "l1 {:?}, hill {}", l1, hill);
+        assert!(l1_point(1.0, Vec2::ZERO, 1.0, Vec2::ZERO).is_none());
+    }
+
+    #[test]
+    fn test_overflow_state_corotates() {
+        let factory = SystemFactory {
+            kind: SystemKind::Binary,
+            mass_ratio: 0.3,
+            ..SystemFactory::default()
+        };
+        let g = 1.0;
+        let bodies = build_system(&factory, g);
+        let (heavy, light) = (bodies[0], bodies[1]);
+        let (l1, velocity) = overflow_state(light, heavy, g, 0.0).unwrap();
+        // on the line between them, and moving with the rigidly rotating frame like the bodies do
+        assert!(l1.y.abs() < 1e-3 && l1.x > heavy.1.x && l1.x < light.1.x);
+        let omega = heavy.2.y / heavy.1.x;
+        assert!((velocity.y - omega * l1.x).abs() < 1e-3);
+        // flying apart, no Roche lobes
+        let escaping = (light.0, light.1, light.2 * 10.0);
+        assert!(overflow_state(escaping, heavy, g, 0.0).is_none());
+    }
+
+    #[test]
+    fn test_ring_is_keplerian() {
+        let factory = SystemFactory {
+            kind: SystemKind::Ring,
+            ring_particles: 200,
+            ..SystemFactory::default()
+        };
+        let bodies = build_system(&factory, 300.0);
+        let planet = bodies[0];
+        let ring = &bodies[1..201];
+        for (_m, pos, vel) in ring {
+            let r = (*pos - planet.1).length();
+            assert!(r >= factory.separation * 0.99 && r <= factory.separation * factory.ring_width * 1.01);
+            let elements = orbit_elements(300.0 * planet.0, *pos - planet.1, *vel - planet.2);
+            assert!(elements.eccentricity < 1e-2);
+        }
+        // the outer shepherd is just outside the ring, the other moon inside it
+        let outer_moon = (bodies[201].1 - planet.1).length();
+        let gap_moon = (bodies[202].1 - planet.1).length();
+        assert!(outer_moon > factory.separation * factory.ring_width);
+        assert!(gap_moon > factory.separation && gap_moon < factory.separation * factory.ring_width);
+    }
+
+    #[test]
+    fn test_belt_histogram_starts_flat() {
+        let factory = SystemFactory {
+            kind: SystemKind::AsteroidBelt,
+            belt_particles: 800,
+            jupiter_mass_ratio: 0.0,
+            ..SystemFactory::default()
+        };
+        let g = 300.0;
+        let bodies = build_system(&factory, g);
+        let star = (bodies[0].1, bodies[0].2);
+        let tracers: Vec<(Vec2, Vec2)> = bodies[2..].iter().map(|(_m, p, v)| (*p, *v)).collect();
+        let counts = axis_histogram(g * bodies[0].0, star, &tracers, factory.separation, 8);
+        assert_eq!(counts.iter().sum::<u32>(), 800);
+        assert!(counts.iter().all(|c| *c == 100), "{:?}", counts);
+    }
+
+    #[test]
+    fn test_kirkwood_resonances() {
+        // the 3:1 gap sits at 2.5 AU with Jupiter at 5.2
+        assert!((resonance_axis(3, 1) * 5.2 - 2.5).abs() < 0.02);
+        assert!((resonance_axis(2, 1) - 0.63).abs() < 0.01);
+        // and every one of them falls inside the belt
+        for (p, q) in RESONANCES {
+            let a = resonance_axis(p, q);
+            assert!(a > crate::factory::BELT_INNER && a < crate::factory::BELT_OUTER);
+        }
+    }
+
+    #[test]
+    fn test_rotating_frame_holds_primaries_still() {
+        let factory = SystemFactory {
+            mass_ratio: 0.25,
+            ..SystemFactory::default()
+        };
+        let bodies = build_system(&factory, 300.0);
+        let frame = RotatingFrame::new([bodies[0], bodies[1]]).unwrap();
+        assert!((frame.mu - 0.2).abs() < 1e-6);
+        let (pos, vel) = frame.to_rotating(bodies[1].1, bodies[1].2);
+        assert!(pos.abs_diff_eq(Vec2::new(0.8, 0.0), 1e-4), "{:?}", pos);
+        assert!(vel.length() < 1e-3, "{:?}", vel);
+
+        // and back again
+        let (p, v) = (Vec2::new(0.3, -0.7), Vec2::new(0.1, 0.4));
+        let (p2, v2) = frame.to_rotating(frame.to_inertial(p, v).0, frame.to_inertial(p, v).1);
+        assert!(p2.abs_diff_eq(p, 1e-4) && v2.abs_diff_eq(v, 1e-4));
+    }
+
+    #[test]
+    fn test_section_starts_share_jacobi_constant() {
+        let starts = section_starts(0.2, 3.5, -1.0, -0.6, 10);
+        assert!(!starts.is_empty());
+        for (pos, vel) in &starts {
+            assert!((jacobi_constant(0.2, *pos, *vel) - 3.5).abs() < 1e-3);
+        }
+        // far inside the forbidden region nothing can start
+        assert!(section_starts(0.2, 100.0, 2.0, 3.0, 10).is_empty());
+    }
+
+    #[test]
+    fn test_section_crossing_interpolates() {
+        let prev = (Vec2::new(1.0, -1.0), Vec2::new(0.0, 2.0));
+        let cur = (Vec2::new(2.0, 1.0), Vec2::new(1.0, 2.0));
+        let point = section_crossing(Section::Y, prev, cur).unwrap();
+        assert!(point.abs_diff_eq(Vec2::new(1.5, 0.5), 1e-6));
+        // going down doesn't count
+        assert!(section_crossing(Section::Y, cur, prev).is_none());
+        assert!(section_crossing(Section::X, prev, cur).is_none());
+    }
+
+    #[test]
+    fn test_divergence_renormalization_keeps_log_separation() {
+        let settings = SimulationSettings {
+            n_bodies: 50,
+            ..SimulationSettings::default()
+        };
+        let reference = HeadlessSim::new(&settings, 7);
+        let mut divergence = Divergence::new(reference, 3, 0.01);
+        assert!((divergence.separation() - 0.01).abs() < 1e-4);
+        assert!(divergence.log_separation().abs() < 1e-2);
+
+        for _ in 0..200 {
+            divergence.step(&settings, settings.delta_t);
+            let before = divergence.log_separation();
+            divergence.renormalize(10.0);
+            assert!((divergence.log_separation() - before).abs() < 5e-2, "{} renormalizations", divergence.renormalizations);
+            assert!(divergence.separation() < 10.0 * 0.01 * 1.0001);
+        }
+        assert!(divergence.exponent().is_some());
+    }
+
+    #[test]
+    fn test_unperturbed_shadow_stays_on_top() {
+        let settings = SimulationSettings {
+            n_bodies: 50,
+            deterministic: true,
+            ..SimulationSettings::default()
+        };
+        let mut divergence = Divergence::new(HeadlessSim::new(&settings, 7), 0, 0.0);
+        for _ in 0..20 {
+            divergence.step(&settings, settings.delta_t);
+        }
+        assert_eq!(divergence.separation(), 0.0);
+    }
+
+    #[test]
+    fn test_unit_systems_give_known_circular_speeds() {
+        let circular = |units: UnitSystem, mass: f64, r: f64| (units.g().unwrap() * mass / r).sqrt() * units.km_per_s().unwrap();
+        // the Earth around the Sun
+        assert!((circular(UnitSystem::Astronomical, 1.0, 1.0) - 29.78).abs() < 0.05);
+        // the Sun around a 1e11 solar mass galaxy
+        assert!((circular(UnitSystem::Galactic, 1e11, 8.0) - 231.9).abs() < 0.5);
+        // the Moon around the Earth
+        assert!((circular(UnitSystem::Si, 5.972e24, 3.844e8) - 1.018).abs() < 0.01);
+        assert_eq!(UnitSystem::Dimensionless.g(), Some(1.0));
+        assert_eq!(UnitSystem::Simulation.g(), None);
+    }
+
+    #[test]
+    fn test_unit_formatting() {
+        assert_eq!(UnitSystem::Astronomical.length(5.2), "5.20 AU");
+        assert_eq!(UnitSystem::Simulation.mass(12.345), "12.35");
+        assert_eq!(UnitSystem::Si.mass(5.972e24), "5.972e24 kg");
+        assert_eq!(UnitSystem::Astronomical.speed(1.0), "4.74 km/s");
+    }
+
+    #[test]
+    fn test_find_clumps() {
+        let mut positions: Vec<Vec2> = (0..10).map(|i| Vec2::new(i as f32 * 0.1, 0.0)).collect();
+        positions.extend((0..3).map(|i| Vec2::new(100.0 + i as f32 * 0.1, 0.0)));
+        positions.push(Vec2::new(-500.0, 0.0));
+
+        let clumps = find_clumps(&positions, 2.0, 5);
+        assert_eq!(clumps.len(), 1);
+        let mut members = clumps[0].clone();
+        members.sort();
+        assert_eq!(members, (0..10).collect::<Vec<_>>());
+        // the small group is enough once fewer members are asked for, and the loner never is
+        assert_eq!(find_clumps(&positions, 2.0, 3).len(), 2);
+    }
+
+    #[test]
+    fn test_merge_clump_conserves_mass_and_momentum() {
+        let members = [
+            (1.0, Vec2::new(0.0, 0.0), Vec2::new(2.0, 0.0)),
+            (3.0, Vec2::new(4.0, 0.0), Vec2::new(0.0, 4.0)),
+        ];
+        let (mass, pos, vel) = merge_clump(&members).unwrap();
+        assert_eq!(mass, 4.0);
+        assert!(pos.abs_diff_eq(Vec2::new(3.0, 0.0), 1e-6));
+        assert!((mass * vel).abs_diff_eq(Vec2::new(2.0, 12.0), 1e-5));
+        assert!(merge_clump(&[]).is_none());
+    }
+
+    #[test]
+    fn test_fragments_conserve_mass_and_momentum() {
+        let (pos, vel) = (Vec2::new(10.0, -5.0), Vec2::new(3.0, 4.0));
+        let pieces = fragment_states(12.0, pos, vel, 2.0, 50.0, 5);
+        assert_eq!(pieces.len(), 5);
+        let mass: f32 = pieces.iter().map(|(m, _p, _v)| m).sum();
+        let momentum: Vec2 = pieces.iter().map(|(m, _p, v)| *m * *v).sum();
+        let center: Vec2 = pieces.iter().map(|(m, p, _v)| *m * *p).sum::<Vec2>() / mass;
+        assert!((mass - 12.0).abs() < 1e-5);
+        assert!(momentum.abs_diff_eq(12.0 * vel, 1e-3));
+        assert!(center.abs_diff_eq(pos, 1e-4));
+        // a single piece is no decay at all
+        assert!(fragment_states(12.0, pos, vel, 2.0, 50.0, 1).is_empty());
+    }
+
+    #[test]
+    fn test_lifetime_presets() {
+        let mut lifetimes = Lifetimes::default();
+        assert!(lifetimes.component(SpawnSource::Slingshot).is_none());
+        lifetimes.presets[SpawnSource::RocheStream as usize].enabled = true;
+        lifetimes.presets[SpawnSource::RocheStream as usize].lifetime = 2.5;
+        let lifetime = lifetimes.component(SpawnSource::RocheStream).unwrap();
+        assert_eq!((lifetime.remaining, lifetime.span), (2.5, 2.5));
+        assert!(lifetimes.component(SpawnSource::Tracers).is_none());
+    }
+
+    #[test]
+    fn test_background_flow_fields() {
+        let mut flow = BackgroundFlow {
+            kind: FlowKind::Vortex,
+            strength: 10.0,
+            core_radius: 5.0,
+            ..BackgroundFlow::default()
+        };
+        // solid body inside the core, 1/r outside, always turning counter-clockwise
+        assert!(flow.velocity_at(Vec2::new(2.5, 0.0)).abs_diff_eq(Vec2::new(0.0, 5.0), 1e-5));
+        assert!(flow.velocity_at(Vec2::new(0.0, 10.0)).abs_diff_eq(Vec2::new(-5.0, 0.0), 1e-5));
+        assert_eq!(flow.velocity_at(Vec2::ZERO), Vec2::ZERO);
+
+        flow.kind = FlowKind::Shear;
+        flow.strength = 2.0;
+        assert!(flow.velocity_at(Vec2::new(7.0, -3.0)).abs_diff_eq(Vec2::new(-6.0, 0.0), 1e-5));
+
+        flow.kind = FlowKind::Uniform;
+        flow.angle = 90.0;
+        assert!(flow.velocity_at(Vec2::new(1.0, 1.0)).abs_diff_eq(Vec2::new(0.0, 2.0), 1e-5));
+    }
+
+    #[test]
+    fn test_drag_relaxes_towards_gas() {
+        let flow = BackgroundFlow {
+            strength: 10.0,
+            stopping_time: 0.1,
+            ..BackgroundFlow::default()
+        };
+        let gas = Vec2::new(10.0, 0.0);
+        // after one stopping time 1/e of the difference is left, whatever the step
+        let one_step = flow.drag(1.0, Vec2::ZERO, Vec2::ZERO, 0.1, false);
+        let mut many_steps = Vec2::ZERO;
+        for _ in 0..100 {
+            many_steps = flow.drag(1.0, Vec2::ZERO, many_steps, 0.001, false);
+        }
+        assert!(((gas - one_step).length() - 10.0 / std::f32::consts::E).abs() < 1e-4);
+        assert!(one_step.abs_diff_eq(many_steps, 1e-3));
+        // huge steps just settle on the gas
+        assert!(flow.drag(1.0, Vec2::ZERO, Vec2::new(0.0, 500.0), 1000.0, false).abs_diff_eq(gas, 1e-4));
+        // a heavy body is slowed less
+        let heavy = flow.drag(1000.0, Vec2::ZERO, Vec2::ZERO, 0.1, false);
+        assert!(heavy.length() < one_step.length());
+        // libm's functions in deterministic runs only differ in the last bits
+        assert!(flow.drag(1000.0, Vec2::ZERO, Vec2::ZERO, 0.1, true).abs_diff_eq(heavy, 1e-4));
+    }
+
+    #[test]
+    fn test_densest_point_finds_the_tight_clump() {
+        let mut rng = StdRng::seed_from_u64(3);
+        let mut positions: Vec<Vec2> = (0..200)
+            .map(|_| Vec2::new(rng.random_range(-500.0..500.0), rng.random_range(-500.0..500.0)))
+            .collect();
+        positions.extend((0..20).map(|i| Vec2::new(300.0, -200.0) + Vec2::from_angle(i as f32) * (i as f32 * 0.1)));
+        let masses = vec![1.0; positions.len()];
+        let densest = densest_point(&masses, &positions).unwrap();
+        assert!(densest.distance(Vec2::new(300.0, -200.0)) < 3.0, "{:?}", densest);
+    }
+
+    #[test]
+    fn test_inset_viewport() {
+        let (position, size) = inset_viewport(UVec2::new(1000, 800), 0.3).unwrap();
+        assert_eq!(size, UVec2::splat(300));
+        // bottom right, inside the window
+        assert_eq!(position + size, UVec2::new(990, 790));
+        // never taller than the window
+        let (_position, size) = inset_viewport(UVec2::new(2000, 300), 0.5).unwrap();
+        assert_eq!(size.y, 280);
+        assert!(inset_viewport(UVec2::new(0, 0), 0.3).is_none());
+    }
+
+    #[test]
+    fn test_minimap_mapping() {
+        let (min, size) = (Vec2::new(-100.0, -50.0), 200.0);
+        // top left of the minimap is the min x, max y corner
+        assert_eq!(world_to_minimap(Vec2::new(-100.0, 150.0), min, size), Vec2::new(0.0, 0.0));
+        let p = Vec2::new(30.0, -20.0);
+        assert!(minimap_to_world(world_to_minimap(p, min, size), min, size).distance(p) < 1e-4);
+
+        let positions = [
+            Vec2::new(-99.0, 149.0),
+            Vec2::new(-98.0, 148.0),
+            Vec2::new(99.0, -49.0),
+            Vec2::new(500.0, 0.0),
+        ];
+        let counts = bin_bodies(&positions, min, size, 10);
+        assert_eq!(counts[0], 2);
+        assert_eq!(counts[99], 1);
+        // outside the square is left out
+        assert_eq!(counts.iter().sum::<u32>(), 3);
+    }
+
+    #[test]
+    fn test_heaviest() {
+        let masses = [3.0, 10.0, 1.0, 7.0, 10.0, 2.0];
+        // heaviest first, equal masses in the order they came
+        assert_eq!(heaviest(&masses, 3), vec![1, 4, 3]);
+        assert_eq!(heaviest(&masses, 100).len(), masses.len());
+        assert!(heaviest(&masses, 0).is_empty());
+        assert!(heaviest(&[], 5).is_empty());
+    }
+
+    #[test]
+    fn test_collision_log_rate() {
+        let event = CollisionEvent {
+            a: Entity::from_raw(3),
+            b: Entity::from_raw(7),
+            mass_a: 2.0,
+            mass_b: 0.5,
+            impact_speed: 12.5,
+            position: Vec2::new(1.0, -4.0),
+        };
+        assert_eq!(csv_line(0.25, &event, DVec2::new(1.0, -4.0)), "0.25,3,7,2.0,0.5,12.5,1.0,-4.0");
+
+        let mut log = CollisionLog::default();
+        log.bin_width = 0.5;
+        for _ in 0..4 {
+            log.record(0.1, event);
+        }
+        log.close_bins(0.2);
+        assert!(log.rate_history.is_empty());
+        // one full bin of 4 collisions, then an empty one
+        log.close_bins(1.1);
+        assert_eq!(log.rate_history, vec![8.0, 0.0]);
+        assert_eq!(log.total, 4);
+        // a reset sends the clock back and starts the plot over
+        log.close_bins(0.0);
+        assert!(log.rate_history.is_empty());
+    }
+
+    #[test]
+    fn test_impact_flash_size() {
+        let event = CollisionEvent {
+            a: Entity::from_raw(0),
+            b: Entity::from_raw(1),
+            mass_a: 2.0,
+            mass_b: 2.0,
+            impact_speed: 4.0,
+            position: Vec2::ZERO,
+        };
+        // reduced mass 1
+        assert!((impact_energy(&event) - 8.0).abs() < 1e-5);
+        assert!((flash_radius(8.0, 3.0) - 6.0).abs() < 1e-5);
+        // eight times the energy doubles the ring
+        assert!((flash_radius(64.0, 1.0) / flash_radius(8.0, 1.0) - 2.0).abs() < 1e-5);
+        assert_eq!(flash_radius(-1.0, 1.0), 0.0);
+    }
+
+    #[test]
+    fn test_trail_fade_independent_of_frame_rate() {
+        // what is left after one second, at 60 and at 20 frames per second
+        let left = |fps: i32| (1.0 - fade_alpha(2.0, 1.0 / fps as f32)).powi(fps);
+        assert!((left(60) - (-2.0f32).exp()).abs() < 1e-4);
+        assert!((left(60) - left(20)).abs() < 1e-4);
+        assert_eq!(fade_alpha(0.0, 0.1), 0.0);
+    }
+
+    #[test]
+    fn test_display_scale() {
+        // 5 units at 0.5 units per pixel is 10 pixels, inside the range
+        assert_eq!(display_scale(5.0, 0.5, 2.0, 20.0), 1.0);
+        // zoomed far out it would be 0.1 pixels, grown to 2
+        assert!((display_scale(5.0, 50.0, 2.0, 20.0) - 20.0).abs() < 1e-4);
+        // zoomed far in it would be 500 pixels, shrunk to 20
+        assert!((display_scale(5.0, 0.01, 2.0, 20.0) - 0.04).abs() < 1e-6);
+        assert_eq!(display_scale(0.0, 1.0, 2.0, 20.0), 1.0);
+    }
+
+    #[test]
+    fn test_radius_laws() {
+        // the old sqrt(m / 10) sizes are the default
+        let settings = SimulationSettings::default();
+        assert!((settings.collision_radius(40.0) - 2.0).abs() < 1e-6);
+        assert!((radius_for_mass(1000.0, RadiusLaw::Volume, 1.0, false) - 10.0).abs() < 1e-4);
+        // eight times the mass doubles a sphere, but almost triples a disc
+        let ratio = |law| radius_for_mass(80.0, law, 10.0, false) / radius_for_mass(10.0, law, 10.0, false);
+        assert!((ratio(RadiusLaw::Volume) - 2.0).abs() < 1e-5);
+        assert!((ratio(RadiusLaw::Area) - 8f32.sqrt()).abs() < 1e-5);
+        assert_eq!(radius_for_mass(0.0, RadiusLaw::Area, 10.0, false), 0.0);
+        // deterministic runs get the same sizes on every platform
+        assert_eq!(radius_for_mass(1000.0, RadiusLaw::Volume, 1.0, true), libm::cbrtf(1000.0));
+    }
+
+    #[test]
+    fn test_validate_settings() {
+        let mut settings = SimulationSettings::default();
+        assert!(validate(&mut settings).is_empty());
+
+        let mut settings = SimulationSettings {
+            min_body_mass: 200.0,
+            max_body_mass: 50.0,
+            delta_t: -0.1,
+            theta: 0.0,
+            spawn_area: 10.0..=-10.0,
+            ..SimulationSettings::default()
+        };
+        let warnings = validate(&mut settings);
+        assert_eq!(warnings.len(), 4);
+        assert_eq!(settings.min_body_mass, 50.0);
+        assert!(settings.delta_t > 0.0);
+        assert!(settings.theta > 0.0 && settings.theta <= 1.0);
+        assert!(settings.spawn_area.start() < settings.spawn_area.end());
+        // corrected settings pass the next time
+        assert!(validate(&mut settings).is_empty());
+
+        let mut settings = SimulationSettings {
+            elasticity: f32::NAN,
+            ..SimulationSettings::default()
+        };
+        assert_eq!(validate(&mut settings).len(), 1);
+        assert_eq!(settings.elasticity, 0.0);
+    }
+
+    #[test]
+    fn test_undo_history() {
+        let snapshot = |label: &'static str, n: usize| Snapshot {
+            label,
+            bodies: vec![
+                BodyState {
+                    entity: Entity::PLACEHOLDER,
+                    body: Body {
+                        mass: 1.0,
+                        radius: 1.0,
+                        hue: 0.0,
+                    },
+                    transform: Transform::default(),
+                    velocity: Vec3::ZERO,
+                    star: false,
+                    pinned: false,
+                    tracer: false,
+                    formed_star: false,
+                    group: None,
+                    lifetime: None,
+                    merges: None,
+                    controller: None,
+                };
+                n
+            ],
+            time: n as f64,
+            steps: n as u64,
+        };
+
+        let mut history = UndoHistory::default();
+        assert!(history.undo(snapshot("", 1)).is_none());
+        history.record(snapshot("Reset", 10));
+        history.record(snapshot("Kick", 20));
+        assert_eq!(history.undo_label(), Some("Kick"));
+
+        // undo gives the state before the kick and keeps the current one for redo
+        let previous = history.undo(snapshot("", 30)).unwrap();
+        assert_eq!(previous.bodies.len(), 20);
+        assert_eq!(history.redo_label(), Some("Kick"));
+        let next = history.redo(snapshot("", 20)).unwrap();
+        assert_eq!(next.bodies.len(), 30);
+
+        // a new action drops the redo side
+        history.undo(snapshot("", 30));
+        history.record(snapshot("Delete", 20));
+        assert_eq!(history.redo_label(), None);
+
+        // over the budget the oldest go, the newest always stays
+        let mut history = UndoHistory::default();
+        history.max_bodies = 25;
+        history.record(snapshot("Reset", 10));
+        history.record(snapshot("Kick", 10));
+        history.record(snapshot("Delete", 10));
+        assert_eq!(history.undo(snapshot("", 1)).unwrap().label, "Delete");
+        assert_eq!(history.undo(snapshot("", 1)).unwrap().label, "Kick");
+        assert!(history.undo(snapshot("", 1)).is_none());
+        history.record(snapshot("Reset", 100));
+        assert_eq!(history.undo_label(), Some("Reset"));
+    }
+
+    #[test]
+    fn test_undo_keeps_tags() {
+        use bevy::ecs::system::RunSystemOnce;
+        use bevy::prelude::{AssetApp, ColorMaterial, Has, Mesh, Res, ResMut, Update};
+
+        let mut app = physics_app(SimulationSettings::default());
+        app.init_asset::<ColorMaterial>()
+            .init_asset::<Mesh>()
+            .init_resource::<UndoHistory>()
+            .add_systems(Update, apply_undo);
+
+        // a craft chasing a merged body in a group, and a tracer
+        let primary = app
+            .world_mut()
+            .spawn((test_body(10.0), at(Vec2::ZERO), Velocity(Vec3::ZERO), GroupTag(2), Merges(3)))
+            .id();
+        let lifetime = Lifetime {
+            remaining: 5.0,
+            span: 10.0,
+            fragments: 2,
+            fragment_speed: 1.0,
+        };
+        app.world_mut().spawn((
+            test_body(1.0),
+            at(Vec2::new(10.0, 0.0)),
+            Velocity(Vec3::ZERO),
+            lifetime,
+            Controller::new("Chase", 1.0, move |input| {
+                input.get(primary).map_or(Vec2::ZERO, |p| (p.position - input.body.position).normalize())
+            }),
+        ));
+        app.world_mut().spawn((test_body(0.0), at(Vec2::new(0.0, 20.0)), Velocity(Vec3::ZERO), Massless));
+
+        // a reset throws them all away, and undo brings them back as new entities
+        app.world_mut()
+            .run_system_once(|bodies: UndoBodies, clock: Res<SimulationClock>, mut history: ResMut<UndoHistory>| {
+                history.record(Snapshot::capture("Reset", &bodies, &clock));
+            })
+            .unwrap();
+        let mut all = app.world_mut().query_filtered::<Entity, With<Body>>();
+        let old: Vec<Entity> = all.iter(app.world()).collect();
+        for entity in old {
+            app.world_mut().despawn(entity);
+        }
+        app.world_mut().resource_mut::<UndoHistory>().pending = Some(UndoStep::Undo);
+        step(&mut app, 2);
+
+        let mut restored = app.world_mut().query::<(
+            Entity,
+            &Body,
+            Option<&GroupTag>,
+            Option<&Merges>,
+            Option<&Lifetime>,
+            Option<&Controller>,
+            Has<Massless>,
+        )>();
+        let restored: Vec<_> = restored.iter(app.world()).collect();
+        assert_eq!(restored.len(), 3);
+        assert!(restored.iter().all(|(entity, ..)| *entity != primary));
+        let heavy = restored.iter().find(|(_e, body, ..)| body.mass == 10.0).unwrap();
+        assert_eq!(heavy.2, Some(&GroupTag(2)));
+        assert_eq!(heavy.3.map(|m| m.0), Some(3));
+        let craft = restored.iter().find(|(_e, body, ..)| body.mass == 1.0).unwrap();
+        assert_eq!(craft.4.map(|l| l.span), Some(10.0));
+        // the controller still finds the body it chases, under its new entity
+        assert_eq!(craft.5.unwrap().thrust, Vec2::new(-1.0, 0.0));
+        assert_eq!(restored.iter().filter(|(.., tracer)| *tracer).count(), 1);
+    }
+
+    #[test]
+    fn test_bookmark_thumbnail() {
+        assert_eq!(thumbnail_pixels(&[], 8), vec![0; 64]);
+
+        // a crowded spot is brighter than a lone body
+        let mut positions = vec![Vec2::new(-50.0, -50.0)];
+        positions.extend(std::iter::repeat_n(Vec2::new(50.0, 50.0), 8));
+        let pixels = thumbnail_pixels(&positions, 8);
+        assert_eq!(pixels.len(), 64);
+        let lit: Vec<u8> = pixels.iter().copied().filter(|&p| p > 0).collect();
+        assert_eq!(lit.len(), 2);
+        assert!(lit.iter().max() > lit.iter().min());
+    }
+
+    #[test]
+    fn test_bookmark_limits() {
+        let empty = Snapshot {
+            label: "Bookmark",
+            bodies: Vec::new(),
+            time: 0.0,
+            steps: 0,
+        };
+        let mut bookmarks = Bookmarks::default();
+        assert!(bookmarks.has_room(MAX_BOOKMARKED_BODIES));
+        assert!(!bookmarks.has_room(MAX_BOOKMARKED_BODIES + 1));
+        for i in 0..MAX_BOOKMARKS {
+            bookmarks.list.push(Bookmark::new(i.to_string(), empty.clone()));
+        }
+        assert!(!bookmarks.has_room(0));
+
+        // dropping the thumbnails keeps the bookmarks
+        assert!(bookmarks.thumbnail_bytes() > 0);
+        bookmarks.drop_thumbnails();
+        assert_eq!(bookmarks.thumbnail_bytes(), 0);
+        assert_eq!(bookmarks.list.len(), MAX_BOOKMARKS);
+    }
+
+    #[test]
+    fn test_branch_viewport() {
+        assert_eq!(right_half(UVec2::new(1000, 800)), Some((UVec2::new(500, 0), UVec2::new(500, 800))));
+        // an odd pixel goes to the original
+        assert_eq!(right_half(UVec2::new(1001, 800)), Some((UVec2::new(501, 0), UVec2::new(500, 800))));
+        assert!(right_half(UVec2::new(1, 800)).is_none());
+    }
+
+    #[test]
+    fn test_director_framing() {
+        // heading straight for each other, 10 apart at a closing speed of 2
+        let (t, d) = closest_approach(Vec2::new(10.0, 0.0), Vec2::new(-2.0, 0.0));
+        assert!((t - 5.0).abs() < 1e-5 && d < 1e-5);
+        // passing by with a miss distance of 3
+        let (t, d) = closest_approach(Vec2::new(10.0, 3.0), Vec2::new(-1.0, 0.0));
+        assert!((t - 10.0).abs() < 1e-5 && (d - 3.0).abs() < 1e-5);
+        // moving apart, the closest they get is now
+        assert_eq!(closest_approach(Vec2::new(4.0, 0.0), Vec2::new(1.0, 0.0)), (0.0, 4.0));
+
+        let positions: Vec<Vec2> = (1..=5).map(|i| Vec2::new(i as f32, 0.0)).collect();
+        assert_eq!(kth_nearest_distance(Vec2::ZERO, &positions, 2), Some(3.0));
+        assert_eq!(kth_nearest_distance(Vec2::ZERO, &positions, 100), Some(5.0));
+        assert_eq!(kth_nearest_distance(Vec2::ZERO, &[], 2), None);
+
+        // a radius of 100 fills the shorter side of an 800 x 400 window
+        assert_eq!(zoom_for_extent(100.0, Vec2::new(800.0, 400.0)), 0.5);
+    }
+
+    #[test]
+    fn test_app_conserves_momentum() {
+        // theta 0 opens every node, so every pair pulls on each other equally and oppositely
+        let mut app = physics_app(SimulationSettings {
+            n_bodies: 50,
+            theta: 0.0,
+            ..SimulationSettings::default()
+        });
+        spawn_generated(&mut app, 3);
+        let start = bodies(&mut app);
+        let before = total_momentum(&mut app);
+
+        step(&mut app, 1000);
+        assert_eq!(clock(&app).steps, 1000);
+        let after = total_momentum(&mut app);
+        let end = bodies(&mut app);
+        // how big the momenta got, to compare the drift against, the bodies start at rest
+        let scale: f64 = end.iter().map(|(m, _, v)| (*m * v.length()) as f64).sum();
+        assert!((after - before).length() < 1e-4 * scale, "{} -> {}", before, after);
+        // and the bodies did move
+        assert_eq!(end.len(), 50);
+        assert!(start.iter().zip(&end).any(|(a, b)| a.1.distance(b.1) > 1.0));
+    }
+
+    // Written with UPDATE_GOLDEN=1 after a deliberate change to the physics, and committed with it
+    const GOLDEN_TRAJECTORY: &str = concat!(env!("CARGO_MANIFEST_DIR"), "/golden/trajectory_100x500.csv");
+
+    #[test]
+    fn test_golden_trajectory() {
+        // the app's own systems, so everything update does on the way is covered too
+        let mut app = physics_app(SimulationSettings {
+            n_bodies: 100,
+            deterministic: true,
+            ..SimulationSettings::default()
+        });
+        spawn_generated(&mut app, 2024);
+        step(&mut app, 500);
+        let state: Vec<[f32; 4]> = bodies(&mut app)
+            .into_iter()
+            .map(|(_mass, position, velocity)| [position.x, position.y, velocity.x, velocity.y])
+            .collect();
+
+        let path = std::path::Path::new(GOLDEN_TRAJECTORY);
+        if std::env::var_os("UPDATE_GOLDEN").is_some() {
+            // {:?} prints the shortest text that reads back as the same f32
+            let lines: Vec<String> = state.iter().map(|s| format!("{:?},{:?},{:?},{:?}", s[0], s[1], s[2], s[3])).collect();
+            std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+            std::fs::write(path, lines.join("\n") + "\n").unwrap();
+            eprintln!("Wrote {}", path.display());
+            return;
+        }
+        assert!(
+            path.exists(),
+            "{} is missing, write it with UPDATE_GOLDEN=1 cargo test test_golden_trajectory and commit it",
+            path.display()
+        );
+
+        let golden: Vec<Vec<f32>> = std::fs::read_to_string(path)
+            .unwrap()
+            .lines()
+            .map(|line| line.split(',').map(|v| v.parse().unwrap()).collect())
+            .collect();
+        assert_eq!(golden.len(), state.len());
+        // loose enough for other platforms' float rounding, far too tight for a changed integrator
+        for (i, (g, s)) in golden.iter().zip(&state).enumerate() {
+            let position = Vec2::new(g[0] - s[0], g[1] - s[1]).length();
+            let velocity = Vec2::new(g[2] - s[2], g[3] - s[3]).length();
+            assert!(position < 1e-2, "body {} is {} away from its golden position", i, position);
+            assert!(velocity < 1e-1, "body {} is {} off its golden velocity", i, velocity);
+        }
+    }
+
+    // Coordinates a tree should survive: non-finite ones, the extremes of f32 and ordinary ones
+    fn adversarial_coordinate() -> impl Strategy<Value = f32> {
+        prop_oneof![
+            Just(f32::NAN),
+            Just(f32::INFINITY),
+            Just(f32::NEG_INFINITY),
+            Just(f32::MAX),
+            Just(f32::MIN),
+            Just(f32::MIN_POSITIVE),
+            Just(0.0),
+            Just(1e30),
+            -1e3f32..1e3,
+            -1e3f32..1e3,
+        ]
+    }
+
+    fn adversarial_positions() -> impl Strategy<Value = Vec<Vec2>> {
+        let point = || (adversarial_coordinate(), adversarial_coordinate()).prop_map(|(x, y)| Vec2::new(x, y));
+        prop_oneof![
+            prop::collection::vec(point(), 0..200),
+            // a few points repeated many times over, more than a leaf can hold
+            (prop::collection::vec(point(), 1..4), prop::collection::vec(0..4usize, 0..300))
+                .prop_map(|(pool, picks)| picks.iter().map(|i| pool[i % pool.len()]).collect()),
+            // all on one line
+            (point(), point(), prop::collection::vec(-1e3f32..1e3, 0..200))
+                .prop_map(|(start, direction, steps)| steps.iter().map(|t| start + direction * *t).collect()),
+        ]
+    }
+
+    proptest! {
+        #![proptest_config(ProptestConfig::with_cases(500))]
+
+        // Whatever goes in, inserting and querying must not panic or recurse forever,
+        // and the tree must weigh what was put into it
+        #[test]
+        fn fuzz_tree_insert(positions in adversarial_positions(), capacity in 1usize..8) {
+            let mut tree = Quadtree::with_leaf_capacity(Quad::new_containing(&positions), capacity);
+            let mut inserted = 0;
+            let mut inserted_mass = 0.0f64;
+            for (i, position) in positions.iter().enumerate() {
+                let body = Body {
+                    mass: 1.0 + (i % 10) as f32,
+                    radius: 1.0,
+                    hue: 0.0,
+                };
+                let before = tree.body_count();
+                tree.insert(Entity::from_raw(i as u32), Transform::from_translation(position.extend(0.0)), body);
+                if tree.body_count() > before {
+                    inserted += 1;
+                    inserted_mass += body.mass as f64;
+                }
+            }
+            prop_assert_eq!(tree.body_count(), inserted);
+            let total = tree.total_mass() as f64;
+            prop_assert!((total - inserted_mass).abs() <= 1e-4 * inserted_mass.max(1.0), "{} != {}", total, inserted_mass);
+
+            // every finite position is kept as long as the root has a finite size to hold it in
+            let finite = positions.iter().filter(|p| p.is_finite()).count();
+            let (min, max) = Quad::new_containing(&positions).bounds();
+            if min.is_finite() && max.is_finite() {
+                prop_assert_eq!(inserted, finite);
+            }
+
+            for query in [Vec2::ZERO, Vec2::NAN, Vec2::INFINITY, positions.first().copied().unwrap_or(Vec2::ONE)] {
+                tree.knn(query, 3);
+                tree.query_circle(query, 10.0).count();
+                tree.get_potential(Entity::from_raw(u32::MAX - 1), Transform::from_translation(query.extend(0.0)), ForceParams::default(), 0.5);
+            }
+            tree.query_rect(Rect::new(-1e3, -1e3, 1e3, 1e3)).count();
+        }
+    }
+
+    #[test]
+    fn test_root_holds_far_away_bodies() {
+        // at 1e8 the unit of padding is below the spacing of f32
+        let points = [Vec2::new(1e8, 0.0), Vec2::new(3e8, 2e8)];
+        let mut tree = Quadtree::new(Quad::new_containing(&points));
+        for (i, p) in points.iter().enumerate() {
+            tree.insert(Entity::from_raw(i as u32), Transform::from_translation(p.extend(0.0)), Body {
+                mass: 1.0,
+                radius: 1.0,
+                hue: 0.0,
+            });
+        }
+        assert_eq!(tree.body_count(), 2);
+    }
+
+    #[test]
+    fn test_tree_with_infinite_root() {
+        // the extremes of f32 are further apart than an f32 can hold, the root is infinitely big
+        let quad = Quad::new_containing(&[Vec2::splat(f32::MAX), Vec2::splat(f32::MIN)]);
+        let mut tree = Quadtree::with_leaf_capacity(quad, 1);
+        let body = Body {
+            mass: 1.0,
+            radius: 1.0,
+            hue: 0.0,
+        };
+        // which used to split without end around the same point
+        for i in 0..10 {
+            tree.insert(Entity::from_raw(i), Transform::from_xyz(f32::MAX, f32::MAX, 0.0), body);
+        }
+        assert_eq!(tree.body_count(), 10);
+        assert_eq!(tree.total_mass(), 10.0);
+    }
+
+    #[test]
+    fn test_benchmark_run() {
+        let settings = SimulationSettings {
+            n_bodies: 100,
+            ..SimulationSettings::default()
+        };
+        let result = run_benchmark(&settings, 5);
+        assert_eq!(result.bodies, 100);
+        let times = result.times;
+        assert!(times.tree >= 0.0 && times.force >= 0.0 && times.integrate >= 0.0);
+        assert!((times.total() - (times.tree + times.force + times.integrate)).abs() < 1e-12);
+
+        // timing a step doesn't change what it does
+        let mut timed = HeadlessSim::new(&settings, 1);
+        let mut plain = HeadlessSim::new(&settings, 1);
+        timed.step_timed(&settings, settings.delta_t);
+        plain.step(&settings);
+        assert_eq!(timed.transforms, plain.transforms);
+        assert_eq!(timed.velocities, plain.velocities);
+    }
+
+    #[test]
+    fn test_pipelined_tree() {
+        AsyncComputeTaskPool::get_or_init(TaskPool::new);
+        let inputs: TreeInputs = random_points(5, 100)
+            .iter()
+            .enumerate()
+            .map(|(i, p)| {
+                let body = Body {
+                    mass: 1.0,
+                    radius: 1.0,
+                    hue: 0.0,
+                };
+                (Entity::from_raw(i as u32), Transform::from_translation(p.extend(0.0)), body)
+            })
+            .collect();
+
+        let mut pipelined = PipelinedTree::default();
+        assert!(pipelined.take(&inputs, 4).is_none());
+        pipelined.start(inputs.clone(), 4);
+        assert_eq!(pipelined.take(&inputs, 4).unwrap().body_count(), 100);
+        // a tree is only handed out once
+        assert!(pipelined.take(&inputs, 4).is_none());
+
+        // a body moved after the tree was started, or the leaves changed size
+        let mut moved = inputs.clone();
+        moved[3].1.translation.x += 1.0;
+        pipelined.start(inputs.clone(), 4);
+        assert!(pipelined.take(&moved, 4).is_none());
+        pipelined.start(inputs.clone(), 4);
+        assert!(pipelined.take(&inputs, 8).is_none());
+    }
+
+    #[test]
+    fn test_interpolation() {
+        let now = Instant::now();
+        let previous = Some(now);
+        let current = Some(now + Duration::from_millis(10));
+        // halfway through the interval after the newest step
+        assert!((interpolation_alpha(previous, current, now + Duration::from_millis(15)) - 0.5).abs() < 1e-3);
+        // never past the newest step, and just the newest one until there are two
+        assert_eq!(interpolation_alpha(previous, current, now + Duration::from_secs(1)), 1.0);
+        assert_eq!(interpolation_alpha(None, current, now), 1.0);
+
+        let moved = interpolate(&[Vec2::ZERO, Vec2::X], &[Vec2::Y, Vec2::X * 3.0], 0.5);
+        assert_eq!(moved, vec![Vec2::new(0.0, 0.5), Vec2::X * 2.0]);
+        // a different number of bodies can't be blended
+        assert_eq!(interpolate(&[Vec2::ZERO], &[Vec2::X, Vec2::Y], 0.5), vec![Vec2::X, Vec2::Y]);
+    }
+
+    #[test]
+    fn test_physics_thread() {
+        let mut app = physics_app(SimulationSettings {
+            n_bodies: 50,
+            theta: 0.0,
+            ..SimulationSettings::default()
+        });
+        spawn_generated(&mut app, 3);
+        let tracer = app
+            .world_mut()
+            .spawn((test_body(0.0), at(Vec2::new(30.0, 0.0)), Velocity(Vec3::ZERO), Massless))
+            .id();
+        let start = bodies(&mut app);
+        let before = total_momentum(&mut app);
+
+        app.world_mut().resource_mut::<PhysicsThread>().enabled = true;
+        step(&mut app, 1);
+        assert!(app.world().resource::<PhysicsThread>().running());
+        app.world_mut().resource_mut::<Energy>().kinetic = 0.0;
+        // the thread keeps going between frames, the frames only pick up its steps
+        let deadline = Instant::now() + Duration::from_secs(30);
+        while clock(&app).steps < 200 && Instant::now() < deadline {
+            std::thread::sleep(Duration::from_millis(5));
+            step(&mut app, 1);
+        }
+        assert!(clock(&app).steps >= 200);
+        // the velocities, the energy and the tracers keep up with the thread while it runs
+        let running = bodies(&mut app);
+        assert!(start.iter().zip(&running).any(|(a, b)| a.2.distance(b.2) > 1e-3));
+        assert!(app.world().resource::<Energy>().kinetic > 0.0);
+        assert!(app.world().get::<Velocity>(tracer).unwrap().0.length() > 0.0);
+
+        app.world_mut().resource_mut::<PhysicsThread>().enabled = false;
+        step(&mut app, 1);
+        assert!(!app.world().resource::<PhysicsThread>().running());
+        let end = bodies(&mut app);
+        assert!(start.iter().zip(&end).any(|(a, b)| a.1.distance(b.1) > 1.0));
+        let after = total_momentum(&mut app);
+        let scale: f64 = end.iter().map(|(m, _, v)| (*m * v.length()) as f64).sum();
+        assert!((after - before).length() < 1e-4 * scale, "{} -> {}", before, after);
+    }
+
+    #[test]
+    fn test_physics_thread_blocked() {
+        let mut app = physics_app(SimulationSettings {
+            n_bodies: 20,
+            collision_enabled: true,
+            ..SimulationSettings::default()
+        });
+        spawn_generated(&mut app, 5);
+        app.world_mut().resource_mut::<PhysicsThread>().enabled = true;
+        step(&mut app, 3);
+        // the main thread keeps stepping, with the collisions the thread would leave out
+        assert!(!app.world().resource::<PhysicsThread>().running());
+        assert_eq!(app.world().resource::<PhysicsThread>().blocked_by, vec!["Collisions"]);
+        assert_eq!(clock(&app).steps, 3);
+
+        app.world_mut().resource_mut::<SimulationSettings>().collision_enabled = false;
+        step(&mut app, 1);
+        assert!(app.world().resource::<PhysicsThread>().running());
+        assert!(app.world().resource::<PhysicsThread>().blocked_by.is_empty());
+
+        // turning one on while it runs hands the bodies back to the main thread
+        app.world_mut().resource_mut::<SimulationSettings>().post_newtonian = true;
+        step(&mut app, 1);
+        assert!(!app.world().resource::<PhysicsThread>().running());
+        assert_eq!(app.world().resource::<PhysicsThread>().blocked_by, vec!["Post-Newtonian Correction"]);
+
+        // so does anything that changes velocities on the main thread
+        app.world_mut().resource_mut::<SimulationSettings>().post_newtonian = false;
+        app.insert_resource(BackgroundFlow {
+            enabled: true,
+            ..BackgroundFlow::default()
+        });
+        step(&mut app, 1);
+        assert!(!app.world().resource::<PhysicsThread>().running());
+        assert_eq!(app.world().resource::<PhysicsThread>().blocked_by, vec!["Gas Flow"]);
+    }
+
+    #[test]
+    fn test_physics_thread_pacing() {
+        let mut app = physics_app(SimulationSettings {
+            n_bodies: 20,
+            ..SimulationSettings::default()
+        });
+        app.world_mut().resource_mut::<SimulationSettings>().time_scale = 3.0;
+        spawn_generated(&mut app, 6);
+        // a body that is already broken is the NaN guard's before the thread gets the rest
+        let broken = app
+            .world_mut()
+            .spawn((
+                Body {
+                    mass: 1.0,
+                    radius: 1.0,
+                    hue: 0.0,
+                },
+                Transform::default(),
+                Velocity(Vec3::new(f32::NAN, 0.0, 0.0)),
+            ))
+            .id();
+        app.world_mut().resource_mut::<PhysicsThread>().enabled = true;
+        step(&mut app, 1);
+        assert!(app.world().resource::<PhysicsThread>().running());
+        assert_eq!(app.world().resource::<NanGuard>().caught, 1);
+        assert!(app.world().get::<Quarantined>(broken).is_some() || app.world().get_entity(broken).is_err());
+
+        // every round of the thread takes three steps, like a frame at this time scale
+        let deadline = Instant::now() + Duration::from_secs(30);
+        while clock(&app).steps < 60 && Instant::now() < deadline {
+            std::thread::sleep(Duration::from_millis(5));
+            step(&mut app, 1);
+        }
+        app.world_mut().resource_mut::<PhysicsThread>().enabled = false;
+        step(&mut app, 1);
+        let (steps, time) = (clock(&app).steps, clock(&app).time);
+        assert!(steps >= 60);
+        assert_eq!(steps % 3, 0);
+        let delta_t = app.world().resource::<SimulationSettings>().delta_t as f64;
+        assert!((time - steps as f64 * delta_t).abs() < 1e-6 * time.max(1.0));
+    }
+
+    #[test]
+    fn test_pending_settings() {
+        let mut settings = SimulationSettings::default();
+        let mut pending = PendingSettings::default();
+
+        let mut spawn = pending.editable(&settings);
+        spawn.n_bodies = 20;
+        spawn.donut = !settings.donut;
+        spawn.render_scale = 2.0;
+        pending.stage(spawn, &settings);
+        assert_eq!(pending.changed_fields(&settings), vec!["Num Bodies", "Drawn Radius x", "Donut Start"]);
+        // nothing is applied until the reset
+        assert_eq!(settings.n_bodies, SimulationSettings::default().n_bodies);
+        assert_eq!(pending.editable(&settings).n_bodies, 20);
+        assert_eq!(settings.render_scale, SimulationSettings::default().render_scale);
+
+        // editing back to the applied values leaves nothing pending
+        let mut spawn = pending.editable(&settings);
+        spawn.donut = settings.donut;
+        spawn.n_bodies = settings.n_bodies;
+        spawn.render_scale = settings.render_scale;
+        pending.stage(spawn, &settings);
+        assert!(pending.staged.is_none());
+
+        let mut spawn = pending.editable(&settings);
+        spawn.max_body_mass = 500.0;
+        pending.stage(spawn.clone(), &settings);
+        pending.revert();
+        assert!(pending.changed_fields(&settings).is_empty());
+
+        pending.stage(spawn.clone(), &settings);
+        pending.apply(&mut settings);
+        assert_eq!(SpawnSettings::from_settings(&settings), spawn);
+        assert!(pending.staged.is_none());
+    }
+
+    #[test]
+    fn test_reset_checks_staged_settings() {
+        use bevy::prelude::{AssetApp, ColorMaterial, Mesh, Update};
+
+        let mut app = physics_app(SimulationSettings::default());
+        app.init_asset::<ColorMaterial>()
+            .init_asset::<Mesh>()
+            .init_resource::<PendingSettings>()
+            .init_resource::<Automation>()
+            .init_resource::<SettingsWarnings>()
+            .add_event::<ResetEvent>()
+            .add_systems(Update, reset_handler);
+
+        // a min above the max would be an empty range to draw the masses from
+        let settings = app.world().resource::<SimulationSettings>().clone();
+        let mut spawn = SpawnSettings::from_settings(&settings);
+        spawn.n_bodies = 30;
+        spawn.min_body_mass = 500.0;
+        spawn.max_body_mass = 100.0;
+        app.world_mut().resource_mut::<PendingSettings>().stage(spawn, &settings);
+        app.world_mut().send_event(ResetEvent);
+        app.update();
+
+        let masses: Vec<f32> = bodies(&mut app).iter().map(|(m, _p, _v)| *m).collect();
+        assert_eq!(masses, vec![100.0; 30]);
+        assert!(app.world().resource::<PendingSettings>().staged.is_none());
+        assert_eq!(app.world().resource::<SimulationSettings>().min_body_mass, 100.0);
+        assert_eq!(app.world().resource::<SettingsWarnings>().messages.len(), 1);
+    }
+
+    #[test]
+    fn test_timeline_script() {
+        let demo = parse_script(include_str!("../timelines/demo.ron")).unwrap();
+        assert!(demo.reset);
+        assert_eq!(demo.events.len(), 5);
+        assert_eq!(demo.events[2].action, Action::SetG(600.0));
+
+        // out of order in the file, in order once read
+        let script = parse_script(
+            "(reset: false, events: [(at: 2.0, action: Pause), (at: 1.0, action: SetG(10.0)), (at: 1.0, action: SetDeltaT(0.01))])",
+        )
+        .unwrap();
+        assert!(!script.reset);
+        let actions: Vec<&Action> = script.events.iter().map(|event| &event.action).collect();
+        assert_eq!(actions, vec![&Action::SetG(10.0), &Action::SetDeltaT(0.01), &Action::Pause]);
+
+        assert!(parse_script("(events: [(at: -1.0, action: Pause)])").is_err());
+        assert!(parse_script("(events: [(at: 1.0, action: SetTimeScale(0.0))])").is_err());
+        assert!(parse_script("(events: [(at: 1.0, action: Explode)])").is_err());
+    }
+
+    #[test]
+    fn test_timeline_playback() {
+        let mut timeline = Timeline::default();
+        timeline.script = Some(parse_script("(events: [(at: 1.0, action: SetG(10.0)), (at: 2.0, action: Pause)])").unwrap());
+        timeline.start(50.0);
+        assert!(timeline.advance(50.5).is_empty());
+        assert_eq!(timeline.advance(51.0), vec![Action::SetG(10.0)]);
+        // the clock going back to 0 is a reset, counted on from there
+        assert!(timeline.advance(0.5).is_empty());
+        assert!((timeline.script_time - 1.5).abs() < 1e-9);
+        assert_eq!(timeline.advance(1.0), vec![Action::Pause]);
+        assert!(!timeline.playing);
+        assert!(timeline.advance(10.0).is_empty());
+
+        let cluster = cluster_bodies(Vec2::new(100.0, 0.0), 20.0, 50, Vec2::X, 3);
+        assert_eq!(cluster.len(), 50);
+        assert!(cluster.iter().all(|(p, v)| p.distance(Vec2::new(100.0, 0.0)) <= 20.0 && *v == Vec2::X));
+        assert_eq!(cluster, cluster_bodies(Vec2::new(100.0, 0.0), 20.0, 50, Vec2::X, 3));
+    }
+
+    #[test]
+    fn test_compare_runs() {
+        // what the export writes reads back the same
+        let data: Vec<f32> = (0..12).map(|i| i as f32 * 0.5 - 1.0).collect();
+        let mut bytes = Vec::new();
+        write_npy(&mut bytes, &[2, 6], &data).unwrap();
+        assert_eq!(read_npy(&bytes).unwrap(), (vec![2, 6], data));
+        bytes.pop();
+        assert!(read_npy(&bytes).is_err());
+
+        let manifest = "{\n  \"delta_t\": 0.001,\n  \"g\": 300.0,\n  \
+            \"normalization\": {\"position\": 300.0, \"velocity\": 50.0, \"mass\": 100.0}\n}\n";
+        assert_eq!(manifest_number(manifest, "g"), Some(300.0));
+        assert_eq!(manifest_number(manifest, "velocity"), Some(50.0));
+        assert_eq!(manifest_number(manifest, "mass"), Some(100.0));
+        assert_eq!(manifest_number(manifest, "softening"), None);
+
+        let a = [Vec2::ZERO, Vec2::X, Vec2::Y];
+        assert_eq!(position_difference(&a, &a), (0.0, 0.0));
+        let (rms, max) = position_difference(&a, &[Vec2::ZERO, Vec2::X, Vec2::Y * 4.0]);
+        // one of three bodies is 3 away
+        assert!((rms - 3.0f64.sqrt()).abs() < 1e-9 && (max - 3.0).abs() < 1e-9);
+
+        let rows = text_plot(&[0.0, 1.0, 2.0], 3);
+        assert_eq!(rows, vec!["  *", " * ", "*  "]);
+    }
+
+    #[cfg(feature = "tui")]
+    #[test]
+    fn test_terminal_density() {
+        use crate::tui::{density_grid, shade};
+
+        // 4 by 2 cells of width 10, so 20 high, centered on the origin
+        let positions = [Vec2::new(-15.0, 15.0), Vec2::new(5.0, -5.0), Vec2::new(6.0, -6.0), Vec2::new(100.0, 0.0)];
+        let grid = density_grid(&positions, Vec2::ZERO, 10.0, 4, 2);
+        assert_eq!(grid, vec![1, 0, 0, 0, 0, 0, 2, 0]);
+
+        assert_eq!(shade(0, 5), ' ');
+        assert_eq!(shade(5, 5), '@');
+        // a lone body still shows, but faintly
+        let faint = shade(1, 1000);
+        assert!(faint != ' ' && faint != '@');
+    }
+
+    #[test]
+    fn test_progress_report() {
+        assert_eq!(eta_secs(0.0, 10.0, 5.0), None);
+        assert_eq!(eta_secs(2.5, 10.0, 5.0), Some(15.0));
+        assert_eq!(eta_secs(12.0, 10.0, 5.0), Some(0.0));
+        assert_eq!(format_duration(6.2), "6s");
+        assert_eq!(format_duration(245.0), "4m05s");
+        assert_eq!(format_duration(3723.0), "1h02m03s");
+
+        let progress = Progress {
+            case: 2,
+            cases: 3,
+            step: 250,
+            total_steps: 1000,
+            time: 0.25,
+            duration: 1.0,
+            steps_per_sec: 50.0,
+            energy_error: f64::NAN,
+            elapsed_secs: 5.0,
+        };
+        assert_eq!(progress.eta(), Some(15.0));
+        assert!(progress.text().starts_with("[2/3] step 250/1000"));
+        assert!(progress.text().contains("ETA 15s"));
+        let json = progress.json("progress");
+        assert!(json.starts_with("{\"event\":\"progress\",\"case\":2,"));
+        assert!(json.contains("\"eta_s\":15.0"));
+        // NaN isn't valid JSON
+        assert!(json.contains("\"energy_error\":null"));
+        assert!(json.ends_with('}'));
+    }
+
+    #[test]
+    fn test_dump_on_exit() {
+        let summary = RunSummary {
+            wall_secs: 12.5,
+            time: 0.25,
+            steps: 250,
+            bodies: 2,
+            energy_error: None,
+            collisions: 3,
+            delta_t: 0.001,
+            theta: 0.5,
+            seed: Some(7),
+        };
+        let text = summary.text();
+        assert!(text.contains("steps=250\n") && text.contains("collisions=3\n") && text.contains("seed=7\n"));
+        assert!(text.contains("energy_error=not tracked"));
+
+        let body = Body {
+            mass: 2.5,
+            radius: 1.0,
+            hue: 0.0,
+        };
+        let csv = state_csv(&[(4, body, DVec2::new(1.0, -2.0), Vec2::new(0.1, 0.0))]);
+        assert_eq!(csv, "entity,mass,radius,x,y,vx,vy\n4,2.5,1.0,1.0,-2.0,0.1,0.0\n");
+
+        // closing the app writes both files, with every body in them
+        let dir = std::env::temp_dir().join(format!("n-body-dump-{}", std::process::id()));
+        let mut app = physics_app(SimulationSettings {
+            n_bodies: 20,
+            dump_on_exit: true,
+            dump_dir: dir.to_string_lossy().into_owned(),
+            ..SimulationSettings::default()
+        });
+        app.add_systems(bevy::app::Last, dump_on_exit);
+        spawn_generated(&mut app, 5);
+        step(&mut app, 5);
+        assert!(!dir.exists());
+        app.world_mut().send_event(bevy::app::AppExit::Success);
+        step(&mut app, 1);
+
+        let state = std::fs::read_to_string(dir.join("final_state.csv")).unwrap();
+        assert_eq!(state.lines().count(), 21);
+        let summary = std::fs::read_to_string(dir.join("run_summary.txt")).unwrap();
+        assert!(summary.contains("steps=6\n") && summary.contains("bodies=20\n"));
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_trace_rows() {
+        let path = std::env::temp_dir().join(format!("n-body-trace-{}.csv", std::process::id()));
+        let mut app = physics_app(SimulationSettings {
+            n_bodies: 10,
+            trace_enabled: true,
+            trace_path: path.to_string_lossy().into_owned(),
+            ..SimulationSettings::default()
+        });
+        app.init_resource::<LagrangianRadii>()
+            .add_systems(bevy::app::Update, trace_step.after(crate::update));
+        spawn_generated(&mut app, 4);
+        // a step every other frame, and a row only for the frames that took one after the starting state
+        app.world_mut().resource_mut::<SimulationSettings>().time_scale = 0.5;
+        step(&mut app, 4);
+        // past the most substeps a frame takes the steps are stretched, every one of them gets a row
+        app.world_mut().resource_mut::<SimulationSettings>().time_scale = 64.0;
+        step(&mut app, 1);
+        app.world_mut().resource_mut::<SimulationSettings>().trace_enabled = false;
+        step(&mut app, 1);
+
+        let trace = std::fs::read_to_string(&path).unwrap();
+        let rows: Vec<(u64, f32)> = trace
+            .lines()
+            .skip(1)
+            .map(|line| {
+                let mut columns = line.split(',');
+                (columns.next().unwrap().parse().unwrap(), columns.next().unwrap().parse().unwrap())
+            })
+            .collect();
+        let dt = SimulationSettings::default().delta_t;
+        let stretched = (3..=34).map(|step| (step, 2.0 * dt));
+        assert_eq!(rows, [(0, 0.0), (1, dt), (2, dt)].into_iter().chain(stretched).collect::<Vec<_>>());
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn test_trace_row_per_substep() {
+        let path = std::env::temp_dir().join(format!("n-body-trace-substeps-{}.csv", std::process::id()));
+        let mut app = physics_app(SimulationSettings {
+            n_bodies: 10,
+            trace_enabled: true,
+            trace_path: path.to_string_lossy().into_owned(),
+            ..SimulationSettings::default()
+        });
+        app.init_resource::<LagrangianRadii>()
+            .add_systems(bevy::app::Update, trace_step.after(crate::update));
+        spawn_generated(&mut app, 5);
+        let selected = app.world_mut().query::<Entity>().iter(app.world()).next().unwrap();
+        app.world_mut().entity_mut(selected).insert(Selected);
+        app.world_mut().resource_mut::<SimulationSettings>().substeps = 4.0;
+        step(&mut app, 3);
+        app.world_mut().resource_mut::<SimulationSettings>().trace_enabled = false;
+        step(&mut app, 1);
+
+        // the state after the first frame's four steps, then every step of the next two frames
+        let trace = std::fs::read_to_string(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        let rows: Vec<Vec<&str>> = trace.lines().skip(1).map(|line| line.split(',').collect()).collect();
+        let steps: Vec<u64> = rows.iter().map(|columns| columns[0].parse().unwrap()).collect();
+        assert_eq!(steps, (4..=12).collect::<Vec<u64>>());
+        // the selected body moves from one step to the next
+        let positions: Vec<&str> = rows.iter().map(|columns| columns[12]).collect();
+        assert!(positions.windows(2).all(|pair| !pair[0].is_empty() && pair[0] != pair[1]));
+    }
+
+    #[test]
+    fn test_trace_across_rebase() {
+        // the same run traced twice, the second one moving the origin halfway through
+        let traces = [false, true].map(|rebase| {
+            let path = std::env::temp_dir().join(format!("n-body-trace-rebase-{}-{}.csv", rebase, std::process::id()));
+            let mut app = physics_app(SimulationSettings {
+                n_bodies: 20,
+                trace_enabled: true,
+                trace_path: path.to_string_lossy().into_owned(),
+                ..SimulationSettings::default()
+            });
+            app.init_resource::<LagrangianRadii>()
+                .insert_resource(WorldOrigin {
+                    threshold: 100.0,
+                    ..WorldOrigin::default()
+                })
+                .add_systems(bevy::app::PreUpdate, rebase_origin)
+                .add_systems(bevy::app::Update, trace_step.after(crate::update));
+            spawn_generated(&mut app, 6);
+            app.world_mut().spawn((crate::MainCamera, Transform::from_xyz(1000.4, -2000.6, 0.0)));
+            step(&mut app, 3);
+            app.world_mut().resource_mut::<WorldOrigin>().enabled = rebase;
+            step(&mut app, 3);
+            assert_eq!(app.world().resource::<WorldOrigin>().rebases, rebase as u32);
+            app.world_mut().resource_mut::<SimulationSettings>().trace_enabled = false;
+            step(&mut app, 1);
+
+            let trace = std::fs::read_to_string(&path).unwrap();
+            std::fs::remove_file(&path).unwrap();
+            trace
+                .lines()
+                .skip(1)
+                .map(|line| {
+                    let columns: Vec<&str> = line.split(',').collect();
+                    Vec2::new(columns[6].parse().unwrap(), columns[7].parse().unwrap())
+                })
+                .collect::<Vec<Vec2>>()
+        });
+        // the center of mass carries on where it was instead of jumping by the shift
+        assert_eq!(traces[0].len(), 6);
+        assert_eq!(traces[0].len(), traces[1].len());
+        for (plain, rebased) in traces[0].iter().zip(&traces[1]) {
+            assert!(plain.distance(*rebased) < 0.01, "{} and {}", plain, rebased);
+        }
+    }
+
+    #[test]
+    fn test_group_interactions() {
+        let mut groups = TagGroups::default();
+        assert!(groups.all_interact());
+        let debris = groups.add("Debris").unwrap();
+        // debris feels the others but pulls on nothing
+        for target in 0..2 {
+            groups.set_acts_on(debris, target, false);
+        }
+        assert!(!groups.all_interact() && !groups.exerts(debris) && groups.exerts(0));
+        assert!(groups.acts_on(0, debris));
+
+        let mut app = physics_app(SimulationSettings::default());
+        app.insert_resource(groups.clone());
+        let body = Body {
+            mass: 100.0,
+            radius: 0.5,
+            hue: 0.0,
+        };
+        let heavy = app.world_mut().spawn((body, Transform::from_xyz(0.0, 0.0, 0.0), Velocity(Vec3::ZERO))).id();
+        let light = app
+            .world_mut()
+            .spawn((body, Transform::from_xyz(20.0, 0.0, 0.0), Velocity(Vec3::ZERO), GroupTag(debris)))
+            .id();
+        step(&mut app, 1);
+        assert!(app.world().get::<Velocity>(heavy).unwrap().0.length() < 1e-6);
+        assert!(app.world().get::<Velocity>(light).unwrap().0.x < 0.0);
+
+        // without the group everything interacts again, and its leftover tags count as the first group
+        groups.remove(debris);
+        assert!(groups.all_interact());
+        assert_eq!(groups.index(Some(&GroupTag(debris))), 0);
+
+        // with the middle one of three gone, a stale tag for the old last group is in the first, not the new last
+        let mut groups = TagGroups::default();
+        let middle = groups.add("Middle").unwrap();
+        let last = groups.add("Last").unwrap();
+        groups.remove(middle);
+        assert_eq!(groups.groups.len(), 2);
+        assert_eq!(groups.index(Some(&GroupTag(last))), 0);
+        assert_eq!(groups.index(Some(&GroupTag(1))), 1);
+        assert_eq!(groups.index(None), 0);
+    }
+
+    #[test]
+    fn test_group_trees_with_mond() {
+        // an empty third group that pulls on nothing puts the other two in the restricted mode, with a
+        // tree per group, while every body still feels every other one
+        let settings = SimulationSettings {
+            n_bodies: 50,
+            theta: 0.0,
+            mond: true,
+            ..SimulationSettings::default()
+        };
+        let [mut plain, mut grouped] = run_twice(settings, 8, 1, |app, restricted| {
+            let mut groups = TagGroups::default();
+            let second = groups.add("Second").unwrap();
+            if restricted {
+                let empty = groups.add("Empty").unwrap();
+                for target in 0..3 {
+                    groups.set_acts_on(empty, target, false);
+                }
+                assert!(!groups.all_interact());
+            }
+            app.insert_resource(groups);
+            let mut query = app.world_mut().query_filtered::<Entity, With<Body>>();
+            let entities: Vec<Entity> = query.iter(app.world()).collect();
+            for entity in entities.into_iter().step_by(2) {
+                app.world_mut().entity_mut(entity).insert(GroupTag(second));
+            }
+        });
+        // MOND isn't linear, so it has to act on the sum of the group trees' pulls and not on each one
+        for ((_m1, _p1, v1), (_m2, _p2, v2)) in bodies(&mut plain).iter().zip(&bodies(&mut grouped)) {
+            assert!(v1.distance(*v2) <= 1e-4 * v1.length() + 1e-6, "{} vs {}", v1, v2);
+        }
+    }
+
+    #[test]
+    fn test_group_frames() {
+        let bodies = [
+            (0, 1.0, Vec2::new(-1.0, 0.0), Vec2::new(0.0, 1.0)),
+            (0, 1.0, Vec2::new(1.0, 0.0), Vec2::new(0.0, -1.0)),
+            (1, 2.0, Vec2::new(10.0, 4.0), Vec2::new(3.0, 0.0)),
+            (1, 2.0, Vec2::new(10.0, 8.0), Vec2::new(5.0, 0.0)),
+        ];
+        let frames = group_frames(3, &bodies);
+        // a spinning pair at rest as a whole
+        assert_eq!(frames[0].com, Vec2::ZERO);
+        assert_eq!(frames[0].com_vel, Vec2::ZERO);
+        assert!((frames[0].internal_kinetic - 1.0).abs() < 1e-6);
+        // the bulk motion of the other group is left out of its internal energy
+        assert_eq!(frames[1].com, Vec2::new(10.0, 6.0));
+        assert_eq!(frames[1].com_vel, Vec2::new(4.0, 0.0));
+        assert!((frames[1].internal_kinetic - 2.0).abs() < 1e-6);
+        assert_eq!(frames[2].bodies, 0);
+        assert_eq!(frames[2].mass, 0.0);
+    }
+
+    #[test]
+    fn test_remnant_report() {
+        // a heavy core with two bodies orbiting it the same way round, and one body flying off
+        let v = 10.0f32.sqrt();
+        let masses = [100.0, 1.0, 1.0, 1.0];
+        let positions = [Vec2::ZERO, Vec2::new(10.0, 0.0), Vec2::new(-10.0, 0.0), Vec2::new(0.0, 50.0)];
+        let velocities = [Vec2::ZERO, Vec2::new(0.0, v), Vec2::new(0.0, -v), Vec2::new(0.0, 100.0)];
+        let report = analyze_remnant(2.5, &masses, &positions, &velocities, 1.0, 0.1);
+        assert_eq!(report.bound_bodies, 3);
+        assert_eq!(report.bound_mass, 102.0);
+        assert_eq!(report.tail_mass, 1.0);
+        assert!((report.bound_fraction() - 102.0 / 103.0).abs() < 1e-6);
+        assert_eq!(report.com, DVec2::ZERO);
+        // the core alone holds more than half the mass
+        assert_eq!(report.half_mass_radius, 0.0);
+        // all the motion left is around the center
+        assert!((report.rotation - 1.0).abs() < 1e-5);
+        let json = report.json();
+        assert!(json.contains("\"time\": 2.5,") && json.contains("\"bound_bodies\": 3,"));
+
+        let ring: Vec<Vec2> = (1..=4).map(|r| Vec2::new(r as f32, 0.0)).collect();
+        assert_eq!(half_mass_radius(&[0, 1, 2, 3], &[1.0; 4], &ring, Vec2::ZERO), 2.0);
+    }
+
+    #[test]
+    fn test_force_providers() {
+        let world = WorldSummary::new(0.0, 1.0, [(1.0, Vec2::ZERO, Vec2::ZERO), (3.0, Vec2::X * 4.0, Vec2::Y)].into_iter());
+        assert_eq!(world.bodies, 2);
+        assert_eq!(world.com, Vec2::X * 3.0);
+        assert_eq!(world.com_vel, Vec2::Y * 0.75);
+
+        let body = ForceInput {
+            entity: Entity::from_raw(0),
+            mass: 1.0,
+            position: Vec2::new(10.0, 0.0),
+            velocity: Vec2::new(0.0, 2.0),
+        };
+        // a point mass of 100 at the origin pulls with G M / r² = 1
+        let point = CentralPotential {
+            center: Vec2::ZERO,
+            mass: 100.0,
+            core_radius: 0.0,
+        };
+        assert!((point.accel(&body, &world) - Vec2::new(-1.0, 0.0)).length() < 1e-6);
+        assert_eq!(Drag { coefficient: 0.5 }.accel(&body, &world), Vec2::new(0.0, -1.0));
+
+        // registered on the app, a lone body is only slowed by the drag
+        let mut app = physics_app(SimulationSettings::default());
+        app.add_force_provider(Drag { coefficient: 1.0 }, true);
+        let entity = app
+            .world_mut()
+            .spawn((
+                Body {
+                    mass: 1.0,
+                    radius: 0.5,
+                    hue: 0.0,
+                },
+                Transform::default(),
+                Velocity(Vec3::new(10.0, 0.0, 0.0)),
+            ))
+            .id();
+        step(&mut app, 1);
+        let velocity = app.world().get::<Velocity>(entity).unwrap().0;
+        assert!(velocity.x < 10.0 && velocity.x > 0.0);
+        assert_eq!(velocity.y, 0.0);
+    }
+
+    #[test]
+    fn test_controller() {
+        // just outside the small body's Hill sphere
+        let l2 = l2_point(1.0, Vec2::ZERO, 0.001, Vec2::new(100.0, 0.0)).unwrap();
+        assert!((l2.x - 107.09).abs() < 0.05 && l2.y.abs() < 1e-4);
+        assert!(l2_point(1.0, Vec2::ZERO, 0.0, Vec2::X).is_none());
+
+        // a craft asking for far more thrust than it has, towards the only other body
+        let mut app = physics_app(SimulationSettings::default());
+        let body = |mass| Body {
+            mass,
+            radius: 0.5,
+            hue: 0.0,
+        };
+        let craft = app
+            .world_mut()
+            .spawn((
+                body(1.0),
+                Transform::default(),
+                Velocity(Vec3::ZERO),
+                Controller::new("Chase", 5.0, |input| {
+                    input
+                        .nearest(1)
+                        .first()
+                        .map_or(Vec2::ZERO, |other| (other.position - input.body.position) * 1000.0)
+                }),
+            ))
+            .id();
+        app.world_mut().spawn((body(1e-6), Transform::from_xyz(1000.0, 0.0, 0.0), Velocity(Vec3::ZERO)));
+        step(&mut app, 1);
+
+        let dt = app.world().resource::<SimulationSettings>().delta_t;
+        let velocity = app.world().get::<Velocity>(craft).unwrap().0;
+        assert!((velocity.x - 5.0 * dt).abs() < 1e-6 && velocity.y.abs() < 1e-9);
+        let controller = app.world().get::<Controller>(craft).unwrap();
+        assert_eq!(controller.thrust, Vec2::new(5.0, 0.0));
+        assert!((controller.delta_v - 5.0 * dt).abs() < 1e-7);
+    }
+
+    #[test]
+    fn test_keep_clear_controller() {
+        let mut app = physics_app(SimulationSettings::default());
+        let body = |mass| Body {
+            mass,
+            radius: 0.5,
+            hue: 0.0,
+        };
+        let craft = app
+            .world_mut()
+            .spawn((body(1e-6), Transform::default(), Velocity(Vec3::ZERO), Controller::keep_clear(10.0, 2.0, 1000.0)))
+            .id();
+        // one body inside the clearance and coming closer, one outside it
+        app.world_mut().spawn((body(1e-6), Transform::from_xyz(4.0, 0.0, 0.0), Velocity(Vec3::new(-1.0, 0.0, 0.0))));
+        app.world_mut().spawn((body(1e-6), Transform::from_xyz(0.0, -30.0, 0.0), Velocity(Vec3::ZERO)));
+        step(&mut app, 1);
+
+        // spring on the 6 units it is too close, plus the damping on its closing speed
+        let thrust = app.world().get::<Controller>(craft).unwrap().thrust;
+        assert!((thrust - Vec2::new(-(4.0 * 6.0 + 4.0 * 1.0), 0.0)).length() < 1e-4, "{thrust:?}");
+
+        // nothing within the clearance, nothing to do
+        let mut app = physics_app(SimulationSettings::default());
+        let craft = app
+            .world_mut()
+            .spawn((body(1e-6), Transform::default(), Velocity(Vec3::ZERO), Controller::keep_clear(10.0, 2.0, 1000.0)))
+            .id();
+        app.world_mut().spawn((body(1e-6), Transform::from_xyz(0.0, -30.0, 0.0), Velocity(Vec3::ZERO)));
+        step(&mut app, 1);
+        assert_eq!(app.world().get::<Controller>(craft).unwrap().thrust, Vec2::ZERO);
+    }
+
+    #[test]
+    fn test_hohmann_transfer() {
+        let transfer = hohmann(1.0, 1.0, 4.0);
+        assert!((transfer.dv1 - ((8.0f32 / 5.0).sqrt() - 1.0)).abs() < 1e-6);
+        assert!((transfer.dv2 - 0.5 * (1.0 - (2.0f32 / 5.0).sqrt())).abs() < 1e-6);
+        assert!((transfer.transfer_time - std::f32::consts::PI * (125.0f32 / 8.0).sqrt()).abs() < 1e-4);
+        // going down both burns slow the body
+        let down = hohmann(1.0, 4.0, 1.0);
+        assert!(down.dv1 < 0.0 && down.dv2 < 0.0);
+
+        // each burn centered on its impulse, half the transfer apart
+        let plan = TransferPlan::new(transfer, 0.1, 10.0);
+        assert_eq!(plan.burn1_start, 10.0);
+        let centers = (
+            plan.burn1_start + plan.burn1_duration / 2.0,
+            plan.burn2_start + plan.burn2_duration / 2.0,
+        );
+        assert!((centers.1 - centers.0 - transfer.transfer_time as f64).abs() < 1e-6);
+        assert_eq!(plan.accel_at(10.5), 0.1);
+        assert_eq!(plan.accel_at(15.0), 0.0);
+        assert_eq!(plan.accel_at(plan.burn2_start + 0.01), 0.1);
+        assert_eq!(TransferPlan::new(down, 0.1, 0.0).accel_at(0.01), -0.1);
+
+        // the drawn coast runs from the departure out to the target on the far side
+        let scheduled = ScheduledTransfer {
+            body: Entity::from_raw(0),
+            primary: Entity::from_raw(1),
+            plan,
+            r1: 1.0,
+            r2: 4.0,
+            departure: Vec2::X,
+            turn: 1.0,
+        };
+        let points = scheduled.ellipse_points();
+        assert!((points[0] - Vec2::X).length() < 1e-5);
+        assert!((points[points.len() - 1] - Vec2::new(-4.0, 0.0)).length() < 1e-4);
+        // counterclockwise, so it passes above the primary
+        assert!(points[points.len() / 2].y > 0.0);
+    }
+
+    #[test]
+    fn test_choreographies() {
+        // Lagrange's triangle: the pull on each body is 1 / sqrt(3) with unit masses on the unit circle
+        let (triangle, period) = rotating_polygon(3);
+        let speed = triangle[0].1.length();
+        assert!((speed * speed - 1.0 / 3.0f32.sqrt()).abs() < 1e-6);
+        assert!((period - std::f32::consts::TAU / speed).abs() < 1e-5);
+
+        // every preset is at rest as a whole
+        for choreography in Choreography::ALL {
+            let (bodies, _period) = choreography.unit_state();
+            let momentum: Vec2 = bodies.iter().map(|(_p, v)| *v).sum();
+            assert!(momentum.length() < 1e-6, "{:?}", choreography);
+        }
+
+        // twice the size at the same mass is 2^(3/2) times slower
+        let (_bodies, small) = scaled_state(Choreography::FigureEight, 1.0, 1.0, 1.0);
+        let (_bodies, large) = scaled_state(Choreography::FigureEight, 1.0, 1.0, 2.0);
+        assert!((large / small - 2.0f32.powf(1.5)).abs() < 1e-4);
+        assert!(choreography_dt(Choreography::MothI, 1.0) < choreography_dt(Choreography::FigureEight, 1.0));
+
+        // the figure eight comes back to where it started after one period at its recommended step
+        let (bodies, period) = scaled_state(Choreography::FigureEight, 1.0, 1.0, 1.0);
+        let settings = SimulationSettings {
+            g: 1.0,
+            theta: 0.0,
+            delta_t: choreography_dt(Choreography::FigureEight, period),
+            ..SimulationSettings::default()
+        };
+        let mut sim = HeadlessSim::from_state(bodies.iter().map(|(mass, position, velocity)| {
+            let body = Body {
+                mass: *mass,
+                radius: 0.0,
+                hue: 0.0,
+            };
+            (body, Transform::from_translation(position.extend(0.0)), velocity.extend(0.0))
+        }));
+        while sim.time < period as f64 - settings.delta_t as f64 / 2.0 {
+            sim.step(&settings);
+        }
+        for ((_mass, start, _velocity), transform) in bodies.iter().zip(&sim.transforms) {
+            assert!(transform.translation.truncate().distance(*start) < 0.02);
+        }
+    }
+
+    #[test]
+    fn test_stability_runs() {
+        assert_eq!(Choreography::from_name("moth-i"), Some(Choreography::MothI));
+        assert_eq!(Choreography::from_name("Figure-Eight"), Some(Choreography::FigureEight));
+        assert_eq!(Choreography::from_name("figure eight"), None);
+
+        // every kick is epsilon times the RMS speed
+        let mut velocities = vec![Vec3::new(3.0, 0.0, 0.0), Vec3::new(0.0, 4.0, 0.0), Vec3::ZERO];
+        let before = velocities.clone();
+        let rms = (25.0f32 / 3.0).sqrt();
+        perturb(&mut velocities, 0.01, &mut StdRng::seed_from_u64(1));
+        for (after, before) in velocities.iter().zip(&before) {
+            assert!((after.distance(*before) - 0.01 * rms).abs() < 1e-5);
+        }
+
+        let body = |radius: f32| Body {
+            mass: 1.0,
+            radius,
+            hue: 0.0,
+        };
+        let at = |x: f32| Transform::from_xyz(x, 0.0, 0.0);
+        let settings = SimulationSettings::default();
+        let criteria = Criteria {
+            check_every: 1,
+            ..Criteria::default()
+        };
+
+        // closing a gap of 8 at 20
+        let mut sim = HeadlessSim::from_state([
+            (body(1.0), at(-5.0), Vec3::new(10.0, 0.0, 0.0)),
+            (body(1.0), at(5.0), Vec3::new(-10.0, 0.0, 0.0)),
+        ]);
+        let survival = survive(&mut sim, &settings, criteria, 10.0);
+        assert_eq!(survival.outcome, Outcome::Collision);
+        assert!(survival.time > 0.35 && survival.time < 0.45);
+
+        // leaving the center of mass at 500 from 5 away, past 10 times that in about 0.09
+        let mut sim = HeadlessSim::from_state([
+            (body(1.0), at(-5.0), Vec3::ZERO),
+            (body(1.0), at(5.0), Vec3::new(1000.0, 0.0, 0.0)),
+        ]);
+        let no_collisions = Criteria {
+            collisions: false,
+            ..criteria
+        };
+        let survival = survive(&mut sim, &settings, no_collisions, 10.0);
+        assert_eq!(survival.outcome, Outcome::Ejection);
+        assert!(survival.time > 0.08 && survival.time < 0.1);
+
+        // the figure eight holds together for a period, and the same seed gives the same runs
+        let (bodies, period) = scaled_state(Choreography::FigureEight, 1.0, 1.0, 1.0);
+        let state: Vec<(Body, Transform, Vec3)> = bodies
+            .iter()
+            .map(|(_mass, position, velocity)| {
+                (body(0.01), Transform::from_translation(position.extend(0.0)), velocity.extend(0.0))
+            })
+            .collect();
+        let settings = SimulationSettings {
+            g: 1.0,
+            theta: 0.0,
+            delta_t: choreography_dt(Choreography::FigureEight, period),
+            ..SimulationSettings::default()
+        };
+        let runs = stability_runs(&state, &settings, Criteria::default(), &[0.0, 1e-3], 2, period as f64, 7);
+        assert_eq!(runs.len(), 4);
+        assert!(runs.iter().all(|(_e, _trial, survival)| survival.outcome == Outcome::Survived));
+        let again = stability_runs(&state, &settings, Criteria::default(), &[0.0, 1e-3], 2, period as f64, 7);
+        assert_eq!(runs, again);
+        let csv = stability_csv(&runs);
+        assert!(csv.starts_with("epsilon,trial,survival_time,steps,outcome\n"));
+        assert_eq!(csv.lines().count(), 5);
+    }
+
+    #[test]
+    fn test_tree_export() {
+        let positions = [
+            Vec2::new(-40.0, -40.0),
+            Vec2::new(-35.0, -38.0),
+            Vec2::new(-30.0, -41.0),
+            Vec2::new(20.0, 30.0),
+            Vec2::new(45.0, -10.0),
+        ];
+        let mut tree = Quadtree::with_leaf_capacity(Quad::new_containing(&positions), 2);
+        for (i, position) in positions.iter().enumerate() {
+            let body = Body {
+                mass: i as f32 + 1.0,
+                radius: 1.0,
+                hue: 0.0,
+            };
+            tree.insert(Entity::from_raw(i as u32), Transform::from_translation(position.extend(0.0)), body);
+        }
+
+        let nodes = tree.nodes();
+        let root = &nodes[0];
+        assert_eq!((root.depth, root.parent, root.bodies), (0, None, positions.len()));
+        assert!((root.mass - 15.0).abs() < 1e-5);
+        let com = positions.iter().enumerate().map(|(i, p)| (i as f32 + 1.0) * *p).sum::<Vec2>() / 15.0;
+        assert!(root.com.distance(com) < 1e-4);
+
+        // every body is in exactly one leaf, and the three close ones made the tree split further
+        let mut held: Vec<u32> = nodes.iter().flat_map(|node| node.entities.iter().map(|e| e.index())).collect();
+        held.sort();
+        assert_eq!(held, vec![0, 1, 2, 3, 4]);
+        assert!(nodes.iter().any(|node| node.depth >= 2));
+        for node in &nodes[1..] {
+            let parent = &nodes[node.parent.unwrap()];
+            assert_eq!(node.depth, parent.depth + 1);
+            assert!(!parent.leaf);
+            assert!(node.min.cmpge(parent.min).all() && node.max.cmple(parent.max).all());
+            if node.leaf {
+                assert_eq!(node.bodies, node.entities.len());
+            }
+            let below: usize = nodes.iter().filter(|child| child.parent == node.parent).map(|child| child.bodies).sum();
+            assert_eq!(below, parent.bodies);
+        }
+
+        let json = tree_json(&tree, 1.5, 0.5, 2);
+        assert!(json.starts_with("{\n  \"time\": 1.5,\n  \"theta\": 0.5,\n  \"leaf_capacity\": 2,\n  \"bodies\": 5,"));
+        assert_eq!(json.matches("\"index\":").count(), nodes.len());
+        assert!(json.contains("\"index\": 0, \"parent\": null, \"depth\": 0,"));
+    }
+
+    #[test]
+    fn test_warm_start() {
+        let mut cluster = random_bodies(3, 300, 500.0);
+        let force = SimulationSettings::default().force_params();
+
+        // the first walk has nothing to start from, the second starts from the first one's cut,
+        // and both give exactly what the plain walk does
+        let tree = tree_of(&cluster, 4);
+        let mut cuts: Vec<Vec<CutNode>> = vec![Vec::new(); cluster.len()];
+        for pass in 0..2 {
+            for (i, cut) in cuts.iter_mut().enumerate() {
+                let (entity, (position, mass)) = (Entity::from_raw(i as u32), cluster[i]);
+                let cold = tree.get_total_accel(entity, at(position), test_body(mass), force, 0.01, 0.5);
+                let (warm, hit) = tree.get_total_accel_warm(entity, at(position), force, 0.01, 0.5, cut);
+                assert_eq!(warm, cold);
+                assert_eq!(hit, pass == 1);
+                assert!(!cut.is_empty());
+            }
+        }
+
+        // after a small step most cuts still fit, and the forces stay close to the plain walk's
+        for (i, (position, _mass)) in cluster.iter_mut().enumerate() {
+            *position += Vec2::new((i % 7) as f32 - 3.0, (i % 5) as f32 - 2.0) * 0.001;
+        }
+        let tree = tree_of(&cluster, 4);
+        let mut hits = 0;
+        let mut difference = 0.0;
+        let mut magnitude = 0.0;
+        for (i, cut) in cuts.iter_mut().enumerate() {
+            let (entity, (position, mass)) = (Entity::from_raw(i as u32), cluster[i]);
+            let cold = tree.get_total_accel(entity, at(position), test_body(mass), force, 0.01, 0.5);
+            let (warm, hit) = tree.get_total_accel_warm(entity, at(position), force, 0.01, 0.5, cut);
+            hits += hit as usize;
+            difference += warm.distance(cold);
+            magnitude += cold.length();
+        }
+        assert!(hits > cluster.len() * 9 / 10, "{} of {}", hits, cluster.len());
+        assert!(difference / magnitude < 0.01);
+
+        // a body thrown far out grows the root, nothing below it is where it was
+        cluster[0].0 = Vec2::new(5000.0, 5000.0);
+        let tree = tree_of(&cluster, 4);
+        let entity = Entity::from_raw(1);
+        let (_accel, hit) = tree.get_total_accel_warm(entity, at(cluster[1].0), force, 0.01, 0.5, &mut cuts[1]);
+        assert!(!hit);
+
+        // the whole system steps the same with it on
+        let settings = SimulationSettings {
+            n_bodies: 200,
+            ..SimulationSettings::default()
+        };
+        let [mut plain, mut warm] = run_twice(settings, 5, 10, |app, warm_start| {
+            app.world_mut().resource_mut::<SimulationSettings>().warm_start = warm_start;
+        });
+        let (plain, warm) = (bodies(&mut plain), bodies(&mut warm));
+        assert_eq!(plain.len(), warm.len());
+        for ((_m1, p1, _v1), (_m2, p2, _v2)) in plain.iter().zip(&warm) {
+            assert!(p1.distance(*p2) < 0.01);
+        }
+    }
+
+    #[test]
+    fn test_warm_cut_shrinks() {
+        let tree = tree_of(&random_bodies(4, 300, 500.0), 4);
+        let force = SimulationSettings::default().force_params();
+
+        // a probe that isn't in the tree leaves the middle of the cluster for far away, one small
+        // step at a time, so its cut is always the last one moved on a little
+        let probe = Entity::from_raw(1000);
+        let mut cut = Vec::new();
+        let mut first = 0;
+        for k in 0..400 {
+            let position = Vec2::new(1.02f32.powi(k) - 1.0, 0.0);
+            let (_accel, hit) = tree.get_total_accel_warm(probe, at(position), force, 0.01, 0.5, &mut cut);
+            assert_eq!(hit, k > 0);
+            let mut fresh = Vec::new();
+            tree.get_total_accel_warm(probe, at(position), force, 0.01, 0.5, &mut fresh);
+            if k == 0 {
+                first = cut.len();
+            }
+            assert!(cut.len() <= 2 * fresh.len() + 4, "step {}: {} nodes, a fresh walk has {}", k, cut.len(), fresh.len());
+        }
+        // a few cluster sizes away it is down to the top of the tree, like a fresh walk, where a cut
+        // that is only ever opened further would still have every node it had in the middle
+        let mut fresh = Vec::new();
+        let far = Vec2::new(1.02f32.powi(399) - 1.0, 0.0);
+        tree.get_total_accel_warm(probe, at(far), force, 0.01, 0.5, &mut fresh);
+        assert!(first > 50);
+        assert_eq!(cut, fresh);
+    }
+
+    #[test]
+    fn test_parallel_gravity_matches_serial() {
+        // enough bodies for several chunks and a short last one, each walk the same as on its own
+        let cluster = random_bodies(4, 1000, 500.0);
+        let tree = tree_of(&cluster, 16);
+        let force = SimulationSettings::default().force_params();
+        let bodies: Vec<(Entity, Body, Transform)> = cluster
+            .iter()
+            .enumerate()
+            .map(|(i, (position, mass))| (Entity::from_raw(i as u32), test_body(*mass), at(*position)))
+            .collect();
+        let walk = crate::GravityWalk::Plain(&tree);
+        let pool = TaskPoolBuilder::new().num_threads(4).build();
+        let parallel = crate::gravity_accels(&walk, &bodies, &mut WarmStart::default(), force, 1.0, 0.5, &pool);
+        let serial: Vec<Vec3> = per_body_accels(&tree, &cluster, 0.5).into_iter().map(|(_e, accel)| accel).collect();
+        assert_eq!(parallel, serial);
+    }
+
+    #[test]
+    fn test_gravity_same_on_any_thread_count() {
+        // only whole bodies are spread over the threads, so the kicks come out the same bits
+        let cluster = random_bodies(9, 1500, 500.0);
+        let tree = tree_of(&cluster, 16);
+        let force = SimulationSettings::default().force_params();
+        let bodies: Vec<(Entity, Body, Transform)> = cluster
+            .iter()
+            .enumerate()
+            .map(|(i, (position, mass))| (Entity::from_raw(i as u32), test_body(*mass), at(*position)))
+            .collect();
+        let accels = |threads: usize| {
+            let pool = TaskPoolBuilder::new().num_threads(threads).build();
+            let walk = crate::GravityWalk::Plain(&tree);
+            crate::gravity_accels(&walk, &bodies, &mut WarmStart::default(), force, 1.0, 0.5, &pool)
+                .into_iter()
+                .flat_map(|accel| [accel.x.to_bits(), accel.y.to_bits()])
+                .collect::<Vec<u32>>()
+        };
+        let one = accels(1);
+        for threads in [2, 3, 8] {
+            assert_eq!(accels(threads), one, "{} threads", threads);
+        }
+    }
+
+    #[test]
+    fn test_dual_tree() {
+        let cluster = random_bodies(1, 1000, 500.0);
+        let tree = tree_of(&cluster, 16);
+        let force = SimulationSettings::default().force_params();
+
+        // theta 0 opens everything on both paths, so both are the exact sum
+        let exact = per_body_accels(&tree, &cluster, 0.0);
+        let all_pairs = dual_tree_accels(&tree, force, 1.0, 0.0);
+        assert_eq!(all_pairs.len(), cluster.len());
+        assert!(rms_error(&all_pairs, &exact) < 1e-5);
+
+        // at the usual theta a few hundredths of a percent off, about as far as the per-body walk
+        let dual = dual_tree_accels(&tree, force, 1.0, 0.5);
+        let per_body = per_body_accels(&tree, &cluster, 0.5);
+        let (dual_error, per_body_error) = (rms_error(&dual, &exact), rms_error(&per_body, &exact));
+        assert!(dual_error < 1e-3, "{}", dual_error);
+        assert!(dual_error < 3.0 * per_body_error.max(1e-4), "{} vs {}", dual_error, per_body_error);
+
+        // and the whole system steps about the same with it on
+        let settings = SimulationSettings {
+            n_bodies: 300,
+            ..SimulationSettings::default()
+        };
+        let [mut plain, mut dual] = run_twice(settings, 2, 10, |app, dual_tree| {
+            app.world_mut().resource_mut::<SimulationSettings>().dual_tree = dual_tree;
+        });
+        for ((_m1, p1, _v1), (_m2, p2, _v2)) in bodies(&mut plain).iter().zip(&bodies(&mut dual)) {
+            assert!(p1.distance(*p2) < 0.01);
+        }
+    }
+
+    #[test]
+    fn test_bucket_walks() {
+        let cluster = random_bodies(4, 800, 400.0);
+        let tree = tree_of(&cluster, 16);
+        let force = SimulationSettings::default().force_params();
+        let exact = per_body_accels(&tree, &cluster, 0.0);
+
+        // every body comes back once
+        let shared = bucket_accels(&tree, force, 1.0, 0.5);
+        let mut seen: Vec<u32> = shared.iter().map(|(entity, _a)| entity.index()).collect();
+        seen.sort();
+        assert_eq!(seen, (0..cluster.len() as u32).collect::<Vec<u32>>());
+
+        // a node has to pass for the whole leaf, so it is at least as accurate as the per-body walk
+        let (shared_error, per_body_error) =
+            (rms_error(&shared, &exact), rms_error(&per_body_accels(&tree, &cluster, 0.5), &exact));
+        assert!(shared_error < 1e-3);
+        assert!(shared_error < 1.5 * per_body_error.max(1e-4), "{} vs {}", shared_error, per_body_error);
+        assert!(rms_error(&bucket_accels(&tree, force, 1.0, 0.0), &exact) < 1e-5);
+
+        // with everything in one leaf only the exact pairs within it are left, whatever theta
+        let one_leaf = tree_of(&cluster, cluster.len());
+        assert!(rms_error(&bucket_accels(&one_leaf, force, 1.0, 1.0), &exact) < 1e-5);
+
+        let settings = SimulationSettings {
+            n_bodies: 300,
+            ..SimulationSettings::default()
+        };
+        let [mut plain, mut bucketed] = run_twice(settings, 6, 10, |app, bucket_walks| {
+            app.world_mut().resource_mut::<SimulationSettings>().bucket_walks = bucket_walks;
+        });
+        for ((_m1, p1, _v1), (_m2, p2, _v2)) in bodies(&mut plain).iter().zip(&bodies(&mut bucketed)) {
+            assert!(p1.distance(*p2) < 0.01);
+        }
+    }
+
+    #[test]
+    fn test_world_origin() {
+        assert_eq!(rebase_shift(Vec2::new(60.0, 80.0), 100.0), None);
+        assert_eq!(rebase_shift(Vec2::new(1000.4, -2000.6), 100.0), Some(Vec2::new(1000.0, -2001.0)));
+
+        // the same run with and without moving the origin, under a fixed potential that has to move too
+        let settings = SimulationSettings {
+            n_bodies: 50,
+            ..SimulationSettings::default()
+        };
+        let [mut plain, mut rebased] = run_twice(settings, 9, 5, |app, rebase| {
+            app.add_force_provider(CentralPotential::default(), true);
+            app.world_mut().spawn((crate::MainCamera, Transform::from_xyz(1000.4, -2000.6, 0.0)));
+            if rebase {
+                app.insert_resource(WorldOrigin {
+                    enabled: true,
+                    threshold: 100.0,
+                    ..WorldOrigin::default()
+                });
+                app.add_systems(bevy::app::PreUpdate, rebase_origin);
+            }
+        });
+        let camera_position = |app: &mut App| {
+            let mut cameras = app.world_mut().query_filtered::<&Transform, With<crate::MainCamera>>();
+            cameras.single(app.world()).unwrap().translation.truncate()
+        };
+        let (camera, rebased_camera) = (camera_position(&mut plain), camera_position(&mut rebased));
+        let origin = rebased.world().resource::<WorldOrigin>();
+        let (offset, rebases) = (origin.offset.as_vec2(), origin.rebases);
+        assert_eq!((offset, rebases), (Vec2::new(1000.0, -2001.0), 1));
+        assert!((rebased_camera + offset).distance(camera) < 1e-3);
+        for ((_m1, p1, v1), (_m2, p2, v2)) in bodies(&mut plain).iter().zip(&bodies(&mut rebased)) {
+            assert!(p1.distance(*p2 + offset) < 0.01);
+            assert!(v1.distance(*v2) < 1e-2);
+        }
+    }
+
+    #[test]
+    fn test_energy_in_plane() {
+        // the donut start leaves the z of the position in the velocity too, nothing ever moves along it
+        let mut app = physics_app(SimulationSettings {
+            n_bodies: 40,
+            donut: true,
+            init_vel: 50.0,
+            ..SimulationSettings::default()
+        });
+        spawn_generated(&mut app, 8);
+        let mut velocities = app.world_mut().query::<&Velocity>();
+        assert!(velocities.iter(app.world()).all(|velocity| velocity.0.z != 0.0));
+        let kinetic: f64 = bodies(&mut app).iter().map(|(m, _p, v)| 0.5 * (*m * v.length_squared()) as f64).sum();
+        step(&mut app, 1);
+        let energy = app.world().resource::<Energy>();
+        assert!((energy.kinetic - kinetic).abs() < 1e-5 * kinetic, "{} vs {}", energy.kinetic, kinetic);
+
+        let body = Body {
+            mass: 10.0,
+            radius: 1.0,
+            hue: 0.0,
+        };
+        let sim = HeadlessSim::from_state([
+            (body, Transform::from_xyz(-10.0, 0.0, 0.0), Vec3::new(0.0, 0.0, 1000.0)),
+            (body, Transform::from_xyz(10.0, 0.0, 0.0), Vec3::new(0.0, 0.0, 1000.0)),
+        ]);
+        let settings = SimulationSettings::default();
+        assert_eq!(sim.energy(&settings).0, 0.0);
+        assert_eq!(sim.bound_fraction(&settings), 1.0);
+    }
+
+    #[test]
+    fn test_nan_guard() {
+        let run = |nan_action: NanAction, nan_auto_pause: bool| {
+            let mut app = physics_app(SimulationSettings {
+                nan_action,
+                nan_auto_pause,
+                ..SimulationSettings::default()
+            });
+            let body = Body {
+                mass: 10.0,
+                radius: 1.0,
+                hue: 0.0,
+            };
+            let good = app.world_mut().spawn((body, Transform::from_xyz(-20.0, 0.0, 0.0), Velocity(Vec3::ZERO))).id();
+            let nan_position =
+                app.world_mut().spawn((body, Transform::from_xyz(f32::NAN, 0.0, 0.0), Velocity(Vec3::ZERO))).id();
+            let nan_velocity = app
+                .world_mut()
+                .spawn((body, Transform::from_xyz(20.0, 0.0, 0.0), Velocity(Vec3::new(0.0, f32::NAN, 0.0))))
+                .id();
+            step(&mut app, 2);
+            (app, good, nan_position, nan_velocity)
+        };
+
+        // both bad bodies are caught once and removed, the good one keeps going without them
+        let (app, good, nan_position, nan_velocity) = run(NanAction::Despawn, false);
+        let world = app.world();
+        assert_eq!(world.resource::<NanGuard>().caught, 2);
+        assert!(world.get_entity(nan_position).is_err() && world.get_entity(nan_velocity).is_err());
+        assert!(world.get::<Transform>(good).unwrap().translation.is_finite());
+        assert!(!world.resource::<SimulationSettings>().paused);
+
+        // frozen bodies stay around hidden and still, out of the physics, and the run pauses
+        let (app, good, nan_position, nan_velocity) = run(NanAction::Freeze, true);
+        let world = app.world();
+        assert_eq!(world.resource::<NanGuard>().caught, 2);
+        for entity in [nan_position, nan_velocity] {
+            assert!(world.get::<Quarantined>(entity).is_some());
+            assert_eq!(world.get::<Visibility>(entity), Some(&Visibility::Hidden));
+            assert_eq!(world.get::<Velocity>(entity).unwrap().0, Vec3::ZERO);
+        }
+        assert!(world.get::<Quarantined>(good).is_none());
+        assert!(world.resource::<SimulationSettings>().paused);
+    }
+
+    #[test]
+    fn test_fade_own_material() {
+        use bevy::prelude::{
+            Alpha, AssetApp, AssetPlugin, Assets, Color, ColorMaterial, MeshMaterial2d, MinimalPlugins, Update,
+        };
+        use bevy::time::TimeUpdateStrategy;
+
+        let mut app = App::new();
+        app.add_plugins((MinimalPlugins, AssetPlugin::default()))
+            .init_asset::<ColorMaterial>()
+            .insert_resource(TimeUpdateStrategy::ManualDuration(Duration::from_millis(100)))
+            .add_systems(Update, fade_system);
+        let shared = app.world_mut().resource_mut::<Assets<ColorMaterial>>().add(ColorMaterial::from(Color::WHITE));
+        let fading = app
+            .world_mut()
+            .spawn((Fade::fade_in(), Transform::default(), MeshMaterial2d(shared.clone()), Massless))
+            .id();
+        let tracer = app.world_mut().spawn((Transform::default(), MeshMaterial2d(shared.clone()), Massless)).id();
+        // a body with a material of its own fades it without a copy
+        let own = app.world_mut().resource_mut::<Assets<ColorMaterial>>().add(ColorMaterial::from(Color::WHITE));
+        let body = app.world_mut().spawn((Fade::fade_in(), Transform::default(), MeshMaterial2d(own.clone()))).id();
+
+        // the fading tracer changes a copy, the others sharing the material stay as they are
+        app.update();
+        app.update();
+        assert_eq!(app.world().resource::<Assets<ColorMaterial>>().len(), 3);
+        assert_eq!(app.world().get::<MeshMaterial2d<ColorMaterial>>(body).unwrap().0, own);
+        assert!(app.world().resource::<Assets<ColorMaterial>>().get(&own).unwrap().color.alpha() < 1.0);
+        let handle = app.world().get::<MeshMaterial2d<ColorMaterial>>(fading).unwrap().0.clone();
+        assert_ne!(handle, shared);
+        let materials = app.world().resource::<Assets<ColorMaterial>>();
+        assert!(materials.get(&handle).unwrap().color.alpha() < 1.0);
+        assert_eq!(materials.get(&shared).unwrap().color.alpha(), 1.0);
+        assert_eq!(app.world().get::<MeshMaterial2d<ColorMaterial>>(tracer).unwrap().0, shared);
+
+        // and gets the shared one back once it is in
+        for _ in 0..6 {
+            app.update();
+        }
+        assert!(app.world().get::<Fade>(fading).is_none());
+        assert_eq!(app.world().get::<MeshMaterial2d<ColorMaterial>>(fading).unwrap().0, shared);
+    }
+
+    #[test]
+    fn test_automation_rules() {
+        let mut settings = SimulationSettings::default();
+        let mut automation = Automation::default();
+        let state = RuleState {
+            time: 5.0,
+            energy_error: Some(0.02),
+            bodies: 50,
+            // no pair is watched
+            pair_distance: None,
+        };
+        // nothing is on by default
+        assert_eq!(automation.check(&settings, &state), None);
+        settings.stop_at_time = true;
+        settings.stop_time = 4.0;
+        assert!(automation.check(&settings, &state).unwrap().starts_with("Reached stop time"));
+        automation.energy_rule = true;
+        assert!(automation.check(&settings, &state).unwrap().starts_with("Energy error"));
+        automation.bodies_rule = true;
+        assert_eq!(automation.check(&settings, &state).unwrap(), "Only 50 bodies left");
+        let watched = RuleState {
+            pair_distance: Some(3.0),
+            ..state
+        };
+        assert!(automation.check(&settings, &watched).unwrap().starts_with("Pair came within"));
+
+        // the same rules on a headless run, with bodies picked by index
+        let body = Body {
+            mass: 10.0,
+            radius: 1.0,
+            hue: 0.0,
+        };
+        let settings = SimulationSettings::default();
+        let mut sim = HeadlessSim::from_state([
+            (body, Transform::from_xyz(-10.0, 0.0, 0.0), Vec3::ZERO),
+            (body, Transform::from_xyz(10.0, 0.0, 0.0), Vec3::ZERO),
+        ]);
+        let automation = Automation {
+            pair: Some((Entity::from_raw(0), Entity::from_raw(1))),
+            pair_distance: 19.0,
+            ..Automation::default()
+        };
+        let (k, p) = sim.energy(&settings);
+        let state = headless_state(&automation, &sim, &settings, k + p);
+        assert_eq!((state.pair_distance, state.energy_error, state.bodies), (Some(20.0), None, 2));
+        assert_eq!(automation.check(&settings, &state), None);
+        let mut steps = 0;
+        while automation.check(&settings, &headless_state(&automation, &sim, &settings, k + p)).is_none() {
+            sim.step(&settings);
+            steps += 1;
+            assert!(steps < 100_000, "the pair never fell together");
+        }
+        assert!(sim.transforms[0].translation.distance(sim.transforms[1].translation) < 19.0);
+
+        // a pair with a body that isn't there never fires
+        let missing = Automation {
+            pair: Some((Entity::from_raw(0), Entity::from_raw(7))),
+            ..automation
+        };
+        assert_eq!(headless_state(&missing, &sim, &settings, k + p).pair_distance, None);
+    }
+
+    #[test]
+    fn test_parse_sweep_cases() {
+        let cases = parse_cases("theta,dt,n,seed\n# coarse\n0.5, 0.01, 100, 1\n\n1.0,0.001,2000,42\n").unwrap();
+        assert_eq!(
+            cases,
+            vec![
+                SweepCase {
+                    theta: 0.5,
+                    delta_t: 0.01,
+                    n_bodies: 100,
+                    seed: 1,
+                },
+                SweepCase {
+                    theta: 1.0,
+                    delta_t: 0.001,
+                    n_bodies: 2000,
+                    seed: 42,
+                },
+            ]
+        );
+
+        // a list with nothing to run is an error, not an empty summary
+        assert!(parse_cases("").is_err());
+        assert!(parse_cases("theta,dt,n,seed\n# nothing yet\n").is_err());
+
+        // ranges aren't expanded, every case is spelled out, and errors name the line and field
+        assert_eq!(parse_cases("0.3..0.7,0.01,100,1").unwrap_err(), "line 1: could not parse theta");
+        assert_eq!(parse_cases("theta,dt,n,seed\n0.5,0.01-0.02,100,1").unwrap_err(), "line 2: could not parse dt");
+        assert_eq!(parse_cases("0.5,0.01,100").unwrap_err(), "line 1: expected 4 fields, got 3");
+        assert_eq!(parse_cases("0.5,0.01,100,1,2").unwrap_err(), "line 1: expected 4 fields, got 5");
+        assert_eq!(parse_cases("0.5,0.01,-100,1").unwrap_err(), "line 1: could not parse n");
+
+        // seeds are whole numbers that aren't negative
+        for seed in ["abc", "1.5", "-1", ""] {
+            let line = format!("0.5,0.01,100,{}", seed);
+            assert_eq!(parse_cases(&line).unwrap_err(), "line 1: could not parse seed");
+        }
+    }
+
+    #[test]
+    fn test_work_error() {
+        // a spring pulls linearly, so the averaged force is its exact work, and the work error is the
+        // integrator's own energy error
+        let (mass, k, dt) = (2.0, 3.0, 0.01);
+        let energy = |x: f32, v: f32| 0.5 * mass * v * v + 0.5 * k * x * x;
+        let (mut x, mut v) = (1.0f32, 0.0f32);
+        let start = energy(x, v);
+        let mut work_error = WorkError::default();
+        let mut settled = start;
+        for _ in 0..1000 {
+            // the step before this one is settled now
+            settled = energy(x, v);
+            let kick = -k * x / mass * dt;
+            let old = v;
+            v += kick;
+            x += v * dt;
+            work_error.record(mass, Vec3::X * old, Vec3::X * v, Vec3::X * kick, dt);
+        }
+        let drift = settled - start;
+        assert!(drift.abs() > 1e-3);
+        assert!((work_error.error - drift).abs() < 1e-4 + 0.05 * drift.abs(), "{} vs {}", work_error.error, drift);
+
+        // no force, no error, however fast the body goes
+        let mut free = WorkError::default();
+        for _ in 0..10 {
+            free.record(1.0, Vec3::new(30.0, -4.0, 0.0), Vec3::new(30.0, -4.0, 0.0), Vec3::ZERO, dt);
+        }
+        assert_eq!(free.error, 0.0);
+    }
+
+    #[cfg(feature = "python")]
+    #[test]
+    fn test_python_module() {
+        use pyo3::prelude::*;
+        use pyo3::types::PyDict;
+        pyo3::prepare_freethreaded_python();
+        Python::with_gil(|py| {
+            let locals = PyDict::new(py);
+            locals.set_item("rust_n_body", pyo3::wrap_pymodule!(rust_n_body::python::rust_n_body)(py)).unwrap();
+            py.run(
+                c"
+sim = rust_n_body.Simulation(g=1.0, theta=0.5, dt=0.01)
+assert sim.add_body(-1.0, 0.0, 0.0, -0.3, 1.0) == 0
+assert sim.add_body(1.0, 0.0, 0.0, 0.3, 1.0) == 1
+ax, ay = sim.accel(0.0, 5.0)
+assert abs(ax) < 1e-6 and ay < 0.0
+sim.step(100)
+positions, velocities, masses = sim.get_state()
+assert len(sim) == 2 and masses == [1.0, 1.0]
+assert abs(positions[0][0] + positions[1][0]) < 1e-4
+assert positions[1][0] - positions[0][0] < 2.0
+sim.set_state([(0.0, 0.0)], [(1.0, 0.0)], [2.0])
+sim.step()
+[(x, y)], velocities, masses = sim.get_state()
+assert abs(x - 0.01) < 1e-6 and y == 0.0 and velocities == [(1.0, 0.0)] and masses == [2.0]
+try:
+    sim.set_state([(0.0, 0.0)], [], [1.0])
+    assert False
+except ValueError:
+    pass
+",
+                None,
+                Some(&locals),
+            )
+            .unwrap();
+        });
+    }
+}
@@ -0,0 +1,60 @@
+use bevy::app::{TaskPoolOptions, TaskPoolPlugin};
+use bevy::prelude::*;
+use bevy::tasks::available_parallelism;
+
+// How much the frame and physics times are smoothed, per frame
+const SMOOTHING: f32 = 0.05;
+
+// Compute threads to start with. 0 asks for as many as there are cores, and reserving a core
+// leaves one for the render thread, so the UI keeps up when the physics has every other core busy.
+pub fn compute_threads(cores: usize, requested: usize, reserve_core: bool) -> usize {
+    let usable = if reserve_core { cores.saturating_sub(1) } else { cores }.max(1);
+    if requested == 0 { usable } else { requested.min(usable) }
+}
+
+// Bevy sets its task pools up once at launch, so this is read from the config before the app is built.
+// With auto and no reserved core bevy splits the cores between its pools as it always does.
+pub fn task_pool_plugin(requested: usize, reserve_core: bool) -> TaskPoolPlugin {
+    let mut options = TaskPoolOptions::default();
+    let cores = available_parallelism();
+    if reserve_core {
+        options.max_total_threads = cores.saturating_sub(1).max(1);
+    }
+    if requested > 0 {
+        let threads = compute_threads(cores, requested, reserve_core);
+        options.compute.min_threads = threads;
+        options.compute.max_threads = threads;
+    }
+    TaskPoolPlugin {
+        task_pool_options: options,
+    }
+}
+
+// How long frames and the physics in them take, smoothed so the overlay can be read
+#[derive(Resource, Default)]
+pub struct Performance {
+    pub frame_ms: f32,
+    pub physics_ms: f32,
+}
+
+impl Performance {
+    fn smooth(old: f32, new: f32) -> f32 {
+        if old == 0.0 { new } else { old + (new - old) * SMOOTHING }
+    }
+
+    pub fn record_physics(&mut self, ms: f32) {
+        self.physics_ms = Self::smooth(self.physics_ms, ms);
+    }
+
+    // Share of the frame spent stepping the bodies
+    pub fn physics_share(&self) -> f32 {
+        if self.frame_ms <= 0.0 {
+            return 0.0;
+        }
+        (self.physics_ms / self.frame_ms).min(1.0)
+    }
+}
+
+pub fn track_frame_time(time: Res<Time>, mut performance: ResMut<Performance>) {
+    performance.frame_ms = Performance::smooth(performance.frame_ms, time.delta_secs() * 1000.0);
+}
@@ -0,0 +1,308 @@
+use bevy::prelude::*;
+use bevy_egui::{EguiContexts, egui};
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+use serde::Deserialize;
+use std::fs;
+use crate::clock::SimulationClock;
+use crate::origin::WorldOrigin;
+use crate::ui_config::UiConfig;
+use crate::{Body, ResetEvent, SimulationSettings, Velocity, mass_to_hue, spawn_body};
+
+// Something a script does at its time. Positions and velocities are (x, y) pairs.
+#[derive(Deserialize, Clone, Debug, PartialEq)]
+pub enum Action {
+    // a disc of equal bodies, drifting together
+    SpawnCluster {
+        center: (f32, f32),
+        radius: f32,
+        bodies: u32,
+        mass: f32,
+        #[serde(default)]
+        velocity: (f32, f32),
+        #[serde(default)]
+        seed: u64,
+    },
+    // added to the velocity of every body within the radius
+    Impulse {
+        center: (f32, f32),
+        radius: f32,
+        velocity: (f32, f32),
+    },
+    SetG(f32),
+    SetTimeScale(f32),
+    SetDeltaT(f32),
+    Pause,
+}
+
+impl Action {
+    pub fn label(&self) -> String {
+        match self {
+            Action::SpawnCluster { center, bodies, .. } => {
+                format!("Spawn {} bodies at ({}, {})", bodies, center.0, center.1)
+            }
+            Action::Impulse { center, velocity, .. } => format!(
+                "Kick by ({}, {}) around ({}, {})",
+                velocity.0, velocity.1, center.0, center.1
+            ),
+            Action::SetG(g) => format!("G = {}", g),
+            Action::SetTimeScale(scale) => format!("Time scale {}×", scale),
+            Action::SetDeltaT(dt) => format!("Delta T = {}", dt),
+            Action::Pause => "Pause".to_string(),
+        }
+    }
+}
+
+#[derive(Deserialize, Clone, Debug, PartialEq)]
+pub struct TimedEvent {
+    // simulated time since the script started
+    pub at: f64,
+    pub action: Action,
+}
+
+fn reset_by_default() -> bool {
+    true
+}
+
+// A timeline file, in RON:
+// Script(reset: true, events: [(at: 5.0, action: SetG(600.0)), ...])
+#[derive(Deserialize, Clone, Debug, PartialEq)]
+pub struct Script {
+    // start from a fresh reset, so the script plays the same every time
+    #[serde(default = "reset_by_default")]
+    pub reset: bool,
+    pub events: Vec<TimedEvent>,
+}
+
+// Reads a script, in the order the events happen
+pub fn parse_script(text: &str) -> Result<Script, String> {
+    let mut script: Script = ron::from_str(text).map_err(|e| e.to_string())?;
+    for event in &script.events {
+        if !(event.at.is_finite() && event.at >= 0.0) {
+            return Err(format!("event at {} can't happen, times start at 0", event.at));
+        }
+        let positive = match event.action {
+            Action::SpawnCluster { radius, mass, .. } => radius > 0.0 && mass > 0.0,
+            Action::Impulse { radius, .. } => radius > 0.0,
+            Action::SetG(g) => g.is_finite(),
+            Action::SetTimeScale(value) | Action::SetDeltaT(value) => value > 0.0,
+            Action::Pause => true,
+        };
+        if !positive {
+            return Err(format!("t = {}: {} needs positive sizes", event.at, event.action.label()));
+        }
+    }
+    // same time keeps the order of the file
+    script.events.sort_by(|a, b| a.at.total_cmp(&b.at));
+    Ok(script)
+}
+
+// Plays a script against the simulated clock. Its time only moves while the simulation does,
+// so pausing holds it and a run at a different speed still does everything at the same moment.
+#[derive(Resource)]
+pub struct Timeline {
+    pub path: String,
+    pub script: Option<Script>,
+    pub error: Option<String>,
+    pub playing: bool,
+    pub script_time: f64,
+    // events already done
+    pub next: usize,
+    last_clock: f64,
+    pub start_requested: bool,
+}
+
+impl Default for Timeline {
+    fn default() -> Self {
+        Timeline {
+            path: "timelines/demo.ron".to_string(),
+            script: None,
+            error: None,
+            playing: false,
+            script_time: 0.0,
+            next: 0,
+            last_clock: 0.0,
+            start_requested: false,
+        }
+    }
+}
+
+impl Timeline {
+    pub fn load(&mut self) {
+        self.playing = false;
+        match fs::read_to_string(&self.path)
+            .map_err(|e| format!("{}: {}", self.path, e))
+            .and_then(|text| parse_script(&text))
+        {
+            Ok(script) => {
+                self.script = Some(script);
+                self.error = None;
+            }
+            Err(error) => {
+                self.script = None;
+                self.error = Some(error);
+            }
+        }
+    }
+
+    pub fn start(&mut self, clock_time: f64) {
+        self.playing = self.script.is_some();
+        self.script_time = 0.0;
+        self.next = 0;
+        self.last_clock = clock_time;
+    }
+
+    // Moves the script on to the clock and hands out what is due. A clock that went back, after
+    // a reset or an undo, counts from 0 again.
+    pub fn advance(&mut self, clock_time: f64) -> Vec<Action> {
+        let Some(script) = &self.script else {
+            return Vec::new();
+        };
+        if !self.playing {
+            return Vec::new();
+        }
+        let elapsed = clock_time - self.last_clock;
+        self.script_time += if elapsed < 0.0 { clock_time } else { elapsed };
+        self.last_clock = clock_time;
+
+        let mut due = Vec::new();
+        while let Some(event) = script.events.get(self.next) {
+            if event.at > self.script_time {
+                break;
+            }
+            due.push(event.action.clone());
+            self.next += 1;
+        }
+        if self.next >= script.events.len() {
+            self.playing = false;
+        }
+        due
+    }
+}
+
+// Positions and velocities of a cluster's bodies
+pub fn cluster_bodies(center: Vec2, radius: f32, count: u32, velocity: Vec2, seed: u64) -> Vec<(Vec2, Vec2)> {
+    let mut rng = StdRng::seed_from_u64(seed);
+    (0..count)
+        .map(|_| {
+            // uniform over the disc
+            let r = radius * rng.random::<f32>().sqrt();
+            let angle = rng.random_range(0.0..std::f32::consts::TAU);
+            (center + Vec2::from_angle(angle) * r, velocity)
+        })
+        .collect()
+}
+
+pub fn run_timeline(
+    mut timeline: ResMut<Timeline>,
+    clock: Res<SimulationClock>,
+    mut settings: ResMut<SimulationSettings>,
+    mut bodies: Query<(&Transform, &mut Velocity), With<Body>>,
+    mut commands: Commands,
+    mut materials: ResMut<Assets<ColorMaterial>>,
+    mut meshes: ResMut<Assets<Mesh>>,
+    mut reset_writer: EventWriter<ResetEvent>,
+    origin: Res<WorldOrigin>,
+) {
+    if timeline.start_requested {
+        timeline.start_requested = false;
+        timeline.start(clock.time);
+        if timeline.playing && timeline.script.as_ref().is_some_and(|script| script.reset) {
+            reset_writer.write(ResetEvent);
+        }
+        // the reset is handled next frame, the script starts counting from there
+        return;
+    }
+
+    for action in timeline.advance(clock.time) {
+        info!("Timeline at t = {:.3}: {}", timeline.script_time, action.label());
+        match action {
+            Action::SpawnCluster {
+                center,
+                radius,
+                bodies: count,
+                mass,
+                velocity,
+                seed,
+            } => {
+                let body = Body {
+                    mass,
+                    radius: settings.collision_radius(mass),
+                    hue: mass_to_hue(mass, settings.min_body_mass, settings.max_body_mass).clamp(0.0, 1.0),
+                };
+                for (position, velocity) in cluster_bodies(origin.local(center.into()), radius, count, velocity.into(), seed) {
+                    spawn_body(
+                        body,
+                        Transform::from_xyz(position.x, position.y, settings.z),
+                        Velocity(velocity.extend(0.0)),
+                        &settings,
+                        &mut commands,
+                        &mut materials,
+                        &mut meshes,
+                    );
+                }
+            }
+            Action::Impulse { center, radius, velocity } => {
+                let center = origin.local(center.into());
+                for (transform, mut body_velocity) in &mut bodies {
+                    if transform.translation.truncate().distance(center) <= radius {
+                        body_velocity.0 += Vec2::from(velocity).extend(0.0);
+                    }
+                }
+            }
+            Action::SetG(g) => settings.g = g,
+            Action::SetTimeScale(scale) => settings.time_scale = scale,
+            Action::SetDeltaT(dt) => settings.delta_t = dt,
+            Action::Pause => settings.paused = true,
+        }
+    }
+}
+
+pub fn timeline_window(mut contexts: EguiContexts, mut timeline: ResMut<Timeline>, ui_config: Res<UiConfig>) {
+    ui_config
+        .place(egui::Window::new("Timeline"), "Timeline")
+        .default_open(false)
+        .show(contexts.ctx_mut(), |ui| {
+            ui.horizontal(|ui| {
+                ui.add(egui::TextEdit::singleline(&mut timeline.path));
+                if ui.button("Load").clicked() {
+                    timeline.load();
+                }
+            });
+            if let Some(error) = &timeline.error {
+                ui.colored_label(egui::Color32::LIGHT_RED, error);
+            }
+            let Some(script) = &timeline.script else {
+                ui.label("No script loaded");
+                return;
+            };
+            let (count, reset) = (script.events.len(), script.reset);
+            ui.horizontal(|ui| {
+                let start = if reset { "Reset And Play" } else { "Play" };
+                if ui.button(start).clicked() {
+                    timeline.start_requested = true;
+                }
+                if ui.add_enabled(timeline.playing, egui::Button::new("Stop")).clicked() {
+                    timeline.playing = false;
+                }
+            });
+            ui.label(format!(
+                "Script time {:.3}, {} of {} events done",
+                timeline.script_time, timeline.next, count
+            ));
+
+            egui::ScrollArea::vertical().max_height(200.0).show(ui, |ui| {
+                let Some(script) = &timeline.script else {
+                    return;
+                };
+                for (i, event) in script.events.iter().enumerate() {
+                    let text = format!("t = {}: {}", event.at, event.action.label());
+                    if i < timeline.next {
+                        ui.weak(text);
+                    } else {
+                        ui.label(text);
+                    }
+                }
+            });
+        });
+}
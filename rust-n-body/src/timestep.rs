@@ -0,0 +1,47 @@
+use bevy::prelude::*;
+use crate::SimulationSettings;
+
+// dt is suggested at this fraction of the shortest timescale sqrt(ε / a_max)
+pub const SUGGESTED_DT_FRACTION: f32 = 0.1;
+
+const REFRESH_SECS: f32 = 1.0;
+
+// Stable dt worked out from the current state, refreshed once a second so the number
+// beside the dt slider stays readable
+#[derive(Resource, Default)]
+pub struct SuggestedDt {
+    // largest acceleration any body felt since the last refresh, filled in by the update system
+    pub max_accel: f32,
+    pub dt: Option<f32>,
+    since_refresh: f32,
+}
+
+impl SuggestedDt {
+    pub fn record(&mut self, accel: f32) {
+        if accel.is_finite() {
+            self.max_accel = self.max_accel.max(accel);
+        }
+    }
+}
+
+// A body at the largest acceleration crosses one softening length in about sqrt(ε / a),
+// a small fraction of that keeps even the closest encounters resolved
+pub fn suggested_dt(softening: f32, max_accel: f32) -> Option<f32> {
+    if max_accel <= 0.0 || softening <= 0.0 {
+        return None;
+    }
+    Some(SUGGESTED_DT_FRACTION * (softening / max_accel).sqrt())
+}
+
+pub fn suggest_dt(time: Res<Time>, settings: Res<SimulationSettings>, mut suggestion: ResMut<SuggestedDt>) {
+    suggestion.since_refresh += time.delta_secs();
+    if suggestion.since_refresh < REFRESH_SECS {
+        return;
+    }
+    suggestion.since_refresh = 0.0;
+    // keep the last one while paused, nothing was measured
+    if suggestion.max_accel > 0.0 {
+        suggestion.dt = suggested_dt(settings.softening, suggestion.max_accel);
+    }
+    suggestion.max_accel = 0.0;
+}
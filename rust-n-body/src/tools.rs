@@ -0,0 +1,421 @@
+use bevy::input::mouse::AccumulatedMouseScroll;
+use bevy::prelude::*;
+use bevy_egui::EguiContexts;
+use crate::bhtree::{Quad, Quadtree};
+use crate::clock::SimulationClock;
+use crate::force::push_mass;
+use crate::lifetime::{Lifetimes, SpawnSource};
+use crate::orbital_mechanics::periapsis_state;
+use crate::selection::{Selected, cursor_world_pos};
+use crate::undo::{Snapshot, UndoBodies, UndoHistory};
+use crate::{Body, MainCamera, SimulationSettings, Velocity, mass_to_hue, spawn_body};
+
+// What the mouse does in the simulation view
+#[derive(Resource, Default, Clone, Copy, PartialEq, Eq, Debug)]
+pub enum ActiveTool {
+    #[default]
+    Select,
+    Attractor,
+    Slingshot,
+    Satellite,
+    Explosion,
+}
+
+// Temporary point mass at the cursor. Left button attracts, right button repels.
+#[derive(Resource)]
+pub struct Attractor {
+    pub pos: Option<Vec2>,
+    pub mass: f32,
+    pub repel: bool,
+}
+
+impl Default for Attractor {
+    fn default() -> Self {
+        Attractor {
+            pos: None,
+            mass: 50000.0,
+            repel: false,
+        }
+    }
+}
+
+impl Attractor {
+    // Mass with the sign flipped when repelling, so it can go straight into the force calculation
+    pub fn signed_mass(&self) -> f32 {
+        if self.repel { -self.mass } else { self.mass }
+    }
+}
+
+pub const ATTRACTOR_MIN_MASS: f32 = 100.0;
+pub const ATTRACTOR_MAX_MASS: f32 = 10_000_000.0;
+
+pub fn attractor_input(
+    mut contexts: EguiContexts,
+    tool: Res<ActiveTool>,
+    buttons: Res<ButtonInput<MouseButton>>,
+    scroll: Res<AccumulatedMouseScroll>,
+    windows: Query<&Window>,
+    cameras: Query<(&Camera, &GlobalTransform), With<MainCamera>>,
+    mut attractor: ResMut<Attractor>,
+) {
+    if *tool != ActiveTool::Attractor || contexts.ctx_mut().wants_pointer_input() {
+        attractor.pos = None;
+        return;
+    }
+
+    // Each scroll notch changes the mass by 10%
+    if scroll.delta.y != 0.0 {
+        attractor.mass = (attractor.mass * 1.1f32.powf(scroll.delta.y))
+            .clamp(ATTRACTOR_MIN_MASS, ATTRACTOR_MAX_MASS);
+    }
+
+    let left = buttons.pressed(MouseButton::Left);
+    let right = buttons.pressed(MouseButton::Right);
+    if !left && !right {
+        attractor.pos = None;
+        return;
+    }
+
+    attractor.repel = right && !left;
+    attractor.pos = cursor_world_pos(&windows, &cameras);
+}
+
+pub fn draw_attractor(mut gizmos: Gizmos, attractor: Res<Attractor>) {
+    let Some(pos) = attractor.pos else {
+        return;
+    };
+    let color = if attractor.repel {
+        Color::linear_rgba(0.3, 0.3, 1.0, 0.8)
+    } else {
+        Color::linear_rgba(1.0, 0.3, 0.3, 0.8)
+    };
+    // Bigger ring for a heavier attractor, grows slowly so it stays usable at high masses
+    gizmos.circle_2d(Isometry2d::from_translation(pos), attractor.mass.log10() * 4.0, color);
+}
+
+// Launches a stream of small bodies. Press to set the launch point, drag to aim, hold to keep firing.
+#[derive(Resource)]
+pub struct Slingshot {
+    pub origin: Option<Vec2>,
+    // bodies per second
+    pub rate: f32,
+    pub speed: f32,
+    pub mass: f32,
+    // time since the last body was fired
+    elapsed: f32,
+}
+
+impl Default for Slingshot {
+    fn default() -> Self {
+        Slingshot {
+            origin: None,
+            rate: 20.0,
+            speed: 300.0,
+            mass: 5.0,
+            elapsed: 0.0,
+        }
+    }
+}
+
+pub fn slingshot_spawner(
+    mut contexts: EguiContexts,
+    tool: Res<ActiveTool>,
+    time: Res<Time>,
+    buttons: Res<ButtonInput<MouseButton>>,
+    windows: Query<&Window>,
+    cameras: Query<(&Camera, &GlobalTransform), With<MainCamera>>,
+    mut slingshot: ResMut<Slingshot>,
+    mut commands: Commands,
+    mut materials: ResMut<Assets<ColorMaterial>>,
+    mut meshes: ResMut<Assets<Mesh>>,
+    settings: Res<SimulationSettings>,
+    lifetimes: Res<Lifetimes>,
+) {
+    if *tool != ActiveTool::Slingshot || !buttons.pressed(MouseButton::Left) {
+        slingshot.origin = None;
+        return;
+    }
+    let Some(cursor) = cursor_world_pos(&windows, &cameras) else {
+        return;
+    };
+
+    if buttons.just_pressed(MouseButton::Left) {
+        if contexts.ctx_mut().wants_pointer_input() {
+            return;
+        }
+        slingshot.origin = Some(cursor);
+        slingshot.elapsed = 0.0;
+    }
+    let Some(origin) = slingshot.origin else {
+        return;
+    };
+
+    // Nothing to fire until the user has dragged out a direction
+    let Some(dir) = (cursor - origin).try_normalize() else {
+        return;
+    };
+
+    slingshot.elapsed += time.delta_secs();
+    let interval = 1.0 / slingshot.rate;
+    while slingshot.elapsed >= interval {
+        slingshot.elapsed -= interval;
+        // Bodies fired earlier in this frame have already travelled a bit,
+        // this also keeps them from being spawned right on top of each other
+        let pos = origin + dir * slingshot.speed * slingshot.elapsed;
+
+        let body = Body {
+            mass: slingshot.mass,
+            radius: settings.collision_radius(slingshot.mass),
+            hue: mass_to_hue(slingshot.mass, settings.min_body_mass, settings.max_body_mass).clamp(0.0, 1.0),
+        };
+        let entity = spawn_body(
+            body,
+            Transform::from_xyz(pos.x, pos.y, settings.z),
+            Velocity((dir * slingshot.speed).extend(0.0)),
+            &settings,
+            &mut commands,
+            &mut materials,
+            &mut meshes,
+        );
+        lifetimes.apply(SpawnSource::Slingshot, &mut commands, entity);
+    }
+}
+
+pub fn draw_slingshot(
+    mut gizmos: Gizmos,
+    slingshot: Res<Slingshot>,
+    windows: Query<&Window>,
+    cameras: Query<(&Camera, &GlobalTransform), With<MainCamera>>,
+) {
+    let (Some(origin), Some(cursor)) = (slingshot.origin, cursor_world_pos(&windows, &cameras)) else {
+        return;
+    };
+    gizmos.arrow_2d(origin, cursor, Color::linear_rgba(1.0, 1.0, 0.3, 0.8));
+}
+
+// Puts a new body on a Kepler orbit around the selected body. The click sets where periapsis points.
+#[derive(Resource)]
+pub struct Satellite {
+    pub semi_major_axis: f32,
+    pub eccentricity: f32,
+    pub mass: f32,
+    pub retrograde: bool,
+}
+
+impl Default for Satellite {
+    fn default() -> Self {
+        Satellite {
+            semi_major_axis: 100.0,
+            eccentricity: 0.0,
+            mass: 5.0,
+            retrograde: false,
+        }
+    }
+}
+
+pub fn satellite_spawner(
+    mut contexts: EguiContexts,
+    tool: Res<ActiveTool>,
+    buttons: Res<ButtonInput<MouseButton>>,
+    windows: Query<&Window>,
+    cameras: Query<(&Camera, &GlobalTransform), With<MainCamera>>,
+    satellite: Res<Satellite>,
+    primary: Query<(&Transform, &Velocity, &Body), With<Selected>>,
+    mut commands: Commands,
+    mut materials: ResMut<Assets<ColorMaterial>>,
+    mut meshes: ResMut<Assets<Mesh>>,
+    settings: Res<SimulationSettings>,
+    lifetimes: Res<Lifetimes>,
+) {
+    if *tool != ActiveTool::Satellite || !buttons.just_pressed(MouseButton::Left) {
+        return;
+    }
+    if contexts.ctx_mut().wants_pointer_input() {
+        return;
+    }
+    let Ok((primary_transform, primary_velocity, primary_body)) = primary.single() else {
+        return;
+    };
+    let Some(cursor) = cursor_world_pos(&windows, &cameras) else {
+        return;
+    };
+    let primary_pos = primary_transform.translation.truncate();
+    let Some(dir) = (cursor - primary_pos).try_normalize() else {
+        return;
+    };
+
+    let (rel_pos, rel_vel) = periapsis_state(
+        settings.g * (primary_body.mass + satellite.mass),
+        satellite.semi_major_axis,
+        satellite.eccentricity,
+        dir,
+        satellite.retrograde,
+    );
+    let pos = primary_pos + rel_pos;
+    let body = Body {
+        mass: satellite.mass,
+        radius: settings.collision_radius(satellite.mass),
+        hue: mass_to_hue(satellite.mass, settings.min_body_mass, settings.max_body_mass).clamp(0.0, 1.0),
+    };
+    let entity = spawn_body(
+        body,
+        Transform::from_xyz(pos.x, pos.y, settings.z),
+        Velocity(primary_velocity.0 + rel_vel.extend(0.0)),
+        &settings,
+        &mut commands,
+        &mut materials,
+        &mut meshes,
+    );
+    lifetimes.apply(SpawnSource::Satellite, &mut commands, entity);
+}
+
+// Preview of the orbit the next satellite would be put on
+pub fn draw_satellite(
+    mut gizmos: Gizmos,
+    tool: Res<ActiveTool>,
+    satellite: Res<Satellite>,
+    primary: Query<&Transform, With<Selected>>,
+    windows: Query<&Window>,
+    cameras: Query<(&Camera, &GlobalTransform), With<MainCamera>>,
+) {
+    if *tool != ActiveTool::Satellite {
+        return;
+    }
+    let (Ok(primary), Some(cursor)) = (primary.single(), cursor_world_pos(&windows, &cameras)) else {
+        return;
+    };
+    let focus = primary.translation.truncate();
+    let Some(dir) = (cursor - focus).try_normalize() else {
+        return;
+    };
+    let (a, e) = (satellite.semi_major_axis, satellite.eccentricity);
+    // the primary sits in a focus, the center is a * e behind it
+    let center = focus - dir * a * e;
+    gizmos.ellipse_2d(
+        Isometry2d::new(center, Rot2::radians(dir.to_angle())),
+        Vec2::new(a, a * (1.0 - e * e).sqrt()),
+        Color::linear_rgba(0.3, 0.8, 1.0, 0.6),
+    );
+}
+
+// How the explosion's kick weakens towards the edge of its radius
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Falloff {
+    Constant,
+    Linear,
+    Quadratic,
+}
+
+impl Falloff {
+    pub const ALL: [Falloff; 3] = [Falloff::Constant, Falloff::Linear, Falloff::Quadratic];
+
+    // Share of the full strength at the given fraction of the radius, 1 at the center and 0 past the edge
+    pub fn factor(self, fraction: f32) -> f32 {
+        if fraction > 1.0 {
+            return 0.0;
+        }
+        match self {
+            Falloff::Constant => 1.0,
+            Falloff::Linear => 1.0 - fraction,
+            Falloff::Quadratic => (1.0 - fraction) * (1.0 - fraction),
+        }
+    }
+}
+
+// Radial impulse from the clicked point to every body within the radius
+#[derive(Resource)]
+pub struct Explosion {
+    // momentum handed to a body at the center, heavier bodies get a smaller velocity change
+    pub strength: f32,
+    pub radius: f32,
+    pub falloff: Falloff,
+}
+
+impl Default for Explosion {
+    fn default() -> Self {
+        Explosion {
+            strength: 5000.0,
+            radius: 100.0,
+            falloff: Falloff::Linear,
+        }
+    }
+}
+
+impl Explosion {
+    // Velocity change of a body of the given mass at pos. A body right on the center has no direction
+    // to be pushed in and is left alone.
+    pub fn kick(&self, center: Vec2, pos: Vec2, mass: f32) -> Vec2 {
+        let offset = pos - center;
+        let Some(dir) = offset.try_normalize() else {
+            return Vec2::ZERO;
+        };
+        let factor = self.falloff.factor(offset.length() / self.radius);
+        dir * self.strength * factor / push_mass(mass)
+    }
+}
+
+pub fn explosion_input(
+    mut contexts: EguiContexts,
+    tool: Res<ActiveTool>,
+    buttons: Res<ButtonInput<MouseButton>>,
+    windows: Query<&Window>,
+    cameras: Query<(&Camera, &GlobalTransform), With<MainCamera>>,
+    explosion: Res<Explosion>,
+    mut bodies: ParamSet<(Query<(Entity, &Transform, &Body, &mut Velocity)>, UndoBodies)>,
+    (mut history, clock): (ResMut<UndoHistory>, Res<SimulationClock>),
+) {
+    if *tool != ActiveTool::Explosion || !buttons.just_pressed(MouseButton::Left) {
+        return;
+    }
+    if contexts.ctx_mut().wants_pointer_input() {
+        return;
+    }
+    let Some(center) = cursor_world_pos(&windows, &cameras) else {
+        return;
+    };
+
+    // Tree of the current positions, so only the quads around the click are looked at
+    let positions: Vec<Vec2> = bodies.p0().iter().map(|(_e, t, _b, _v)| t.translation.truncate()).collect();
+    if positions.is_empty() {
+        return;
+    }
+    let mut tree = Quadtree::new(Quad::new_containing(&positions));
+    for (entity, transform, body, _velocity) in &bodies.p0() {
+        tree.insert(entity, *transform, *body);
+    }
+
+    let kicks: Vec<(Entity, Vec2)> = tree
+        .query_circle(center, explosion.radius)
+        .map(|(entity, transform, body)| (entity, explosion.kick(center, transform.translation.truncate(), body.mass)))
+        .collect();
+    if kicks.is_empty() {
+        return;
+    }
+    history.record(Snapshot::capture("Explosion", &bodies.p1(), &clock));
+    let mut kicked = bodies.p0();
+    for (entity, kick) in kicks {
+        if let Ok((_e, _t, _b, mut velocity)) = kicked.get_mut(entity) {
+            velocity.0 += kick.extend(0.0);
+        }
+    }
+}
+
+pub fn draw_explosion(
+    mut gizmos: Gizmos,
+    tool: Res<ActiveTool>,
+    explosion: Res<Explosion>,
+    windows: Query<&Window>,
+    cameras: Query<(&Camera, &GlobalTransform), With<MainCamera>>,
+) {
+    if *tool != ActiveTool::Explosion {
+        return;
+    }
+    let Some(cursor) = cursor_world_pos(&windows, &cameras) else {
+        return;
+    };
+    gizmos.circle_2d(
+        Isometry2d::from_translation(cursor),
+        explosion.radius,
+        Color::linear_rgba(1.0, 0.6, 0.2, 0.6),
+    );
+}
@@ -0,0 +1,176 @@
+use bevy::math::DVec2;
+use bevy::prelude::*;
+use std::fs::File;
+use std::io::{BufWriter, Write};
+use crate::clock::SimulationClock;
+use crate::guard::Quarantined;
+use crate::lagrangian::LagrangianRadii;
+use crate::origin::WorldOrigin;
+use crate::selection::{Selected, SelectedBreakdown};
+use crate::{Body, SimulationSettings, Velocity};
+
+// Per-step trace written as CSV, so two runs can be diffed line by line to find the first step they diverge.
+// Floats are written with {:?}, which prints the shortest string that parses back to the exact same value.
+// The first row is the state the trace started from, after that update measures a row after every step
+// it takes, however many a frame has, and they are written out at the end of the frame. Positions are in
+// the coordinates the run started in, so moving the world origin doesn't show up as a jump.
+#[derive(Resource, Default)]
+pub struct TraceLog {
+    writer: Option<BufWriter<File>>,
+    // the steps of this frame, waiting to be written
+    rows: Vec<TraceRow>,
+}
+
+impl TraceLog {
+    // Steps are only measured once the file is open and the row they follow on from is written
+    pub fn recording(&self) -> bool {
+        self.writer.is_some()
+    }
+
+    pub fn record(&mut self, row: TraceRow) {
+        self.rows.push(row);
+    }
+}
+
+// Everything in a row but the Lagrangian radii, measured right after the step
+pub struct TraceRow {
+    step: u64,
+    delta_t: f32,
+    n_bodies: usize,
+    kinetic_energy: f64,
+    momentum: DVec2,
+    com: DVec2,
+    // the selected body's entity index, position, velocity and acceleration
+    body: Option<(u32, DVec2, Vec2, Vec2)>,
+}
+
+impl TraceRow {
+    // Takes every body's mass, position in the starting coordinates and velocity.
+    // Sums are done in f64 so the trace itself does not add rounding noise.
+    pub fn measure(
+        step: u64,
+        delta_t: f32,
+        bodies: impl Iterator<Item = (f32, DVec2, Vec2)>,
+        body: Option<(u32, DVec2, Vec2, Vec2)>,
+    ) -> TraceRow {
+        let mut n_bodies = 0;
+        let mut kinetic_energy = 0.0f64;
+        let mut momentum = DVec2::ZERO;
+        let mut mass_pos = DVec2::ZERO;
+        let mut total_mass = 0.0f64;
+        for (mass, position, velocity) in bodies {
+            let m = mass as f64;
+            let velocity = velocity.as_dvec2();
+            n_bodies += 1;
+            kinetic_energy += 0.5 * m * velocity.length_squared();
+            momentum += m * velocity;
+            mass_pos += m * position;
+            total_mass += m;
+        }
+        let com = if total_mass > 0.0 { mass_pos / total_mass } else { DVec2::ZERO };
+        TraceRow {
+            step,
+            delta_t,
+            n_bodies,
+            kinetic_energy,
+            momentum,
+            com,
+            body,
+        }
+    }
+
+    fn write(&self, writer: &mut impl Write, lagrangian_columns: &str) {
+        // the body columns are left empty when nothing is selected
+        let body_columns = match self.body {
+            Some((index, position, velocity, accel)) => format!(
+                "{},{:?},{:?},{:?},{:?},{:?},{:?}",
+                index, position.x, position.y, velocity.x, velocity.y, accel.x, accel.y
+            ),
+            None => ",,,,,,".to_string(),
+        };
+        let _ = writeln!(
+            writer,
+            "{},{:?},{},{:?},{:?},{:?},{:?},{:?},{},{}",
+            self.step,
+            self.delta_t,
+            self.n_bodies,
+            self.kinetic_energy,
+            self.momentum.x,
+            self.momentum.y,
+            self.com.x,
+            self.com.y,
+            lagrangian_columns,
+            body_columns
+        );
+    }
+}
+
+// The Lagrangian radii are whatever was last measured by the end of the frame, empty while they aren't
+// tracked. delta_t is the dt of the step, which a faster speed stretches.
+const TRACE_HEADER: &str = "step,delta_t,n_bodies,kinetic_energy,momentum_x,momentum_y,com_x,com_y,\
+lagrangian_r10,lagrangian_r50,lagrangian_r90,body,pos_x,pos_y,vel_x,vel_y,accel_x,accel_y";
+
+pub fn trace_step(
+    mut trace: ResMut<TraceLog>,
+    mut settings: ResMut<SimulationSettings>,
+    clock: Res<SimulationClock>,
+    bodies: Query<(&Transform, &Velocity, &Body), Without<Quarantined>>,
+    selected: Query<(Entity, &Transform, &Velocity), With<Selected>>,
+    selected_breakdown: Res<SelectedBreakdown>,
+    lagrangian: Res<LagrangianRadii>,
+    origin: Res<WorldOrigin>,
+) {
+    if !settings.trace_enabled {
+        if let Some(mut writer) = trace.writer.take() {
+            let _ = writer.flush();
+        }
+        trace.rows.clear();
+        return;
+    }
+
+    let lagrangian_columns = match lagrangian.radii.as_slice() {
+        [r10, r50, r90] => format!("{:?},{:?},{:?}", r10, r50, r90),
+        _ => ",,".to_string(),
+    };
+
+    if trace.writer.is_none() {
+        let mut writer = match File::create(&settings.trace_path) {
+            Ok(file) => BufWriter::new(file),
+            Err(err) => {
+                error!("Could not open trace file {}: {}", settings.trace_path, err);
+                settings.trace_enabled = false;
+                return;
+            }
+        };
+        let _ = writeln!(writer, "{}", TRACE_HEADER);
+
+        // The state the steps after it start from, the chosen body is whichever one is selected
+        let body = match (selected.single(), &selected_breakdown.breakdown) {
+            (Ok((entity, transform, velocity)), Some(breakdown)) => {
+                // the tree returns the velocity kick for the step, turn it back into an acceleration
+                let accel = breakdown.total / selected_breakdown.dt;
+                let position = origin.original(transform.translation.truncate());
+                Some((entity.index(), position, velocity.0.truncate(), accel.truncate()))
+            }
+            _ => None,
+        };
+        let start = TraceRow::measure(
+            clock.steps,
+            clock.dt,
+            bodies.iter().map(|(transform, velocity, body)| {
+                (body.mass, origin.original(transform.translation.truncate()), velocity.0.truncate())
+            }),
+            body,
+        );
+        start.write(&mut writer, &lagrangian_columns);
+        trace.writer = Some(writer);
+        return;
+    }
+
+    let TraceLog { writer, rows } = &mut *trace;
+    if let Some(writer) = writer.as_mut() {
+        for row in rows.drain(..) {
+            row.write(writer, &lagrangian_columns);
+        }
+    }
+}
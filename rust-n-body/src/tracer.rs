@@ -0,0 +1,95 @@
+use bevy::prelude::*;
+use crate::fade::Fade;
+use crate::lifetime::{Lifetimes, SpawnSource};
+use crate::{Body, SimulationSettings, Velocity};
+
+// Marker for tracer particles.
+// Tracers feel gravity from the other bodies but are never inserted into the quadtree,
+// so they show the flow field without pulling on anything themselves.
+#[derive(Component)]
+pub struct Massless;
+
+#[derive(Event)]
+pub struct SpawnTracersEvent;
+
+const TRACER_RADIUS: f32 = 1.0;
+
+pub fn tracer_handler(
+    mut tracer_event: EventReader<SpawnTracersEvent>,
+    mut commands: Commands,
+    mut materials: ResMut<Assets<ColorMaterial>>,
+    mut meshes: ResMut<Assets<Mesh>>,
+    settings: Res<SimulationSettings>,
+    lifetimes: Res<Lifetimes>,
+) {
+    if tracer_event.is_empty() {
+        return;
+    }
+    tracer_event.clear();
+
+    // All tracers share one mesh and material, they look the same anyway
+    let mesh = tracer_mesh(&mut meshes);
+    let material = materials.add(ColorMaterial::from_color(Color::WHITE));
+
+    for pos in tracer_positions(&settings) {
+        let entity = commands.spawn(tracer_bundle(&mesh, &material, pos, Vec2::ZERO, settings.z)).id();
+        lifetimes.apply(SpawnSource::Tracers, &mut commands, entity);
+    }
+}
+
+// Everything a tracer is made of, sharing the mesh and material with the others
+pub fn tracer_bundle(
+    mesh: &Handle<Mesh>,
+    material: &Handle<ColorMaterial>,
+    pos: Vec2,
+    velocity: Vec2,
+    z: f32,
+) -> impl Bundle {
+    (
+        Mesh2d(mesh.clone()),
+        MeshMaterial2d(material.clone()),
+        Body {
+            mass: 0.0,
+            radius: TRACER_RADIUS,
+            hue: 0.0,
+        },
+        Massless,
+        Transform::from_xyz(pos.x, pos.y, z).with_scale(Vec3::splat(0.01)),
+        Velocity(velocity.extend(0.0)),
+        Fade::fade_in(),
+    )
+}
+
+pub fn tracer_mesh(meshes: &mut Assets<Mesh>) -> Handle<Mesh> {
+    meshes.add(Circle::new(TRACER_RADIUS))
+}
+
+// Tracers are laid out either on a square grid covering the spawn area, or on a ring inside it
+pub fn tracer_positions(settings: &SimulationSettings) -> Vec<Vec2> {
+    let n = settings.n_tracers as usize;
+    let min = *settings.spawn_area.start();
+    let max = *settings.spawn_area.end();
+
+    if n == 0 {
+        return Vec::new();
+    }
+
+    if settings.tracer_ring {
+        let radius = (max - min) * 0.25;
+        let center = Vec2::splat((min + max) * 0.5);
+        (0..n)
+            .map(|i| {
+                let angle = i as f32 / n as f32 * std::f32::consts::TAU;
+                center + Vec2::from_angle(angle) * radius
+            })
+            .collect()
+    } else {
+        // round up so the grid has room for n_tracers points, the top row is left short
+        let side = (n as f32).sqrt().ceil() as usize;
+        let step = if side > 1 { (max - min) / (side - 1) as f32 } else { 0.0 };
+        (0..side * side)
+            .map(|i| Vec2::new(min + (i % side) as f32 * step, min + (i / side) as f32 * step))
+            .take(n)
+            .collect()
+    }
+}
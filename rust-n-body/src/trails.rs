@@ -0,0 +1,212 @@
+use bevy::asset::RenderAssetUsages;
+use bevy::gizmos::config::{DefaultGizmoConfigGroup, GizmoConfigStore};
+use bevy::prelude::*;
+use bevy::render::camera::RenderTarget;
+use bevy::render::render_resource::{Extent3d, TextureDimension, TextureFormat, TextureUsages};
+use bevy::render::view::RenderLayers;
+use bevy::render::view::screenshot::{Screenshot, save_to_disk};
+use bevy::window::PrimaryWindow;
+use bevy_egui::{EguiContexts, egui};
+use crate::ui_config::UiConfig;
+use crate::MainCamera;
+
+// The black quad that dims the trail image a little every frame, only the trail camera sees it
+const FADE_LAYER: usize = 1;
+// The trail image shown behind the bodies, only the main camera sees it
+pub const DISPLAY_LAYER: usize = 2;
+
+// Renders the bodies into an image that is never cleared
+#[derive(Component)]
+pub struct TrailCamera;
+
+#[derive(Component)]
+pub struct TrailFade;
+
+#[derive(Component)]
+pub struct TrailDisplay;
+
+// Trails drawn on the GPU: a second camera draws the bodies into an image that keeps what was there
+// before and is dimmed a little each frame. Costs one more draw of the bodies however long the trails
+// are, where a polyline per body would be rebuilt on the CPU every frame.
+#[derive(Resource)]
+pub struct Trails {
+    pub enabled: bool,
+    // how fast the trails fade, per second: after 1 / fade_rate seconds they are down to about a third
+    pub fade_rate: f32,
+    // never fades, like a camera with the shutter left open, for star trail pictures of the dynamics
+    pub long_exposure: bool,
+    pub clear_requested: bool,
+    pub save_requested: bool,
+    pub save_path: String,
+    image: Option<Handle<Image>>,
+    size: UVec2,
+}
+
+impl Default for Trails {
+    fn default() -> Self {
+        Trails {
+            enabled: false,
+            fade_rate: 1.0,
+            long_exposure: false,
+            clear_requested: false,
+            save_requested: false,
+            save_path: "exposure.png".to_string(),
+            image: None,
+            size: UVec2::ZERO,
+        }
+    }
+}
+
+impl Trails {
+    // The image on the GPU, four bytes a pixel
+    pub fn memory_bytes(&self) -> usize {
+        if self.image.is_some() { self.size.x as usize * self.size.y as usize * 4 } else { 0 }
+    }
+}
+
+// Opacity of the fade quad for one frame, so the trails decay at the same rate whatever the frame rate
+pub fn fade_alpha(fade_rate: f32, dt: f32) -> f32 {
+    1.0 - (-fade_rate.max(0.0) * dt.max(0.0)).exp()
+}
+
+fn trail_image(size: UVec2) -> Image {
+    let mut image = Image::new_fill(
+        Extent3d {
+            width: size.x,
+            height: size.y,
+            depth_or_array_layers: 1,
+        },
+        TextureDimension::D2,
+        &[0, 0, 0, 255],
+        TextureFormat::Bgra8UnormSrgb,
+        RenderAssetUsages::default(),
+    );
+    image.texture_descriptor.usage =
+        TextureUsages::TEXTURE_BINDING | TextureUsages::COPY_DST | TextureUsages::RENDER_ATTACHMENT;
+    image
+}
+
+pub fn update_trails(
+    time: Res<Time>,
+    mut trails: ResMut<Trails>,
+    windows: Query<&Window, With<PrimaryWindow>>,
+    main_camera: Query<(&Transform, &Projection), With<MainCamera>>,
+    mut trail_camera: Query<(&mut Transform, &mut Projection), (With<TrailCamera>, Without<MainCamera>)>,
+    mut fade: Query<(&mut Transform, &mut Sprite), (With<TrailFade>, Without<TrailCamera>, Without<MainCamera>)>,
+    mut display: Query<
+        (&mut Transform, &mut Sprite),
+        (With<TrailDisplay>, Without<TrailFade>, Without<TrailCamera>, Without<MainCamera>),
+    >,
+    parts: Query<Entity, Or<(With<TrailCamera>, With<TrailFade>, With<TrailDisplay>)>>,
+    mut images: ResMut<Assets<Image>>,
+    mut gizmo_config: ResMut<GizmoConfigStore>,
+    mut commands: Commands,
+) {
+    let size = windows
+        .single()
+        .map(|window| UVec2::new(window.physical_width(), window.physical_height()))
+        .unwrap_or(UVec2::ZERO);
+
+    // starting over on a resize, the old image has the wrong shape
+    if !trails.enabled || trails.clear_requested || size != trails.size || size.min_element() == 0 {
+        trails.clear_requested = false;
+        if let Some(image) = trails.image.take() {
+            images.remove(&image);
+            for entity in &parts {
+                commands.entity(entity).despawn();
+            }
+            gizmo_config.config_mut::<DefaultGizmoConfigGroup>().0.render_layers = RenderLayers::default();
+        }
+        trails.size = size;
+        if !trails.enabled || size.min_element() == 0 {
+            return;
+        }
+    }
+
+    if trails.image.is_none() {
+        let image = images.add(trail_image(size));
+        commands.spawn((
+            Camera2d,
+            Camera {
+                // before the main camera, which shows what it drew
+                order: -1,
+                target: RenderTarget::Image(image.clone().into()),
+                clear_color: ClearColorConfig::None,
+                ..default()
+            },
+            Msaa::Off,
+            RenderLayers::from_layers(&[0, FADE_LAYER]),
+            TrailCamera,
+        ));
+        commands.spawn((Sprite::from_color(Color::BLACK, Vec2::ONE), RenderLayers::layer(FADE_LAYER), TrailFade));
+        commands.spawn((Sprite::from_image(image.clone()), RenderLayers::layer(DISPLAY_LAYER), TrailDisplay));
+        // gizmos would leave trails of their own
+        gizmo_config.config_mut::<DefaultGizmoConfigGroup>().0.render_layers = RenderLayers::layer(DISPLAY_LAYER);
+        trails.image = Some(image);
+        return;
+    }
+
+    if trails.save_requested {
+        trails.save_requested = false;
+        if let Some(image) = trails.image.clone() {
+            info!("Saving the trail image to {}", trails.save_path);
+            commands.spawn(Screenshot::image(image)).observe(save_to_disk(trails.save_path.clone()));
+        }
+    }
+
+    let Ok((camera, Projection::Orthographic(ortho))) = main_camera.single() else {
+        return;
+    };
+    let view = ortho.area.size();
+    let center = camera.translation.truncate();
+
+    if let Ok((mut transform, mut projection)) = trail_camera.single_mut() {
+        *transform = *camera;
+        *projection = Projection::Orthographic(ortho.clone());
+    }
+    // under the bodies, which are drawn at z 10
+    if let Ok((mut transform, mut sprite)) = fade.single_mut() {
+        *transform = Transform::from_translation(center.extend(0.0)).with_rotation(camera.rotation);
+        sprite.custom_size = Some(view * 2.0);
+        let alpha = if trails.long_exposure {
+            0.0
+        } else {
+            fade_alpha(trails.fade_rate, time.delta_secs())
+        };
+        sprite.color = Color::BLACK.with_alpha(alpha);
+    }
+    if let Ok((mut transform, mut sprite)) = display.single_mut() {
+        *transform = Transform::from_translation(center.extend(-100.0)).with_rotation(camera.rotation);
+        sprite.custom_size = Some(view);
+    }
+}
+
+pub fn trails_window(mut contexts: EguiContexts, mut trails: ResMut<Trails>, ui_config: Res<UiConfig>) {
+    ui_config
+        .place(egui::Window::new("Trails"), "Trails")
+        .default_open(false)
+        .show(contexts.ctx_mut(), |ui| {
+            ui.add(egui::Checkbox::new(&mut trails.enabled, "Draw Trails"));
+            // a new exposure starts from a clean image
+            if ui.add(egui::Checkbox::new(&mut trails.long_exposure, "Long Exposure")).changed() && trails.long_exposure {
+                trails.enabled = true;
+                trails.clear_requested = true;
+            }
+            ui.add_enabled(
+                !trails.long_exposure,
+                egui::Slider::new(&mut trails.fade_rate, 0.01..=20.0)
+                    .logarithmic(true)
+                    .text("Fade Rate (per second)"),
+            );
+            ui.horizontal(|ui| {
+                if ui.button("Clear").clicked() {
+                    trails.clear_requested = true;
+                }
+                if ui.add_enabled(trails.enabled, egui::Button::new("Save Image")).clicked() {
+                    trails.save_requested = true;
+                }
+                ui.add(egui::TextEdit::singleline(&mut trails.save_path));
+            });
+            ui.label("Trails are drawn where the bodies were on screen, moving the view smears them");
+        });
+}
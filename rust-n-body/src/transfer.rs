@@ -0,0 +1,228 @@
+use bevy::prelude::*;
+use bevy_egui::{EguiContexts, egui};
+use std::f32::consts::PI;
+use crate::clock::SimulationClock;
+use crate::controller::Controller;
+use crate::orbital_mechanics::find_primary;
+use crate::selection::Selected;
+use crate::ui_config::UiConfig;
+use crate::{Body, SimulationSettings, Velocity};
+
+// Points along the drawn half of the transfer ellipse
+const ELLIPSE_SEGMENTS: usize = 64;
+
+// The two burns of a Hohmann transfer between circular orbits, positive along the motion
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Hohmann {
+    pub dv1: f32,
+    pub dv2: f32,
+    // half the period of the transfer ellipse
+    pub transfer_time: f32,
+}
+
+// mu is G (M + m), r1 the current orbit's radius and r2 the target's
+pub fn hohmann(mu: f32, r1: f32, r2: f32) -> Hohmann {
+    let sum = r1 + r2;
+    Hohmann {
+        dv1: (mu / r1).sqrt() * ((2.0 * r2 / sum).sqrt() - 1.0),
+        dv2: (mu / r2).sqrt() * (1.0 - (2.0 * r1 / sum).sqrt()),
+        transfer_time: PI * (sum.powi(3) / (8.0 * mu)).sqrt(),
+    }
+}
+
+// A transfer turned into two finite burns at full thrust, each centered on the moment its impulse
+// would happen. The first one starts right away.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct TransferPlan {
+    pub hohmann: Hohmann,
+    pub max_accel: f32,
+    pub burn1_start: f64,
+    pub burn1_duration: f64,
+    pub burn2_start: f64,
+    pub burn2_duration: f64,
+}
+
+impl TransferPlan {
+    pub fn new(hohmann: Hohmann, max_accel: f32, now: f64) -> Self {
+        let burn1_duration = (hohmann.dv1.abs() / max_accel) as f64;
+        let burn2_duration = (hohmann.dv2.abs() / max_accel) as f64;
+        let second_impulse = now + burn1_duration / 2.0 + hohmann.transfer_time as f64;
+        TransferPlan {
+            hohmann,
+            max_accel,
+            burn1_start: now,
+            burn1_duration,
+            burn2_start: second_impulse - burn2_duration / 2.0,
+            burn2_duration,
+        }
+    }
+
+    // Thrust along the motion at this time, negative to slow down for a lower orbit
+    pub fn accel_at(&self, time: f64) -> f32 {
+        let burning = |start: f64, duration: f64| time >= start && time < start + duration;
+        if burning(self.burn1_start, self.burn1_duration) {
+            self.max_accel.copysign(self.hohmann.dv1)
+        } else if burning(self.burn2_start, self.burn2_duration) {
+            self.max_accel.copysign(self.hohmann.dv2)
+        } else {
+            0.0
+        }
+    }
+
+    pub fn end(&self) -> f64 {
+        self.burn2_start + self.burn2_duration
+    }
+}
+
+// A scheduled transfer and what is needed to draw it
+#[derive(Clone, Copy, Debug)]
+pub struct ScheduledTransfer {
+    pub body: Entity,
+    pub primary: Entity,
+    pub plan: TransferPlan,
+    pub r1: f32,
+    pub r2: f32,
+    // where the body left from, relative to the primary
+    pub departure: Vec2,
+    // 1 for counterclockwise orbits, -1 for clockwise
+    pub turn: f32,
+}
+
+impl ScheduledTransfer {
+    // The half of the ellipse the body coasts along, relative to the primary
+    pub fn ellipse_points(&self) -> Vec<Vec2> {
+        let a = (self.r1 + self.r2) / 2.0;
+        let e = (self.r2 - self.r1).abs() / (self.r1 + self.r2);
+        // going out the departure is the periapsis, coming in it is the apoapsis
+        let (periapsis, start) = if self.r2 >= self.r1 { (self.departure, 0.0) } else { (-self.departure, PI) };
+        (0..=ELLIPSE_SEGMENTS)
+            .map(|i| {
+                let theta = start + PI * i as f32 / ELLIPSE_SEGMENTS as f32;
+                let r = a * (1.0 - e * e) / (1.0 + e * theta.cos());
+                Vec2::from_angle(self.turn * theta).rotate(periapsis) * r
+            })
+            .collect()
+    }
+}
+
+#[derive(Resource)]
+pub struct TransferPlanner {
+    pub target_radius: f32,
+    pub max_accel: f32,
+    pub scheduled: Option<ScheduledTransfer>,
+}
+
+impl Default for TransferPlanner {
+    fn default() -> Self {
+        TransferPlanner {
+            target_radius: 500.0,
+            max_accel: 100.0,
+            scheduled: None,
+        }
+    }
+}
+
+// Hands the burns to the controller, which points them along the motion around the primary
+fn transfer_controller(primary: Entity, plan: TransferPlan) -> Controller {
+    Controller::new("Hohmann transfer", plan.max_accel, move |input| {
+        let Some(primary) = input.get(primary) else {
+            return Vec2::ZERO;
+        };
+        let accel = plan.accel_at(input.time);
+        if accel == 0.0 {
+            return Vec2::ZERO;
+        }
+        (input.body.velocity - primary.velocity).normalize_or_zero() * accel
+    })
+}
+
+pub fn transfer_window(
+    mut contexts: EguiContexts,
+    mut planner: ResMut<TransferPlanner>,
+    ui_config: Res<UiConfig>,
+    settings: Res<SimulationSettings>,
+    clock: Res<SimulationClock>,
+    selected: Query<Entity, With<Selected>>,
+    bodies: Query<(Entity, &Body, &Transform, &Velocity)>,
+    mut commands: Commands,
+) {
+    ui_config
+        .place(egui::Window::new("Transfer Planner"), "Transfer Planner")
+        .default_open(false)
+        .show(contexts.ctx_mut(), |ui| {
+            let Some((entity, body, transform, velocity)) =
+                selected.single().ok().and_then(|entity| bodies.get(entity).ok())
+            else {
+                ui.label("Select the body to move");
+                return;
+            };
+            let points: Vec<(Vec2, f32)> = bodies
+                .iter()
+                .map(|(_e, body, transform, _v)| (transform.translation.truncate(), body.mass))
+                .collect();
+            let entities: Vec<Entity> = bodies.iter().map(|(entity, ..)| entity).collect();
+            let Some(primary) = find_primary(transform.translation.truncate(), body.mass, &points) else {
+                ui.label("Nothing heavier to orbit");
+                return;
+            };
+            let (_, primary_body, primary_transform, primary_velocity) = bodies.get(entities[primary]).unwrap();
+            let rel_pos = (transform.translation - primary_transform.translation).truncate();
+            let rel_vel = (velocity.0 - primary_velocity.0).truncate();
+            let mu = settings.g * (primary_body.mass + body.mass);
+            let r1 = rel_pos.length();
+
+            let units = settings.units;
+            ui.label(format!("Orbiting a body of mass {} at {}", units.mass(primary_body.mass), units.length(r1)));
+            ui.label("Assumes a circular orbit around a primary that doesn't move much");
+            ui.add(egui::DragValue::new(&mut planner.target_radius).range(1.0..=1e6).prefix("Target Radius: "));
+            ui.add(egui::Slider::new(&mut planner.max_accel, 0.1..=1000.0).logarithmic(true).text("Max Thrust"));
+
+            let transfer = hohmann(mu, r1, planner.target_radius);
+            let plan = TransferPlan::new(transfer, planner.max_accel, clock.time);
+            ui.label(format!("First burn: delta-v {:.3} over {:.3}", transfer.dv1, plan.burn1_duration));
+            ui.label(format!(
+                "Second burn: delta-v {:.3} over {:.3}, {} later",
+                transfer.dv2,
+                plan.burn2_duration,
+                units.time(transfer.transfer_time as f64)
+            ));
+            if ui.button("Schedule Burns").clicked() {
+                commands.entity(entity).insert(transfer_controller(entities[primary], plan));
+                planner.scheduled = Some(ScheduledTransfer {
+                    body: entity,
+                    primary: entities[primary],
+                    plan,
+                    r1,
+                    r2: planner.target_radius,
+                    departure: rel_pos.normalize_or_zero(),
+                    turn: if rel_pos.perp_dot(rel_vel) >= 0.0 { 1.0 } else { -1.0 },
+                });
+            }
+        });
+}
+
+// The planned coast and the target orbit, until the second burn is done
+pub fn draw_transfer(
+    mut planner: ResMut<TransferPlanner>,
+    clock: Res<SimulationClock>,
+    transforms: Query<&Transform, With<Body>>,
+    mut gizmos: Gizmos,
+) {
+    let Some(scheduled) = planner.scheduled else {
+        return;
+    };
+    let Ok(primary) = transforms.get(scheduled.primary) else {
+        planner.scheduled = None;
+        return;
+    };
+    if clock.time > scheduled.plan.end() || !transforms.contains(scheduled.body) {
+        planner.scheduled = None;
+        return;
+    }
+    let center = primary.translation.truncate();
+    gizmos.circle_2d(Isometry2d::from_translation(center), scheduled.r2, Color::srgba(0.4, 0.8, 1.0, 0.4));
+    gizmos.linestrip_2d(
+        scheduled.ellipse_points().into_iter().map(|p| center + p),
+        Color::srgb(1.0, 0.8, 0.3),
+    );
+}
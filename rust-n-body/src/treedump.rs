@@ -0,0 +1,76 @@
+use bevy::prelude::*;
+use std::fs;
+use crate::bhtree::{NodeInfo, Quadtree};
+use crate::progress::json_number;
+
+// The quadtree written out as JSON on request, for looking at it in other tools or attaching it to a
+// report about a wrong acceleration. The update system writes it right after building the tree.
+#[derive(Resource)]
+pub struct TreeExport {
+    pub path: String,
+    pub requested: bool,
+}
+
+impl Default for TreeExport {
+    fn default() -> Self {
+        TreeExport {
+            path: "quadtree.json".to_string(),
+            requested: false,
+        }
+    }
+}
+
+fn vec_json(v: Vec2) -> String {
+    format!("[{}, {}]", json_number(v.x as f64), json_number(v.y as f64))
+}
+
+fn node_json(index: usize, node: &NodeInfo) -> String {
+    let entities: Vec<String> = node.entities.iter().map(|entity| entity.index().to_string()).collect();
+    format!(
+        "{{\"index\": {}, \"parent\": {}, \"depth\": {}, \"min\": {}, \"max\": {}, \"mass\": {}, \"com\": {}, \
+         \"bodies\": {}, \"leaf\": {}, \"entities\": [{}]}}",
+        index,
+        node.parent.map_or("null".to_string(), |parent| parent.to_string()),
+        node.depth,
+        vec_json(node.min),
+        vec_json(node.max),
+        json_number(node.mass as f64),
+        vec_json(node.com),
+        node.bodies,
+        node.leaf,
+        entities.join(", ")
+    )
+}
+
+// The nodes in the order Quadtree::nodes lists them, each with its index so parents can be looked up.
+// Leaves list the entity indices they hold.
+pub fn tree_json(tree: &Quadtree, time: f64, theta: f32, leaf_capacity: u32) -> String {
+    let nodes = tree.nodes();
+    let depth = nodes.iter().map(|node| node.depth).max().unwrap_or(0);
+    let lines: Vec<String> = nodes
+        .iter()
+        .enumerate()
+        .map(|(i, node)| format!("    {}", node_json(i, node)))
+        .collect();
+    format!(
+        "{{\n  \"time\": {},\n  \"theta\": {},\n  \"leaf_capacity\": {},\n  \"bodies\": {},\n  \"depth\": {},\n  \
+         \"nodes\": [\n{}\n  ]\n}}\n",
+        json_number(time),
+        json_number(theta as f64),
+        leaf_capacity,
+        tree.body_count(),
+        depth,
+        lines.join(",\n")
+    )
+}
+
+pub fn export_tree(export: &mut TreeExport, tree: &Quadtree, time: f64, theta: f32, leaf_capacity: u32) {
+    if !export.requested {
+        return;
+    }
+    export.requested = false;
+    match fs::write(&export.path, tree_json(tree, time, theta, leaf_capacity)) {
+        Ok(()) => info!("Wrote the quadtree to {}", export.path),
+        Err(err) => error!("Could not write the quadtree to {}: {}", export.path, err),
+    }
+}
@@ -0,0 +1,177 @@
+use bevy::platform::time::Instant;
+use bevy::prelude::*;
+use ratatui::crossterm::event::{self, Event, KeyCode, KeyEventKind};
+use ratatui::layout::{Constraint, Layout};
+use ratatui::widgets::Paragraph;
+use ratatui::{DefaultTerminal, Frame};
+use std::time::Duration;
+use crate::SimulationSettings;
+use crate::headless::HeadlessSim;
+use crate::validate::validate;
+
+// Fuller characters for more bodies in a cell
+const SHADES: &[u8] = b" .:-=+*#%@";
+// Terminal cells are about twice as tall as they are wide
+const CELL_ASPECT: f32 = 2.0;
+
+// How many bodies fall in each terminal cell, row by row from the top. `cell_width` is the
+// width of a cell in world units, the view is centered on `center`.
+pub fn density_grid(positions: &[Vec2], center: Vec2, cell_width: f32, cols: usize, rows: usize) -> Vec<u32> {
+    let mut grid = vec![0; cols * rows];
+    if cols == 0 || rows == 0 || cell_width <= 0.0 {
+        return grid;
+    }
+    let cell = Vec2::new(cell_width, cell_width * CELL_ASPECT);
+    // world y points up, rows count down
+    let top_left = center + Vec2::new(-(cols as f32), rows as f32) * cell / 2.0;
+    for position in positions {
+        let col = ((position.x - top_left.x) / cell.x).floor();
+        let row = ((top_left.y - position.y) / cell.y).floor();
+        if col >= 0.0 && row >= 0.0 && (col as usize) < cols && (row as usize) < rows {
+            grid[row as usize * cols + col as usize] += 1;
+        }
+    }
+    grid
+}
+
+// On a log scale, so a lone body still shows next to a dense core
+pub fn shade(count: u32, max: u32) -> char {
+    if count == 0 || max == 0 {
+        return ' ';
+    }
+    let fraction = (count as f32).ln_1p() / (max as f32).ln_1p();
+    let level = 1 + (fraction * (SHADES.len() - 2) as f32).round() as usize;
+    SHADES[level.min(SHADES.len() - 1)] as char
+}
+
+// How long a frame waits for a key before stepping on
+const FRAME: Duration = Duration::from_millis(33);
+const ZOOM_STEP: f32 = 1.25;
+// cells moved by one press of an arrow key
+const PAN_CELLS: f32 = 5.0;
+
+const USAGE: &str = "usage: rust-n-body --tui [--bodies <n>] [--seed <seed>] [--theta <theta>] [--dt <dt>] [--steps-per-frame <n>]
+Runs the simulation in the terminal, without collisions. Keys: q quit, space pause, + and - zoom, arrows pan, r reset.";
+
+fn parse<T: std::str::FromStr>(value: Option<&String>) -> Result<T, String> {
+    value.and_then(|v| v.parse().ok()).ok_or(USAGE.to_string())
+}
+
+struct View {
+    center: Vec2,
+    cell_width: f32,
+    paused: bool,
+    steps_per_sec: f32,
+}
+
+// The simulation drawn as characters in the terminal, for servers without a display
+pub fn run(args: &[String]) -> Result<(), String> {
+    let mut settings = SimulationSettings {
+        n_bodies: 2000,
+        ..Default::default()
+    };
+    let mut seed: u64 = 0;
+    let mut steps_per_frame: u32 = 1;
+
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--bodies" => settings.n_bodies = parse(args.next())?,
+            "--seed" => seed = parse(args.next())?,
+            "--theta" => settings.theta = parse(args.next())?,
+            "--dt" => settings.delta_t = parse(args.next())?,
+            "--steps-per-frame" => steps_per_frame = parse(args.next())?,
+            _ => return Err(USAGE.to_string()),
+        }
+    }
+    let warnings = validate(&mut settings);
+    if !warnings.is_empty() {
+        return Err(warnings.join(", "));
+    }
+
+    let mut terminal = ratatui::init();
+    let result = event_loop(&mut terminal, &settings, seed, steps_per_frame);
+    ratatui::restore();
+    result
+}
+
+fn event_loop(
+    terminal: &mut DefaultTerminal,
+    settings: &SimulationSettings,
+    seed: u64,
+    steps_per_frame: u32,
+) -> Result<(), String> {
+    let mut sim = HeadlessSim::new(settings, seed);
+    let size = terminal.size().map_err(|e| e.to_string())?;
+    // the spawn area and a margin across the width
+    let extent = settings.spawn_area.end() - settings.spawn_area.start();
+    let mut view = View {
+        center: Vec2::ZERO,
+        cell_width: extent * 1.5 / size.width.max(1) as f32,
+        paused: false,
+        steps_per_sec: 0.0,
+    };
+
+    loop {
+        if !view.paused {
+            let started = Instant::now();
+            for _ in 0..steps_per_frame {
+                sim.step(settings);
+            }
+            let secs = started.elapsed().as_secs_f32();
+            if secs > 0.0 {
+                view.steps_per_sec = steps_per_frame as f32 / secs;
+            }
+        }
+        terminal.draw(|frame| draw(frame, &sim, &view)).map_err(|e| e.to_string())?;
+
+        if !event::poll(FRAME).map_err(|e| e.to_string())? {
+            continue;
+        }
+        let Event::Key(key) = event::read().map_err(|e| e.to_string())? else {
+            continue;
+        };
+        if key.kind != KeyEventKind::Press {
+            continue;
+        }
+        let pan = view.cell_width * PAN_CELLS;
+        match key.code {
+            KeyCode::Char('q') | KeyCode::Esc => return Ok(()),
+            KeyCode::Char(' ') => view.paused = !view.paused,
+            KeyCode::Char('+') | KeyCode::Char('=') => view.cell_width /= ZOOM_STEP,
+            KeyCode::Char('-') => view.cell_width *= ZOOM_STEP,
+            KeyCode::Left => view.center.x -= pan,
+            KeyCode::Right => view.center.x += pan,
+            KeyCode::Up => view.center.y += pan,
+            KeyCode::Down => view.center.y -= pan,
+            KeyCode::Char('r') => sim = HeadlessSim::new(settings, seed),
+            _ => {}
+        }
+    }
+}
+
+fn draw(frame: &mut Frame, sim: &HeadlessSim, view: &View) {
+    let [map, status] = Layout::vertical([Constraint::Min(1), Constraint::Length(1)]).areas(frame.area());
+    let (cols, rows) = (map.width as usize, map.height as usize);
+    let positions: Vec<Vec2> = sim.transforms.iter().map(|t| t.translation.truncate()).collect();
+    let grid = density_grid(&positions, view.center, view.cell_width, cols, rows);
+    let max = grid.iter().copied().max().unwrap_or(0);
+    let lines: Vec<String> = grid
+        .chunks(cols.max(1))
+        .map(|row| row.iter().map(|count| shade(*count, max)).collect())
+        .collect();
+    frame.render_widget(Paragraph::new(lines.join("\n")), map);
+
+    let state = if view.paused { "paused" } else { "running" };
+    frame.render_widget(
+        Paragraph::new(format!(
+            "t = {:.3}  {} steps  {} bodies  {:.0} steps/s  {}  |  q quit, space pause, +/- zoom, arrows pan, r reset",
+            sim.time,
+            sim.steps,
+            sim.bodies.len(),
+            view.steps_per_sec,
+            state
+        )),
+        status,
+    );
+}
@@ -0,0 +1,170 @@
+use bevy::prelude::*;
+use bevy_egui::{EguiContexts, egui};
+use std::collections::BTreeMap;
+use std::fs;
+
+// UI preferences that survive a restart: scale, theme and where each window was left.
+// Stored as key=value lines next to the executable's working directory.
+const CONFIG_PATH: &str = "ui_config.txt";
+const WINDOWS: [&str; 27] = [
+    "Settings",
+    "Inspector",
+    "Automation",
+    "Help",
+    "System Factory",
+    "Kiosk",
+    "Groups",
+    "Lagrangian Radii",
+    "Kirkwood Gaps",
+    "Poincare Section",
+    "Lyapunov",
+    "Background Flow",
+    "Picture In Picture",
+    "Leaderboard",
+    "Collisions",
+    "Trails",
+    "Bookmarks",
+    "Branch",
+    "Director",
+    "Benchmark",
+    "Physics Thread",
+    "Timeline",
+    "Body Groups",
+    "Custom Forces",
+    "Controllers",
+    "Transfer Planner",
+    "Choreographies",
+];
+
+#[derive(Resource)]
+pub struct UiConfig {
+    pub scale: f32,
+    pub dark: bool,
+    // task pool sizes, only read at launch. 0 threads is auto.
+    pub compute_threads: usize,
+    pub reserve_core: bool,
+    windows: BTreeMap<String, egui::Rect>,
+    // what is in the file right now, so it's only written when something changed
+    saved: String,
+    applied: Option<(f32, bool)>,
+}
+
+impl Default for UiConfig {
+    fn default() -> Self {
+        // no config file yet is fine, that's just the first launch
+        let text = fs::read_to_string(CONFIG_PATH).unwrap_or_default();
+        let mut config = UiConfig::parse(&text);
+        config.saved = text;
+        config
+    }
+}
+
+impl UiConfig {
+    pub fn parse(text: &str) -> Self {
+        let mut config = UiConfig {
+            scale: 1.0,
+            dark: true,
+            compute_threads: 0,
+            reserve_core: false,
+            windows: BTreeMap::new(),
+            saved: String::new(),
+            applied: None,
+        };
+        for line in text.lines() {
+            let Some((key, value)) = line.split_once('=') else {
+                continue;
+            };
+            match key.trim() {
+                "ui_scale" => {
+                    if let Ok(scale) = value.trim().parse::<f32>() {
+                        config.scale = scale.clamp(0.5, 4.0);
+                    }
+                }
+                "theme" => config.dark = value.trim() != "light",
+                "compute_threads" => config.compute_threads = value.trim().parse().unwrap_or(0),
+                "reserve_core" => config.reserve_core = value.trim() == "true",
+                key => {
+                    let Some(title) = key.strip_prefix("window.") else {
+                        continue;
+                    };
+                    let numbers: Vec<f32> = value.split(',').filter_map(|n| n.trim().parse().ok()).collect();
+                    if let [x, y, w, h] = numbers[..] {
+                        config.windows.insert(
+                            title.to_string(),
+                            egui::Rect::from_min_size(egui::pos2(x, y), egui::vec2(w, h)),
+                        );
+                    }
+                }
+            }
+        }
+        config
+    }
+
+    pub fn to_text(&self) -> String {
+        let mut text = format!(
+            "ui_scale={}\ntheme={}\n",
+            self.scale,
+            if self.dark { "dark" } else { "light" }
+        );
+        // left out at their defaults, like a file from before they existed
+        if self.compute_threads > 0 {
+            text.push_str(&format!("compute_threads={}\n", self.compute_threads));
+        }
+        if self.reserve_core {
+            text.push_str("reserve_core=true\n");
+        }
+        for (title, rect) in &self.windows {
+            text.push_str(&format!(
+                "window.{}={},{},{},{}\n",
+                title,
+                rect.min.x,
+                rect.min.y,
+                rect.width(),
+                rect.height()
+            ));
+        }
+        text
+    }
+
+    // Opens the window where it was left last time
+    pub fn place<'a>(&self, window: egui::Window<'a>, title: &str) -> egui::Window<'a> {
+        match self.windows.get(title) {
+            Some(rect) => window.default_pos(rect.min).default_size(rect.size()),
+            None => window,
+        }
+    }
+}
+
+// Runs after the windows, so the rects egui remembers are this frame's
+pub fn apply_ui_config(mut contexts: EguiContexts, mut config: ResMut<UiConfig>) {
+    let ctx = contexts.ctx_mut();
+
+    let wanted = (config.scale, config.dark);
+    if config.applied != Some(wanted) {
+        ctx.set_zoom_factor(config.scale);
+        ctx.set_visuals(if config.dark {
+            egui::Visuals::dark()
+        } else {
+            egui::Visuals::light()
+        });
+        config.applied = Some(wanted);
+    }
+
+    for title in WINDOWS {
+        if let Some(rect) = ctx.memory(|mem| mem.area_rect(egui::Id::new(title))) {
+            config.windows.insert(title.to_string(), rect);
+        }
+    }
+
+    // wait until a drag or resize is finished instead of writing every frame of it
+    if ctx.input(|input| input.pointer.any_down()) {
+        return;
+    }
+    let text = config.to_text();
+    if text != config.saved {
+        if let Err(err) = fs::write(CONFIG_PATH, &text) {
+            warn!("Could not save {}: {}", CONFIG_PATH, err);
+        }
+        config.saved = text;
+    }
+}
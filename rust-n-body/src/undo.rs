@@ -0,0 +1,326 @@
+use bevy::prelude::*;
+use bevy_egui::EguiContexts;
+use std::collections::{HashMap, VecDeque};
+use std::sync::Arc;
+use crate::clock::SimulationClock;
+use crate::controller::Controller;
+use crate::energy::Energy;
+use crate::fade::despawn_with_fade;
+use crate::force::Star;
+use crate::guard::Quarantined;
+use crate::leaderboard::Merges;
+use crate::lifetime::Lifetime;
+use crate::selection::Pinned;
+use crate::starformation::FormedStar;
+use crate::tags::GroupTag;
+use crate::tracer::{Massless, tracer_bundle, tracer_mesh};
+use crate::{Body, ResetEvent, SimulationSettings, Velocity, spawn_body};
+
+// Steps kept however small the runs are
+const MAX_STEPS: usize = 20;
+
+// A body and everything it is tagged with. The entity is the one it had when the snapshot was taken,
+// so controllers aimed at it can find it again once it is spawned anew.
+#[derive(Clone)]
+pub struct BodyState {
+    pub entity: Entity,
+    pub body: Body,
+    pub transform: Transform,
+    pub velocity: Vec3,
+    pub star: bool,
+    pub pinned: bool,
+    pub tracer: bool,
+    pub formed_star: bool,
+    pub group: Option<GroupTag>,
+    pub lifetime: Option<Lifetime>,
+    pub merges: Option<Merges>,
+    // few bodies have one, so it doesn't make every state bigger
+    pub controller: Option<Box<Controller>>,
+}
+
+// Every body as it was before an action, tracers included
+#[derive(Clone)]
+pub struct Snapshot {
+    pub label: &'static str,
+    pub bodies: Vec<BodyState>,
+    pub time: f64,
+    pub steps: u64,
+}
+
+impl Snapshot {
+    // Moves the bodies along with the world when the origin moves to shift
+    pub fn rebase(&mut self, shift: Vec2) {
+        for body in &mut self.bodies {
+            body.transform.translation -= shift.extend(0.0);
+        }
+    }
+}
+
+// The bodies a snapshot is taken of and restored over
+pub type UndoBodies<'w, 's> = Query<
+    'w,
+    's,
+    (
+        Entity,
+        &'static Body,
+        &'static Transform,
+        &'static Velocity,
+        (Has<Star>, Has<Pinned>, Has<Massless>, Has<FormedStar>),
+        (Option<&'static GroupTag>, Option<&'static Lifetime>, Option<&'static Merges>, Option<&'static Controller>),
+    ),
+    Without<Quarantined>,
+>;
+
+impl Snapshot {
+    pub fn capture(label: &'static str, bodies: &UndoBodies, clock: &SimulationClock) -> Self {
+        Snapshot {
+            label,
+            bodies: bodies
+                .iter()
+                .map(|(entity, body, transform, velocity, markers, tags)| {
+                    let (star, pinned, tracer, formed_star) = markers;
+                    let (group, lifetime, merges, controller) = tags;
+                    BodyState {
+                        entity,
+                        body: *body,
+                        transform: *transform,
+                        velocity: velocity.0,
+                        star,
+                        pinned,
+                        tracer,
+                        formed_star,
+                        group: group.copied(),
+                        lifetime: lifetime.copied(),
+                        merges: merges.copied(),
+                        controller: controller.map(|c| Box::new(c.clone())),
+                    }
+                })
+                .collect(),
+            time: clock.time,
+            steps: clock.steps,
+        }
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum UndoStep {
+    Undo,
+    Redo,
+}
+
+// Snapshots taken before destructive actions. Memory is bounded by the total number of bodies kept,
+// the oldest snapshots go first, so a run of half a million bodies only keeps a couple.
+#[derive(Resource)]
+pub struct UndoHistory {
+    undo: VecDeque<Snapshot>,
+    redo: Vec<Snapshot>,
+    pub max_bodies: usize,
+    pub pending: Option<UndoStep>,
+}
+
+impl Default for UndoHistory {
+    fn default() -> Self {
+        UndoHistory {
+            undo: VecDeque::new(),
+            redo: Vec::new(),
+            max_bodies: 1_000_000,
+            pending: None,
+        }
+    }
+}
+
+impl UndoHistory {
+    // A new action makes whatever was undone before unreachable
+    pub fn record(&mut self, snapshot: Snapshot) {
+        self.redo.clear();
+        self.undo.push_back(snapshot);
+        self.trim();
+    }
+
+    fn stored_bodies(&self) -> usize {
+        self.undo.iter().chain(&self.redo).map(|s| s.bodies.len()).sum()
+    }
+
+    pub fn memory_bytes(&self) -> usize {
+        self.stored_bodies() * size_of::<BodyState>()
+    }
+
+    // Nothing left to undo or redo, for when memory runs short
+    pub fn clear(&mut self) {
+        self.undo.clear();
+        self.redo.clear();
+    }
+
+    // The newest snapshot stays even if it alone is over the budget
+    fn trim(&mut self) {
+        while self.undo.len() > 1 && (self.undo.len() > MAX_STEPS || self.stored_bodies() > self.max_bodies) {
+            self.undo.pop_front();
+        }
+    }
+
+    // Gives the state to go back to, keeping the current one for redo
+    pub fn undo(&mut self, current: Snapshot) -> Option<Snapshot> {
+        let previous = self.undo.pop_back()?;
+        self.redo.push(Snapshot {
+            label: previous.label,
+            ..current
+        });
+        Some(previous)
+    }
+
+    pub fn redo(&mut self, current: Snapshot) -> Option<Snapshot> {
+        let next = self.redo.pop()?;
+        self.undo.push_back(Snapshot {
+            label: next.label,
+            ..current
+        });
+        self.trim();
+        Some(next)
+    }
+
+    pub fn rebase(&mut self, shift: Vec2) {
+        for snapshot in self.undo.iter_mut().chain(&mut self.redo) {
+            snapshot.rebase(shift);
+        }
+    }
+
+    pub fn undo_label(&self) -> Option<&'static str> {
+        self.undo.back().map(|s| s.label)
+    }
+
+    pub fn redo_label(&self) -> Option<&'static str> {
+        self.redo.last().map(|s| s.label)
+    }
+}
+
+// A reset is the action most worth undoing, it throws the whole run away
+pub fn checkpoint_reset(
+    mut reset_events: EventReader<ResetEvent>,
+    bodies: UndoBodies,
+    clock: Res<SimulationClock>,
+    mut history: ResMut<UndoHistory>,
+) {
+    if reset_events.is_empty() {
+        return;
+    }
+    reset_events.clear();
+    if bodies.is_empty() {
+        return;
+    }
+    history.record(Snapshot::capture("Reset", &bodies, &clock));
+}
+
+// Ctrl+Z undoes, Ctrl+Y or Ctrl+Shift+Z redoes
+pub fn undo_input(mut contexts: EguiContexts, keys: Res<ButtonInput<KeyCode>>, mut history: ResMut<UndoHistory>) {
+    // text fields have their own undo
+    if contexts.ctx_mut().wants_keyboard_input() {
+        return;
+    }
+    if !keys.any_pressed([KeyCode::ControlLeft, KeyCode::ControlRight]) {
+        return;
+    }
+    let shift = keys.any_pressed([KeyCode::ShiftLeft, KeyCode::ShiftRight]);
+    if keys.just_pressed(KeyCode::KeyY) || (shift && keys.just_pressed(KeyCode::KeyZ)) {
+        history.pending = Some(UndoStep::Redo);
+    } else if keys.just_pressed(KeyCode::KeyZ) {
+        history.pending = Some(UndoStep::Undo);
+    }
+}
+
+pub fn apply_undo(
+    mut history: ResMut<UndoHistory>,
+    bodies: UndoBodies,
+    mut clock: ResMut<SimulationClock>,
+    mut energy: ResMut<Energy>,
+    settings: Res<SimulationSettings>,
+    mut commands: Commands,
+    mut materials: ResMut<Assets<ColorMaterial>>,
+    mut meshes: ResMut<Assets<Mesh>>,
+) {
+    let Some(step) = history.pending.take() else {
+        return;
+    };
+    let current = Snapshot::capture("", &bodies, &clock);
+    let target = match step {
+        UndoStep::Undo => history.undo(current),
+        UndoStep::Redo => history.redo(current),
+    };
+    let Some(target) = target else {
+        return;
+    };
+    info!("{:?} {}", step, target.label);
+    restore(&target, &bodies, &settings, &mut commands, &mut materials, &mut meshes);
+    clock.time = target.time;
+    clock.steps = target.steps;
+    energy.reset();
+}
+
+// Swaps the bodies for the ones in the snapshot, the caller puts the clock back
+pub fn restore(
+    snapshot: &Snapshot,
+    bodies: &UndoBodies,
+    settings: &SimulationSettings,
+    commands: &mut Commands,
+    materials: &mut ResMut<Assets<ColorMaterial>>,
+    meshes: &mut ResMut<Assets<Mesh>>,
+) {
+    for (entity, ..) in bodies {
+        despawn_with_fade(commands, entity);
+    }
+    // the tracers share one mesh and material again, like when they were made
+    let tracer_look = snapshot
+        .bodies
+        .iter()
+        .any(|state| state.tracer)
+        .then(|| (tracer_mesh(meshes), materials.add(ColorMaterial::from_color(Color::WHITE))));
+    let respawned: Vec<Entity> = snapshot
+        .bodies
+        .iter()
+        .map(|state| match &tracer_look {
+            Some((mesh, material)) if state.tracer => {
+                let position = state.transform.translation;
+                commands
+                    .spawn(tracer_bundle(mesh, material, position.truncate(), state.velocity.truncate(), position.z))
+                    .id()
+            }
+            _ => spawn_body(
+                state.body,
+                state.transform,
+                Velocity(state.velocity),
+                settings,
+                commands,
+                materials,
+                meshes,
+            ),
+        })
+        .collect();
+    let moved: Arc<HashMap<Entity, Entity>> =
+        Arc::new(snapshot.bodies.iter().zip(&respawned).map(|(state, entity)| (state.entity, *entity)).collect());
+
+    for (state, entity) in snapshot.bodies.iter().zip(respawned) {
+        let mut entity = commands.entity(entity);
+        if state.star {
+            entity.insert(Star);
+        }
+        if state.pinned {
+            entity.insert(Pinned);
+        }
+        if state.formed_star {
+            entity.insert(FormedStar);
+        }
+        if let Some(group) = state.group {
+            entity.insert(group);
+        }
+        if let Some(lifetime) = state.lifetime {
+            entity.insert(lifetime);
+        }
+        if let Some(merges) = state.merges {
+            entity.insert(merges);
+        }
+        if let Some(controller) = &state.controller {
+            let mut controller = Controller::clone(controller);
+            controller.respawned(&moved);
+            entity.insert(controller);
+        }
+    }
+}
@@ -0,0 +1,102 @@
+// What one unit of length, mass and time in the simulation stands for. Picking a system sets G to its
+// value in those units, so orbits come out right, and lets the inspector show physical quantities.
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Default)]
+pub enum UnitSystem {
+    // plain simulation units, G is left to the slider
+    #[default]
+    Simulation,
+    // G = 1, lengths, masses and times all dimensionless
+    Dimensionless,
+    // m, kg, s
+    Si,
+    // AU, solar masses, years
+    Astronomical,
+    // kpc, solar masses, Myr
+    Galactic,
+}
+
+const KM_PER_AU: f64 = 1.495_978_707e8;
+const KM_PER_KPC: f64 = 3.085_677_581e16;
+const SECONDS_PER_YEAR: f64 = 3.155_76e7;
+
+impl UnitSystem {
+    pub const ALL: [UnitSystem; 5] = [
+        UnitSystem::Simulation,
+        UnitSystem::Dimensionless,
+        UnitSystem::Si,
+        UnitSystem::Astronomical,
+        UnitSystem::Galactic,
+    ];
+
+    pub fn label(self) -> &'static str {
+        match self {
+            UnitSystem::Simulation => "Simulation units",
+            UnitSystem::Dimensionless => "Dimensionless (G = 1)",
+            UnitSystem::Si => "SI (m, kg, s)",
+            UnitSystem::Astronomical => "Astronomical (AU, M☉, yr)",
+            UnitSystem::Galactic => "Galactic (kpc, M☉, Myr)",
+        }
+    }
+
+    // G in these units, None when it is up to the user
+    pub fn g(self) -> Option<f64> {
+        match self {
+            UnitSystem::Simulation => None,
+            UnitSystem::Dimensionless => Some(1.0),
+            UnitSystem::Si => Some(6.674_30e-11),
+            // Kepler's third law with P in years and a in AU: 4π²
+            UnitSystem::Astronomical => Some(4.0 * std::f64::consts::PI * std::f64::consts::PI),
+            UnitSystem::Galactic => Some(4.498_5e-12),
+        }
+    }
+
+    // Names of the length, mass and time units, empty when there are none
+    pub fn names(self) -> (&'static str, &'static str, &'static str) {
+        match self {
+            UnitSystem::Simulation | UnitSystem::Dimensionless => ("", "", ""),
+            UnitSystem::Si => ("m", "kg", "s"),
+            UnitSystem::Astronomical => ("AU", "M☉", "yr"),
+            UnitSystem::Galactic => ("kpc", "M☉", "Myr"),
+        }
+    }
+
+    // km/s per unit of speed, for the systems with a physical speed
+    pub fn km_per_s(self) -> Option<f64> {
+        match self {
+            UnitSystem::Simulation | UnitSystem::Dimensionless => None,
+            UnitSystem::Si => Some(1e-3),
+            UnitSystem::Astronomical => Some(KM_PER_AU / SECONDS_PER_YEAR),
+            UnitSystem::Galactic => Some(KM_PER_KPC / (SECONDS_PER_YEAR * 1e6)),
+        }
+    }
+
+    pub fn length(self, value: f32) -> String {
+        quantity(value, self.names().0, 2)
+    }
+
+    pub fn mass(self, value: f32) -> String {
+        quantity(value, self.names().1, 2)
+    }
+
+    pub fn time(self, value: f64) -> String {
+        quantity(value as f32, self.names().2, 4)
+    }
+
+    // The speed in km/s where there is such a thing, otherwise as it is
+    pub fn speed(self, value: f32) -> String {
+        match self.km_per_s() {
+            Some(km_per_s) => quantity((value as f64 * km_per_s) as f32, "km/s", 2),
+            None => quantity(value, "", 2),
+        }
+    }
+}
+
+fn quantity(value: f32, unit: &str, decimals: usize) -> String {
+    // SI quantities are rarely of a size that reads well without an exponent
+    let number = if value != 0.0 && !(1e-3..1e5).contains(&value.abs()) {
+        format!("{:.3e}", value)
+    } else {
+        format!("{:.*}", decimals, value)
+    };
+    if unit.is_empty() { number } else { format!("{} {}", number, unit) }
+}
@@ -0,0 +1,103 @@
+use bevy::prelude::*;
+use crate::SimulationSettings;
+
+// Corrections shown in the settings window, the oldest go first
+const MAX_WARNINGS: usize = 8;
+
+// What the last corrections of the settings were, until dismissed
+#[derive(Resource, Default)]
+pub struct SettingsWarnings {
+    pub messages: Vec<String>,
+}
+
+impl SettingsWarnings {
+    // Logs corrections and keeps the newest of them for the settings window
+    pub fn record(&mut self, warnings: Vec<String>) {
+        for warning in warnings {
+            warn!("{}", warning);
+            self.messages.push(warning);
+        }
+        if self.messages.len() > MAX_WARNINGS {
+            let excess = self.messages.len() - MAX_WARNINGS;
+            self.messages.drain(..excess);
+        }
+    }
+}
+
+// Clamps a value into range, noting it down when it was out of it. NaN counts as out of range.
+fn clamp_noted(value: &mut f32, min: f32, max: f32, name: &str, warnings: &mut Vec<String>) {
+    let clamped = if value.is_nan() { min } else { value.clamp(min, max) };
+    if clamped != *value {
+        warnings.push(format!("{} was {}, set to {}", name, value, clamped));
+        *value = clamped;
+    }
+}
+
+// Brings settings that depend on each other or can't work back into shape, returning what was changed.
+// Meant to be run before anything uses them, so no system has to guard against them on its own.
+pub fn validate(settings: &mut SimulationSettings) -> Vec<String> {
+    let mut warnings = Vec::new();
+
+    clamp_noted(&mut settings.delta_t, 1e-8, 1.0, "Delta T", &mut warnings);
+    clamp_noted(&mut settings.substeps, 0.01, 1000.0, "Substeps Per Frame", &mut warnings);
+    clamp_noted(&mut settings.time_scale, 0.01, 1000.0, "Time Scale", &mut warnings);
+    // theta 0 would open every node, the tree would never approximate anything
+    clamp_noted(&mut settings.theta, 0.01, 1.0, "BH Theta", &mut warnings);
+    clamp_noted(&mut settings.ghost_theta, 0.01, 1.0, "Ghost Theta", &mut warnings);
+    clamp_noted(&mut settings.softening, 0.0, f32::MAX, "Softening Length", &mut warnings);
+    clamp_noted(&mut settings.elasticity, 0.0, 1.0, "Elasticity", &mut warnings);
+    clamp_noted(&mut settings.density, 1e-6, f32::MAX, "Density", &mut warnings);
+    clamp_noted(&mut settings.render_scale, 1e-3, f32::MAX, "Drawn Radius", &mut warnings);
+    clamp_noted(&mut settings.min_body_mass, 1e-6, f32::MAX, "Min Body Mass", &mut warnings);
+    clamp_noted(&mut settings.max_body_mass, 1e-6, f32::MAX, "Max Body Mass", &mut warnings);
+
+    if settings.min_body_mass > settings.max_body_mass {
+        warnings.push(format!(
+            "Min Body Mass {} was above Max Body Mass, lowered to {}",
+            settings.min_body_mass, settings.max_body_mass
+        ));
+        settings.min_body_mass = settings.max_body_mass;
+    }
+    if settings.min_pixels > settings.max_pixels {
+        warnings.push("Smallest on-screen size was above the largest, lowered to it".to_string());
+        settings.min_pixels = settings.max_pixels;
+    }
+    if settings.leaf_capacity == 0 {
+        warnings.push("Bodies Per Leaf was 0, set to 1".to_string());
+        settings.leaf_capacity = 1;
+    }
+    if settings.star_members < 2 {
+        warnings.push(format!("Bodies Per Star was {}, set to 2", settings.star_members));
+        settings.star_members = 2;
+    }
+
+    // an empty or reversed spawn area would make sampling positions from it panic
+    let (start, end) = (*settings.spawn_area.start(), *settings.spawn_area.end());
+    if !(start.is_finite() && end.is_finite() && start < end) {
+        let default = SimulationSettings::default().spawn_area;
+        warnings.push(format!(
+            "Spawn area {}..{} is empty, reset to {}..{}",
+            start,
+            end,
+            default.start(),
+            default.end()
+        ));
+        settings.spawn_area = default;
+    }
+
+    warnings
+}
+
+// Checks the settings whenever they change, so the UI can't leave them in a state that breaks a reset
+pub fn validate_settings(mut settings: ResMut<SimulationSettings>, mut shown: ResMut<SettingsWarnings>) {
+    if !settings.is_changed() {
+        return;
+    }
+    // only a correction counts as a change, or every frame would look like one
+    let warnings = validate(settings.bypass_change_detection());
+    if warnings.is_empty() {
+        return;
+    }
+    settings.set_changed();
+    shown.record(warnings);
+}
@@ -0,0 +1,156 @@
+use bevy::prelude::*;
+use bevy_egui::{EguiContexts, egui};
+use rand::Rng;
+use std::f32::consts::TAU;
+use crate::energy::Energy;
+use crate::factory::SpawnSystemEvent;
+use crate::tracer::Massless;
+use crate::{Body, ResetEvent, SimulationSettings, Velocity};
+
+// Steps after a spawn before the ratio is looked at, the new bodies need a step to show up in the sums
+const CHECK_AFTER_STEPS: u32 = 2;
+
+// Factor the velocities around the center of mass are multiplied by to bring the virial ratio to 1.
+// T goes with the square of the velocities while U doesn't change.
+pub fn virial_scale(ratio: f64) -> Option<f32> {
+    if ratio > 0.0 && ratio.is_finite() {
+        Some((1.0 / ratio).sqrt() as f32)
+    } else {
+        None
+    }
+}
+
+// The velocity of the center of mass and the kinetic energy around it
+fn internal_kinetic(bodies: &[(f32, Vec2)]) -> (Vec2, f64) {
+    let mass: f32 = bodies.iter().map(|(m, _v)| m).sum();
+    if mass <= 0.0 {
+        return (Vec2::ZERO, 0.0);
+    }
+    let bulk = bodies.iter().map(|(m, v)| *m * *v).sum::<Vec2>() / mass;
+    let kinetic = bodies.iter().map(|(m, v)| 0.5 * (*m * (*v - bulk).length_squared()) as f64).sum();
+    (bulk, kinetic)
+}
+
+// New velocities for the bodies, given as mass and velocity, that make 2T = |U| with the center of
+// mass moving on as before. A start at rest has no motion to scale, so every body first gets the same
+// speed in a random direction. None when there is no potential to balance, or a single body.
+pub fn virial_velocities(bodies: &[(f32, Vec2)], potential: f64, rng: &mut impl Rng) -> Option<Vec<Vec2>> {
+    let (bulk, kinetic) = internal_kinetic(bodies);
+    let mut velocities: Vec<(f32, Vec2)> = bodies.to_vec();
+    let kinetic = if kinetic > 0.0 {
+        kinetic
+    } else {
+        for (_mass, velocity) in &mut velocities {
+            *velocity = bulk + Vec2::from_angle(rng.random_range(0.0..TAU));
+        }
+        // the random directions don't add up to nothing, take out what they do add up to
+        let (drift, _kinetic) = internal_kinetic(&velocities);
+        for (_mass, velocity) in &mut velocities {
+            *velocity += bulk - drift;
+        }
+        internal_kinetic(&velocities).1
+    };
+    let scale = virial_scale(2.0 * kinetic / potential.abs())?;
+    Some(velocities.into_iter().map(|(_mass, velocity)| bulk + (velocity - bulk) * scale).collect())
+}
+
+// Watches 2T/|U| after every spawn and offers to fix a preset that starts far from equilibrium,
+// which otherwise either flies apart or collapses violently in its first dynamical time
+#[derive(Resource)]
+pub struct VirialMonitor {
+    // how far from 1 the ratio can be before warning
+    pub tolerance: f32,
+    // the ratio a fresh spawn started out with, while the warning is up
+    pub warning: Option<f64>,
+    pub rescale_requested: bool,
+    check_in: Option<u32>,
+}
+
+impl Default for VirialMonitor {
+    fn default() -> Self {
+        VirialMonitor {
+            tolerance: 0.5,
+            warning: None,
+            rescale_requested: false,
+            check_in: None,
+        }
+    }
+}
+
+impl VirialMonitor {
+    pub fn far_from_equilibrium(&self, ratio: f64) -> bool {
+        (ratio - 1.0).abs() > self.tolerance as f64
+    }
+}
+
+pub fn virial_monitor(
+    mut reset_events: EventReader<ResetEvent>,
+    mut spawn_events: EventReader<SpawnSystemEvent>,
+    settings: Res<SimulationSettings>,
+    energy: Res<Energy>,
+    mut monitor: ResMut<VirialMonitor>,
+    mut bodies: Query<(&mut Velocity, &Body), Without<Massless>>,
+) {
+    if !reset_events.is_empty() || !spawn_events.is_empty() {
+        reset_events.clear();
+        spawn_events.clear();
+        monitor.warning = None;
+        monitor.check_in = Some(CHECK_AFTER_STEPS);
+    }
+
+    if monitor.rescale_requested {
+        monitor.rescale_requested = false;
+        monitor.warning = None;
+        let state: Vec<(f32, Vec2)> = bodies.iter().map(|(v, b)| (b.mass, v.0.truncate())).collect();
+        if let Some(velocities) = virial_velocities(&state, energy.potential, &mut rand::rng()) {
+            // z is only the draw order and is left alone
+            for ((mut velocity, _body), new) in bodies.iter_mut().zip(velocities) {
+                velocity.0.x = new.x;
+                velocity.0.y = new.y;
+            }
+            info!("Set the velocities to virial equilibrium");
+        }
+    }
+
+    if settings.paused {
+        return;
+    }
+    let Some(steps) = monitor.check_in else {
+        return;
+    };
+    if steps > 0 {
+        monitor.check_in = Some(steps - 1);
+        return;
+    }
+    monitor.check_in = None;
+    if let Some(ratio) = energy.virial_ratio()
+        && monitor.far_from_equilibrium(ratio)
+    {
+        monitor.warning = Some(ratio);
+    }
+}
+
+pub fn virial_window(mut contexts: EguiContexts, mut monitor: ResMut<VirialMonitor>) {
+    let Some(ratio) = monitor.warning else {
+        return;
+    };
+    egui::Window::new("Far From Equilibrium")
+        .collapsible(false)
+        .resizable(false)
+        .anchor(egui::Align2::CENTER_TOP, [0.0, 10.0])
+        .show(contexts.ctx_mut(), |ui| {
+            let fate = if ratio > 1.0 { "fly apart" } else { "collapse" };
+            ui.colored_label(
+                egui::Color32::YELLOW,
+                format!("This start has 2T/|U| = {:.2}, far from 1, and will {} before settling.", ratio, fate),
+            );
+            ui.horizontal(|ui| {
+                if ui.button("Rescale Velocities To Virial Equilibrium").clicked() {
+                    monitor.rescale_requested = true;
+                }
+                if ui.button("Dismiss").clicked() {
+                    monitor.warning = None;
+                }
+            });
+        });
+}
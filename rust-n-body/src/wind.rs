@@ -0,0 +1,190 @@
+use bevy::prelude::*;
+use bevy_egui::{EguiContexts, egui};
+use crate::clock::SimulationClock;
+use crate::selection::Pinned;
+use crate::ui_config::UiConfig;
+use crate::{Body, MainCamera, SimulationSettings, Velocity};
+
+// Arrows along each side of the view when the flow is drawn
+const ARROW_GRID: u32 = 16;
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum FlowKind {
+    // the same velocity everywhere
+    Uniform,
+    // turning around the center: like a solid body inside the core, falling off as 1/r outside it
+    Vortex,
+    // along x, growing linearly with y from the center
+    Shear,
+}
+
+impl FlowKind {
+    pub const ALL: [FlowKind; 3] = [FlowKind::Uniform, FlowKind::Vortex, FlowKind::Shear];
+}
+
+// An analytic gas flow the bodies are dragged towards, instead of simulating the gas itself.
+// Light bodies follow it within their stopping time, heavy ones hardly notice.
+#[derive(Resource)]
+pub struct BackgroundFlow {
+    pub enabled: bool,
+    pub kind: FlowKind,
+    // speed of the uniform wind and at the vortex core, velocity change per unit of y for the shear
+    pub strength: f32,
+    // direction of the uniform wind in degrees
+    pub angle: f32,
+    pub center: Vec2,
+    pub core_radius: f32,
+    // simulated time a body of mass 1 or less takes to lose 1/e of its speed relative to the gas
+    pub stopping_time: f32,
+    pub draw: bool,
+}
+
+impl Default for BackgroundFlow {
+    fn default() -> Self {
+        BackgroundFlow {
+            enabled: false,
+            kind: FlowKind::Uniform,
+            strength: 50.0,
+            angle: 0.0,
+            center: Vec2::ZERO,
+            core_radius: 100.0,
+            stopping_time: 0.05,
+            draw: true,
+        }
+    }
+}
+
+impl BackgroundFlow {
+    // Gas velocity at a point
+    pub fn velocity_at(&self, pos: Vec2) -> Vec2 {
+        let r = pos - self.center;
+        match self.kind {
+            FlowKind::Uniform => Vec2::from_angle(self.angle.to_radians()) * self.strength,
+            FlowKind::Vortex => {
+                let d = r.length();
+                if d <= 0.0 || self.core_radius <= 0.0 {
+                    return Vec2::ZERO;
+                }
+                let speed = if d < self.core_radius {
+                    self.strength * d / self.core_radius
+                } else {
+                    self.strength * self.core_radius / d
+                };
+                r.perp() / d * speed
+            }
+            FlowKind::Shear => Vec2::new(self.strength * r.y, 0.0),
+        }
+    }
+
+    // Epstein drag: the stopping time goes with the size of the grain, so with the cube root of its mass.
+    // cbrt and exp aren't exact, so deterministic runs take libm's to come out the same on every platform.
+    pub fn stopping_time(&self, mass: f32, deterministic: bool) -> f32 {
+        let size = if deterministic { libm::cbrtf(mass.max(1.0)) } else { mass.max(1.0).cbrt() };
+        self.stopping_time * size
+    }
+
+    // Velocity after dt of drag, solved exactly so any dt is stable: the difference to the gas decays exponentially
+    pub fn drag(&self, mass: f32, pos: Vec2, vel: Vec2, dt: f32, deterministic: bool) -> Vec2 {
+        let t_s = self.stopping_time(mass, deterministic);
+        if t_s <= 0.0 {
+            return self.velocity_at(pos);
+        }
+        let gas = self.velocity_at(pos);
+        let decay = if deterministic { libm::expf(-dt / t_s) } else { (-dt / t_s).exp() };
+        gas + (vel - gas) * decay
+    }
+}
+
+pub fn apply_flow(
+    clock: Res<SimulationClock>,
+    settings: Res<SimulationSettings>,
+    flow: Res<BackgroundFlow>,
+    mut last_time: Local<f64>,
+    mut bodies: Query<(&Body, &Transform, &mut Velocity), Without<Pinned>>,
+) {
+    let elapsed = clock.time - *last_time;
+    *last_time = clock.time;
+    if !flow.enabled || elapsed <= 0.0 {
+        return;
+    }
+    for (body, transform, mut velocity) in &mut bodies {
+        let (pos, vel) = (transform.translation.truncate(), velocity.0.truncate());
+        let vel = flow.drag(body.mass, pos, vel, elapsed as f32, settings.deterministic);
+        velocity.0 = vel.extend(velocity.0.z);
+    }
+}
+
+pub fn draw_flow(
+    flow: Res<BackgroundFlow>,
+    cameras: Query<(&Transform, &Projection), With<MainCamera>>,
+    mut gizmos: Gizmos,
+) {
+    if !flow.enabled || !flow.draw {
+        return;
+    }
+    let Ok((camera, Projection::Orthographic(ortho))) = cameras.single() else {
+        return;
+    };
+    let center = camera.translation.truncate();
+    let (min, max) = (center + ortho.area.min, center + ortho.area.max);
+    let cell = (max - min) / ARROW_GRID as f32;
+
+    // the fastest arrow spans most of a cell
+    let points: Vec<(Vec2, Vec2)> = (0..ARROW_GRID * ARROW_GRID)
+        .map(|k| {
+            let p = min + (Vec2::new((k % ARROW_GRID) as f32, (k / ARROW_GRID) as f32) + 0.5) * cell;
+            (p, flow.velocity_at(p))
+        })
+        .collect();
+    let fastest = points.iter().map(|(_p, u)| u.length()).fold(0.0, f32::max);
+    if fastest <= 0.0 {
+        return;
+    }
+    let scale = 0.8 * cell.min_element() / fastest;
+    for (p, u) in points {
+        gizmos.arrow_2d(p, p + u * scale, Color::srgba(0.5, 0.8, 1.0, 0.4));
+    }
+}
+
+pub fn flow_window(mut contexts: EguiContexts, mut flow: ResMut<BackgroundFlow>, ui_config: Res<UiConfig>) {
+    ui_config
+        .place(egui::Window::new("Background Flow"), "Background Flow")
+        .default_open(false)
+        .show(contexts.ctx_mut(), |ui| {
+            ui.add(egui::Checkbox::new(&mut flow.enabled, "Drag Bodies Towards The Flow"));
+            ui.add(egui::Checkbox::new(&mut flow.draw, "Draw Flow"));
+            egui::ComboBox::from_label("Flow")
+                .selected_text(format!("{:?}", flow.kind))
+                .show_ui(ui, |ui| {
+                    for kind in FlowKind::ALL {
+                        ui.selectable_value(&mut flow.kind, kind, format!("{:?}", kind));
+                    }
+                });
+            match flow.kind {
+                FlowKind::Uniform => {
+                    ui.add(egui::Slider::new(&mut flow.strength, 0.0..=1000.0).text("Wind Speed"));
+                    ui.add(egui::Slider::new(&mut flow.angle, 0.0..=360.0).text("Direction (degrees)"));
+                }
+                FlowKind::Vortex => {
+                    ui.add(egui::Slider::new(&mut flow.strength, -1000.0..=1000.0).text("Speed At Core"));
+                    ui.add(egui::Slider::new(&mut flow.core_radius, 1.0..=2000.0).logarithmic(true).text("Core Radius"));
+                }
+                FlowKind::Shear => {
+                    ui.add(egui::Slider::new(&mut flow.strength, -10.0..=10.0).text("Shear Rate"));
+                }
+            }
+            if flow.kind != FlowKind::Uniform {
+                ui.horizontal(|ui| {
+                    ui.label("Center");
+                    ui.add(egui::DragValue::new(&mut flow.center.x));
+                    ui.add(egui::DragValue::new(&mut flow.center.y));
+                });
+            }
+            ui.add(
+                egui::Slider::new(&mut flow.stopping_time, 0.001..=10.0)
+                    .logarithmic(true)
+                    .text("Stopping Time"),
+            );
+            ui.label("Heavier bodies take longer to stop, as the cube root of their mass");
+        });
+}